@@ -0,0 +1,64 @@
+//! Benchmarks of the individual-based model: the cost of one year-step (reproduction, survival,
+//! and stage transitions for every individual) at several population sizes, with and without
+//! genetics. Run with `cargo bench --features bench --bench individual_based`.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use ecolysis_cmd::populations::individual_level_simulation::{
+    Genotype, IndividualBasedModel, Locus, TransitionTable,
+};
+use ecolysis_cmd::simulation::Simulation;
+use ecolysis_cmd::PopulationMatrix;
+
+/// Return a stable three-stage model (lambda close to one) starting with `size` individuals.
+fn model(size: usize) -> IndividualBasedModel {
+    let survival = PopulationMatrix::build(vec![
+        vec![0.0, 0.0, 0.0],
+        vec![0.3, 0.5, 0.0],
+        vec![0.0, 0.3, 0.9],
+    ])
+    .unwrap();
+    let third = size as f64 / 3.0;
+    IndividualBasedModel::build(
+        vec![third, third, third].into(),
+        TransitionTable::from_survival_matrix(&survival).unwrap(),
+        vec![0.0, 0.0, 0.6],
+    )
+    .unwrap()
+}
+
+/// Return the model with 20 loci of 10 alleles each.
+fn genetic_model(size: usize) -> IndividualBasedModel {
+    let model = model(size);
+    let founders = model.get_initial_population().total() as usize;
+    let loci = (0..20).map(|x| Locus::new(&format!("L{x}"))).collect();
+    let genotypes = (0..founders)
+        .map(|x| {
+            let allele = (x % 10) as u16;
+            Genotype::new(vec![[Some(allele), Some((allele + 3) % 10)]; 20]).unwrap()
+        })
+        .collect();
+    model.with_founder_genotypes(loci, genotypes).unwrap()
+}
+
+fn year_step(c: &mut Criterion) {
+    for (name, build) in [
+        ("ibm_year_step", model as fn(usize) -> IndividualBasedModel),
+        ("ibm_year_step_genetics", genetic_model),
+    ] {
+        let mut group = c.benchmark_group(name);
+        for size in [100, 1_000, 10_000] {
+            let model = build(size);
+            group.throughput(Throughput::Elements(size as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+                b.iter_batched(
+                    || model.simulation(0, 1),
+                    |mut simulation| simulation.step(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, year_step);
+criterion_main!(benches);
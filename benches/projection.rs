@@ -0,0 +1,182 @@
+//! Benchmarks of matrix-vector projection, the operation that dominates the runtime of stochastic
+//! PVA. Models with 2 to 8 lifestages use the unrolled path of `project_vector`, the 9 lifestage
+//! case uses the general dense path for comparison, and the 50 and 200 lifestage Leslie matrices
+//! use the sparse path. The 12 to 20 lifestage Leslie matrices sit either side of the sparse
+//! threshold, and fully populated 3 to 20 lifestage matrices measure the dense path on its own.
+//! The unrolled path is compared with the general loop it replaced for 2 to 8 lifestages.
+//! The contiguous storage of a Population Matrix is compared with projecting from separate rows,
+//! as matrices were stored before. Projecting 1000 vectors by a dense matrix one at a time is
+//! compared with projecting them in one batch.
+//! Stochastic replicate throughput is measured at several replicate counts.
+//! Run with `cargo bench --features bench --bench projection`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ecolysis_cmd::{PopulationMatrix, PopulationVector, StochasticPva};
+
+fn leslie_matrix(size: usize) -> Vec<Vec<f64>> {
+    let mut matrix = vec![vec![0.0; size]; size];
+    for (column, fecundity) in matrix[0].iter_mut().enumerate().skip(1) {
+        *fecundity = 0.5 + column as f64 / size as f64;
+    }
+    for row in 1..size {
+        matrix[row][row - 1] = 0.8;
+    }
+    matrix[size - 1][size - 1] = 0.9;
+    matrix
+}
+
+/// A matrix with no zeros, so projection always uses the dense path.
+fn dense_matrix(size: usize) -> Vec<Vec<f64>> {
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|column| 0.1 + ((row * size + column) % 7) as f64 / 10.0)
+                .collect()
+        })
+        .collect()
+}
+
+fn project_vector(c: &mut Criterion) {
+    let mut group = c.benchmark_group("project_vector");
+    for size in [2, 3, 4, 8, 9, 12, 16, 20, 50, 200] {
+        let popmatrix = PopulationMatrix::build(leslie_matrix(size)).unwrap();
+        let popvector = PopulationVector::new(vec![10.0; size]);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| black_box(&popmatrix).project_vector(black_box(&popvector)))
+        });
+    }
+    group.finish();
+    let mut group = c.benchmark_group("project_vector_dense");
+    for size in [3, 5, 8, 10, 12, 16, 20] {
+        let popmatrix = PopulationMatrix::build(dense_matrix(size)).unwrap();
+        let popvector = PopulationVector::new(vec![10.0; size]);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| black_box(&popmatrix).project_vector(black_box(&popvector)))
+        });
+    }
+    group.finish();
+}
+
+/// Project a vector by a matrix stored as separate rows, the layout Population Matrices used
+/// before their values were stored contiguously.
+fn project_rows(matrix: &[Vec<f64>], vector: &[f64]) -> Vec<f64> {
+    matrix
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(vector)
+                .map(|(item, value)| item * value)
+                .sum()
+        })
+        .collect()
+}
+
+/// Project a vector by a contiguous matrix with the general loop, which `project_vector` used for
+/// every size before small matrices were unrolled.
+fn project_loop(matrix: &[f64], vector: &[f64]) -> Vec<f64> {
+    matrix
+        .chunks_exact(vector.len())
+        .map(|row| {
+            row.iter()
+                .zip(vector)
+                .map(|(item, value)| item * value)
+                .sum()
+        })
+        .collect()
+}
+
+fn unrolled_projection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("unrolled_projection");
+    for size in [2, 3, 4, 5, 6, 7, 8] {
+        let rows = dense_matrix(size);
+        let elements: Vec<f64> = rows.concat();
+        let popmatrix = PopulationMatrix::build(rows).unwrap();
+        let popvector = PopulationVector::new(vec![10.0; size]);
+        group.bench_with_input(BenchmarkId::new("loop", size), &size, |b, _| {
+            b.iter(|| project_loop(black_box(&elements), black_box(popvector.get_vector())))
+        });
+        group.bench_with_input(BenchmarkId::new("unrolled", size), &size, |b, _| {
+            b.iter(|| black_box(&popmatrix).project_vector(black_box(&popvector)))
+        });
+    }
+    group.finish();
+}
+
+fn matrix_storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matrix_storage");
+    for size in [9, 12, 20] {
+        let rows = dense_matrix(size);
+        let popmatrix = PopulationMatrix::build(rows.clone()).unwrap();
+        let popvector = PopulationVector::new(vec![10.0; size]);
+        group.bench_with_input(BenchmarkId::new("rows", size), &size, |b, _| {
+            b.iter(|| project_rows(black_box(&rows), black_box(popvector.get_vector())))
+        });
+        group.bench_with_input(BenchmarkId::new("contiguous", size), &size, |b, _| {
+            b.iter(|| black_box(&popmatrix).project_vector(black_box(&popvector)))
+        });
+    }
+    group.finish();
+}
+
+fn project_vectors(c: &mut Criterion) {
+    let mut group = c.benchmark_group("project_1000_vectors");
+    for size in [12, 20, 50] {
+        let popmatrix = PopulationMatrix::build(dense_matrix(size)).unwrap();
+        let popvectors: Vec<PopulationVector> = (0..1000)
+            .map(|i| PopulationVector::new(vec![i as f64; size]))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("single", size), &size, |b, _| {
+            b.iter(|| {
+                popvectors
+                    .iter()
+                    .map(|x| black_box(&popmatrix).project_vector(x).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("batch", size), &size, |b, _| {
+            b.iter(|| black_box(&popmatrix).project_vectors(black_box(&popvectors)))
+        });
+    }
+    group.finish();
+}
+
+fn stochastic_projection(c: &mut Criterion) {
+    let population = StochasticPva::build_from_vectors(
+        vec![40.0, 20.0, 100.0],
+        vec![
+            vec![
+                vec![0.0, 0.0, 0.1],
+                vec![0.6, 0.8, 0.0],
+                vec![0.0, 0.8, 0.95],
+            ],
+            vec![
+                vec![0.0, 0.0, 0.05],
+                vec![0.5, 0.7, 0.0],
+                vec![0.0, 0.7, 0.9],
+            ],
+        ],
+    )
+    .unwrap();
+    c.bench_function("stochastic_projection_3_stages", |b| {
+        b.iter(|| population.stochastic_projection(black_box(100), 100, 1))
+    });
+    let mut group = c.benchmark_group("stochastic_replicates");
+    for replicates in [10, 100, 1000] {
+        group.throughput(Throughput::Elements(replicates as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(replicates),
+            &replicates,
+            |b, replicates| b.iter(|| population.stochastic_projection(50, *replicates, 1)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    project_vector,
+    unrolled_projection,
+    matrix_storage,
+    project_vectors,
+    stochastic_projection
+);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+//! Fuzz the CSV readers with arbitrary input: each must return a value or an error, and never
+//! panic. Run with `cargo +nightly fuzz run csv_readers` from the repository root.
+#![no_main]
+use ecolysis_cmd::imports::read_popbio_csv;
+use ecolysis_cmd::populations::individual_level_simulation::{GenotypeTable, Locus, TransitionTable};
+use ecolysis_cmd::validation::{validate_matrix_csv, validate_vector_csv};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = validate_matrix_csv(data);
+    let _ = validate_vector_csv(data);
+    let _ = read_popbio_csv(data);
+    let _ = TransitionTable::read_csv(data);
+    let _ = GenotypeTable::read_csv(data);
+    let _ = Locus::read_csv(data);
+});
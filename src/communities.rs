@@ -0,0 +1,271 @@
+//! This module contains community simulations: several species, each with its own stage-structured Population Matrix, whose growth is modified by the abundance of the other species through a community interaction matrix of competition, predation, or facilitation coefficients.
+use crate::outputs::{OutputTable, ToOutputTable};
+use crate::populations::population_level_simulation::{PopulationMatrix, PopulationVector};
+use crate::simulation::Simulation;
+mod predator_prey;
+pub use predator_prey::*;
+
+/// This struct holds one species of a community: its name, Population Matrix, and initial
+/// Population Vector.
+#[derive(Clone)]
+pub struct Species {
+    name: String,
+    matrix: PopulationMatrix,
+    initial_population: PopulationVector,
+}
+impl Species {
+    /// Build a Species.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lengths of the Population Vector and the Matrix do
+    /// not match.
+    pub fn build(
+        name: &str,
+        matrix: PopulationMatrix,
+        initial_population: PopulationVector,
+    ) -> Result<Species, &'static str> {
+        if matrix.get_lifestage_count() != initial_population.get_lifestage_count() {
+            return Err("Population vector size does not match matrices.");
+        }
+        Ok(Species {
+            name: name.to_string(),
+            matrix,
+            initial_population,
+        })
+    }
+    /// Return the name of the species.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the Population Matrix of the species.
+    pub fn get_matrix(&self) -> &PopulationMatrix {
+        &self.matrix
+    }
+    /// Return the initial Population Vector of the species.
+    pub fn get_initial_population(&self) -> &PopulationVector {
+        &self.initial_population
+    }
+}
+
+/// This struct holds a community of interacting species. Each step, every species is first
+/// projected by its own matrix, and the result is then multiplied by
+/// `exp(sum over j of interactions[i][j] * N_j)`, where `N_j` is the total population of species
+/// `j` at the start of the step. A negative coefficient means species `j` reduces the growth of
+/// species `i` (competition, or being eaten); a positive coefficient means it increases it
+/// (facilitation, or food). Coefficients on the diagonal give density dependence within a
+/// species.
+/// ```
+/// use ecolysis_cmd::communities::{Community, Species};
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// let grass = Species::build("grass",
+///     PopulationMatrix::build(vec![vec![0.5, 2.0], vec![0.3, 0.9]]).unwrap(),
+///     PopulationVector::new(vec![100.0, 50.0])).unwrap();
+/// let moss = Species::build("moss",
+///     PopulationMatrix::build(vec![vec![1.3]]).unwrap(),
+///     PopulationVector::new(vec![80.0])).unwrap();
+/// let community = Community::build(vec![grass, moss],
+///     vec![vec![-0.002, -0.001], vec![-0.003, -0.001]]).unwrap();
+/// let output = community.project(50);
+/// assert_eq!(output.totals(0).unwrap().len(), 50);
+/// assert!(output.totals(2).is_none());
+/// ```
+#[derive(Clone)]
+pub struct Community {
+    species: Vec<Species>,
+    interactions: Vec<Vec<f64>>,
+}
+impl Community {
+    /// Build a Community from a list of species and a square interaction matrix with one row
+    /// and one column per species.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no species, the interaction matrix is not
+    /// square with one row per species, or any coefficient is not finite.
+    pub fn build(
+        species: Vec<Species>,
+        interactions: Vec<Vec<f64>>,
+    ) -> Result<Community, &'static str> {
+        if species.is_empty() {
+            return Err("A community needs at least one species.");
+        }
+        if interactions.len() != species.len()
+            || interactions.iter().any(|row| row.len() != species.len())
+        {
+            return Err("The interaction matrix must have one row and one column per species.");
+        }
+        if interactions.iter().flatten().any(|x| !x.is_finite()) {
+            return Err("Interaction coefficients must be finite.");
+        }
+        Ok(Community {
+            species,
+            interactions,
+        })
+    }
+    /// Return the species of the community.
+    pub fn get_species(&self) -> &Vec<Species> {
+        &self.species
+    }
+    /// Return the interaction matrix.
+    pub fn get_interactions(&self) -> &Vec<Vec<f64>> {
+        &self.interactions
+    }
+    /// Return a [`Simulation`] of the community, starting from each species' initial population.
+    pub fn simulation(&self) -> CommunitySimulation<'_> {
+        CommunitySimulation {
+            community: self,
+            populations: self
+                .species
+                .iter()
+                .map(|x| x.initial_population.clone())
+                .collect(),
+            steps_taken: 0,
+        }
+    }
+    /// Return the output of projecting the community for the given number of steps.
+    pub fn project(&self, steps: u32) -> CommunityOutput {
+        CommunityOutput {
+            species_names: self.species.iter().map(|x| x.name.clone()).collect(),
+            result: self.simulation().run(steps),
+        }
+    }
+}
+
+/// The running state of a [`Community`] projection, created by [`Community::simulation`]. The
+/// state holds one Population Vector per species.
+pub struct CommunitySimulation<'a> {
+    community: &'a Community,
+    populations: Vec<PopulationVector>,
+    steps_taken: u32,
+}
+impl Simulation for CommunitySimulation<'_> {
+    type State = Vec<PopulationVector>;
+    fn step(&mut self) {
+        let totals: Vec<f64> = self
+            .populations
+            .iter()
+            .map(|x| x.iter().sum::<f64>())
+            .collect();
+        self.populations = self
+            .community
+            .species
+            .iter()
+            .zip(&self.populations)
+            .zip(&self.community.interactions)
+            .map(|((species, popvector), interactions)| {
+                let effect: f64 = interactions.iter().zip(&totals).map(|(a, n)| a * n).sum();
+                &(&species.matrix * popvector) * effect.exp()
+            })
+            .collect();
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &Vec<PopulationVector> {
+        &self.populations
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+/// This struct stores the output of a community projection: the Population Vector of every
+/// species at every step.
+pub struct CommunityOutput {
+    species_names: Vec<String>,
+    result: Vec<Vec<PopulationVector>>,
+}
+impl CommunityOutput {
+    /// Return the names of the species, in the order used by the output.
+    pub fn get_species_names(&self) -> &Vec<String> {
+        &self.species_names
+    }
+    /// Return the Population Vectors of every species, for each step.
+    pub fn get_steps(&self) -> &Vec<Vec<PopulationVector>> {
+        &self.result
+    }
+    /// Return the total population of one species at each step, or `None` if there is no
+    /// species at that position.
+    pub fn totals(&self, species: usize) -> Option<Vec<f64>> {
+        if species >= self.species_names.len() {
+            return None;
+        }
+        Some(
+            self.result
+                .iter()
+                .map(|step| step[species].iter().sum())
+                .collect(),
+        )
+    }
+}
+impl ToOutputTable for CommunityOutput {
+    /// The table has a `step` column followed by one column per lifestage of each species,
+    /// named `<species>_stage_<n>`.
+    fn to_output_table(&self) -> OutputTable {
+        let mut columns = vec![String::from("step")];
+        if let Some(first) = self.result.first() {
+            for (name, popvector) in self.species_names.iter().zip(first) {
+                columns.extend(
+                    (0..popvector.get_lifestage_count()).map(|x| format!("{}_stage_{}", name, x)),
+                );
+            }
+        }
+        let rows = self
+            .result
+            .iter()
+            .enumerate()
+            .map(|(step, populations)| {
+                let mut row = vec![(step + 1) as f64];
+                row.extend(populations.iter().flatten());
+                row
+            })
+            .collect();
+        OutputTable::build(columns, rows).expect("Rows are built with one value per column.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_stage(name: &str, growth: f64, initial: f64) -> Species {
+        Species::build(
+            name,
+            PopulationMatrix::build(vec![vec![growth]]).unwrap(),
+            PopulationVector::new(vec![initial]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn competition_reaches_coexistence_equilibrium() {
+        // Two Ricker competitors with weaker between-species than within-species competition
+        // coexist at N1 = N2 = ln(r) / (a + b).
+        let community = Community::build(
+            vec![single_stage("a", 1.5, 10.0), single_stage("b", 1.5, 50.0)],
+            vec![vec![-0.01, -0.005], vec![-0.005, -0.01]],
+        )
+        .unwrap();
+        let output = community.project(500);
+        let equilibrium = 1.5_f64.ln() / 0.015;
+        for species in 0..2 {
+            let totals = output.totals(species).unwrap();
+            assert!((totals.last().unwrap() - equilibrium).abs() < 1e-6);
+        }
+    }
+    #[test]
+    fn no_interactions_match_independent_projection() {
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+        let species =
+            Species::build("x", matrix.clone(), PopulationVector::new(vec![5.0, 5.0])).unwrap();
+        let community = Community::build(vec![species], vec![vec![0.0]]).unwrap();
+        let output = community.project(3);
+        let direct = &matrix.pow(3) * &PopulationVector::new(vec![5.0, 5.0]);
+        for (x, y) in output.get_steps()[2][0].iter().zip(&direct) {
+            assert!((x - y).abs() < 1e-9);
+        }
+        assert_eq!(
+            output.to_output_table().get_columns(),
+            &vec!["step", "x_stage_0", "x_stage_1"]
+        );
+    }
+    #[test]
+    fn interaction_matrix_must_match_species() {
+        assert!(Community::build(vec![single_stage("a", 1.0, 1.0)], vec![vec![0.0, 0.0]]).is_err());
+    }
+}
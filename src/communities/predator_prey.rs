@@ -0,0 +1,295 @@
+//! This module contains a coupled predator–prey simulation. Each species follows its own Decomposed Matrix, but the prey's survival depends on how many predators there are, and the predator's fertility depends on how much prey it eats, giving stage-structured Lotka–Volterra dynamics.
+use crate::outputs::{stage_columns, OutputTable, ToOutputTable};
+use crate::populations::population_level_simulation::{
+    DecomposedMatrix, PopulationVector, PvaDeterministicOutput,
+};
+use crate::simulation::Simulation;
+
+/// This struct holds the parameters of the interaction between predator and prey, using a
+/// Holling type II functional response. With `N` prey and `P` predators in total:
+/// - Each predator eats `attack_rate * N / (1 + attack_rate * handling_time * N)` prey per step.
+/// - Each prey survives predation with probability
+///   `exp(-attack_rate * P / (1 + attack_rate * handling_time * N))`.
+/// - The predator's fertility matrix is multiplied by `conversion_efficiency` times the number of
+///   prey eaten per predator, so the fertility matrix gives offspring per unit of food.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PredationParameters {
+    pub attack_rate: f64,
+    pub handling_time: f64,
+    pub conversion_efficiency: f64,
+}
+
+/// The state of a predator–prey simulation at one step.
+#[derive(Clone)]
+pub struct PredatorPreyState {
+    prey: PopulationVector,
+    predator: PopulationVector,
+}
+impl PredatorPreyState {
+    /// Return the Population Vector of the prey.
+    pub fn get_prey(&self) -> &PopulationVector {
+        &self.prey
+    }
+    /// Return the Population Vector of the predator.
+    pub fn get_predator(&self) -> &PopulationVector {
+        &self.predator
+    }
+}
+
+/// This struct holds a predator–prey model: the Decomposed Matrices and initial populations of
+/// both species and the parameters linking them.
+/// ```
+/// use ecolysis_cmd::communities::{PredationParameters, PredatorPrey};
+/// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// let hares = DecomposedMatrix::build(
+///     PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.7]]).unwrap(),
+///     PopulationMatrix::build(vec![vec![0.0, 2.5], vec![0.0, 0.0]]).unwrap(),
+/// ).unwrap();
+/// let lynx = DecomposedMatrix::build(
+///     PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.3, 0.8]]).unwrap(),
+///     PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.0, 0.0]]).unwrap(),
+/// ).unwrap();
+/// let model = PredatorPrey::build(
+///     hares, PopulationVector::new(vec![200.0, 300.0]),
+///     lynx, PopulationVector::new(vec![5.0, 10.0]),
+///     PredationParameters { attack_rate: 0.01, handling_time: 0.2, conversion_efficiency: 0.1 },
+/// ).unwrap();
+/// let output = model.project(100);
+/// // Too few hares are caught to sustain the lynx, which decline.
+/// assert!(output.get_predator().iter().last().unwrap().total() < 15.0);
+/// ```
+#[derive(Clone)]
+pub struct PredatorPrey {
+    prey: DecomposedMatrix,
+    prey_initial: PopulationVector,
+    predator: DecomposedMatrix,
+    predator_initial: PopulationVector,
+    parameters: PredationParameters,
+}
+impl PredatorPrey {
+    /// Build a predator–prey model.
+    /// # Errors
+    /// Will return `Err<'static str>` if either initial population does not match its matrix, or
+    /// any parameter is negative or not finite.
+    pub fn build(
+        prey: DecomposedMatrix,
+        prey_initial: PopulationVector,
+        predator: DecomposedMatrix,
+        predator_initial: PopulationVector,
+        parameters: PredationParameters,
+    ) -> Result<PredatorPrey, &'static str> {
+        if prey.get_lifestage_count() != prey_initial.get_lifestage_count()
+            || predator.get_lifestage_count() != predator_initial.get_lifestage_count()
+        {
+            return Err("Population vector size does not match matrices.");
+        }
+        if [
+            parameters.attack_rate,
+            parameters.handling_time,
+            parameters.conversion_efficiency,
+        ]
+        .iter()
+        .any(|x| !x.is_finite() || *x < 0.0)
+        {
+            return Err("Predation parameters must be finite and not negative.");
+        }
+        Ok(PredatorPrey {
+            prey,
+            prey_initial,
+            predator,
+            predator_initial,
+            parameters,
+        })
+    }
+    /// Return the predation parameters.
+    pub fn get_parameters(&self) -> PredationParameters {
+        self.parameters
+    }
+    /// Return the number of prey eaten per predator in one step when there are `prey_total`
+    /// prey.
+    pub fn prey_eaten_per_predator(&self, prey_total: f64) -> f64 {
+        let PredationParameters {
+            attack_rate,
+            handling_time,
+            ..
+        } = self.parameters;
+        attack_rate * prey_total / (1.0 + attack_rate * handling_time * prey_total)
+    }
+    /// Return a [`Simulation`] of the model, starting from the initial populations.
+    pub fn simulation(&self) -> PredatorPreySimulation<'_> {
+        PredatorPreySimulation {
+            model: self,
+            state: PredatorPreyState {
+                prey: self.prey_initial.clone(),
+                predator: self.predator_initial.clone(),
+            },
+            steps_taken: 0,
+        }
+    }
+    /// Return the output of projecting the model for the given number of steps.
+    pub fn project(&self, steps: u32) -> PredatorPreyOutput {
+        let (prey, predator) = self
+            .simulation()
+            .run(steps)
+            .into_iter()
+            .map(|state| (state.prey, state.predator))
+            .unzip();
+        PredatorPreyOutput {
+            prey: PvaDeterministicOutput::new(prey),
+            predator: PvaDeterministicOutput::new(predator),
+        }
+    }
+}
+
+/// The running state of a [`PredatorPrey`] projection, created by [`PredatorPrey::simulation`].
+pub struct PredatorPreySimulation<'a> {
+    model: &'a PredatorPrey,
+    state: PredatorPreyState,
+    steps_taken: u32,
+}
+impl Simulation for PredatorPreySimulation<'_> {
+    type State = PredatorPreyState;
+    fn step(&mut self) {
+        let prey_total: f64 = self.state.prey.iter().sum();
+        let predator_total: f64 = self.state.predator.iter().sum();
+        let eaten = self.model.prey_eaten_per_predator(prey_total);
+        let escape = if prey_total > 0.0 {
+            (-eaten * predator_total / prey_total).exp()
+        } else {
+            1.0
+        };
+        let prey = &(self.model.prey.get_survival() * &self.state.prey) * escape;
+        let prey = &prey + &(self.model.prey.get_fertility() * &self.state.prey);
+        let feeding = self.model.parameters.conversion_efficiency * eaten;
+        let predator = &(self.model.predator.get_fertility() * &self.state.predator) * feeding;
+        let predator = &predator + &(self.model.predator.get_survival() * &self.state.predator);
+        self.state = PredatorPreyState { prey, predator };
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &PredatorPreyState {
+        &self.state
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+/// This struct stores the output of a predator–prey projection as a pair of deterministic
+/// outputs, one for each species.
+pub struct PredatorPreyOutput {
+    prey: PvaDeterministicOutput,
+    predator: PvaDeterministicOutput,
+}
+impl PredatorPreyOutput {
+    /// Return the trajectory of the prey.
+    pub fn get_prey(&self) -> &PvaDeterministicOutput {
+        &self.prey
+    }
+    /// Return the trajectory of the predator.
+    pub fn get_predator(&self) -> &PvaDeterministicOutput {
+        &self.predator
+    }
+}
+impl ToOutputTable for PredatorPreyOutput {
+    /// The table has a `step` column followed by one column per prey lifestage
+    /// (`prey_stage_<n>`) and one per predator lifestage (`predator_stage_<n>`).
+    fn to_output_table(&self) -> OutputTable {
+        let prey = self.prey.to_output_table();
+        let predator = self.predator.to_output_table();
+        let count = |output: &PvaDeterministicOutput| {
+            output
+                .iter()
+                .next()
+                .map_or(0, |x| x.get_lifestage_count() as usize)
+        };
+        let mut columns = vec![String::from("step")];
+        columns.extend(
+            stage_columns(count(&self.prey))
+                .iter()
+                .map(|x| format!("prey_{}", x)),
+        );
+        columns.extend(
+            stage_columns(count(&self.predator))
+                .iter()
+                .map(|x| format!("predator_{}", x)),
+        );
+        let rows = prey
+            .get_rows()
+            .iter()
+            .zip(predator.get_rows())
+            .map(|(x, y)| x.iter().chain(&y[1..]).copied().collect())
+            .collect();
+        OutputTable::build(columns, rows).expect("Rows are built with one value per column.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PopulationMatrix;
+
+    fn model(attack_rate: f64) -> PredatorPrey {
+        let single = |survival: f64, fertility: f64| {
+            DecomposedMatrix::build(
+                PopulationMatrix::build(vec![vec![survival]]).unwrap(),
+                PopulationMatrix::build(vec![vec![fertility]]).unwrap(),
+            )
+            .unwrap()
+        };
+        PredatorPrey::build(
+            single(0.5, 0.7),
+            PopulationVector::new(vec![100.0]),
+            single(0.6, 1.0),
+            PopulationVector::new(vec![10.0]),
+            PredationParameters {
+                attack_rate,
+                handling_time: 0.1,
+                conversion_efficiency: 0.2,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn without_predation_species_are_independent() {
+        let output = model(0.0).project(5);
+        let prey = output.get_prey().iter().last().unwrap().iter().sum::<f64>();
+        let predator = output
+            .get_predator()
+            .iter()
+            .last()
+            .unwrap()
+            .iter()
+            .sum::<f64>();
+        assert!((prey - 100.0 * 1.2_f64.powi(5)).abs() < 1e-9);
+        assert!((predator - 10.0 * 0.6_f64.powi(5)).abs() < 1e-9);
+    }
+    #[test]
+    fn predation_reduces_prey_and_feeds_predators() {
+        let without = model(0.0).project(1);
+        let with = model(0.01).project(1);
+        let total =
+            |output: &PvaDeterministicOutput| output.iter().last().unwrap().iter().sum::<f64>();
+        assert!(total(with.get_prey()) < total(without.get_prey()));
+        assert!(total(with.get_predator()) > total(without.get_predator()));
+        assert_eq!(
+            with.to_output_table().get_columns(),
+            &vec!["step", "prey_stage_0", "predator_stage_0"]
+        );
+    }
+    #[test]
+    fn negative_parameters_are_rejected() {
+        let mut parameters = model(0.0).get_parameters();
+        parameters.handling_time = -1.0;
+        let original = model(0.0);
+        assert!(PredatorPrey::build(
+            original.prey,
+            original.prey_initial,
+            original.predator,
+            original.predator_initial,
+            parameters
+        )
+        .is_err());
+    }
+}
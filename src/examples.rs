@@ -0,0 +1,2 @@
+//! This module contains example data that ships with the crate, so analyses can be tried without preparing any input files first.
+pub mod datasets;
@@ -0,0 +1,175 @@
+//! This module contains classic published projection matrices, embedded in the crate so they can be used straight away for learning, demonstrations, and checking results against the literature.
+//!
+//! Each matrix is as published in the cited study. The initial populations are illustrative starting points chosen for demonstrations, not data from the studies.
+use crate::{PopulationMatrix, PopulationVector};
+
+/// This struct holds one example dataset: a published projection matrix with the names of its
+/// lifestages, where it was published, and an initial population to project.
+/// ```
+/// use ecolysis_cmd::examples::datasets;
+/// let turtles = datasets::dataset("loggerhead").unwrap();
+/// let lambda = turtles.get_matrix().lambda();
+/// assert!((lambda - 0.945).abs() < 1e-3);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExampleDataset {
+    name: &'static str,
+    title: &'static str,
+    description: &'static str,
+    citation: &'static str,
+    lifestages: &'static [&'static str],
+    matrix: &'static [&'static [f64]],
+    initial_population: &'static [f64],
+}
+impl ExampleDataset {
+    /// Return the short name used to look up the dataset, such as `loggerhead`.
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+    /// Return the common and scientific name of the species.
+    pub fn get_title(&self) -> &'static str {
+        self.title
+    }
+    /// Return a sentence or two about the study and what the matrix is known for.
+    pub fn get_description(&self) -> &'static str {
+        self.description
+    }
+    /// Return the reference for the published matrix.
+    pub fn get_citation(&self) -> &'static str {
+        self.citation
+    }
+    /// Return the name of each lifestage, in the order of the rows and columns of the matrix.
+    pub fn get_lifestages(&self) -> &'static [&'static str] {
+        self.lifestages
+    }
+    /// Return the published projection matrix.
+    pub fn get_matrix(&self) -> PopulationMatrix {
+        PopulationMatrix::build(self.matrix.iter().map(|row| row.to_vec()).collect())
+            .expect("Example matrices are square and valid.")
+    }
+    /// Return an illustrative initial population for projections.
+    pub fn get_initial_population(&self) -> PopulationVector {
+        PopulationVector::new(self.initial_population.to_vec())
+    }
+}
+
+const DATASETS: [ExampleDataset; 3] = [
+    ExampleDataset {
+        name: "loggerhead",
+        title: "Loggerhead sea turtle (Caretta caretta)",
+        description: "A stage-based model of loggerhead turtles nesting in Georgia, USA. It \
+            showed that protecting eggs on nesting beaches alone could not stop the decline, \
+            and that improving the survival of large juveniles (for example, with turtle \
+            excluder devices on shrimp trawls) mattered far more.",
+        citation: "Crouse, D. T., Crowder, L. B., & Caswell, H. (1987). A stage-based population \
+            model for loggerhead sea turtles and implications for conservation. Ecology, 68(5), \
+            1412-1423.",
+        lifestages: &[
+            "eggs and hatchlings",
+            "small juveniles",
+            "large juveniles",
+            "subadults",
+            "novice breeders",
+            "first-year remigrants",
+            "mature breeders",
+        ],
+        matrix: &[
+            &[0.0, 0.0, 0.0, 0.0, 127.0, 4.0, 80.0],
+            &[0.6747, 0.7370, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0486, 0.6610, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0147, 0.6907, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0518, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 0.8091, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 0.0, 0.8091, 0.8089],
+        ],
+        initial_population: &[2000.0, 1500.0, 900.0, 400.0, 30.0, 25.0, 150.0],
+    },
+    ExampleDataset {
+        name: "teasel",
+        title: "Teasel (Dipsacus sylvestris)",
+        description: "A stage-classified model of a monocarpic perennial plant, in which seeds \
+            can stay dormant in the soil and rosettes grow for several years before flowering \
+            once and dying. Flowering plants produce both seeds and, directly, new rosettes.",
+        citation: "Werner, P. A., & Caswell, H. (1977). Population growth rates and age versus \
+            stage-distribution models for teasel (Dipsacus sylvestris Huds.). Ecology, 58(5), \
+            1103-1111. Matrix as given in Caswell, H. (2001). Matrix Population Models (2nd \
+            ed.). Sinauer.",
+        lifestages: &[
+            "first-year dormant seeds",
+            "second-year dormant seeds",
+            "small rosettes",
+            "medium rosettes",
+            "large rosettes",
+            "flowering plants",
+        ],
+        matrix: &[
+            &[0.0, 0.0, 0.0, 0.0, 0.0, 322.38],
+            &[0.966, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.013, 0.010, 0.125, 0.0, 0.0, 3.448],
+            &[0.007, 0.0, 0.125, 0.238, 0.0, 30.170],
+            &[0.008, 0.0, 0.038, 0.245, 0.167, 0.862],
+            &[0.0, 0.0, 0.0, 0.023, 0.750, 0.0],
+        ],
+        initial_population: &[1000.0, 0.0, 50.0, 20.0, 10.0, 5.0],
+    },
+    ExampleDataset {
+        name: "killer_whale",
+        title: "Killer whale (Orcinus orca)",
+        description: "A stage-classified model of the female killer whales of the resident pods \
+            off British Columbia and Washington, with a post-reproductive stage. The population \
+            grows slowly, and lambda is most sensitive to the survival of mature females.",
+        citation: "Brault, S., & Caswell, H. (1993). Pod-specific demography of killer whales \
+            (Orcinus orca). Ecology, 74(5), 1444-1454.",
+        lifestages: &[
+            "yearlings",
+            "juveniles",
+            "mature females",
+            "post-reproductive females",
+        ],
+        matrix: &[
+            &[0.0, 0.0043, 0.1132, 0.0],
+            &[0.9775, 0.9111, 0.0, 0.0],
+            &[0.0, 0.0736, 0.9534, 0.0],
+            &[0.0, 0.0, 0.0452, 0.9804],
+        ],
+        initial_population: &[10.0, 30.0, 20.0, 10.0],
+    },
+];
+
+/// Return every example dataset.
+pub fn datasets() -> &'static [ExampleDataset] {
+    &DATASETS
+}
+
+/// Return the example dataset with the given name, or `None` if there is none.
+pub fn dataset(name: &str) -> Option<&'static ExampleDataset> {
+    DATASETS.iter().find(|x| x.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn published_growth_rates_are_reproduced() {
+        for (name, lambda) in [
+            ("loggerhead", 0.9450),
+            ("teasel", 2.3339),
+            ("killer_whale", 1.0254),
+        ] {
+            let found = dataset(name).unwrap().get_matrix().lambda();
+            assert!((found - lambda).abs() < 5e-4, "{}: {}", name, found);
+        }
+    }
+
+    #[test]
+    fn datasets_are_consistent() {
+        for example in datasets() {
+            let size = example.get_matrix().get_lifestage_count();
+            assert_eq!(example.get_lifestages().len(), size as usize);
+            assert_eq!(example.get_initial_population().get_lifestage_count(), size);
+            assert_eq!(dataset(example.get_name()), Some(example));
+        }
+        assert!(dataset("dodo").is_none());
+    }
+}
@@ -0,0 +1,473 @@
+//! This module contains tools for running experiments over many versions of a model, such as sweeping one or two vital rates over a range of values and recording results for each combination, comparing a model under several covariate futures, searching for a minimum viable population, estimating the power of monitoring designs, reporting several completed runs side by side, or keeping their full outputs together in a labeled ensemble.
+use crate::populations::population_level_simulation::{replicate_seed, PopulationMatrix};
+use std::{
+    error::Error,
+    io::Write,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+mod ensemble;
+mod minimum_viable_population;
+mod monitoring_power;
+mod run_registry;
+mod scenario_comparison;
+pub use ensemble::*;
+pub use minimum_viable_population::*;
+pub use monitoring_power::*;
+pub use run_registry::*;
+pub use scenario_comparison::*;
+
+/// This struct describes one parameter of a sweep: a single element of the projection matrix
+/// (given by its row and column) and the values it should take.
+#[derive(Clone)]
+pub struct SweepParameter {
+    name: String,
+    row: usize,
+    column: usize,
+    values: Vec<f64>,
+}
+impl SweepParameter {
+    /// Create a new Sweep Parameter for the matrix element at `(row, column)` that takes each of
+    /// the given values in turn.
+    pub fn new(name: &str, row: usize, column: usize, values: Vec<f64>) -> SweepParameter {
+        SweepParameter {
+            name: name.to_string(),
+            row,
+            column,
+            values,
+        }
+    }
+    /// Create a new Sweep Parameter whose values run from `start` to `end` (inclusive) in steps
+    /// of `step`. Values are calculated as `start + i * step` rather than by repeated addition, so
+    /// floating point errors do not build up along the range.
+    /// # Errors
+    /// Will return `Err<'static str>` if `step` is not positive or `end` is smaller than `start`.
+    /// ```
+    /// use ecolysis_cmd::experiments::SweepParameter;
+    /// let adult_survival = SweepParameter::range("adult_survival", 2, 2, 0.80, 0.95, 0.01).unwrap();
+    /// assert_eq!(adult_survival.get_values().len(), 16);
+    /// ```
+    pub fn range(
+        name: &str,
+        row: usize,
+        column: usize,
+        start: f64,
+        end: f64,
+        step: f64,
+    ) -> Result<SweepParameter, &'static str> {
+        if step.is_nan() || step <= 0.0 {
+            return Err("The step of a parameter range must be positive.");
+        }
+        if end < start {
+            return Err("The end of a parameter range cannot be smaller than its start.");
+        }
+        let count = ((end - start) / step + 1e-9).floor() as usize + 1;
+        let values = (0..count).map(|i| start + i as f64 * step).collect();
+        Ok(SweepParameter::new(name, row, column, values))
+    }
+    /// Return the name of the parameter.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the values the parameter takes.
+    pub fn get_values(&self) -> &Vec<f64> {
+        &self.values
+    }
+}
+
+/// This struct holds one combination of parameter values in a sweep, and the matrix built from
+/// it. It is passed to the model being run.
+pub struct SweepCell {
+    index: usize,
+    seed: u64,
+    parameter_values: Vec<f64>,
+    matrix: PopulationMatrix,
+}
+impl SweepCell {
+    /// Return the position of this cell in the sweep, starting at 0.
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+    /// Return the random seed for this cell, derived from the master seed of the sweep and the
+    /// cell's position. Stochastic models should use this seed so that results do not depend on
+    /// which thread ran the cell or in what order.
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    /// Return the value of each sweep parameter in this cell, in the order the parameters were
+    /// given.
+    pub fn get_parameter_values(&self) -> &Vec<f64> {
+        &self.parameter_values
+    }
+    /// Return the projection matrix with the parameter values of this cell applied.
+    pub fn get_matrix(&self) -> &PopulationMatrix {
+        &self.matrix
+    }
+}
+
+/// This struct describes a sweep of one or two parameters over a baseline projection matrix.
+/// Every combination of parameter values becomes one [`SweepCell`].
+pub struct ParameterSweep {
+    base: PopulationMatrix,
+    parameters: Vec<SweepParameter>,
+    seed: u64,
+}
+impl ParameterSweep {
+    /// Build a Parameter Sweep from a baseline matrix and one or two parameters.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are not one or two parameters, or if a parameter
+    /// refers to an element outside the matrix.
+    pub fn build(
+        base: PopulationMatrix,
+        parameters: Vec<SweepParameter>,
+    ) -> Result<ParameterSweep, &'static str> {
+        if parameters.is_empty() || parameters.len() > 2 {
+            return Err("A sweep must have one or two parameters.");
+        }
+        let size = base.get_lifestage_count() as usize;
+        if parameters
+            .iter()
+            .any(|parameter| parameter.row >= size || parameter.column >= size)
+        {
+            return Err("A sweep parameter refers to an element outside the matrix.");
+        }
+        Ok(ParameterSweep {
+            base,
+            parameters,
+            seed: 0,
+        })
+    }
+    /// Set the master seed from which the seed of every cell is derived. The default is 0.
+    pub fn with_seed(mut self, seed: u64) -> ParameterSweep {
+        self.seed = seed;
+        self
+    }
+    /// Return the parameters of the sweep.
+    pub fn get_parameters(&self) -> &Vec<SweepParameter> {
+        &self.parameters
+    }
+    /// Return every combination of parameter values as a Sweep Cell. When there are two
+    /// parameters, the second parameter varies fastest.
+    pub fn cells(&self) -> Vec<SweepCell> {
+        let mut combinations: Vec<Vec<f64>> = vec![vec![]];
+        for parameter in &self.parameters {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    parameter.values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push(*value);
+                        combination
+                    })
+                })
+                .collect();
+        }
+        combinations
+            .into_iter()
+            .enumerate()
+            .map(|(index, parameter_values)| {
+                let mut matrix = self.base.get_matrix().clone();
+                for (parameter, value) in self.parameters.iter().zip(&parameter_values) {
+                    matrix[parameter.row][parameter.column] = *value;
+                }
+                SweepCell {
+                    index,
+                    seed: replicate_seed(self.seed, index as u32),
+                    parameter_values,
+                    matrix: PopulationMatrix::build(matrix)
+                        .expect("The baseline matrix is already valid."),
+                }
+            })
+            .collect()
+    }
+    /// Run a model for every cell of the sweep. The model recieves the cell and returns a list of
+    /// named results (for example `("lambda", 1.02)`), which are collected into a long-format
+    /// results table.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::experiments::{ParameterSweep, SweepParameter};
+    /// let base = PopulationMatrix::build(vec![
+    ///     vec![0.0, 0.0, 0.1],
+    ///     vec![0.6, 0.8, 0.0],
+    ///     vec![0.0, 0.8, 0.95],
+    /// ]).unwrap();
+    /// let sweep = ParameterSweep::build(
+    ///     base,
+    ///     vec![SweepParameter::range("adult_survival", 2, 2, 0.80, 0.95, 0.01).unwrap()],
+    /// ).unwrap();
+    /// let results = sweep.run(|cell| vec![(String::from("lambda"), cell.get_matrix().lambda())]);
+    /// results.write_csv(std::io::stdout()).unwrap();
+    /// ```
+    pub fn run<F>(&self, model: F) -> SweepResults
+    where
+        F: Fn(&SweepCell) -> Vec<(String, f64)>,
+    {
+        let mut results = SweepResults::new(self.parameter_names());
+        for cell in self.cells() {
+            results.push_cell(&cell, model(&cell));
+        }
+        results
+    }
+    /// Run a model for every cell of the sweep like [`ParameterSweep::run`], spreading the cells
+    /// across `threads` threads (or one per available processor if `threads` is 0). Results are
+    /// returned in cell order, so they are identical to those of `run` no matter how many
+    /// threads are used, as long as the model only uses the cell's seed for randomness. On
+    /// `wasm32-unknown-unknown`, which has no threads, the cells are run one after another.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, StochasticPva};
+    /// use ecolysis_cmd::experiments::{ParameterSweep, SweepParameter};
+    /// let base = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let sweep = ParameterSweep::build(
+    ///     base,
+    ///     vec![SweepParameter::range("adult_survival", 1, 1, 0.1, 0.8, 0.1).unwrap()],
+    /// ).unwrap().with_seed(42);
+    /// let results = sweep.run_parallel(0, |cell| {
+    ///     let population = StochasticPva::build_from_vectors(
+    ///         vec![5.0, 5.0],
+    ///         vec![cell.get_matrix().get_matrix().clone()],
+    ///     ).unwrap();
+    ///     let summary = population.stochastic_summary(20, 100, cell.get_seed(), 1.0);
+    ///     vec![(String::from("extinction"), summary.extinction_probability())]
+    /// });
+    /// assert_eq!(results.get_rows().len(), 8);
+    /// ```
+    pub fn run_parallel<F>(&self, threads: usize, model: F) -> SweepResults
+    where
+        F: Fn(&SweepCell) -> Vec<(String, f64)> + Sync,
+    {
+        let cells = self.cells();
+        let mut results = SweepResults::new(self.parameter_names());
+        for (cell, metrics) in cells.iter().zip(parallel_map(&cells, threads, model)) {
+            results.push_cell(cell, metrics);
+        }
+        results
+    }
+    fn parameter_names(&self) -> Vec<String> {
+        self.parameters
+            .iter()
+            .map(|parameter| parameter.name.clone())
+            .collect()
+    }
+}
+
+/// Apply `f` to every item on `threads` threads (or one per available processor if `threads` is
+/// 0), returning the results in the order of the items. Threads take the next unstarted item as
+/// they finish, so slow items do not hold up the others. With one thread, or on
+/// `wasm32-unknown-unknown`, which has no threads, the items are run one after another.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    }
+    .min(items.len());
+    if threads <= 1 || cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return items.iter().map(f).collect();
+    }
+    let next_item = AtomicUsize::new(0);
+    let mut finished: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+                    loop {
+                        let index = next_item.fetch_add(1, Ordering::Relaxed);
+                        match items.get(index) {
+                            Some(item) => finished.push((index, f(item))),
+                            None => return finished,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("A worker thread panicked."))
+            .collect()
+    });
+    finished.sort_by_key(|(index, _)| *index);
+    finished.into_iter().map(|(_, result)| result).collect()
+}
+
+/// One row of a long-format sweep results table: the parameter values of a cell, the name of a
+/// result, and its value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepRow {
+    parameter_values: Vec<f64>,
+    metric: String,
+    value: f64,
+}
+impl SweepRow {
+    /// Return the parameter values of the cell this row came from.
+    pub fn get_parameter_values(&self) -> &Vec<f64> {
+        &self.parameter_values
+    }
+    /// Return the name of the result.
+    pub fn get_metric(&self) -> &str {
+        &self.metric
+    }
+    /// Return the value of the result.
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// This struct stores the results of a Parameter Sweep in long format, with one row for each
+/// result of each cell.
+pub struct SweepResults {
+    parameter_names: Vec<String>,
+    rows: Vec<SweepRow>,
+}
+impl SweepResults {
+    fn new(parameter_names: Vec<String>) -> SweepResults {
+        SweepResults {
+            parameter_names,
+            rows: Vec::new(),
+        }
+    }
+    fn push_cell(&mut self, cell: &SweepCell, metrics: Vec<(String, f64)>) {
+        for (metric, value) in metrics {
+            self.rows.push(SweepRow {
+                parameter_values: cell.parameter_values.clone(),
+                metric,
+                value,
+            });
+        }
+    }
+    /// Return the names of the sweep parameters.
+    pub fn get_parameter_names(&self) -> &Vec<String> {
+        &self.parameter_names
+    }
+    /// Return every row of the results.
+    pub fn get_rows(&self) -> &Vec<SweepRow> {
+        &self.rows
+    }
+    /// Return the rows for a single named result.
+    pub fn rows_for(&self, metric: &str) -> Vec<&SweepRow> {
+        self.rows
+            .iter()
+            .filter(|row| row.metric == metric)
+            .collect()
+    }
+    /// Write the results as a long-format CSV with one column per parameter, then `metric` and
+    /// `value` columns.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        let mut header = self.parameter_names.clone();
+        header.push(String::from("metric"));
+        header.push(String::from("value"));
+        csv_writer.write_record(&header)?;
+        for row in &self.rows {
+            let mut record: Vec<String> =
+                row.parameter_values.iter().map(|x| x.to_string()).collect();
+            record.push(row.metric.clone());
+            record.push(row.value.to_string());
+            csv_writer.write_record(&record)?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    #[test]
+    fn range_endpoints() {
+        let parameter = SweepParameter::range("s", 1, 1, 0.1, 0.3, 0.1).unwrap();
+        assert_eq!(parameter.get_values().len(), 3);
+        assert!((parameter.get_values()[2] - 0.3).abs() < 1e-12);
+        assert!(SweepParameter::range("s", 1, 1, 0.3, 0.1, 0.1).is_err());
+        assert!(SweepParameter::range("s", 1, 1, 0.1, 0.3, 0.0).is_err());
+    }
+    #[test]
+    fn two_parameter_grid() {
+        let sweep = ParameterSweep::build(
+            base(),
+            vec![
+                SweepParameter::new("juvenile", 1, 0, vec![0.3, 0.4]),
+                SweepParameter::new("adult", 1, 1, vec![0.7, 0.8, 0.9]),
+            ],
+        )
+        .unwrap();
+        let cells = sweep.cells();
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells[1].get_parameter_values(), &vec![0.3, 0.8]);
+        assert_eq!(cells[1].get_matrix()[(1, 1)], 0.8);
+        assert_eq!(cells[1].get_matrix()[(1, 0)], 0.3);
+    }
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert!(ParameterSweep::build(base(), vec![]).is_err());
+        assert!(
+            ParameterSweep::build(base(), vec![SweepParameter::new("x", 2, 0, vec![1.0])]).is_err()
+        );
+    }
+    #[test]
+    fn parallel_matches_sequential() {
+        let sweep = ParameterSweep::build(
+            base(),
+            vec![
+                SweepParameter::range("juvenile", 1, 0, 0.1, 0.5, 0.1).unwrap(),
+                SweepParameter::range("adult", 1, 1, 0.1, 0.9, 0.2).unwrap(),
+            ],
+        )
+        .unwrap()
+        .with_seed(7);
+        let model = |cell: &SweepCell| {
+            vec![
+                (String::from("lambda"), cell.get_matrix().lambda()),
+                (String::from("seed"), cell.get_seed() as f64),
+            ]
+        };
+        let sequential = sweep.run(model);
+        for threads in [1, 3, 8] {
+            assert_eq!(
+                sweep.run_parallel(threads, model).get_rows(),
+                sequential.get_rows()
+            );
+        }
+    }
+    #[test]
+    fn cell_seeds_depend_on_master_seed() {
+        let parameters = vec![SweepParameter::new("adult", 1, 1, vec![0.7, 0.9])];
+        let first = ParameterSweep::build(base(), parameters.clone()).unwrap();
+        let second = ParameterSweep::build(base(), parameters)
+            .unwrap()
+            .with_seed(1);
+        let (a, b) = (first.cells(), second.cells());
+        assert_ne!(a[0].get_seed(), a[1].get_seed());
+        assert_ne!(a[0].get_seed(), b[0].get_seed());
+    }
+    #[test]
+    fn long_format_results() {
+        let sweep = ParameterSweep::build(
+            base(),
+            vec![SweepParameter::new("adult", 1, 1, vec![0.7, 0.9])],
+        )
+        .unwrap();
+        let results = sweep.run(|cell| {
+            vec![
+                (String::from("lambda"), cell.get_matrix().lambda()),
+                (String::from("index"), cell.get_index() as f64),
+            ]
+        });
+        assert_eq!(results.get_rows().len(), 4);
+        let lambdas = results.rows_for("lambda");
+        assert!(lambdas[0].get_value() < lambdas[1].get_value());
+        let mut buffer = Vec::new();
+        results.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.starts_with("adult,metric,value\n0.7,lambda,"));
+    }
+}
@@ -0,0 +1,383 @@
+//! This module keeps the complete outputs of several runs (different scenarios, seeds, or models) together in one ensemble, each under a label and with the parameter values that set it apart, so runs can be looked up by label or parameter and exported as one table.
+use super::{RunOutput, RunRegistry, RunReport};
+use crate::outputs::{OutputTable, ToOutputTable};
+use crate::populations::population_level_simulation::{
+    PvaDeterministicOutput, PvaStochasticOutput, PvaStochasticSummary,
+};
+use crate::provenance::RunMetadata;
+use std::{error::Error, fmt::Display, io::Write};
+
+/// This enum holds the output of one run of an ensemble.
+pub enum EnsembleOutput {
+    Deterministic(PvaDeterministicOutput),
+    Stochastic(PvaStochasticOutput),
+    Summary(PvaStochasticSummary),
+}
+impl EnsembleOutput {
+    /// Return `deterministic`, `stochastic`, or `summary`, depending on the type of output.
+    pub fn get_kind(&self) -> &'static str {
+        match self {
+            EnsembleOutput::Deterministic(_) => "deterministic",
+            EnsembleOutput::Stochastic(_) => "stochastic",
+            EnsembleOutput::Summary(_) => "summary",
+        }
+    }
+}
+impl ToOutputTable for EnsembleOutput {
+    fn to_output_table(&self) -> OutputTable {
+        match self {
+            EnsembleOutput::Deterministic(output) => output.to_output_table(),
+            EnsembleOutput::Stochastic(output) => output.to_output_table(),
+            EnsembleOutput::Summary(output) => output.to_output_table(),
+        }
+    }
+}
+impl RunOutput for EnsembleOutput {
+    fn report(&self, label: &str, extinction_threshold: f64) -> RunReport {
+        match self {
+            EnsembleOutput::Deterministic(output) => output.report(label, extinction_threshold),
+            EnsembleOutput::Stochastic(output) => output.report(label, extinction_threshold),
+            EnsembleOutput::Summary(output) => output.report(label, extinction_threshold),
+        }
+    }
+}
+impl From<PvaDeterministicOutput> for EnsembleOutput {
+    fn from(output: PvaDeterministicOutput) -> EnsembleOutput {
+        EnsembleOutput::Deterministic(output)
+    }
+}
+impl From<PvaStochasticOutput> for EnsembleOutput {
+    fn from(output: PvaStochasticOutput) -> EnsembleOutput {
+        EnsembleOutput::Stochastic(output)
+    }
+}
+impl From<PvaStochasticSummary> for EnsembleOutput {
+    fn from(output: PvaStochasticSummary) -> EnsembleOutput {
+        EnsembleOutput::Summary(output)
+    }
+}
+
+/// This struct holds one run of an ensemble: its label, the parameters that describe it (as
+/// name and value text, like the parameters of [`RunMetadata`]), and its output. The output can
+/// be of any type that converts to an [`OutputTable`]; use [`EnsembleOutput`] to mix
+/// deterministic and stochastic outputs in one ensemble.
+pub struct EnsembleMember<T = EnsembleOutput> {
+    label: String,
+    parameters: Vec<(String, String)>,
+    output: T,
+}
+impl<T: ToOutputTable> EnsembleMember<T> {
+    /// Create a member with no parameters.
+    pub fn new(label: &str, output: T) -> EnsembleMember<T> {
+        EnsembleMember {
+            label: label.to_string(),
+            parameters: Vec::new(),
+            output,
+        }
+    }
+    /// Return the member with a parameter added, replacing any earlier value of the same name.
+    pub fn with_parameter<V: Display>(mut self, name: &str, value: V) -> Self {
+        let value = value.to_string();
+        match self.parameters.iter_mut().find(|(x, _)| x == name) {
+            Some(parameter) => parameter.1 = value,
+            None => self.parameters.push((name.to_string(), value)),
+        }
+        self
+    }
+    /// Return the member with the seed (if any) and every parameter of a run's metadata added.
+    pub fn with_metadata(mut self, metadata: &RunMetadata) -> Self {
+        if let Some(seed) = metadata.get_seed() {
+            self = self.with_parameter("seed", seed);
+        }
+        for (name, value) in metadata.get_parameters() {
+            self = self.with_parameter(name, value);
+        }
+        self
+    }
+    /// Return the label of the member.
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+    /// Return the parameters of the member, in the order they were added.
+    pub fn get_parameters(&self) -> &Vec<(String, String)> {
+        &self.parameters
+    }
+    /// Return the value of a parameter, if the member has it.
+    pub fn get_parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+    /// Return the output of the member.
+    pub fn get_output(&self) -> &T {
+        &self.output
+    }
+}
+
+/// This struct keeps labeled outputs of several runs together. Unlike a [`RunRegistry`], which
+/// only keeps summary measures, an ensemble holds every output in full, so members can be looked
+/// up and exported jointly. Members can hold any output that converts to an [`OutputTable`],
+/// including the tables of individual-based runs, sample projections, and
+/// [`InitialPopulationOutputs`](crate::populations::population_level_simulation::InitialPopulationOutputs).
+/// ```
+/// use ecolysis_cmd::StochasticPva;
+/// use ecolysis_cmd::experiments::{Ensemble, EnsembleMember};
+/// let model = StochasticPva::build_from_vectors(vec![20.0, 20.0], vec![
+///     vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+///     vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+/// ]).unwrap();
+/// let mut ensemble = Ensemble::new();
+/// for seed in [1, 2, 3] {
+///     let output = model.stochastic_projection(10, 20, seed);
+///     let member = EnsembleMember::new(&format!("seed {}", seed), output).with_parameter("seed", seed);
+///     ensemble.add(member).unwrap();
+/// }
+/// assert_eq!(ensemble.find_by_parameter("seed", "2")[0].get_label(), "seed 2");
+/// let mut csv = Vec::new();
+/// ensemble.write_csv(&mut csv).unwrap();
+/// assert!(String::from_utf8(csv).unwrap().starts_with("label,seed,replicate,step,"));
+/// ```
+pub struct Ensemble<T = EnsembleOutput> {
+    members: Vec<EnsembleMember<T>>,
+}
+impl<T: ToOutputTable> Default for Ensemble<T> {
+    fn default() -> Self {
+        Ensemble::new()
+    }
+}
+impl<T: ToOutputTable> Ensemble<T> {
+    /// Create an empty ensemble.
+    pub fn new() -> Ensemble<T> {
+        Ensemble {
+            members: Vec::new(),
+        }
+    }
+    /// Add a member to the ensemble.
+    /// # Errors
+    /// Will return `Err<'static str>` if the ensemble already has a member with the same label.
+    pub fn add(&mut self, member: EnsembleMember<T>) -> Result<(), &'static str> {
+        if self.get(&member.label).is_some() {
+            return Err("The ensemble already has a member with this label.");
+        }
+        self.members.push(member);
+        Ok(())
+    }
+    /// Return the members of the ensemble, in the order they were added.
+    pub fn get_members(&self) -> &Vec<EnsembleMember<T>> {
+        &self.members
+    }
+    /// Return the number of members.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+    /// Return true if the ensemble has no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+    /// Return the labels of the members, in the order they were added.
+    pub fn labels(&self) -> Vec<&str> {
+        self.members.iter().map(|x| x.label.as_str()).collect()
+    }
+    /// Return the member with a label, if there is one.
+    pub fn get(&self, label: &str) -> Option<&EnsembleMember<T>> {
+        self.members.iter().find(|x| x.label == label)
+    }
+    /// Return the members whose parameter `name` has the value `value`, compared as text.
+    pub fn find_by_parameter(&self, name: &str, value: &str) -> Vec<&EnsembleMember<T>> {
+        self.members
+            .iter()
+            .filter(|x| x.get_parameter(name) == Some(value))
+            .collect()
+    }
+    /// Return every distinct value of a parameter among the members, in the order they first
+    /// appear.
+    pub fn parameter_values(&self, name: &str) -> Vec<&str> {
+        let mut values: Vec<&str> = Vec::new();
+        for value in self.members.iter().filter_map(|x| x.get_parameter(name)) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        values
+    }
+    /// Return the names of every parameter used by any member, in the order they first appear.
+    pub fn parameter_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in self.members.iter().flat_map(|x| &x.parameters) {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+    /// Return a [`RunRegistry`] with the comparison measures of every member, counting
+    /// deterministic and stochastic outputs as extinct when their total population falls to or
+    /// below `extinction_threshold`.
+    pub fn report(&self, extinction_threshold: f64) -> RunRegistry
+    where
+        T: RunOutput,
+    {
+        let mut registry = RunRegistry::new(extinction_threshold);
+        for member in &self.members {
+            registry.add(&member.label, &member.output);
+        }
+        registry
+    }
+    /// Write the outputs of every member as one CSV table. Each row starts with the member's
+    /// label and a column for each parameter, followed by the columns of the outputs. Members of
+    /// different kinds have different output columns, so the table has every column of any
+    /// member, and values a member does not have (including parameters it was not given) are
+    /// left empty. A parameter whose name is `label` or the name of an output column is written
+    /// under its name prefixed with `parameter_`.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let parameters = self.parameter_names();
+        let tables: Vec<_> = self
+            .members
+            .iter()
+            .map(|x| x.output.to_output_table())
+            .collect();
+        let mut columns: Vec<&String> = Vec::new();
+        for column in tables.iter().flat_map(|x| x.get_columns()) {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        let mut headers = vec![String::from("label")];
+        for name in &parameters {
+            let mut header = name.to_string();
+            while headers.contains(&header) || columns.contains(&&header) {
+                header = format!("parameter_{}", header);
+            }
+            headers.push(header);
+        }
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record(
+            headers
+                .iter()
+                .map(String::as_str)
+                .chain(columns.iter().map(|x| x.as_str())),
+        )?;
+        for (member, table) in self.members.iter().zip(&tables) {
+            let positions: Vec<Option<usize>> = columns
+                .iter()
+                .map(|column| table.get_columns().iter().position(|x| x == *column))
+                .collect();
+            for row in table.get_rows() {
+                let mut record = vec![member.label.clone()];
+                record.extend(
+                    parameters
+                        .iter()
+                        .map(|x| member.get_parameter(x).unwrap_or("").to_string()),
+                );
+                record.extend(
+                    positions
+                        .iter()
+                        .map(|x| x.map_or(String::new(), |x| row[x].to_string())),
+                );
+                csv_writer.write_record(record)?;
+            }
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DeterministicPva, StochasticPva};
+
+    fn ensemble() -> Ensemble {
+        let matrices = vec![
+            vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+            vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+        ];
+        let deterministic =
+            DeterministicPva::build_from_vectors(vec![20.0, 20.0], matrices[0].clone()).unwrap();
+        let stochastic = StochasticPva::build_from_vectors(vec![20.0, 20.0], matrices).unwrap();
+        let mut metadata = RunMetadata::new().with_seed(7);
+        metadata.add_parameter("matrices", 2);
+        let mut ensemble = Ensemble::new();
+        ensemble
+            .add(
+                EnsembleMember::new("good", deterministic.deterministic_projection(3).into())
+                    .with_parameter("matrices", 1),
+            )
+            .unwrap();
+        ensemble
+            .add(
+                EnsembleMember::new("variable", stochastic.stochastic_projection(3, 2, 7).into())
+                    .with_metadata(&metadata),
+            )
+            .unwrap();
+        ensemble
+            .add(EnsembleMember::new(
+                "summary",
+                stochastic.stochastic_summary(3, 10, 7, 1.0).into(),
+            ))
+            .unwrap();
+        ensemble
+    }
+
+    #[test]
+    fn members_are_found_by_label_and_parameter() {
+        let mut ensemble = ensemble();
+        assert_eq!(ensemble.labels(), vec!["good", "variable", "summary"]);
+        assert_eq!(ensemble.parameter_names(), vec!["matrices", "seed"]);
+        assert_eq!(ensemble.parameter_values("matrices"), vec!["1", "2"]);
+        assert_eq!(
+            ensemble.get("variable").unwrap().get_output().get_kind(),
+            "stochastic"
+        );
+        assert_eq!(ensemble.find_by_parameter("seed", "7").len(), 1);
+        assert!(ensemble.find_by_parameter("seed", "8").is_empty());
+        assert!(ensemble.get("missing").is_none());
+        let duplicate = DeterministicPva::build_from_vectors(vec![1.0], vec![vec![1.0]])
+            .unwrap()
+            .deterministic_projection(1);
+        assert!(ensemble
+            .add(EnsembleMember::new("good", duplicate.into()))
+            .is_err());
+        assert_eq!(ensemble.len(), 3);
+        assert_eq!(ensemble.report(1.0).get_reports()[2].kind, "summary");
+    }
+
+    #[test]
+    fn members_are_exported_together() {
+        let mut csv = Vec::new();
+        ensemble().write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].starts_with("label,matrices,seed,step,stage_0,stage_1,replicate,"));
+        // Three deterministic steps, two replicates of three steps, and three summary steps.
+        assert_eq!(lines.len(), 1 + 3 + 6 + 3);
+        assert!(lines[1].starts_with("good,1,,1,"));
+        assert!(lines[4].starts_with("variable,2,7,"));
+        assert!(lines[12].starts_with("summary,,,"));
+    }
+
+    #[test]
+    fn any_output_table_can_be_a_member() {
+        let table = |value: f64| {
+            OutputTable::build(
+                vec![String::from("step"), String::from("total")],
+                vec![vec![1.0, value]],
+            )
+            .unwrap()
+        };
+        let mut ensemble = Ensemble::new();
+        ensemble
+            .add(EnsembleMember::new("first", table(3.0)).with_parameter("step", "annual"))
+            .unwrap();
+        ensemble
+            .add(EnsembleMember::new("second", table(4.0)).with_parameter("label", "b"))
+            .unwrap();
+        let mut csv = Vec::new();
+        ensemble.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "label,parameter_step,parameter_label,step,total\nfirst,annual,,1,3\nsecond,,b,1,4\n"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! This module estimates the minimum viable population (MVP) of a stochastic model: the smallest initial population that persists with a chosen probability over a chosen time horizon.
+use crate::populations::population_level_simulation::{ProjectionConfig, StochasticPva};
+
+/// This struct holds the result of a minimum viable population search: the smallest initial
+/// total population found to reach the target persistence probability, and every population size
+/// tried along the way with its estimated persistence probability.
+pub struct MinimumViablePopulation {
+    size: u32,
+    persistence: f64,
+    evaluations: Vec<(u32, f64)>,
+}
+impl MinimumViablePopulation {
+    /// Search for the smallest whole-number initial population (up to `max_size`) whose
+    /// probability of persisting, that is of staying above the configured extinction threshold
+    /// for the configured number of steps, is at least `target_persistence`.
+    ///
+    /// Each candidate size is simulated with the configured number of replicates, starting from
+    /// the model's initial population rescaled to that total, so its stage structure is kept.
+    /// Every candidate uses the same seed (common random numbers), which keeps persistence from
+    /// decreasing with population size because of sampling noise alone, so the search can bisect
+    /// between sizes that fail and succeed.
+    /// # Errors
+    /// Will return `Err<'static str>` if `target_persistence` is not between 0 and 1, if the
+    /// model's initial population is empty, or if even `max_size` does not reach the target.
+    /// ```
+    /// use ecolysis_cmd::StochasticPva;
+    /// use ecolysis_cmd::experiments::MinimumViablePopulation;
+    /// use ecolysis_cmd::populations::population_level_simulation::ProjectionConfig;
+    /// let model = StochasticPva::build_from_vectors(vec![1.0, 1.0], vec![
+    ///     vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+    ///     vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+    /// ]).unwrap();
+    /// let config = ProjectionConfig::new().steps(50).replicates(200).seed(3).extinction_threshold(10.0);
+    /// let mvp = MinimumViablePopulation::search(&model, &config, 0.9, 100_000).unwrap();
+    /// assert!(mvp.get_persistence() >= 0.9);
+    /// ```
+    pub fn search(
+        model: &StochasticPva,
+        config: &ProjectionConfig,
+        target_persistence: f64,
+        max_size: u32,
+    ) -> Result<MinimumViablePopulation, &'static str> {
+        if !(0.0..=1.0).contains(&target_persistence) {
+            return Err("The target persistence probability must be between 0 and 1.");
+        }
+        let total = model.get_initial_population().total();
+        if !total.is_finite() || total <= 0.0 {
+            return Err(
+                "The model's initial population has no individuals, so it gives no stage structure to scale.",
+            );
+        }
+        let structure = model.get_initial_population().proportions();
+        let mut evaluations = Vec::new();
+        let mut persistence = |size: u32| -> Result<f64, &'static str> {
+            let rescaled = model.with_initial_population(&structure * size as f64)?;
+            let probability = 1.0 - rescaled.summarize(config)?.extinction_probability();
+            evaluations.push((size, probability));
+            Ok(probability)
+        };
+        let mut upper = (max_size, persistence(max_size)?);
+        if upper.1 < target_persistence {
+            return Err(
+                "The target persistence is not reached within the maximum population size.",
+            );
+        }
+        let mut lower = 0;
+        while upper.0 - lower > 1 {
+            let middle = lower + (upper.0 - lower) / 2;
+            let probability = persistence(middle)?;
+            if probability >= target_persistence {
+                upper = (middle, probability);
+            } else {
+                lower = middle;
+            }
+        }
+        Ok(MinimumViablePopulation {
+            size: upper.0,
+            persistence: upper.1,
+            evaluations,
+        })
+    }
+    /// Return the minimum viable population size.
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+    /// Return the estimated persistence probability of the minimum viable population.
+    pub fn get_persistence(&self) -> f64 {
+        self.persistence
+    }
+    /// Return each population size tried by the search, in order, with its estimated
+    /// persistence probability.
+    pub fn get_evaluations(&self) -> &Vec<(u32, f64)> {
+        &self.evaluations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> StochasticPva {
+        StochasticPva::build_from_vectors(
+            vec![3.0, 1.0],
+            vec![
+                vec![vec![0.0, 1.8], vec![0.5, 0.7]],
+                vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn smaller_population_misses_target() {
+        let config = ProjectionConfig::new()
+            .steps(30)
+            .replicates(300)
+            .seed(11)
+            .extinction_threshold(20.0);
+        let mvp = MinimumViablePopulation::search(&model(), &config, 0.8, 50_000).unwrap();
+        assert!(mvp.get_persistence() >= 0.8);
+        let below = mvp
+            .get_evaluations()
+            .iter()
+            .find(|(size, _)| *size == mvp.get_size() - 1);
+        assert!(below.is_some_and(|(_, probability)| *probability < 0.8));
+    }
+    #[test]
+    fn unreachable_target_is_an_error() {
+        let config = ProjectionConfig::new()
+            .steps(30)
+            .replicates(50)
+            .extinction_threshold(20.0);
+        assert!(MinimumViablePopulation::search(&model(), &config, 0.9, 25).is_err());
+        assert!(MinimumViablePopulation::search(&model(), &config, 1.5, 1_000).is_err());
+        let empty = model()
+            .with_initial_population(vec![0.0, 0.0].into())
+            .unwrap();
+        assert!(MinimumViablePopulation::search(&empty, &config, 0.5, 1_000).is_err());
+    }
+}
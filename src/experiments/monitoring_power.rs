@@ -0,0 +1,440 @@
+//! This module estimates the statistical power of monitoring designs: the probability that a survey program of a given length and frequency detects a given rate of decline. The population and its survey counts are simulated many times, and each simulated series of counts is tested for a declining trend.
+use crate::populations::population_level_simulation::{
+    replicate_seed, ObservationModel, PopulationMatrix, PopulationVector, StochasticPva,
+};
+use crate::statistics::student_t_cdf;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{error::Error, io::Write};
+
+/// Mixed into the seeds of simulated survey counts so that they are independent of the
+/// population's own random draws.
+const SURVEY_STREAM: u64 = 0x6D6F_6E69_746F_7273;
+
+/// This struct describes a monitoring design: a survey at the start (step 0) and then every
+/// `interval` steps up to `duration` steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MonitoringDesign {
+    duration: u32,
+    interval: u32,
+}
+impl MonitoringDesign {
+    /// Create a monitoring design that surveys every `interval` steps for `duration` steps.
+    /// # Errors
+    /// Will return `Err<'static str>` if `interval` is zero or the design has fewer than three
+    /// surveys, the fewest that a trend and its standard error can be estimated from.
+    pub fn new(duration: u32, interval: u32) -> Result<MonitoringDesign, &'static str> {
+        if interval == 0 {
+            return Err("The interval between surveys must be at least one step.");
+        }
+        if duration / interval < 2 {
+            return Err("A monitoring design needs at least three surveys.");
+        }
+        Ok(MonitoringDesign { duration, interval })
+    }
+    /// Return the number of steps the monitoring lasts.
+    pub fn get_duration(&self) -> u32 {
+        self.duration
+    }
+    /// Return the number of steps between surveys.
+    pub fn get_interval(&self) -> u32 {
+        self.interval
+    }
+    /// Return the steps at which surveys are made, starting with step 0.
+    pub fn survey_steps(&self) -> Vec<u32> {
+        (0..=self.duration)
+            .step_by(self.interval as usize)
+            .collect()
+    }
+}
+
+/// This struct holds the estimated power of one monitoring design against one rate of decline.
+/// - `decline`: the proportional decline per step the model was rescaled to, or `None` if the
+///   model was used as given.
+/// - `surveys`: the number of surveys in the design.
+/// - `power`: the proportion of simulated survey series in which a declining trend was detected.
+/// - `mean_trend`: the mean estimated proportional change per step, `exp(slope) - 1`, which is
+///   negative for a decline. Comparing it with `decline` shows how biased the trend estimate is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PowerEstimate {
+    decline: Option<f64>,
+    duration: u32,
+    interval: u32,
+    surveys: usize,
+    power: f64,
+    mean_trend: f64,
+}
+impl PowerEstimate {
+    /// Return the proportional decline per step the model was rescaled to, or `None` if the
+    /// model was used as given.
+    pub fn get_decline(&self) -> Option<f64> {
+        self.decline
+    }
+    /// Return the number of steps the design monitors for.
+    pub fn get_duration(&self) -> u32 {
+        self.duration
+    }
+    /// Return the number of steps between surveys in the design.
+    pub fn get_interval(&self) -> u32 {
+        self.interval
+    }
+    /// Return the number of surveys in the design.
+    pub fn get_surveys(&self) -> usize {
+        self.surveys
+    }
+    /// Return the proportion of simulated survey series in which a declining trend was detected.
+    pub fn get_power(&self) -> f64 {
+        self.power
+    }
+    /// Return the mean estimated proportional change per step.
+    pub fn get_mean_trend(&self) -> f64 {
+        self.mean_trend
+    }
+}
+
+/// This struct estimates the power of monitoring designs to detect a decline in a stochastic
+/// model. Each replicate simulates the population, counts it at every step with the observation
+/// model, and regresses the log counts (`ln(count + 1)`) of each design's surveys on time. A
+/// decline is detected when a one-sided t-test finds the slope below zero at the significance
+/// level `alpha`. Every design sees the same simulated populations and counts (common random
+/// numbers), so differences between designs are not blurred by sampling noise.
+///
+/// The test treats the deviations of the counts from the trend as independent, which holds for
+/// observation error but not for environmental variation carried over from step to step, so
+/// strongly variable models detect declines that are not there more often than `alpha`.
+/// ```
+/// use ecolysis_cmd::experiments::{MonitoringDesign, PowerAnalysis};
+/// use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+/// let model = StochasticPva::build_from_vectors(vec![100.0, 100.0], vec![
+///     vec![vec![0.0, 1.4], vec![0.5, 0.7]],
+///     vec![vec![0.0, 0.8], vec![0.4, 0.6]],
+/// ]).unwrap();
+/// let analysis = PowerAnalysis::new(model, "lognormal 0.3".parse().unwrap())
+///     .with_replicates(200)
+///     .unwrap();
+/// let designs = [
+///     MonitoringDesign::new(5, 1).unwrap(),
+///     MonitoringDesign::new(20, 1).unwrap(),
+/// ];
+/// let results = analysis.run(&[0.05], &designs).unwrap();
+/// let estimates = results.get_estimates();
+/// // Monitoring for longer makes a 5% decline per step easier to detect.
+/// assert!(estimates[1].get_power() > estimates[0].get_power());
+/// ```
+#[derive(Clone)]
+pub struct PowerAnalysis {
+    model: StochasticPva,
+    observation: ObservationModel,
+    replicates: u32,
+    alpha: f64,
+    seed: u64,
+}
+impl PowerAnalysis {
+    /// Create a power analysis of `model` surveyed with `observation`, using 500 replicates, a
+    /// significance level of 0.05, and a seed of 0.
+    pub fn new(model: StochasticPva, observation: ObservationModel) -> PowerAnalysis {
+        PowerAnalysis {
+            model,
+            observation,
+            replicates: 500,
+            alpha: 0.05,
+            seed: 0,
+        }
+    }
+    /// Return the analysis with a different number of replicates.
+    /// # Errors
+    /// Will return `Err<'static str>` if `replicates` is zero.
+    pub fn with_replicates(mut self, replicates: u32) -> Result<Self, &'static str> {
+        if replicates == 0 {
+            return Err("A power analysis needs at least one replicate.");
+        }
+        self.replicates = replicates;
+        Ok(self)
+    }
+    /// Return the analysis with a different significance level for the trend test.
+    /// # Errors
+    /// Will return `Err<'static str>` if `alpha` is not between zero and one.
+    pub fn with_alpha(mut self, alpha: f64) -> Result<Self, &'static str> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err("The significance level must be between zero and one.");
+        }
+        self.alpha = alpha;
+        Ok(self)
+    }
+    /// Return the analysis with a different seed for the random number generator.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    /// Return the model being monitored.
+    pub fn get_model(&self) -> &StochasticPva {
+        &self.model
+    }
+    /// Return the observation model of the surveys.
+    pub fn get_observation(&self) -> ObservationModel {
+        self.observation
+    }
+    /// Return the number of replicates.
+    pub fn get_replicates(&self) -> u32 {
+        self.replicates
+    }
+    /// Return the significance level of the trend test.
+    pub fn get_alpha(&self) -> f64 {
+        self.alpha
+    }
+    /// Return the seed of the random number generator.
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    /// Estimate the power of each design to detect the trend of the model as it is given.
+    pub fn power(&self, designs: &[MonitoringDesign]) -> PowerResults {
+        PowerResults {
+            alpha: self.alpha,
+            estimates: self.estimate(&self.model, None, designs),
+        }
+    }
+    /// Estimate the power of each design against each rate of decline, given as a proportion
+    /// per step (0.05 is a 5% decline per step). For each decline every matrix of the model is
+    /// multiplied by the same factor so that the growth rate of their mean matrix is
+    /// `1 - decline`, which keeps the model's stage structure and environmental variation.
+    /// Results are ordered by decline, then by design.
+    /// # Errors
+    /// Will return `Err<'static str>` if a decline is not below one or the mean matrix of the
+    /// model has no positive growth rate to rescale.
+    pub fn run(
+        &self,
+        declines: &[f64],
+        designs: &[MonitoringDesign],
+    ) -> Result<PowerResults, &'static str> {
+        let mut estimates = Vec::with_capacity(declines.len() * designs.len());
+        for decline in declines {
+            let model = declining_model(&self.model, *decline)?;
+            estimates.extend(self.estimate(&model, Some(*decline), designs));
+        }
+        Ok(PowerResults {
+            alpha: self.alpha,
+            estimates,
+        })
+    }
+    fn estimate(
+        &self,
+        model: &StochasticPva,
+        decline: Option<f64>,
+        designs: &[MonitoringDesign],
+    ) -> Vec<PowerEstimate> {
+        let steps = designs.iter().map(|x| x.duration).max().unwrap_or(0);
+        let mut detections = vec![0u32; designs.len()];
+        let mut trends = vec![0.0; designs.len()];
+        for replicate in 0..self.replicates {
+            let mut rng =
+                StdRng::seed_from_u64(replicate_seed(self.seed, replicate) ^ SURVEY_STREAM);
+            let counts: Vec<f64> = std::iter::once(model.get_initial_population().clone())
+                .chain(model.replicate_iter(replicate, self.seed))
+                .take(steps as usize + 1)
+                .map(|x| self.observation.observe(&x, &mut rng).total())
+                .collect();
+            for (index, design) in designs.iter().enumerate() {
+                let series: Vec<(f64, f64)> = design
+                    .survey_steps()
+                    .into_iter()
+                    .map(|x| (x as f64, (counts[x as usize] + 1.0).ln()))
+                    .collect();
+                let (slope, p_value) = trend_test(&series);
+                if p_value < self.alpha {
+                    detections[index] += 1;
+                }
+                trends[index] += slope.exp() - 1.0;
+            }
+        }
+        designs
+            .iter()
+            .enumerate()
+            .map(|(index, design)| PowerEstimate {
+                decline,
+                duration: design.duration,
+                interval: design.interval,
+                surveys: design.survey_steps().len(),
+                power: detections[index] as f64 / self.replicates as f64,
+                mean_trend: trends[index] / self.replicates as f64,
+            })
+            .collect()
+    }
+}
+
+/// This struct holds the results of a power analysis, one [`PowerEstimate`] per combination of
+/// decline and monitoring design.
+pub struct PowerResults {
+    alpha: f64,
+    estimates: Vec<PowerEstimate>,
+}
+impl PowerResults {
+    /// Return the significance level of the trend test.
+    pub fn get_alpha(&self) -> f64 {
+        self.alpha
+    }
+    /// Return the estimates, in the order they were calculated.
+    pub fn get_estimates(&self) -> &Vec<PowerEstimate> {
+        &self.estimates
+    }
+    /// Write the results as a CSV table with one row per estimate. Estimates for the model as
+    /// given have an empty `decline`.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record([
+            "decline",
+            "duration",
+            "interval",
+            "surveys",
+            "alpha",
+            "power",
+            "mean_trend",
+        ])?;
+        for estimate in &self.estimates {
+            csv_writer.write_record([
+                estimate.decline.map_or(String::new(), |x| x.to_string()),
+                estimate.duration.to_string(),
+                estimate.interval.to_string(),
+                estimate.surveys.to_string(),
+                self.alpha.to_string(),
+                estimate.power.to_string(),
+                estimate.mean_trend.to_string(),
+            ])?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Return a copy of `model` with every matrix multiplied by the factor that gives their mean
+/// matrix a growth rate of `1 - decline`.
+fn declining_model(model: &StochasticPva, decline: f64) -> Result<StochasticPva, &'static str> {
+    if !(decline.is_finite() && decline < 1.0) {
+        return Err("A rate of decline must be below one.");
+    }
+    let matrices = model.get_matrices();
+    let mean = matrices
+        .iter()
+        .skip(1)
+        .fold(matrices[0].clone(), |sum, x| &sum + x)
+        * (1.0 / matrices.len() as f64);
+    let lambda = mean.lambda();
+    if !(lambda.is_finite() && lambda > 0.0) {
+        return Err("The mean matrix of the model must have a positive growth rate.");
+    }
+    let factor = (1.0 - decline) / lambda;
+    let rescaled: Vec<PopulationMatrix> = matrices.iter().map(|x| x * factor).collect();
+    let mut declining = StochasticPva::build(
+        PopulationVector::clone(model.get_initial_population()),
+        rescaled,
+    )?
+    .with_density_dependence(model.get_core().get_density_dependence())
+    .with_reproduction_entries(model.get_core().get_reproduction_entries().to_vec())?;
+    if let Some(time_step) = model.get_time_step() {
+        declining = declining.with_time_step(time_step);
+    }
+    Ok(declining)
+}
+
+/// Fit a least squares line to `(time, value)` pairs and return its slope and the one-sided
+/// p-value of a t-test that the slope is below zero. A perfect fit is significant exactly when
+/// its slope is negative.
+fn trend_test(series: &[(f64, f64)]) -> (f64, f64) {
+    let n = series.len() as f64;
+    let mean_time = series.iter().map(|x| x.0).sum::<f64>() / n;
+    let mean_value = series.iter().map(|x| x.1).sum::<f64>() / n;
+    let sxx: f64 = series.iter().map(|x| (x.0 - mean_time).powi(2)).sum();
+    let sxy: f64 = series
+        .iter()
+        .map(|x| (x.0 - mean_time) * (x.1 - mean_value))
+        .sum();
+    let slope = sxy / sxx;
+    let residuals: f64 = series
+        .iter()
+        .map(|x| (x.1 - mean_value - slope * (x.0 - mean_time)).powi(2))
+        .sum();
+    let standard_error = (residuals / (n - 2.0) / sxx).sqrt();
+    let p_value = if standard_error > 0.0 {
+        student_t_cdf(slope / standard_error, n - 2.0)
+    } else if slope < 0.0 {
+        0.0
+    } else {
+        1.0
+    };
+    (slope, p_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> StochasticPva {
+        StochasticPva::build_from_vectors(
+            vec![150.0, 150.0],
+            vec![
+                vec![vec![0.0, 1.5], vec![0.5, 0.7]],
+                vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn designs_are_checked() {
+        assert_eq!(
+            MonitoringDesign::new(10, 3).unwrap().survey_steps(),
+            vec![0, 3, 6, 9]
+        );
+        assert!(MonitoringDesign::new(10, 0).is_err());
+        assert!(MonitoringDesign::new(5, 3).is_err());
+        assert!(PowerAnalysis::new(model(), "poisson".parse().unwrap())
+            .with_alpha(1.0)
+            .is_err());
+    }
+
+    #[test]
+    fn declines_are_rescaled_from_the_mean_matrix() {
+        let declining = declining_model(&model(), 0.1).unwrap();
+        let matrices = declining.get_matrices();
+        let mean = (&matrices[0] + &matrices[1]) * 0.5;
+        assert!((mean.lambda() - 0.9).abs() < 1e-9);
+        assert_eq!(
+            declining.get_initial_population(),
+            model().get_initial_population()
+        );
+        assert!(declining_model(&model(), 1.0).is_err());
+    }
+
+    #[test]
+    fn power_grows_with_decline_and_effort() {
+        // A single matrix started at its stable stage distribution has no trend of its own, so
+        // any noise around the trend comes from the surveys.
+        let matrix = model().get_matrices()[0].clone();
+        let stable =
+            StochasticPva::build(matrix.stable_stage_distribution() * 300.0, vec![matrix]).unwrap();
+        let analysis = PowerAnalysis::new(stable, "lognormal 0.2".parse().unwrap())
+            .with_replicates(300)
+            .unwrap()
+            .with_seed(4);
+        let designs = [
+            MonitoringDesign::new(6, 2).unwrap(),
+            MonitoringDesign::new(12, 1).unwrap(),
+        ];
+        let results = analysis.run(&[0.0, 0.1], &designs).unwrap();
+        let power: Vec<f64> = results
+            .get_estimates()
+            .iter()
+            .map(|x| x.get_power())
+            .collect();
+        // Without a decline, detections are false positives at roughly the significance level.
+        assert!(power[0] < 0.15 && power[1] < 0.15);
+        assert!(power[3] > power[2] && power[3] > 0.9);
+        assert!((results.get_estimates()[3].get_mean_trend() + 0.1).abs() < 0.03);
+        assert_eq!(results.get_estimates()[2].get_surveys(), 4);
+        let same = analysis.run(&[0.1], &designs).unwrap();
+        assert_eq!(same.get_estimates()[..], results.get_estimates()[2..]);
+        let mut csv = Vec::new();
+        analysis.power(&designs[..1]).write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("decline,duration,interval,surveys,alpha,power,mean_trend\n,6,2,4,0.05,"));
+    }
+}
@@ -0,0 +1,317 @@
+//! This module keeps the results of several completed runs together under labels (for example, one per management plan or carrying capacity) and writes a report comparing them side by side, as CSV or as an HTML table.
+use crate::populations::population_level_simulation::{
+    InitialPopulationOutputs, PvaDeterministicOutput, PvaStochasticOutput, PvaStochasticSummary,
+};
+use crate::statistics::{log_growth_rate, RunningStatistics};
+use std::{error::Error, io::Write};
+
+/// This struct holds the comparison measures of one registered run.
+/// - `kind`: `deterministic`, `stochastic`, or `summary`, depending on the type of output.
+/// - `steps` and `replicates`: the size of the run.
+/// - `log_growth_rate`: the average log growth per step of the mean total population across
+///   replicates (see [`log_growth_rate`]).
+/// - `extinction_probability`: the proportion of replicates whose total population fell to or
+///   below the extinction threshold at any step.
+/// - `final_mean` and `final_sd`: the mean and standard deviation of the total population at the
+///   last step across replicates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunReport {
+    pub label: String,
+    pub kind: &'static str,
+    pub steps: usize,
+    pub replicates: usize,
+    pub log_growth_rate: f64,
+    pub extinction_probability: f64,
+    pub final_mean: f64,
+    pub final_sd: f64,
+}
+
+/// The column names of a comparison report, in order.
+const REPORT_COLUMNS: [&str; 8] = [
+    "label",
+    "kind",
+    "steps",
+    "replicates",
+    "log_growth_rate",
+    "extinction_probability",
+    "final_mean",
+    "final_sd",
+];
+
+/// This trait is implemented by the outputs a [`RunRegistry`] can compare, and gives the
+/// [`RunReport`] of one output.
+pub trait RunOutput {
+    /// Return the comparison measures of the output under a label, counting it as extinct when
+    /// its total population falls to or below `extinction_threshold` (summaries use the threshold
+    /// they were created with).
+    fn report(&self, label: &str, extinction_threshold: f64) -> RunReport;
+}
+impl RunOutput for PvaDeterministicOutput {
+    fn report(&self, label: &str, extinction_threshold: f64) -> RunReport {
+        let totals = self.total_population();
+        let extinct = totals.iter().any(|x| *x <= extinction_threshold);
+        RunReport {
+            label: label.to_string(),
+            kind: "deterministic",
+            steps: totals.len(),
+            replicates: 1,
+            log_growth_rate: log_growth_rate(&totals),
+            extinction_probability: f64::from(extinct),
+            final_mean: totals.last().copied().unwrap_or(f64::NAN),
+            final_sd: 0.0,
+        }
+    }
+}
+impl RunOutput for PvaStochasticOutput {
+    fn report(&self, label: &str, extinction_threshold: f64) -> RunReport {
+        let totals = self.total_population();
+        let steps = totals.iter().map(Vec::len).max().unwrap_or(0);
+        let mut means = vec![RunningStatistics::new(); steps];
+        let mut last = RunningStatistics::new();
+        for replicate in &totals {
+            for (statistics, total) in means.iter_mut().zip(replicate) {
+                statistics.push(*total);
+            }
+            if let Some(total) = replicate.last() {
+                last.push(*total);
+            }
+        }
+        let means: Vec<f64> = means.iter().map(RunningStatistics::mean).collect();
+        RunReport {
+            label: label.to_string(),
+            kind: "stochastic",
+            steps,
+            replicates: self.replicate_count(),
+            log_growth_rate: log_growth_rate(&means),
+            extinction_probability: self.extinction_probability(extinction_threshold),
+            final_mean: last.mean(),
+            final_sd: last.standard_deviation(),
+        }
+    }
+}
+impl RunOutput for PvaStochasticSummary {
+    fn report(&self, label: &str, _extinction_threshold: f64) -> RunReport {
+        let statistics = self.total_statistics();
+        let means: Vec<f64> = statistics.iter().map(RunningStatistics::mean).collect();
+        let last = statistics.last();
+        RunReport {
+            label: label.to_string(),
+            kind: "summary",
+            steps: self.last_step() as usize,
+            replicates: last.map_or(0, |x| x.count() as usize),
+            log_growth_rate: log_growth_rate(&means),
+            extinction_probability: self.extinction_probability(),
+            final_mean: last.map_or(f64::NAN, RunningStatistics::mean),
+            final_sd: last.map_or(f64::NAN, RunningStatistics::standard_deviation),
+        }
+    }
+}
+
+/// This struct keeps labeled results of completed runs together so they can be compared. Runs
+/// are summarized as they are added, so the registry does not hold on to every trajectory.
+/// ```
+/// use ecolysis_cmd::{DeterministicPva, StochasticPva};
+/// use ecolysis_cmd::experiments::RunRegistry;
+/// let matrices = vec![
+///     vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+///     vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+/// ];
+/// let mut registry = RunRegistry::new(10.0);
+/// let deterministic = DeterministicPva::build_from_vectors(vec![20.0, 20.0], matrices[0].clone()).unwrap();
+/// registry.add_deterministic("good years only", &deterministic.deterministic_projection(25));
+/// let stochastic = StochasticPva::build_from_vectors(vec![20.0, 20.0], matrices).unwrap();
+/// registry.add_stochastic("variable", &stochastic.stochastic_projection(25, 100, 1));
+/// let mut csv = Vec::new();
+/// registry.write_csv(&mut csv).unwrap();
+/// assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 3);
+/// ```
+pub struct RunRegistry {
+    extinction_threshold: f64,
+    reports: Vec<RunReport>,
+}
+impl RunRegistry {
+    /// Create an empty registry. Deterministic and stochastic outputs added to it are counted as
+    /// extinct when their total population falls to or below `extinction_threshold`; summaries
+    /// use the threshold they were created with.
+    pub fn new(extinction_threshold: f64) -> RunRegistry {
+        RunRegistry {
+            extinction_threshold,
+            reports: Vec::new(),
+        }
+    }
+    /// Add the comparison measures of a run's output under a label.
+    pub fn add<T: RunOutput>(&mut self, label: &str, output: &T) {
+        self.reports
+            .push(output.report(label, self.extinction_threshold));
+    }
+    /// Add the output for each initial population of a projection from several initial
+    /// populations, labeled `label` followed by the position of the initial population in
+    /// brackets (starting at zero, as in its output table).
+    pub fn add_initial_populations<T: RunOutput>(
+        &mut self,
+        label: &str,
+        outputs: &InitialPopulationOutputs<T>,
+    ) {
+        for (index, output) in outputs.get_outputs().iter().enumerate() {
+            self.add(&format!("{} [{}]", label, index), output);
+        }
+    }
+    /// Add the output of a deterministic projection, which counts as a single replicate.
+    pub fn add_deterministic(&mut self, label: &str, output: &PvaDeterministicOutput) {
+        self.add(label, output);
+    }
+    /// Add the output of a stochastic projection.
+    pub fn add_stochastic(&mut self, label: &str, output: &PvaStochasticOutput) {
+        self.add(label, output);
+    }
+    /// Add the summary of a stochastic projection.
+    pub fn add_summary(&mut self, label: &str, summary: &PvaStochasticSummary) {
+        self.add(label, summary);
+    }
+    /// Return the report of every registered run, in the order they were added.
+    pub fn get_reports(&self) -> &Vec<RunReport> {
+        &self.reports
+    }
+    /// Write the comparison report as CSV, with one row per run.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record(REPORT_COLUMNS)?;
+        for report in &self.reports {
+            csv_writer.write_record(report_values(report))?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+    /// Write the comparison report as a standalone HTML page containing one table, with one row
+    /// per run.
+    pub fn write_html<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(
+            writer,
+            "<html><head><meta charset=\"utf-8\"><title>Scenario comparison</title></head><body>"
+        )?;
+        writeln!(writer, "<table>")?;
+        let header: String = REPORT_COLUMNS
+            .iter()
+            .map(|column| format!("<th>{}</th>", column))
+            .collect();
+        writeln!(writer, "<tr>{}</tr>", header)?;
+        for report in &self.reports {
+            let cells: String = report_values(report)
+                .iter()
+                .map(|value| format!("<td>{}</td>", escape_html(value)))
+                .collect();
+            writeln!(writer, "<tr>{}</tr>", cells)?;
+        }
+        writeln!(writer, "</table>")?;
+        writeln!(writer, "</body></html>")?;
+        Ok(())
+    }
+}
+
+fn report_values(report: &RunReport) -> Vec<String> {
+    vec![
+        report.label.clone(),
+        report.kind.to_string(),
+        report.steps.to_string(),
+        report.replicates.to_string(),
+        report.log_growth_rate.to_string(),
+        report.extinction_probability.to_string(),
+        report.final_mean.to_string(),
+        report.final_sd.to_string(),
+    ]
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{
+        DeterministicPva, PopulationVector, StochasticPva,
+    };
+
+    #[test]
+    fn deterministic_report_matches_lambda() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let mut registry = RunRegistry::new(1.0);
+        registry.add_deterministic("baseline", &population.deterministic_projection(200));
+        let report = &registry.get_reports()[0];
+        let lambda = population.get_matrix().lambda();
+        assert!((report.log_growth_rate - lambda.ln()).abs() < 1e-3);
+        assert_eq!(report.extinction_probability, 0.0);
+    }
+    #[test]
+    fn summary_and_stored_output_agree() {
+        let population = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+                vec![vec![0.0, 0.5], vec![0.2, 0.5]],
+            ],
+        )
+        .unwrap();
+        let mut registry = RunRegistry::new(5.0);
+        registry.add_stochastic("stored", &population.stochastic_projection(30, 50, 2));
+        registry.add_summary("summary", &population.stochastic_summary(30, 50, 2, 5.0));
+        let reports = registry.get_reports();
+        assert_eq!(
+            reports[0].extinction_probability,
+            reports[1].extinction_probability
+        );
+        assert!((reports[0].final_mean - reports[1].final_mean).abs() < 1e-9);
+        assert_eq!(reports[1].replicates, 50);
+    }
+    #[test]
+    fn html_escapes_labels() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let mut registry = RunRegistry::new(1.0);
+        registry.add_deterministic("K < 500 & harvest", &population.deterministic_projection(5));
+        let mut buffer = Vec::new();
+        registry.write_html(&mut buffer).unwrap();
+        let html = String::from_utf8(buffer).unwrap();
+        assert!(html.contains("<td>K &lt; 500 &amp; harvest</td>"));
+        assert!(html.contains("<th>extinction_probability</th>"));
+    }
+    #[test]
+    fn initial_populations_are_reported_separately() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let outputs = population
+            .deterministic_projections(
+                vec![
+                    PopulationVector::new(vec![1.0, 0.0]),
+                    PopulationVector::new(vec![50.0, 50.0]),
+                ],
+                10,
+            )
+            .unwrap();
+        let mut registry = RunRegistry::new(2.0);
+        registry.add_initial_populations("census", &outputs);
+        let reports = registry.get_reports();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1].label, "census [1]");
+        assert_eq!(reports[0].extinction_probability, 1.0);
+        assert_eq!(reports[1].extinction_probability, 0.0);
+        assert_eq!(
+            reports[0].log_growth_rate,
+            outputs.get_outputs()[0].report("", 2.0).log_growth_rate
+        );
+    }
+}
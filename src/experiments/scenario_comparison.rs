@@ -0,0 +1,183 @@
+//! This module compares one covariate-driven model under several covariate futures, such as climate scenarios, summarizing growth and extinction risk under each.
+use crate::populations::population_level_simulation::{
+    CovariateMatrix, CovariateSeries, PopulationVector,
+};
+use crate::statistics::log_growth_rate;
+use std::{error::Error, io::Write};
+
+/// This struct holds the summary of one covariate future.
+/// - `mean_lambda`: the mean asymptotic growth rate of the matrices used at each step.
+/// - `log_growth_rate`: the realized (stochastic) growth rate, the mean of `ln(N_t+1 / N_t)` over
+///   the projection (see [`log_growth_rate`]).
+/// - `final_total` and `minimum_total`: the total population at the end, and its lowest value.
+/// - `quasi_extinction_step`: the first step at which the total population fell to or below the
+///   extinction threshold, if it did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioSummary {
+    pub scenario: String,
+    pub steps: usize,
+    pub mean_lambda: f64,
+    pub log_growth_rate: f64,
+    pub final_total: f64,
+    pub minimum_total: f64,
+    pub quasi_extinction_step: Option<usize>,
+}
+
+/// This struct holds the comparison of a model under several covariate futures, one
+/// [`ScenarioSummary`] per future.
+pub struct ScenarioComparison {
+    extinction_threshold: f64,
+    summaries: Vec<ScenarioSummary>,
+}
+impl ScenarioComparison {
+    /// Project the model from `initial_population` under each named covariate future and
+    /// summarize the results. A future counts as reaching quasi-extinction when the total
+    /// population falls to or below `extinction_threshold`.
+    /// # Errors
+    /// Returns `Err<String>` (naming the scenario) if a future is missing one of the model's
+    /// covariates or the population does not match the model.
+    /// ```
+    /// use ecolysis_cmd::experiments::ScenarioComparison;
+    /// use ecolysis_cmd::populations::population_level_simulation::{CovariateMatrix, CovariateSeries};
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let model = CovariateMatrix::build(
+    ///     PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap(),
+    ///     vec![(String::from("temperature"),
+    ///         PopulationMatrix::build(vec![vec![0.0, -0.5], vec![0.0, -0.1]]).unwrap())],
+    /// ).unwrap();
+    /// let mild = CovariateSeries::build(vec![String::from("temperature")], vec![vec![0.5]; 50]).unwrap();
+    /// let severe = CovariateSeries::build(vec![String::from("temperature")], vec![vec![3.0]; 50]).unwrap();
+    /// let comparison = ScenarioComparison::run(&model, &PopulationVector::new(vec![50.0, 50.0]),
+    ///     &[(String::from("mild"), mild), (String::from("severe"), severe)], 10.0).unwrap();
+    /// let summaries = comparison.get_summaries();
+    /// assert!(summaries[0].mean_lambda > summaries[1].mean_lambda);
+    /// assert!(summaries[1].quasi_extinction_step.is_some());
+    /// ```
+    pub fn run(
+        model: &CovariateMatrix,
+        initial_population: &PopulationVector,
+        futures: &[(String, CovariateSeries)],
+        extinction_threshold: f64,
+    ) -> Result<ScenarioComparison, String> {
+        let mut summaries = Vec::with_capacity(futures.len());
+        for (name, series) in futures {
+            let error = |e: String| format!("{}: {}", name, e);
+            let output = model.project(initial_population, series).map_err(error)?;
+            let mut lambdas = Vec::with_capacity(series.len());
+            for values in series.values_for(&model.covariate_names()).map_err(error)? {
+                lambdas.push(model.matrix_for(&values).map_err(String::from)?.lambda());
+            }
+            let totals: Vec<f64> = std::iter::once(initial_population.iter().sum())
+                .chain(output.iter().map(|x| x.iter().sum()))
+                .collect();
+            summaries.push(ScenarioSummary {
+                scenario: name.clone(),
+                steps: series.len(),
+                mean_lambda: mean(&lambdas),
+                log_growth_rate: log_growth_rate(&totals),
+                final_total: *totals.last().unwrap_or(&0.0),
+                minimum_total: totals[1..].iter().copied().fold(f64::NAN, f64::min),
+                quasi_extinction_step: totals[1..]
+                    .iter()
+                    .position(|x| *x <= extinction_threshold)
+                    .map(|x| x + 1),
+            });
+        }
+        Ok(ScenarioComparison {
+            extinction_threshold,
+            summaries,
+        })
+    }
+    /// Return the extinction threshold used.
+    pub fn get_extinction_threshold(&self) -> f64 {
+        self.extinction_threshold
+    }
+    /// Return the summary of each future, in the order they were given.
+    pub fn get_summaries(&self) -> &Vec<ScenarioSummary> {
+        &self.summaries
+    }
+    /// Write the comparison as a CSV table with one row per scenario. Futures that never reached
+    /// quasi-extinction have an empty `quasi_extinction_step`.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record([
+            "scenario",
+            "steps",
+            "mean_lambda",
+            "log_growth_rate",
+            "final_total",
+            "minimum_total",
+            "quasi_extinct",
+            "quasi_extinction_step",
+        ])?;
+        for summary in &self.summaries {
+            csv_writer.write_record([
+                summary.scenario.clone(),
+                summary.steps.to_string(),
+                summary.mean_lambda.to_string(),
+                summary.log_growth_rate.to_string(),
+                summary.final_total.to_string(),
+                summary.minimum_total.to_string(),
+                summary.quasi_extinction_step.is_some().to_string(),
+                summary
+                    .quasi_extinction_step
+                    .map_or(String::new(), |x| x.to_string()),
+            ])?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Return the mean of some values, or NaN if there are none.
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PopulationMatrix;
+
+    #[test]
+    fn unchanging_future_matches_matrix_lambda() {
+        let base = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+        let model = CovariateMatrix::build(
+            base.clone(),
+            vec![(String::from("x"), PopulationMatrix::identity(2))],
+        )
+        .unwrap();
+        let series = CovariateSeries::build(vec![String::from("x")], vec![vec![0.0]; 200]).unwrap();
+        let initial = base.stable_stage_distribution();
+        let comparison =
+            ScenarioComparison::run(&model, &initial, &[(String::from("now"), series)], 0.0)
+                .unwrap();
+        let summary = &comparison.get_summaries()[0];
+        assert!((summary.mean_lambda - base.lambda()).abs() < 1e-9);
+        assert!((summary.log_growth_rate - base.lambda().ln()).abs() < 1e-6);
+        assert_eq!(summary.quasi_extinction_step, None);
+        let mut buffer = Vec::new();
+        comparison.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.lines().nth(1).unwrap().starts_with("now,200,"));
+        assert!(text.lines().nth(1).unwrap().ends_with(",false,"));
+    }
+    #[test]
+    fn missing_covariate_names_the_scenario() {
+        let model = CovariateMatrix::build(
+            PopulationMatrix::identity(1),
+            vec![(String::from("x"), PopulationMatrix::identity(1))],
+        )
+        .unwrap();
+        let series = CovariateSeries::build(vec![String::from("y")], vec![vec![1.0]]).unwrap();
+        let error = ScenarioComparison::run(
+            &model,
+            &PopulationVector::new(vec![1.0]),
+            &[(String::from("rcp85"), series)],
+            0.0,
+        )
+        .err()
+        .unwrap();
+        assert!(error.starts_with("rcp85: "));
+    }
+}
@@ -0,0 +1,278 @@
+//! This module exposes the simulation engines to other languages through C-compatible functions, so they can be called from R (`.C`/`.Call`), Python (`ctypes`/`cffi`), or C. Matrices are passed as opaque pointers created by [`ecolysis_matrix_new`] and freed by [`ecolysis_matrix_free`]; vectors and results are passed as flat arrays of doubles allocated by the caller. A C header can be generated with `cbindgen --crate ecolysis_cmd --lang c`.
+//!
+//! Matrices are read row by row (row-major order), and results are written step by step, with
+//! the lifestages of each step next to each other. Stochastic results are written replicate by
+//! replicate.
+use crate::populations::population_level_simulation::{
+    PopulationMatrix, PopulationVector, StochasticPva,
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+/// The result of a call to one of the functions in this module.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EcolysisStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// The inputs were invalid, for example a matrix with no lifestages or more than 255, or
+    /// matrices with different numbers of lifestages, or an output too large to address.
+    InvalidInput = 2,
+    /// The call failed with an internal error, which was caught instead of unwinding into the
+    /// caller. Please file a bug report.
+    Panic = 3,
+}
+
+/// Run `call`, returning `on_panic` if it panics, since unwinding across an `extern "C"`
+/// function is undefined behavior.
+fn guard<T>(on_panic: T, call: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or(on_panic)
+}
+
+/// An opaque Population Matrix owned by the caller, created by [`ecolysis_matrix_new`].
+pub struct EcolysisMatrix {
+    matrix: PopulationMatrix,
+}
+
+/// Build a matrix from `lifestages * lifestages` values in row-major order. Returns a null
+/// pointer if `values` is null, `lifestages` is not between 1 and 255, or an internal error
+/// occurs. The matrix must be freed with [`ecolysis_matrix_free`].
+///
+/// # Safety
+/// `values` must point to at least `lifestages * lifestages` readable doubles.
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_matrix_new(
+    values: *const f64,
+    lifestages: usize,
+) -> *mut EcolysisMatrix {
+    if values.is_null() || lifestages == 0 || lifestages > u8::MAX as usize {
+        return std::ptr::null_mut();
+    }
+    guard(std::ptr::null_mut(), || {
+        let values = slice::from_raw_parts(values, lifestages * lifestages);
+        match PopulationMatrix::build(values.chunks(lifestages).map(<[f64]>::to_vec).collect()) {
+            Ok(matrix) => Box::into_raw(Box::new(EcolysisMatrix { matrix })),
+            Err(_) => std::ptr::null_mut(),
+        }
+    })
+}
+
+/// Free a matrix created by [`ecolysis_matrix_new`]. Passing a null pointer does nothing.
+///
+/// # Safety
+/// `matrix` must be null or a pointer returned by `ecolysis_matrix_new` that has not already
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_matrix_free(matrix: *mut EcolysisMatrix) {
+    if !matrix.is_null() {
+        guard((), || drop(Box::from_raw(matrix)));
+    }
+}
+
+/// Return the number of lifestages of a matrix, or 0 if the pointer is null.
+///
+/// # Safety
+/// `matrix` must be null or a valid pointer returned by [`ecolysis_matrix_new`].
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_matrix_lifestages(matrix: *const EcolysisMatrix) -> usize {
+    match matrix.as_ref() {
+        Some(matrix) => matrix.matrix.get_lifestage_count() as usize,
+        None => 0,
+    }
+}
+
+/// Return the asymptotic growth rate (lambda) of a matrix, or NaN if the pointer is null or an
+/// internal error occurs.
+///
+/// # Safety
+/// `matrix` must be null or a valid pointer returned by [`ecolysis_matrix_new`].
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_matrix_lambda(matrix: *const EcolysisMatrix) -> f64 {
+    match matrix.as_ref() {
+        Some(matrix) => guard(f64::NAN, || matrix.matrix.lambda()),
+        None => f64::NAN,
+    }
+}
+
+/// Project `initial` (one value per lifestage) by a matrix for `steps` steps, writing
+/// `steps * lifestages` values to `output`.
+///
+/// # Safety
+/// `matrix` must be null or a valid pointer returned by [`ecolysis_matrix_new`]. `initial` must
+/// point to as many readable doubles as the matrix has lifestages, and `output` to
+/// `steps * lifestages` writable doubles.
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_deterministic_projection(
+    matrix: *const EcolysisMatrix,
+    initial: *const f64,
+    steps: u32,
+    output: *mut f64,
+) -> EcolysisStatus {
+    let Some(matrix) = matrix.as_ref() else {
+        return EcolysisStatus::NullPointer;
+    };
+    if initial.is_null() || output.is_null() {
+        return EcolysisStatus::NullPointer;
+    }
+    let lifestages = matrix.matrix.get_lifestage_count() as usize;
+    let Some(length) = (steps as usize).checked_mul(lifestages) else {
+        return EcolysisStatus::InvalidInput;
+    };
+    guard(EcolysisStatus::Panic, || {
+        let output = slice::from_raw_parts_mut(output, length);
+        let mut popvector =
+            PopulationVector::new(slice::from_raw_parts(initial, lifestages).to_vec());
+        for step in output.chunks_mut(lifestages) {
+            popvector = &matrix.matrix * &popvector;
+            step.copy_from_slice(popvector.get_vector());
+        }
+        EcolysisStatus::Ok
+    })
+}
+
+/// Run a stochastic projection of `initial` for `steps` steps and `replicates` replicates, drawing
+/// one of `matrix_count` matrices at random for each step, as
+/// [`StochasticPva::stochastic_projection`] does. Writes `replicates * steps * lifestages` values
+/// to `output`.
+///
+/// # Safety
+/// `matrices` must point to `matrix_count` valid pointers returned by [`ecolysis_matrix_new`].
+/// `initial` must point to as many readable doubles as the matrices have lifestages, and
+/// `output` to `replicates * steps * lifestages` writable doubles.
+#[no_mangle]
+pub unsafe extern "C" fn ecolysis_stochastic_projection(
+    matrices: *const *const EcolysisMatrix,
+    matrix_count: usize,
+    initial: *const f64,
+    steps: u32,
+    replicates: u32,
+    seed: u64,
+    output: *mut f64,
+) -> EcolysisStatus {
+    if matrices.is_null() || initial.is_null() || output.is_null() {
+        return EcolysisStatus::NullPointer;
+    }
+    guard(EcolysisStatus::Panic, || {
+        let mut built = Vec::with_capacity(matrix_count);
+        for matrix in slice::from_raw_parts(matrices, matrix_count) {
+            match matrix.as_ref() {
+                Some(matrix) => built.push(matrix.matrix.clone()),
+                None => return EcolysisStatus::NullPointer,
+            }
+        }
+        let Some(lifestages) = built.first().map(|x| x.get_lifestage_count() as usize) else {
+            return EcolysisStatus::InvalidInput;
+        };
+        let initial = PopulationVector::new(slice::from_raw_parts(initial, lifestages).to_vec());
+        let Ok(model) = StochasticPva::build(initial, built) else {
+            return EcolysisStatus::InvalidInput;
+        };
+        let Some((replicate_length, length)) = (steps as usize)
+            .checked_mul(lifestages)
+            .and_then(|x| Some((x, x.checked_mul(replicates as usize)?)))
+        else {
+            return EcolysisStatus::InvalidInput;
+        };
+        let output = slice::from_raw_parts_mut(output, length);
+        if replicate_length == 0 {
+            return EcolysisStatus::Ok;
+        }
+        for (replicate, values) in output.chunks_mut(replicate_length).enumerate() {
+            for (popvector, step) in model
+                .replicate_iter(replicate as u32, seed)
+                .zip(values.chunks_mut(lifestages))
+            {
+                step.copy_from_slice(popvector.get_vector());
+            }
+        }
+        EcolysisStatus::Ok
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_projection_through_ffi() {
+        let values = [0.0, 2.0, 0.5, 0.5];
+        let initial = [10.0, 10.0];
+        let mut output = [0.0; 4];
+        unsafe {
+            let matrix = ecolysis_matrix_new(values.as_ptr(), 2);
+            assert_eq!(ecolysis_matrix_lifestages(matrix), 2);
+            assert_eq!(
+                ecolysis_deterministic_projection(matrix, initial.as_ptr(), 2, output.as_mut_ptr()),
+                EcolysisStatus::Ok
+            );
+            ecolysis_matrix_free(matrix);
+        }
+        assert_eq!(output, [20.0, 10.0, 20.0, 15.0]);
+    }
+    #[test]
+    fn stochastic_projection_matches_library() {
+        let good = [0.0, 2.0, 0.5, 0.5];
+        let bad = [0.0, 1.0, 0.3, 0.5];
+        let initial = [10.0, 10.0];
+        let mut output = vec![0.0; 3 * 5 * 2];
+        unsafe {
+            let matrices = [
+                ecolysis_matrix_new(good.as_ptr(), 2) as *const EcolysisMatrix,
+                ecolysis_matrix_new(bad.as_ptr(), 2) as *const EcolysisMatrix,
+            ];
+            let status = ecolysis_stochastic_projection(
+                matrices.as_ptr(),
+                2,
+                initial.as_ptr(),
+                5,
+                3,
+                42,
+                output.as_mut_ptr(),
+            );
+            assert_eq!(status, EcolysisStatus::Ok);
+            for matrix in matrices {
+                ecolysis_matrix_free(matrix as *mut EcolysisMatrix);
+            }
+        }
+        let expected = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.0], vec![0.3, 0.5]],
+            ],
+        )
+        .unwrap()
+        .stochastic_projection(5, 3, 42);
+        let flattened: Vec<f64> = expected
+            .get_replicates()
+            .iter()
+            .flatten()
+            .flat_map(|x| x.get_vector().clone())
+            .collect();
+        assert_eq!(output, flattened);
+    }
+    #[test]
+    fn invalid_inputs_are_reported() {
+        unsafe {
+            assert!(ecolysis_matrix_new(std::ptr::null(), 2).is_null());
+            assert!(ecolysis_matrix_lambda(std::ptr::null()).is_nan());
+            assert_eq!(
+                ecolysis_deterministic_projection(
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    1,
+                    std::ptr::null_mut()
+                ),
+                EcolysisStatus::NullPointer
+            );
+        }
+        assert_eq!(
+            guard(EcolysisStatus::Panic, || panic!("caught")),
+            EcolysisStatus::Panic
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! This module contains functions for reading population matrices from files produced by other software, such as matrices saved from R's popbio package and the flat CSV exports of the COMPADRE and COMADRE matrix databases, and habitat-quality rasters for spatial models.
+use crate::populations::population_level_simulation::{DecomposedMatrix, PopulationMatrix};
+use csv::ReaderBuilder;
+use std::{error::Error, io::Read};
+mod csv_format;
+mod raster;
+mod text;
+#[cfg(feature = "xlsx")]
+mod workbook;
+pub use csv_format::*;
+pub use raster::*;
+pub use text::*;
+#[cfg(feature = "xlsx")]
+pub use workbook::*;
+
+/// This struct holds a Population Matrix together with the names of its lifestages, as read from
+/// a labelled matrix file.
+pub struct LabelledMatrix {
+    stage_names: Vec<String>,
+    matrix: PopulationMatrix,
+}
+impl LabelledMatrix {
+    /// Return the lifestage names, in the same order as the matrix rows and columns.
+    pub fn get_stage_names(&self) -> &Vec<String> {
+        &self.stage_names
+    }
+    /// Return the Population Matrix.
+    pub fn get_matrix(&self) -> &PopulationMatrix {
+        &self.matrix
+    }
+    /// Consume the LabelledMatrix, returning the Population Matrix.
+    pub fn into_matrix(self) -> PopulationMatrix {
+        self.matrix
+    }
+}
+
+/// Read a matrix saved from R with `write.csv(A)`, as is common for popbio matrices. R writes the
+/// stage names as both a header row (with an empty first cell) and a first column; both are
+/// optional, and unnamed stages are called `stage_0`, `stage_1`, etc. Semicolon-delimited files
+/// with decimal commas, as saved by European spreadsheets, are detected (see
+/// [`CsvFormat::detect`]).
+/// # Errors
+/// Returns an error if the file cannot be read, a cell cannot be read as a number (with its row
+/// and column), or the matrix is not square.
+/// ```
+/// use ecolysis_cmd::imports::read_popbio_csv;
+/// let file = "\"\",\"seed\",\"rosette\",\"flowering\"\n\
+///             \"seed\",0,0,322\n\
+///             \"rosette\",0.01,0.4,0\n\
+///             \"flowering\",0,0.2,0\n";
+/// let imported = read_popbio_csv(file.as_bytes()).unwrap();
+/// assert_eq!(imported.get_stage_names(), &vec!["seed", "rosette", "flowering"]);
+/// assert_eq!(imported.get_matrix()[(0, 2)], 322.0);
+/// ```
+pub fn read_popbio_csv<R: Read>(reader: R) -> Result<LabelledMatrix, Box<dyn Error>> {
+    let (contents, format) = CsvFormat::read_detected(reader)?;
+    read_popbio_csv_with_format(contents.as_slice(), &format)
+}
+
+/// Read a matrix saved from R, as [`read_popbio_csv`] does, in a given CSV format rather than
+/// one detected from the file.
+/// # Errors
+/// Returns an error if a cell cannot be read as a number in `format` (with its row and column),
+/// or if the matrix is not square.
+/// ```
+/// use ecolysis_cmd::imports::{read_popbio_csv_with_format, CsvFormat};
+/// let file = "\"\";\"seed\";\"adult\"\n\"seed\";0;1.322,5\n\"adult\";0,01;0,8\n";
+/// let imported = read_popbio_csv_with_format(file.as_bytes(), &CsvFormat::european()).unwrap();
+/// assert_eq!(imported.get_matrix()[(0, 1)], 1322.5);
+/// ```
+pub fn read_popbio_csv_with_format<R: Read>(
+    reader: R,
+    format: &CsvFormat,
+) -> Result<LabelledMatrix, Box<dyn Error>> {
+    let contents = read_text(reader)?;
+    let mut rdr = format
+        .reader_builder()
+        .has_headers(false)
+        .from_reader(contents.as_bytes());
+    let mut records = Vec::new();
+    for record in rdr.records() {
+        records.push(record?.iter().map(String::from).collect::<Vec<_>>());
+    }
+    if records.is_empty() {
+        return Err("The matrix file is empty.".into());
+    }
+    let has_header = records[0]
+        .iter()
+        .any(|cell| format.parse_number(cell).is_none());
+    let header = if has_header {
+        Some(records.remove(0))
+    } else {
+        None
+    };
+    let has_row_names = records.iter().any(|row| {
+        row.first()
+            .is_some_and(|cell| format.parse_number(cell).is_none())
+    });
+    let mut stage_names = Vec::new();
+    let mut matrix = Vec::new();
+    for (row_number, row) in records.into_iter().enumerate() {
+        let mut cells = row.into_iter();
+        if has_row_names {
+            stage_names.push(cells.next().unwrap_or_default());
+        }
+        let mut values = Vec::new();
+        for (column_number, cell) in cells.enumerate() {
+            values.push(format.parse_number(&cell).ok_or_else(|| {
+                format!(
+                    "Could not read \"{}\" at row {}, column {} as a number.",
+                    cell,
+                    row_number + 1,
+                    column_number + 1
+                )
+            })?);
+        }
+        matrix.push(values);
+    }
+    if !has_row_names {
+        stage_names = match header {
+            Some(header) => {
+                let corner_cells = header.len().saturating_sub(matrix.len());
+                header.into_iter().skip(corner_cells).collect()
+            }
+            None => (0..matrix.len())
+                .map(|stage| format!("stage_{}", stage))
+                .collect(),
+        };
+    }
+    Ok(LabelledMatrix {
+        stage_names,
+        matrix: PopulationMatrix::build(matrix)?,
+    })
+}
+
+/// Parse a matrix written in the bracketed format used by COMPADRE and COMADRE flat files and by
+/// MATLAB, where values in a row are separated by spaces and rows are separated by semicolons:
+/// `[0 0 5.2;0.3 0.4 0;0 0.5 0.9]`.
+/// # Errors
+/// Returns an error if a value is missing (`NA`) or not a number, or if the matrix is not square.
+/// ```
+/// use ecolysis_cmd::imports::parse_compadre_matrix;
+/// let matrix = parse_compadre_matrix("[0 1.5;0.3 0.8]").unwrap();
+/// assert_eq!(matrix.get_matrix(), vec![vec![0.0, 1.5], vec![0.3, 0.8]]);
+/// ```
+pub fn parse_compadre_matrix(input: &str) -> Result<PopulationMatrix, Box<dyn Error>> {
+    let trimmed = input.trim().trim_start_matches('[').trim_end_matches(']');
+    let mut matrix = Vec::new();
+    for (row_number, row) in trimmed.split(';').enumerate() {
+        let mut values = Vec::new();
+        for cell in row.split(|c: char| c.is_whitespace() || c == ',') {
+            if cell.is_empty() {
+                continue;
+            }
+            values.push(cell.parse::<f64>().map_err(|_| {
+                format!(
+                    "Could not read \"{}\" in row {} of the matrix as a number.",
+                    cell,
+                    row_number + 1
+                )
+            })?);
+        }
+        matrix.push(values);
+    }
+    Ok(PopulationMatrix::build(matrix)?)
+}
+
+/// This struct holds one matrix record from a COMPADRE or COMADRE flat file: the full projection
+/// matrix A and, where the database provides them, the survival/growth submatrix U, the sexual
+/// reproduction submatrix F, and the clonal reproduction submatrix C.
+pub struct CompadreRecord {
+    species: Option<String>,
+    stage_names: Vec<String>,
+    matrix_a: PopulationMatrix,
+    matrix_u: Option<PopulationMatrix>,
+    matrix_f: Option<PopulationMatrix>,
+    matrix_c: Option<PopulationMatrix>,
+}
+impl CompadreRecord {
+    /// Return the accepted species name of the record, if the file includes one.
+    pub fn get_species(&self) -> Option<&str> {
+        self.species.as_deref()
+    }
+    /// Return the lifestage names of the record, if the file includes them.
+    pub fn get_stage_names(&self) -> &Vec<String> {
+        &self.stage_names
+    }
+    /// Return the full projection matrix A.
+    pub fn get_matrix_a(&self) -> &PopulationMatrix {
+        &self.matrix_a
+    }
+    /// Return the survival/growth submatrix U, if available.
+    pub fn get_matrix_u(&self) -> Option<&PopulationMatrix> {
+        self.matrix_u.as_ref()
+    }
+    /// Return the sexual reproduction submatrix F, if available.
+    pub fn get_matrix_f(&self) -> Option<&PopulationMatrix> {
+        self.matrix_f.as_ref()
+    }
+    /// Return the clonal reproduction submatrix C, if available.
+    pub fn get_matrix_c(&self) -> Option<&PopulationMatrix> {
+        self.matrix_c.as_ref()
+    }
+    /// Return the record as a Decomposed Matrix, with U as the survival matrix and `F + C` as
+    /// the fertility matrix (clonal offspring are treated as reproduction), or `None` if the
+    /// record does not include U and F.
+    /// # Errors
+    /// Will return `Err<'static str>` if F and C have different numbers of stages, or U and
+    /// `F + C` do not form a valid Decomposed Matrix.
+    pub fn to_decomposed(&self) -> Option<Result<DecomposedMatrix, &'static str>> {
+        let (survival, fertility) = (self.matrix_u.as_ref()?, self.matrix_f.as_ref()?);
+        let fertility = match &self.matrix_c {
+            Some(clonal) => fertility.checked_add(clonal),
+            None => Ok(fertility.clone()),
+        };
+        Some(fertility.and_then(|x| DecomposedMatrix::build(survival.clone(), x)))
+    }
+}
+
+/// Read a COMPADRE or COMADRE flat CSV file (as produced by `Rcompadre::cdb_flatten` and
+/// `write.csv`). Matrices are read from the `matA`, `matU`, `matF`, and `matC` columns, species
+/// names from `SpeciesAccepted`, and stage names from `MatrixClassAuthor` (separated by `|`).
+/// If `matA` is missing or empty, A is rebuilt as U + F + C. Rows whose matrices contain missing
+/// values are reported as errors rather than skipped, so no data is silently dropped.
+/// # Errors
+/// Returns an error if the file has neither a `matA` column nor a `matU` and `matF` column, if
+/// any matrix cannot be read, or if the matrices of a record have different numbers of stages.
+/// ```
+/// use ecolysis_cmd::imports::read_compadre_csv;
+/// let file = "SpeciesAccepted,matU,matF,MatrixClassAuthor\n\
+///             Dipsacus sylvestris,[0 0;0.5 0.8],[0 3.2;0 0],seedling | adult\n";
+/// let records = read_compadre_csv(file.as_bytes()).unwrap();
+/// assert_eq!(records[0].get_species(), Some("Dipsacus sylvestris"));
+/// assert_eq!(records[0].get_matrix_a().get_matrix(), vec![vec![0.0, 3.2], vec![0.5, 0.8]]);
+/// ```
+pub fn read_compadre_csv<R: Read>(reader: R) -> Result<Vec<CompadreRecord>, Box<dyn Error>> {
+    let contents = read_text(reader)?;
+    let mut rdr = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (a, u, f, c) = (
+        column("matA"),
+        column("matU"),
+        column("matF"),
+        column("matC"),
+    );
+    let (species, stages) = (column("SpeciesAccepted"), column("MatrixClassAuthor"));
+    if a.is_none() && (u.is_none() || f.is_none()) {
+        return Err("The file must have a matA column, or both matU and matF columns.".into());
+    }
+    let mut records = Vec::new();
+    for (row_number, record) in rdr.records().enumerate() {
+        let record = record?;
+        let read = |index: Option<usize>| -> Result<Option<PopulationMatrix>, Box<dyn Error>> {
+            match index.and_then(|i| record.get(i)).filter(|x| !x.is_empty()) {
+                Some(cell) => parse_compadre_matrix(cell)
+                    .map(Some)
+                    .map_err(|e| format!("Record {}: {}", row_number + 1, e).into()),
+                None => Ok(None),
+            }
+        };
+        let (matrix_u, matrix_f, matrix_c) = (read(u)?, read(f)?, read(c)?);
+        let matrix_a = read(a)?;
+        let sizes: Vec<u8> = [&matrix_a, &matrix_u, &matrix_f, &matrix_c]
+            .into_iter()
+            .flatten()
+            .map(|x| x.get_lifestage_count())
+            .collect();
+        if sizes.windows(2).any(|x| x[0] != x[1]) {
+            return Err(format!(
+                "Record {}: the matrices do not all have the same number of stages.",
+                row_number + 1
+            )
+            .into());
+        }
+        let matrix_a = match (matrix_a, &matrix_u, &matrix_f) {
+            (Some(matrix_a), _, _) => matrix_a,
+            (None, Some(matrix_u), Some(matrix_f)) => {
+                let sum = matrix_u + matrix_f;
+                match &matrix_c {
+                    Some(matrix_c) => &sum + matrix_c,
+                    None => sum,
+                }
+            }
+            _ => return Err(format!("Record {} has no matrix A, U, or F.", row_number + 1).into()),
+        };
+        records.push(CompadreRecord {
+            species: species.and_then(|i| record.get(i)).map(String::from),
+            stage_names: stages
+                .and_then(|i| record.get(i))
+                .map(|x| x.split('|').map(|x| x.trim().to_string()).collect())
+                .unwrap_or_default(),
+            matrix_a,
+            matrix_u,
+            matrix_f,
+            matrix_c,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn popbio_without_labels() {
+        let imported = read_popbio_csv("0,1.2\n0.5,0.8\n".as_bytes()).unwrap();
+        assert_eq!(imported.get_stage_names(), &vec!["stage_0", "stage_1"]);
+        assert_eq!(imported.get_matrix()[(1, 0)], 0.5);
+    }
+    #[test]
+    fn popbio_header_only() {
+        let imported = read_popbio_csv("juvenile,adult\n0,1.2\n0.5,0.8\n".as_bytes()).unwrap();
+        assert_eq!(imported.get_stage_names(), &vec!["juvenile", "adult"]);
+    }
+    #[test]
+    fn popbio_from_windows_spreadsheet() {
+        let file = "\u{feff}\"\";\"seed\";\"adult\"\r\n\"seed\";0;1,5\r\n\"adult\";0,5;0,8\r\n";
+        let imported = read_popbio_csv(file.as_bytes()).unwrap();
+        assert_eq!(imported.get_stage_names(), &vec!["seed", "adult"]);
+        assert_eq!(imported.get_matrix()[(0, 1)], 1.5);
+        let format = CsvFormat::european();
+        assert!(read_popbio_csv_with_format(file.as_bytes(), &format).is_ok());
+        let compadre = "\u{feff}matA\r\n[0 2;0.5 0.8]\r\n";
+        assert_eq!(read_compadre_csv(compadre.as_bytes()).unwrap().len(), 1);
+    }
+    #[test]
+    fn popbio_reports_bad_cell() {
+        let error = read_popbio_csv("0,1.2\n0.5,x\n".as_bytes())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("row 2, column 2"));
+    }
+    #[test]
+    fn compadre_matrix_with_missing_values() {
+        assert!(parse_compadre_matrix("[0 NA;0.5 0.8]").is_err());
+        assert!(parse_compadre_matrix("[0 1;0.5]").is_err());
+    }
+    #[test]
+    fn compadre_prefers_matrix_a() {
+        let file = "matA,matU,matF,matC\n[0 2;0.5 0.8],[0 0;0.5 0.8],[0 1.5;0 0],[0 0.5;0 0]\n";
+        let records = read_compadre_csv(file.as_bytes()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].get_matrix_a()[(0, 1)], 2.0);
+        assert!(records[0].get_matrix_c().is_some());
+        let decomposed = records[0].to_decomposed().unwrap().unwrap();
+        assert_eq!(decomposed.get_fertility()[(0, 1)], 2.0);
+        assert!(records[0].get_stage_names().is_empty());
+    }
+    #[test]
+    fn compadre_decomposition_checks_sizes() {
+        let record = CompadreRecord {
+            species: None,
+            stage_names: Vec::new(),
+            matrix_a: PopulationMatrix::identity(2),
+            matrix_u: Some(PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap()),
+            matrix_f: Some(PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.0, 0.0]]).unwrap()),
+            matrix_c: Some(PopulationMatrix::identity(3)),
+        };
+        assert!(record.to_decomposed().unwrap().is_err());
+        let record = CompadreRecord {
+            matrix_f: Some(PopulationMatrix::identity(3)),
+            matrix_c: None,
+            ..record
+        };
+        assert!(record.to_decomposed().unwrap().is_err());
+    }
+    #[test]
+    fn compadre_requires_matrix_columns() {
+        assert!(read_compadre_csv("matU\n[1]\n".as_bytes()).is_err());
+    }
+    #[test]
+    fn compadre_rejects_mismatched_matrices() {
+        let file = "matU,matF\n[0 0;0.5 0.8],[2]\n";
+        let error = read_compadre_csv(file.as_bytes())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("Record 1"));
+        let file = "matU,matF,matC\n[0 0;0.5 0.8],[0 1;0 0],[1]\n";
+        assert!(read_compadre_csv(file.as_bytes()).is_err());
+    }
+}
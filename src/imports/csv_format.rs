@@ -0,0 +1,264 @@
+//! This module contains the CSV formats the importers read: the delimiter between cells and the decimal separator, detected from the file when possible, so that files saved by spreadsheets in European locales (semicolons and decimal commas, with thousands separators) read the same as plain CSV.
+use super::decode_text;
+use csv::ReaderBuilder;
+use std::io::{self, Read};
+
+/// The character that separates the whole and fractional parts of a number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    /// `1234.5`, as written in English-speaking locales and by R and Python.
+    #[default]
+    Point,
+    /// `1234,5`, as written by spreadsheets in most of continental Europe and Latin America.
+    Comma,
+}
+impl DecimalSeparator {
+    fn as_char(self) -> char {
+        match self {
+            DecimalSeparator::Point => '.',
+            DecimalSeparator::Comma => ',',
+        }
+    }
+}
+
+/// The characters accepted between groups of thousands in addition to the one that is not the
+/// decimal separator: spaces (including no-break and narrow no-break spaces) and apostrophes.
+const GROUP_SEPARATORS: [char; 5] = [' ', '\u{a0}', '\u{202f}', '\'', '\u{2019}'];
+
+/// This struct describes how a CSV file of numbers is written: the delimiter between cells and
+/// the decimal separator. Spreadsheets in many European locales save "CSV" files with semicolons
+/// between cells and decimal commas (`0,5;1,2`), which the default format reads as text.
+///
+/// Numbers may also use thousands separators (`12,500.5`, `12.500,5`, `12 500,5`, or
+/// `12'500.5`) and be surrounded by stray whitespace. Groups must have three digits and the first
+/// group cannot start with 0, so `0.500` with a decimal comma is rejected rather than read as 500.
+/// ```
+/// use ecolysis_cmd::imports::{CsvFormat, DecimalSeparator};
+/// let format = CsvFormat::detect("0;1,5\n0,3;0,8\n");
+/// assert_eq!(format.get_delimiter(), b';');
+/// assert_eq!(format.get_decimal_separator(), DecimalSeparator::Comma);
+/// assert_eq!(format.parse_number(" 1.234,5 "), Some(1234.5));
+/// assert_eq!(format.parse_number("0.500"), None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvFormat {
+    delimiter: u8,
+    decimal: DecimalSeparator,
+}
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: b',',
+            decimal: DecimalSeparator::Point,
+        }
+    }
+}
+impl CsvFormat {
+    /// Build a CSV Format from a cell delimiter and decimal separator.
+    /// # Errors
+    /// Will return `Err<'static str>` if the delimiter is part of how numbers are written (a
+    /// digit, sign, point, or `e`) or is the decimal separator.
+    pub fn new(delimiter: u8, decimal: DecimalSeparator) -> Result<CsvFormat, &'static str> {
+        if delimiter.is_ascii_alphanumeric() || matches!(delimiter, b'.' | b'-' | b'+' | b'"') {
+            return Err("The delimiter cannot be a character used to write numbers.");
+        }
+        if char::from(delimiter) == decimal.as_char() {
+            return Err("The delimiter cannot be the decimal separator.");
+        }
+        Ok(CsvFormat { delimiter, decimal })
+    }
+    /// The format written by spreadsheets in most European locales: semicolons between cells
+    /// and decimal commas.
+    pub fn european() -> CsvFormat {
+        CsvFormat {
+            delimiter: b';',
+            decimal: DecimalSeparator::Comma,
+        }
+    }
+    /// Guess the format of a CSV file from its text. A semicolon or tab delimiter is chosen if
+    /// it appears (outside quotes) on every non-blank line of the first 20; otherwise cells are
+    /// assumed to be separated by commas. With a semicolon or tab delimiter, decimal commas are
+    /// assumed if any cell is only a number when read with one, so `1.500;2` is read with
+    /// decimal points. Files that match neither pattern get the default format.
+    pub fn detect(sample: &str) -> CsvFormat {
+        let lines: Vec<&str> = sample
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .take(20)
+            .collect();
+        let Some(delimiter) = [b';', b'\t'].into_iter().find(|x| {
+            !lines.is_empty() && lines.iter().all(|line| count_outside_quotes(line, *x) > 0)
+        }) else {
+            return CsvFormat::default();
+        };
+        let comma = CsvFormat {
+            delimiter,
+            decimal: DecimalSeparator::Comma,
+        };
+        let point = CsvFormat {
+            delimiter,
+            decimal: DecimalSeparator::Point,
+        };
+        let decimal_comma = lines.iter().any(|line| {
+            line.split(char::from(delimiter))
+                .map(|cell| cell.trim().trim_matches('"'))
+                .any(|cell| {
+                    cell.contains(',')
+                        && comma.parse_number(cell).is_some()
+                        && point.parse_number(cell).is_none()
+                })
+        });
+        if decimal_comma {
+            comma
+        } else {
+            point
+        }
+    }
+    /// Read all of `reader` as text (see [`decode_text`]) and guess its format with
+    /// [`CsvFormat::detect`], returning the contents and the format.
+    /// # Errors
+    /// Returns an error if the reader fails, or an error of kind `InvalidData` giving the
+    /// position of the first byte that is not text.
+    pub fn read_detected<R: Read>(mut reader: R) -> io::Result<(Vec<u8>, CsvFormat)> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let contents =
+            decode_text(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let format = CsvFormat::detect(&contents);
+        Ok((contents.into_bytes(), format))
+    }
+    /// Return the delimiter between cells.
+    pub fn get_delimiter(&self) -> u8 {
+        self.delimiter
+    }
+    /// Return the decimal separator.
+    pub fn get_decimal_separator(&self) -> DecimalSeparator {
+        self.decimal
+    }
+    /// Return a CSV reader builder for this format that trims whitespace around cells.
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder.delimiter(self.delimiter).trim(csv::Trim::All);
+        builder
+    }
+    /// Read a cell as a number written in this format, or return `None` if it is not one.
+    pub fn parse_number(&self, cell: &str) -> Option<f64> {
+        let cell = cell.trim();
+        if self.decimal == DecimalSeparator::Point {
+            if let Ok(value) = cell.parse() {
+                return Some(value);
+            }
+        }
+        let (sign, unsigned) = match cell.strip_prefix(['-', '+']) {
+            Some(rest) => (&cell[..1], rest),
+            None => ("", cell),
+        };
+        let (whole, fraction) = match unsigned.split_once(self.decimal.as_char()) {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let grouping = match self.decimal {
+            DecimalSeparator::Point => ',',
+            DecimalSeparator::Comma => '.',
+        };
+        let groups: Vec<&str> = whole
+            .split(|x: char| x == grouping || GROUP_SEPARATORS.contains(&x))
+            .collect();
+        if groups.len() > 1 {
+            let digits = |group: &str| group.bytes().all(|x| x.is_ascii_digit());
+            let first = groups[0];
+            if !(1..=3).contains(&first.len())
+                || !digits(first)
+                || first.starts_with('0')
+                || groups[1..].iter().any(|x| x.len() != 3 || !digits(x))
+            {
+                return None;
+            }
+        }
+        let mut number = format!("{}{}", sign, groups.concat());
+        if let Some(fraction) = fraction {
+            if fraction.contains([',', '.']) {
+                return None;
+            }
+            number = format!("{}.{}", number, fraction);
+        }
+        number.parse().ok()
+    }
+}
+
+/// Count the occurrences of `delimiter` in a line that are not inside double quotes.
+fn count_outside_quotes(line: &str, delimiter: u8) -> usize {
+    let mut quoted = false;
+    let mut count = 0;
+    for byte in line.bytes() {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if byte == delimiter && !quoted {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_with_decimal_points() {
+        let format = CsvFormat::default();
+        assert_eq!(format.parse_number("0.5"), Some(0.5));
+        assert_eq!(format.parse_number(" -1.5e-3\u{a0}"), Some(-0.0015));
+        assert_eq!(format.parse_number("12,500.25"), Some(12500.25));
+        assert_eq!(format.parse_number("1 234 567"), Some(1234567.0));
+        assert_eq!(format.parse_number("12'500"), Some(12500.0));
+        assert_eq!(format.parse_number("1,5"), None);
+        assert_eq!(format.parse_number("0,500"), None);
+        assert_eq!(format.parse_number("1.2.3"), None);
+        assert_eq!(format.parse_number(""), None);
+        assert_eq!(format.parse_number("seed"), None);
+    }
+
+    #[test]
+    fn numbers_with_decimal_commas() {
+        let format = CsvFormat::european();
+        assert_eq!(format.parse_number("0,5"), Some(0.5));
+        assert_eq!(format.parse_number("-0,25"), Some(-0.25));
+        assert_eq!(format.parse_number("12.500,5"), Some(12500.5));
+        assert_eq!(format.parse_number("12 500,5"), Some(12500.5));
+        assert_eq!(format.parse_number("3"), Some(3.0));
+        assert_eq!(format.parse_number("1,5e2"), Some(150.0));
+        assert_eq!(format.parse_number("0.5"), None);
+        assert_eq!(format.parse_number("1,2,3"), None);
+        assert_eq!(format.parse_number("1,2.5"), None);
+    }
+
+    #[test]
+    fn formats_are_detected() {
+        assert_eq!(CsvFormat::detect("0,1.5\n0.3,0.8\n"), CsvFormat::default());
+        assert_eq!(CsvFormat::detect("0;1,5\n0,3;0,8\n"), CsvFormat::european());
+        assert_eq!(
+            CsvFormat::detect("0;1.5\n0.3;0.8\n"),
+            CsvFormat::new(b';', DecimalSeparator::Point).unwrap()
+        );
+        assert_eq!(
+            CsvFormat::detect("0\t1,5\n\n0,3\t0,8\n"),
+            CsvFormat::new(b'\t', DecimalSeparator::Comma).unwrap()
+        );
+        // A semicolon inside a quoted stage name is not a delimiter.
+        assert_eq!(
+            CsvFormat::detect("\"\",\"a;b\",\"c\"\n\"a;b\",0,1.5\n\"c\",0.3,0.8\n"),
+            CsvFormat::default()
+        );
+        assert_eq!(CsvFormat::detect("12\n"), CsvFormat::default());
+        assert_eq!(CsvFormat::detect(""), CsvFormat::default());
+    }
+
+    #[test]
+    fn invalid_formats_are_rejected() {
+        assert!(CsvFormat::new(b',', DecimalSeparator::Comma).is_err());
+        assert!(CsvFormat::new(b'.', DecimalSeparator::Comma).is_err());
+        assert!(CsvFormat::new(b'e', DecimalSeparator::Point).is_err());
+        assert!(CsvFormat::new(b'|', DecimalSeparator::Comma).is_ok());
+    }
+}
@@ -0,0 +1,387 @@
+//! This module contains habitat-quality rasters: grids of cells covering a landscape, each holding a habitat-quality value. Rasters can be read from ESRI ASCII grid files, or from GeoTIFF files with the `geotiff` feature, and turned into patches for spatial models with vital rates scaled by quality.
+use super::read_text;
+use crate::populations::patch_occupancy::Patch;
+use std::{error::Error, io::Read};
+
+/// This struct holds a habitat-quality raster. Values are stored row by row, starting with the
+/// northernmost (top) row, as in ASCII grid files; cells with no data hold `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HabitatRaster {
+    columns: usize,
+    rows: usize,
+    x_lower_left: f64,
+    y_lower_left: f64,
+    cell_size: f64,
+    values: Vec<Option<f64>>,
+}
+impl HabitatRaster {
+    /// Build a Habitat Raster from its size, the coordinates of its lower left corner, the width
+    /// of each (square) cell, and its values row by row from the top.
+    /// # Errors
+    /// Will return `Err<'static str>` if the number of values does not match the size, or the
+    /// cell size is not positive.
+    pub fn build(
+        columns: usize,
+        rows: usize,
+        x_lower_left: f64,
+        y_lower_left: f64,
+        cell_size: f64,
+        values: Vec<Option<f64>>,
+    ) -> Result<HabitatRaster, &'static str> {
+        if columns.checked_mul(rows) != Some(values.len()) {
+            return Err("The raster must have one value for every cell.");
+        }
+        if cell_size.is_nan() || cell_size <= 0.0 {
+            return Err("The raster cell size must be positive.");
+        }
+        Ok(HabitatRaster {
+            columns,
+            rows,
+            x_lower_left,
+            y_lower_left,
+            cell_size,
+            values,
+        })
+    }
+    /// Return the number of columns.
+    pub fn get_columns(&self) -> usize {
+        self.columns
+    }
+    /// Return the number of rows.
+    pub fn get_rows(&self) -> usize {
+        self.rows
+    }
+    /// Return the width of each cell.
+    pub fn get_cell_size(&self) -> f64 {
+        self.cell_size
+    }
+    /// Return the value of a cell, or `None` if the cell has no data or is outside the raster.
+    /// Row 0 is the top row.
+    pub fn value(&self, row: usize, column: usize) -> Option<f64> {
+        if row >= self.rows || column >= self.columns {
+            return None;
+        }
+        self.values[row * self.columns + column]
+    }
+    /// Return the value of the cell containing the point `(x, y)`, or `None` if the point is
+    /// outside the raster, either coordinate is not finite, or the cell has no data.
+    pub fn value_at(&self, x: f64, y: f64) -> Option<f64> {
+        let column = ((x - self.x_lower_left) / self.cell_size).floor();
+        let row_from_bottom = ((y - self.y_lower_left) / self.cell_size).floor();
+        if !column.is_finite()
+            || !row_from_bottom.is_finite()
+            || column < 0.0
+            || row_from_bottom < 0.0
+            || row_from_bottom >= self.rows as f64
+        {
+            return None;
+        }
+        self.value(self.rows - 1 - row_from_bottom as usize, column as usize)
+    }
+    /// Return the coordinates of the center of a cell.
+    pub fn cell_center(&self, row: usize, column: usize) -> (f64, f64) {
+        (
+            self.x_lower_left + (column as f64 + 0.5) * self.cell_size,
+            self.y_lower_left + (self.rows - row) as f64 * self.cell_size - 0.5 * self.cell_size,
+        )
+    }
+    /// Return one patch for every cell with a quality above zero, located at the center of the
+    /// cell, for use in a [`crate::populations::patch_occupancy::OccupancyModel`]. Vital rates
+    /// are scaled by quality: the colonization rate is `colonization_rate * quality`, and the
+    /// extinction probability is `extinction_probability / quality` (at most one), so a cell with
+    /// a quality of one has the given rates and poorer cells are harder to colonize and more
+    /// likely to go extinct. Patches are listed row by row from the top.
+    pub fn to_patches(&self, extinction_probability: f64, colonization_rate: f64) -> Vec<Patch> {
+        let mut patches = Vec::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                if let Some(quality) = self.value(row, column).filter(|x| *x > 0.0) {
+                    let (x, y) = self.cell_center(row, column);
+                    patches.push(Patch {
+                        x,
+                        y,
+                        extinction_probability: (extinction_probability / quality).min(1.0),
+                        colonization_rate: colonization_rate * quality,
+                    });
+                }
+            }
+        }
+        patches
+    }
+    /// Return one patch for every cell with data, located at the center of the cell, with the
+    /// given rates unscaled. These are the patches to use with a
+    /// [`crate::populations::patch_occupancy::HabitatTimeline`], which scales the rates by the
+    /// quality of each step instead, so cells with a quality of zero at the start can still be
+    /// restored. Cells with no data get no patch; use [`HabitatRaster::union_cell_patches`] when
+    /// later snapshots add data. Patches are listed row by row from the top.
+    pub fn cell_patches(&self, extinction_probability: f64, colonization_rate: f64) -> Vec<Patch> {
+        HabitatRaster::union_cell_patches(&[self], extinction_probability, colonization_rate)
+            .expect("This error should not be possible. A raster covers its own grid. Please file a bug report.")
+    }
+    /// Return one patch for every cell with data in any of the rasters, located at the center of
+    /// the cell, with the given rates unscaled, as [`HabitatRaster::cell_patches`] does for one
+    /// raster. This covers every cell a series of snapshots gives habitat to, including cells
+    /// with no data at the start.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no rasters, or they do not all cover the same
+    /// grid of cells.
+    pub fn union_cell_patches(
+        rasters: &[&HabitatRaster],
+        extinction_probability: f64,
+        colonization_rate: f64,
+    ) -> Result<Vec<Patch>, &'static str> {
+        let first = rasters.first().ok_or("At least one raster is required.")?;
+        let grid = |x: &HabitatRaster| {
+            (
+                x.columns,
+                x.rows,
+                x.x_lower_left,
+                x.y_lower_left,
+                x.cell_size,
+            )
+        };
+        if rasters.iter().any(|x| grid(x) != grid(first)) {
+            return Err("Every raster must cover the same grid of cells.");
+        }
+        let mut patches = Vec::new();
+        for row in 0..first.rows {
+            for column in 0..first.columns {
+                if rasters.iter().any(|x| x.value(row, column).is_some()) {
+                    let (x, y) = first.cell_center(row, column);
+                    patches.push(Patch {
+                        x,
+                        y,
+                        extinction_probability,
+                        colonization_rate,
+                    });
+                }
+            }
+        }
+        Ok(patches)
+    }
+}
+
+/// The largest number of cells [`read_ascii_grid`] accepts, so a corrupt header cannot make it
+/// reserve more memory than any habitat map needs.
+pub const MAX_RASTER_CELLS: usize = 100_000_000;
+
+/// Read a raster in ESRI ASCII grid format (`.asc`): a header of `ncols`, `nrows`, `xllcorner` (or
+/// `xllcenter`), `yllcorner` (or `yllcenter`), `cellsize`, and optionally `NODATA_value`,
+/// followed by the values row by row from the top.
+/// # Errors
+/// Returns an error if a header entry is missing, `ncols` or `nrows` is not a whole number, the
+/// raster has more than [`MAX_RASTER_CELLS`] cells, a value cannot be read as a number, or the
+/// number of values does not match the header.
+/// ```
+/// use ecolysis_cmd::imports::read_ascii_grid;
+/// let file = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 10\nNODATA_value -9999\n\
+///             0.5 1 -9999\n0 0.25 1\n";
+/// let raster = read_ascii_grid(file.as_bytes()).unwrap();
+/// assert_eq!(raster.value(0, 1), Some(1.0));
+/// assert_eq!(raster.value(0, 2), None);
+/// assert_eq!(raster.value_at(15.0, 5.0), Some(0.25));
+/// ```
+pub fn read_ascii_grid<R: Read>(reader: R) -> Result<HabitatRaster, Box<dyn Error>> {
+    let contents = read_text(reader)?;
+    let mut tokens = contents.split_whitespace().peekable();
+    let (mut columns, mut rows, mut x, mut y, mut cell_size) = (None, None, None, None, None);
+    let (mut centered_x, mut centered_y, mut nodata) = (false, false, None);
+    while let Some(key) = tokens.peek() {
+        if key.parse::<f64>().is_ok() {
+            break;
+        }
+        let key = tokens.next().unwrap_or_default().to_lowercase();
+        let value = tokens
+            .next()
+            .ok_or_else(|| format!("Missing value for \"{}\".", key))?;
+        let number: f64 = value
+            .parse()
+            .map_err(|_| format!("Invalid value for \"{}\": \"{}\".", key, value))?;
+        if (key == "ncols" || key == "nrows") && (number < 0.0 || number.fract() != 0.0) {
+            return Err(format!("\"{}\" must be a whole number, not \"{}\".", key, value).into());
+        }
+        match key.as_str() {
+            "ncols" => columns = Some(number as usize),
+            "nrows" => rows = Some(number as usize),
+            "xllcorner" => x = Some(number),
+            "yllcorner" => y = Some(number),
+            "xllcenter" => (x, centered_x) = (Some(number), true),
+            "yllcenter" => (y, centered_y) = (Some(number), true),
+            "cellsize" => cell_size = Some(number),
+            "nodata_value" => nodata = Some(number),
+            _ => return Err(format!("Unknown header entry \"{}\".", key).into()),
+        }
+    }
+    let missing = |name: &str| format!("The header is missing \"{}\".", name);
+    let columns = columns.ok_or_else(|| missing("ncols"))?;
+    let rows = rows.ok_or_else(|| missing("nrows"))?;
+    let cell_size = cell_size.ok_or_else(|| missing("cellsize"))?;
+    let mut x = x.ok_or_else(|| missing("xllcorner"))?;
+    let mut y = y.ok_or_else(|| missing("yllcorner"))?;
+    if centered_x {
+        x -= cell_size / 2.0;
+    }
+    if centered_y {
+        y -= cell_size / 2.0;
+    }
+    let cells = columns
+        .checked_mul(rows)
+        .filter(|x| *x <= MAX_RASTER_CELLS)
+        .ok_or_else(|| {
+            format!(
+                "The raster has more than {} cells ({} rows of {}).",
+                MAX_RASTER_CELLS, rows, columns
+            )
+        })?;
+    let mut values = Vec::with_capacity(cells);
+    for (index, token) in tokens.enumerate() {
+        let value: f64 = token.parse().map_err(|_| {
+            format!(
+                "Invalid value \"{}\" at row {}, column {}.",
+                token,
+                index / columns.max(1) + 1,
+                index % columns.max(1) + 1
+            )
+        })?;
+        values.push(if Some(value) == nodata {
+            None
+        } else {
+            Some(value)
+        });
+    }
+    if values.len() != cells {
+        return Err(format!(
+            "Expected {} values ({} rows of {}), found {}.",
+            cells,
+            rows,
+            columns,
+            values.len()
+        )
+        .into());
+    }
+    Ok(HabitatRaster::build(
+        columns, rows, x, y, cell_size, values,
+    )?)
+}
+
+/// Read the first band of a GeoTIFF file as a raster. The location and cell size are taken from
+/// the `ModelTiepointTag` and `ModelPixelScaleTag` tags when present (otherwise the raster starts
+/// at the origin with cells of width one), and the no-data value from the `GDAL_NODATA` tag.
+/// # Errors
+/// Returns an error if the file cannot be decoded or its cells are not square.
+#[cfg(feature = "geotiff")]
+pub fn read_geotiff<R: Read + std::io::Seek>(reader: R) -> Result<HabitatRaster, Box<dyn Error>> {
+    use tiff::{
+        decoder::{Decoder, DecodingResult},
+        tags::Tag,
+    };
+    let mut decoder = Decoder::new(reader)?;
+    let (columns, rows) = decoder.dimensions()?;
+    let (columns, rows) = (columns as usize, rows as usize);
+    let scale = decoder.get_tag_f64_vec(Tag::Unknown(33550)).ok();
+    let tiepoint = decoder.get_tag_f64_vec(Tag::Unknown(33922)).ok();
+    let nodata: Option<f64> = decoder
+        .get_tag_ascii_string(Tag::Unknown(42113))
+        .ok()
+        .and_then(|x| x.trim_matches(char::from(0)).trim().parse().ok());
+    let (cell_size, x_top_left, y_top_left) = match (scale, tiepoint) {
+        (Some(scale), Some(tiepoint)) if scale.len() >= 2 && tiepoint.len() >= 5 => {
+            if (scale[0] - scale[1]).abs() > 1e-9 * scale[0].abs() {
+                return Err("Only rasters with square cells are supported.".into());
+            }
+            (
+                scale[0],
+                tiepoint[3] - tiepoint[0] * scale[0],
+                tiepoint[4] + tiepoint[1] * scale[1],
+            )
+        }
+        _ => (1.0, 0.0, rows as f64),
+    };
+    let values: Vec<f64> = match decoder.read_image()? {
+        DecodingResult::U8(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::U32(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::U64(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I8(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::I16(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::I32(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::I64(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::F32(x) => x.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(x) => x,
+    };
+    let samples = values.len() / (columns * rows).max(1);
+    let values = values
+        .into_iter()
+        .step_by(samples.max(1))
+        .map(|x| {
+            if Some(x) == nodata || x.is_nan() {
+                None
+            } else {
+                Some(x)
+            }
+        })
+        .collect();
+    Ok(HabitatRaster::build(
+        columns,
+        rows,
+        x_top_left,
+        y_top_left - rows as f64 * cell_size,
+        cell_size,
+        values,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_grid_centers_and_errors() {
+        let file = "NCOLS 2\nNROWS 2\nXLLCENTER 5\nYLLCENTER 5\nCELLSIZE 10\n1 2\n3 4\n";
+        let raster = read_ascii_grid(file.as_bytes()).unwrap();
+        assert_eq!(raster.cell_center(1, 0), (5.0, 5.0));
+        assert_eq!(raster.value_at(19.0, 19.0), Some(2.0));
+        assert_eq!(raster.value_at(-1.0, 5.0), None);
+        assert_eq!(raster.value_at(f64::NAN, 5.0), None);
+        assert_eq!(raster.value_at(5.0, f64::INFINITY), None);
+        let short = "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2 3\n";
+        assert!(read_ascii_grid(short.as_bytes()).is_err());
+        let missing = "ncols 2\nnrows 1\nxllcorner 0\ncellsize 1\n1 2\n";
+        assert!(read_ascii_grid(missing.as_bytes()).is_err());
+        let huge = "ncols 4000000000\nnrows 4000000000\nxllcorner 0\nyllcorner 0\ncellsize 1\n1\n";
+        assert!(read_ascii_grid(huge.as_bytes()).is_err());
+        let fractional = "ncols 1.5\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n1\n";
+        assert!(read_ascii_grid(fractional.as_bytes()).is_err());
+    }
+    #[test]
+    fn patches_scale_with_quality() {
+        let raster =
+            HabitatRaster::build(3, 1, 0.0, 0.0, 1.0, vec![Some(1.0), Some(0.0), Some(0.5)])
+                .unwrap();
+        let patches = raster.to_patches(0.2, 0.8);
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[1].x, 2.5);
+        assert!((patches[1].extinction_probability - 0.4).abs() < 1e-12);
+        assert!((patches[1].colonization_rate - 0.4).abs() < 1e-12);
+        assert_eq!(raster.cell_patches(0.2, 0.8).len(), 3);
+        let shifted =
+            HabitatRaster::build(3, 1, 1.0, 0.0, 1.0, vec![Some(1.0), None, None]).unwrap();
+        assert!(HabitatRaster::union_cell_patches(&[&raster, &shifted], 0.2, 0.8).is_err());
+        assert!(HabitatRaster::union_cell_patches(&[], 0.2, 0.8).is_err());
+    }
+    #[cfg(feature = "geotiff")]
+    #[test]
+    fn geotiff_round_trip() {
+        use tiff::encoder::{colortype::Gray32Float, TiffEncoder};
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        TiffEncoder::new(&mut buffer)
+            .unwrap()
+            .write_image::<Gray32Float>(2, 2, &[0.5, 1.0, 0.0, 0.25])
+            .unwrap();
+        buffer.set_position(0);
+        let raster = read_geotiff(buffer).unwrap();
+        assert_eq!(raster.get_columns(), 2);
+        assert_eq!(raster.value(1, 1), Some(0.25));
+        assert_eq!(raster.value_at(1.5, 1.5), Some(1.0));
+    }
+}
@@ -0,0 +1,129 @@
+//! This module reads the text of input files the way spreadsheets and editors on Windows save it: with a byte order mark, with Windows (`\r\n`) or old Mac (`\r`) line endings, or as UTF-16. Bytes that are not text in any of these encodings are reported with their line and column, rather than as an unexplained failure to read the file.
+use std::{error::Error, io::Read};
+
+/// The byte order mark that Excel and Notepad write at the start of UTF-8 files.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Decode the contents of a text file. A UTF-8 byte order mark is removed, files starting with a
+/// UTF-16 byte order mark (as saved by Excel's "Unicode Text" option) are decoded as UTF-16, and
+/// every line ending becomes `\n`.
+/// # Errors
+/// Returns a message with the line and column of the first byte that is not UTF-8 (or UTF-16)
+/// text, such as a Latin-1 accented letter.
+/// ```
+/// use ecolysis_cmd::imports::decode_text;
+/// assert_eq!(decode_text(b"\xEF\xBB\xBFstage,value\r\nseed,0.5\r\n").unwrap(), "stage,value\nseed,0.5\n");
+/// let error = decode_text(b"stage\nfl\xE9t,1\n").unwrap_err();
+/// assert!(error.contains("line 2, column 3"));
+/// ```
+pub fn decode_text(bytes: &[u8]) -> Result<String, String> {
+    let text = if let Some(bytes) = bytes.strip_prefix(UTF8_BOM) {
+        utf8(bytes)?
+    } else if let Some(bytes) = bytes.strip_prefix(b"\xFF\xFE") {
+        utf16(bytes, u16::from_le_bytes)?
+    } else if let Some(bytes) = bytes.strip_prefix(b"\xFE\xFF") {
+        utf16(bytes, u16::from_be_bytes)?
+    } else {
+        utf8(bytes)?
+    };
+    Ok(if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text
+    })
+}
+
+/// Read all of `reader` and decode it with [`decode_text`].
+/// # Errors
+/// Returns an error if the reader fails or the contents are not text.
+pub fn read_text<R: Read>(mut reader: R) -> Result<String, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(decode_text(&bytes)?)
+}
+
+/// Return the line and column (both starting at 1) of the end of `text`.
+fn position(text: &str) -> (usize, usize) {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let line_start = text.rfind('\n').map_or(0, |x| x + 1);
+    (
+        text.matches('\n').count() + 1,
+        text[line_start..].chars().count() + 1,
+    )
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, String> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.to_string()),
+        Err(error) => {
+            let valid = &bytes[..error.valid_up_to()];
+            let (line, column) = position(std::str::from_utf8(valid).unwrap_or_default());
+            Err(format!(
+                "The byte 0x{:02X} at line {}, column {} is not UTF-8 text. Save the file with \
+UTF-8 encoding (\"CSV UTF-8\" in Excel).",
+                bytes[error.valid_up_to()],
+                line,
+                column
+            ))
+        }
+    }
+}
+
+fn utf16(bytes: &[u8], decode: fn([u8; 2]) -> u16) -> Result<String, String> {
+    let units = bytes.chunks(2).map(|x| match *x {
+        [first, second] => decode([first, second]),
+        // An odd trailing byte cannot be a whole character.
+        _ => 0xDC00,
+    });
+    let mut text = String::with_capacity(bytes.len() / 2);
+    for character in char::decode_utf16(units) {
+        match character {
+            Ok(character) => text.push(character),
+            Err(_) => {
+                let (line, column) = position(&text);
+                return Err(format!(
+                    "The UTF-16 text is damaged at line {}, column {}. Save the file with UTF-8 \
+encoding (\"CSV UTF-8\" in Excel).",
+                    line, column
+                ));
+            }
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_text_is_decoded() {
+        assert_eq!(decode_text(b"a,b\rc,d").unwrap(), "a,b\nc,d");
+        let utf16: Vec<u8> = [0xFEFF, 0x61, 0x2C, 0xE9, 0x0D, 0x0A, 0x31]
+            .iter()
+            .flat_map(|x: &u16| x.to_le_bytes())
+            .collect();
+        assert_eq!(decode_text(&utf16).unwrap(), "a,\u{e9}\n1");
+        let utf16: Vec<u8> = [0xFEFF, 0x62]
+            .iter()
+            .flat_map(|x: &u16| x.to_be_bytes())
+            .collect();
+        assert_eq!(decode_text(&utf16).unwrap(), "b");
+        assert_eq!(decode_text(b"").unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_bytes_are_located() {
+        let error = decode_text(b"\xEF\xBB\xBFseed,1\r\n\xE9t\xE9,2\n").unwrap_err();
+        assert!(
+            error.starts_with("The byte 0xE9 at line 2, column 1 "),
+            "{}",
+            error
+        );
+        let error = decode_text(b"\xE9\xE9\xE9,1\n").unwrap_err();
+        assert!(error.contains("line 1, column 1"), "{}", error);
+        assert!(decode_text(b"\xFF\xFEa\x00b")
+            .unwrap_err()
+            .contains("line 1, column 2"));
+    }
+}
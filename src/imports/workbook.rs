@@ -0,0 +1,336 @@
+//! This module contains readers for matrices and population vectors kept in spreadsheet workbooks (Excel `.xlsx`, `.xlsm`, and `.xls` files, and OpenDocument `.ods` files), with the `xlsx` feature. The cells to read are chosen by worksheet and range, as they would be typed in a spreadsheet formula (`Adults!B2:H8`).
+use super::{CsvFormat, LabelledMatrix};
+use crate::populations::population_level_simulation::{PopulationMatrix, PopulationVector};
+use calamine::{open_workbook_auto_from_rs, Data, Range, Reader};
+use std::{
+    error::Error,
+    io::{Cursor, Read},
+};
+
+/// This struct selects the cells to read from a workbook: a worksheet (the first one if none is
+/// named) and a rectangular range of cells on it (every used cell if none is given).
+/// ```
+/// use ecolysis_cmd::imports::SheetSelection;
+/// let selection = SheetSelection::parse("'Vital rates'!$B$2:H8").unwrap();
+/// assert_eq!(selection.get_sheet(), Some("Vital rates"));
+/// assert_eq!(selection.get_range(), Some(((1, 1), (7, 7))));
+/// assert_eq!(SheetSelection::parse("!B2:C3").unwrap().get_sheet(), None);
+/// assert!(SheetSelection::parse("Adults!B2").is_err());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SheetSelection {
+    sheet: Option<String>,
+    range: Option<((u32, u32), (u32, u32))>,
+}
+impl SheetSelection {
+    /// Create a Sheet Selection of every used cell on the first worksheet.
+    pub fn new() -> SheetSelection {
+        SheetSelection::default()
+    }
+    /// Parse a selection written as in a spreadsheet formula: a worksheet name, a range
+    /// (`B2:H8`) after a `!`, or both (`Adults!B2:H8`). Worksheet names can be quoted
+    /// (`'Vital rates'!B2:H8`), and `$` signs in the range are ignored.
+    /// # Errors
+    /// Will return `Err<'static str>` if the range is not two cells separated by a colon.
+    pub fn parse(reference: &str) -> Result<SheetSelection, &'static str> {
+        let (sheet, range) = match reference.rsplit_once('!') {
+            Some((sheet, range)) => (sheet, Some(range)),
+            None => (reference, None),
+        };
+        let sheet = sheet.trim();
+        let sheet = sheet
+            .strip_prefix('\'')
+            .and_then(|x| x.strip_suffix('\''))
+            .unwrap_or(sheet);
+        let mut selection = SheetSelection::new();
+        if !sheet.is_empty() {
+            selection = selection.with_sheet(sheet);
+        }
+        match range {
+            Some(range) => selection.with_range(range),
+            None => Ok(selection),
+        }
+    }
+    /// Read from the named worksheet.
+    pub fn with_sheet(mut self, name: &str) -> Self {
+        self.sheet = Some(name.to_string());
+        self
+    }
+    /// Read only the cells in a range such as `B2:H8`. The corners can be given in either order.
+    /// # Errors
+    /// Will return `Err<'static str>` if the range is not two cells separated by a colon.
+    pub fn with_range(mut self, range: &str) -> Result<Self, &'static str> {
+        const INVALID: &str = "A cell range must be two cells separated by a colon, as in B2:H8.";
+        let (first, last) = range.split_once(':').ok_or(INVALID)?;
+        let (first, last) = (
+            parse_cell(first).ok_or(INVALID)?,
+            parse_cell(last).ok_or(INVALID)?,
+        );
+        self.range = Some((
+            (first.0.min(last.0), first.1.min(last.1)),
+            (first.0.max(last.0), first.1.max(last.1)),
+        ));
+        Ok(self)
+    }
+    /// Return the name of the selected worksheet, or `None` for the first one.
+    pub fn get_sheet(&self) -> Option<&str> {
+        self.sheet.as_deref()
+    }
+    /// Return the first and last (row, column) of the selected range, counted from 0, or `None`
+    /// if every used cell is selected.
+    pub fn get_range(&self) -> Option<((u32, u32), (u32, u32))> {
+        self.range
+    }
+}
+
+/// Read a cell reference such as `B12` or `$B$12` as a (row, column) pair counted from 0.
+fn parse_cell(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.trim().replace('$', "");
+    let split = reference.find(|x: char| x.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || letters.len() > 3 || !letters.bytes().all(|x| x.is_ascii_alphabetic())
+    {
+        return None;
+    }
+    let column = letters.bytes().fold(0, |column, x| {
+        column * 26 + u32::from(x.to_ascii_uppercase() - b'A' + 1)
+    });
+    let row = digits.parse::<u32>().ok().filter(|x| *x > 0)?;
+    Some((row - 1, column - 1))
+}
+
+/// Write a (row, column) pair counted from 0 as a cell reference such as `B12`.
+fn cell_name((row, column): (u32, u32)) -> String {
+    let mut letters = Vec::new();
+    let mut column = column + 1;
+    while column > 0 {
+        letters.push(b'A' + ((column - 1) % 26) as u8);
+        column = (column - 1) / 26;
+    }
+    letters.reverse();
+    format!("{}{}", String::from_utf8_lossy(&letters), row + 1)
+}
+
+/// Read the selected cells of a workbook.
+fn read_cells<R: Read>(
+    mut reader: R,
+    selection: &SheetSelection,
+) -> Result<Range<Data>, Box<dyn Error>> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(contents))
+        .map_err(|e| format!("Could not open the workbook: {}", e))?;
+    let sheet = match selection.get_sheet() {
+        Some(sheet) => sheet.to_string(),
+        None => workbook
+            .sheet_names()
+            .into_iter()
+            .next()
+            .ok_or("The workbook has no worksheets.")?,
+    };
+    let cells = workbook
+        .worksheet_range(&sheet)
+        .map_err(|e| format!("Could not read the worksheet \"{}\": {}", sheet, e))?;
+    Ok(match selection.get_range() {
+        Some((first, last)) => cells.range(first, last),
+        None => cells,
+    })
+}
+
+/// Return the value of a numeric cell. Text cells holding a number (as left by some CSV imports)
+/// are read too.
+fn number(cell: &Data) -> Option<f64> {
+    match cell {
+        Data::Int(x) => Some(*x as f64),
+        Data::Float(x) => Some(*x),
+        Data::String(x) => CsvFormat::default().parse_number(x),
+        _ => None,
+    }
+}
+
+/// The error for a cell that should hold a number.
+fn not_a_number(cell: &Data, position: (u32, u32)) -> String {
+    match cell {
+        Data::Empty => format!("Cell {} is empty.", cell_name(position)),
+        cell => format!(
+            "Cell {} (\"{}\") is not a number.",
+            cell_name(position),
+            cell
+        ),
+    }
+}
+
+/// Read a projection matrix from the selected cells of a workbook. As in
+/// [`read_popbio_csv`](super::read_popbio_csv), the cells can include a row of stage names above
+/// the matrix (with an empty or labelled corner cell) and a column of stage names to its left;
+/// unnamed stages are called `stage_0`, `stage_1`, etc.
+/// # Errors
+/// Returns an error if the workbook or worksheet cannot be read, a cell is empty or not a number
+/// (with its cell reference), or the matrix is not square.
+pub fn read_workbook_matrix<R: Read>(
+    reader: R,
+    selection: &SheetSelection,
+) -> Result<LabelledMatrix, Box<dyn Error>> {
+    let cells = read_cells(reader, selection)?;
+    let Some((first_row, first_column)) = cells.start() else {
+        return Err("The selected cells are empty.".into());
+    };
+    let mut rows: Vec<(u32, &[Data])> = (first_row..).zip(cells.rows()).collect();
+    let has_header = rows[0].1.iter().any(|cell| number(cell).is_none());
+    let header = if has_header {
+        Some(rows.remove(0).1)
+    } else {
+        None
+    };
+    let has_row_names = rows
+        .iter()
+        .any(|(_, row)| row.first().is_some_and(|cell| number(cell).is_none()));
+    let mut stage_names = Vec::new();
+    let mut matrix = Vec::new();
+    for (row_number, row) in rows {
+        let mut values = Vec::new();
+        for (column_number, cell) in (first_column..).zip(row) {
+            if has_row_names && column_number == first_column {
+                stage_names.push(cell.to_string());
+                continue;
+            }
+            values
+                .push(number(cell).ok_or_else(|| not_a_number(cell, (row_number, column_number)))?);
+        }
+        matrix.push(values);
+    }
+    if !has_row_names {
+        stage_names = match header {
+            Some(header) => {
+                let corner_cells = header.len().saturating_sub(matrix.len());
+                header[corner_cells..].iter().map(Data::to_string).collect()
+            }
+            None => (0..matrix.len())
+                .map(|stage| format!("stage_{}", stage))
+                .collect(),
+        };
+    }
+    Ok(LabelledMatrix {
+        stage_names,
+        matrix: PopulationMatrix::build(matrix)?,
+    })
+}
+
+/// Read a population vector from the selected cells of a workbook, which must be a single row or
+/// a single column. A label in the first cell (such as `initial` beside the counts) is skipped.
+/// # Errors
+/// Returns an error if the workbook or worksheet cannot be read, the cells are not a single row
+/// or column, or a count is not a non-negative number (with its cell reference).
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use ecolysis_cmd::imports::{read_workbook_vector, SheetSelection};
+/// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/loggerhead.xlsx");
+/// let workbook = std::fs::File::open(path)?;
+/// let vector = read_workbook_vector(workbook, &SheetSelection::parse("Loggerhead!A12:H12")?)?;
+/// assert_eq!(vector.get_vector()[0], 2000.0);
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_workbook_vector<R: Read>(
+    reader: R,
+    selection: &SheetSelection,
+) -> Result<PopulationVector, Box<dyn Error>> {
+    let cells = read_cells(reader, selection)?;
+    let Some(start) = cells.start() else {
+        return Err("The selected cells are empty.".into());
+    };
+    let (height, width) = cells.get_size();
+    if height > 1 && width > 1 {
+        return Err("A population vector must be a single row or a single column of cells.".into());
+    }
+    let mut cells: Vec<((u32, u32), &Data)> = cells
+        .cells()
+        .map(|(row, column, cell)| ((start.0 + row as u32, start.1 + column as u32), cell))
+        .collect();
+    if cells.len() > 1 && number(cells[0].1).is_none() {
+        cells.remove(0);
+    }
+    let mut vector = Vec::with_capacity(cells.len());
+    for (position, cell) in cells {
+        match number(cell) {
+            Some(count) if count.is_finite() && count >= 0.0 => vector.push(count),
+            Some(_) => {
+                return Err(format!("Cell {} is not a valid count.", cell_name(position)).into())
+            }
+            None => return Err(not_a_number(cell, position).into()),
+        }
+    }
+    Ok(PopulationVector::new(vector))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKBOOK: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/loggerhead.xlsx"
+    ));
+
+    #[test]
+    fn cell_references() {
+        assert_eq!(parse_cell("A1"), Some((0, 0)));
+        assert_eq!(parse_cell("$ab$12"), Some((11, 27)));
+        assert_eq!(parse_cell("A0"), None);
+        assert_eq!(parse_cell("12"), None);
+        assert_eq!(parse_cell("B2C"), None);
+        for cell in [(0, 0), (11, 25), (3, 26), (9, 701), (0, 702)] {
+            assert_eq!(parse_cell(&cell_name(cell)), Some(cell));
+        }
+        assert_eq!(cell_name((0, 702)), "AAA1");
+        let reversed = SheetSelection::new().with_range("H8:B2").unwrap();
+        assert_eq!(reversed.get_range(), Some(((1, 1), (7, 7))));
+    }
+
+    #[test]
+    fn labelled_matrix_from_a_named_sheet() {
+        let selection = SheetSelection::parse("Loggerhead!A3:H10").unwrap();
+        let imported = read_workbook_matrix(WORKBOOK, &selection).unwrap();
+        assert_eq!(imported.get_stage_names()[1], "small_juveniles");
+        assert_eq!(imported.get_matrix()[(0, 4)], 127.0);
+        assert_eq!(imported.get_matrix()[(6, 6)], 0.8089);
+        let unlabelled = SheetSelection::parse("Loggerhead!B4:H10").unwrap();
+        let imported = read_workbook_matrix(WORKBOOK, &unlabelled).unwrap();
+        assert_eq!(imported.get_stage_names()[6], "stage_6");
+    }
+
+    #[test]
+    fn errors_name_the_cell() {
+        let error = |selection: &str| {
+            let selection = SheetSelection::parse(selection).unwrap();
+            read_workbook_matrix(WORKBOOK, &selection)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("Loggerhead!B12:C13"),
+            "Cell C13 (\"many\") is not a number."
+        );
+        assert_eq!(error("Loggerhead!I4:J5"), "Cell J5 is empty.");
+        assert!(error("Missing").contains("\"Missing\""));
+        // The first sheet only holds notes.
+        assert!(read_workbook_matrix(WORKBOOK, &SheetSelection::new()).is_err());
+        let vector = SheetSelection::parse("Loggerhead!A12:H13").unwrap();
+        assert!(read_workbook_vector(WORKBOOK, &vector).is_err());
+        assert!(read_workbook_matrix(&b"not a workbook"[..], &SheetSelection::new()).is_err());
+    }
+
+    #[test]
+    fn vectors_from_rows_and_columns() {
+        let row = SheetSelection::parse("Loggerhead!B12:H12").unwrap();
+        let vector = read_workbook_vector(WORKBOOK, &row).unwrap();
+        assert_eq!(
+            vector.get_vector(),
+            &vec![2000.0, 1500.0, 900.0, 400.0, 30.0, 25.0, 150.0]
+        );
+        let column = SheetSelection::parse("Loggerhead!B4:B10").unwrap();
+        let vector = read_workbook_vector(WORKBOOK, &column).unwrap();
+        assert_eq!(vector.get_vector()[1], 0.6747);
+    }
+}
@@ -0,0 +1,169 @@
+//! This module contains the command line interface: the interactive prompt and the subcommands that can be run directly from the shell (for example `ecolysis_cmd validate --matrix m.csv`).
+mod analyze;
+mod batch;
+mod compare;
+mod demo;
+mod entry;
+mod generate;
+mod project;
+mod repl;
+mod style;
+mod validate;
+use crate::outputs::create_output_file;
+use std::{fs::File, io, path::Path};
+pub enum Step<T> {
+    Continue(T),
+    Cancel,
+}
+
+/// The usage text printed by `help` and when an unknown command is given.
+const USAGE: &str = "Usage: ecolysis_cmd [COMMAND] [OPTIONS]
+
+Run without a command to use the interactive prompt.
+
+Commands:
+  analyze    Report lambda, sensitivities, and other summaries of a matrix without projecting
+  batch      Run every scenario file in a directory and write an index of the results
+  compare    Compare a covariate-driven scenario under several covariate futures
+  demo       Analyse a classic published matrix that is built into the program
+  generate   Generate random projection matrices for teaching and testing
+  project    Project a population from matrix and vector files or standard input
+  validate   Check matrix, population vector, and scenario files without running a simulation
+  help       Show this message
+
+Options:
+  --plain    Print errors, warnings, and headings without color (as does setting NO_COLOR)";
+
+/// Run the command given in `args` (the program arguments, without the program name) and return
+/// the exit code of the program. `--plain` can be given before the command, or anywhere among the
+/// arguments of a command (see [`Arguments::parse`]), to turn off color.
+pub fn run(mut args: Vec<String>) -> i32 {
+    while args.first().is_some_and(|x| x == "--plain") {
+        style::set_plain(true);
+        args.remove(0);
+    }
+    match args.first().map(String::as_str) {
+        None => match repl::run() {
+            Ok(()) => 0,
+            Err(error) => {
+                eprintln!("{}", style::error(error));
+                1
+            }
+        },
+        Some("analyze") => analyze::run(&args[1..]),
+        Some("batch") => batch::run(&args[1..]),
+        Some("compare") => compare::run(&args[1..]),
+        Some("demo") => demo::run(&args[1..]),
+        Some("generate") => generate::run(&args[1..]),
+        Some("project") => project::run(&args[1..]),
+        Some("validate") => validate::run(&args[1..]),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            0
+        }
+        Some(command) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error(format!("Unknown command \"{}\".", command)),
+                USAGE
+            );
+            2
+        }
+    }
+}
+
+/// This struct holds command line arguments split into positional arguments, options with
+/// values (`--name value` or `--name=value`), and flags without values (`--name`).
+pub struct Arguments {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+impl Arguments {
+    /// Split `args` into positional arguments, options, and flags. Any argument starting with
+    /// `--` whose name is in `flag_names` is a flag; any other is an option that takes a value.
+    /// Every command also accepts the `--plain` flag, which turns off color as soon as it is
+    /// read, while an option value that happens to be `--plain` is kept as a value.
+    /// # Errors
+    /// Returns an error if an option is missing its value.
+    pub fn parse(args: &[String], flag_names: &[&str]) -> Result<Arguments, String> {
+        let mut arguments = Arguments {
+            positional: Vec::new(),
+            options: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some("plain") => {
+                    style::set_plain(true);
+                    arguments.flags.push(String::from("plain"));
+                }
+                Some(name) if flag_names.contains(&name) => arguments.flags.push(name.to_string()),
+                Some(name) => match name.split_once('=') {
+                    Some((name, value)) => arguments
+                        .options
+                        .push((name.to_string(), value.to_string())),
+                    None => {
+                        let value = args
+                            .next()
+                            .ok_or_else(|| format!("The option --{} needs a value.", name))?;
+                        arguments.options.push((name.to_string(), value.clone()));
+                    }
+                },
+                None => arguments.positional.push(arg.clone()),
+            }
+        }
+        Ok(arguments)
+    }
+    /// Return the positional arguments.
+    pub fn get_positional(&self) -> &Vec<String> {
+        &self.positional
+    }
+    /// Return the value of the last use of an option, if it was given.
+    pub fn get_option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+    /// Return true if a flag was given.
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+}
+
+/// Create an output file named on the command line, refusing to replace an existing file unless
+/// `force` is true.
+fn create_output(path: &str, force: bool) -> Result<File, String> {
+    create_output_file(Path::new(path), force).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!("{}. Use --force to replace it.", e),
+        _ => format!("Could not create {}: {}", path, e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arguments;
+    #[test]
+    fn argument_parsing() {
+        let args: Vec<String> = ["file.csv", "--matrix", "m.csv", "--force", "--steps=10"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let arguments = Arguments::parse(&args, &["force"]).unwrap();
+        assert_eq!(arguments.get_positional(), &vec!["file.csv"]);
+        assert_eq!(arguments.get_option("matrix"), Some("m.csv"));
+        assert_eq!(arguments.get_option("steps"), Some("10"));
+        assert!(arguments.has_flag("force"));
+        assert!(Arguments::parse(&[String::from("--matrix")], &[]).is_err());
+        let args: Vec<String> = ["--plain", "--output", "--plain"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let arguments = Arguments::parse(&args, &[]).unwrap();
+        assert!(arguments.has_flag("plain"));
+        assert_eq!(arguments.get_option("output"), Some("--plain"));
+    }
+}
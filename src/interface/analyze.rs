@@ -0,0 +1,195 @@
+//! The `analyze` command, which reports the analytical summaries of a projection matrix (growth rate, stable stage distribution, reproductive values, sensitivities, elasticities, net reproductive rate, and generation time) without an initial population or a projection.
+use super::{create_output, style, Arguments};
+use crate::imports::{read_popbio_csv, LabelledMatrix};
+use crate::populations::population_level_simulation::{DecomposedMatrix, TimeStep};
+use std::{error::Error, fs::File, io, io::Write};
+
+const USAGE: &str = "Usage: ecolysis_cmd analyze [MATRIX] [--matrix FILE] [--fertility-rows ROWS]
+                          [--time-step STEP] [--output FILE] [--force]
+
+Writes a CSV table of the analytical summaries of a projection matrix, with one row per value:
+lambda, the net reproductive rate (R0), the generation time, the stable stage distribution and
+reproductive value of each lifestage, and the sensitivity and elasticity of lambda to each
+matrix element. The `stage` and `from` columns name the lifestages a value belongs to (for matrix
+elements, the row and column). No initial population is needed and nothing is projected. An
+existing output file is only replaced with --force.
+
+Options:
+  --matrix FILE     The matrix to analyze, instead of giving it as the first argument
+  --fertility-rows ROWS
+                    The matrix rows (starting at 1, separated by commas) that hold
+                    reproduction rather than survival, used for R0 and the generation
+                    time (default 1)
+  --time-step STEP  Length of time one step represents, such as `1 year` or `6 months`;
+                    adds the generation time in that unit (as `generation_time_months`)
+  --output FILE     Write the table to FILE instead of standard output
+  --force           Replace FILE if it already exists";
+
+/// Run the `analyze` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let Some(matrix) = arguments
+        .get_option("matrix")
+        .or(arguments.get_positional().first().map(String::as_str))
+    else {
+        eprintln!("{}\n\n{}", style::error("A matrix is required."), USAGE);
+        return 2;
+    };
+    let fertility_rows = match parse_rows(arguments.get_option("fertility-rows").unwrap_or("1")) {
+        Ok(rows) => rows,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    let time_step = match arguments
+        .get_option("time-step")
+        .map(|x| x.parse::<TimeStep>())
+        .transpose()
+    {
+        Ok(time_step) => time_step,
+        Err(error) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error(format!("--time-step: {}", error)),
+                USAGE
+            );
+            return 2;
+        }
+    };
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let file = File::open(matrix).map_err(|e| format!("Could not open {}: {}", matrix, e))?;
+        let matrix = read_popbio_csv(file).map_err(|e| format!("{}: {}", matrix, e))?;
+        let writer: Box<dyn Write> = match arguments.get_option("output") {
+            Some(path) => Box::new(create_output(path, arguments.has_flag("force"))?),
+            None => Box::new(io::stdout().lock()),
+        };
+        analyze(&matrix, &fertility_rows, time_step, writer)
+    })();
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Parse a list of matrix rows starting at 1, such as `1,2`, into row positions starting at 0.
+fn parse_rows(text: &str) -> Result<Vec<usize>, String> {
+    text.split(',')
+        .map(|x| match x.trim().parse::<usize>() {
+            Ok(row) if row > 0 => Ok(row - 1),
+            _ => Err(format!(
+                "--fertility-rows must be row numbers starting at 1, not \"{}\".",
+                x.trim()
+            )),
+        })
+        .collect()
+}
+
+/// Write the analysis table of `labelled` to `writer`, with the generation time also in the unit
+/// of `time_step` if one is given. If the matrix cannot be split into survival and fertility with
+/// `fertility_rows`, R0 and the generation time are left out and the reason is printed as a
+/// warning.
+fn analyze<W: Write>(
+    labelled: &LabelledMatrix,
+    fertility_rows: &[usize],
+    time_step: Option<TimeStep>,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let matrix = labelled.get_matrix();
+    let names = labelled.get_stage_names();
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(["measure", "stage", "from", "value"])?;
+    let mut write = |measure: &str, stage: &str, from: &str, value: f64| {
+        csv_writer.write_record([measure, stage, from, &value.to_string()])
+    };
+    write("lambda", "", "", matrix.lambda())?;
+    match DecomposedMatrix::from_fertility_rows(matrix, fertility_rows)
+        .and_then(|x| Ok((x.net_reproductive_rate()?, x.generation_time()?)))
+    {
+        Ok((net_reproductive_rate, generation_time)) => {
+            write("net_reproductive_rate", "", "", net_reproductive_rate)?;
+            write("generation_time", "", "", generation_time)?;
+            if let Some(time_step) = time_step {
+                let measure = format!("generation_time_{}", time_step.get_unit().get_plural());
+                write(&measure, "", "", time_step.duration(generation_time))?;
+            }
+        }
+        Err(error) => eprintln!(
+            "{}",
+            style::warning(format!(
+                "Warning: R0 and the generation time are not reported: {}",
+                error
+            ))
+        ),
+    }
+    for (name, value) in names.iter().zip(matrix.stable_stage_distribution().iter()) {
+        write("stable_stage_distribution", name, "", *value)?;
+    }
+    for (name, value) in names.iter().zip(matrix.reproductive_values().iter()) {
+        write("reproductive_value", name, "", *value)?;
+    }
+    for (measure, values) in [
+        ("sensitivity", matrix.sensitivities()),
+        ("elasticity", matrix.elasticities()),
+    ] {
+        for (row, stage) in names.iter().enumerate() {
+            for (column, from) in names.iter().enumerate() {
+                write(measure, stage, from, values[(row, column)])?;
+            }
+        }
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_summary_is_reported() {
+        let file = "\"\",\"juvenile\",\"adult\"\n\"juvenile\",0,3\n\"adult\",0.5,0.8\n";
+        let matrix = read_popbio_csv(file.as_bytes()).unwrap();
+        let mut buffer = Vec::new();
+        analyze(&matrix, &[0], None, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        // Header, lambda, R0, generation time, two stable stage and reproductive values, and
+        // four sensitivities and elasticities.
+        assert_eq!(lines.len(), 1 + 3 + 4 + 8);
+        let r0 = lines[2].strip_prefix("net_reproductive_rate,,,").unwrap();
+        assert!((r0.parse::<f64>().unwrap() - 7.5).abs() < 1e-9);
+        assert!(lines[4].starts_with("stable_stage_distribution,juvenile,,"));
+        assert!(lines[12].starts_with("elasticity,juvenile,juvenile,0"));
+        // Fertility in the second row leaves a survival column adding up to more than one.
+        let mut buffer = Vec::new();
+        analyze(&matrix, &[1], None, &mut buffer).unwrap();
+        assert!(!String::from_utf8(buffer)
+            .unwrap()
+            .contains("net_reproductive_rate"));
+        let mut buffer = Vec::new();
+        analyze(&matrix, &[0], "6 months".parse().ok(), &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let generation_time: f64 = text.lines().nth(3).unwrap()[18..].parse().unwrap();
+        let in_months = text.lines().nth(4).unwrap();
+        assert_eq!(
+            in_months,
+            format!("generation_time_months,,,{}", 6.0 * generation_time)
+        );
+        assert_eq!(parse_rows("1, 3"), Ok(vec![0, 2]));
+        assert!(parse_rows("0").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! The `batch` command, which runs every scenario file in a directory and writes the results to a mirrored results directory, with an index of every run.
+use super::{style, Arguments};
+use crate::experiments::parallel_map;
+use crate::imports::read_popbio_csv;
+use crate::outputs::{write_csv, RunDirectory};
+use crate::populations::population_level_simulation::{DeterministicPva, PerturbationSchedule};
+use crate::provenance::RunMetadata;
+use crate::scenarios::Scenario;
+use crate::validation::validate_vector_csv;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str =
+    "Usage: ecolysis_cmd batch DIRECTORY [--output DIRECTORY] [--force] [--steps N] [--parallel]
+                          [--threads N]
+
+Runs every `.scenario` file in DIRECTORY and its subdirectories. Each result is written to the
+output directory at the same relative path as its scenario, with a `.csv` extension and a
+provenance sidecar, and `index.csv` in the output directory lists every run. A scenario that
+fails is recorded in the index and the other scenarios still run. `manifest.csv` lists every
+file written, with its size and SHA-256 hash.
+
+Each batch is written to a new directory under DIRECTORY/results named after the time it
+started, unless --output is given. An output directory that already holds files is only used
+with --force, and then files of the same name are replaced.
+
+Options:
+  --steps N      Steps to project for scenarios that do not set `steps` (default 100)
+  --parallel     Run scenarios on several threads
+  --threads N    Number of threads with --parallel (default: all available)";
+
+/// The extension of the scenario files run by the command.
+const SCENARIO_EXTENSION: &str = "scenario";
+
+/// The outcome of a single scenario in a batch.
+#[derive(Clone, Debug, PartialEq)]
+struct BatchRun {
+    scenario: PathBuf,
+    result: Result<RunSummary, String>,
+}
+
+/// The summary of a scenario that ran.
+#[derive(Clone, Debug, PartialEq)]
+struct RunSummary {
+    output: PathBuf,
+    sidecar: PathBuf,
+    steps: u32,
+    lambda: f64,
+    initial_total: f64,
+    final_total: f64,
+}
+
+/// Run the `batch` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "parallel", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let Some(directory) = arguments.get_positional().first().map(Path::new) else {
+        eprintln!(
+            "{}\n\n{}",
+            style::error("A scenario directory is required."),
+            USAGE
+        );
+        return 2;
+    };
+    let steps = match arguments.get_option("steps").map(str::parse::<u32>) {
+        None => 100,
+        Some(Ok(steps)) => steps,
+        Some(Err(_)) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error("--steps must be a whole number."),
+                USAGE
+            );
+            return 2;
+        }
+    };
+    let threads = match arguments.get_option("threads").map(str::parse::<usize>) {
+        _ if !arguments.has_flag("parallel") => 1,
+        None => 0,
+        Some(Ok(threads)) => threads,
+        Some(Err(_)) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error("--threads must be a whole number."),
+                USAGE
+            );
+            return 2;
+        }
+    };
+    let output = match arguments.get_option("output") {
+        Some(output) => RunDirectory::named(Path::new(output), arguments.has_flag("force")),
+        None => RunDirectory::timestamped(&directory.join("results")),
+    };
+    let mut output = match output {
+        Ok(output) => output,
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            eprintln!(
+                "{}",
+                style::error(format!("{}. Use --force to write into it anyway.", error))
+            );
+            return 1;
+        }
+        Err(error) => {
+            eprintln!(
+                "{}",
+                style::error(format!("Could not create the output directory: {}", error))
+            );
+            return 1;
+        }
+    };
+    match batch(directory, &mut output, steps, threads) {
+        Ok(runs) => {
+            let failed = runs.iter().filter(|run| run.result.is_err()).count();
+            for run in runs.iter().filter(|run| run.result.is_err()) {
+                if let Err(error) = &run.result {
+                    eprintln!(
+                        "{}",
+                        style::error(format!("{}: {}", run.scenario.display(), error))
+                    );
+                }
+            }
+            println!(
+                "Ran {} scenario(s), {} failed. Index written to {}.",
+                runs.len(),
+                failed,
+                output.get_path().join("index.csv").display()
+            );
+            i32::from(failed > 0)
+        }
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Run every scenario under `directory` on `threads` threads (zero uses every available thread),
+/// writing results, the index, and the manifest to `output`.
+fn batch(
+    directory: &Path,
+    output: &mut RunDirectory,
+    default_steps: u32,
+    threads: usize,
+) -> Result<Vec<BatchRun>, Box<dyn Error>> {
+    let mut scenarios = Vec::new();
+    find_scenarios(directory, output.get_path(), &mut scenarios)?;
+    if scenarios.is_empty() {
+        return Err(format!(
+            "No .{} files were found in {}.",
+            SCENARIO_EXTENSION,
+            directory.display()
+        )
+        .into());
+    }
+    scenarios.sort();
+    let results = output.get_path().to_path_buf();
+    let run_one = |scenario: &PathBuf| {
+        let relative = scenario.strip_prefix(directory).unwrap_or(scenario);
+        BatchRun {
+            scenario: relative.to_path_buf(),
+            result: run_scenario(
+                scenario,
+                &results.join(relative).with_extension("csv"),
+                default_steps,
+            )
+            .map_err(|e| e.to_string()),
+        }
+    };
+    let runs = parallel_map(&scenarios, threads, run_one);
+    for summary in runs.iter().filter_map(|run| run.result.as_ref().ok()) {
+        output.add_file(&summary.output);
+        output.add_file(&summary.sidecar);
+    }
+    write_index(&runs, output.create_file(Path::new("index.csv"))?)?;
+    output.write_manifest()?;
+    Ok(runs)
+}
+
+/// Add every scenario file under `directory` to `scenarios`, skipping the output directory.
+fn find_scenarios(
+    directory: &Path,
+    output: &Path,
+    scenarios: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let entries = fs::read_dir(directory)
+        .map_err(|e| format!("Could not read directory {}: {}", directory.display(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path != output {
+                find_scenarios(&path, output, scenarios)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == SCENARIO_EXTENSION)
+        {
+            scenarios.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Run a single scenario as a deterministic projection and write the results, with a provenance
+/// sidecar, to `output`.
+fn run_scenario(
+    path: &Path,
+    output: &Path,
+    default_steps: u32,
+) -> Result<RunSummary, Box<dyn Error>> {
+    let scenario = Scenario::from_file(path)?;
+    if !scenario.get_covariates().is_empty() {
+        return Err("Scenarios with covariates need futures; use the compare command.".into());
+    }
+    let vector_path = scenario
+        .get_initial_population()
+        .ok_or("The scenario does not set an initial population.")?;
+    let open = |path: &Path| {
+        File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))
+    };
+    let (report, initial_population) = validate_vector_csv(open(vector_path)?);
+    let initial_population = initial_population.ok_or_else(|| {
+        let issues: Vec<String> = report.get_issues().iter().map(|x| x.to_string()).collect();
+        format!("{}: {}", vector_path.display(), issues.join("; "))
+    })?;
+    let matrix = read_popbio_csv(open(scenario.get_matrix())?)
+        .map_err(|e| format!("{}: {}", scenario.get_matrix().display(), e))?
+        .into_matrix();
+    let steps = scenario.get_steps().unwrap_or(default_steps);
+    let initial_total = initial_population.total();
+    let lambda = matrix.lambda();
+    let mut projection = if scenario.get_perturbations().is_empty() {
+        DeterministicPva::build(initial_population, matrix)?.deterministic_projection(steps)
+    } else {
+        let mut schedule = PerturbationSchedule::new(matrix);
+        for perturbation in scenario.get_perturbations() {
+            schedule = schedule.with_perturbation(*perturbation)?;
+        }
+        schedule.project(&initial_population, steps)?
+    };
+    if let Some(time_step) = scenario.get_time_step() {
+        projection = projection.with_time_step(time_step);
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_csv(&projection, File::create(output)?)?;
+    let mut metadata = RunMetadata::new();
+    for input in [path, scenario.get_matrix(), vector_path] {
+        metadata.add_input_file(input)?;
+    }
+    metadata.add_parameter("steps", steps);
+    if let Some(time_step) = scenario.get_time_step() {
+        metadata.add_parameter("time_step", time_step);
+    }
+    for perturbation in scenario.get_perturbations() {
+        metadata.add_parameter("perturbation", perturbation);
+    }
+    let sidecar = metadata.write_sidecar(output)?;
+    Ok(RunSummary {
+        output: output.to_path_buf(),
+        sidecar,
+        steps,
+        lambda,
+        initial_total,
+        final_total: projection
+            .iter()
+            .last()
+            .map_or(initial_total, |x| x.total()),
+    })
+}
+
+/// Write the index of a batch: one row per scenario with its status, summary values, and output
+/// file, or the error that stopped it.
+fn write_index<W: std::io::Write>(runs: &[BatchRun], writer: W) -> Result<(), Box<dyn Error>> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record([
+        "scenario",
+        "status",
+        "steps",
+        "lambda",
+        "initial_total",
+        "final_total",
+        "output",
+        "error",
+    ])?;
+    for run in runs {
+        let scenario = run.scenario.display().to_string();
+        match &run.result {
+            Ok(summary) => csv_writer.write_record([
+                scenario,
+                String::from("ok"),
+                summary.steps.to_string(),
+                summary.lambda.to_string(),
+                summary.initial_total.to_string(),
+                summary.final_total.to_string(),
+                summary.output.display().to_string(),
+                String::new(),
+            ])?,
+            Err(error) => csv_writer.write_record([
+                scenario,
+                String::from("failed"),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                error.clone(),
+            ])?,
+        }
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_mirrors_directories_and_indexes_failures() {
+        let directory = std::env::temp_dir().join("ecolysis_batch_command");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("turtles")).unwrap();
+        fs::write(directory.join("m.csv"), "0,2\n0.5,0.8\n").unwrap();
+        fs::write(directory.join("v.csv"), "50,50\n").unwrap();
+        fs::write(
+            directory.join("a.scenario"),
+            "matrix = m.csv\ninitial_population = v.csv\nsteps = 3\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("turtles/b.scenario"),
+            "matrix = ../m.csv\ninitial_population = ../v.csv\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("turtles/broken.scenario"),
+            "matrix = none.csv\n",
+        )
+        .unwrap();
+        let output = directory.join("results");
+        let mut results = RunDirectory::named(&output, false).unwrap();
+        let sequential = batch(&directory, &mut results, 5, 1).unwrap();
+        assert_eq!(sequential.len(), 3);
+        assert_eq!(sequential[0].scenario, Path::new("a.scenario"));
+        assert_eq!(sequential[1].result.as_ref().unwrap().steps, 5);
+        assert!(sequential[2].result.is_err());
+        let projection = fs::read_to_string(output.join("turtles/b.csv")).unwrap();
+        assert_eq!(projection.lines().count(), 6);
+        assert!(output.join("a.csv.metadata.json").exists());
+        let index = fs::read_to_string(output.join("index.csv")).unwrap();
+        assert_eq!(index.lines().count(), 4);
+        assert!(index.lines().nth(3).unwrap().contains(",failed,"));
+        let manifest = fs::read_to_string(output.join("manifest.csv")).unwrap();
+        assert_eq!(manifest.lines().count(), 6);
+        assert!(manifest.contains("\nturtles/b.csv.metadata.json,"));
+        assert!(RunDirectory::named(&output, false).is_err());
+        let mut forced = RunDirectory::named(&output, true).unwrap();
+        assert_eq!(batch(&directory, &mut forced, 5, 3).unwrap(), sequential);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
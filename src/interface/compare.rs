@@ -0,0 +1,189 @@
+//! The `compare` command, which runs a covariate-driven scenario under several covariate futures (for example climate scenarios) and prints a comparison table.
+use super::{create_output, style, Arguments};
+use crate::experiments::ScenarioComparison;
+use crate::imports::read_popbio_csv;
+use crate::populations::population_level_simulation::{
+    CovariateMatrix, CovariateSeries, PopulationMatrix,
+};
+use crate::scenarios::Scenario;
+use crate::validation::validate_vector_csv;
+use std::{error::Error, fs::File, io, path::Path};
+
+const USAGE: &str =
+    "Usage: ecolysis_cmd compare --scenario FILE FUTURE... [--threshold N] [--output FILE]
+                          [--force]
+
+Projects the scenario's model under each FUTURE, a CSV file with a header row of covariate names
+and one row of values per step, and writes a table of growth rates and quasi-extinction per
+future. The scenario must set `initial_population` and at least one `covariate.<name>` effect
+matrix. If the scenario sets `steps`, each future is cut to that many steps. An existing output
+file is only replaced with --force.";
+
+/// Run the `compare` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let Some(scenario) = arguments.get_option("scenario") else {
+        eprintln!(
+            "{}\n\n{}",
+            style::error("A scenario file is required."),
+            USAGE
+        );
+        return 2;
+    };
+    if arguments.get_positional().is_empty() {
+        eprintln!(
+            "{}\n\n{}",
+            style::error("At least one covariate future is required."),
+            USAGE
+        );
+        return 2;
+    }
+    let threshold = match arguments.get_option("threshold").map(str::parse::<f64>) {
+        None => 0.0,
+        Some(Ok(threshold)) => threshold,
+        Some(Err(_)) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error("The threshold must be a number."),
+                USAGE
+            );
+            return 2;
+        }
+    };
+    match compare(
+        Path::new(scenario),
+        arguments.get_positional(),
+        threshold,
+        arguments.get_option("output"),
+        arguments.has_flag("force"),
+    ) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Load the scenario and futures, run the comparison, and write the table to `output` (or
+/// standard output), replacing an existing file only if `force` is true.
+fn compare(
+    scenario_path: &Path,
+    futures: &[String],
+    threshold: f64,
+    output: Option<&str>,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    let scenario = Scenario::from_file(scenario_path)?;
+    if scenario.get_covariates().is_empty() {
+        return Err("The scenario does not set any covariate.<name> effect matrices.".into());
+    }
+    let vector_path = scenario
+        .get_initial_population()
+        .ok_or("The scenario does not set an initial population.")?;
+    let (report, initial_population) = validate_vector_csv(open(vector_path)?);
+    let initial_population = initial_population.ok_or_else(|| {
+        let issues: Vec<String> = report.get_issues().iter().map(|x| x.to_string()).collect();
+        format!("{}: {}", vector_path.display(), issues.join("; "))
+    })?;
+    let mut effects = Vec::new();
+    for (name, path) in scenario.get_covariates() {
+        effects.push((name.clone(), load_matrix(path)?));
+    }
+    let model = CovariateMatrix::build(load_matrix(scenario.get_matrix())?, effects)?;
+    let mut series = Vec::new();
+    for future in futures {
+        let path = Path::new(future);
+        let mut values = CovariateSeries::read_csv(open(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(steps) = scenario.get_steps() {
+            if values.len() < steps as usize {
+                return Err(format!(
+                    "{}: has {} steps, but the scenario needs {}.",
+                    path.display(),
+                    values.len(),
+                    steps
+                )
+                .into());
+            }
+            values = values.truncate(steps as usize);
+        }
+        let name = path
+            .file_stem()
+            .map_or(future.clone(), |x| x.to_string_lossy().to_string());
+        series.push((name, values));
+    }
+    let comparison = ScenarioComparison::run(&model, &initial_population, &series, threshold)?;
+    match output {
+        Some(output) => comparison.write_csv(create_output(output, force)?),
+        None => comparison.write_csv(io::stdout()),
+    }
+}
+
+fn open(path: &Path) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))
+}
+
+fn load_matrix(path: &Path) -> Result<PopulationMatrix, String> {
+    read_popbio_csv(open(path)?)
+        .map(|x| x.into_matrix())
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn compare_writes_one_row_per_future() {
+        let directory = std::env::temp_dir().join("ecolysis_compare_command");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("m.csv"), "0,2\n0.5,0.8\n").unwrap();
+        fs::write(directory.join("t.csv"), "0,-0.5\n0,-0.1\n").unwrap();
+        fs::write(directory.join("v.csv"), "50,50\n").unwrap();
+        fs::write(
+            directory.join("model.txt"),
+            "matrix = m.csv\ninitial_population = v.csv\ncovariate.temperature = t.csv\nsteps = 3\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("mild.csv"),
+            "year,temperature\n1,0\n2,0\n3,0\n4,9\n",
+        )
+        .unwrap();
+        fs::write(directory.join("short.csv"), "temperature\n1\n").unwrap();
+        let output = directory.join("out.csv");
+        let futures = vec![directory.join("mild.csv").display().to_string()];
+        let run = |force| {
+            compare(
+                &directory.join("model.txt"),
+                &futures,
+                1.0,
+                Some(output.to_str().unwrap()),
+                force,
+            )
+        };
+        run(false).unwrap();
+        let table = fs::read_to_string(&output).unwrap();
+        assert_eq!(table.lines().count(), 2);
+        assert!(table.lines().nth(1).unwrap().starts_with("mild,3,"));
+        let error = run(false).unwrap_err().to_string();
+        assert!(error.ends_with("already exists. Use --force to replace it."));
+        run(true).unwrap();
+        let short = vec![directory.join("short.csv").display().to_string()];
+        assert!(compare(&directory.join("model.txt"), &short, 1.0, None, false).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+//! The `demo` command, which analyses one of the example datasets built into the program so new users can see what it does without preparing any input files.
+use super::{style, Arguments};
+use crate::examples::datasets::{self, ExampleDataset};
+use crate::outputs::{write_matrix_csv, write_vector_csv, RunDirectory};
+use crate::DeterministicPva;
+use std::{error::Error, io, path::Path};
+
+const USAGE: &str = "Usage: ecolysis_cmd demo [NAME] [--steps N] [--output DIR] [--force]
+
+Analyses a classic published projection matrix that is built into the program: its growth rate,
+stable stage distribution, reproductive values, the vital rate lambda is most sensitive to, and
+a short projection. Without a name, the available datasets are listed. With --output, the matrix
+and initial population are also written to DIR as matrix.csv and vector.csv, ready for the other
+commands; an existing directory is only written into with --force.
+
+Options:
+  --steps N       Number of steps to project (default 10)
+  --output DIR    Also write matrix.csv and vector.csv to DIR
+  --force         Write into DIR even if it already exists";
+
+/// Run the `demo` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let Some(name) = arguments.get_positional().first() else {
+        println!("{}", style::header("Example datasets:"));
+        for example in datasets::datasets() {
+            println!("  {:<14}{}", example.get_name(), example.get_title());
+        }
+        println!("\nRun `ecolysis_cmd demo NAME` to analyse one.");
+        return 0;
+    };
+    let Some(example) = datasets::dataset(name) else {
+        eprintln!(
+            "{}",
+            style::error(format!(
+                "There is no example dataset named \"{}\". Run `ecolysis_cmd demo` to list them.",
+                name
+            ))
+        );
+        return 2;
+    };
+    let steps = match arguments.get_option("steps").map(str::parse::<u32>) {
+        None => 10,
+        Some(Ok(steps)) => steps,
+        Some(Err(_)) => {
+            eprintln!(
+                "{}\n\n{}",
+                style::error("--steps must be a whole number."),
+                USAGE
+            );
+            return 2;
+        }
+    };
+    print_analysis(example, steps);
+    let Some(output) = arguments.get_option("output") else {
+        println!(
+            "\nAdd `--output DIR` to write the matrix and initial population as CSV files for the other commands."
+        );
+        return 0;
+    };
+    match write_files(example, Path::new(output), arguments.has_flag("force")) {
+        Ok(()) => {
+            println!(
+                "\nWrote matrix.csv and vector.csv to {}. Try `ecolysis_cmd project {} --vector {} --steps 50`.",
+                output,
+                Path::new(output).join("matrix.csv").display(),
+                Path::new(output).join("vector.csv").display()
+            );
+            0
+        }
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Print the description and analysis of an example dataset.
+fn print_analysis(example: &ExampleDataset, steps: u32) {
+    let matrix = example.get_matrix();
+    println!("{}\n", style::header(example.get_title()));
+    println!("{}\n", example.get_description());
+    println!("Source: {}\n", example.get_citation());
+    let lambda = matrix.lambda();
+    println!(
+        "Lambda is {:.4}: the population {} by {:.1}% per step once it settles into its stable stage distribution.\n",
+        lambda,
+        if lambda >= 1.0 { "grows" } else { "shrinks" },
+        (lambda - 1.0).abs() * 100.0
+    );
+    let lifestages = example.get_lifestages();
+    let width = lifestages.iter().map(|x| x.len()).max().unwrap_or(0);
+    println!(
+        "{}",
+        style::header(format!(
+            "{:<width$}  stable distribution  reproductive value",
+            "lifestage",
+            width = width
+        ))
+    );
+    let stable = matrix.stable_stage_distribution();
+    let reproductive = matrix.reproductive_values();
+    for ((name, stable), reproductive) in lifestages
+        .iter()
+        .zip(stable.iter())
+        .zip(reproductive.iter())
+    {
+        println!(
+            "{:<width$}  {:>19.3}  {:>18.3}",
+            name,
+            stable,
+            reproductive,
+            width = width
+        );
+    }
+    let ((row, column), elasticity) = matrix.largest_elasticity();
+    let transition = if row == column {
+        format!("{} staying in their stage", lifestages[row])
+    } else if row == 0 {
+        format!("reproduction by {}", lifestages[column])
+    } else {
+        format!("{} becoming {}", lifestages[column], lifestages[row])
+    };
+    println!(
+        "\nLambda is most sensitive to {} (elasticity {:.3}).\n",
+        transition, elasticity
+    );
+    let pva = DeterministicPva::build(example.get_initial_population(), matrix)
+        .expect("Example initial populations match their matrices.");
+    println!("step,total");
+    println!("0,{:.1}", example.get_initial_population().total());
+    for (step, population) in pva.projection_iter().take(steps as usize).enumerate() {
+        println!("{},{:.1}", step + 1, population.total());
+    }
+}
+
+/// Write the matrix and initial population of an example dataset to a new directory.
+fn write_files(example: &ExampleDataset, path: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+    let mut directory = RunDirectory::named(path, force).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!("{}. Use --force to write into it anyway.", e),
+        _ => format!("Could not create {}: {}", path.display(), e),
+    })?;
+    write_matrix_csv(
+        &example.get_matrix(),
+        directory.create_file(Path::new("matrix.csv"))?,
+    )?;
+    write_vector_csv(
+        &example.get_initial_population(),
+        directory.create_file(Path::new("vector.csv"))?,
+    )?;
+    directory.write_manifest()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imports::read_popbio_csv;
+    use std::fs;
+
+    #[test]
+    fn written_files_can_be_read_back() {
+        let directory = std::env::temp_dir().join("ecolysis_demo_command");
+        let _ = fs::remove_dir_all(&directory);
+        let example = datasets::dataset("teasel").unwrap();
+        write_files(example, &directory, false).unwrap();
+        let matrix = read_popbio_csv(fs::File::open(directory.join("matrix.csv")).unwrap())
+            .unwrap()
+            .into_matrix();
+        assert_eq!(matrix, example.get_matrix());
+        assert!(write_files(example, &directory, false).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
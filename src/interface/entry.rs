@@ -0,0 +1,191 @@
+//! Typing tables of numbers (matrix rows and population vectors) at the interactive prompt. After the rows are typed, the values are shown for review so individual cells or rows can be corrected before they are used.
+use super::{style, Step};
+
+const REVIEW_HELP: &str = "Type `ok` to use these values, `set ROW COLUMN VALUE` to change a cell,
+`row ROW` to type a row again, or `cancel` to discard them.";
+
+/// Parse a row of numbers separated by commas and/or spaces.
+/// # Errors
+/// Returns a message if a value is not a number or there are not `columns` values.
+fn parse_row(input: &str, columns: usize) -> Result<Vec<f64>, String> {
+    let row = input
+        .split(|x: char| x == ',' || x.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<f64>()
+                .map_err(|_| format!("\"{}\" is not a number.", x))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if row.len() != columns {
+        return Err(format!(
+            "Expected {} values but found {}.",
+            columns,
+            row.len()
+        ));
+    }
+    Ok(row)
+}
+
+/// Format a table with one-based row and column numbers, as shown for review.
+fn format_table(table: &[Vec<f64>]) -> String {
+    let columns = table.first().map_or(0, Vec::len);
+    let mut lines = vec![format!(
+        "     {}",
+        (1..=columns)
+            .map(|column| format!("{:>10}", column))
+            .collect::<String>()
+    )];
+    for (index, row) in table.iter().enumerate() {
+        lines.push(format!(
+            "{:>4} {}",
+            index + 1,
+            row.iter().map(|x| format!("{:>10}", x)).collect::<String>()
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Prompt for one row until it is typed correctly, returning `Step::Cancel` if input ends.
+fn read_row<F: FnMut(&str) -> Option<String>>(
+    row: usize,
+    columns: usize,
+    input: &mut F,
+) -> Step<Vec<f64>> {
+    loop {
+        let Some(line) = input(&format!("row {}> ", row + 1)) else {
+            return Step::Cancel;
+        };
+        match parse_row(&line, columns) {
+            Ok(values) => return Step::Continue(values),
+            Err(error) => eprintln!(
+                "{}",
+                style::error(format!("{} Please type row {} again.", error, row + 1))
+            ),
+        }
+    }
+}
+
+/// Ask for a table of `rows` rows of `columns` numbers, one row per line, using `input` to show a
+/// prompt and read a line (returning `None` if input ends). The values are then shown for review
+/// and can be changed until the user confirms them. `check` is run on confirmation, and any
+/// error it returns is shown and the review continues, so a table that cannot be used is never
+/// returned.
+///
+/// Returns `Step::Cancel` if the user cancels or input ends.
+pub fn enter_table<F, C>(rows: usize, columns: usize, mut input: F, check: C) -> Step<Vec<Vec<f64>>>
+where
+    F: FnMut(&str) -> Option<String>,
+    C: Fn(&[Vec<f64>]) -> Result<(), String>,
+{
+    println!(
+        "Type {} row(s) of {} value(s), separated by commas or spaces.",
+        rows, columns
+    );
+    let mut table = Vec::with_capacity(rows);
+    for row in 0..rows {
+        match read_row(row, columns, &mut input) {
+            Step::Continue(values) => table.push(values),
+            Step::Cancel => return Step::Cancel,
+        }
+    }
+    loop {
+        println!("{}\n{}", format_table(&table), REVIEW_HELP);
+        let Some(line) = input("review> ") else {
+            return Step::Cancel;
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let index = |word: &str, count: usize| {
+            word.parse::<usize>()
+                .ok()
+                .filter(|x| (1..=count).contains(x))
+                .map(|x| x - 1)
+        };
+        match words.as_slice() {
+            ["ok" | "y" | "yes"] => match check(&table) {
+                Ok(()) => return Step::Continue(table),
+                Err(error) => eprintln!("{}", style::error(error)),
+            },
+            ["cancel"] => return Step::Cancel,
+            ["set", row, column, value] => {
+                match (index(row, rows), index(column, columns), value.parse()) {
+                    (Some(row), Some(column), Ok(value)) => table[row][column] = value,
+                    _ => eprintln!(
+                        "{}",
+                        style::error(format!(
+                            "Use `set ROW COLUMN VALUE` with a row from 1 to {} and a column from 1 to {}.",
+                            rows, columns
+                        ))
+                    ),
+                }
+            }
+            ["row", row] => match index(row, rows) {
+                Some(row) => match read_row(row, columns, &mut input) {
+                    Step::Continue(values) => table[row] = values,
+                    Step::Cancel => return Step::Cancel,
+                },
+                None => eprintln!(
+                    "{}",
+                    style::error(format!("Choose a row from 1 to {}.", rows))
+                ),
+            },
+            _ => eprintln!("{}", style::error("That was not understood.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripted(lines: &[&str]) -> impl FnMut(&str) -> Option<String> {
+        let mut lines: Vec<String> = lines.iter().rev().map(|x| x.to_string()).collect();
+        move |_| lines.pop()
+    }
+    fn accept(_: &[Vec<f64>]) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rows() {
+        assert_eq!(parse_row("0, 1.5  2", 3), Ok(vec![0.0, 1.5, 2.0]));
+        assert!(parse_row("0, x, 2", 3).is_err());
+        assert!(parse_row("0, 1", 3).is_err());
+    }
+    #[test]
+    fn review_corrects_cells_and_rows() {
+        let input = scripted(&[
+            "0 2",
+            "0.5 bad",
+            "0.5 0.8",
+            "set 1 2 3",
+            "set 3 1 9",
+            "row 2",
+            "0.4 0.7",
+            "ok",
+        ]);
+        assert!(matches!(
+            enter_table(2, 2, input, accept),
+            Step::Continue(x) if x == vec![vec![0.0, 3.0], vec![0.4, 0.7]]
+        ));
+        assert!(matches!(
+            enter_table(1, 2, scripted(&["1 2", "cancel"]), accept),
+            Step::Cancel
+        ));
+        assert!(matches!(
+            enter_table(1, 2, scripted(&["1 2"]), accept),
+            Step::Cancel
+        ));
+    }
+    #[test]
+    fn failed_check_keeps_reviewing() {
+        let check = |table: &[Vec<f64>]| match table[0][0] < 0.0 {
+            true => Err(String::from("negative")),
+            false => Ok(()),
+        };
+        let input = scripted(&["-1 2", "ok", "set 1 1 1", "ok"]);
+        assert!(matches!(
+            enter_table(1, 2, input, check),
+            Step::Continue(x) if x == vec![vec![1.0, 2.0]]
+        ));
+    }
+}
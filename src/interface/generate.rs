@@ -0,0 +1,179 @@
+//! The `generate` command, which writes random but biologically plausible projection matrices for classroom exercises and test fixtures.
+use super::{create_output, style, Arguments};
+use crate::outputs::{write_matrix_csv, RunDirectory};
+use crate::populations::population_level_simulation::{MatrixShape, RandomMatrixGenerator};
+use std::{error::Error, io, path::Path};
+
+const USAGE: &str = "Usage: ecolysis_cmd generate --stages N [--shape leslie|lefkovitch]
+                          [--survival MIN,MAX] [--fecundity MIN,MAX] [--lambda X]
+                          [--count N] [--seed N] [--output PATH] [--force]
+
+Generates random projection matrices. Survival out of each lifestage and the fecundity of every
+lifestage but the first are drawn from the given ranges. One matrix is written as CSV to standard
+output, or to the file given by --output. With --count above 1, --output names a directory, where
+matrix_1.csv, matrix_2.csv, etc. and a manifest are written. Existing results are only replaced
+with --force.
+
+Options:
+  --stages N           Number of lifestages (at least 2)
+  --shape SHAPE        lefkovitch (stage classes, the default) or leslie (age classes)
+  --survival MIN,MAX   Range of the survival out of each lifestage (default 0.2,0.9)
+  --fecundity MIN,MAX  Range of the fecundity of each reproductive lifestage (default 0.5,5)
+  --lambda X           Scale fecundity so that the population growth rate is X
+  --count N            Number of matrices to generate (default 1)
+  --seed N             Random seed (default 0)
+  --output PATH        File (or, with --count above 1, directory) to write the matrices to
+  --force              Replace existing results";
+
+/// Run the `generate` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let settings = (|| -> Result<_, String> {
+        let number = |name: &str, default: u64| match arguments.get_option(name) {
+            None => Ok(default),
+            Some(value) => value
+                .parse::<u64>()
+                .map_err(|_| format!("--{} must be a whole number.", name)),
+        };
+        let stages = arguments
+            .get_option("stages")
+            .ok_or("The number of lifestages is required.")?
+            .parse::<u8>()
+            .map_err(|_| "--stages must be a whole number up to 255.")?;
+        let mut generator = RandomMatrixGenerator::new(stages)?.with_shape(
+            match arguments.get_option("shape").unwrap_or("lefkovitch") {
+                "leslie" => MatrixShape::Leslie,
+                "lefkovitch" => MatrixShape::Lefkovitch,
+                shape => return Err(format!("Unknown shape \"{}\".", shape)),
+            },
+        );
+        if let Some(range) = arguments.get_option("survival") {
+            let (lower, upper) = parse_range("survival", range)?;
+            generator = generator.with_survival_range(lower, upper)?;
+        }
+        if let Some(range) = arguments.get_option("fecundity") {
+            let (lower, upper) = parse_range("fecundity", range)?;
+            generator = generator.with_fecundity_range(lower, upper)?;
+        }
+        if let Some(lambda) = arguments.get_option("lambda") {
+            let lambda = lambda
+                .parse::<f64>()
+                .map_err(|_| "--lambda must be a number.")?;
+            generator = generator.with_target_lambda(lambda)?;
+        }
+        let count = u32::try_from(number("count", 1)?).map_err(|_| "--count is too large.")?;
+        if count == 0 {
+            return Err(String::from("--count must be at least 1."));
+        }
+        if count > 1 && arguments.get_option("output").is_none() {
+            return Err(String::from(
+                "--output must name a directory when --count is above 1.",
+            ));
+        }
+        Ok((generator, count, number("seed", 0)?))
+    })();
+    let (generator, count, seed) = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    match generate(
+        &generator,
+        count,
+        seed,
+        arguments.get_option("output"),
+        arguments.has_flag("force"),
+    ) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Parse a range written as `MIN,MAX`.
+fn parse_range(name: &str, range: &str) -> Result<(f64, f64), String> {
+    range
+        .split_once(',')
+        .and_then(|(lower, upper)| Some((lower.trim().parse().ok()?, upper.trim().parse().ok()?)))
+        .ok_or_else(|| format!("--{} must be two numbers separated by a comma.", name))
+}
+
+/// Generate `count` matrices and write them to `output`: a file (or standard output) for one
+/// matrix, or a run directory for several.
+fn generate(
+    generator: &RandomMatrixGenerator,
+    count: u32,
+    seed: u64,
+    output: Option<&str>,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    let matrices = generator.generate_many(count, seed)?;
+    match output {
+        None => write_matrix_csv(&matrices[0], io::stdout().lock()),
+        Some(path) if count == 1 => write_matrix_csv(&matrices[0], create_output(path, force)?),
+        Some(path) => {
+            let mut directory =
+                RunDirectory::named(Path::new(path), force).map_err(|e| match e.kind() {
+                    io::ErrorKind::AlreadyExists => {
+                        format!("{}. Use --force to write into it anyway.", e)
+                    }
+                    _ => format!("Could not create {}: {}", path, e),
+                })?;
+            for (index, matrix) in matrices.iter().enumerate() {
+                let name = format!("matrix_{}.csv", index + 1);
+                write_matrix_csv(matrix, directory.create_file(Path::new(&name))?)?;
+            }
+            directory.write_manifest()?;
+            println!("Wrote {} matrices to {}.", count, path);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imports::read_popbio_csv;
+    use std::fs;
+
+    #[test]
+    fn ranges_are_parsed() {
+        assert_eq!(parse_range("survival", "0.2, 0.9"), Ok((0.2, 0.9)));
+        assert!(parse_range("survival", "0.2").is_err());
+        assert!(parse_range("survival", "low,high").is_err());
+    }
+
+    #[test]
+    fn several_matrices_are_written_to_a_directory() {
+        let directory = std::env::temp_dir().join("ecolysis_generate_command");
+        let _ = fs::remove_dir_all(&directory);
+        let generator = RandomMatrixGenerator::new(3)
+            .unwrap()
+            .with_target_lambda(1.1)
+            .unwrap();
+        let path = directory.to_str().unwrap();
+        generate(&generator, 3, 5, Some(path), false).unwrap();
+        let matrix = read_popbio_csv(fs::File::open(directory.join("matrix_3.csv")).unwrap())
+            .unwrap()
+            .into_matrix();
+        assert!((matrix.lambda() - 1.1).abs() < 1e-9);
+        assert!(directory.join("manifest.csv").exists());
+        assert!(generate(&generator, 3, 5, Some(path), false).is_err());
+        generate(&generator, 3, 6, Some(path), true).unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
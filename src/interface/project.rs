@@ -0,0 +1,419 @@
+//! The `project` command, which runs a projection without the interactive prompt. Inputs can be read from standard input and results are written to standard output, so the command can be used in shell pipelines and driven by other programs.
+use super::{create_output, style, Arguments};
+use crate::imports::read_popbio_csv;
+#[cfg(feature = "xlsx")]
+use crate::imports::{read_workbook_matrix, read_workbook_vector, SheetSelection};
+#[cfg(feature = "json")]
+use crate::outputs::write_json;
+use crate::outputs::{write_csv, ToOutputTable};
+use crate::populations::population_level_simulation::{
+    DeterministicPva, ObservationModel, PopulationMatrix, PopulationVector, ProjectionConfig,
+    ProjectionRecord, StochasticPva, Summation, TimeStep, Verbosity,
+};
+use crate::validation::validate_vector_csv;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+const USAGE: &str =
+    "Usage: ecolysis_cmd project MATRIX... --vector FILE [--steps N] [--replicates N]
+                          [--seed N] [--time-step STEP] [--observation ERROR]
+                          [--detail LEVEL] [--summation METHOD] [--format csv|json]
+                          [--output FILE] [--force]
+
+Projects the initial population vector with one matrix (deterministic) or several matrices (a
+stochastic projection drawing one matrix per step). Use `-` for one of the input files to read it
+from standard input. Results are written to standard output unless --output is given; an
+existing output file is only replaced with --force.
+
+With the xlsx feature, inputs can be cells of a workbook (.xlsx, .xlsm, .xls, or .ods), written
+as the file followed by `#` and a sheet and range, as in `rates.xlsx#Adults!B2:H8`.
+
+Options:
+  --vector FILE     The initial population vector (required)
+  --steps N         Number of steps to project (default 10)
+  --replicates N    Number of stochastic replicates (default 100)
+  --seed N          Random seed for stochastic projections and observation error (default 0)
+  --time-step STEP  Length of time one step represents, such as `1 year` or `6 months`;
+                    adds an elapsed time column named after the unit
+  --observation ERROR
+                    Write simulated survey counts instead of true abundances, with
+                    `poisson`, `lognormal SIGMA`, or `binomial DETECTION` error
+  --detail LEVEL    Keep only part of the projection: `final` (the last step of each
+                    replicate), `totals` (the total population at each step),
+                    `trajectories`, or `diagnostics` (trajectories with growth rates, the
+                    distance from the stable stage distribution, and the matrix used)
+  --summation METHOD
+                    `plain` (default) or `compensated`, which adds up each projected value
+                    with compensated summation to limit round-off in long projections
+  --format FORMAT   csv (default) or json (requires the json feature)
+  --output FILE     Write the results to FILE instead of standard output
+  --force           Replace FILE if it already exists";
+
+/// The formats results can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Csv,
+    #[cfg(feature = "json")]
+    Json,
+}
+impl Format {
+    fn parse(input: &str) -> Result<Format, String> {
+        match input {
+            "csv" => Ok(Format::Csv),
+            #[cfg(feature = "json")]
+            "json" => Ok(Format::Json),
+            #[cfg(not(feature = "json"))]
+            "json" => Err(String::from(
+                "JSON output requires building with the json feature.",
+            )),
+            _ => Err(format!("Unknown format \"{}\".", input)),
+        }
+    }
+    fn write<T: ToOutputTable, W: Write>(
+        self,
+        output: &T,
+        writer: W,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            Format::Csv => write_csv(output, writer),
+            #[cfg(feature = "json")]
+            Format::Json => write_json(output, writer),
+        }
+    }
+}
+
+/// The settings of a projection, read from the options.
+#[derive(Clone, Copy, Debug)]
+struct Settings {
+    steps: u32,
+    replicates: u32,
+    seed: u64,
+    time_step: Option<TimeStep>,
+    observation: Option<ObservationModel>,
+    detail: Option<Verbosity>,
+    summation: Summation,
+    format: Format,
+}
+
+impl Settings {
+    /// Return the projection configuration of a run that keeps `detail`.
+    fn config(&self, detail: Verbosity) -> ProjectionConfig {
+        ProjectionConfig::new()
+            .steps(self.steps)
+            .replicates(self.replicates)
+            .seed(self.seed)
+            .verbosity(detail)
+    }
+}
+
+/// Run the `project` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help", "force"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let settings = (|| -> Result<_, String> {
+        let number = |name: &str, default: u64| match arguments.get_option(name) {
+            None => Ok(default),
+            Some(value) => value
+                .parse::<u64>()
+                .map_err(|_| format!("--{} must be a whole number.", name)),
+        };
+        if arguments.get_positional().is_empty() {
+            return Err(String::from("At least one matrix is required."));
+        }
+        let vector = arguments
+            .get_option("vector")
+            .ok_or("An initial population vector is required.")?;
+        let inputs: Vec<&str> = arguments
+            .get_positional()
+            .iter()
+            .map(String::as_str)
+            .chain([vector])
+            .collect();
+        if inputs.iter().filter(|x| **x == "-").count() > 1 {
+            return Err(String::from(
+                "Only one input can be read from standard input.",
+            ));
+        }
+        if arguments.get_option("detail").is_some() && arguments.get_option("observation").is_some()
+        {
+            return Err(String::from(
+                "--detail cannot be used with --observation, which always writes every step.",
+            ));
+        }
+        Ok(Settings {
+            steps: u32::try_from(number("steps", 10)?).map_err(|_| "--steps is too large.")?,
+            replicates: u32::try_from(number("replicates", 100)?)
+                .map_err(|_| "--replicates is too large.")?,
+            seed: number("seed", 0)?,
+            time_step: arguments
+                .get_option("time-step")
+                .map(|x| x.parse().map_err(|e| format!("--time-step: {}", e)))
+                .transpose()?,
+            observation: arguments
+                .get_option("observation")
+                .map(|x| x.parse().map_err(|e| format!("--observation: {}", e)))
+                .transpose()?,
+            detail: arguments
+                .get_option("detail")
+                .map(|x| x.parse().map_err(|e| format!("--detail: {}", e)))
+                .transpose()?,
+            summation: arguments
+                .get_option("summation")
+                .map(|x| x.parse().map_err(|e| format!("--summation: {}", e)))
+                .transpose()?
+                .unwrap_or_default(),
+            format: Format::parse(arguments.get_option("format").unwrap_or("csv"))?,
+        })
+    })();
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let mut matrices = Vec::new();
+        for name in arguments.get_positional() {
+            matrices.push(load_matrix(name)?);
+        }
+        let vector = load_vector(arguments.get_option("vector").unwrap_or("-"))?;
+        let writer: Box<dyn Write> = match arguments.get_option("output") {
+            Some(path) => Box::new(create_output(path, arguments.has_flag("force"))?),
+            None => Box::new(io::stdout().lock()),
+        };
+        project(matrices, vector, &settings, writer)
+    })();
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", style::error(error));
+            1
+        }
+    }
+}
+
+/// Run a deterministic projection for a single matrix or a stochastic projection for several,
+/// and write the results (or simulated survey counts of them, or only the requested detail) to
+/// `writer`.
+fn project<W: Write>(
+    matrices: Vec<PopulationMatrix>,
+    initial_population: PopulationVector,
+    settings: &Settings,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    if matrices.len() == 1 {
+        let mut model =
+            DeterministicPva::build(initial_population, matrices.into_iter().next().unwrap())?
+                .with_summation(settings.summation);
+        if let Some(time_step) = settings.time_step {
+            model = model.with_time_step(time_step);
+        }
+        if let Some(detail) = settings.detail {
+            return write_record(model.record(&settings.config(detail)), settings, writer);
+        }
+        let output = model.checked_projection(settings.steps)?;
+        match settings.observation {
+            Some(observation) => settings.format.write(
+                &observation.observe_deterministic(&output, settings.seed),
+                writer,
+            ),
+            None => settings.format.write(&output, writer),
+        }
+    } else {
+        let mut model =
+            StochasticPva::build(initial_population, matrices)?.with_summation(settings.summation);
+        if let Some(time_step) = settings.time_step {
+            model = model.with_time_step(time_step);
+        }
+        if let Some(detail) = settings.detail {
+            return write_record(model.record(&settings.config(detail)), settings, writer);
+        }
+        let output =
+            model.checked_projection(settings.steps, settings.replicates, settings.seed)?;
+        match settings.observation {
+            Some(observation) => settings.format.write(
+                &observation.observe_stochastic(&output, settings.seed),
+                writer,
+            ),
+            None => settings.format.write(&output, writer),
+        }
+    }
+}
+
+/// Write a record of the requested detail, or return the first invalid value it reached.
+fn write_record<W: Write>(
+    record: ProjectionRecord,
+    settings: &Settings,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(fault) = record.get_faults().first() {
+        return Err(Box::new(*fault));
+    }
+    settings.format.write(&record, writer)
+}
+
+/// Open a named input file, or standard input if the name is `-`.
+fn open(name: &str) -> Result<Box<dyn Read>, String> {
+    if name == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    File::open(name)
+        .map(|file| Box::new(file) as Box<dyn Read>)
+        .map_err(|e| format!("Could not open {}: {}", name, e))
+}
+
+/// The extensions of spreadsheet workbooks, which are read with the xlsx feature.
+const WORKBOOK_EXTENSIONS: [&str; 4] = ["xlsx", "xlsm", "xls", "ods"];
+
+/// If `name` is a workbook, optionally followed by `#` and the cells to read
+/// (`rates.xlsx#Adults!B2:H8`), return the file name and the cells.
+fn split_workbook(name: &str) -> Option<(&str, &str)> {
+    let (path, cells) = name.rsplit_once('#').unwrap_or((name, ""));
+    Path::new(path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| WORKBOOK_EXTENSIONS.contains(&x.to_lowercase().as_str()))
+        .then_some((path, cells))
+}
+
+/// Open a workbook input and parse the cells to read from it, or return `None` if `name` is
+/// not a workbook.
+#[cfg(feature = "xlsx")]
+fn open_workbook(name: &str) -> Result<Option<(File, SheetSelection)>, String> {
+    let Some((path, cells)) = split_workbook(name) else {
+        return Ok(None);
+    };
+    let selection = SheetSelection::parse(cells).map_err(|e| format!("{}: {}", name, e))?;
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+    Ok(Some((file, selection)))
+}
+
+/// Return an error if `name` is a workbook, which cannot be read without the xlsx feature.
+#[cfg(not(feature = "xlsx"))]
+fn reject_workbook(name: &str) -> Result<(), String> {
+    match split_workbook(name) {
+        Some(_) => Err(format!(
+            "{}: reading workbooks requires building with the xlsx feature.",
+            name
+        )),
+        None => Ok(()),
+    }
+}
+
+fn load_matrix(name: &str) -> Result<PopulationMatrix, String> {
+    #[cfg(feature = "xlsx")]
+    if let Some((file, selection)) = open_workbook(name)? {
+        return read_workbook_matrix(file, &selection)
+            .map(|x| x.into_matrix())
+            .map_err(|e| format!("{}: {}", name, e));
+    }
+    #[cfg(not(feature = "xlsx"))]
+    reject_workbook(name)?;
+    read_popbio_csv(open(name)?)
+        .map(|x| x.into_matrix())
+        .map_err(|e| format!("{}: {}", name, e))
+}
+
+fn load_vector(name: &str) -> Result<PopulationVector, String> {
+    #[cfg(feature = "xlsx")]
+    if let Some((file, selection)) = open_workbook(name)? {
+        return read_workbook_vector(file, &selection).map_err(|e| format!("{}: {}", name, e));
+    }
+    #[cfg(not(feature = "xlsx"))]
+    reject_workbook(name)?;
+    let (report, vector) = validate_vector_csv(open(name)?);
+    vector.ok_or_else(|| {
+        let issues: Vec<String> = report.get_issues().iter().map(|x| x.to_string()).collect();
+        format!("{}: {}", name, issues.join("; "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_matrix_is_deterministic_and_several_are_stochastic() {
+        let matrix = || PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap();
+        let vector = || PopulationVector::new(vec![10.0, 10.0]);
+        let mut settings = Settings {
+            steps: 2,
+            replicates: 5,
+            seed: 0,
+            time_step: None,
+            observation: None,
+            detail: None,
+            summation: Summation::Compensated,
+            format: Format::Csv,
+        };
+        let mut buffer = Vec::new();
+        project(vec![matrix()], vector(), &settings, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "step,stage_0,stage_1\n1,10,10\n2,10,10\n"
+        );
+        let mut buffer = Vec::new();
+        project(vec![matrix(), matrix()], vector(), &settings, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap().lines().count(), 11);
+        assert!(Format::parse("xml").is_err());
+        let mismatched = PopulationVector::new(vec![10.0]);
+        assert!(project(vec![matrix()], mismatched, &settings, io::sink()).is_err());
+        let exploding = PopulationMatrix::build(vec![vec![0.0, 1e300], vec![1e300, 0.0]]).unwrap();
+        let error = project(vec![exploding], vector(), &settings, io::sink()).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("lifestage 0 became inf from element (0, 1) of matrix 0."));
+        settings.time_step = Some("3 months".parse().unwrap());
+        let mut buffer = Vec::new();
+        project(vec![matrix()], vector(), &settings, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "step,month,stage_0,stage_1\n1,3,10,10\n2,6,10,10\n"
+        );
+        settings.observation = Some("binomial 0".parse().unwrap());
+        let mut buffer = Vec::new();
+        project(vec![matrix()], vector(), &settings, &mut buffer).unwrap();
+        assert!(String::from_utf8(buffer).unwrap().ends_with("\n2,6,0,0\n"));
+        settings.observation = None;
+        settings.detail = Some(Verbosity::Final);
+        let mut buffer = Vec::new();
+        project(vec![matrix(), matrix()], vector(), &settings, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.starts_with("replicate,step,month,stage_0,stage_1\n0,2,6,10,10\n"));
+        assert_eq!(text.lines().count(), 6);
+        let exploding = PopulationMatrix::build(vec![vec![0.0, 1e300], vec![1e300, 0.0]]).unwrap();
+        let error = project(vec![exploding], vector(), &settings, io::sink()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Step 2: lifestage 0 became inf"));
+    }
+
+    #[test]
+    fn workbook_inputs() {
+        assert_eq!(
+            split_workbook("rates.XLSX#Adults!B2:H8"),
+            Some(("rates.XLSX", "Adults!B2:H8"))
+        );
+        assert_eq!(split_workbook("rates.ods"), Some(("rates.ods", "")));
+        assert_eq!(split_workbook("rates#1.csv"), None);
+        assert_eq!(split_workbook("-"), None);
+        let missing = load_matrix("missing.xlsx#Adults!B2:H8").unwrap_err();
+        assert!(
+            missing.starts_with("Could not open missing.xlsx") || missing.contains("xlsx feature")
+        );
+    }
+}
@@ -0,0 +1,554 @@
+//! The interactive interface: a small command prompt with history and tab completion, shown when the program is run without a command. Each line is a command such as `load matrix m.csv`, `project 50`, `show lambda`, or `save out.csv`; type `help` for the full list.
+use super::{create_output, entry::enter_table, style, Step};
+use crate::examples::datasets;
+use crate::imports::read_popbio_csv;
+use crate::outputs::write_csv;
+#[cfg(feature = "json")]
+use crate::outputs::write_json;
+use crate::populations::population_level_simulation::{
+    DeterministicPva, PopulationMatrix, PopulationVector, PvaDeterministicOutput,
+};
+use crate::validation::validate_vector_csv;
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+mod browser;
+mod tutorial;
+
+/// The commands understood at the prompt.
+const COMMANDS: [&str; 8] = [
+    "load", "enter", "project", "show", "save", "tutorial", "help", "quit",
+];
+/// The kinds of data that can be loaded.
+const LOAD_TARGETS: [&str; 3] = ["matrix", "vector", "example"];
+/// The kinds of data that can be typed in.
+const ENTER_TARGETS: [&str; 2] = ["matrix", "vector"];
+/// The values that can be shown.
+const SHOW_TARGETS: [&str; 6] = [
+    "matrix",
+    "vector",
+    "lambda",
+    "stable",
+    "elasticities",
+    "output",
+];
+
+const HELP: &str = "Commands:
+  load matrix FILE     Load a projection matrix from a CSV file
+  load vector FILE     Load an initial population vector from a CSV file
+  load example NAME    Load the matrix and initial population of a built-in example
+                       (loggerhead, teasel, or killer_whale)
+  enter matrix SIZE    Type a SIZE x SIZE projection matrix, row by row
+  enter vector SIZE    Type an initial population vector of SIZE lifestages
+  project STEPS        Project the initial population with the matrix
+  show WHAT            Show the matrix, vector, lambda, stable (stage distribution),
+                       elasticities, or output (of the last projection)
+  show output STEP     Show the last projection from a step onwards, a page at a time:
+                       press Enter for the next page, type a step number to jump to it,
+                       or q to stop
+  save FILE            Save the last projection as CSV (or JSON, for files ending in .json),
+                       asking before an existing file is replaced
+  tutorial             Walk through a complete analysis of an example, step by step
+  help                 Show this message
+  quit                 Leave the program
+Press tab to complete commands and file names.";
+
+/// This struct holds the data loaded and produced during an interactive session.
+#[derive(Default)]
+pub struct Session {
+    matrix: Option<PopulationMatrix>,
+    vector: Option<PopulationVector>,
+    output: Option<PvaDeterministicOutput>,
+    /// The lifestage names of the loaded matrix, if it came from a file or an example.
+    stage_names: Vec<String>,
+}
+impl Session {
+    /// Run a single command line, returning the text to print, or `Step::Cancel` if the session
+    /// should end. Commands that ask for more input (such as `enter matrix 3`) call `input` with
+    /// a prompt to read each line, which returns `None` if input ends.
+    /// # Errors
+    /// Returns a message describing the problem if the command is not understood or fails.
+    pub fn execute<F: FnMut(&str) -> Option<String>>(
+        &mut self,
+        line: &str,
+        mut input: F,
+    ) -> Result<Step<String>, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(Step::Continue(String::new())),
+            ["quit" | "exit"] => Ok(Step::Cancel),
+            ["help"] => Ok(Step::Continue(HELP.to_string())),
+            ["tutorial"] => Ok(tutorial::run(self, &mut input)),
+            ["load", "matrix", path] => {
+                let labelled =
+                    read_popbio_csv(open(path)?).map_err(|e| format!("{}: {}", path, e))?;
+                self.stage_names = labelled.get_stage_names().clone();
+                let matrix = labelled.into_matrix();
+                let message = format!(
+                    "Loaded a {0}x{0} matrix with lambda {1:.4}.",
+                    matrix.get_lifestage_count(),
+                    matrix.lambda()
+                );
+                self.matrix = Some(matrix);
+                Ok(Step::Continue(message))
+            }
+            ["load", "example", name] => {
+                let example = datasets::dataset(name).ok_or_else(|| {
+                    let names: Vec<&str> =
+                        datasets::datasets().iter().map(|x| x.get_name()).collect();
+                    format!(
+                        "There is no example named \"{}\". Choose one of: {}.",
+                        name,
+                        names.join(", ")
+                    )
+                })?;
+                let matrix = example.get_matrix();
+                let vector = example.get_initial_population();
+                let message = format!(
+                    "Loaded the {} example: a {}x{} matrix with lambda {:.4}, and a population of {}.",
+                    example.get_title(),
+                    matrix.get_lifestage_count(),
+                    matrix.get_lifestage_count(),
+                    matrix.lambda(),
+                    vector.total()
+                );
+                self.matrix = Some(matrix);
+                self.vector = Some(vector);
+                self.stage_names = example
+                    .get_lifestages()
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect();
+                Ok(Step::Continue(message))
+            }
+            ["load", "vector", path] => {
+                let (report, vector) = validate_vector_csv(open(path)?);
+                let vector = vector.ok_or_else(|| {
+                    let issues: Vec<String> =
+                        report.get_issues().iter().map(|x| x.to_string()).collect();
+                    format!("{}: {}", path, issues.join("; "))
+                })?;
+                let message = format!(
+                    "Loaded a population of {} across {} lifestages.",
+                    vector.total(),
+                    vector.get_lifestage_count()
+                );
+                self.vector = Some(vector);
+                Ok(Step::Continue(message))
+            }
+            ["enter", target @ ("matrix" | "vector"), size] => {
+                let size: usize = size
+                    .parse()
+                    .ok()
+                    .filter(|x| (1..=u8::MAX as usize).contains(x))
+                    .ok_or("The size must be a whole number of lifestages from 1 to 255.")?;
+                if *target == "matrix" {
+                    let check = |rows: &[Vec<f64>]| {
+                        PopulationMatrix::build(rows.to_vec())
+                            .map(|_| ())
+                            .map_err(String::from)
+                    };
+                    let Step::Continue(rows) = enter_table(size, size, input, check) else {
+                        return Ok(Step::Continue(String::from("The matrix was discarded.")));
+                    };
+                    let matrix = PopulationMatrix::build(rows)?;
+                    let message = format!("Entered a matrix with lambda {:.4}.", matrix.lambda());
+                    self.matrix = Some(matrix);
+                    self.stage_names.clear();
+                    Ok(Step::Continue(message))
+                } else {
+                    let check = |rows: &[Vec<f64>]| {
+                        PopulationVector::build(rows.concat())
+                            .map(|_| ())
+                            .map_err(String::from)
+                    };
+                    let Step::Continue(rows) = enter_table(1, size, input, check) else {
+                        return Ok(Step::Continue(String::from("The vector was discarded.")));
+                    };
+                    let vector = PopulationVector::build(rows.concat())?;
+                    let message = format!("Entered a population of {}.", vector.total());
+                    self.vector = Some(vector);
+                    Ok(Step::Continue(message))
+                }
+            }
+            ["project", steps] => {
+                let steps: u32 = steps
+                    .parse()
+                    .map_err(|_| String::from("The number of steps must be a whole number."))?;
+                let model = DeterministicPva::build(
+                    self.vector.clone().ok_or("Load a vector first.")?,
+                    self.matrix.clone().ok_or("Load a matrix first.")?,
+                )?;
+                let output = model.deterministic_projection(steps);
+                let message = format!(
+                    "Projected {} steps. Final population: {}.",
+                    steps,
+                    output.iter().last().map_or(0.0, |x| x.total())
+                );
+                self.output = Some(output);
+                Ok(Step::Continue(message))
+            }
+            ["show", "output"] => self.browse(1, input).map(Step::Continue),
+            ["show", "output", step] => {
+                let step = step
+                    .parse()
+                    .map_err(|_| String::from("The step must be a whole number."))?;
+                self.browse(step, input).map(Step::Continue)
+            }
+            ["show", target] => self.show(target).map(Step::Continue),
+            ["save", path] => {
+                let output = self.output.as_ref().ok_or("Run a projection first.")?;
+                let replace = Path::new(path).exists();
+                if replace {
+                    let answer = input(&format!("{} already exists. Replace it? [y/N] ", path));
+                    if !matches!(answer.as_deref().map(str::trim), Some("y" | "yes")) {
+                        return Ok(Step::Continue(format!("{} was not saved.", path)));
+                    }
+                }
+                let file = create_output(path, replace)?;
+                let result = if path.ends_with(".json") {
+                    save_json(output, file)
+                } else {
+                    write_csv(output, file)
+                };
+                result.map_err(|e| e.to_string())?;
+                Ok(Step::Continue(format!("Saved {}.", path)))
+            }
+            [command, ..] => Err(format!(
+                "\"{}\" was not understood. Type `help` for a list of commands.",
+                command
+            )),
+        }
+    }
+    /// Show the last projection from step `start`, a page at a time (see [`browser::browse`]),
+    /// with columns named after the lifestages of the loaded matrix.
+    fn browse<F: FnMut(&str) -> Option<String>>(
+        &self,
+        start: usize,
+        mut input: F,
+    ) -> Result<String, String> {
+        let output = self.output.as_ref().ok_or("Run a projection first.")?;
+        let count = output
+            .iter()
+            .next()
+            .map_or(0, |x| x.get_lifestage_count() as usize);
+        let names = self.names(count);
+        browser::browse(output, &names, start, &mut input, io::stdout().lock())
+    }
+    /// Return the names of the first `count` lifestages of the loaded matrix, or `stage_0`,
+    /// `stage_1`, and so on for lifestages without a name.
+    fn names(&self, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|x| {
+                self.stage_names
+                    .get(x)
+                    .cloned()
+                    .unwrap_or_else(|| format!("stage_{}", x))
+            })
+            .collect()
+    }
+    /// Return the text shown by `show <target>`.
+    fn show(&self, target: &str) -> Result<String, String> {
+        let matrix = || self.matrix.as_ref().ok_or("Load a matrix first.");
+        // Matrices are shown as tables with the lifestage names along both edges.
+        let rows = |rows: &Vec<Vec<f64>>| {
+            let names = self.names(rows.len());
+            let header = [String::new()].into_iter().chain(names.iter().cloned());
+            let table: Vec<Vec<String>> = [header.collect()]
+                .into_iter()
+                .chain(rows.iter().zip(&names).map(|(row, name)| {
+                    [name.clone()]
+                        .into_iter()
+                        .chain(row.iter().map(|x| x.to_string()))
+                        .collect()
+                }))
+                .collect();
+            style::align(&table).join("\n")
+        };
+        Ok(match target {
+            "matrix" => rows(&matrix()?.get_matrix()),
+            "vector" => format!(
+                "{:?}",
+                self.vector
+                    .as_ref()
+                    .ok_or("Load a vector first.")?
+                    .get_vector()
+            ),
+            "lambda" => format!("{:.6}", matrix()?.lambda()),
+            "stable" => format!("{:?}", matrix()?.stable_stage_distribution().get_vector()),
+            "elasticities" => rows(&matrix()?.elasticities().get_matrix()),
+            _ => {
+                return Err(format!(
+                    "Cannot show \"{}\". Choose one of: {}.",
+                    target,
+                    SHOW_TARGETS.join(", ")
+                ))
+            }
+        })
+    }
+}
+
+fn open(path: &str) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))
+}
+
+#[cfg(feature = "json")]
+fn save_json(
+    output: &PvaDeterministicOutput,
+    file: File,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_json(output, file)
+}
+#[cfg(not(feature = "json"))]
+fn save_json(_: &PvaDeterministicOutput, _: File) -> Result<(), Box<dyn std::error::Error>> {
+    Err("JSON output requires building with the json feature.".into())
+}
+
+/// Return the start of the word being completed and the command words that could complete it,
+/// or `None` if the word is a file name.
+fn complete_command(line: &str) -> Option<(usize, Vec<String>)> {
+    let start = line.rfind(char::is_whitespace).map_or(0, |x| x + 1);
+    let previous: Vec<&str> = line[..start].split_whitespace().collect();
+    let candidates: &[&str] = match previous.as_slice() {
+        [] => &COMMANDS,
+        ["load"] => &LOAD_TARGETS,
+        ["enter"] => &ENTER_TARGETS,
+        ["show"] => &SHOW_TARGETS,
+        ["load", "example"] => {
+            let word = &line[start..];
+            return Some((
+                start,
+                datasets::datasets()
+                    .iter()
+                    .map(|x| x.get_name())
+                    .filter(|x| x.starts_with(word))
+                    .map(String::from)
+                    .collect(),
+            ));
+        }
+        ["load", _] | ["save"] => return None,
+        _ => &[],
+    };
+    let word = &line[start..];
+    Some((
+        start,
+        candidates
+            .iter()
+            .filter(|x| x.starts_with(word))
+            .map(|x| x.to_string())
+            .collect(),
+    ))
+}
+
+/// The rustyline helper providing tab completion.
+struct ReplHelper {
+    filenames: FilenameCompleter,
+}
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        match complete_command(&line[..pos]) {
+            Some((start, words)) => Ok((
+                start,
+                words
+                    .into_iter()
+                    .map(|word| Pair {
+                        display: word.clone(),
+                        replacement: word + " ",
+                    })
+                    .collect(),
+            )),
+            None => self.filenames.complete_path(line, pos),
+        }
+    }
+}
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
+
+/// The file that command history is kept in between sessions.
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ecolysis_history"))
+}
+
+/// Run the interactive prompt until the user quits or closes standard input.
+pub fn run() -> io::Result<()> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new().map_err(io::Error::other)?;
+    editor.set_helper(Some(ReplHelper {
+        filenames: FilenameCompleter::new(),
+    }));
+    let history = history_file();
+    if let Some(history) = &history {
+        // There is no history the first time the program is run.
+        let _ = editor.load_history(history);
+    }
+    println!(
+        "{}",
+        style::header(
+            "Welcome to EcolysisCMD, a Rust tool for ecologicial simulation and analysis."
+        )
+    );
+    println!("Type `help` for a list of commands, or `tutorial` for a guided first analysis.");
+    let mut session = Session::default();
+    loop {
+        let line = match editor.readline("ecolysis> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(error) => return Err(io::Error::other(error)),
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        let input = |prompt: &str| editor.readline(prompt).ok();
+        match session.execute(&line, input) {
+            Ok(Step::Continue(message)) if message.is_empty() => {}
+            Ok(Step::Continue(message)) => println!("{}", message),
+            Ok(Step::Cancel) => break,
+            Err(error) => eprintln!("{}", style::error(error)),
+        }
+    }
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn no_input(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn session_commands() {
+        let directory = std::env::temp_dir().join("ecolysis_repl_session");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let matrix = directory.join("m.csv");
+        let vector = directory.join("v.csv");
+        let output = directory.join("out.csv");
+        fs::write(&matrix, "0,1\n0.5,0.5\n").unwrap();
+        fs::write(&vector, "10,10\n").unwrap();
+        let mut session = Session::default();
+        assert!(session.execute("project 2", no_input).is_err());
+        session
+            .execute(&format!("load matrix {}", matrix.display()), no_input)
+            .unwrap();
+        session
+            .execute(&format!("load vector {}", vector.display()), no_input)
+            .unwrap();
+        assert!(matches!(
+            session.execute("show lambda", no_input),
+            Ok(Step::Continue(x)) if x == "1.000000"
+        ));
+        assert!(matches!(
+            session.execute("show matrix", no_input),
+            Ok(Step::Continue(x)) if x == "         stage_0  stage_1\nstage_0        0        1\nstage_1      0.5      0.5"
+        ));
+        session.execute("project 2", no_input).unwrap();
+        session
+            .execute(&format!("save {}", output.display()), no_input)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "step,stage_0,stage_1\n1,10,10\n2,10,10\n"
+        );
+        session.execute("project 1", no_input).unwrap();
+        let save = format!("save {}", output.display());
+        assert!(matches!(
+            session.execute(&save, no_input),
+            Ok(Step::Continue(x)) if x.ends_with("was not saved.")
+        ));
+        assert_eq!(fs::read_to_string(&output).unwrap().lines().count(), 3);
+        session.execute(&save, |_| Some(String::from("y"))).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap().lines().count(), 2);
+        assert!(matches!(
+            session.execute("show output", no_input),
+            Ok(Step::Continue(x)) if x == "step  stage_0  stage_1\n   1    10.00    10.00"
+        ));
+        assert!(session.execute("show output 2", no_input).is_err());
+        session.execute("load example teasel", no_input).unwrap();
+        session.execute("project 30", no_input).unwrap();
+        assert!(matches!(
+            session.execute("show output 25", no_input),
+            Ok(Step::Continue(x)) if x.starts_with("step  ") && x.contains("rosette") && x.lines().count() == 7
+        ));
+        assert!(session.execute("show colours", no_input).is_err());
+        assert!(session.execute("fly", no_input).is_err());
+        assert!(matches!(
+            session.execute("quit", no_input),
+            Ok(Step::Cancel)
+        ));
+        let mut lines = vec!["ok", "set 1 2 5", "ok", "5 NaN"];
+        session
+            .execute("enter vector 2", |_| lines.pop().map(String::from))
+            .unwrap();
+        assert_eq!(
+            session.vector.as_ref().unwrap().get_vector(),
+            &vec![5.0, 5.0]
+        );
+        let mut lines = vec!["ok", "set 1 2 0.3", "0 2", "0.5 0.8"];
+        session
+            .execute("enter matrix 2", |_| lines.pop().map(String::from))
+            .unwrap();
+        assert_eq!(
+            session.matrix.as_ref().unwrap().get_matrix(),
+            vec![vec![0.5, 0.3], vec![0.0, 2.0]]
+        );
+        assert!(session.execute("enter matrix many", no_input).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+    #[test]
+    fn completion() {
+        assert_eq!(
+            complete_command("pr"),
+            Some((0, vec![String::from("project")]))
+        );
+        assert_eq!(
+            complete_command("show e"),
+            Some((5, vec![String::from("elasticities")]))
+        );
+        assert_eq!(
+            complete_command("load "),
+            Some((
+                5,
+                vec![
+                    String::from("matrix"),
+                    String::from("vector"),
+                    String::from("example")
+                ]
+            ))
+        );
+        assert_eq!(
+            complete_command("enter "),
+            Some((6, vec![String::from("matrix"), String::from("vector")]))
+        );
+        assert_eq!(
+            complete_command("load example t"),
+            Some((13, vec![String::from("teasel")]))
+        );
+        assert_eq!(complete_command("load matrix da"), None);
+        assert_eq!(complete_command("save "), None);
+    }
+}
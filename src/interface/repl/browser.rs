@@ -0,0 +1,139 @@
+//! The result browser of the interactive interface, which shows a projection as a table with a labelled column for each lifestage, one page at a time, so that long projections can be read at the prompt instead of scrolling past as CSV.
+use super::style;
+use crate::populations::population_level_simulation::PvaDeterministicOutput;
+use std::io::Write;
+
+/// The number of steps shown on each page.
+pub(super) const PAGE_SIZE: usize = 20;
+
+/// Return the header and the rows of the table of `output`: a step column, then a column for
+/// each lifestage headed by its name in `names`, with every column right-aligned to a common
+/// width.
+fn table(output: &PvaDeterministicOutput, names: &[String]) -> (String, Vec<String>) {
+    let header = [String::from("step")]
+        .into_iter()
+        .chain(names.iter().cloned());
+    let cells: Vec<Vec<String>> = [header.collect()]
+        .into_iter()
+        .chain(output.iter().zip(1..).map(|(vector, step)| {
+            [step.to_string()]
+                .into_iter()
+                .chain(vector.iter().map(|x| format!("{:.2}", x)))
+                .collect()
+        }))
+        .collect();
+    let mut rows = style::align(&cells);
+    let header = rows.remove(0);
+    (header, rows)
+}
+
+/// Show the steps of `output` from step `start` onwards, [`PAGE_SIZE`] steps at a time, with the
+/// lifestage `names` as column headings. Each page but the last is written to `writer`, followed
+/// by a prompt read with `input`: Enter shows the next page, a step number jumps to the page
+/// starting at that step, and `q` (or the end of input) stops browsing. Returns the last page,
+/// or nothing if browsing was stopped early.
+/// # Errors
+/// Returns a message if `start` is not one of the steps of the projection.
+pub(super) fn browse<W: Write>(
+    output: &PvaDeterministicOutput,
+    names: &[String],
+    start: usize,
+    input: &mut dyn FnMut(&str) -> Option<String>,
+    mut writer: W,
+) -> Result<String, String> {
+    let (header, rows) = table(output, names);
+    let out_of_range = |step: usize| {
+        format!(
+            "There is no step {}. The projection has steps 1 to {}.",
+            step,
+            rows.len()
+        )
+    };
+    if rows.is_empty() {
+        return Ok(header);
+    }
+    if !(1..=rows.len()).contains(&start) {
+        return Err(out_of_range(start));
+    }
+    let mut position = start - 1;
+    loop {
+        let end = (position + PAGE_SIZE).min(rows.len());
+        let page = format!("{}\n{}", header, rows[position..end].join("\n"));
+        if end == rows.len() {
+            return Ok(page);
+        }
+        writeln!(writer, "{}", page).map_err(|e| e.to_string())?;
+        let prompt = format!(
+            "-- steps {} to {} of {}: Enter for more, a step number to jump to it, q to stop -- ",
+            position + 1,
+            end,
+            rows.len()
+        );
+        loop {
+            let Some(answer) = input(&prompt) else {
+                return Ok(String::new());
+            };
+            match answer.trim() {
+                "" => position = end,
+                "q" | "quit" => return Ok(String::new()),
+                answer => match answer.parse::<usize>() {
+                    Ok(step) if (1..=rows.len()).contains(&step) => position = step - 1,
+                    Ok(step) => {
+                        writeln!(writer, "{}", out_of_range(step)).map_err(|e| e.to_string())?;
+                        continue;
+                    }
+                    Err(_) => {
+                        writeln!(writer, "\"{}\" is not a step number.", answer)
+                            .map_err(|e| e.to_string())?;
+                        continue;
+                    }
+                },
+            }
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::DeterministicPva;
+
+    #[test]
+    fn pages_are_shown_and_jumped_between() {
+        let output = DeterministicPva::build_from_vectors(
+            vec![10.0, 1000.0],
+            vec![vec![0.0, 1.0], vec![0.5, 0.5]],
+        )
+        .unwrap()
+        .deterministic_projection(50);
+        let names = vec![String::from("juvenile"), String::from("a")];
+        // Answers are read from the end: the next page, a step that does not exist, a jump to
+        // step 45, which shows the last page.
+        let mut answers = vec!["45", "60", ""];
+        let mut written = Vec::new();
+        let last = browse(
+            &output,
+            &names,
+            1,
+            &mut |_| answers.pop().map(String::from),
+            &mut written,
+        )
+        .unwrap();
+        assert!(answers.is_empty());
+        let written = String::from_utf8(written).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[0], "step  juvenile       a");
+        assert_eq!(lines[1], "   1   1000.00  505.00");
+        assert_eq!(lines.len(), 2 * (PAGE_SIZE + 1) + 1);
+        assert!(lines[2 * PAGE_SIZE + 2].starts_with("There is no step 60."));
+        let last: Vec<&str> = last.lines().collect();
+        assert_eq!(last.len(), 1 + 6);
+        assert!(last[1].starts_with("  45 "));
+        // Stopping shows nothing more, and a missing step is an error.
+        let mut written = Vec::new();
+        let stopped = browse(&output, &names, 1, &mut |_| Some("q".into()), &mut written);
+        assert_eq!(stopped, Ok(String::new()));
+        assert!(browse(&output, &names, 51, &mut |_| None, &mut written).is_err());
+    }
+}
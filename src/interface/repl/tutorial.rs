@@ -0,0 +1,258 @@
+//! The guided tutorial started with `tutorial` at the interactive prompt. It walks a new user through a complete analysis of a built-in example: loading it, reading the matrix, interpreting lambda and elasticities, projecting, and saving the results. Each step explains what is about to happen, asks the user to type the command themselves (or press Enter to have it run), and then explains the result.
+use super::Session;
+use crate::examples::datasets;
+use crate::interface::{style, Step};
+
+/// One step of the tutorial: what to read before the command, the command to type, and a
+/// function returning an explanation of the result.
+struct TutorialStep {
+    introduction: &'static str,
+    command: &'static str,
+    explain: fn(&Session) -> String,
+}
+
+const STEPS: [TutorialStep; 6] = [
+    TutorialStep {
+        introduction: "A matrix population model describes a population split into lifestages \
+(such as eggs, juveniles, and adults) and how, over one time step, individuals in each \
+lifestage survive, grow into other lifestages, and reproduce. We will analyse a classic \
+published example: loggerhead sea turtles, whose decline in the 1980s was studied with exactly \
+this kind of model. It comes with a starting population to project.",
+        command: "load example loggerhead",
+        explain: explain_load,
+    },
+    TutorialStep {
+        introduction: "Next, look at the projection matrix itself.",
+        command: "show matrix",
+        explain: explain_matrix,
+    },
+    TutorialStep {
+        introduction: "The most important summary of a matrix is lambda, its dominant \
+eigenvalue: the factor the population is multiplied by each time step once its lifestages \
+have settled into stable proportions.",
+        command: "show lambda",
+        explain: explain_lambda,
+    },
+    TutorialStep {
+        introduction: "Which vital rates matter most? The elasticity of each matrix element is \
+the proportional change in lambda caused by a proportional change in that element, so \
+elasticities can be compared directly to rank where management would have the most effect. \
+They add up to one.",
+        command: "show elasticities",
+        explain: explain_elasticities,
+    },
+    TutorialStep {
+        introduction: "Lambda describes the long run. To see what happens to this particular \
+starting population, project it forward. The number after `project` is the number of time \
+steps.",
+        command: "project 50",
+        explain: explain_projection,
+    },
+    TutorialStep {
+        introduction: "Finally, save the projection so it can be plotted or analysed in a \
+spreadsheet or another program. The name after `save` is the file to write.",
+        command: "save tutorial_projection.csv",
+        explain: explain_save,
+    },
+];
+
+const FINISHED: &str = "That is the whole workflow. To analyse your own data, use `load matrix \
+FILE` and `load vector FILE` with CSV files (or `enter matrix SIZE` to type a matrix in), then \
+the same commands as above. Type `help` to see every command.";
+
+/// Run the tutorial, using `input` to show a prompt and read each line (returning `None` if
+/// input ends). Returns the message to show when the tutorial ends.
+pub fn run(session: &mut Session, input: &mut dyn FnMut(&str) -> Option<String>) -> Step<String> {
+    println!(
+        "Welcome to the tutorial. At each step, type the command shown (or press Enter to have it \
+run for you). Type `quit` to leave the tutorial at any time.\n"
+    );
+    for (index, step) in STEPS.iter().enumerate() {
+        println!(
+            "Step {} of {}. {}\n\nType `{}`.",
+            index + 1,
+            STEPS.len(),
+            step.introduction,
+            step.command
+        );
+        loop {
+            let Some(line) = input("tutorial> ") else {
+                return Step::Continue(String::from("The tutorial was stopped."));
+            };
+            let line = match line.trim() {
+                "" => step.command.to_string(),
+                "quit" | "exit" => {
+                    return Step::Continue(String::from(
+                        "The tutorial was stopped. Type `tutorial` to start again.",
+                    ))
+                }
+                line => line.to_string(),
+            };
+            if line.split_whitespace().next() != step.command.split_whitespace().next() {
+                eprintln!(
+                    "{}",
+                    style::error(format!(
+                        "This step uses `{}`. Type it, or press Enter to have it run for you.",
+                        step.command
+                    ))
+                );
+                continue;
+            }
+            match session.execute(&line, &mut *input) {
+                Ok(Step::Continue(message)) => {
+                    if !message.is_empty() {
+                        println!("{}", message);
+                    }
+                    break;
+                }
+                Ok(Step::Cancel) => return Step::Cancel,
+                Err(error) => eprintln!("{}", style::error(error)),
+            }
+        }
+        println!("\n{}\n", (step.explain)(session));
+    }
+    Step::Continue(FINISHED.to_string())
+}
+
+/// Return the name of a lifestage: its name in the built-in example that is loaded, or its
+/// number if the matrix is not one of the examples.
+fn stage_name(session: &Session, stage: usize) -> String {
+    datasets::datasets()
+        .iter()
+        .find(|x| session.matrix.as_ref() == Some(&x.get_matrix()))
+        .and_then(|x| x.get_lifestages().get(stage))
+        .map_or_else(|| format!("stage {}", stage + 1), |x| x.to_string())
+}
+
+fn explain_load(session: &Session) -> String {
+    let count = session
+        .matrix
+        .as_ref()
+        .map_or(0, |x| x.get_lifestage_count() as usize);
+    let names: Vec<String> = (0..count).map(|x| stage_name(session, x)).collect();
+    format!(
+        "The model has {} lifestages: {}. The initial population vector holds the number of \
+individuals in each, in the same order.",
+        count,
+        names.join(", ")
+    )
+}
+
+fn explain_matrix(_: &Session) -> String {
+    String::from(
+        "Each column is where individuals start and each row is where they end up one step \
+later. The value in row i, column j is the average number of individuals in lifestage i next \
+step for each individual in lifestage j now. Values in the first row are usually \
+reproduction (offspring per individual), and the rest are probabilities of surviving and \
+staying in a lifestage (the diagonal) or moving to another one, which is why they are between \
+zero and one.",
+    )
+}
+
+fn explain_lambda(session: &Session) -> String {
+    let Some(matrix) = &session.matrix else {
+        return String::new();
+    };
+    let lambda = matrix.lambda();
+    let change = (lambda - 1.0).abs() * 100.0;
+    if lambda < 1.0 {
+        format!(
+            "Lambda is {:.4}, which is below one: in the long run the population shrinks by \
+about {:.1}% each step, and without a change in its vital rates it will decline towards \
+extinction.",
+            lambda, change
+        )
+    } else if lambda > 1.0 {
+        format!(
+            "Lambda is {:.4}, which is above one: in the long run the population grows by about \
+{:.1}% each step.",
+            lambda, change
+        )
+    } else {
+        String::from("Lambda is one: in the long run the population neither grows nor shrinks.")
+    }
+}
+
+fn explain_elasticities(session: &Session) -> String {
+    let Some(matrix) = &session.matrix else {
+        return String::new();
+    };
+    let ((row, column), elasticity) = matrix.largest_elasticity();
+    format!(
+        "The largest elasticity, {:.3}, is in row {}, column {}: the rate at which {} end up as \
+{}. A 10% increase in that rate would raise lambda by about {:.1}%. Elasticities of \
+reproduction are often small, which is why protecting eggs alone can do less for a long-lived \
+species than improving the survival of older lifestages.",
+        elasticity,
+        row + 1,
+        column + 1,
+        stage_name(session, column),
+        stage_name(session, row),
+        elasticity * 10.0
+    )
+}
+
+fn explain_projection(session: &Session) -> String {
+    let (Some(vector), Some(output)) = (&session.vector, &session.output) else {
+        return String::new();
+    };
+    let last = output.iter().last().map_or(0.0, |x| x.total());
+    format!(
+        "The population went from {:.0} to {:.0}. Over the first few steps the total can move \
+against the trend set by lambda, because the starting population is not in the stable stage \
+distribution; this is called transient dynamics. Type `show output` later to see every step.",
+        vector.total(),
+        last
+    )
+}
+
+fn explain_save(_: &Session) -> String {
+    String::from(
+        "The file has one row per step, with a `step` column and the number of individuals in \
+each lifestage (`stage_0`, `stage_1`, and so on). Files ending in .json are saved as JSON.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn tutorial_runs_every_step() {
+        let directory = std::env::temp_dir().join("ecolysis_tutorial");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let save = format!("save {}", directory.join("projection.csv").display());
+        // Lines are read from the end: Enter runs the suggested command, and a command for a
+        // different step is refused and asked for again.
+        let mut lines: Vec<String> = ["", "", "", "", "project 5", "show output"]
+            .map(String::from)
+            .into();
+        lines.push(save);
+        lines.reverse();
+        let mut session = Session::default();
+        let result = run(&mut session, &mut |_| lines.pop());
+        assert!(matches!(result, Step::Continue(x) if x == FINISHED));
+        assert!(lines.is_empty());
+        assert_eq!(session.output.as_ref().unwrap().iter().count(), 5);
+        assert_eq!(
+            fs::read_to_string(directory.join("projection.csv"))
+                .unwrap()
+                .lines()
+                .count(),
+            6
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn tutorial_can_be_left() {
+        let mut lines = vec!["quit", ""];
+        let mut session = Session::default();
+        let result = run(&mut session, &mut |_| lines.pop().map(String::from));
+        assert!(matches!(result, Step::Continue(x) if x.starts_with("The tutorial was stopped")));
+        assert!(session.matrix.is_some());
+        assert!(session.output.is_none());
+    }
+}
@@ -0,0 +1,117 @@
+//! This module is the formatting layer of the command line interface: errors in red, warnings in yellow, and headings in bold, with tables aligned in columns. Colors are only written to terminals, and never when the `--plain` flag is given or the `NO_COLOR` environment variable is set (see <https://no-color.org>), so piped output and golden files stay plain text.
+use std::{
+    env,
+    fmt::Display,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// True if `--plain` was given.
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// The kinds of styled text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Error,
+    Warning,
+    Header,
+}
+impl Style {
+    /// Return the ANSI code of the style: red, yellow, or bold.
+    fn code(&self) -> &'static str {
+        match self {
+            Style::Error => "31",
+            Style::Warning => "33",
+            Style::Header => "1",
+        }
+    }
+}
+
+/// Turn styling off (or back on) for the rest of the program, as `--plain` does.
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Return true if output to a stream that is (or is not) a terminal is styled.
+fn enabled(terminal: bool) -> bool {
+    terminal
+        && !PLAIN.load(Ordering::Relaxed)
+        && env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+}
+
+/// Return `text` wrapped in the ANSI escape codes of `style` if `enabled` is true.
+fn paint<T: Display>(text: T, style: Style, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Return `text` in `style` if it is printed to standard output.
+pub fn stdout<T: Display>(style: Style, text: T) -> String {
+    paint(text, style, enabled(io::stdout().is_terminal()))
+}
+
+/// Return `text` in `style` if it is printed to standard error.
+pub fn stderr<T: Display>(style: Style, text: T) -> String {
+    paint(text, style, enabled(io::stderr().is_terminal()))
+}
+
+/// Return an error message styled for standard error.
+pub fn error<T: Display>(text: T) -> String {
+    stderr(Style::Error, text)
+}
+
+/// Return a warning styled for standard error.
+pub fn warning<T: Display>(text: T) -> String {
+    stderr(Style::Warning, text)
+}
+
+/// Return a heading styled for standard output.
+pub fn header<T: Display>(text: T) -> String {
+    stdout(Style::Header, text)
+}
+
+/// Return the lines of a table with every column right-aligned to its widest cell, with two
+/// spaces between columns. Rows may have different numbers of cells.
+pub fn align(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            match widths.get_mut(column) {
+                Some(width) => *width = (*width).max(cell.chars().count()),
+                None => widths.push(cell.chars().count()),
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:>1$}", cell, width))
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_are_only_used_when_enabled() {
+        assert_eq!(
+            paint("No file.", Style::Error, true),
+            "\x1b[31mNo file.\x1b[0m"
+        );
+        assert_eq!(paint("No file.", Style::Warning, false), "No file.");
+        assert!(!enabled(false));
+        let rows = vec![
+            vec![String::from("stage"), String::from("value")],
+            vec![String::from("égg"), String::from("1.5"), String::from("x")],
+        ];
+        assert_eq!(align(&rows), vec!["stage  value", "  égg    1.5  x"]);
+    }
+}
@@ -0,0 +1,67 @@
+//! The `validate` command, which checks input files and reports problems without running a simulation.
+use super::{
+    style::{self, Style},
+    Arguments,
+};
+use crate::validation::{validate_files, validate_scenario, Severity, ValidationReport};
+use std::path::Path;
+
+const USAGE: &str =
+    "Usage: ecolysis_cmd validate [MATRIX] [--matrix FILE] [--vector FILE] [--scenario FILE]
+
+Checks files for dimension mismatches, non-numeric cells, implausible vital rates, and reports
+eigenvalue diagnostics. Exits with code 1 if any errors are found.";
+
+/// Run the `validate` command and return the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match Arguments::parse(args, &["help"]) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", style::error(error), USAGE);
+            return 2;
+        }
+    };
+    if arguments.has_flag("help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let matrix = arguments
+        .get_option("matrix")
+        .or(arguments.get_positional().first().map(String::as_str));
+    let report = match (arguments.get_option("scenario"), matrix) {
+        (Some(scenario), _) => validate_scenario(Path::new(scenario)),
+        (None, Some(matrix)) => validate_files(
+            Path::new(matrix),
+            arguments.get_option("vector").map(Path::new),
+        ),
+        (None, None) => {
+            eprintln!("{}\n\n{}", style::error("Nothing to validate."), USAGE);
+            return 2;
+        }
+    };
+    print_report(&report);
+    i32::from(report.has_errors())
+}
+
+/// Print every issue in a report, followed by a count of errors and warnings.
+fn print_report(report: &ValidationReport) {
+    for issue in report.get_issues() {
+        match issue.get_severity() {
+            Severity::Error => println!("{}", style::stdout(Style::Error, issue)),
+            Severity::Warning => println!("{}", style::stdout(Style::Warning, issue)),
+            Severity::Note => println!("{}", issue),
+        }
+    }
+    let count = |severity| {
+        report
+            .get_issues()
+            .iter()
+            .filter(|issue| issue.get_severity() == severity)
+            .count()
+    };
+    println!(
+        "{} error(s), {} warning(s)",
+        count(Severity::Error),
+        count(Severity::Warning)
+    );
+}
@@ -0,0 +1,620 @@
+//! This module contains functions for writing simulation results to CSV, JSON (with the `json` feature), and Apache Parquet (with the `parquet` feature). Every output type is first converted to an [`OutputTable`], so each writer works with every output type.
+use crate::populations::population_level_simulation::{
+    InitialPopulationOutputs, PopulationMatrix, PopulationVector, ProjectionRecord,
+    PvaDeterministicOutput, PvaStochasticOutput, PvaStochasticSummary, SampleProjection, TimeStep,
+    Verbosity, SUMMARY_QUANTILES,
+};
+use crate::provenance::RunMetadata;
+use crate::statistics::quantile;
+use std::{error::Error, io::Write};
+#[cfg(feature = "cli")]
+mod run_directory;
+#[cfg(feature = "cli")]
+pub use run_directory::*;
+
+/// This struct holds simulation results as a table of named columns containing floating point
+/// values, with one row per step (or per step of each replicate).
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputTable {
+    columns: Vec<String>,
+    rows: Vec<Vec<f64>>,
+}
+impl OutputTable {
+    /// Build an OutputTable from column names and rows.
+    /// # Errors
+    /// Will return `Err<'static str>` if any row has a different number of values than there are
+    /// columns.
+    pub fn build(columns: Vec<String>, rows: Vec<Vec<f64>>) -> Result<OutputTable, &'static str> {
+        if rows.iter().any(|row| row.len() != columns.len()) {
+            return Err("All rows must have one value for each column.");
+        }
+        Ok(OutputTable { columns, rows })
+    }
+    /// Return the column names of the table.
+    pub fn get_columns(&self) -> &Vec<String> {
+        &self.columns
+    }
+    /// Return the rows of the table.
+    pub fn get_rows(&self) -> &Vec<Vec<f64>> {
+        &self.rows
+    }
+}
+
+/// Return column names for each lifestage: `stage_0`, `stage_1`, etc.
+pub(crate) fn stage_columns(lifestage_count: usize) -> Vec<String> {
+    (0..lifestage_count)
+        .map(|stage| format!("stage_{}", stage))
+        .collect()
+}
+
+/// Return the name of the elapsed time column added after `step` when the model that produced an
+/// output declared a time step (such as `month`), and the length of one step in that unit.
+fn time_column(time_step: Option<TimeStep>) -> Option<(String, f64)> {
+    time_step.map(|x| (x.get_unit().get_name().to_string(), x.get_length()))
+}
+
+/// This trait is implemented by simulation outputs that can be written by the functions in this
+/// module.
+pub trait ToOutputTable {
+    /// Convert the output to an OutputTable.
+    fn to_output_table(&self) -> OutputTable;
+}
+impl ToOutputTable for OutputTable {
+    /// A table that was already built, such as the result of
+    /// [`simulation_table`](crate::simulation::simulation_table) for an individual-based model, is
+    /// written as it is.
+    fn to_output_table(&self) -> OutputTable {
+        self.clone()
+    }
+}
+impl ToOutputTable for PvaDeterministicOutput {
+    /// The table has a `step` column followed by one column per lifestage. If the model declared
+    /// a time step, a column named after its unit (such as `year`) with the time elapsed at the
+    /// end of each step follows `step`. If vital rates changed during the projection, an
+    /// `intervention` column is added, which is one at each step where they changed and zero
+    /// elsewhere.
+    fn to_output_table(&self) -> OutputTable {
+        let lifestage_count = self
+            .iter()
+            .next()
+            .map_or(0, |x| x.get_lifestage_count() as usize);
+        let interventions = self.get_interventions();
+        let time = time_column(self.get_time_step());
+        let mut columns = vec![String::from("step")];
+        columns.extend(time.iter().map(|(name, _)| name.clone()));
+        columns.extend(stage_columns(lifestage_count));
+        if !interventions.is_empty() {
+            columns.push(String::from("intervention"));
+        }
+        let rows = self
+            .iter()
+            .enumerate()
+            .map(|(step, popvector)| {
+                let mut row = vec![(step + 1) as f64];
+                row.extend(time.iter().map(|(_, length)| (step + 1) as f64 * length));
+                row.extend(popvector.iter());
+                if !interventions.is_empty() {
+                    row.push(f64::from(interventions.contains(&(step as u32 + 1))));
+                }
+                row
+            })
+            .collect();
+        OutputTable { columns, rows }
+    }
+}
+impl ToOutputTable for PvaStochasticOutput {
+    /// The table has `replicate` and `step` columns followed by one column per lifestage, with
+    /// an elapsed time column after `step` if the model declared a time step (as for
+    /// [`PvaDeterministicOutput`]).
+    fn to_output_table(&self) -> OutputTable {
+        let lifestage_count = self
+            .get_replicates()
+            .iter()
+            .flatten()
+            .next()
+            .map_or(0, |x| x.get_lifestage_count() as usize);
+        let time = time_column(self.get_time_step());
+        let mut columns = vec![String::from("replicate"), String::from("step")];
+        columns.extend(time.iter().map(|(name, _)| name.clone()));
+        columns.extend(stage_columns(lifestage_count));
+        let mut rows = Vec::new();
+        for (replicate, steps) in self.get_replicates().iter().enumerate() {
+            for (step, popvector) in steps.iter().enumerate() {
+                let mut row = vec![replicate as f64, (step + 1) as f64];
+                row.extend(time.iter().map(|(_, length)| (step + 1) as f64 * length));
+                row.extend(popvector.iter());
+                rows.push(row);
+            }
+        }
+        OutputTable { columns, rows }
+    }
+}
+impl ToOutputTable for PvaStochasticSummary {
+    /// The table has a `step` column (followed by an elapsed time column if the model declared a
+    /// time step), the mean and standard deviation of the total population, the estimated
+    /// quantiles of the total population, and the cumulative extinction probability.
+    fn to_output_table(&self) -> OutputTable {
+        let time = time_column(self.get_time_step());
+        let mut columns = vec![String::from("step")];
+        columns.extend(time.iter().map(|(name, _)| name.clone()));
+        columns.extend([String::from("total_mean"), String::from("total_sd")]);
+        for (quantile, _) in self.total_quantiles(0).unwrap_or_default() {
+            columns.push(format!("total_q{}", quantile * 100.0).replace('.', "_"));
+        }
+        columns.push(String::from("cumulative_extinction"));
+        let cumulative_extinction = self.cumulative_extinction_probability();
+        let rows = (0..self.step_count())
+            .map(|index| {
+                let stats = &self.total_statistics()[index];
+                let step = (self.first_step() as usize + index) as f64;
+                let mut row = vec![step];
+                row.extend(time.iter().map(|(_, length)| step * length));
+                row.extend([stats.mean(), stats.standard_deviation()]);
+                row.extend(
+                    self.total_quantiles(index)
+                        .into_iter()
+                        .flatten()
+                        .map(|(_, x)| x),
+                );
+                row.push(cumulative_extinction[index]);
+                row
+            })
+            .collect();
+        OutputTable { columns, rows }
+    }
+}
+impl ToOutputTable for SampleProjection {
+    /// The table has a `step` column, the median of the total population across the sampled
+    /// matrices, and the quantiles in [`SUMMARY_QUANTILES`] (the bounds of the 95% credible
+    /// interval and the median).
+    fn to_output_table(&self) -> OutputTable {
+        let mut columns = vec![String::from("step")];
+        columns.extend(
+            SUMMARY_QUANTILES
+                .iter()
+                .map(|x| format!("total_q{}", x * 100.0).replace('.', "_")),
+        );
+        let rows = (0..self.step_count())
+            .map_while(|step| {
+                let totals = self.totals_at(step)?;
+                let mut row = vec![(step + 1) as f64];
+                row.extend(SUMMARY_QUANTILES.iter().map(|x| quantile(&totals, *x)));
+                Some(row)
+            })
+            .collect();
+        OutputTable { columns, rows }
+    }
+}
+impl ToOutputTable for ProjectionRecord {
+    /// The table has `replicate` and `step` columns (followed by an elapsed time column if the
+    /// model declared a time step), then columns that depend on the verbosity:
+    /// - `Final`: one row per replicate, for the step it stopped at, with one column per
+    ///   lifestage.
+    /// - `Totals`: one row per step of each replicate with a `total` column.
+    /// - `Trajectories`: one row per step of each replicate with one column per lifestage.
+    /// - `Diagnostics`: as for `Trajectories`, followed by `total`, `log_growth_rate`,
+    ///   `structure_distance`, and `matrix` columns.
+    fn to_output_table(&self) -> OutputTable {
+        let lifestage_count = self
+            .get_final_populations()
+            .first()
+            .map_or(0, |x| x.get_lifestage_count() as usize);
+        let time = time_column(self.get_time_step());
+        let verbosity = self.get_verbosity();
+        let mut columns = vec![String::from("replicate"), String::from("step")];
+        columns.extend(time.iter().map(|(name, _)| name.clone()));
+        if verbosity != Verbosity::Totals {
+            columns.extend(stage_columns(lifestage_count));
+        }
+        if verbosity == Verbosity::Totals || verbosity == Verbosity::Diagnostics {
+            columns.push(String::from("total"));
+        }
+        if verbosity == Verbosity::Diagnostics {
+            columns.extend(["log_growth_rate", "structure_distance", "matrix"].map(String::from));
+        }
+        let start = |replicate: usize, step: usize| {
+            let mut row = vec![replicate as f64, step as f64];
+            row.extend(time.iter().map(|(_, length)| step as f64 * length));
+            row
+        };
+        let mut rows = Vec::new();
+        for replicate in 0..self.replicate_count() {
+            match verbosity {
+                Verbosity::Final => {
+                    let mut row = start(replicate, self.get_steps_taken()[replicate] as usize);
+                    row.extend(self.get_final_populations()[replicate].iter());
+                    rows.push(row);
+                }
+                Verbosity::Totals => {
+                    for (step, total) in self.get_totals()[replicate].iter().enumerate() {
+                        let mut row = start(replicate, step + 1);
+                        row.push(*total);
+                        rows.push(row);
+                    }
+                }
+                Verbosity::Trajectories | Verbosity::Diagnostics => {
+                    for (step, popvector) in self.get_trajectories()[replicate].iter().enumerate() {
+                        let mut row = start(replicate, step + 1);
+                        row.extend(popvector.iter());
+                        if let Some(diagnostics) = self
+                            .get_diagnostics()
+                            .get(replicate)
+                            .and_then(|x| x.get(step))
+                        {
+                            row.extend([
+                                popvector.total(),
+                                diagnostics.log_growth_rate,
+                                diagnostics.structure_distance,
+                                diagnostics.matrix as f64,
+                            ]);
+                        }
+                        rows.push(row);
+                    }
+                }
+            }
+        }
+        OutputTable { columns, rows }
+    }
+}
+impl<T: ToOutputTable> ToOutputTable for InitialPopulationOutputs<T> {
+    /// The table has an `initial_population` column holding the position (starting at zero) of
+    /// the initial population each row belongs to, followed by the columns of the output type.
+    fn to_output_table(&self) -> OutputTable {
+        let mut columns = vec![String::from("initial_population")];
+        let mut rows = Vec::new();
+        for (index, output) in self.get_outputs().iter().enumerate() {
+            let table = output.to_output_table();
+            if index == 0 {
+                columns.extend(table.columns);
+            }
+            rows.extend(table.rows.into_iter().map(|row| {
+                let mut grouped = vec![index as f64];
+                grouped.extend(row);
+                grouped
+            }));
+        }
+        OutputTable { columns, rows }
+    }
+}
+
+/// Write an output as CSV, with a header row of column names.
+/// ```
+/// use ecolysis_cmd::outputs::write_csv;
+/// use ecolysis_cmd::DeterministicPva;
+/// let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+///     vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap();
+/// let mut buffer = Vec::new();
+/// write_csv(&population.deterministic_projection(2), &mut buffer).unwrap();
+/// assert_eq!(String::from_utf8(buffer).unwrap(), "step,stage_0,stage_1\n1,10,10\n2,10,10\n");
+/// ```
+pub fn write_csv<T: ToOutputTable, W: Write>(output: &T, writer: W) -> Result<(), Box<dyn Error>> {
+    let table = output.to_output_table();
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(&table.columns)?;
+    for row in &table.rows {
+        csv_writer.write_record(row.iter().map(|x| x.to_string()))?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write a Population Matrix as CSV, with one line per row and no header, in the format the
+/// matrix readers in [`crate::imports`] accept.
+/// ```
+/// use ecolysis_cmd::outputs::write_matrix_csv;
+/// use ecolysis_cmd::PopulationMatrix;
+/// let matrix = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+/// let mut buffer = Vec::new();
+/// write_matrix_csv(&matrix, &mut buffer).unwrap();
+/// assert_eq!(String::from_utf8(buffer).unwrap(), "0,1.5\n0.5,0.8\n");
+/// ```
+pub fn write_matrix_csv<W: Write>(
+    matrix: &PopulationMatrix,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    for row in matrix.get_matrix() {
+        csv_writer.write_record(row.iter().map(|x| x.to_string()))?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write a Population Vector as CSV, on one line with no header, in the format the vector readers
+/// in [`crate::imports`] accept.
+/// ```
+/// use ecolysis_cmd::outputs::write_vector_csv;
+/// use ecolysis_cmd::PopulationVector;
+/// let mut buffer = Vec::new();
+/// write_vector_csv(&PopulationVector::new(vec![120.0, 35.5]), &mut buffer).unwrap();
+/// assert_eq!(String::from_utf8(buffer).unwrap(), "120,35.5\n");
+/// ```
+pub fn write_vector_csv<W: Write>(
+    vector: &PopulationVector,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    csv_writer.write_record(vector.iter().map(|x| x.to_string()))?;
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write an output as CSV like [`write_csv`], preceded by the run metadata as lines starting with
+/// `#` (see [`RunMetadata::write_comment_block`]).
+pub fn write_csv_with_metadata<T: ToOutputTable, W: Write>(
+    output: &T,
+    metadata: &RunMetadata,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    metadata.write_comment_block(&mut writer)?;
+    write_csv(output, writer)
+}
+
+/// Write an output as JSON in the "split" layout understood by pandas
+/// (`pandas.read_json(path, orient="split")`): an object with a `columns` list and a `data` list
+/// of rows. Non-finite values are written as `null`.
+#[cfg(feature = "json")]
+pub fn write_json<T: ToOutputTable, W: Write>(output: &T, writer: W) -> Result<(), Box<dyn Error>> {
+    let table = output.to_output_table();
+    serde_json::to_writer(
+        writer,
+        &serde_json::json!({
+            "columns": table.columns,
+            "data": table.rows,
+        }),
+    )?;
+    Ok(())
+}
+
+/// Write an output as an Apache Parquet file with one `DOUBLE` column per table column, which can
+/// be read with `pandas.read_parquet` or R's `arrow::read_parquet`.
+#[cfg(feature = "parquet")]
+pub fn write_parquet<T: ToOutputTable, W: Write + Send>(
+    output: &T,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    use parquet::{
+        data_type::DoubleType,
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use std::sync::Arc;
+
+    let table = output.to_output_table();
+    let fields: String = table
+        .columns
+        .iter()
+        .map(|column| format!("REQUIRED DOUBLE {};", column))
+        .collect();
+    let schema = Arc::new(parse_message_type(&format!(
+        "message ecolysis_output {{ {} }}",
+        fields
+    ))?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut file_writer = SerializedFileWriter::new(writer, schema, properties)?;
+    let mut row_group = file_writer.next_row_group()?;
+    let mut column_index = 0;
+    while let Some(mut column_writer) = row_group.next_column()? {
+        let values: Vec<f64> = table.rows.iter().map(|row| row[column_index]).collect();
+        column_writer
+            .typed::<DoubleType>()
+            .write_batch(&values, None, None)?;
+        column_writer.close()?;
+        column_index += 1;
+    }
+    row_group.close()?;
+    file_writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DeterministicPva, StochasticPva};
+
+    fn deterministic_output() -> PvaDeterministicOutput {
+        DeterministicPva::build_from_vectors(
+            vec![40.0, 20.0, 100.0],
+            vec![
+                vec![0.0, 0.0, 0.1],
+                vec![0.6, 0.8, 0.0],
+                vec![0.0, 0.8, 0.95],
+            ],
+        )
+        .unwrap()
+        .deterministic_projection(5)
+    }
+
+    #[test]
+    fn interventions_are_annotated() {
+        use crate::populations::population_level_simulation::{
+            MatrixSchedule, PopulationMatrix, PopulationVector,
+        };
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap();
+        let output = MatrixSchedule::new(matrix.clone())
+            .change_at(2, matrix)
+            .unwrap()
+            .project(&PopulationVector::new(vec![10.0, 10.0]), 3)
+            .unwrap();
+        let table = output.to_output_table();
+        assert_eq!(table.get_columns().last().unwrap(), "intervention");
+        let flags: Vec<f64> = table.get_rows().iter().map(|row| row[3]).collect();
+        assert_eq!(flags, vec![0.0, 1.0, 0.0]);
+    }
+    #[test]
+    fn elapsed_time_follows_step() {
+        use crate::populations::population_level_simulation::{TimeStep, TimeUnit};
+        let months = TimeStep::new(6.0, TimeUnit::Month).unwrap();
+        let output = deterministic_output().with_time_step(months);
+        let table = output.to_output_table();
+        assert_eq!(&table.get_columns()[..3], &["step", "month", "stage_0"]);
+        assert_eq!(table.get_rows()[2][..2], [3.0, 18.0]);
+        let summary = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![vec![0.0, 1.0], vec![0.5, 0.5]]],
+        )
+        .unwrap()
+        .with_time_step(months)
+        .stochastic_summary(4, 3, 1, 0.0);
+        let table = summary.to_output_table();
+        assert_eq!(&table.get_columns()[..3], &["step", "month", "total_mean"]);
+        assert_eq!(table.get_rows()[3][1], 24.0);
+    }
+    #[test]
+    fn record_tables_follow_verbosity() {
+        use crate::populations::population_level_simulation::ProjectionConfig;
+        let model = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![vec![0.0, 1.0], vec![0.5, 0.5]]],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new().steps(4).replicates(2);
+        let table = |verbosity| {
+            model
+                .record(&config.clone().verbosity(verbosity))
+                .to_output_table()
+        };
+        let last = table(Verbosity::Final);
+        assert_eq!(
+            last.get_columns(),
+            &vec!["replicate", "step", "stage_0", "stage_1"]
+        );
+        assert_eq!(last.get_rows()[1][..2], [1.0, 4.0]);
+        let totals = table(Verbosity::Totals);
+        assert_eq!(totals.get_columns(), &vec!["replicate", "step", "total"]);
+        assert_eq!(totals.get_rows().len(), 8);
+        let diagnostics = table(Verbosity::Diagnostics);
+        assert_eq!(diagnostics.get_columns().len(), 8);
+        assert_eq!(diagnostics.get_rows()[0][7], 0.0);
+    }
+    #[test]
+    fn output_table_rejects_ragged_rows() {
+        assert!(OutputTable::build(vec![String::from("a")], vec![vec![1.0, 2.0]]).is_err());
+    }
+    #[test]
+    fn stochastic_output_table_layout() {
+        let output = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![vec![0.0, 1.0], vec![0.5, 0.5]]],
+        )
+        .unwrap()
+        .stochastic_projection(4, 3, 1);
+        let table = output.to_output_table();
+        assert_eq!(
+            table.get_columns(),
+            &vec!["replicate", "step", "stage_0", "stage_1"]
+        );
+        assert_eq!(table.get_rows().len(), 12);
+        assert_eq!(table.get_rows()[11][..2], [2.0, 4.0]);
+    }
+    #[test]
+    fn initial_population_table_layout() {
+        use crate::populations::population_level_simulation::PopulationVector;
+        let outputs = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![vec![0.0, 1.0], vec![0.5, 0.5]]],
+        )
+        .unwrap()
+        .stochastic_projections(
+            vec![
+                PopulationVector::new(vec![5.0, 5.0]),
+                PopulationVector::new(vec![20.0, 20.0]),
+            ],
+            4,
+            3,
+            1,
+        )
+        .unwrap();
+        let table = outputs.to_output_table();
+        assert_eq!(
+            table.get_columns(),
+            &vec![
+                "initial_population",
+                "replicate",
+                "step",
+                "stage_0",
+                "stage_1"
+            ]
+        );
+        assert_eq!(table.get_rows().len(), 24);
+        assert_eq!(table.get_rows()[23][..3], [1.0, 2.0, 4.0]);
+    }
+    #[test]
+    fn sample_projection_table_layout() {
+        use crate::populations::population_level_simulation::{
+            MatrixSample, PopulationMatrix, PopulationVector,
+        };
+        let sample = MatrixSample::from_matrices(vec![
+            PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap(),
+            PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap(),
+        ])
+        .unwrap();
+        let table = sample
+            .project(&PopulationVector::new(vec![10.0, 10.0]), 3)
+            .unwrap()
+            .to_output_table();
+        assert_eq!(
+            table.get_columns(),
+            &vec!["step", "total_q2_5", "total_q50", "total_q97_5"]
+        );
+        // The median of two totals (20 and 30 after one step) is their mean.
+        assert_eq!(table.get_rows()[0][..3], [1.0, 20.25, 25.0]);
+    }
+    #[test]
+    fn csv_round_trip() {
+        let mut buffer = Vec::new();
+        write_csv(&deterministic_output(), &mut buffer).unwrap();
+        let mut reader = csv::Reader::from_reader(buffer.as_slice());
+        let rows: Vec<Vec<f64>> = reader
+            .records()
+            .map(|record| record.unwrap().iter().map(|x| x.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(&rows, deterministic_output().to_output_table().get_rows());
+    }
+    #[test]
+    fn csv_with_metadata_block() {
+        let mut buffer = Vec::new();
+        let metadata = RunMetadata::new().with_seed(3);
+        write_csv_with_metadata(&deterministic_output(), &metadata, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let data: Vec<&str> = text.lines().filter(|x| !x.starts_with('#')).collect();
+        assert!(text.starts_with("# ecolysis_cmd_version: "));
+        assert_eq!(data[0], "step,stage_0,stage_1,stage_2");
+        assert_eq!(data.len(), 6);
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_layout() {
+        let mut buffer = Vec::new();
+        write_json(&deterministic_output(), &mut buffer).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(value["columns"][0], "step");
+        assert_eq!(value["data"].as_array().unwrap().len(), 5);
+    }
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_round_trip() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let path = std::env::temp_dir().join("ecolysis_parquet_round_trip.parquet");
+        write_parquet(
+            &deterministic_output(),
+            std::fs::File::create(&path).unwrap(),
+        )
+        .unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 5);
+        assert_eq!(
+            reader
+                .metadata()
+                .file_metadata()
+                .schema_descr()
+                .num_columns(),
+            4
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+//! This module contains output directory management for runs that write several files: each run gets its own directory (timestamped by default), existing results are never replaced unless overwriting is asked for, and a manifest lists every file the run produced.
+use crate::provenance::{current_unix_seconds, format_timestamp, hex};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// The name of the manifest written in each run directory.
+pub const MANIFEST_NAME: &str = "manifest.csv";
+
+/// Create a file for writing, creating its parent directories, but refuse to replace an existing
+/// file unless `overwrite` is true.
+/// # Errors
+/// Returns an error of kind [`io::ErrorKind::AlreadyExists`] if the file exists and `overwrite`
+/// is false, or any error from creating the file.
+pub fn create_output_file(path: &Path, overwrite: bool) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ),
+        _ => e,
+    })
+}
+
+/// This struct holds the directory a run writes its results to and the files written to it, so
+/// a manifest of them can be written when the run finishes.
+/// ```
+/// use ecolysis_cmd::outputs::RunDirectory;
+/// use std::io::Write;
+/// let parent = std::env::temp_dir().join("ecolysis_run_directory_example");
+/// let mut run = RunDirectory::timestamped(&parent).unwrap();
+/// write!(run.create_file("populations.csv".as_ref()).unwrap(), "step,total\n1,10\n").unwrap();
+/// let manifest = std::fs::read_to_string(run.write_manifest().unwrap()).unwrap();
+/// assert!(manifest.starts_with("file,bytes,sha256\npopulations.csv,16,"));
+/// // A named directory that already holds results is not reused unless overwriting is allowed.
+/// assert!(RunDirectory::named(run.get_path(), false).is_err());
+/// # std::fs::remove_dir_all(parent).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunDirectory {
+    path: PathBuf,
+    overwrite: bool,
+    files: Vec<PathBuf>,
+}
+impl RunDirectory {
+    /// Create a new directory under `parent` named after the current UTC time, such as
+    /// `2024-05-01T134500Z`. If a run started in the same second already has that name, `-2`,
+    /// `-3`, etc. is added.
+    /// # Errors
+    /// Returns an error if the directory cannot be created.
+    pub fn timestamped(parent: &Path) -> io::Result<RunDirectory> {
+        RunDirectory::timestamped_at(parent, current_unix_seconds())
+    }
+    fn timestamped_at(parent: &Path, seconds: u64) -> io::Result<RunDirectory> {
+        fs::create_dir_all(parent)?;
+        let name = format_timestamp(seconds).replace(':', "");
+        for attempt in 1.. {
+            let path = match attempt {
+                1 => parent.join(&name),
+                attempt => parent.join(format!("{}-{}", name, attempt)),
+            };
+            match fs::create_dir(&path) {
+                Ok(()) => {
+                    return Ok(RunDirectory {
+                        path,
+                        overwrite: false,
+                        files: Vec::new(),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+    /// Use the directory at `path`, creating it if needed. If it already holds files, it is only
+    /// used if `overwrite` is true, in which case files written by this run replace files of the
+    /// same name; other files are left in place.
+    /// # Errors
+    /// Returns an error of kind [`io::ErrorKind::AlreadyExists`] if the directory is not empty
+    /// and `overwrite` is false, or an error if it cannot be created or read.
+    pub fn named(path: &Path, overwrite: bool) -> io::Result<RunDirectory> {
+        if path.is_dir() && !overwrite && fs::read_dir(path)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already holds results", path.display()),
+            ));
+        }
+        fs::create_dir_all(path)?;
+        Ok(RunDirectory {
+            path: path.to_path_buf(),
+            overwrite,
+            files: Vec::new(),
+        })
+    }
+    /// Return the path of the directory.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    /// Return the files recorded so far, relative to the directory.
+    pub fn get_files(&self) -> &Vec<PathBuf> {
+        &self.files
+    }
+    /// Create a file at `relative` (a path inside the directory) for writing, creating
+    /// subdirectories as needed, and record it in the manifest.
+    /// # Errors
+    /// Returns an error if `relative` is absolute or has a `..` component, which could place the
+    /// file outside the directory, if the file already exists and overwriting was not allowed, or
+    /// if it cannot be created.
+    pub fn create_file(&mut self, relative: &Path) -> io::Result<File> {
+        if relative
+            .components()
+            .any(|x| !matches!(x, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not inside the run directory.", relative.display()),
+            ));
+        }
+        let file = create_output_file(&self.path.join(relative), self.overwrite)?;
+        self.add_file(relative);
+        Ok(file)
+    }
+    /// Record a file that was written into the directory some other way (such as a provenance
+    /// sidecar). `path` can be relative to the directory or include it.
+    pub fn add_file(&mut self, path: &Path) {
+        let relative = path.strip_prefix(&self.path).unwrap_or(path).to_path_buf();
+        if !self.files.contains(&relative) {
+            self.files.push(relative);
+        }
+    }
+    /// Write `manifest.csv` in the directory, listing every recorded file (with `/` between
+    /// directory names) with its size in bytes and the SHA-256 hash of its contents, and return
+    /// its path. The manifest is always replaced, as it describes the latest run.
+    /// # Errors
+    /// Returns an error if a recorded file cannot be read or the manifest cannot be written.
+    pub fn write_manifest(&self) -> io::Result<PathBuf> {
+        let mut files = self.files.clone();
+        files.sort();
+        let path = self.path.join(MANIFEST_NAME);
+        let mut writer = csv::Writer::from_writer(create_output_file(&path, true)?);
+        writer.write_record(["file", "bytes", "sha256"])?;
+        for file in files {
+            let contents = fs::read(self.path.join(&file))?;
+            let name: Vec<String> = file
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy().to_string())
+                .collect();
+            writer.write_record([
+                name.join("/"),
+                contents.len().to_string(),
+                hex(&Sha256::digest(&contents)),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamped_directories_are_unique() {
+        let parent = std::env::temp_dir().join("ecolysis_timestamped_runs");
+        let _ = fs::remove_dir_all(&parent);
+        let first = RunDirectory::timestamped_at(&parent, 1_714_571_100).unwrap();
+        let second = RunDirectory::timestamped_at(&parent, 1_714_571_100).unwrap();
+        assert!(first.get_path().ends_with("2024-05-01T134500Z"));
+        assert!(second.get_path().ends_with("2024-05-01T134500Z-2"));
+        fs::remove_dir_all(parent).unwrap();
+    }
+
+    #[test]
+    fn existing_results_are_protected() {
+        let path = std::env::temp_dir().join("ecolysis_named_run");
+        let _ = fs::remove_dir_all(&path);
+        let mut run = RunDirectory::named(&path, false).unwrap();
+        run.create_file(Path::new("nested/out.csv")).unwrap();
+        let error = run.create_file(Path::new("nested/out.csv")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            RunDirectory::named(&path, false).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        let mut forced = RunDirectory::named(&path, true).unwrap();
+        forced.create_file(Path::new("nested/out.csv")).unwrap();
+        forced.add_file(&path.join("nested/out.csv"));
+        assert_eq!(forced.get_files(), &vec![PathBuf::from("nested/out.csv")]);
+        let manifest = fs::read_to_string(forced.write_manifest().unwrap()).unwrap();
+        assert_eq!(
+            manifest,
+            "file,bytes,sha256\nnested/out.csv,0,\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n"
+        );
+        assert!(create_output_file(&path.join(MANIFEST_NAME), false).is_err());
+        for outside in [
+            "../escaped.csv",
+            "nested/../../escaped.csv",
+            "/tmp/escaped.csv",
+        ] {
+            let error = forced.create_file(Path::new(outside)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
 //! This module includes functions having to do with population-related analyses.
+pub mod disease;
 pub mod individual_level_simulation;
+pub mod ipm;
+pub mod patch_occupancy;
 pub mod population_level_simulation;
 pub use population_level_simulation::*;
@@ -0,0 +1,344 @@
+//! This module contains an epidemiological layer that can be added to population models. Individuals (in the individual-based model) or the members of each stage (in a matrix model) are split among susceptible, infected, and recovered (S/I/R) compartments. Each step, susceptibles are infected at a rate set by the number of infected individuals, infected individuals recover or die of the disease, and recovered individuals may lose their immunity; the demographic cycle then runs as usual, with all offspring born susceptible.
+use crate::populations::population_level_simulation::{DecomposedMatrix, PopulationVector};
+use crate::simulation::Simulation;
+
+/// This enum describes how the rate of infection depends on the number of infected individuals
+/// `I` in a population of `N` individuals:
+/// - `Density`: the force of infection is `transmission_rate * I`, as for diseases spread by
+///   contact that increases with crowding.
+/// - `Frequency`: the force of infection is `transmission_rate * I / N`, as for sexually or
+///   vector-transmitted diseases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transmission {
+    Density,
+    Frequency,
+}
+
+/// This enum describes the disease state of an individual.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DiseaseState {
+    #[default]
+    Susceptible,
+    Infected,
+    Recovered,
+}
+
+/// This struct holds the parameters of a disease. Each step, a susceptible individual is
+/// infected with probability `1 - exp(-force of infection)` (see [`Transmission`]), an infected
+/// individual recovers with probability `recovery` and otherwise dies of the disease with
+/// probability `disease_mortality` (on top of its usual mortality), and a recovered individual
+/// becomes susceptible again with probability `immunity_loss`.
+/// ```
+/// use ecolysis_cmd::populations::disease::{Disease, Transmission};
+/// let disease = Disease::new(0.002, 0.2, 0.1)
+///     .unwrap()
+///     .with_immunity_loss(0.05)
+///     .unwrap();
+/// assert!((disease.infection_probability(10.0, 500.0) - (1.0 - (-0.02f64).exp())).abs() < 1e-12);
+/// let frequency = disease.with_transmission(Transmission::Frequency);
+/// assert!((frequency.infection_probability(10.0, 500.0) - (1.0 - (-0.00004f64).exp())).abs() < 1e-12);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disease {
+    transmission_rate: f64,
+    transmission: Transmission,
+    recovery: f64,
+    disease_mortality: f64,
+    immunity_loss: f64,
+}
+impl Disease {
+    /// Create a density-dependent disease with lifelong immunity after recovery.
+    /// # Errors
+    /// Will return `Err<'static str>` if the transmission rate is negative or not finite, or the
+    /// recovery or disease mortality is not a probability.
+    pub fn new(
+        transmission_rate: f64,
+        recovery: f64,
+        disease_mortality: f64,
+    ) -> Result<Disease, &'static str> {
+        if !transmission_rate.is_finite() || transmission_rate < 0.0 {
+            return Err("The transmission rate must be finite and not negative.");
+        }
+        if !(0.0..=1.0).contains(&recovery) || !(0.0..=1.0).contains(&disease_mortality) {
+            return Err("Recovery and disease mortality must be between 0 and 1.");
+        }
+        Ok(Disease {
+            transmission_rate,
+            transmission: Transmission::Density,
+            recovery,
+            disease_mortality,
+            immunity_loss: 0.0,
+        })
+    }
+    /// Return the disease with the given form of transmission.
+    pub fn with_transmission(mut self, transmission: Transmission) -> Self {
+        self.transmission = transmission;
+        self
+    }
+    /// Return the disease with recovered individuals becoming susceptible again with the given
+    /// probability each step.
+    /// # Errors
+    /// Will return `Err<'static str>` if the probability is not between 0 and 1.
+    pub fn with_immunity_loss(mut self, probability: f64) -> Result<Self, &'static str> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("Immunity loss must be between 0 and 1.");
+        }
+        self.immunity_loss = probability;
+        Ok(self)
+    }
+    /// Return the transmission rate.
+    pub fn get_transmission_rate(&self) -> f64 {
+        self.transmission_rate
+    }
+    /// Return the form of transmission.
+    pub fn get_transmission(&self) -> Transmission {
+        self.transmission
+    }
+    /// Return the probability that an infected individual recovers each step.
+    pub fn get_recovery(&self) -> f64 {
+        self.recovery
+    }
+    /// Return the probability that an infected individual that does not recover dies of the
+    /// disease each step.
+    pub fn get_disease_mortality(&self) -> f64 {
+        self.disease_mortality
+    }
+    /// Return the probability that a recovered individual becomes susceptible each step.
+    pub fn get_immunity_loss(&self) -> f64 {
+        self.immunity_loss
+    }
+    /// Return the probability that a susceptible individual is infected in one step, given the
+    /// number of infected individuals and the total population size.
+    pub fn infection_probability(&self, infected: f64, total: f64) -> f64 {
+        let force = match self.transmission {
+            Transmission::Density => self.transmission_rate * infected,
+            Transmission::Frequency if total > 0.0 => self.transmission_rate * infected / total,
+            Transmission::Frequency => 0.0,
+        };
+        1.0 - (-force).exp()
+    }
+}
+
+/// This struct holds the number of individuals in each stage of each disease compartment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpidemicState {
+    pub susceptible: PopulationVector,
+    pub infected: PopulationVector,
+    pub recovered: PopulationVector,
+}
+impl EpidemicState {
+    /// Return the number of individuals in each stage, over all compartments.
+    pub fn population(&self) -> PopulationVector {
+        PopulationVector::new(
+            self.susceptible
+                .iter()
+                .zip(self.infected.iter())
+                .zip(self.recovered.iter())
+                .map(|((s, i), r)| s + i + r)
+                .collect(),
+        )
+    }
+    /// Return the fraction of the population that is infected (0 if the population is empty).
+    pub fn prevalence(&self) -> f64 {
+        let total = self.population().total();
+        if total > 0.0 {
+            self.infected.total() / total
+        } else {
+            0.0
+        }
+    }
+}
+
+/// This struct holds a matrix model with a disease layer: the survival/growth and fertility
+/// parts of the matrix, applied to every compartment, and the disease. The projection is
+/// deterministic, following the expected number of individuals in each compartment.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// use ecolysis_cmd::populations::disease::{Disease, EpidemicMatrixModel};
+/// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+/// use ecolysis_cmd::simulation::Simulation;
+/// let matrix = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+/// let model = EpidemicMatrixModel::build(
+///     DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap(),
+///     Disease::new(0.01, 0.1, 0.3).unwrap(),
+/// );
+/// let mut simulation = model.simulation(vec![100.0, 100.0].into(), vec![0.0, 1.0].into()).unwrap();
+/// let trajectory = simulation.run(50);
+/// assert!(trajectory.iter().any(|x| x.prevalence() > 0.2));
+/// ```
+#[derive(Clone)]
+pub struct EpidemicMatrixModel {
+    matrix: DecomposedMatrix,
+    disease: Disease,
+}
+impl EpidemicMatrixModel {
+    /// Build a matrix model with a disease layer.
+    pub fn build(matrix: DecomposedMatrix, disease: Disease) -> EpidemicMatrixModel {
+        EpidemicMatrixModel { matrix, disease }
+    }
+    /// Return the matrix of the model.
+    pub fn get_matrix(&self) -> &DecomposedMatrix {
+        &self.matrix
+    }
+    /// Return the disease of the model.
+    pub fn get_disease(&self) -> &Disease {
+        &self.disease
+    }
+    /// Return a simulation starting from the given numbers of susceptible and infected
+    /// individuals in each stage, with none recovered.
+    /// # Errors
+    /// Will return `Err<'static str>` if either vector does not have one value per stage.
+    pub fn simulation(
+        &self,
+        susceptible: PopulationVector,
+        infected: PopulationVector,
+    ) -> Result<EpidemicSimulation<'_>, &'static str> {
+        let stages = self.matrix.get_lifestage_count();
+        if susceptible.get_lifestage_count() != stages || infected.get_lifestage_count() != stages {
+            return Err("The initial compartments must have one value per stage.");
+        }
+        Ok(EpidemicSimulation {
+            model: self,
+            state: EpidemicState {
+                susceptible,
+                infected,
+                recovered: PopulationVector::new(vec![0.0; stages as usize]),
+            },
+            steps_taken: 0,
+        })
+    }
+}
+
+/// This struct holds the running state of an [`EpidemicMatrixModel`]. Each step, the disease
+/// compartments are updated first, then every compartment is projected by the survival/growth
+/// matrix (infected individuals also surviving the disease mortality) and all offspring join
+/// the susceptible compartment.
+pub struct EpidemicSimulation<'a> {
+    model: &'a EpidemicMatrixModel,
+    state: EpidemicState,
+    steps_taken: u32,
+}
+impl Simulation for EpidemicSimulation<'_> {
+    type State = EpidemicState;
+    fn step(&mut self) {
+        let disease = &self.model.disease;
+        let state = &self.state;
+        let infection =
+            disease.infection_probability(state.infected.total(), state.population().total());
+        let (recovery, loss) = (disease.recovery, disease.immunity_loss);
+        let disease_survival = 1.0 - disease.disease_mortality;
+        let mut susceptible = Vec::new();
+        let mut infected = Vec::new();
+        let mut recovered = Vec::new();
+        for ((s, i), r) in state
+            .susceptible
+            .iter()
+            .zip(state.infected.iter())
+            .zip(state.recovered.iter())
+        {
+            susceptible.push(s * (1.0 - infection) + r * loss);
+            infected.push(s * infection + i * (1.0 - recovery) * disease_survival);
+            recovered.push(i * recovery + r * (1.0 - loss));
+        }
+        let (susceptible, infected, recovered) = (
+            PopulationVector::new(susceptible),
+            PopulationVector::new(infected),
+            PopulationVector::new(recovered),
+        );
+        let survival = self.model.matrix.get_survival();
+        let fertility = self.model.matrix.get_fertility();
+        let project = |vector: &PopulationVector| {
+            survival
+                .project_vector(vector)
+                .expect("Compartments have one value per stage.")
+        };
+        let births = fertility
+            .project_vector(
+                &EpidemicState {
+                    susceptible: susceptible.clone(),
+                    infected: infected.clone(),
+                    recovered: recovered.clone(),
+                }
+                .population(),
+            )
+            .expect("Compartments have one value per stage.");
+        self.state = EpidemicState {
+            susceptible: PopulationVector::new(
+                project(&susceptible)
+                    .iter()
+                    .zip(births.iter())
+                    .map(|(a, b)| a + b)
+                    .collect(),
+            ),
+            infected: project(&infected),
+            recovered: project(&recovered),
+        };
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &EpidemicState {
+        &self.state
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::PopulationMatrix;
+
+    fn model(disease: Disease) -> EpidemicMatrixModel {
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 1.2], vec![0.5, 0.9]]).unwrap();
+        EpidemicMatrixModel::build(
+            DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap(),
+            disease,
+        )
+    }
+
+    #[test]
+    fn invalid_diseases() {
+        assert!(Disease::new(-1.0, 0.1, 0.1).is_err());
+        assert!(Disease::new(0.1, 1.1, 0.1).is_err());
+        assert!(Disease::new(0.1, 0.1, 0.1)
+            .unwrap()
+            .with_immunity_loss(2.0)
+            .is_err());
+        assert_eq!(
+            Disease::new(0.1, 0.0, 0.0)
+                .unwrap()
+                .with_transmission(Transmission::Frequency)
+                .infection_probability(5.0, 0.0),
+            0.0
+        );
+    }
+    #[test]
+    fn disease_free_population_follows_the_matrix() {
+        let model = model(Disease::new(0.5, 0.1, 0.5).unwrap());
+        assert!(model
+            .simulation(vec![1.0].into(), vec![0.0, 0.0].into())
+            .is_err());
+        let mut simulation = model
+            .simulation(vec![10.0, 10.0].into(), vec![0.0, 0.0].into())
+            .unwrap();
+        simulation.step();
+        assert_eq!(
+            simulation.state().susceptible.get_vector(),
+            &vec![12.0, 14.0]
+        );
+        assert_eq!(simulation.state().prevalence(), 0.0);
+    }
+    #[test]
+    fn lethal_disease_reduces_the_population() {
+        let healthy = model(Disease::new(0.0, 0.0, 0.0).unwrap());
+        let lethal = model(Disease::new(0.01, 0.05, 0.5).unwrap());
+        let total = |model: &EpidemicMatrixModel| {
+            let mut simulation = model
+                .simulation(vec![100.0, 100.0].into(), vec![0.0, 5.0].into())
+                .unwrap();
+            simulation.run(20);
+            simulation.state().population().total()
+        };
+        assert!(total(&lethal) < 0.5 * total(&healthy));
+    }
+}
@@ -1,9 +1,1365 @@
 //! This module contains functions to simulate populations demographics (including genetics) using forward-direction individual-based simulation methods. Populations are represented by a list of individuals with defined behaviors and attributes.
+//!
+//! Each step, every individual first reproduces (drawing its number of offspring from the
+//! [`OffspringDistribution`] of its stage, Poisson by default), then survives and moves between stages
+//! according to a [`TransitionTable`] (and, optionally, an age-dependent [`MortalityHazard`]),
+//! and finally ages by one step. With the same vital rates,
+//! the expected stage counts follow the matrix model `n(t+1) = (U + F) n(t)`, but each replicate
+//! includes demographic stochasticity and the individuals can carry their own attributes.
+//!
+//! When the founders are given genotypes (see [`IndividualBasedModel::with_founder_genotypes`]),
+//! mating is random: each offspring has the breeding individual as its mother and a father drawn
+//! at random from the other individuals of stages that can reproduce, and it inherits a gamete
+//! from each parent, formed with recombination between linked loci (see [`GeneticMap`]). With
+//! sex determination (see [`IndividualBasedModel::with_sex_determination`]), only females breed
+//! and fathers are drawn from the males. Offspring can also be clones of their parent (see
+//! [`IndividualBasedModel::with_reproduction_mode`]) or selfed (see
+//! [`IndividualBasedModel::with_selfing_rate`]), and alleles can mutate when passed on.
+use crate::populations::disease::{Disease, DiseaseState, EpidemicState};
+use crate::populations::population_level_simulation::{
+    replicate_seed, CovariateTrack, DensityDependence, Dormancy, PopulationVector,
+    PvaStochasticOutput, RoundingRule, StageDuration,
+};
+use crate::simulation::Simulation;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+mod cohort;
+mod condition;
+mod development;
+mod dispersal;
+mod dormancy;
+mod event_log;
+mod genetics;
+mod hybrid;
+mod linkage;
+mod mortality;
+mod pedigree;
+mod quantitative_trait;
+mod reproduction;
+mod reproduction_mode;
+mod sex_determination;
+mod territory;
+mod transitions;
+mod translocation;
+pub use cohort::*;
+pub use condition::*;
+pub use dispersal::*;
+pub use event_log::*;
+pub use genetics::*;
+pub use hybrid::*;
+pub use linkage::*;
+pub use mortality::*;
+pub use pedigree::*;
+pub use quantitative_trait::*;
+pub use reproduction::*;
+pub use reproduction_mode::*;
+pub use sex_determination::*;
+pub use territory::*;
+pub use transitions::*;
+pub use translocation::*;
 
-struct Individual {
+/// This struct holds one individual: a unique identifier, its age in steps, its current stage,
+/// the identifiers of its parents (empty for founders; the mother and then the father when the
+/// model has genetics or sexes, and only the parent for a clone), its [`Genotype`] (with no loci
+/// without genetics), its disease state, its [`Sex`] (none without sex determination), its
+/// dominance score (zero unless breeding sites are ranked by dominance, see [`BreederRanking`]),
+/// its body condition (zero without [`BodyCondition`]), its breeding value and phenotype for the
+/// [`QuantitativeTrait`] (zero without one), and the number of steps it has spent in its current
+/// stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Individual {
     id: usize,
     age: u16,
     lifestage: u8,
     parents: Vec<usize>,
-    genotype: Vec<Vec<u8>>,
+    genotype: Genotype,
+    disease: DiseaseState,
+    sex: Option<Sex>,
+    dominance: f64,
+    condition: f64,
+    breeding_value: f64,
+    phenotype: f64,
+    stage_time: u16,
+    stage_duration: u16,
+}
+impl Individual {
+    /// Create a founder individual with no parents and an empty genotype.
+    pub fn new(id: usize, lifestage: u8, age: u16) -> Individual {
+        Individual {
+            id,
+            age,
+            lifestage,
+            parents: Vec::new(),
+            genotype: Genotype::default(),
+            disease: DiseaseState::Susceptible,
+            sex: None,
+            dominance: 0.0,
+            condition: 0.0,
+            breeding_value: 0.0,
+            phenotype: 0.0,
+            stage_time: 0,
+            stage_duration: 0,
+        }
+    }
+    /// Return the identifier of the individual.
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+    /// Return the age of the individual in steps.
+    pub fn get_age(&self) -> u16 {
+        self.age
+    }
+    /// Return the current stage of the individual.
+    pub fn get_lifestage(&self) -> u8 {
+        self.lifestage
+    }
+    /// Return the identifiers of the parents of the individual.
+    pub fn get_parents(&self) -> &Vec<usize> {
+        &self.parents
+    }
+    /// Return the genotype of the individual.
+    pub fn get_genotype(&self) -> &Genotype {
+        &self.genotype
+    }
+    /// Return the disease state of the individual (susceptible if the model has no disease).
+    pub fn get_disease_state(&self) -> DiseaseState {
+        self.disease
+    }
+    /// Return the sex of the individual, or `None` if the model has no sex determination.
+    pub fn get_sex(&self) -> Option<Sex> {
+        self.sex
+    }
+    /// Return the dominance score of the individual.
+    pub fn get_dominance(&self) -> f64 {
+        self.dominance
+    }
+    /// Return the body condition of the individual.
+    pub fn get_condition(&self) -> f64 {
+        self.condition
+    }
+    /// Return the breeding value of the individual for the quantitative trait.
+    pub fn get_breeding_value(&self) -> f64 {
+        self.breeding_value
+    }
+    /// Return the phenotype of the individual for the quantitative trait.
+    pub fn get_phenotype(&self) -> f64 {
+        self.phenotype
+    }
+    /// Return the number of steps the individual has spent in its current stage (for founders,
+    /// since the start of the replicate).
+    pub fn get_time_in_stage(&self) -> u16 {
+        self.stage_time
+    }
+}
+
+/// This struct holds an individual-based model: the initial number of individuals in each stage,
+/// the [`TransitionTable`] describing survival and movement between stages, the mean number of
+/// offspring produced by an individual of each stage each step, and the stage offspring are born
+/// into (the first stage by default).
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{IndividualBasedModel, TransitionTable};
+/// use ecolysis_cmd::PopulationMatrix;
+/// let survival = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap();
+/// let model = IndividualBasedModel::build(
+///     vec![20.0, 20.0].into(),
+///     TransitionTable::from_survival_matrix(&survival).unwrap(),
+///     vec![0.0, 1.2],
+/// ).unwrap();
+/// let output = model.projection(20, 100, 42);
+/// // The matching matrix model grows (lambda is about 1.27), so few replicates die out.
+/// assert!(output.extinction_probability(0.0) < 0.1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IndividualBasedModel {
+    initial_population: PopulationVector,
+    initial_ages: Vec<u16>,
+    transitions: TransitionTable,
+    hazards: Vec<Option<MortalityHazard>>,
+    reproduction: Vec<OffspringDistribution>,
+    age_fecundity: Vec<f64>,
+    newborn_stage: u8,
+    loci: Vec<Locus>,
+    genetic_map: GeneticMap,
+    founder_genotypes: Vec<Genotype>,
+    disease: Option<Disease>,
+    initially_infected: u32,
+    sex_determination: Option<SexDetermination>,
+    sex_covariate: CovariateTrack,
+    breeding_sites: Option<BreedingSites>,
+    body_condition: Option<BodyCondition>,
+    stage_durations: Vec<Option<(StageDuration, u8)>>,
+    dormancy: Vec<Dormancy>,
+    reproduction_mode: ReproductionMode,
+    mate_limitation: DensityDependence,
+    mutation_rate: f64,
+    allele_counts: Vec<u16>,
+    selfing_rate: f64,
+    quantitative_trait: Option<QuantitativeTrait>,
+    pedigree: bool,
+}
+impl IndividualBasedModel {
+    /// Build an individual-based model. The initial population is rounded to whole individuals,
+    /// and every founder starts at age 0 (see [`IndividualBasedModel::with_initial_ages`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if the initial population has no stages or any negative or
+    /// non-finite value, the fecundity does not have one value per stage, any fecundity is
+    /// negative or not finite, or the transition table refers to a stage that does not exist.
+    pub fn build(
+        initial_population: PopulationVector,
+        transitions: TransitionTable,
+        fecundity: Vec<f64>,
+    ) -> Result<IndividualBasedModel, &'static str> {
+        let stage_count = initial_population.get_lifestage_count() as usize;
+        if stage_count == 0
+            || initial_population
+                .iter()
+                .any(|x| !x.is_finite() || *x < 0.0)
+        {
+            return Err(
+                "The initial population must have at least one stage and only finite, non-negative values.",
+            );
+        }
+        if fecundity.len() != stage_count {
+            return Err("The fecundity must have one value per stage.");
+        }
+        if fecundity.iter().any(|x| !x.is_finite() || *x < 0.0) {
+            return Err("Fecundities must be finite and not negative.");
+        }
+        if transitions
+            .max_stage()
+            .is_some_and(|x| x as usize >= stage_count)
+        {
+            return Err("The transition table refers to a stage that does not exist.");
+        }
+        Ok(IndividualBasedModel {
+            initial_population: initial_population.round(RoundingRule::Nearest),
+            initial_ages: vec![0; stage_count],
+            transitions,
+            hazards: vec![None; stage_count],
+            reproduction: fecundity
+                .into_iter()
+                .map(|mean| OffspringDistribution::Poisson { mean })
+                .collect(),
+            age_fecundity: Vec::new(),
+            newborn_stage: 0,
+            loci: Vec::new(),
+            genetic_map: GeneticMap::default(),
+            founder_genotypes: Vec::new(),
+            disease: None,
+            initially_infected: 0,
+            sex_determination: None,
+            sex_covariate: CovariateTrack::default(),
+            breeding_sites: None,
+            body_condition: None,
+            stage_durations: vec![None; stage_count],
+            dormancy: Vec::new(),
+            reproduction_mode: ReproductionMode::Sexual,
+            mate_limitation: DensityDependence::None,
+            mutation_rate: 0.0,
+            allele_counts: Vec::new(),
+            selfing_rate: 0.0,
+            quantitative_trait: None,
+            pedigree: false,
+        })
+    }
+    /// Return the model with founders of each stage starting at the given age, for life cycles
+    /// with age limits on their transitions.
+    /// # Errors
+    /// Will return `Err<'static str>` if there is not one age per stage.
+    pub fn with_initial_ages(mut self, ages: Vec<u16>) -> Result<Self, &'static str> {
+        if ages.len() != self.reproduction.len() {
+            return Err("There must be one initial age per stage.");
+        }
+        self.initial_ages = ages;
+        Ok(self)
+    }
+    /// Return the model with an age-dependent mortality hazard applied to individuals of a stage.
+    /// Each step, an individual of that stage must first survive the hazard (with probability
+    /// [`MortalityHazard::survival`] at its age) before the transition table is applied, so for
+    /// survival to depend on the hazard alone, the transitions out of the stage should add up to
+    /// one.
+    /// # Errors
+    /// Will return `Err<'static str>` if the stage does not exist or the hazard is not valid.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, MortalityHazard, StageTransition, TransitionTable,
+    /// };
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![100.0].into(), table, vec![0.0])
+    ///     .unwrap()
+    ///     .with_mortality_hazard(0, MortalityHazard::Gompertz { baseline: 0.01, senescence: 0.3 })
+    ///     .unwrap();
+    /// let output = model.projection(40, 1, 1);
+    /// assert_eq!(output.get_replicates()[0][39].total(), 0.0);
+    /// ```
+    pub fn with_mortality_hazard(
+        mut self,
+        stage: u8,
+        hazard: MortalityHazard,
+    ) -> Result<Self, &'static str> {
+        hazard.validate()?;
+        *self
+            .hazards
+            .get_mut(stage as usize)
+            .ok_or("The stage does not exist.")? = Some(hazard);
+        Ok(self)
+    }
+    /// Return the mortality hazard applied to each stage, if any.
+    pub fn get_mortality_hazards(&self) -> &Vec<Option<MortalityHazard>> {
+        &self.hazards
+    }
+    /// Return the model with offspring born into the given stage.
+    /// # Errors
+    /// Will return `Err<'static str>` if the stage does not exist.
+    pub fn with_newborn_stage(mut self, stage: u8) -> Result<Self, &'static str> {
+        if stage as usize >= self.reproduction.len() {
+            return Err("The newborn stage does not exist.");
+        }
+        self.newborn_stage = stage;
+        Ok(self)
+    }
+    /// Return the initial number of individuals in each stage.
+    pub fn get_initial_population(&self) -> &PopulationVector {
+        &self.initial_population
+    }
+    /// Return the transition table of the model.
+    pub fn get_transitions(&self) -> &TransitionTable {
+        &self.transitions
+    }
+    /// Return the model with the number of offspring of individuals of a stage drawn from the
+    /// given distribution, replacing the Poisson distribution set by
+    /// [`IndividualBasedModel::build`].
+    /// # Errors
+    /// Will return `Err<'static str>` if the stage does not exist or the distribution is not
+    /// valid.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, OffspringDistribution, StageTransition, TransitionTable,
+    /// };
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 1, 0.4).unwrap(),
+    ///     StageTransition::new(1, 1, 0.8).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![0.0, 50.0].into(), table, vec![0.0, 0.5])
+    ///     .unwrap()
+    ///     // One in five adults breeds each year, laying a clutch of 5 eggs.
+    ///     .with_offspring_distribution(1, OffspringDistribution::Clutch { size: 5, breeding_probability: 0.2 })
+    ///     .unwrap();
+    /// assert_eq!(model.get_fecundity(), vec![0.0, 1.0]);
+    /// ```
+    pub fn with_offspring_distribution(
+        mut self,
+        stage: u8,
+        distribution: OffspringDistribution,
+    ) -> Result<Self, &'static str> {
+        distribution.validate()?;
+        *self
+            .reproduction
+            .get_mut(stage as usize)
+            .ok_or("The stage does not exist.")? = distribution;
+        Ok(self)
+    }
+    /// Return the model with the offspring distribution of every individual scaled by its age:
+    /// an individual of age `a` uses `multipliers[a]` (see [`OffspringDistribution::scaled`]),
+    /// and individuals older than the list use its last value. For example `[0.0, 0.5, 1.0]`
+    /// means breeding starts at age 1 at half the full rate.
+    /// # Errors
+    /// Will return `Err<'static str>` if any multiplier is negative or not finite.
+    pub fn with_age_fecundity(mut self, multipliers: Vec<f64>) -> Result<Self, &'static str> {
+        if multipliers.iter().any(|x| !x.is_finite() || *x < 0.0) {
+            return Err("Age fecundity multipliers must be finite and not negative.");
+        }
+        self.age_fecundity = multipliers;
+        Ok(self)
+    }
+    /// Return the offspring distribution of each stage.
+    pub fn get_offspring_distributions(&self) -> &Vec<OffspringDistribution> {
+        &self.reproduction
+    }
+    /// Return the mean number of offspring per individual of each stage (before any scaling by
+    /// age).
+    pub fn get_fecundity(&self) -> Vec<f64> {
+        self.reproduction.iter().map(|x| x.mean()).collect()
+    }
+    /// Return the offspring distribution of an individual of the given stage and age.
+    fn offspring_distribution(&self, stage: u8, age: u16) -> OffspringDistribution {
+        let distribution = self.reproduction[stage as usize];
+        match self.age_fecundity.last() {
+            None => distribution,
+            Some(last) => {
+                distribution.scaled(*self.age_fecundity.get(age as usize).unwrap_or(last))
+            }
+        }
+    }
+    /// Return the model with founders carrying the given genotypes at the given loci, one
+    /// genotype per founder in the order of [`IndividualBasedModel::founders`]. Offspring then
+    /// inherit their genotypes from their parents under random mating, with the genetic map given
+    /// by the chromosomes and positions of the loci (see [`GeneticMap::from_loci`]). A
+    /// [`GenotypeTable`] read from a file with a `stage` column gives the initial population, the
+    /// loci, and the genotypes.
+    /// # Errors
+    /// Will return `Err<'static str>` if there is not one genotype per founder, or a genotype
+    /// does not have one pair of alleles per locus.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     GenotypeTable, IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// let file = "id,stage,L1\nA1,1,120/124\nA2,1,124/128\n";
+    /// let genotypes = GenotypeTable::read_csv(file.as_bytes()).unwrap();
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 1, 0.5).unwrap(),
+    ///     StageTransition::new(1, 1, 0.9).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(genotypes.stage_counts(2).unwrap().into(), table, vec![0.0, 1.0])
+    ///     .unwrap()
+    ///     .with_founder_genotypes(genotypes.get_loci().clone(), genotypes.founder_genotypes())
+    ///     .unwrap();
+    /// assert_eq!(model.founders()[1].get_genotype().get(0), Some([Some(1), Some(2)]));
+    /// ```
+    pub fn with_founder_genotypes(
+        mut self,
+        loci: Vec<Locus>,
+        genotypes: Vec<Genotype>,
+    ) -> Result<Self, &'static str> {
+        if genotypes.len() != self.initial_population.total() as usize {
+            return Err("There must be one genotype per founder.");
+        }
+        if genotypes.iter().any(|x| x.locus_count() != loci.len()) {
+            return Err("Every genotype must have one pair of alleles per locus.");
+        }
+        self.genetic_map = GeneticMap::from_loci(&loci);
+        self.loci = loci;
+        self.founder_genotypes = genotypes;
+        Ok(self)
+    }
+    /// Return the model with its simulations keeping the [`Pedigree`] of every individual born,
+    /// living or dead, for kinship, relatedness, and inbreeding. The pedigree grows with every
+    /// birth, so it is not kept unless asked for.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.5).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![20.0].into(), table, vec![1.0]).unwrap();
+    /// assert!(model.simulation(0, 1).get_pedigree().is_none());
+    /// let tracked = model.with_pedigree();
+    /// assert_eq!(tracked.simulation(0, 1).get_pedigree().unwrap().len(), 20);
+    /// ```
+    pub fn with_pedigree(mut self) -> Self {
+        self.pedigree = true;
+        self
+    }
+    /// Return whether simulations of the model keep a pedigree.
+    pub fn get_pedigree(&self) -> bool {
+        self.pedigree
+    }
+    /// Return the model with gametes formed using the given genetic map instead of the one built
+    /// from the loci by [`IndividualBasedModel::with_founder_genotypes`].
+    /// # Errors
+    /// Will return `Err<'static str>` if the map does not have one locus per locus of the model.
+    pub fn with_genetic_map(mut self, map: GeneticMap) -> Result<Self, &'static str> {
+        if map.locus_count() != self.loci.len() {
+            return Err("The genetic map must have one locus per locus of the model.");
+        }
+        self.genetic_map = map;
+        Ok(self)
+    }
+    /// Return the genetic map used to form gametes.
+    pub fn get_genetic_map(&self) -> &GeneticMap {
+        &self.genetic_map
+    }
+    /// Return the loci of the model (empty if the model has no genetics).
+    pub fn get_loci(&self) -> &Vec<Locus> {
+        &self.loci
+    }
+    /// Return the genotypes of the founders (empty if the model has no genetics).
+    pub fn get_founder_genotypes(&self) -> &Vec<Genotype> {
+        &self.founder_genotypes
+    }
+    /// Return the model with a disease spreading among individuals, starting with the given
+    /// number of founders (chosen at random in each replicate) infected. Each step, the disease
+    /// states of all individuals are updated (see [`Disease`]) before reproduction, and infected
+    /// individuals that die of the disease are removed.
+    /// # Errors
+    /// Will return `Err<'static str>` if more founders are infected than there are founders.
+    /// ```
+    /// use ecolysis_cmd::populations::disease::Disease;
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.95).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![200.0].into(), table, vec![0.05])
+    ///     .unwrap()
+    ///     .with_disease(Disease::new(0.005, 0.1, 0.2).unwrap(), 5)
+    ///     .unwrap();
+    /// let mut simulation = model.simulation(0, 1);
+    /// assert_eq!(simulation.epidemic_state().infected.total(), 5.0);
+    /// simulation.run(10);
+    /// // The disease spreads beyond the founders first infected.
+    /// assert!(simulation.epidemic_state().prevalence() > 5.0 / 200.0);
+    /// ```
+    pub fn with_disease(
+        mut self,
+        disease: Disease,
+        initially_infected: u32,
+    ) -> Result<Self, &'static str> {
+        if initially_infected as f64 > self.initial_population.total() {
+            return Err("More founders cannot be infected than there are founders.");
+        }
+        self.disease = Some(disease);
+        self.initially_infected = initially_infected;
+        Ok(self)
+    }
+    /// Return the disease of the model, if any.
+    pub fn get_disease(&self) -> Option<&Disease> {
+        self.disease.as_ref()
+    }
+    /// Return the founders of a replicate, numbered from 0 in stage order.
+    pub fn founders(&self) -> Vec<Individual> {
+        let mut founders = Vec::new();
+        for (stage, count) in self.initial_population.iter().enumerate() {
+            for _ in 0..*count as usize {
+                let mut founder =
+                    Individual::new(founders.len(), stage as u8, self.initial_ages[stage]);
+                if let Some(genotype) = self.founder_genotypes.get(founder.id) {
+                    founder.genotype = genotype.clone();
+                }
+                if let Some(condition) = &self.body_condition {
+                    founder.condition = condition.get_initial();
+                }
+                if let Some(quantitative_trait) = &self.quantitative_trait {
+                    founder.breeding_value = quantitative_trait.breeding_value(&founder.genotype);
+                    founder.phenotype = founder.breeding_value;
+                }
+                founders.push(founder);
+            }
+        }
+        founders
+    }
+    /// Return true if individuals need a dominance score.
+    fn ranks_by_dominance(&self) -> bool {
+        self.breeding_sites
+            .is_some_and(|x| x.get_ranking() == BreederRanking::Dominance)
+    }
+    /// Return a simulation of a single replicate. The replicate number and the master seed
+    /// together determine the random numbers used, so the same inputs always produce the same
+    /// replicate.
+    pub fn simulation(&self, replicate: u32, seed: u64) -> IndividualSimulation<'_> {
+        let mut individuals = self.founders();
+        let mut rng = StdRng::seed_from_u64(replicate_seed(seed, replicate));
+        if self.disease.is_some() {
+            for index in rand::seq::index::sample(
+                &mut rng,
+                individuals.len(),
+                self.initially_infected as usize,
+            ) {
+                individuals[index].disease = DiseaseState::Infected;
+            }
+        }
+        if self.sex_determination.is_some() {
+            for individual in &mut individuals {
+                individual.sex = Some(match rng.gen::<bool>() {
+                    true => Sex::Female,
+                    false => Sex::Male,
+                });
+            }
+        }
+        if self.ranks_by_dominance() {
+            for individual in &mut individuals {
+                individual.dominance = rng.gen();
+            }
+        }
+        for individual in &mut individuals {
+            individual.stage_duration = self.draw_duration(individual.lifestage, &mut rng);
+        }
+        if let Some(quantitative_trait) = &self.quantitative_trait {
+            for individual in &mut individuals {
+                individual.phenotype =
+                    quantitative_trait.phenotype(individual.breeding_value, &mut rng);
+            }
+        }
+        let pedigree = self.pedigree.then(|| {
+            let mut pedigree = Pedigree::new();
+            for _ in &individuals {
+                pedigree.add(None, None).expect("founders have no parents");
+            }
+            pedigree
+        });
+        IndividualSimulation {
+            model: self,
+            next_id: individuals.len(),
+            counts: self.initial_population.clone(),
+            individuals,
+            rng,
+            steps_taken: 0,
+            event_log: None,
+            cohorts: None,
+            pedigree,
+            imported: HashMap::new(),
+        }
+    }
+    /// Run the model for the given number of steps and replicates, returning the number of
+    /// individuals in each stage at every step of every replicate.
+    pub fn projection(&self, steps: u32, replicates: u32, seed: u64) -> PvaStochasticOutput {
+        PvaStochasticOutput::new(
+            (0..replicates)
+                .map(|replicate| self.simulation(replicate, seed).run(steps))
+                .collect(),
+        )
+    }
+}
+
+/// This struct holds one running replicate of an [`IndividualBasedModel`]. It implements
+/// [`Simulation`], with the number of individuals in each stage as its state, and keeps the
+/// [`Pedigree`] of every individual born so far if the model asks for one (see
+/// [`IndividualBasedModel::with_pedigree`]).
+pub struct IndividualSimulation<'a> {
+    model: &'a IndividualBasedModel,
+    individuals: Vec<Individual>,
+    next_id: usize,
+    counts: PopulationVector,
+    rng: StdRng,
+    steps_taken: u32,
+    event_log: Option<EventLog>,
+    cohorts: Option<CohortTracker>,
+    pedigree: Option<Pedigree>,
+    /// The identifier in this pedigree of each individual copied from the pedigree of another
+    /// population, keyed by that population and its identifier there.
+    imported: HashMap<(usize, usize), usize>,
+}
+impl IndividualSimulation<'_> {
+    /// Return the simulation recording the life events of individuals in the given log from
+    /// now on.
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+    /// Return the event log, if one was added.
+    pub fn get_event_log(&self) -> Option<&EventLog> {
+        self.event_log.as_ref()
+    }
+    /// Record an event in the log, if there is one.
+    fn record(&mut self, individual: usize, event: LifeEvent) {
+        if let Some(log) = &mut self.event_log {
+            log.record(self.steps_taken + 1, individual, event);
+        }
+    }
+    /// Record in the log, if there is one, that the individuals with the given identifiers moved
+    /// between populations at the end of the last step.
+    pub(crate) fn record_movement(&mut self, ids: &[usize], event: LifeEvent) {
+        if let Some(log) = &mut self.event_log {
+            for id in ids {
+                log.record(self.steps_taken, *id, event);
+            }
+        }
+    }
+    /// Draw a father at random from the potential mates of a mother (the mother herself only if
+    /// she is the only one), returning its identifier and the genotype of their offspring.
+    /// `mates` holds the positions in `parents` of the potential fathers in increasing order, and
+    /// `mother` the position of the mother.
+    fn mate(
+        &mut self,
+        parents: &[Individual],
+        mates: &[usize],
+        mother: usize,
+    ) -> (usize, Genotype) {
+        let own = mates.binary_search(&mother).ok();
+        let father = match mates.len() - usize::from(own.is_some()) {
+            0 => &parents[mother],
+            others => {
+                let mut choice = self.rng.gen_range(0..others);
+                if own.is_some_and(|x| choice >= x) {
+                    choice += 1;
+                }
+                &parents[mates[choice]]
+            }
+        };
+        (
+            father.id,
+            inherit(
+                &parents[mother].genotype,
+                &father.genotype,
+                &self.model.genetic_map,
+                &mut self.rng,
+            ),
+        )
+    }
+    /// Return the individuals alive now.
+    pub fn get_individuals(&self) -> &Vec<Individual> {
+        &self.individuals
+    }
+    /// Return the pedigree of every individual in the simulation so far, living or dead, with
+    /// the same identifiers as the individuals, if the model keeps one.
+    pub fn get_pedigree(&self) -> Option<&Pedigree> {
+        self.pedigree.as_ref()
+    }
+    /// Remove the individuals with the given identifiers, returning them.
+    pub(crate) fn remove_individuals(&mut self, ids: &[usize]) -> Vec<Individual> {
+        let ids: HashSet<usize> = ids.iter().copied().collect();
+        let (removed, kept) = std::mem::take(&mut self.individuals)
+            .into_iter()
+            .partition(|x| ids.contains(&x.id));
+        self.individuals = kept;
+        self.update_counts();
+        removed
+    }
+    /// Add individuals from population `from` of a metapopulation, with their `lineage` in the
+    /// pedigree of that population (see [`Pedigree::lineage`]), empty if it keeps none. They keep
+    /// their stage, age, and genotype, and are given new identifiers. If this population keeps a
+    /// pedigree, they and their ancestors are copied into it with their parents, so kinship and
+    /// inbreeding carry over; each ancestor is copied once for each population it comes from, so
+    /// migrants from the same population that arrive in different steps stay related. Migrants
+    /// without a lineage enter as founders. Returns the new identifiers.
+    pub(crate) fn add_individuals(
+        &mut self,
+        individuals: Vec<Individual>,
+        from: usize,
+        lineage: Vec<(usize, [Option<usize>; 2], bool)>,
+    ) -> Vec<usize> {
+        let Some(pedigree) = &mut self.pedigree else {
+            let mut ids = Vec::with_capacity(individuals.len());
+            for mut individual in individuals {
+                // Without a pedigree the parents have no identifiers in this population.
+                individual.id = self.next_id;
+                individual.parents.clear();
+                self.next_id += 1;
+                ids.push(individual.id);
+                self.individuals.push(individual);
+            }
+            self.update_counts();
+            return ids;
+        };
+        for (id, parents, clone) in lineage {
+            if self.imported.contains_key(&(from, id)) {
+                continue;
+            }
+            let [mother, father] = parents.map(|x| x.map(|x| self.imported[&(from, x)]));
+            let copied = match clone {
+                true => pedigree.add_clone(mother.expect("clones have a parent")),
+                false => pedigree.add(mother, father),
+            }
+            .expect("ancestors are copied before their offspring");
+            self.imported.insert((from, id), copied);
+        }
+        let mut ids = Vec::with_capacity(individuals.len());
+        for mut individual in individuals {
+            individual.id = match self.imported.get(&(from, individual.id)) {
+                Some(id) => *id,
+                None => pedigree.add(None, None).expect("founders have no parents"),
+            };
+            individual.parents = individual
+                .parents
+                .iter()
+                .filter_map(|x| self.imported.get(&(from, *x)).copied())
+                .collect();
+            ids.push(individual.id);
+            self.individuals.push(individual);
+        }
+        self.next_id = pedigree.len();
+        self.update_counts();
+        ids
+    }
+    /// Return the number of individuals in each stage of each disease compartment.
+    pub fn epidemic_state(&self) -> EpidemicState {
+        let stages = self.model.reproduction.len();
+        let mut counts = [vec![0.0; stages], vec![0.0; stages], vec![0.0; stages]];
+        for individual in &self.individuals {
+            let compartment = match individual.disease {
+                DiseaseState::Susceptible => 0,
+                DiseaseState::Infected => 1,
+                DiseaseState::Recovered => 2,
+            };
+            counts[compartment][individual.lifestage as usize] += 1.0;
+        }
+        let [susceptible, infected, recovered] = counts.map(PopulationVector::new);
+        EpidemicState {
+            susceptible,
+            infected,
+            recovered,
+        }
+    }
+    /// Update the disease state of every individual, removing those that die of the disease.
+    fn spread_disease(&mut self, disease: &Disease) {
+        let infected = self
+            .individuals
+            .iter()
+            .filter(|x| x.disease == DiseaseState::Infected)
+            .count();
+        let infection =
+            disease.infection_probability(infected as f64, self.individuals.len() as f64);
+        let mut survivors = Vec::with_capacity(self.individuals.len());
+        for mut individual in std::mem::take(&mut self.individuals) {
+            let draw = self.rng.gen::<f64>();
+            match individual.disease {
+                DiseaseState::Susceptible if draw < infection => {
+                    individual.disease = DiseaseState::Infected;
+                }
+                DiseaseState::Infected if draw < disease.get_recovery() => {
+                    individual.disease = DiseaseState::Recovered;
+                }
+                DiseaseState::Infected
+                    if self.rng.gen::<f64>() < disease.get_disease_mortality() =>
+                {
+                    let cause = DeathCause::Disease;
+                    self.record(individual.id, LifeEvent::Death { cause });
+                    continue;
+                }
+                DiseaseState::Recovered if draw < disease.get_immunity_loss() => {
+                    individual.disease = DiseaseState::Susceptible;
+                }
+                _ => {}
+            }
+            survivors.push(individual);
+        }
+        self.individuals = survivors;
+    }
+    /// Recount the individuals in each stage.
+    fn update_counts(&mut self) {
+        let mut counts = vec![0.0; self.model.reproduction.len()];
+        for individual in &self.individuals {
+            counts[individual.lifestage as usize] += 1.0;
+        }
+        self.counts = PopulationVector::new(counts);
+    }
+}
+impl Simulation for IndividualSimulation<'_> {
+    type State = PopulationVector;
+    fn step(&mut self) {
+        let model = self.model;
+        if let Some(disease) = &model.disease {
+            self.spread_disease(disease);
+        }
+        if let Some(condition) = &model.body_condition {
+            let population = self.individuals.len();
+            for individual in &mut self.individuals {
+                individual.condition =
+                    condition.updated(individual.condition, population, self.steps_taken);
+            }
+        }
+        let mut offspring = Vec::new();
+        let female_proportion = model.female_proportion(self.steps_taken);
+        let clonal_proportion = model.reproduction_mode.clonal_proportion();
+        let mating_success = match clonal_proportion < 1.0 {
+            true => model
+                .mate_limitation
+                .allee_factor(self.individuals.len() as f64),
+            false => 1.0,
+        };
+        // The positions of the potential fathers among the individuals at the start of the step.
+        let mates: Vec<usize> = if model.founder_genotypes.is_empty() && female_proportion.is_none()
+        {
+            Vec::new()
+        } else {
+            self.individuals
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| model.reproduction[x.lifestage as usize].mean() > 0.0)
+                .filter(|(_, x)| x.sex != Some(Sex::Female))
+                .map(|(index, _)| index)
+                .collect()
+        };
+        // The holders of breeding sites, sorted by identifier for lookup.
+        let holders = self.territory_holders().map(|mut x| {
+            x.sort_unstable();
+            x
+        });
+        // Every individual breeds and moves on in place, and the survivors are kept at the end,
+        // so fathers can be looked up by position throughout the step.
+        let mut parents = std::mem::take(&mut self.individuals);
+        let mut survivors = vec![false; parents.len()];
+        for index in 0..parents.len() {
+            let individual = &parents[index];
+            let breeds = match individual.sex {
+                Some(Sex::Male) => false,
+                Some(Sex::Female) => !mates.is_empty() || clonal_proportion > 0.0,
+                None => true,
+            } && holders
+                .as_ref()
+                .is_none_or(|x| x.binary_search(&individual.id).is_ok())
+                && (mating_success >= 1.0 || self.rng.gen::<f64>() < mating_success);
+            let count = match breeds {
+                true => {
+                    let mut distribution =
+                        model.offspring_distribution(individual.lifestage, individual.age);
+                    if let Some(condition) = &model.body_condition {
+                        distribution =
+                            distribution.scaled(condition.breeding(individual.condition));
+                    }
+                    if let Some(quantitative_trait) = &model.quantitative_trait {
+                        distribution = distribution.scaled(
+                            quantitative_trait.breeding(individual.phenotype, self.steps_taken),
+                        );
+                    }
+                    distribution.sample(&mut self.rng)
+                }
+                false => 0,
+            };
+            if count > 0 {
+                self.record(individual.id, LifeEvent::Breeding { offspring: count });
+                if let Some(cohorts) = &mut self.cohorts {
+                    cohorts.record_breeding(individual.id, individual.age, count);
+                }
+            }
+            for _ in 0..count {
+                let mut child = Individual::new(self.next_id, model.newborn_stage, 0);
+                child.parents.push(individual.id);
+                // Females without mates can only reproduce clonally.
+                let clone = match clonal_proportion {
+                    x if x <= 0.0 => false,
+                    x if x >= 1.0 => true,
+                    x => {
+                        (individual.sex.is_some() && mates.is_empty()) || self.rng.gen::<f64>() < x
+                    }
+                };
+                if clone {
+                    child.genotype = individual.genotype.clone();
+                    child.sex = individual.sex;
+                } else {
+                    let selfed = individual.sex.is_none()
+                        && model.selfing_rate > 0.0
+                        && self.rng.gen::<f64>() < model.selfing_rate;
+                    if selfed {
+                        child.parents.push(individual.id);
+                        child.genotype = inherit(
+                            &individual.genotype,
+                            &individual.genotype,
+                            &model.genetic_map,
+                            &mut self.rng,
+                        );
+                    } else if !mates.is_empty() {
+                        let (father, genotype) = self.mate(&parents, &mates, index);
+                        child.parents.push(father);
+                        child.genotype = genotype;
+                    }
+                    if let Some(proportion) = female_proportion {
+                        child.sex = Some(match self.rng.gen::<f64>() < proportion {
+                            true => Sex::Female,
+                            false => Sex::Male,
+                        });
+                    }
+                }
+                if model.mutation_rate > 0.0 {
+                    mutate(
+                        &mut child.genotype,
+                        model.mutation_rate,
+                        &model.allele_counts,
+                        &mut self.rng,
+                    );
+                }
+                if model.ranks_by_dominance() {
+                    child.dominance = self.rng.gen();
+                }
+                if let Some(condition) = &model.body_condition {
+                    child.condition = condition.get_initial();
+                }
+                child.stage_duration = model.draw_duration(child.lifestage, &mut self.rng);
+                if let Some(quantitative_trait) = &model.quantitative_trait {
+                    child.breeding_value = quantitative_trait.breeding_value(&child.genotype);
+                    child.phenotype =
+                        quantitative_trait.phenotype(child.breeding_value, &mut self.rng);
+                }
+                if let Some(pedigree) = &mut self.pedigree {
+                    match clone {
+                        true => pedigree.add_clone(individual.id),
+                        false => pedigree.add(
+                            child.parents.first().copied(),
+                            child.parents.get(1).copied(),
+                        ),
+                    }
+                    .expect("parents are added to the pedigree before their offspring");
+                }
+                self.record(
+                    child.id,
+                    LifeEvent::Birth {
+                        parent: individual.id,
+                        stage: child.lifestage,
+                    },
+                );
+                if let Some(cohorts) = &mut self.cohorts {
+                    cohorts.record_birth(self.steps_taken + 1, child.id);
+                }
+                offspring.push(child);
+                self.next_id += 1;
+            }
+            let individual = &mut parents[index];
+            if let Some(condition) = &model.body_condition {
+                individual.condition = (individual.condition
+                    - condition.get_reproduction_cost() * count as f64)
+                    .max(0.0);
+                if self.rng.gen::<f64>() >= condition.survival(individual.condition) {
+                    let cause = DeathCause::Condition;
+                    self.record(individual.id, LifeEvent::Death { cause });
+                    continue;
+                }
+            }
+            if let Some(quantitative_trait) = &model.quantitative_trait {
+                let survival = quantitative_trait.survival(individual.phenotype, self.steps_taken);
+                if survival < 1.0 && self.rng.gen::<f64>() >= survival {
+                    let cause = DeathCause::Selection;
+                    self.record(individual.id, LifeEvent::Death { cause });
+                    continue;
+                }
+            }
+            if let Some(hazard) = &model.hazards[individual.lifestage as usize] {
+                if self.rng.gen::<f64>() >= hazard.survival(individual.age) {
+                    let cause = DeathCause::Hazard;
+                    self.record(individual.id, LifeEvent::Death { cause });
+                    continue;
+                }
+            }
+            let draw = self.rng.gen::<f64>();
+            match model.next_stage(individual, draw, self.steps_taken) {
+                Some(stage) => {
+                    let from = individual.lifestage;
+                    self.record(individual.id, LifeEvent::Transition { from, to: stage });
+                    if stage == from {
+                        individual.stage_time = individual.stage_time.saturating_add(1);
+                    } else {
+                        individual.stage_time = 0;
+                        individual.stage_duration = model.draw_duration(stage, &mut self.rng);
+                    }
+                    individual.lifestage = stage;
+                    individual.age = individual.age.saturating_add(1);
+                    survivors[index] = true;
+                }
+                None => {
+                    let cause = DeathCause::StageSurvival;
+                    self.record(individual.id, LifeEvent::Death { cause });
+                }
+            }
+        }
+        let mut next: Vec<Individual> = parents
+            .into_iter()
+            .zip(survivors)
+            .filter_map(|(individual, survived)| survived.then_some(individual))
+            .collect();
+        next.append(&mut offspring);
+        self.individuals = next;
+        self.update_counts();
+        self.steps_taken += 1;
+        if let Some(cohorts) = &mut self.cohorts {
+            cohorts.record_census(self.steps_taken, &self.individuals);
+        }
+    }
+    fn state(&self) -> &PopulationVector {
+        &self.counts
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DeterministicPva, PopulationMatrix};
+
+    fn model() -> IndividualBasedModel {
+        let survival = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap();
+        IndividualBasedModel::build(
+            vec![100.0, 100.0].into(),
+            TransitionTable::from_survival_matrix(&survival).unwrap(),
+            vec![0.0, 1.2],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn mean_matches_matrix_model() {
+        let output = model().projection(5, 400, 3);
+        let matrix = DeterministicPva::build_from_vectors(
+            vec![100.0, 100.0],
+            vec![vec![0.0, 1.2], vec![0.5, 0.8]],
+        )
+        .unwrap()
+        .project_n_steps(5);
+        let mean: f64 = output.total_population().iter().map(|x| x[4]).sum::<f64>() / 400.0;
+        assert!((mean - matrix.total()).abs() / matrix.total() < 0.03);
+        assert_eq!(
+            output.get_replicates()[7],
+            model().projection(5, 8, 3).get_replicates()[7]
+        );
+    }
+    #[test]
+    fn individuals_age_and_record_parents() {
+        let model = model();
+        let mut simulation = model.simulation(0, 1);
+        simulation.run(3);
+        let individuals = simulation.get_individuals();
+        assert_eq!(individuals.len() as f64, simulation.state().total());
+        assert!(individuals
+            .iter()
+            .any(|x| x.get_age() == 3 && x.get_parents().is_empty()));
+        let child = individuals.iter().find(|x| x.get_id() >= 200).unwrap();
+        assert_eq!(child.get_parents().len(), 1);
+        assert!(child.get_parents()[0] < child.get_id());
+    }
+    #[test]
+    fn age_limited_transitions() {
+        // Juveniles must wait until age 2 to mature; nothing dies.
+        let table = TransitionTable::build(vec![
+            StageTransition::new(0, 0, 1.0)
+                .unwrap()
+                .with_age_range(None, Some(1))
+                .unwrap(),
+            StageTransition::new(0, 1, 1.0)
+                .unwrap()
+                .with_age_range(Some(2), None)
+                .unwrap(),
+            StageTransition::new(1, 1, 1.0).unwrap(),
+        ])
+        .unwrap();
+        let model =
+            IndividualBasedModel::build(vec![10.0, 0.0].into(), table, vec![0.0, 0.0]).unwrap();
+        let trajectory = model.simulation(0, 0).run(3);
+        assert_eq!(trajectory[1].get_vector(), &vec![10.0, 0.0]);
+        assert_eq!(trajectory[2].get_vector(), &vec![0.0, 10.0]);
+    }
+    #[test]
+    fn invalid_models() {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 2, 0.5).unwrap()]).unwrap();
+        assert!(
+            IndividualBasedModel::build(vec![1.0, 1.0].into(), table.clone(), vec![0.0, 1.0])
+                .is_err()
+        );
+        assert!(
+            IndividualBasedModel::build(vec![1.0, 1.0, 1.0].into(), table.clone(), vec![0.0])
+                .is_err()
+        );
+        assert!(IndividualBasedModel::build(
+            vec![1.0, 1.0, 1.0].into(),
+            table.clone(),
+            vec![0.0, -1.0, 0.0]
+        )
+        .is_err());
+        let model =
+            IndividualBasedModel::build(vec![1.0, 1.0, 1.0].into(), table, vec![0.0; 3]).unwrap();
+        assert!(model.clone().with_newborn_stage(3).is_err());
+        assert!(model.with_initial_ages(vec![0]).is_err());
+    }
+    #[test]
+    fn hazard_survival_depends_on_age() {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        let hazard = MortalityHazard::Weibull {
+            scale: 8.0,
+            shape: 3.0,
+        };
+        let model = |age| {
+            IndividualBasedModel::build(vec![4000.0].into(), table.clone(), vec![0.0])
+                .unwrap()
+                .with_initial_ages(vec![age])
+                .unwrap()
+                .with_mortality_hazard(0, hazard)
+                .unwrap()
+        };
+        for age in [0, 6] {
+            let survivors = model(age).simulation(0, 9).run(1)[0].total() / 4000.0;
+            assert!((survivors - hazard.survival(age)).abs() < 0.03);
+        }
+        assert!(model(0).with_mortality_hazard(1, hazard).is_err());
+    }
+    #[test]
+    fn offspring_distributions_and_age_fecundity() {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        let model = IndividualBasedModel::build(vec![1000.0].into(), table, vec![2.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 4,
+                    breeding_probability: 0.5,
+                },
+            )
+            .unwrap();
+        let mut simulation = model.simulation(0, 2);
+        simulation.step();
+        let births = simulation.state().total() - 1000.0;
+        assert_eq!(births % 4.0, 0.0);
+        assert!((births - 2000.0).abs() < 200.0);
+        let juvenile = model.clone().with_age_fecundity(vec![0.0, 1.0]).unwrap();
+        assert_eq!(juvenile.simulation(0, 2).run(1)[0].total(), 1000.0);
+        let mature = juvenile.with_initial_ages(vec![5]).unwrap();
+        assert!(mature.simulation(0, 2).run(1)[0].total() > 2000.0);
+        assert!(model.clone().with_age_fecundity(vec![-1.0]).is_err());
+        assert!(model
+            .with_offspring_distribution(1, OffspringDistribution::Poisson { mean: 1.0 })
+            .is_err());
+    }
+    #[test]
+    fn founder_genotypes_are_inherited() {
+        let homozygote = |allele| Genotype::new(vec![[Some(allele), Some(allele)]]).unwrap();
+        let loci = vec![Locus::new("L1")];
+        let founders = vec![homozygote(0); 100]
+            .into_iter()
+            .chain(vec![homozygote(1); 100])
+            .collect();
+        assert!(model()
+            .with_founder_genotypes(loci.clone(), vec![homozygote(0)])
+            .is_err());
+        assert!(model()
+            .with_founder_genotypes(Vec::new(), vec![homozygote(0); 200])
+            .is_err());
+        let genetic = model().with_founder_genotypes(loci, founders).unwrap();
+        assert!(genetic
+            .clone()
+            .with_genetic_map(GeneticMap::unlinked(2))
+            .is_err());
+        let mut simulation = genetic.simulation(0, 2);
+        simulation.run(1);
+        let children: Vec<_> = simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_id() >= 200)
+            .collect();
+        assert!(!children.is_empty());
+        for child in children {
+            let parents = child.get_parents();
+            assert_eq!(parents.len(), 2);
+            assert!(parents[0] >= 100 && parents[1] >= 100 && parents[0] != parents[1]);
+            assert_eq!(child.get_genotype(), &homozygote(1));
+        }
+    }
+    #[test]
+    fn pedigree_covers_every_individual() {
+        let founders = vec![Genotype::new(vec![[Some(0), Some(1)]]).unwrap(); 200];
+        let genetic = model()
+            .with_founder_genotypes(vec![Locus::new("L1")], founders)
+            .unwrap()
+            .with_pedigree();
+        let mut simulation = genetic.simulation(0, 3);
+        simulation.run(3);
+        let pedigree = simulation.get_pedigree().unwrap();
+        let living: Vec<usize> = simulation
+            .get_individuals()
+            .iter()
+            .map(|x| x.get_id())
+            .collect();
+        assert_eq!(pedigree.len(), *living.iter().max().unwrap() + 1);
+        let child = simulation.get_individuals().last().unwrap();
+        let parents = pedigree.get_parents(child.get_id()).unwrap();
+        assert_eq!(parents[0], Some(child.get_parents()[0]));
+        assert_eq!(parents[1], Some(child.get_parents()[1]));
+        assert!(pedigree.kinship(child.get_id(), child.get_parents()[0]) >= 0.25);
+        assert_eq!(pedigree.suggest_pairings(&living).len(), living.len() / 2);
+    }
+    #[test]
+    fn disease_spreads_and_kills() {
+        let disease = Disease::new(0.01, 0.0, 0.5).unwrap();
+        assert!(model().with_disease(disease, 201).is_err());
+        let infected = model().with_disease(disease, 20).unwrap();
+        let mut simulation = infected.simulation(0, 1).with_event_log(EventLog::new());
+        assert_eq!(simulation.epidemic_state().infected.total(), 20.0);
+        simulation.step();
+        let state = simulation.epidemic_state();
+        assert_eq!(state.population(), *simulation.state());
+        assert!(state.infected.total() > 20.0);
+        // Offspring are born susceptible.
+        assert!(simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_id() >= 200)
+            .all(|x| x.get_disease_state() == DiseaseState::Susceptible));
+        let disease_deaths = simulation
+            .get_event_log()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .filter(|x| {
+                x.event
+                    == LifeEvent::Death {
+                        cause: DeathCause::Disease,
+                    }
+            })
+            .count();
+        assert!(disease_deaths > 0);
+        let healthy = model().projection(10, 20, 1).total_population();
+        let sick = infected.projection(10, 20, 1).total_population();
+        let mean = |x: &Vec<Vec<f64>>| x.iter().map(|x| x[9]).sum::<f64>();
+        assert!(mean(&sick) < mean(&healthy));
+    }
+    #[test]
+    fn event_log_follows_individuals() {
+        let model = model();
+        let mut simulation = model.simulation(0, 4).with_event_log(EventLog::new());
+        simulation.run(3);
+        let log = simulation.get_event_log().unwrap();
+        let events = |kind: fn(&LifeEvent) -> bool| {
+            log.get_entries().iter().filter(|x| kind(&x.event)).count()
+        };
+        let births = events(|x| matches!(x, LifeEvent::Birth { .. }));
+        let deaths = events(|x| matches!(x, LifeEvent::Death { .. }));
+        assert_eq!(200 + births - deaths, simulation.get_individuals().len());
+        let bred: u32 = log
+            .get_entries()
+            .iter()
+            .map(|x| match x.event {
+                LifeEvent::Breeding { offspring } => offspring,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(bred as usize, births);
+        assert!(log.get_entries().iter().all(|x| (1..=3).contains(&x.step)));
+        assert!(model.simulation(0, 4).get_event_log().is_none());
+    }
+    #[test]
+    fn migrants_keep_their_pedigree() {
+        let mut source = Pedigree::new();
+        let (dam, sire) = (
+            source.add(None, None).unwrap(),
+            source.add(None, None).unwrap(),
+        );
+        let siblings: Vec<usize> = (0..3)
+            .map(|_| source.add(Some(dam), Some(sire)).unwrap())
+            .collect();
+        let migrant = |id: usize| {
+            let mut individual = Individual::new(id, 1, 1);
+            individual.parents = vec![dam, sire];
+            individual
+        };
+        let model = model().with_pedigree();
+        let mut destination = model.simulation(0, 1);
+        let residents = destination.get_pedigree().unwrap().len();
+        let first = siblings[..2].to_vec();
+        destination.add_individuals(
+            first.iter().map(|x| migrant(*x)).collect(),
+            3,
+            source.lineage(&first),
+        );
+        destination.add_individuals(
+            vec![migrant(siblings[2])],
+            3,
+            source.lineage(&siblings[2..]),
+        );
+        // The parents are copied once, and every migrant is a full sibling of the others.
+        let pedigree = destination.get_pedigree().unwrap();
+        assert_eq!(pedigree.len(), residents + 5);
+        let arrived: Vec<usize> = destination.get_individuals()[residents..]
+            .iter()
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(arrived, vec![residents + 2, residents + 3, residents + 4]);
+        assert_eq!(pedigree.kinship(arrived[0], arrived[2]), 0.25);
+        assert_eq!(
+            pedigree.get_parents(arrived[2]),
+            Some([Some(residents), Some(residents + 1)])
+        );
+        assert_eq!(
+            destination.get_individuals()[residents + 2].parents,
+            vec![residents, residents + 1]
+        );
+        let removed = destination.remove_individuals(&[arrived[1], 0]);
+        assert_eq!(removed.len(), 2);
+    }
+    #[test]
+    fn migrants_without_a_pedigree_get_new_identifiers() {
+        let model = model();
+        let mut destination = model.simulation(0, 1);
+        destination.run(2);
+        assert!(destination.get_pedigree().is_none());
+        let next = destination.next_id;
+        let mut migrant = Individual::new(3, 1, 1);
+        migrant.parents = vec![0, 1];
+        let ids = destination.add_individuals(vec![migrant], 2, Vec::new());
+        assert_eq!(ids, vec![next]);
+        let arrived = destination.get_individuals().last().unwrap();
+        assert_eq!(arrived.get_id(), next);
+        assert!(arrived.get_parents().is_empty());
+    }
 }
@@ -0,0 +1,252 @@
+//! This module contains cohort tracking for the individual-based model: following every individual born in chosen steps until the last of them dies, and summarising them as a classical life table. Comparing the survivorship, fecundity, net reproductive rate, and generation time of simulated cohorts with field life tables (or with the matrix model the IBM was built from) is a direct check that the individual rules produce the intended demography.
+use super::{Individual, IndividualSimulation};
+use std::{collections::HashMap, error::Error, io::Write};
+
+/// This struct follows the cohorts of individuals born in chosen steps of an individual-based
+/// simulation (see [`IndividualSimulation::with_cohort_tracker`]), counting at the end of each
+/// step how many members of each cohort are alive and how many offspring they produced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CohortTracker {
+    birth_steps: Vec<u32>,
+    members: HashMap<usize, usize>,
+    alive: Vec<Vec<u32>>,
+    offspring: Vec<Vec<u32>>,
+}
+impl CohortTracker {
+    /// Create a tracker for the cohorts born in the given steps (starting at 1).
+    pub fn new(birth_steps: Vec<u32>) -> CohortTracker {
+        let cohorts = birth_steps.len();
+        CohortTracker {
+            birth_steps,
+            members: HashMap::new(),
+            alive: vec![Vec::new(); cohorts],
+            offspring: vec![Vec::new(); cohorts],
+        }
+    }
+    /// Return the steps the tracked cohorts were born in.
+    pub fn get_birth_steps(&self) -> &Vec<u32> {
+        &self.birth_steps
+    }
+    /// Mark an individual born in a step as a member of that step's cohort, if it is tracked.
+    pub(crate) fn record_birth(&mut self, step: u32, individual: usize) {
+        if let Some(cohort) = self.birth_steps.iter().position(|x| *x == step) {
+            self.members.insert(individual, cohort);
+        }
+    }
+    /// Count the offspring produced by an individual of the given age, if it is tracked.
+    pub(crate) fn record_breeding(&mut self, individual: usize, age: u16, offspring: u32) {
+        if let Some(cohort) = self.members.get(&individual) {
+            let counts = &mut self.offspring[*cohort];
+            if counts.len() <= age as usize {
+                counts.resize(age as usize + 1, 0);
+            }
+            counts[age as usize] += offspring;
+        }
+    }
+    /// Count the living members of every cohort born by the end of a step.
+    pub(crate) fn record_census(&mut self, step: u32, individuals: &[Individual]) {
+        let mut alive = vec![0; self.birth_steps.len()];
+        for individual in individuals {
+            if let Some(cohort) = self.members.get(&individual.id) {
+                alive[*cohort] += 1;
+            }
+        }
+        for (cohort, count) in alive.into_iter().enumerate() {
+            let birth_step = self.birth_steps[cohort];
+            let counts = &mut self.alive[cohort];
+            // A cohort is only counted from the step it was born in, and stays at zero once
+            // every member has died.
+            if birth_step <= step && counts.last() != Some(&0) {
+                counts.push(count);
+            }
+        }
+    }
+    /// Return the life table of each cohort, in the order of the birth steps. The tables of
+    /// cohorts with living members are incomplete.
+    pub fn life_tables(&self) -> Vec<LifeTable> {
+        (0..self.birth_steps.len())
+            .map(|cohort| LifeTable {
+                birth_step: self.birth_steps[cohort],
+                alive: self.alive[cohort].clone(),
+                offspring: self.offspring[cohort].clone(),
+            })
+            .collect()
+    }
+}
+
+/// This struct holds the life table of one cohort: the number of its members alive at each age
+/// (in steps since their birth, so age 0 is the whole cohort) and the number of offspring they
+/// produced at each age. Offspring produced at age `x` are born `x + 1` steps after their
+/// parents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LifeTable {
+    birth_step: u32,
+    alive: Vec<u32>,
+    offspring: Vec<u32>,
+}
+impl LifeTable {
+    /// Return the step the cohort was born in.
+    pub fn get_birth_step(&self) -> u32 {
+        self.birth_step
+    }
+    /// Return the number of members born (zero if the cohort has not been born yet).
+    pub fn size(&self) -> u32 {
+        self.alive.first().copied().unwrap_or(0)
+    }
+    /// Return the number of members alive at each age.
+    pub fn get_alive(&self) -> &Vec<u32> {
+        &self.alive
+    }
+    /// Return true if every member of the cohort has died.
+    pub fn is_complete(&self) -> bool {
+        self.alive.last() == Some(&0)
+    }
+    /// Return the survivorship `l(x)`: the proportion of the cohort alive at each age.
+    pub fn survivorship(&self) -> Vec<f64> {
+        let size = self.size() as f64;
+        self.alive
+            .iter()
+            .map(|x| match size > 0.0 {
+                true => *x as f64 / size,
+                false => 0.0,
+            })
+            .collect()
+    }
+    /// Return the fecundity `m(x)`: the mean number of offspring produced at each age per member
+    /// alive at that age (zero once none are alive).
+    pub fn fecundity(&self) -> Vec<f64> {
+        self.alive
+            .iter()
+            .enumerate()
+            .map(|(age, alive)| {
+                let offspring = self.offspring.get(age).copied().unwrap_or(0);
+                match *alive > 0 {
+                    true => offspring as f64 / *alive as f64,
+                    false => 0.0,
+                }
+            })
+            .collect()
+    }
+    /// Return the net reproductive rate `R0 = sum l(x) m(x)`, the mean lifetime number of
+    /// offspring per member.
+    pub fn net_reproductive_rate(&self) -> f64 {
+        self.survivorship()
+            .iter()
+            .zip(self.fecundity())
+            .map(|(l, m)| l * m)
+            .sum()
+    }
+    /// Return the realised generation time: the mean number of steps between the birth of a
+    /// member and the birth of its offspring, or `None` if the cohort produced no offspring.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     CohortTracker, IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.7).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![500.0].into(), table, vec![0.3]).unwrap();
+    /// let mut simulation = model.simulation(0, 2).with_cohort_tracker(CohortTracker::new(vec![1]));
+    /// simulation.run(60);
+    /// let cohort = &simulation.get_cohort_tracker().unwrap().life_tables()[0];
+    /// assert!(cohort.is_complete());
+    /// // About 0.3 / (1 - 0.7) = 1 offspring per member, at a mean of 1 / (1 - 0.7) steps.
+    /// assert!((cohort.net_reproductive_rate() - 1.0).abs() < 0.4);
+    /// assert!((cohort.generation_time().unwrap() - 3.33).abs() < 1.0);
+    /// ```
+    pub fn generation_time(&self) -> Option<f64> {
+        let net = self.net_reproductive_rate();
+        if net <= 0.0 {
+            return None;
+        }
+        let weighted: f64 = self
+            .survivorship()
+            .iter()
+            .zip(self.fecundity())
+            .enumerate()
+            .map(|(age, (l, m))| (age + 1) as f64 * l * m)
+            .sum();
+        Some(weighted / net)
+    }
+    /// Write the life table as CSV with the columns `age`, `alive`, `lx`, and `mx`.
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record(["age", "alive", "lx", "mx"])?;
+        for (age, ((alive, l), m)) in self
+            .alive
+            .iter()
+            .zip(self.survivorship())
+            .zip(self.fecundity())
+            .enumerate()
+        {
+            csv_writer.write_record([
+                age.to_string(),
+                alive.to_string(),
+                l.to_string(),
+                m.to_string(),
+            ])?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+impl IndividualSimulation<'_> {
+    /// Return the simulation following the cohorts of the tracker from now on.
+    pub fn with_cohort_tracker(mut self, tracker: CohortTracker) -> Self {
+        self.cohorts = Some(tracker);
+        self
+    }
+    /// Return the cohort tracker, if one was added.
+    pub fn get_cohort_tracker(&self) -> Option<&CohortTracker> {
+        self.cohorts.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        IndividualBasedModel, OffspringDistribution, StageTransition, TransitionTable,
+    };
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn fixed_life_cycle_gives_exact_life_table() {
+        // Every individual has two offspring a step and dies after breeding at age 3.
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0)
+            .unwrap()
+            .with_age_range(None, Some(2))
+            .unwrap()])
+        .unwrap();
+        let model = IndividualBasedModel::build(vec![1.0].into(), table, vec![0.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 2,
+                    breeding_probability: 1.0,
+                },
+            )
+            .unwrap();
+        let tracker = CohortTracker::new(vec![1, 2, 20]);
+        let mut simulation = model.simulation(0, 0).with_cohort_tracker(tracker);
+        simulation.run(6);
+        let tables = simulation.get_cohort_tracker().unwrap().life_tables();
+        assert_eq!(tables[0].get_alive(), &vec![2, 2, 2, 2, 0]);
+        assert_eq!(tables[1].size(), 6);
+        assert_eq!(tables[0].survivorship(), vec![1.0, 1.0, 1.0, 1.0, 0.0]);
+        assert_eq!(tables[0].fecundity(), vec![2.0, 2.0, 2.0, 2.0, 0.0]);
+        assert_eq!(tables[0].net_reproductive_rate(), 8.0);
+        assert_eq!(tables[0].generation_time(), Some(2.5));
+        assert!(tables[0].is_complete() && tables[1].is_complete());
+        assert!(!tables[2].is_complete());
+        assert_eq!((tables[2].size(), tables[2].generation_time()), (0, None));
+        let mut csv = Vec::new();
+        tables[0].write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("age,alive,lx,mx\n0,2,1,2\n"));
+    }
+}
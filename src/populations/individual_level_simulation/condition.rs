@@ -0,0 +1,300 @@
+//! This module contains body condition for the individual-based model: an energy reserve carried by each individual that is topped up by food intake, drawn down by maintenance, crowding, poor environments, and reproduction, and that can in turn set the probability of surviving and breeding. This gives a simple energy-budget model within the existing life cycle, in which a bad year affects the following years through the condition of the survivors.
+use super::IndividualBasedModel;
+use crate::populations::population_level_simulation::{CovariateSeries, CovariateTrack};
+use crate::statistics::logistic;
+
+/// This enum describes how a vital rate depends on the body condition `c` of an individual,
+/// as a probability (of surviving a step, or a multiplier of the mean number of offspring):
+/// - `Logistic`: `1 / (1 + exp(-slope * (c - midpoint)))`, rising smoothly from 0 to 1 around
+///   `midpoint` for a positive `slope`.
+/// - `Threshold`: 1 if the condition is at least `minimum`, otherwise 0, as for capital breeders
+///   that skip breeding below a reserve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConditionResponse {
+    Logistic { midpoint: f64, slope: f64 },
+    Threshold { minimum: f64 },
+}
+impl ConditionResponse {
+    /// Check that the parameters of the response are valid.
+    /// # Errors
+    /// Will return `Err<'static str>` if any parameter is not finite.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let valid = match *self {
+            ConditionResponse::Logistic { midpoint, slope } => {
+                midpoint.is_finite() && slope.is_finite()
+            }
+            ConditionResponse::Threshold { minimum } => minimum.is_finite(),
+        };
+        match valid {
+            true => Ok(()),
+            false => Err("Condition responses need finite parameters."),
+        }
+    }
+    /// Return the probability at a body condition.
+    pub fn probability(&self, condition: f64) -> f64 {
+        match *self {
+            ConditionResponse::Logistic { midpoint, slope } => {
+                logistic(slope * (condition - midpoint))
+            }
+            ConditionResponse::Threshold { minimum } => match condition >= minimum {
+                true => 1.0,
+                false => 0.0,
+            },
+        }
+    }
+}
+
+/// This struct holds the dynamics of body condition. Every individual starts (as a founder or at
+/// birth) at the `initial` condition, and at the start of each step its condition changes by
+///
+/// ```text
+/// intake - maintenance - crowding * N + environment_effect * x(t)
+/// ```
+///
+/// where `N` is the number of individuals alive and `x(t)` is the value of an environmental
+/// covariate that step, and is kept between zero and `maximum`. Breeding then costs
+/// `reproduction_cost` per offspring, down to a condition of zero. Optional
+/// [`ConditionResponse`]s make survival and the mean number of offspring depend on the condition.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{BodyCondition, ConditionResponse};
+/// let condition = BodyCondition::build(5.0, 10.0, 1.0, 0.5)
+///     .unwrap()
+///     .with_crowding(0.01)
+///     .unwrap()
+///     .with_survival(ConditionResponse::Logistic { midpoint: 1.0, slope: 3.0 })
+///     .unwrap();
+/// // With 100 individuals, intake no longer covers maintenance.
+/// assert_eq!(condition.updated(5.0, 100, 0), 4.5);
+/// assert!(condition.survival(0.0) < 0.05);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BodyCondition {
+    initial: f64,
+    maximum: f64,
+    intake: f64,
+    maintenance: f64,
+    crowding: f64,
+    environment_effect: f64,
+    environment: CovariateTrack,
+    reproduction_cost: f64,
+    survival: Option<ConditionResponse>,
+    breeding: Option<ConditionResponse>,
+}
+impl BodyCondition {
+    /// Build body condition dynamics from the initial and maximum condition and the intake and
+    /// maintenance cost per step, with no effect of crowding or the environment.
+    /// # Errors
+    /// Will return `Err<'static str>` if any value is negative or not finite, or the initial
+    /// condition is above the maximum.
+    pub fn build(
+        initial: f64,
+        maximum: f64,
+        intake: f64,
+        maintenance: f64,
+    ) -> Result<BodyCondition, &'static str> {
+        if [initial, maximum, intake, maintenance]
+            .iter()
+            .any(|x| !x.is_finite() || *x < 0.0)
+        {
+            return Err("Body condition parameters must be finite and not negative.");
+        }
+        if initial > maximum {
+            return Err("The initial body condition cannot be above the maximum.");
+        }
+        Ok(BodyCondition {
+            initial,
+            maximum,
+            intake,
+            maintenance,
+            crowding: 0.0,
+            environment_effect: 0.0,
+            environment: CovariateTrack::default(),
+            reproduction_cost: 0.0,
+            survival: None,
+            breeding: None,
+        })
+    }
+    /// Return the dynamics with condition reduced by `crowding` for each individual alive.
+    /// # Errors
+    /// Will return `Err<'static str>` if the crowding cost is negative or not finite.
+    pub fn with_crowding(mut self, crowding: f64) -> Result<Self, &'static str> {
+        if !crowding.is_finite() || crowding < 0.0 {
+            return Err("The crowding cost must be finite and not negative.");
+        }
+        self.crowding = crowding;
+        Ok(self)
+    }
+    /// Return the dynamics with condition changed each step by `effect` times the value of a
+    /// covariate in the series (the last value for steps beyond the series).
+    /// # Errors
+    /// Will return `Err<'static str>` if the effect is not finite, or the series is empty or has
+    /// no column for the covariate.
+    pub fn with_environment(
+        mut self,
+        series: &CovariateSeries,
+        covariate: &str,
+        effect: f64,
+    ) -> Result<Self, &'static str> {
+        if !effect.is_finite() {
+            return Err("The environmental effect on body condition must be finite.");
+        }
+        self.environment = CovariateTrack::from_series(series, covariate)?;
+        self.environment_effect = effect;
+        Ok(self)
+    }
+    /// Return the dynamics with each offspring costing its parent `cost` condition.
+    /// # Errors
+    /// Will return `Err<'static str>` if the cost is negative or not finite.
+    pub fn with_reproduction_cost(mut self, cost: f64) -> Result<Self, &'static str> {
+        if !cost.is_finite() || cost < 0.0 {
+            return Err("The reproduction cost must be finite and not negative.");
+        }
+        self.reproduction_cost = cost;
+        Ok(self)
+    }
+    /// Return the dynamics with the probability of surviving each step multiplied by the
+    /// response to condition.
+    /// # Errors
+    /// Will return `Err<'static str>` if the response is not valid.
+    pub fn with_survival(mut self, response: ConditionResponse) -> Result<Self, &'static str> {
+        response.validate()?;
+        self.survival = Some(response);
+        Ok(self)
+    }
+    /// Return the dynamics with the mean number of offspring multiplied by the response to
+    /// condition.
+    /// # Errors
+    /// Will return `Err<'static str>` if the response is not valid.
+    pub fn with_breeding(mut self, response: ConditionResponse) -> Result<Self, &'static str> {
+        response.validate()?;
+        self.breeding = Some(response);
+        Ok(self)
+    }
+    /// Return the condition of founders and newborns.
+    pub fn get_initial(&self) -> f64 {
+        self.initial
+    }
+    /// Return the maximum condition.
+    pub fn get_maximum(&self) -> f64 {
+        self.maximum
+    }
+    /// Return the condition lost per offspring.
+    pub fn get_reproduction_cost(&self) -> f64 {
+        self.reproduction_cost
+    }
+    /// Return the condition after a step starting from `condition`, with `population`
+    /// individuals alive, after `steps_taken` steps.
+    pub fn updated(&self, condition: f64, population: usize, steps_taken: u32) -> f64 {
+        let environment = self.environment.value(steps_taken).unwrap_or(0.0);
+        let change = self.intake - self.maintenance - self.crowding * population as f64
+            + self.environment_effect * environment;
+        (condition + change).clamp(0.0, self.maximum)
+    }
+    /// Return the probability of surviving a step at a condition (one without a survival
+    /// response).
+    pub fn survival(&self, condition: f64) -> f64 {
+        self.survival.map_or(1.0, |x| x.probability(condition))
+    }
+    /// Return the multiplier of the mean number of offspring at a condition (one without a
+    /// breeding response).
+    pub fn breeding(&self, condition: f64) -> f64 {
+        self.breeding.map_or(1.0, |x| x.probability(condition))
+    }
+}
+
+impl IndividualBasedModel {
+    /// Return the model with every individual carrying a body condition that follows the given
+    /// dynamics. Each step, condition is updated for every individual before reproduction, the
+    /// number of offspring is drawn with its mean scaled by the breeding response, and an
+    /// individual must survive the condition's survival response before any mortality hazard
+    /// and the transition table.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     BodyCondition, ConditionResponse, IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+    /// // Food runs short above 50 individuals, and starving individuals die.
+    /// let condition = BodyCondition::build(3.0, 5.0, 1.0, 0.5).unwrap()
+    ///     .with_crowding(0.01).unwrap()
+    ///     .with_survival(ConditionResponse::Threshold { minimum: 0.5 }).unwrap();
+    /// let model = IndividualBasedModel::build(vec![200.0].into(), table, vec![0.0])
+    ///     .unwrap()
+    ///     .with_body_condition(condition);
+    /// let trajectory = model.simulation(0, 1).run(5);
+    /// assert_eq!(trajectory[0].total(), 200.0);
+    /// assert_eq!(trajectory[4].total(), 0.0);
+    /// ```
+    pub fn with_body_condition(mut self, condition: BodyCondition) -> Self {
+        self.body_condition = Some(condition);
+        self
+    }
+    /// Return the body condition dynamics of the model, if any.
+    pub fn get_body_condition(&self) -> Option<&BodyCondition> {
+        self.body_condition.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        OffspringDistribution, StageTransition, TransitionTable,
+    };
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn condition_follows_the_environment() {
+        let series =
+            CovariateSeries::build(vec![String::from("rain")], vec![vec![1.0], vec![-2.0]])
+                .unwrap();
+        let condition = BodyCondition::build(2.0, 4.0, 1.0, 1.0)
+            .unwrap()
+            .with_environment(&series, "rain", 1.5)
+            .unwrap();
+        assert_eq!(condition.updated(2.0, 10, 0), 3.5);
+        assert_eq!(condition.updated(3.5, 10, 0), 4.0);
+        assert_eq!(condition.updated(2.0, 10, 1), 0.0);
+        assert_eq!(condition.updated(2.0, 10, 7), 0.0);
+        assert!(condition
+            .clone()
+            .with_environment(&series, "temperature", 1.0)
+            .is_err());
+        assert!(BodyCondition::build(5.0, 4.0, 1.0, 1.0).is_err());
+        assert!(condition
+            .with_breeding(ConditionResponse::Threshold { minimum: f64::NAN })
+            .is_err());
+    }
+    #[test]
+    fn reproduction_costs_condition() {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        // A clutch of two leaves 0.5 of a reserve of 2, so individuals breed every other step.
+        let condition = BodyCondition::build(2.0, 2.0, 1.0, 0.0)
+            .unwrap()
+            .with_reproduction_cost(0.75)
+            .unwrap()
+            .with_breeding(ConditionResponse::Threshold { minimum: 2.0 })
+            .unwrap();
+        let model = IndividualBasedModel::build(vec![10.0].into(), table, vec![0.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 2,
+                    breeding_probability: 1.0,
+                },
+            )
+            .unwrap()
+            .with_newborn_stage(0)
+            .unwrap()
+            .with_body_condition(condition);
+        let mut simulation = model.simulation(0, 3);
+        let trajectory = simulation.run(3);
+        assert_eq!(trajectory[0].total(), 30.0);
+        // Founders are recovering, while newborns start with a full reserve and breed at once.
+        assert_eq!(trajectory[1].total(), 70.0);
+        assert_eq!(trajectory[2].total(), 170.0);
+        let founder = &simulation.get_individuals()[0];
+        assert_eq!(founder.get_condition(), 0.5);
+    }
+}
@@ -0,0 +1,160 @@
+//! This module contains fixed or drawn development times for lifestages of the individual-based model. An individual entering such a lifestage draws how many steps it will spend there, and advances only once that time has passed, instead of leaving with the same probability every step as the transition table implies.
+use super::{Individual, IndividualBasedModel};
+use crate::populations::population_level_simulation::StageDuration;
+use rand::Rng;
+
+impl IndividualBasedModel {
+    /// Return the model with individuals spending a duration drawn from `duration` in a stage
+    /// before moving to `next_stage`. The duration is drawn when an individual enters the stage
+    /// (founders draw it at the start of a replicate). Each step, a member of the stage survives
+    /// with the total probability of the transitions out of the stage in the transition table,
+    /// whatever their destinations; a survivor that has completed its duration moves to
+    /// `next_stage`, and the others stay. The matching matrix model is built by
+    /// [`DecomposedMatrix::expand_stage_duration`](crate::populations::population_level_simulation::DecomposedMatrix::expand_stage_duration).
+    /// # Errors
+    /// Will return `Err<'static str>` if either stage does not exist, they are the same, or the
+    /// duration is not valid.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::populations::population_level_simulation::StageDuration;
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap(),
+    ///     StageTransition::new(1, 1, 1.0).unwrap()]).unwrap();
+    /// // Larvae take exactly three steps to metamorphose.
+    /// let model = IndividualBasedModel::build(vec![10.0, 0.0].into(), table, vec![0.0, 0.0])
+    ///     .unwrap()
+    ///     .with_stage_duration(0, StageDuration::Fixed { steps: 3 }, 1)
+    ///     .unwrap();
+    /// let trajectory = model.simulation(0, 1).run(3);
+    /// assert_eq!(trajectory[1].get_vector(), &vec![10.0, 0.0]);
+    /// assert_eq!(trajectory[2].get_vector(), &vec![0.0, 10.0]);
+    /// ```
+    pub fn with_stage_duration(
+        mut self,
+        stage: u8,
+        duration: StageDuration,
+        next_stage: u8,
+    ) -> Result<Self, &'static str> {
+        let stages = self.reproduction.len();
+        if stage as usize >= stages || next_stage as usize >= stages {
+            return Err("The stage does not exist.");
+        }
+        if stage == next_stage {
+            return Err("Individuals must move to another stage after its duration.");
+        }
+        duration.validate()?;
+        self.stage_durations[stage as usize] = Some((duration, next_stage));
+        Ok(self)
+    }
+    /// Return the duration of each stage and the stage individuals move to after it, if any.
+    pub fn get_stage_durations(&self) -> &Vec<Option<(StageDuration, u8)>> {
+        &self.stage_durations
+    }
+    /// Draw how long an individual entering a stage will spend in it (zero for a stage without a
+    /// duration).
+    pub(super) fn draw_duration<R: Rng>(&self, stage: u8, rng: &mut R) -> u16 {
+        match &self.stage_durations[stage as usize] {
+            Some((duration, _)) => duration.sample(rng),
+            None => 0,
+        }
+    }
+    /// Return the stage an individual moves to in the step after `steps_taken` steps, given a
+    /// uniform random number `draw` between 0 and 1, or `None` if it dies.
+    pub(super) fn next_stage(
+        &self,
+        individual: &Individual,
+        draw: f64,
+        steps_taken: u32,
+    ) -> Option<u8> {
+        let (stage, age) = (individual.lifestage, individual.age);
+        if let Some(dormancy) = self
+            .dormancy
+            .iter()
+            .find(|x| x.get_stage() == stage as usize)
+        {
+            let survival = dormancy.get_survival();
+            return match draw < survival * dormancy.germination(steps_taken) {
+                true => Some(dormancy.get_emergence_stage() as u8),
+                false => (draw < survival).then_some(stage),
+            };
+        }
+        match &self.stage_durations[stage as usize] {
+            None => self.transitions.next_stage(stage, age, draw),
+            Some((_, next_stage)) => (draw < self.transitions.survival(stage, age)).then(|| {
+                match individual.stage_time + 1 >= individual.stage_duration {
+                    true => *next_stage,
+                    false => stage,
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{StageTransition, TransitionTable};
+    use crate::populations::population_level_simulation::{
+        DecomposedMatrix, PopulationMatrix, PopulationVector,
+    };
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn drawn_durations_match_expanded_matrix() {
+        let table = TransitionTable::build(vec![
+            StageTransition::new(0, 1, 0.5).unwrap(),
+            StageTransition::new(1, 1, 0.8).unwrap(),
+            StageTransition::new(2, 2, 0.6).unwrap(),
+        ])
+        .unwrap();
+        let duration = StageDuration::Discrete {
+            probabilities: vec![0.5, 0.0, 0.5],
+        };
+        let model =
+            IndividualBasedModel::build(vec![0.0, 0.0, 50.0].into(), table, vec![0.0, 0.0, 1.5])
+                .unwrap()
+                .with_stage_duration(1, duration.clone(), 2)
+                .unwrap();
+        let output = model.projection(8, 400, 5);
+        let mean: f64 = output.total_population().iter().map(|x| x[7]).sum::<f64>() / 400.0;
+        let decomposed = DecomposedMatrix::build(
+            PopulationMatrix::build(vec![
+                vec![0.0, 0.0, 0.0],
+                vec![0.5, 0.8, 0.0],
+                vec![0.0, 0.0, 0.6],
+            ])
+            .unwrap(),
+            PopulationMatrix::build(vec![
+                vec![0.0, 0.0, 1.5],
+                vec![0.0, 0.0, 0.0],
+                vec![0.0, 0.0, 0.0],
+            ])
+            .unwrap(),
+        )
+        .unwrap();
+        let matrix = decomposed
+            .expand_stage_duration(1, &duration, 2)
+            .unwrap()
+            .get_matrix();
+        let mut expected = vec![0.0; 5];
+        expected[4] = 50.0;
+        let mut expected: PopulationVector = expected.into();
+        for _ in 0..8 {
+            expected = &matrix * &expected;
+        }
+        assert!((mean - expected.total()).abs() / expected.total() < 0.05);
+        let mut simulation = model.simulation(0, 2);
+        simulation.run(4);
+        assert!(simulation
+            .get_individuals()
+            .iter()
+            .all(|x| x.get_lifestage() != 1 || x.get_time_in_stage() < 3));
+        assert!(model
+            .clone()
+            .with_stage_duration(1, duration.clone(), 1)
+            .is_err());
+        assert!(model.with_stage_duration(3, duration, 1).is_err());
+    }
+}
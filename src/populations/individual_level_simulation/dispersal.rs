@@ -0,0 +1,400 @@
+//! This module contains dispersal between the populations of an [`IndividualMetapopulation`], treated as habitat patches (such as the cells of a [`crate::imports::HabitatRaster`]). The probability that an individual leaves its patch can depend on its phenotype for the [`super::QuantitativeTrait`], and each patch's model can select towards its own optimum, so gene flow and local adaptation across the landscape can be simulated together.
+use super::{IndividualMetapopulation, LifeEvent};
+use crate::populations::patch_occupancy::HabitatTimeline;
+use crate::statistics::{logistic, logit};
+use rand::Rng;
+
+/// This struct describes dispersal between patches. Each step, an individual of a dispersing
+/// stage leaves its patch with probability
+/// `1 / (1 + exp(-(logit(probability) + slope * z)))` for its phenotype `z` (zero without a
+/// trait), so `probability` applies at a phenotype of zero and a positive slope makes
+/// individuals with larger phenotypes more likely to leave. A disperser settles in one of the
+/// other patches, chosen at random, or, when the patches have locations, with weights
+/// `exp(-distance / dispersal_distance)` so that nearby patches receive more dispersers.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::TraitDispersal;
+/// let dispersal = TraitDispersal::build(0.1, 2.0)
+///     .unwrap()
+///     .of_stage(0)
+///     .with_locations(vec![(0.0, 0.0), (1.0, 0.0), (5.0, 0.0)], 1.0)
+///     .unwrap();
+/// assert!((dispersal.probability(0.0) - 0.1).abs() < 1e-12);
+/// assert!(dispersal.probability(1.0) > 0.4);
+/// let weights = dispersal.destination_weights(0, 3);
+/// assert!(weights[1] > 50.0 * weights[2] && weights[0] == 0.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraitDispersal {
+    probability: f64,
+    slope: f64,
+    stage: Option<u8>,
+    locations: Vec<(f64, f64)>,
+    dispersal_distance: f64,
+}
+impl TraitDispersal {
+    /// Build dispersal with the given probability of leaving at a phenotype of zero and slope on
+    /// the logit scale, for individuals of every stage.
+    /// # Errors
+    /// Will return `Err<'static str>` if the probability is not between 0 and 1 or the slope is
+    /// not finite.
+    pub fn build(probability: f64, slope: f64) -> Result<TraitDispersal, &'static str> {
+        if !(0.0..=1.0).contains(&probability) || !slope.is_finite() {
+            return Err("Dispersal needs a probability between 0 and 1 and a finite slope.");
+        }
+        Ok(TraitDispersal {
+            probability,
+            slope,
+            stage: None,
+            locations: Vec::new(),
+            dispersal_distance: f64::INFINITY,
+        })
+    }
+    /// Return the dispersal applying only to individuals of the given stage.
+    pub fn of_stage(mut self, stage: u8) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+    /// Return the dispersal with the patches at the given locations (one per population), and
+    /// destinations weighted by distance.
+    /// # Errors
+    /// Will return `Err<'static str>` if a coordinate is not finite or the dispersal distance is
+    /// not positive.
+    pub fn with_locations(
+        mut self,
+        locations: Vec<(f64, f64)>,
+        dispersal_distance: f64,
+    ) -> Result<Self, &'static str> {
+        if locations
+            .iter()
+            .any(|(x, y)| !x.is_finite() || !y.is_finite())
+        {
+            return Err("Patch locations must be finite.");
+        }
+        if dispersal_distance.is_nan() || dispersal_distance <= 0.0 {
+            return Err("The dispersal distance must be positive.");
+        }
+        self.locations = locations;
+        self.dispersal_distance = dispersal_distance;
+        Ok(self)
+    }
+    /// Return the stage that disperses, or `None` if every stage does.
+    pub fn get_stage(&self) -> Option<u8> {
+        self.stage
+    }
+    /// Return the locations of the patches (empty if destinations are chosen uniformly).
+    pub fn get_locations(&self) -> &Vec<(f64, f64)> {
+        &self.locations
+    }
+    /// Return the probability that an individual with the given phenotype leaves its patch.
+    pub fn probability(&self, phenotype: f64) -> f64 {
+        if self.slope == 0.0 || self.probability <= 0.0 || self.probability >= 1.0 {
+            return self.probability;
+        }
+        logistic(logit(self.probability) + self.slope * phenotype)
+    }
+    /// Return the relative chance that a disperser from patch `from` settles in each of
+    /// `patches` patches (zero for its own patch). With locations, the weights are divided by
+    /// that of the nearest other patch, which therefore has a weight of one, so patches much
+    /// further apart than the dispersal distance still receive dispersers instead of every
+    /// weight rounding to zero.
+    pub fn destination_weights(&self, from: usize, patches: usize) -> Vec<f64> {
+        if self.locations.is_empty() {
+            return (0..patches).map(|to| f64::from(to != from)).collect();
+        }
+        let (x0, y0) = self.locations[from];
+        let distances: Vec<f64> = self.locations[..patches]
+            .iter()
+            .map(|(x1, y1)| (x1 - x0).hypot(y1 - y0))
+            .collect();
+        let nearest = distances
+            .iter()
+            .enumerate()
+            .filter(|(to, _)| *to != from)
+            .map(|(_, x)| *x)
+            .fold(f64::INFINITY, f64::min);
+        distances
+            .iter()
+            .enumerate()
+            .map(|(to, distance)| match to == from {
+                true => 0.0,
+                false => (-(distance - nearest) / self.dispersal_distance).exp(),
+            })
+            .collect()
+    }
+}
+
+impl IndividualMetapopulation<'_> {
+    /// Return the metapopulation with individuals dispersing between its populations. Dispersal
+    /// happens each step after every population has stepped and before any translocations, and
+    /// dispersers keep their stage, age, genotype, and trait values, with their ancestors copied
+    /// into the pedigree of their new population.
+    /// # Errors
+    /// Will return `Err<'static str>` if the dispersal has locations but not one per population,
+    /// or the stage does not exist in every population.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, IndividualMetapopulation, StageTransition, TraitDispersal, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+    /// let full = IndividualBasedModel::build(vec![100.0].into(), table.clone(), vec![0.0]).unwrap();
+    /// let empty = IndividualBasedModel::build(vec![0.0].into(), table, vec![0.0]).unwrap();
+    /// let mut metapopulation = IndividualMetapopulation::new(
+    ///     vec![full.simulation(0, 1), empty.simulation(0, 1)], 1)
+    ///     .unwrap()
+    ///     .with_dispersal(TraitDispersal::build(0.5, 0.0).unwrap())
+    ///     .unwrap();
+    /// metapopulation.run(20);
+    /// let [first, second] = [0, 1].map(|x| metapopulation.state()[x].total());
+    /// assert_eq!(first + second, 100.0);
+    /// assert!((first - second).abs() < 30.0);
+    /// ```
+    pub fn with_dispersal(mut self, dispersal: TraitDispersal) -> Result<Self, &'static str> {
+        if !dispersal.locations.is_empty() && dispersal.locations.len() != self.populations.len() {
+            return Err("There must be one patch location per population.");
+        }
+        if dispersal.stage.is_some_and(|stage| {
+            self.populations
+                .iter()
+                .any(|x| stage as usize >= x.model.reproduction.len())
+        }) {
+            return Err("The dispersing stage does not exist in every population.");
+        }
+        self.dispersal = Some(dispersal);
+        Ok(self)
+    }
+    /// Return the metapopulation with habitat quality changing over time, one quality per
+    /// population. Dispersers then settle in each patch in proportion to its quality during the
+    /// step, so a patch whose habitat has been lost receives none and a restored patch is
+    /// recolonized; survival and reproduction within a patch still follow its own model.
+    /// # Errors
+    /// Will return `Err<'static str>` if the timeline does not have one quality per population.
+    pub fn with_habitat_timeline(
+        mut self,
+        timeline: HabitatTimeline,
+    ) -> Result<Self, &'static str> {
+        if timeline.patch_count() != self.populations.len() {
+            return Err("The habitat timeline must have one quality per population.");
+        }
+        self.habitat = Some(timeline);
+        Ok(self)
+    }
+    /// Return each movement of dispersers so far: the step after which it happened, the source
+    /// and destination populations, and the number of individuals moved.
+    pub fn get_dispersed(&self) -> &Vec<(u32, usize, usize, u32)> {
+        &self.dispersed
+    }
+    /// Return the mean breeding value of the individuals alive now in each population, for
+    /// following divergence between patches.
+    pub fn mean_breeding_values(&self) -> Vec<f64> {
+        self.populations
+            .iter()
+            .map(|x| x.mean_breeding_value())
+            .collect()
+    }
+    /// Move the dispersers of this step between the populations.
+    pub(super) fn disperse(&mut self) {
+        let Some(dispersal) = &self.dispersal else {
+            return;
+        };
+        let patches = self.populations.len();
+        let quality = self
+            .habitat
+            .as_ref()
+            .map(|x| x.quality(self.steps_taken.saturating_sub(1)));
+        let mut moving = vec![vec![Vec::new(); patches]; patches];
+        for (from, population) in self.populations.iter().enumerate() {
+            let mut weights = dispersal.destination_weights(from, patches);
+            if let Some(quality) = &quality {
+                weights.iter_mut().zip(quality).for_each(|(x, q)| *x *= q);
+            }
+            let total: f64 = weights.iter().sum();
+            if total <= 0.0 {
+                continue;
+            }
+            for individual in &population.individuals {
+                if dispersal.stage.is_some_and(|x| x != individual.lifestage)
+                    || self.rng.gen::<f64>() >= dispersal.probability(individual.phenotype)
+                {
+                    continue;
+                }
+                let mut draw = self.rng.gen::<f64>() * total;
+                let to = weights
+                    .iter()
+                    .position(|x| {
+                        draw -= x;
+                        draw < 0.0
+                    })
+                    .unwrap_or_else(|| weights.iter().rposition(|x| *x > 0.0).unwrap());
+                moving[from][to].push(individual.id);
+            }
+        }
+        let mut arrivals = vec![Vec::new(); patches];
+        for (from, destinations) in moving.into_iter().enumerate() {
+            for (to, ids) in destinations.into_iter().enumerate() {
+                if ids.is_empty() {
+                    continue;
+                }
+                let lineage = self.populations[from]
+                    .get_pedigree()
+                    .map_or_else(Vec::new, |x| x.lineage(&ids));
+                self.populations[from].record_movement(&ids, LifeEvent::Dispersal { from, to });
+                let individuals = self.populations[from].remove_individuals(&ids);
+                self.dispersed
+                    .push((self.steps_taken, from, to, individuals.len() as u32));
+                arrivals[to].push((from, individuals, lineage));
+            }
+        }
+        for (to, groups) in arrivals.into_iter().enumerate() {
+            for (from, individuals, lineage) in groups {
+                let ids = self.populations[to].add_individuals(individuals, from, lineage);
+                self.populations[to].record_movement(&ids, LifeEvent::Dispersal { from, to });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        Genotype, IndividualBasedModel, Locus, OffspringDistribution, QuantitativeTrait,
+        SelectedRate, StageTransition, TransitionTable,
+    };
+    use crate::simulation::Simulation;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn patch(optimum: f64) -> IndividualBasedModel {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.0).unwrap()]).unwrap();
+        let mut rng = StdRng::seed_from_u64(4);
+        let genotypes = (0..200)
+            .map(|_| {
+                Genotype::new(
+                    (0..4)
+                        .map(|_| [Some(rng.gen_range(0..2)), Some(rng.gen_range(0..2))])
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let size = QuantitativeTrait::build((0..4).collect(), vec![vec![-0.25, 0.25]; 4], 0.1)
+            .unwrap()
+            .with_selection(SelectedRate::Fecundity, 0.7, optimum)
+            .unwrap();
+        IndividualBasedModel::build(vec![200.0].into(), table, vec![0.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 4,
+                    breeding_probability: 0.5,
+                },
+            )
+            .unwrap()
+            .with_founder_genotypes(
+                (0..4).map(|x| Locus::new(&x.to_string())).collect(),
+                genotypes,
+            )
+            .unwrap()
+            .with_quantitative_trait(size)
+            .unwrap()
+    }
+
+    #[test]
+    fn patches_adapt_to_their_optima() {
+        let (low, high) = (patch(-1.0), patch(1.0));
+        let mut metapopulation =
+            IndividualMetapopulation::new(vec![low.simulation(0, 3), high.simulation(1, 3)], 5)
+                .unwrap()
+                .with_dispersal(TraitDispersal::build(0.02, 0.0).unwrap())
+                .unwrap();
+        let start = metapopulation.mean_breeding_values();
+        assert_eq!(start[0], start[1]);
+        metapopulation.run(6);
+        let adapted = metapopulation.mean_breeding_values();
+        assert!(adapted[0] < start[0] - 0.3 && adapted[1] > start[1] + 0.3);
+        assert!(!metapopulation.get_dispersed().is_empty());
+    }
+    #[test]
+    fn bold_individuals_leave() {
+        let model = patch(0.0);
+        let empty = IndividualBasedModel::build(
+            vec![0.0].into(),
+            TransitionTable::build(Vec::new()).unwrap(),
+            vec![0.0],
+        )
+        .unwrap()
+        .with_founder_genotypes(model.get_loci().clone(), Vec::new())
+        .unwrap();
+        let dispersal = TraitDispersal::build(0.2, 3.0).unwrap();
+        let mut metapopulation =
+            IndividualMetapopulation::new(vec![model.simulation(0, 2), empty.simulation(0, 2)], 5)
+                .unwrap()
+                .with_dispersal(dispersal.clone())
+                .unwrap();
+        let mean_phenotype = metapopulation.get_populations()[0].mean_phenotype();
+        metapopulation.disperse();
+        let arrived = &metapopulation.get_populations()[1];
+        assert!(arrived.state().total() > 0.0);
+        assert!(arrived.mean_phenotype() > mean_phenotype + 0.2);
+        assert!(TraitDispersal::build(1.5, 0.0).is_err());
+        let metapopulation =
+            IndividualMetapopulation::new(vec![model.simulation(0, 2), empty.simulation(0, 2)], 5)
+                .unwrap();
+        assert!(metapopulation
+            .with_dispersal(dispersal.with_locations(vec![(0.0, 0.0)], 1.0).unwrap())
+            .is_err());
+    }
+    #[test]
+    fn distant_patches_and_missing_stages() {
+        let far = TraitDispersal::build(0.5, 0.0)
+            .unwrap()
+            .with_locations(vec![(0.0, 0.0), (1e4, 0.0), (1e4 + 1.0, 0.0)], 1.0)
+            .unwrap();
+        let weights = far.destination_weights(0, 3);
+        assert_eq!(weights[1], 1.0);
+        assert!((weights[2] - (-1.0f64).exp()).abs() < 1e-12);
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        let model =
+            IndividualBasedModel::build(vec![10.0, 0.0].into(), table, vec![0.0, 0.0]).unwrap();
+        let metapopulation =
+            IndividualMetapopulation::new(vec![model.simulation(0, 1), model.simulation(1, 1)], 1)
+                .unwrap();
+        assert!(metapopulation
+            .with_dispersal(TraitDispersal::build(0.5, 0.0).unwrap().of_stage(2))
+            .is_err());
+    }
+    #[test]
+    fn dispersers_avoid_lost_habitat() {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        let full =
+            IndividualBasedModel::build(vec![50.0].into(), table.clone(), vec![0.0]).unwrap();
+        let empty = IndividualBasedModel::build(vec![0.0].into(), table, vec![0.0]).unwrap();
+        let timeline = HabitatTimeline::build(vec![(0, vec![1.0, 1.0, 0.0])]).unwrap();
+        let simulations = || {
+            vec![
+                full.simulation(0, 1),
+                empty.simulation(0, 1),
+                empty.simulation(0, 1),
+            ]
+        };
+        let mut metapopulation = IndividualMetapopulation::new(simulations(), 2)
+            .unwrap()
+            .with_dispersal(TraitDispersal::build(1.0, 0.0).unwrap())
+            .unwrap()
+            .with_habitat_timeline(timeline)
+            .unwrap();
+        metapopulation.disperse();
+        let totals: Vec<f64> = metapopulation
+            .get_populations()
+            .iter()
+            .map(|x| x.state().total())
+            .collect();
+        assert_eq!(totals, vec![0.0, 50.0, 0.0]);
+        let two = HabitatTimeline::build(vec![(0, vec![1.0, 1.0])]).unwrap();
+        assert!(IndividualMetapopulation::new(simulations(), 2)
+            .unwrap()
+            .with_habitat_timeline(two)
+            .is_err());
+    }
+}
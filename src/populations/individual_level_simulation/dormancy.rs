@@ -0,0 +1,97 @@
+//! This module contains dormant stages of the individual-based model, such as seed banks and resting eggs. Dormant individuals follow their [`Dormancy`] instead of the transition table, surviving in the bank and re-entering the active population when the germination trigger allows, and they count towards the population, so a replicate persisting only as a seed bank is not extinct.
+use super::IndividualBasedModel;
+use crate::populations::population_level_simulation::Dormancy;
+
+impl IndividualBasedModel {
+    /// Return the model with a dormant stage. Each step, a dormant individual survives with the
+    /// survival of the dormancy and then either germinates into the emergence stage, with the
+    /// germination probability of that step, or stays dormant; the transition table and any
+    /// stage duration of the dormant stage are not used.
+    /// # Errors
+    /// Will return `Err<'static str>` if either stage does not exist.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::populations::population_level_simulation::Dormancy;
+    /// // Annual plants die after seeding, so the population survives winters only as seeds.
+    /// let table = TransitionTable::build(vec![StageTransition::new(1, 1, 0.0).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![200.0, 0.0].into(), table, vec![0.0, 3.0])
+    ///     .unwrap()
+    ///     .with_dormancy(Dormancy::build(0, 1, 0.8, 0.3).unwrap())
+    ///     .unwrap();
+    /// let output = model.projection(5, 20, 1);
+    /// assert!(output.extinction_probability(0.0) < 0.5);
+    /// ```
+    pub fn with_dormancy(mut self, dormancy: Dormancy) -> Result<Self, &'static str> {
+        let stages = self.reproduction.len();
+        if dormancy.get_stage() >= stages || dormancy.get_emergence_stage() >= stages {
+            return Err("The stage does not exist.");
+        }
+        self.dormancy
+            .retain(|x| x.get_stage() != dormancy.get_stage());
+        self.dormancy.push(dormancy);
+        Ok(self)
+    }
+    /// Return the dormant stages of the model.
+    pub fn get_dormancy(&self) -> &Vec<Dormancy> {
+        &self.dormancy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{StageTransition, TransitionTable};
+    use crate::populations::population_level_simulation::{CovariateSeries, DeterministicPva};
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn seeds_wait_for_the_trigger() {
+        let rain = CovariateSeries::build(
+            vec![String::from("rain")],
+            vec![vec![0.0], vec![0.0], vec![1.0]],
+        )
+        .unwrap();
+        let dormancy = Dormancy::build(0, 1, 0.9, 0.0).unwrap();
+        let table = TransitionTable::build(vec![StageTransition::new(1, 1, 0.5).unwrap()]).unwrap();
+        let model = IndividualBasedModel::build(vec![2000.0, 0.0].into(), table, vec![0.0, 0.0])
+            .unwrap()
+            .with_dormancy(dormancy)
+            .unwrap();
+        let trajectory = model.simulation(0, 3).run(2);
+        assert_eq!(trajectory[1].get_vector()[1], 0.0);
+        assert!((trajectory[1].total() - 2000.0 * 0.81).abs() < 60.0);
+        let triggered = Dormancy::build(0, 1, 0.9, 0.5)
+            .unwrap()
+            .with_trigger(&rain, "rain", 3.0)
+            .unwrap();
+        let model = model.with_dormancy(triggered.clone()).unwrap();
+        assert_eq!(model.get_dormancy().len(), 1);
+        let output = model.projection(3, 100, 2);
+        let mean: Vec<f64> = (0..3)
+            .map(|step| {
+                output
+                    .total_population()
+                    .iter()
+                    .map(|x| x[step])
+                    .sum::<f64>()
+                    / 100.0
+            })
+            .collect();
+        let expected = DeterministicPva::build_from_vectors(
+            vec![2000.0, 0.0],
+            vec![vec![0.0, 0.0], vec![0.0, 0.5]],
+        )
+        .unwrap()
+        .with_dormancy(triggered)
+        .unwrap()
+        .deterministic_projection(3)
+        .total_population();
+        for (simulated, expected) in mean.iter().zip(expected) {
+            assert!((simulated - expected).abs() / expected < 0.02);
+        }
+        let outside = Dormancy::build(0, 2, 0.9, 0.5).unwrap();
+        assert!(model.with_dormancy(outside).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! This module contains the optional event log of the individual-based model, which records what happened to each individual (births, breeding, stage transitions, movements between populations, and deaths with their cause). Logs of large simulations grow quickly, so a log can follow only a sample of individuals and stop after a maximum number of events.
+use std::{error::Error, io::Write};
+
+/// The cause of an individual's death.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeathCause {
+    /// The individual did not survive its age-dependent [`super::MortalityHazard`].
+    Hazard,
+    /// The individual did not survive the transitions out of its stage.
+    StageSurvival,
+    /// The individual died of an infection (see [`crate::populations::disease::Disease`]).
+    Disease,
+    /// The individual did not survive its body condition (see [`super::BodyCondition`]).
+    Condition,
+    /// The individual did not survive selection on its trait (see [`super::QuantitativeTrait`]).
+    Selection,
+}
+impl DeathCause {
+    fn name(&self) -> &'static str {
+        match self {
+            DeathCause::Hazard => "hazard",
+            DeathCause::StageSurvival => "stage_survival",
+            DeathCause::Disease => "disease",
+            DeathCause::Condition => "condition",
+            DeathCause::Selection => "selection",
+        }
+    }
+}
+
+/// The kinds of event recorded in an [`EventLog`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LifeEvent {
+    /// The individual was born into `stage` to the parent with the given identifier.
+    Birth { parent: usize, stage: u8 },
+    /// The individual produced `offspring` offspring.
+    Breeding { offspring: u32 },
+    /// The individual survived and moved from stage `from` to stage `to` (which may be the
+    /// same stage).
+    Transition { from: u8, to: u8 },
+    /// The individual dispersed from population `from` to population `to` of a metapopulation.
+    /// The event is recorded in the logs of both populations, under the identifier the
+    /// individual has in each.
+    Dispersal { from: usize, to: usize },
+    /// The individual was translocated from population `from` to population `to` of a
+    /// metapopulation, and is recorded like [`LifeEvent::Dispersal`].
+    Translocation { from: usize, to: usize },
+    /// The individual died.
+    Death { cause: DeathCause },
+}
+
+/// This struct holds one entry of an [`EventLog`]: the step (starting at 1) in which an event
+/// happened to an individual.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogEntry {
+    pub step: u32,
+    pub individual: usize,
+    pub event: LifeEvent,
+}
+
+/// This struct records the life events of individuals in an individual-based simulation (see
+/// [`super::IndividualSimulation::with_event_log`]). By default every event of every individual
+/// is recorded.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{
+///     EventLog, IndividualBasedModel, StageTransition, TransitionTable,
+/// };
+/// use ecolysis_cmd::simulation::Simulation;
+/// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.9).unwrap()]).unwrap();
+/// let model = IndividualBasedModel::build(vec![50.0].into(), table, vec![0.2]).unwrap();
+/// let log = EventLog::new().with_sample_fraction(0.5).unwrap().with_max_events(1000);
+/// let mut simulation = model.simulation(0, 1).with_event_log(log);
+/// simulation.run(10);
+/// let mut csv = Vec::new();
+/// simulation.get_event_log().unwrap().write_csv(&mut csv).unwrap();
+/// assert!(String::from_utf8(csv).unwrap().starts_with("step,individual,event,"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventLog {
+    entries: Vec<LogEntry>,
+    sample_fraction: f64,
+    max_events: Option<usize>,
+    truncated: bool,
+}
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog::new()
+    }
+}
+impl EventLog {
+    /// Create an empty log that records every event.
+    pub fn new() -> EventLog {
+        EventLog {
+            entries: Vec::new(),
+            sample_fraction: 1.0,
+            max_events: None,
+            truncated: false,
+        }
+    }
+    /// Return the log recording only the events of a fraction of individuals. Whether an
+    /// individual is followed depends only on its identifier, so the complete history of every
+    /// followed individual is kept.
+    /// # Errors
+    /// Will return `Err<'static str>` if the fraction is not between 0 and 1.
+    pub fn with_sample_fraction(mut self, fraction: f64) -> Result<Self, &'static str> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err("The sample fraction must be between 0 and 1.");
+        }
+        self.sample_fraction = fraction;
+        Ok(self)
+    }
+    /// Return the log stopping after `max_events` events have been recorded.
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        self.max_events = Some(max_events);
+        self
+    }
+    /// Return true if events of the individual with this identifier are recorded.
+    pub fn follows(&self, individual: usize) -> bool {
+        if self.sample_fraction >= 1.0 {
+            return true;
+        }
+        // The SplitMix64 finalizer spreads consecutive identifiers evenly over 0 to 1.
+        let mut x = (individual as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+        ((x >> 11) as f64 / (1u64 << 53) as f64) < self.sample_fraction
+    }
+    /// Record an event, if the individual is followed and the log is not full.
+    pub fn record(&mut self, step: u32, individual: usize, event: LifeEvent) {
+        if !self.follows(individual) {
+            return;
+        }
+        if self.max_events.is_some_and(|x| self.entries.len() >= x) {
+            self.truncated = true;
+            return;
+        }
+        self.entries.push(LogEntry {
+            step,
+            individual,
+            event,
+        });
+    }
+    /// Return the recorded events, in the order they happened.
+    pub fn get_entries(&self) -> &Vec<LogEntry> {
+        &self.entries
+    }
+    /// Return true if events were left out because the log was full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+    /// Write the log as CSV with the columns `step`, `individual`, `event`, `from_stage`,
+    /// `to_stage`, `parent`, `offspring`, `cause`, `from_population`, and `to_population`.
+    /// Columns that do not apply to an event are left empty.
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record([
+            "step",
+            "individual",
+            "event",
+            "from_stage",
+            "to_stage",
+            "parent",
+            "offspring",
+            "cause",
+            "from_population",
+            "to_population",
+        ])?;
+        for entry in &self.entries {
+            let text = |x: Option<String>| x.unwrap_or_default();
+            let movement = match entry.event {
+                LifeEvent::Dispersal { from, to } | LifeEvent::Translocation { from, to } => {
+                    Some((from, to))
+                }
+                _ => None,
+            };
+            let (name, from, to, parent, offspring, cause) = match entry.event {
+                LifeEvent::Birth { parent, stage } => {
+                    ("birth", None, Some(stage), Some(parent), None, None)
+                }
+                LifeEvent::Breeding { offspring } => {
+                    ("breeding", None, None, None, Some(offspring), None)
+                }
+                LifeEvent::Transition { from, to } => {
+                    ("transition", Some(from), Some(to), None, None, None)
+                }
+                LifeEvent::Dispersal { .. } => ("dispersal", None, None, None, None, None),
+                LifeEvent::Translocation { .. } => ("translocation", None, None, None, None, None),
+                LifeEvent::Death { cause } => ("death", None, None, None, None, Some(cause.name())),
+            };
+            csv_writer.write_record([
+                entry.step.to_string(),
+                entry.individual.to_string(),
+                name.to_string(),
+                text(from.map(|x| x.to_string())),
+                text(to.map(|x| x.to_string())),
+                text(parent.map(|x| x.to_string())),
+                text(offspring.map(|x| x.to_string())),
+                text(cause.map(String::from)),
+                text(movement.map(|x| x.0.to_string())),
+                text(movement.map(|x| x.1.to_string())),
+            ])?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_and_cap() {
+        let log = EventLog::new().with_sample_fraction(0.25).unwrap();
+        let followed = (0..10_000).filter(|x| log.follows(*x)).count();
+        assert!((followed as f64 - 2500.0).abs() < 150.0);
+        assert!(EventLog::new().with_sample_fraction(1.5).is_err());
+        let mut log = EventLog::new().with_max_events(2);
+        for individual in 0..3 {
+            log.record(1, individual, LifeEvent::Breeding { offspring: 1 });
+        }
+        assert_eq!(log.get_entries().len(), 2);
+        assert!(log.is_truncated());
+    }
+    #[test]
+    fn csv_columns() {
+        let mut log = EventLog::new();
+        log.record(
+            1,
+            4,
+            LifeEvent::Birth {
+                parent: 2,
+                stage: 0,
+            },
+        );
+        log.record(
+            2,
+            4,
+            LifeEvent::Death {
+                cause: DeathCause::Hazard,
+            },
+        );
+        log.record(3, 5, LifeEvent::Dispersal { from: 1, to: 0 });
+        let mut buffer = Vec::new();
+        log.write_csv(&mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "step,individual,event,from_stage,to_stage,parent,offspring,cause,from_population,to_population\n\
+             1,4,birth,,0,2,,,,\n2,4,death,,,,,hazard,,\n3,5,dispersal,,,,,,1,0\n"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! This module contains the genetics of the individual-based model: genotypes, locus metadata, reading founder genotypes from a file, and Mendelian inheritance. A [`Genotype`] holds two alleles per locus as integer codes (or missing); the allele labels used in the file (such as `120` or `A`) are kept alongside so results can be reported in the original coding.
+use super::GeneticMap;
+use crate::imports::read_text;
+use csv::ReaderBuilder;
+use rand::Rng;
+use std::{error::Error, io::Read};
+
+/// The characters that can separate the two alleles of a call, as in `120/124` or `A|G`.
+const ALLELE_SEPARATORS: [char; 4] = ['/', '|', ':', ' '];
+/// The labels read as a missing allele or, for a whole cell, a missing call.
+const MISSING_LABELS: [&str; 5] = ["", "NA", "?", "-", "."];
+
+/// This struct holds a diploid genotype: two alleles at each locus, each an allele code up to
+/// 65534 or missing. The alleles are stored in a single flat list, two per locus, with missing
+/// alleles stored as [`Genotype::MISSING`].
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::Genotype;
+/// let genotype = Genotype::new(vec![[Some(3), Some(300)], [None, None]]).unwrap();
+/// assert_eq!(genotype.locus_count(), 2);
+/// assert_eq!(genotype.get(0), Some([Some(3), Some(300)]));
+/// assert!(genotype.is_missing(1));
+/// assert_eq!(genotype.missing_count(), 2);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Genotype {
+    alleles: Vec<u16>,
+}
+impl Genotype {
+    /// The stored value of a missing allele, which cannot be used as an allele code.
+    pub const MISSING: u16 = u16::MAX;
+    /// Create a genotype from the two alleles at each locus.
+    /// # Errors
+    /// Will return `Err<'static str>` if an allele code is [`Genotype::MISSING`].
+    pub fn new(loci: Vec<[Option<u16>; 2]>) -> Result<Genotype, &'static str> {
+        let mut alleles = Vec::with_capacity(loci.len() * 2);
+        for allele in loci.into_iter().flatten() {
+            alleles.push(match allele {
+                Some(Genotype::MISSING) => return Err("Allele codes must be less than 65535."),
+                Some(code) => code,
+                None => Genotype::MISSING,
+            });
+        }
+        Ok(Genotype { alleles })
+    }
+    /// Return the number of loci.
+    pub fn locus_count(&self) -> usize {
+        self.alleles.len() / 2
+    }
+    /// Return the two alleles at a locus, or `None` if the locus does not exist.
+    pub fn get(&self, locus: usize) -> Option<[Option<u16>; 2]> {
+        let pair = self.alleles.get(locus * 2..locus * 2 + 2)?;
+        Some([decode(pair[0]), decode(pair[1])])
+    }
+    /// Return the two alleles at each locus.
+    pub fn iter(&self) -> impl Iterator<Item = [Option<u16>; 2]> + '_ {
+        self.alleles
+            .chunks_exact(2)
+            .map(|x| [decode(x[0]), decode(x[1])])
+    }
+    /// Return whether both alleles at a locus are missing (or the locus does not exist).
+    pub fn is_missing(&self, locus: usize) -> bool {
+        self.get(locus).is_none_or(|x| x == [None, None])
+    }
+    /// Return the number of missing alleles.
+    pub fn missing_count(&self) -> usize {
+        self.alleles
+            .iter()
+            .filter(|x| **x == Genotype::MISSING)
+            .count()
+    }
+}
+
+/// Return an allele code, or `None` if it is missing.
+fn decode(allele: u16) -> Option<u16> {
+    (allele != Genotype::MISSING).then_some(allele)
+}
+
+/// This struct holds the metadata of a locus: its name and, optionally, the chromosome it is on
+/// and its position on that chromosome in centimorgans.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locus {
+    name: String,
+    chromosome: Option<String>,
+    position: Option<f64>,
+}
+impl Locus {
+    /// Create a locus with no chromosome.
+    pub fn new(name: &str) -> Locus {
+        Locus {
+            name: name.to_string(),
+            chromosome: None,
+            position: None,
+        }
+    }
+    /// Read a list of loci from a CSV file with a `locus` column and, optionally, `chromosome`
+    /// and `position` (in centimorgans) columns. Empty cells leave the value unknown.
+    /// # Errors
+    /// Returns an error if the file cannot be read, has no `locus` column, or a position is not
+    /// a finite number.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::Locus;
+    /// let file = "locus,chromosome,position\nCa21,1,12.5\nCa32,,\n";
+    /// let loci = Locus::read_csv(file.as_bytes()).unwrap();
+    /// assert_eq!(loci[0].get_position(), Some(12.5));
+    /// assert_eq!(loci[1].get_chromosome(), None);
+    /// ```
+    pub fn read_csv<R: Read>(reader: R) -> Result<Vec<Locus>, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(contents.as_bytes());
+        let header = rdr.headers()?.clone();
+        let column = |name: &str| header.iter().position(|x| x.eq_ignore_ascii_case(name));
+        let name_column = column("locus").ok_or("The locus file has no \"locus\" column.")?;
+        let (chromosome_column, position_column) = (column("chromosome"), column("position"));
+        let mut loci = Vec::new();
+        for (row_number, record) in rdr.records().enumerate() {
+            let record = record?;
+            let cell = |column: Option<usize>| {
+                column.and_then(|x| record.get(x)).filter(|x| !x.is_empty())
+            };
+            let mut locus = Locus::new(cell(Some(name_column)).unwrap_or_default());
+            if let Some(chromosome) = cell(chromosome_column) {
+                locus = locus.with_chromosome(chromosome);
+            }
+            if let Some(position) = cell(position_column) {
+                let error = || {
+                    format!(
+                        "Row {}, column \"position\": \"{}\" is not a finite number.",
+                        row_number + 1,
+                        position
+                    )
+                };
+                locus = locus
+                    .with_position(position.parse().map_err(|_| error())?)
+                    .map_err(|_| error())?;
+            }
+            loci.push(locus);
+        }
+        Ok(loci)
+    }
+    /// Return the locus placed on the given chromosome.
+    pub fn with_chromosome(mut self, chromosome: &str) -> Self {
+        self.chromosome = Some(chromosome.to_string());
+        self
+    }
+    /// Return the locus at the given position on its chromosome, in centimorgans.
+    /// # Errors
+    /// Will return `Err<'static str>` if the position is not finite.
+    pub fn with_position(mut self, centimorgans: f64) -> Result<Self, &'static str> {
+        if !centimorgans.is_finite() {
+            return Err("The position of a locus must be finite.");
+        }
+        self.position = Some(centimorgans);
+        Ok(self)
+    }
+    /// Return the name of the locus.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the chromosome of the locus, if known.
+    pub fn get_chromosome(&self) -> Option<&str> {
+        self.chromosome.as_deref()
+    }
+    /// Return the position of the locus on its chromosome in centimorgans, if known.
+    pub fn get_position(&self) -> Option<f64> {
+        self.position
+    }
+}
+
+/// This struct holds genotypes read from a CSV file of individuals (rows) by loci (columns).
+/// The first column holds an identifier for each individual, an optional column named `stage`
+/// holds the stage of each individual, and every other column is a locus. Each cell holds the
+/// two alleles of a diploid call, separated by `/`, `|`, `:`, or a space (`120/124`, `A|G`), or
+/// written as two characters without a separator (`AG`). An empty cell, `NA`, `?`, `-`, or `.`
+/// is a missing allele, or a missing call when it fills the whole cell (`NA`, `?/?`).
+///
+/// Allele labels are coded per locus as integers in the order they are first seen.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::GenotypeTable;
+/// let file = "id,stage,Ca21,Ca32\nF1,1,120/124,AG\nF2,1,124/124,NA\n";
+/// let table = GenotypeTable::read_csv(file.as_bytes()).unwrap();
+/// assert_eq!(table.get_loci()[1].get_name(), "Ca32");
+/// assert_eq!(table.get_genotypes()[1].get(0), Some([Some(1), Some(1)]));
+/// assert!(table.get_genotypes()[1].is_missing(1));
+/// assert_eq!(table.get_allele_labels()[0], vec!["120", "124"]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenotypeTable {
+    ids: Vec<String>,
+    stages: Option<Vec<u8>>,
+    loci: Vec<Locus>,
+    allele_labels: Vec<Vec<String>>,
+    genotypes: Vec<Genotype>,
+}
+impl GenotypeTable {
+    /// Read a genotype table from a CSV file (see [`GenotypeTable`]).
+    /// # Errors
+    /// Returns an error if the file cannot be read, has no loci, a cell is neither a diploid
+    /// call nor missing, a stage is not a whole number, or a locus has more than 65535 alleles.
+    pub fn read_csv<R: Read>(reader: R) -> Result<GenotypeTable, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(contents.as_bytes());
+        let header: Vec<String> = rdr.headers()?.iter().map(String::from).collect();
+        let stage_column = header
+            .iter()
+            .skip(1)
+            .position(|x| x.eq_ignore_ascii_case("stage"))
+            .map(|x| x + 1);
+        let loci: Vec<usize> = (1..header.len())
+            .filter(|x| Some(*x) != stage_column)
+            .collect();
+        if loci.is_empty() {
+            return Err("The genotype file has no locus columns.".into());
+        }
+        let mut table = GenotypeTable {
+            ids: Vec::new(),
+            stages: stage_column.map(|_| Vec::new()),
+            loci: loci.iter().map(|x| Locus::new(&header[*x])).collect(),
+            allele_labels: vec![Vec::new(); loci.len()],
+            genotypes: Vec::new(),
+        };
+        for (row_number, record) in rdr.records().enumerate() {
+            let record = record?;
+            let cell = |column: usize| record.get(column).unwrap_or_default();
+            table.ids.push(cell(0).to_string());
+            if let (Some(column), Some(stages)) = (stage_column, &mut table.stages) {
+                stages.push(cell(column).parse().map_err(|_| {
+                    format!(
+                        "Row {}: the stage \"{}\" is not a whole number.",
+                        row_number + 1,
+                        cell(column)
+                    )
+                })?);
+            }
+            let mut genotype = Vec::with_capacity(loci.len());
+            for (locus, column) in loci.iter().enumerate() {
+                let (first, second) = split_call(cell(*column)).ok_or_else(|| {
+                    format!(
+                        "Row {}, locus \"{}\": \"{}\" is not a diploid allele call.",
+                        row_number + 1,
+                        header[*column],
+                        cell(*column)
+                    )
+                })?;
+                let labels = &mut table.allele_labels[locus];
+                let mut code = |label: &str| -> Result<Option<u16>, String> {
+                    if MISSING_LABELS.contains(&label) {
+                        return Ok(None);
+                    }
+                    let index = match labels.iter().position(|x| x == label) {
+                        Some(index) => index,
+                        None => {
+                            labels.push(label.to_string());
+                            labels.len() - 1
+                        }
+                    };
+                    match u16::try_from(index) {
+                        Ok(code) if code != Genotype::MISSING => Ok(Some(code)),
+                        _ => Err(format!(
+                            "Locus \"{}\" has more than 65535 alleles.",
+                            header[*column]
+                        )),
+                    }
+                };
+                genotype.push([code(first)?, code(second)?]);
+            }
+            table.genotypes.push(Genotype::new(genotype)?);
+        }
+        Ok(table)
+    }
+    /// Return the identifier of each individual.
+    pub fn get_ids(&self) -> &Vec<String> {
+        &self.ids
+    }
+    /// Return the stage of each individual, if the file had a `stage` column.
+    pub fn get_stages(&self) -> Option<&Vec<u8>> {
+        self.stages.as_ref()
+    }
+    /// Return the metadata of each locus. Only the names come from the file.
+    pub fn get_loci(&self) -> &Vec<Locus> {
+        &self.loci
+    }
+    /// Return the table with the metadata of its loci (such as their chromosomes and positions,
+    /// from [`Locus::read_csv`]) replaced. The loci must be in the order of the locus columns.
+    /// # Errors
+    /// Will return `Err<'static str>` if the locus names do not match the locus columns.
+    pub fn with_loci(mut self, loci: Vec<Locus>) -> Result<Self, &'static str> {
+        if loci.len() != self.loci.len()
+            || loci.iter().zip(&self.loci).any(|(a, b)| a.name != b.name)
+        {
+            return Err("The locus names must match the locus columns of the genotype file.");
+        }
+        self.loci = loci;
+        Ok(self)
+    }
+    /// Return the allele labels of each locus; the code of an allele is its index in this list.
+    pub fn get_allele_labels(&self) -> &Vec<Vec<String>> {
+        &self.allele_labels
+    }
+    /// Return the genotype of each individual, in the order of the file.
+    pub fn get_genotypes(&self) -> &Vec<Genotype> {
+        &self.genotypes
+    }
+    /// Return the number of individuals in each of `stage_count` stages, for use as the initial
+    /// population of a model.
+    /// # Errors
+    /// Will return `Err<'static str>` if the file had no `stage` column or an individual's stage
+    /// does not exist.
+    pub fn stage_counts(&self, stage_count: u8) -> Result<Vec<f64>, &'static str> {
+        let stages = self
+            .stages
+            .as_ref()
+            .ok_or("The genotype file has no stage column.")?;
+        let mut counts = vec![0.0; stage_count as usize];
+        for stage in stages {
+            *counts.get_mut(*stage as usize).ok_or(
+                "An individual in the genotype file is in a stage that does not exist.",
+            )? += 1.0;
+        }
+        Ok(counts)
+    }
+    /// Return the genotypes ordered to match the founders of a model whose initial population is
+    /// [`GenotypeTable::stage_counts`]: by stage, and in file order within each stage. Without a
+    /// `stage` column, the genotypes are returned in file order.
+    pub fn founder_genotypes(&self) -> Vec<Genotype> {
+        let mut order: Vec<usize> = (0..self.genotypes.len()).collect();
+        if let Some(stages) = &self.stages {
+            order.sort_by_key(|x| stages[*x]);
+        }
+        order
+            .into_iter()
+            .map(|x| self.genotypes[x].clone())
+            .collect()
+    }
+}
+
+/// Split a diploid allele call into its two allele labels.
+fn split_call(call: &str) -> Option<(&str, &str)> {
+    if MISSING_LABELS.contains(&call) {
+        return Some((call, call));
+    }
+    let split = ALLELE_SEPARATORS
+        .iter()
+        .find_map(|separator| call.split_once(*separator));
+    if let Some((first, second)) = split {
+        let (first, second) = (first.trim(), second.trim());
+        return (!first.is_empty() && !second.is_empty()).then_some((first, second));
+    }
+    let mut characters = call.char_indices();
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some(_), Some((split, _)), None) => Some((&call[..split], &call[split..])),
+        _ => None,
+    }
+}
+
+/// Return the genotype of an offspring, formed from a gamete of each parent (see
+/// [`GeneticMap`]). A missing allele is passed on as missing.
+pub(crate) fn inherit<R: Rng>(
+    mother: &Genotype,
+    father: &Genotype,
+    map: &GeneticMap,
+    rng: &mut R,
+) -> Genotype {
+    let (egg, sperm) = (map.gamete(mother, rng), map.gamete(father, rng));
+    let mut alleles = Vec::with_capacity(egg.len() * 2);
+    for (m, f) in egg.into_iter().zip(sperm) {
+        alleles.push(m.unwrap_or(Genotype::MISSING));
+        alleles.push(f.unwrap_or(Genotype::MISSING));
+    }
+    Genotype { alleles }
+}
+
+/// Mutate each allele of a genotype with the given probability to one of the other alleles of
+/// its locus, chosen at random from the `allele_counts[locus]` codes of that locus. Missing
+/// alleles, and alleles of loci with a single allele, are left unchanged.
+pub(crate) fn mutate<R: Rng>(
+    genotype: &mut Genotype,
+    rate: f64,
+    allele_counts: &[u16],
+    rng: &mut R,
+) {
+    for (index, allele) in genotype.alleles.iter_mut().enumerate() {
+        let count = allele_counts[index / 2];
+        if *allele == Genotype::MISSING || count < 2 || rng.gen::<f64>() >= rate {
+            continue;
+        }
+        let other = rng.gen_range(0..count - 1);
+        *allele = match other >= *allele {
+            true => other + 1,
+            false => other,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn allele_calls() {
+        assert_eq!(split_call("120/124"), Some(("120", "124")));
+        assert_eq!(split_call("A | G"), Some(("A", "G")));
+        assert_eq!(split_call("AG"), Some(("A", "G")));
+        assert_eq!(split_call("A"), None);
+        assert_eq!(split_call("120"), None);
+        assert_eq!(split_call("/124"), None);
+        assert_eq!(split_call("NA"), Some(("NA", "NA")));
+        assert_eq!(split_call("?/12"), Some(("?", "12")));
+    }
+    #[test]
+    fn stage_ordering_and_errors() {
+        let file = "id,L1,stage\na,1/2,1\nb,2/2,0\nc,3/1,1\n";
+        let table = GenotypeTable::read_csv(file.as_bytes()).unwrap();
+        assert_eq!(table.stage_counts(2).unwrap(), vec![1.0, 2.0]);
+        assert!(table.stage_counts(1).is_err());
+        let expected: Vec<_> = [[1, 1], [0, 1], [2, 0]]
+            .iter()
+            .map(|[a, b]| Genotype::new(vec![[Some(*a), Some(*b)]]).unwrap())
+            .collect();
+        assert_eq!(table.founder_genotypes(), expected);
+        let table = table
+            .with_loci(vec![Locus::new("L1").with_chromosome("2")])
+            .unwrap();
+        assert_eq!(table.get_loci()[0].get_chromosome(), Some("2"));
+        assert!(table.clone().with_loci(Vec::new()).is_err());
+        assert!(table.with_loci(vec![Locus::new("L2")]).is_err());
+        assert!(Locus::read_csv("name\nL1\n".as_bytes()).is_err());
+        assert!(Locus::read_csv("locus,position\nL1,x\n".as_bytes()).is_err());
+        assert!(GenotypeTable::read_csv("id\na\n".as_bytes()).is_err());
+        assert!(GenotypeTable::read_csv("id,L1\na,120\n".as_bytes()).is_err());
+        let missing = GenotypeTable::read_csv("id,L1\na,\nb,./1\n".as_bytes()).unwrap();
+        assert!(missing.get_genotypes()[0].is_missing(0));
+        assert_eq!(missing.get_genotypes()[1].get(0), Some([None, Some(0)]));
+        assert!(GenotypeTable::read_csv("id,stage,L1\na,x,1/1\n".as_bytes()).is_err());
+    }
+    #[test]
+    fn genotype_storage() {
+        assert!(Genotype::new(vec![[Some(Genotype::MISSING), None]]).is_err());
+        let genotype = Genotype::new(vec![[Some(1000), None], [Some(2), Some(3)]]).unwrap();
+        assert_eq!(genotype.get(2), None);
+        assert!(genotype.is_missing(2) && !genotype.is_missing(0));
+        assert_eq!(genotype.iter().nth(1), Some([Some(2), Some(3)]));
+        assert_eq!(genotype.missing_count(), 1);
+    }
+    #[test]
+    fn mendelian_inheritance() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mother = Genotype::new(vec![[Some(0), Some(1)]]).unwrap();
+        let father = Genotype::new(vec![[Some(2), Some(2)]]).unwrap();
+        let children: Vec<_> = (0..1000)
+            .map(|_| inherit(&mother, &father, &GeneticMap::unlinked(1), &mut rng))
+            .collect();
+        assert!(children.iter().all(|x| x.get(0).unwrap()[1] == Some(2)));
+        let zeros = children
+            .iter()
+            .filter(|x| x.get(0).unwrap()[0] == Some(0))
+            .count();
+        assert!((zeros as f64 - 500.0).abs() < 60.0);
+    }
+}
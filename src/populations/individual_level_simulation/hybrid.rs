@@ -0,0 +1,368 @@
+//! This module contains a hybrid model for large populations: demography follows a matrix model with demographic stochasticity, while genetics are tracked in a sample of individuals that stands in for the whole population. Inbreeding accumulates with the census size of the population and reduces recruitment through inbreeding depression, while the sample tracks allele frequencies and the loss of heterozygosity.
+use super::{inherit, GeneticMap, Genotype, Locus};
+use crate::populations::population_level_simulation::{
+    replicate_seed, DecomposedMatrix, PopulationMatrix, PopulationVector, PvaStochasticOutput,
+    RoundingRule,
+};
+use crate::simulation::Simulation;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// This struct holds a hybrid model: a matrix model split into survival/growth and fertility,
+/// the initial population, the genotypes of a sample of founders, the largest number of
+/// individuals kept in the genetic sample, and the number of lethal equivalents.
+///
+/// Each step, survivors and offspring are drawn from the matrix model, with the fertility
+/// multiplied by `exp(-B F)` for `B` lethal equivalents and inbreeding `F` (inbreeding
+/// depression in the survival of offspring to recruitment). The genetic sample is then renewed
+/// in proportion: the share of offspring in the new population is replaced by offspring of
+/// random pairs from the sample, the rest by randomly chosen members of the old sample, and the
+/// sample size follows the population up to its maximum. Inbreeding follows the census size `N`
+/// of the population, taken as its effective size: `F' = 1 - (1 - F)(1 - 1 / 2N)` each step.
+/// The sample is drawn with its own random numbers, so the sample size does not change the
+/// demography.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// use ecolysis_cmd::populations::individual_level_simulation::{Genotype, HybridModel, Locus};
+/// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+/// use ecolysis_cmd::simulation::Simulation;
+/// let matrix = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.6]]).unwrap();
+/// let founders: Vec<Genotype> = (0..20)
+///     .map(|x| Genotype::new(vec![[Some(x), Some(x + 20)]]).unwrap())
+///     .collect();
+/// let model = HybridModel::build(
+///     DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap(),
+///     vec![5000.0, 5000.0].into(),
+///     vec![Locus::new("L1")],
+///     founders,
+///     20,
+/// )
+/// .unwrap()
+/// .with_lethal_equivalents(3.0)
+/// .unwrap();
+/// let mut simulation = model.simulation(0, 1);
+/// simulation.run(50);
+/// assert!(simulation.inbreeding() > 0.0);
+/// assert!(simulation.heterozygosity_loss() > 0.0);
+/// ```
+#[derive(Clone)]
+pub struct HybridModel {
+    survival: DecomposedMatrix,
+    fertility: PopulationMatrix,
+    zero: PopulationMatrix,
+    initial_population: PopulationVector,
+    loci: Vec<Locus>,
+    founder_genotypes: Vec<Genotype>,
+    genetic_map: GeneticMap,
+    sample_size: usize,
+    lethal_equivalents: f64,
+}
+impl HybridModel {
+    /// Build a hybrid model with no inbreeding depression. The initial population is rounded to
+    /// whole individuals, and the founder genotypes are the initial genetic sample.
+    /// # Errors
+    /// Will return `Err<'static str>` if the initial population does not have one value per
+    /// stage, there are no founder genotypes or more than the sample size, or a genotype does not
+    /// have one pair of alleles per locus.
+    pub fn build(
+        matrix: DecomposedMatrix,
+        initial_population: PopulationVector,
+        loci: Vec<Locus>,
+        founder_genotypes: Vec<Genotype>,
+        sample_size: usize,
+    ) -> Result<HybridModel, &'static str> {
+        let stages = matrix.get_lifestage_count();
+        if initial_population.get_lifestage_count() != stages {
+            return Err("The initial population must have one value per stage.");
+        }
+        if founder_genotypes.is_empty() || founder_genotypes.len() > sample_size {
+            return Err("There must be between one founder genotype and the sample size of them.");
+        }
+        if founder_genotypes
+            .iter()
+            .any(|x| x.locus_count() != loci.len())
+        {
+            return Err("Every genotype must have one pair of alleles per locus.");
+        }
+        let zero = PopulationMatrix::build(vec![vec![0.0; stages as usize]; stages as usize])?;
+        Ok(HybridModel {
+            survival: DecomposedMatrix::build(matrix.get_survival().clone(), zero.clone())?,
+            fertility: matrix.get_fertility().clone(),
+            zero,
+            initial_population: initial_population.round(RoundingRule::Nearest),
+            genetic_map: GeneticMap::from_loci(&loci),
+            loci,
+            founder_genotypes,
+            sample_size,
+            lethal_equivalents: 0.0,
+        })
+    }
+    /// Return the model with inbreeding depression from the given number of lethal equivalents
+    /// (diploid; 3.14 is a commonly used default for mammals).
+    /// # Errors
+    /// Will return `Err<'static str>` if the number is negative or not finite.
+    pub fn with_lethal_equivalents(
+        mut self,
+        lethal_equivalents: f64,
+    ) -> Result<Self, &'static str> {
+        if !lethal_equivalents.is_finite() || lethal_equivalents < 0.0 {
+            return Err("The number of lethal equivalents must be finite and not negative.");
+        }
+        self.lethal_equivalents = lethal_equivalents;
+        Ok(self)
+    }
+    /// Return the loci of the model.
+    pub fn get_loci(&self) -> &Vec<Locus> {
+        &self.loci
+    }
+    /// Return the largest number of individuals in the genetic sample.
+    pub fn get_sample_size(&self) -> usize {
+        self.sample_size
+    }
+    /// Return a simulation of a single replicate (see
+    /// [`super::IndividualBasedModel::simulation`] for how seeds are used).
+    pub fn simulation(&self, replicate: u32, seed: u64) -> HybridSimulation<'_> {
+        let initial_heterozygosity = expected_heterozygosity(&self.founder_genotypes);
+        let mut rng = StdRng::seed_from_u64(replicate_seed(seed, replicate));
+        let sample_rng = StdRng::seed_from_u64(rng.gen());
+        HybridSimulation {
+            model: self,
+            population: self.initial_population.clone(),
+            sample: self.founder_genotypes.clone(),
+            initial_heterozygosity,
+            inbreeding: 0.0,
+            rng,
+            sample_rng,
+            steps_taken: 0,
+        }
+    }
+    /// Run the model for the given number of steps and replicates.
+    pub fn projection(&self, steps: u32, replicates: u32, seed: u64) -> PvaStochasticOutput {
+        PvaStochasticOutput::new(
+            (0..replicates)
+                .map(|replicate| self.simulation(replicate, seed).run(steps))
+                .collect(),
+        )
+    }
+}
+
+/// This struct holds one running replicate of a [`HybridModel`]. Its state is the number of
+/// individuals in each stage.
+pub struct HybridSimulation<'a> {
+    model: &'a HybridModel,
+    population: PopulationVector,
+    sample: Vec<Genotype>,
+    initial_heterozygosity: f64,
+    inbreeding: f64,
+    rng: StdRng,
+    sample_rng: StdRng,
+    steps_taken: u32,
+}
+impl HybridSimulation<'_> {
+    /// Return the genotypes of the genetic sample.
+    pub fn get_sample(&self) -> &Vec<Genotype> {
+        &self.sample
+    }
+    /// Return the frequency of each allele (indexed by allele code) at each locus in the
+    /// genetic sample, ignoring missing alleles.
+    pub fn allele_frequencies(&self) -> Vec<Vec<f64>> {
+        allele_frequencies(&self.sample, self.model.loci.len())
+    }
+    /// Return the expected heterozygosity of the genetic sample, averaged over loci.
+    pub fn expected_heterozygosity(&self) -> f64 {
+        expected_heterozygosity(&self.sample)
+    }
+    /// Return the inbreeding coefficient of the population, accumulated from its census size.
+    pub fn inbreeding(&self) -> f64 {
+        self.inbreeding
+    }
+    /// Return the loss of expected heterozygosity in the genetic sample since the start,
+    /// `1 - H / H0` (0 if the founders had none).
+    pub fn heterozygosity_loss(&self) -> f64 {
+        if self.initial_heterozygosity > 0.0 {
+            (1.0 - self.expected_heterozygosity() / self.initial_heterozygosity).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+impl Simulation for HybridSimulation<'_> {
+    type State = PopulationVector;
+    fn step(&mut self) {
+        let model = self.model;
+        let depression = (-model.lethal_equivalents * self.inbreeding).exp();
+        let census = self.population.total();
+        if census > 0.0 {
+            self.inbreeding = 1.0 - (1.0 - self.inbreeding) * (1.0 - 0.5 / census);
+        }
+        let survivors = model
+            .survival
+            .project_demographic(&self.population, &mut self.rng);
+        let births = DecomposedMatrix::build(model.zero.clone(), &model.fertility * depression)
+            .expect("The fertility matrix was already checked.")
+            .project_demographic(&self.population, &mut self.rng);
+        self.population = survivors
+            .checked_add(&births)
+            .expect("Survivors and births have one value per stage.");
+        let total = self.population.total();
+        let size = model.sample_size.min(total as usize);
+        let recruits = if total > 0.0 {
+            (births.total() / total * size as f64).round() as usize
+        } else {
+            0
+        };
+        let kept = (size - recruits.min(size)).min(self.sample.len());
+        let mut sample: Vec<Genotype> = self
+            .sample
+            .choose_multiple(&mut self.sample_rng, kept)
+            .cloned()
+            .collect();
+        if !self.sample.is_empty() {
+            for _ in kept..size {
+                let mother = self.sample_rng.gen_range(0..self.sample.len());
+                let mut father = self.sample_rng.gen_range(0..self.sample.len());
+                while father == mother && self.sample.len() > 1 {
+                    father = self.sample_rng.gen_range(0..self.sample.len());
+                }
+                sample.push(inherit(
+                    &self.sample[mother],
+                    &self.sample[father],
+                    &model.genetic_map,
+                    &mut self.sample_rng,
+                ));
+            }
+        }
+        self.sample = sample;
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &PopulationVector {
+        &self.population
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+/// Return the frequency of each allele at each locus among the given genotypes.
+fn allele_frequencies(genotypes: &[Genotype], locus_count: usize) -> Vec<Vec<f64>> {
+    let mut counts: Vec<Vec<f64>> = vec![Vec::new(); locus_count];
+    for genotype in genotypes {
+        for (locus, alleles) in genotype.iter().enumerate() {
+            for allele in alleles.into_iter().flatten() {
+                let allele = allele as usize;
+                if counts[locus].len() <= allele {
+                    counts[locus].resize(allele + 1, 0.0);
+                }
+                counts[locus][allele] += 1.0;
+            }
+        }
+    }
+    for locus in &mut counts {
+        let total: f64 = locus.iter().sum();
+        if total > 0.0 {
+            locus.iter_mut().for_each(|x| *x /= total);
+        }
+    }
+    counts
+}
+
+/// Return the expected heterozygosity `1 - sum(p^2)` averaged over loci (0 with no loci).
+fn expected_heterozygosity(genotypes: &[Genotype]) -> f64 {
+    let locus_count = genotypes.first().map_or(0, |x| x.locus_count());
+    if locus_count == 0 {
+        return 0.0;
+    }
+    allele_frequencies(genotypes, locus_count)
+        .iter()
+        .map(|x| 1.0 - x.iter().map(|p| p * p).sum::<f64>())
+        .sum::<f64>()
+        / locus_count as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(founders: usize, sample_size: usize, initial: f64) -> HybridModel {
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.6]]).unwrap();
+        let founders = (0..founders as u16)
+            .map(|x| Genotype::new(vec![[Some(x), Some(x + 100)]; 2]).unwrap())
+            .collect();
+        HybridModel::build(
+            DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap(),
+            vec![initial, initial].into(),
+            vec![Locus::new("L1"), Locus::new("L2")],
+            founders,
+            sample_size,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn invalid_models() {
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.6]]).unwrap();
+        let matrix = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+        let genotype = Genotype::new(vec![[Some(0), Some(1)]]).unwrap();
+        let loci = vec![Locus::new("L1")];
+        let build = |initial: Vec<f64>, loci: Vec<Locus>, founders: Vec<Genotype>, size| {
+            HybridModel::build(matrix.clone(), initial.into(), loci, founders, size)
+        };
+        assert!(build(vec![1.0], loci.clone(), vec![genotype.clone()], 5).is_err());
+        assert!(build(vec![1.0, 1.0], loci.clone(), Vec::new(), 5).is_err());
+        assert!(build(vec![1.0, 1.0], loci.clone(), vec![genotype.clone(); 6], 5).is_err());
+        assert!(build(vec![1.0, 1.0], Vec::new(), vec![genotype.clone()], 5).is_err());
+        assert!(build(vec![1.0, 1.0], loci, vec![genotype], 5)
+            .unwrap()
+            .with_lethal_equivalents(-1.0)
+            .is_err());
+    }
+    #[test]
+    fn heterozygosity_and_frequencies() {
+        let genotypes = vec![
+            Genotype::new(vec![[Some(0), Some(1)]]).unwrap(),
+            Genotype::new(vec![[Some(1), None]]).unwrap(),
+        ];
+        assert_eq!(
+            allele_frequencies(&genotypes, 1),
+            vec![vec![1.0 / 3.0, 2.0 / 3.0]]
+        );
+        assert!((expected_heterozygosity(&genotypes) - 4.0 / 9.0).abs() < 1e-12);
+        assert_eq!(expected_heterozygosity(&[]), 0.0);
+    }
+    #[test]
+    fn small_samples_lose_diversity() {
+        let small = model(10, 10, 500.0);
+        let mut simulation = small.simulation(0, 2);
+        assert_eq!(simulation.heterozygosity_loss(), 0.0);
+        simulation.run(40);
+        assert!(simulation.get_sample().len() <= 10);
+        assert!(simulation.heterozygosity_loss() > 0.2);
+        // Inbreeding follows the census size, not the sample.
+        assert!(simulation.inbreeding() < 0.05);
+    }
+    #[test]
+    fn small_populations_depress_recruitment() {
+        let small = model(10, 10, 10.0);
+        let mut simulation = small.simulation(0, 2);
+        simulation.run(20);
+        assert!(simulation.inbreeding() > 0.2);
+        let total = |x: &Vec<Vec<f64>>| x.iter().map(|x| x[19]).sum::<f64>();
+        let healthy = model(10, 10, 10.0).projection(20, 20, 2).total_population();
+        let depressed = model(10, 10, 10.0)
+            .with_lethal_equivalents(6.0)
+            .unwrap()
+            .projection(20, 20, 2)
+            .total_population();
+        assert!(total(&depressed) < total(&healthy));
+    }
+    #[test]
+    fn sample_size_does_not_change_demography() {
+        let trajectories = |sample_size| {
+            model(10, sample_size, 50.0)
+                .with_lethal_equivalents(3.0)
+                .unwrap()
+                .projection(30, 3, 4)
+                .total_population()
+        };
+        assert_eq!(trajectories(10), trajectories(200));
+    }
+}
@@ -0,0 +1,158 @@
+//! This module contains the genetic map used to form gametes in the individual-based model. Loci on the same chromosome with known positions are linked, with recombination fractions from the Haldane map function; all other pairs of loci assort independently.
+use super::{Genotype, Locus};
+use rand::Rng;
+
+/// This struct holds a genetic map: an order of the loci along the genome and the recombination
+/// fraction between each locus and the one before it in that order. A fraction of 0.5 (always
+/// used for the first locus) means the locus starts a new linkage group and assorts
+/// independently of the loci before it.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{GeneticMap, Locus};
+/// let loci = vec![
+///     Locus::new("A").with_chromosome("1").with_position(10.0).unwrap(),
+///     Locus::new("B").with_chromosome("2").with_position(0.0).unwrap(),
+///     Locus::new("C").with_chromosome("1").with_position(30.0).unwrap(),
+/// ];
+/// let map = GeneticMap::from_loci(&loci);
+/// // 20 cM apart on chromosome 1.
+/// assert!((map.recombination_fraction(0, 2) - 0.5 * (1.0 - (-0.4f64).exp())).abs() < 1e-12);
+/// assert_eq!(map.recombination_fraction(0, 1), 0.5);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeneticMap {
+    order: Vec<usize>,
+    fractions: Vec<f64>,
+}
+impl GeneticMap {
+    /// Create a genetic map with the loci in their given order and the recombination fraction
+    /// between each pair of adjacent loci (one fewer than the number of loci).
+    /// # Errors
+    /// Will return `Err<'static str>` if any fraction is not between 0 and 0.5.
+    pub fn new(fractions: Vec<f64>) -> Result<GeneticMap, &'static str> {
+        if fractions.iter().any(|x| !(0.0..=0.5).contains(x)) {
+            return Err("Recombination fractions must be between 0 and 0.5.");
+        }
+        Ok(GeneticMap {
+            order: (0..=fractions.len()).collect(),
+            fractions: std::iter::once(0.5).chain(fractions).collect(),
+        })
+    }
+    /// Create a genetic map in which every locus assorts independently.
+    pub fn unlinked(locus_count: usize) -> GeneticMap {
+        GeneticMap {
+            order: (0..locus_count).collect(),
+            fractions: vec![0.5; locus_count],
+        }
+    }
+    /// Create a genetic map from the chromosomes and positions of loci. Loci on the same
+    /// chromosome are ordered by position and linked with the Haldane map function,
+    /// `r = (1 - exp(-2d)) / 2` for a distance of `d` Morgans; loci without a chromosome or a
+    /// position assort independently.
+    pub fn from_loci(loci: &[Locus]) -> GeneticMap {
+        let mut order: Vec<usize> = (0..loci.len()).collect();
+        let key = |x: &usize| (loci[*x].get_chromosome(), loci[*x].get_position().is_none());
+        order.sort_by(|a, b| {
+            key(a).cmp(&key(b)).then(
+                loci[*a]
+                    .get_position()
+                    .partial_cmp(&loci[*b].get_position())
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+        });
+        let mut fractions = Vec::with_capacity(order.len());
+        for (index, locus) in order.iter().enumerate() {
+            let previous = index.checked_sub(1).map(|x| &loci[order[x]]);
+            let locus = &loci[*locus];
+            fractions.push(match previous {
+                Some(previous)
+                    if previous.get_chromosome().is_some()
+                        && previous.get_chromosome() == locus.get_chromosome() =>
+                {
+                    match (previous.get_position(), locus.get_position()) {
+                        (Some(a), Some(b)) => haldane((b - a) / 100.0),
+                        _ => 0.5,
+                    }
+                }
+                _ => 0.5,
+            });
+        }
+        GeneticMap { order, fractions }
+    }
+    /// Return the number of loci in the map.
+    pub fn locus_count(&self) -> usize {
+        self.order.len()
+    }
+    /// Return the probability that a gamete carries alleles from different parental chromosomes
+    /// at two loci, combining the intervals between them (0.5 for unlinked loci).
+    /// # Panics
+    /// Panics if either locus is not in the map.
+    pub fn recombination_fraction(&self, first: usize, second: usize) -> f64 {
+        let position = |locus| self.order.iter().position(|x| *x == locus).unwrap();
+        let (a, b) = (position(first), position(second));
+        let (a, b) = (a.min(b), a.max(b));
+        let linkage: f64 = self.fractions[a + 1..=b]
+            .iter()
+            .map(|x| 1.0 - 2.0 * x)
+            .product();
+        0.5 * (1.0 - linkage)
+    }
+    /// Return a gamete of a parent: one allele per locus, switching between the parent's two
+    /// chromosomes with the recombination fraction of each interval.
+    pub(crate) fn gamete<R: Rng>(&self, parent: &Genotype, rng: &mut R) -> Vec<Option<u16>> {
+        let mut gamete = vec![None; self.order.len()];
+        let mut strand = 0;
+        for (locus, fraction) in self.order.iter().zip(&self.fractions) {
+            if rng.gen::<f64>() < *fraction {
+                strand = 1 - strand;
+            }
+            gamete[*locus] = parent.get(*locus).and_then(|x| x[strand]);
+        }
+        gamete
+    }
+}
+
+/// Return the recombination fraction for a map distance in Morgans (the Haldane map function).
+fn haldane(morgans: f64) -> f64 {
+    0.5 * (1.0 - (-2.0 * morgans.abs()).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn recombination_between_linked_loci() {
+        let map = GeneticMap::new(vec![0.1, 0.0]).unwrap();
+        assert!(GeneticMap::new(vec![0.6]).is_err());
+        assert!((map.recombination_fraction(0, 2) - 0.1).abs() < 1e-12);
+        assert_eq!(map.recombination_fraction(1, 2), 0.0);
+        let parent = Genotype::new(vec![[Some(0), Some(1)]; 3]).unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        let gametes: Vec<_> = (0..2000).map(|_| map.gamete(&parent, &mut rng)).collect();
+        assert!(gametes.iter().all(|x| x[1] == x[2]));
+        let recombinant = gametes.iter().filter(|x| x[0] != x[1]).count() as f64 / 2000.0;
+        assert!((recombinant - 0.1).abs() < 0.025);
+    }
+    #[test]
+    fn map_from_loci() {
+        let loci = vec![
+            Locus::new("A")
+                .with_chromosome("1")
+                .with_position(50.0)
+                .unwrap(),
+            Locus::new("B").with_chromosome("1"),
+            Locus::new("C")
+                .with_chromosome("1")
+                .with_position(0.0)
+                .unwrap(),
+            Locus::new("D").with_position(0.0).unwrap(),
+        ];
+        let map = GeneticMap::from_loci(&loci);
+        assert_eq!(map.locus_count(), 4);
+        assert!((map.recombination_fraction(0, 2) - haldane(0.5)).abs() < 1e-12);
+        assert_eq!(map.recombination_fraction(0, 1), 0.5);
+        assert_eq!(map.recombination_fraction(2, 3), 0.5);
+        assert_eq!(GeneticMap::unlinked(2).recombination_fraction(0, 1), 0.5);
+    }
+}
@@ -0,0 +1,146 @@
+//! This module contains parametric mortality hazard curves, which make the risk of dying in the individual-based model depend on age. Senescence (mortality rising with age) is hard to represent compactly with stage-based matrices, but is a single parameter of a Gompertz or Weibull hazard.
+
+/// This enum describes an instantaneous mortality hazard `h(a)` as a function of age `a` (in
+/// steps):
+/// - `Gompertz`: `h(a) = baseline * exp(senescence * a)`. A positive `senescence` makes
+///   mortality rise exponentially with age; zero gives a constant hazard.
+/// - `Weibull`: `h(a) = (shape / scale) * (a / scale)^(shape - 1)`. A `shape` above one makes
+///   mortality rise with age, below one makes it fall (for example high infant mortality), and
+///   exactly one gives a constant hazard of `1 / scale`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MortalityHazard {
+    Gompertz { baseline: f64, senescence: f64 },
+    Weibull { scale: f64, shape: f64 },
+}
+impl MortalityHazard {
+    /// Check that the parameters of the hazard are valid.
+    /// # Errors
+    /// Will return `Err<'static str>` if a Gompertz baseline is negative or a Weibull scale or
+    /// shape is not positive, or if any parameter is not finite.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match *self {
+            MortalityHazard::Gompertz {
+                baseline,
+                senescence,
+            } => {
+                if !baseline.is_finite() || !senescence.is_finite() || baseline < 0.0 {
+                    return Err("A Gompertz hazard needs a finite, non-negative baseline and a finite senescence rate.");
+                }
+            }
+            MortalityHazard::Weibull { scale, shape } => {
+                if !scale.is_finite() || !shape.is_finite() || scale <= 0.0 || shape <= 0.0 {
+                    return Err("A Weibull hazard needs a finite, positive scale and shape.");
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Return the instantaneous hazard at an age.
+    pub fn hazard(&self, age: f64) -> f64 {
+        match *self {
+            MortalityHazard::Gompertz {
+                baseline,
+                senescence,
+            } => baseline * (senescence * age).exp(),
+            MortalityHazard::Weibull { scale, shape } => {
+                shape / scale * (age / scale).powf(shape - 1.0)
+            }
+        }
+    }
+    /// Return the cumulative hazard from birth to an age, `H(a)`, the integral of the hazard.
+    pub fn cumulative_hazard(&self, age: f64) -> f64 {
+        match *self {
+            MortalityHazard::Gompertz {
+                baseline,
+                senescence,
+            } => {
+                if senescence == 0.0 {
+                    baseline * age
+                } else {
+                    baseline / senescence * ((senescence * age).exp() - 1.0)
+                }
+            }
+            MortalityHazard::Weibull { scale, shape } => (age / scale).powf(shape),
+        }
+    }
+    /// Return the probability that an individual of the given age survives the next step:
+    /// `exp(-(H(age + 1) - H(age)))`. If the cumulative hazard overflows at that age, the
+    /// individual does not survive.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::MortalityHazard;
+    /// let hazard = MortalityHazard::Gompertz { baseline: 0.01, senescence: 0.1 };
+    /// assert!(hazard.survival(30) < hazard.survival(5));
+    /// let constant = MortalityHazard::Weibull { scale: 10.0, shape: 1.0 };
+    /// assert!((constant.survival(0) - (-0.1_f64).exp()).abs() < 1e-12);
+    /// ```
+    pub fn survival(&self, age: u16) -> f64 {
+        let age = age as f64;
+        let increment = self.cumulative_hazard(age + 1.0) - self.cumulative_hazard(age);
+        if increment.is_nan() {
+            // Both cumulative hazards are infinite, so the hazard over the step is too.
+            return 0.0;
+        }
+        (-increment).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cumulative_hazard_integrates_hazard() {
+        let hazards = [
+            MortalityHazard::Gompertz {
+                baseline: 0.02,
+                senescence: 0.15,
+            },
+            MortalityHazard::Gompertz {
+                baseline: 0.02,
+                senescence: 0.0,
+            },
+            MortalityHazard::Weibull {
+                scale: 12.0,
+                shape: 2.5,
+            },
+        ];
+        for hazard in hazards {
+            let steps = 10_000;
+            let integral: f64 = (0..steps)
+                .map(|i| hazard.hazard((i as f64 + 0.5) * 5.0 / steps as f64) * 5.0 / steps as f64)
+                .sum();
+            assert!((integral - hazard.cumulative_hazard(5.0)).abs() < 1e-6);
+            assert!(hazard.validate().is_ok());
+        }
+    }
+    #[test]
+    fn invalid_hazards() {
+        assert!(MortalityHazard::Gompertz {
+            baseline: -0.1,
+            senescence: 0.1
+        }
+        .validate()
+        .is_err());
+        assert!(MortalityHazard::Weibull {
+            scale: 0.0,
+            shape: 1.0
+        }
+        .validate()
+        .is_err());
+        assert!(MortalityHazard::Gompertz {
+            baseline: f64::NAN,
+            senescence: 0.1
+        }
+        .validate()
+        .is_err());
+    }
+    #[test]
+    fn overflowing_hazard_kills() {
+        let hazard = MortalityHazard::Gompertz {
+            baseline: 0.01,
+            senescence: 10.0,
+        };
+        assert!(hazard.cumulative_hazard(200.0).is_infinite());
+        assert_eq!(hazard.survival(200), 0.0);
+    }
+}
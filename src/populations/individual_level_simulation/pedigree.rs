@@ -0,0 +1,349 @@
+//! This module contains the pedigree of an individual-based simulation and the kinship tools used to manage captive or small populations: kinship and relatedness matrices, mean kinship, and pairings that minimize mean kinship.
+use std::collections::HashMap;
+
+/// This struct holds a pedigree: the mother and father (when known) of every individual, indexed
+/// by identifier, or the single parent of a clone. Identifiers are given in order from 0, so
+/// parents always have smaller identifiers than their offspring.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::Pedigree;
+/// let mut pedigree = Pedigree::new();
+/// let (sire, dam) = (pedigree.add(None, None).unwrap(), pedigree.add(None, None).unwrap());
+/// let first = pedigree.add(Some(dam), Some(sire)).unwrap();
+/// let second = pedigree.add(Some(dam), Some(sire)).unwrap();
+/// // Full siblings.
+/// assert_eq!(pedigree.kinship(first, second), 0.25);
+/// let inbred = pedigree.add(Some(first), Some(second)).unwrap();
+/// assert_eq!(pedigree.inbreeding(inbred), 0.25);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pedigree {
+    parents: Vec<[Option<usize>; 2]>,
+    clones: Vec<bool>,
+}
+impl Pedigree {
+    /// Create an empty pedigree.
+    pub fn new() -> Pedigree {
+        Pedigree::default()
+    }
+    /// Add an individual with the given parents (`None` for founders or unknown parents),
+    /// returning its identifier.
+    /// # Errors
+    /// Will return `Err<'static str>` if a parent is not already in the pedigree.
+    pub fn add(
+        &mut self,
+        mother: Option<usize>,
+        father: Option<usize>,
+    ) -> Result<usize, &'static str> {
+        if [mother, father]
+            .iter()
+            .flatten()
+            .any(|x| *x >= self.parents.len())
+        {
+            return Err("The parents must already be in the pedigree.");
+        }
+        self.parents.push([mother, father]);
+        self.clones.push(false);
+        Ok(self.parents.len() - 1)
+    }
+    /// Add a clone of an individual, returning its identifier. A clone is genetically identical
+    /// to its parent, so its kinship with any individual (including itself) and its inbreeding
+    /// are those of its parent, unlike an offspring of selfing.
+    /// # Errors
+    /// Will return `Err<'static str>` if the parent is not already in the pedigree.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::Pedigree;
+    /// let mut pedigree = Pedigree::new();
+    /// let parent = pedigree.add(None, None).unwrap();
+    /// let clone = pedigree.add_clone(parent).unwrap();
+    /// let selfed = pedigree.add(Some(parent), Some(parent)).unwrap();
+    /// assert_eq!(pedigree.kinship(parent, clone), 0.5);
+    /// assert_eq!((pedigree.inbreeding(clone), pedigree.inbreeding(selfed)), (0.0, 0.5));
+    /// ```
+    pub fn add_clone(&mut self, parent: usize) -> Result<usize, &'static str> {
+        let id = self.add(Some(parent), None)?;
+        self.clones[id] = true;
+        Ok(id)
+    }
+    /// Return the number of individuals in the pedigree.
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+    /// Return whether the pedigree has no individuals.
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+    /// Return the mother and father of an individual, if known (for a clone, its parent and then
+    /// `None`).
+    pub fn get_parents(&self, id: usize) -> Option<[Option<usize>; 2]> {
+        self.parents.get(id).copied()
+    }
+    /// Return the given individuals and all their ancestors in order of identifier, so parents
+    /// come before their offspring, each with its parents and whether it is a clone.
+    pub(crate) fn lineage(&self, ids: &[usize]) -> Vec<(usize, [Option<usize>; 2], bool)> {
+        let mut included = vec![false; self.parents.len()];
+        let mut pending = ids.to_vec();
+        while let Some(id) = pending.pop() {
+            if !std::mem::replace(&mut included[id], true) {
+                pending.extend(self.parents[id].iter().flatten());
+            }
+        }
+        (0..self.parents.len())
+            .filter(|x| included[*x])
+            .map(|x| (x, self.parents[x], self.clones[x]))
+            .collect()
+    }
+    /// Return whether an individual is a clone of its parent.
+    pub fn is_clone(&self, id: usize) -> bool {
+        self.clones.get(id).copied().unwrap_or(false)
+    }
+    /// Return the individual a clone was copied from, following chains of clones, or the
+    /// individual itself if it is not a clone.
+    fn origin(&self, mut id: usize) -> usize {
+        while self.clones[id] {
+            id = self.parents[id][0].expect("clones have a parent");
+        }
+        id
+    }
+    /// Return the kinship (coancestry) of two individuals: the probability that alleles drawn
+    /// at random from each at the same locus are identical by descent. Founders are assumed to
+    /// be unrelated and not inbred.
+    /// # Panics
+    /// Panics if either individual is not in the pedigree.
+    pub fn kinship(&self, first: usize, second: usize) -> f64 {
+        Kinship::new(self).get(first, second)
+    }
+    /// Return the inbreeding coefficient of an individual: the kinship of its parents.
+    /// # Panics
+    /// Panics if the individual is not in the pedigree.
+    pub fn inbreeding(&self, id: usize) -> f64 {
+        Kinship::new(self).inbreeding(id)
+    }
+    /// Return the mean inbreeding coefficient of the given individuals (zero if there are none).
+    /// # Panics
+    /// Panics if an individual is not in the pedigree.
+    pub fn mean_inbreeding(&self, ids: &[usize]) -> f64 {
+        if ids.is_empty() {
+            return 0.0;
+        }
+        let mut kinship = Kinship::new(self);
+        ids.iter().map(|x| kinship.inbreeding(*x)).sum::<f64>() / ids.len() as f64
+    }
+    /// Return the matrix of kinships between the given individuals.
+    /// # Panics
+    /// Panics if an individual is not in the pedigree.
+    pub fn kinship_matrix(&self, ids: &[usize]) -> Vec<Vec<f64>> {
+        let mut kinship = Kinship::new(self);
+        ids.iter()
+            .map(|a| ids.iter().map(|b| kinship.get(*a, *b)).collect())
+            .collect()
+    }
+    /// Return the matrix of coefficients of relationship between the given individuals,
+    /// `2 f(x, y) / sqrt((1 + F(x)) (1 + F(y)))` for kinship `f` and inbreeding `F`, which is 0.5
+    /// for parents and offspring or full siblings in an outbred pedigree.
+    /// # Panics
+    /// Panics if an individual is not in the pedigree.
+    pub fn relatedness_matrix(&self, ids: &[usize]) -> Vec<Vec<f64>> {
+        let mut kinship = Kinship::new(self);
+        let inbreeding: Vec<f64> = ids.iter().map(|x| kinship.inbreeding(*x)).collect();
+        ids.iter()
+            .enumerate()
+            .map(|(i, a)| {
+                ids.iter()
+                    .enumerate()
+                    .map(|(j, b)| {
+                        2.0 * kinship.get(*a, *b)
+                            / ((1.0 + inbreeding[i]) * (1.0 + inbreeding[j])).sqrt()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    /// Return the mean kinship of each of the given individuals with all of them (including
+    /// itself), the measure used to rank animals for breeding in captive populations.
+    /// # Panics
+    /// Panics if an individual is not in the pedigree.
+    pub fn mean_kinship(&self, ids: &[usize]) -> Vec<f64> {
+        self.kinship_matrix(ids)
+            .iter()
+            .map(|row| row.iter().sum::<f64>() / ids.len() as f64)
+            .collect()
+    }
+    /// Suggest breeding pairs among the given individuals to minimize mean kinship: the
+    /// individual with the lowest mean kinship is paired with the remaining individual it is
+    /// least related to (ties broken by lower mean kinship), and so on until fewer than two
+    /// are left. Partners are then exchanged between pairs whenever that lowers the total
+    /// kinship of the pairs. Returns each pair with its kinship, which is also the inbreeding
+    /// coefficient of their offspring.
+    /// # Panics
+    /// Panics if an individual is not in the pedigree.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::Pedigree;
+    /// let mut pedigree = Pedigree::new();
+    /// for _ in 0..2 {
+    ///     pedigree.add(None, None).unwrap();
+    /// }
+    /// let (a, b) = (pedigree.add(Some(0), Some(1)).unwrap(), pedigree.add(Some(0), Some(1)).unwrap());
+    /// let c = pedigree.add(None, None).unwrap();
+    /// let d = pedigree.add(None, None).unwrap();
+    /// // The siblings are not paired together.
+    /// for (first, second, kinship) in pedigree.suggest_pairings(&[a, b, c, d]) {
+    ///     assert_eq!(kinship, 0.0);
+    ///     assert!(!(first == a && second == b) && !(first == b && second == a));
+    /// }
+    /// ```
+    pub fn suggest_pairings(&self, ids: &[usize]) -> Vec<(usize, usize, f64)> {
+        let matrix = self.kinship_matrix(ids);
+        let mean: Vec<f64> = matrix
+            .iter()
+            .map(|row| row.iter().sum::<f64>() / ids.len() as f64)
+            .collect();
+        let mut remaining: Vec<usize> = (0..ids.len()).collect();
+        remaining.sort_by(|a, b| mean[*a].total_cmp(&mean[*b]));
+        let mut pairs = Vec::new();
+        while remaining.len() >= 2 {
+            let first = remaining.remove(0);
+            let (position, second) = remaining
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    matrix[first][**a]
+                        .total_cmp(&matrix[first][**b])
+                        .then(mean[**a].total_cmp(&mean[**b]))
+                })
+                .map(|(position, x)| (position, *x))
+                .unwrap();
+            remaining.remove(position);
+            pairs.push((first, second));
+        }
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for i in 0..pairs.len() {
+                for j in i + 1..pairs.len() {
+                    let ((a, b), (c, d)) = (pairs[i], pairs[j]);
+                    let current = matrix[a][b] + matrix[c][d];
+                    if matrix[a][c] + matrix[b][d] < current - 1e-12 {
+                        (pairs[i], pairs[j]) = ((a, c), (b, d));
+                        improved = true;
+                    } else if matrix[a][d] + matrix[b][c] < current - 1e-12 {
+                        (pairs[i], pairs[j]) = ((a, d), (c, b));
+                        improved = true;
+                    }
+                }
+            }
+        }
+        pairs
+            .into_iter()
+            .map(|(a, b)| (ids[a], ids[b], matrix[a][b]))
+            .collect()
+    }
+}
+
+/// Kinships computed with the recursive method, remembering every pair computed so far.
+struct Kinship<'a> {
+    pedigree: &'a Pedigree,
+    memo: HashMap<(usize, usize), f64>,
+}
+impl<'a> Kinship<'a> {
+    fn new(pedigree: &'a Pedigree) -> Kinship<'a> {
+        Kinship {
+            pedigree,
+            memo: HashMap::new(),
+        }
+    }
+    fn inbreeding(&mut self, id: usize) -> f64 {
+        match self.pedigree.parents[self.pedigree.origin(id)] {
+            [Some(mother), Some(father)] => self.get(mother, father),
+            _ => 0.0,
+        }
+    }
+    /// Return the pair of individuals whose kinship is that of the given pair, with clones
+    /// replaced by their origins and the younger individual first.
+    fn key(&self, first: usize, second: usize) -> (usize, usize) {
+        let (first, second) = (self.pedigree.origin(first), self.pedigree.origin(second));
+        (first.max(second), first.min(second))
+    }
+    /// Return the kinship of two individuals. The recursion is unrolled onto a stack, since
+    /// pedigrees from long simulations can be many generations deep.
+    fn get(&mut self, first: usize, second: usize) -> f64 {
+        let key = self.key(first, second);
+        let mut stack = vec![key];
+        while let Some(&(a, b)) = stack.last() {
+            if self.memo.contains_key(&(a, b)) {
+                stack.pop();
+                continue;
+            }
+            // The younger individual (with the larger identifier) cannot be an ancestor of the
+            // other, so the recursion follows its parents.
+            let parents = self.pedigree.parents[a];
+            let needed: Vec<(usize, usize)> = if a == b {
+                match parents {
+                    [Some(m), Some(f)] => vec![self.key(m, f)],
+                    _ => Vec::new(),
+                }
+            } else {
+                parents.iter().flatten().map(|p| self.key(*p, b)).collect()
+            };
+            let missing: Vec<_> = needed
+                .iter()
+                .filter(|x| !self.memo.contains_key(x))
+                .copied()
+                .collect();
+            if !missing.is_empty() {
+                stack.extend(missing);
+                continue;
+            }
+            let value = if a == b {
+                0.5 * (1.0 + needed.first().map_or(0.0, |x| self.memo[x]))
+            } else {
+                0.5 * needed.iter().map(|x| self.memo[x]).sum::<f64>()
+            };
+            self.memo.insert((a, b), value);
+            stack.pop();
+        }
+        self.memo[&key]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinship_coefficients() {
+        let mut pedigree = Pedigree::new();
+        assert!(pedigree.add(Some(0), None).is_err());
+        let (a, b, c) = (
+            pedigree.add(None, None).unwrap(),
+            pedigree.add(None, None).unwrap(),
+            pedigree.add(None, None).unwrap(),
+        );
+        let child = pedigree.add(Some(a), Some(b)).unwrap();
+        let half = pedigree.add(Some(a), Some(c)).unwrap();
+        let single = pedigree.add(Some(b), None).unwrap();
+        assert_eq!(pedigree.kinship(a, a), 0.5);
+        assert_eq!(pedigree.kinship(a, child), 0.25);
+        assert_eq!(pedigree.kinship(child, half), 0.125);
+        assert_eq!(pedigree.kinship(a, c), 0.0);
+        assert_eq!(pedigree.kinship(single, single), 0.5);
+        assert_eq!(pedigree.kinship(single, child), 0.125);
+        let selfed = pedigree.add(Some(child), Some(child)).unwrap();
+        assert_eq!(pedigree.inbreeding(selfed), 0.5);
+        let relatedness = pedigree.relatedness_matrix(&[a, child, selfed]);
+        assert!((relatedness[0][1] - 0.5).abs() < 1e-12);
+        assert!((relatedness[2][2] - 1.0).abs() < 1e-12);
+        let mean = pedigree.mean_kinship(&[a, b, child]);
+        assert!((mean[2] - 1.0 / 3.0).abs() < 1e-12);
+    }
+    #[test]
+    fn deep_pedigrees() {
+        let mut pedigree = Pedigree::new();
+        let mut line = pedigree.add(None, None).unwrap();
+        for _ in 0..100_000 {
+            line = pedigree.add(Some(line), None).unwrap();
+        }
+        assert_eq!(pedigree.kinship(0, 1), 0.25);
+        assert_eq!(pedigree.kinship(line, 0), 0.0);
+        assert_eq!(pedigree.len(), 100_001);
+    }
+}
@@ -0,0 +1,384 @@
+//! This module contains a polygenic quantitative trait for the individual-based model, such as body size or thermal tolerance. Each individual's breeding value adds up small allelic effects at many loci and is passed on by the ordinary inheritance of its genotype, and its phenotype adds an environmental deviation. Selection on the phenotype can lower survival or breeding, so a population whose optimum moves away (after warming, say) declines unless it adapts fast enough: the evolutionary-rescue questions that a matrix model cannot ask.
+use super::{Genotype, IndividualBasedModel, IndividualSimulation};
+use crate::populations::population_level_simulation::{CovariateSeries, CovariateTrack};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+/// This enum lists the vital rates that selection on a [`QuantitativeTrait`] can act on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectedRate {
+    Survival,
+    Fecundity,
+    Both,
+}
+
+/// This struct holds an additive quantitative trait. The breeding value of an individual is the
+/// sum, over the trait loci and both alleles, of the effect of each allele
+/// (`allele_effects[i][code]` for an allele of code `code` at the `i`-th trait locus; missing
+/// alleles and codes beyond the list have no effect). Its phenotype is the breeding value plus a
+/// normal environmental deviation with standard deviation `environmental_sd`, drawn once when
+/// the individual is born (or founds the population).
+///
+/// With selection, the phenotype `z` has Gaussian fitness `exp(-(z - optimum)^2 / (2 width^2))`,
+/// which multiplies the probability of surviving each step, the mean number of offspring, or
+/// both. A narrow `width` means strong selection.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{Genotype, QuantitativeTrait, SelectedRate};
+/// // Allele 1 at either of two loci adds 0.5 to the trait.
+/// let size = QuantitativeTrait::build(vec![0, 1], vec![vec![0.0, 0.5], vec![0.0, 0.5]], 0.3)
+///     .unwrap()
+///     .with_selection(SelectedRate::Survival, 1.0, 2.0)
+///     .unwrap();
+/// let genotype = Genotype::new(vec![[Some(1), Some(1)], [Some(0), Some(1)]]).unwrap();
+/// assert_eq!(size.breeding_value(&genotype), 1.5);
+/// assert!((size.fitness(1.0, 0) - (-0.5f64).exp()).abs() < 1e-12);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantitativeTrait {
+    loci: Vec<usize>,
+    allele_effects: Vec<Vec<f64>>,
+    environmental_sd: f64,
+    selected_rate: Option<SelectedRate>,
+    width: f64,
+    optimum: f64,
+    optimum_series: CovariateTrack,
+}
+impl QuantitativeTrait {
+    /// Build a trait from the indices of its loci among the loci of the model, the effects of
+    /// the alleles at each of them, and the standard deviation of the environmental deviation.
+    /// # Errors
+    /// Will return `Err<'static str>` if there is not one list of effects per locus, or an effect
+    /// or the standard deviation is not finite, or the standard deviation is negative.
+    pub fn build(
+        loci: Vec<usize>,
+        allele_effects: Vec<Vec<f64>>,
+        environmental_sd: f64,
+    ) -> Result<QuantitativeTrait, &'static str> {
+        if loci.len() != allele_effects.len() {
+            return Err("There must be one list of allele effects per trait locus.");
+        }
+        if allele_effects.iter().flatten().any(|x| !x.is_finite()) {
+            return Err("Allele effects must be finite.");
+        }
+        if !environmental_sd.is_finite() || environmental_sd < 0.0 {
+            return Err("The environmental standard deviation must be finite and not negative.");
+        }
+        Ok(QuantitativeTrait {
+            loci,
+            allele_effects,
+            environmental_sd,
+            selected_rate: None,
+            width: f64::INFINITY,
+            optimum: 0.0,
+            optimum_series: CovariateTrack::default(),
+        })
+    }
+    /// Return the trait with Gaussian selection of the given width around a fixed optimum,
+    /// acting on the given vital rates. An optimum series, whether added before or after, takes
+    /// the place of the fixed optimum.
+    /// # Errors
+    /// Will return `Err<'static str>` if the width is not positive or the optimum is not finite.
+    pub fn with_selection(
+        mut self,
+        rate: SelectedRate,
+        width: f64,
+        optimum: f64,
+    ) -> Result<Self, &'static str> {
+        if !width.is_finite() || width <= 0.0 || !optimum.is_finite() {
+            return Err("Selection needs a finite, positive width and a finite optimum.");
+        }
+        self.selected_rate = Some(rate);
+        self.width = width;
+        self.optimum = optimum;
+        Ok(self)
+    }
+    /// Return the trait with the optimum of each step given by a covariate in the series (using
+    /// its last value for steps beyond the series), for an optimum that moves with the
+    /// environment.
+    /// # Errors
+    /// Will return `Err<'static str>` if the series is empty or has no column for the covariate.
+    pub fn with_optimum_series(
+        mut self,
+        series: &CovariateSeries,
+        covariate: &str,
+    ) -> Result<Self, &'static str> {
+        self.optimum_series = CovariateTrack::from_series(series, covariate)?;
+        Ok(self)
+    }
+    /// Return the indices of the trait loci.
+    pub fn get_loci(&self) -> &Vec<usize> {
+        &self.loci
+    }
+    /// Return the standard deviation of the environmental deviation.
+    pub fn get_environmental_sd(&self) -> f64 {
+        self.environmental_sd
+    }
+    /// Return the vital rates under selection, if any.
+    pub fn get_selected_rate(&self) -> Option<SelectedRate> {
+        self.selected_rate
+    }
+    /// Return the breeding value of a genotype.
+    pub fn breeding_value(&self, genotype: &Genotype) -> f64 {
+        self.loci
+            .iter()
+            .zip(&self.allele_effects)
+            .filter_map(|(locus, effects)| {
+                let alleles = genotype.get(*locus)?;
+                Some(
+                    alleles
+                        .into_iter()
+                        .flatten()
+                        .map(|x| effects.get(x as usize).copied().unwrap_or(0.0))
+                        .sum::<f64>(),
+                )
+            })
+            .sum()
+    }
+    /// Draw the phenotype of an individual with the given breeding value.
+    pub fn phenotype<R: Rng>(&self, breeding_value: f64, rng: &mut R) -> f64 {
+        match self.environmental_sd > 0.0 {
+            true => {
+                breeding_value
+                    + Normal::new(0.0, self.environmental_sd)
+                        .expect("the standard deviation is valid")
+                        .sample(rng)
+            }
+            false => breeding_value,
+        }
+    }
+    /// Return the optimum during the step after `steps_taken` steps.
+    pub fn optimum(&self, steps_taken: u32) -> f64 {
+        self.optimum_series
+            .value(steps_taken)
+            .unwrap_or(self.optimum)
+    }
+    /// Return the fitness of a phenotype during the step after `steps_taken` steps (one without
+    /// selection).
+    pub fn fitness(&self, phenotype: f64, steps_taken: u32) -> f64 {
+        if self.selected_rate.is_none() {
+            return 1.0;
+        }
+        let distance = phenotype - self.optimum(steps_taken);
+        (-distance * distance / (2.0 * self.width * self.width)).exp()
+    }
+    /// Return the multiplier of the probability of surviving the step.
+    pub fn survival(&self, phenotype: f64, steps_taken: u32) -> f64 {
+        match self.selected_rate {
+            Some(SelectedRate::Survival | SelectedRate::Both) => {
+                self.fitness(phenotype, steps_taken)
+            }
+            _ => 1.0,
+        }
+    }
+    /// Return the multiplier of the mean number of offspring during the step.
+    pub fn breeding(&self, phenotype: f64, steps_taken: u32) -> f64 {
+        match self.selected_rate {
+            Some(SelectedRate::Fecundity | SelectedRate::Both) => {
+                self.fitness(phenotype, steps_taken)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+impl IndividualBasedModel {
+    /// Return the model with every individual carrying the trait. Breeding values follow the
+    /// genotypes, so the founder genotypes must be set first. With selection, the number of
+    /// offspring is drawn with its mean scaled by the fecundity fitness, and an individual must
+    /// survive the survival fitness after its body condition and before any mortality hazard and
+    /// the transition table.
+    /// # Errors
+    /// Will return `Err<'static str>` if a trait locus is not a locus of the model.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     Genotype, IndividualBasedModel, Locus, QuantitativeTrait, SelectedRate, StageTransition,
+    ///     TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.0).unwrap()]).unwrap();
+    /// let genotypes = (0..200).map(|x| Genotype::new(vec![[Some(x % 2), Some(x / 100)]]).unwrap()).collect();
+    /// // The optimum sits at the largest breeding value.
+    /// let size = QuantitativeTrait::build(vec![0], vec![vec![0.0, 1.0]], 0.5).unwrap()
+    ///     .with_selection(SelectedRate::Survival, 1.0, 2.0).unwrap();
+    /// let model = IndividualBasedModel::build(vec![200.0].into(), table, vec![1.5])
+    ///     .unwrap()
+    ///     .with_founder_genotypes(vec![Locus::new("L1")], genotypes)
+    ///     .unwrap()
+    ///     .with_quantitative_trait(size)
+    ///     .unwrap();
+    /// assert!((model.founder_heritability().unwrap() - 0.5 / 0.75).abs() < 1e-9);
+    /// let mut simulation = model.simulation(0, 1);
+    /// simulation.run(5);
+    /// assert!(simulation.mean_breeding_value() > 1.0);
+    /// ```
+    pub fn with_quantitative_trait(
+        mut self,
+        quantitative_trait: QuantitativeTrait,
+    ) -> Result<Self, &'static str> {
+        if quantitative_trait
+            .loci
+            .iter()
+            .any(|x| *x >= self.loci.len())
+        {
+            return Err("A trait locus is not a locus of the model.");
+        }
+        self.quantitative_trait = Some(quantitative_trait);
+        Ok(self)
+    }
+    /// Return the quantitative trait of the model, if any.
+    pub fn get_quantitative_trait(&self) -> Option<&QuantitativeTrait> {
+        self.quantitative_trait.as_ref()
+    }
+    /// Return the narrow-sense heritability of the trait among the founders, the variance of
+    /// their breeding values over the variance of their phenotypes, or `None` without a trait or
+    /// without phenotypic variance.
+    pub fn founder_heritability(&self) -> Option<f64> {
+        let quantitative_trait = self.quantitative_trait.as_ref()?;
+        let values: Vec<f64> = self
+            .founder_genotypes
+            .iter()
+            .map(|x| quantitative_trait.breeding_value(x))
+            .collect();
+        let additive = variance(&values);
+        let phenotypic = additive + quantitative_trait.environmental_sd.powi(2);
+        (phenotypic > 0.0).then(|| additive / phenotypic)
+    }
+}
+
+impl IndividualSimulation<'_> {
+    /// Return the mean breeding value of the individuals alive now (zero if there are none).
+    pub fn mean_breeding_value(&self) -> f64 {
+        mean(self.individuals.iter().map(|x| x.breeding_value))
+    }
+    /// Return the variance of the breeding values of the individuals alive now, the additive
+    /// genetic variance available to selection.
+    pub fn additive_variance(&self) -> f64 {
+        let values: Vec<f64> = self.individuals.iter().map(|x| x.breeding_value).collect();
+        variance(&values)
+    }
+    /// Return the mean phenotype of the individuals alive now (zero if there are none).
+    pub fn mean_phenotype(&self) -> f64 {
+        mean(self.individuals.iter().map(|x| x.phenotype))
+    }
+}
+
+/// Return the mean of the values, or zero if there are none.
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (count, sum) = values.fold((0usize, 0.0), |(n, s), x| (n + 1, s + x));
+    match count {
+        0 => 0.0,
+        _ => sum / count as f64,
+    }
+}
+
+/// Return the population variance of the values, or zero if there are none.
+fn variance(values: &[f64]) -> f64 {
+    let centre = mean(values.iter().copied());
+    mean(values.iter().map(|x| (x - centre).powi(2)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        Locus, OffspringDistribution, StageTransition, TransitionTable,
+    };
+    use crate::simulation::Simulation;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn model(selection: Option<f64>) -> IndividualBasedModel {
+        // Annual generations of constant size, with ten loci of small effect.
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.0).unwrap()]).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        let genotypes = (0..300)
+            .map(|_| {
+                Genotype::new(
+                    (0..10)
+                        .map(|_| [Some(rng.gen_range(0..2)), Some(rng.gen_range(0..2))])
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let mut size =
+            QuantitativeTrait::build((0..10).collect(), vec![vec![0.0, 0.1]; 10], 0.2).unwrap();
+        if let Some(optimum) = selection {
+            size = size
+                .with_selection(SelectedRate::Fecundity, 0.5, optimum)
+                .unwrap();
+        }
+        IndividualBasedModel::build(vec![300.0].into(), table, vec![0.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 1,
+                    breeding_probability: 1.0,
+                },
+            )
+            .unwrap()
+            .with_founder_genotypes(
+                (0..10).map(|x| Locus::new(&x.to_string())).collect(),
+                genotypes,
+            )
+            .unwrap()
+            .with_quantitative_trait(size)
+            .unwrap()
+    }
+
+    #[test]
+    fn selection_shifts_the_breeding_value() {
+        let neutral = model(None);
+        let mut simulation = neutral.simulation(0, 6);
+        let start = simulation.mean_breeding_value();
+        assert!((start - 1.0).abs() < 0.1);
+        assert!((simulation.mean_phenotype() - start).abs() < 0.05);
+        let heritability = neutral.founder_heritability().unwrap();
+        let additive = simulation.additive_variance();
+        assert!((heritability - additive / (additive + 0.04)).abs() < 1e-9);
+        simulation.run(5);
+        assert!((simulation.mean_breeding_value() - start).abs() < 0.1);
+        let selected = model(Some(2.0));
+        let mut simulation = selected.simulation(0, 6);
+        simulation.run(5);
+        assert!(simulation.mean_breeding_value() > start + 0.2);
+        assert!(simulation.additive_variance() < additive);
+    }
+    #[test]
+    fn trait_validation() {
+        assert!(QuantitativeTrait::build(vec![0], Vec::new(), 0.1).is_err());
+        assert!(QuantitativeTrait::build(vec![0], vec![vec![f64::NAN]], 0.1).is_err());
+        assert!(QuantitativeTrait::build(vec![0], vec![vec![1.0]], -0.1).is_err());
+        let size = QuantitativeTrait::build(vec![3], vec![vec![1.0]], 0.1).unwrap();
+        assert_eq!(size.fitness(10.0, 0), 1.0);
+        assert!(size
+            .clone()
+            .with_selection(SelectedRate::Both, 0.0, 1.0)
+            .is_err());
+        let table = TransitionTable::build(Vec::new()).unwrap();
+        let model = IndividualBasedModel::build(vec![1.0].into(), table, vec![0.0]).unwrap();
+        assert!(model.with_quantitative_trait(size).is_err());
+        let warming = CovariateSeries::build(
+            vec![String::from("t")],
+            vec![vec![0.0], vec![0.5], vec![1.0]],
+        )
+        .unwrap();
+        let moving = QuantitativeTrait::build(Vec::new(), Vec::new(), 0.0)
+            .unwrap()
+            .with_selection(SelectedRate::Survival, 1.0, 0.0)
+            .unwrap()
+            .with_optimum_series(&warming, "t")
+            .unwrap();
+        assert_eq!(moving.optimum(1), 0.5);
+        assert_eq!(moving.optimum(8), 1.0);
+        let series_first = QuantitativeTrait::build(Vec::new(), Vec::new(), 0.0)
+            .unwrap()
+            .with_optimum_series(&warming, "t")
+            .unwrap()
+            .with_selection(SelectedRate::Survival, 1.0, 0.0)
+            .unwrap();
+        assert_eq!(series_first, moving);
+        assert_eq!(moving.survival(1.0, 2), 1.0);
+        assert_eq!(moving.breeding(0.0, 2), 1.0);
+    }
+}
@@ -0,0 +1,173 @@
+//! This module contains the distributions that the number of offspring of each breeding individual is drawn from in the individual-based model. The mean number of offspring sets the growth rate, but the variance among parents (reproductive skew) sets the effective population size, so both can be controlled.
+use rand::Rng;
+use rand_distr::{Distribution, Gamma, Poisson};
+
+/// This enum describes the distribution of the number of offspring produced by one individual in
+/// one step:
+/// - `Poisson`: offspring counts with the given mean and a variance equal to the mean.
+/// - `NegativeBinomial`: offspring counts with the given mean and a variance of
+///   `mean + mean^2 / dispersion`, so a small `dispersion` means a few individuals produce most
+///   of the offspring. As `dispersion` grows the distribution approaches the Poisson.
+/// - `Clutch`: with probability `breeding_probability` an individual breeds and produces exactly
+///   `size` offspring; otherwise it produces none.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OffspringDistribution {
+    Poisson {
+        mean: f64,
+    },
+    NegativeBinomial {
+        mean: f64,
+        dispersion: f64,
+    },
+    Clutch {
+        size: u32,
+        breeding_probability: f64,
+    },
+}
+impl OffspringDistribution {
+    /// Check that the parameters of the distribution are valid.
+    /// # Errors
+    /// Will return `Err<'static str>` if a mean is negative or not finite, a dispersion is not
+    /// positive, or a breeding probability is not between 0 and 1.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let valid = match *self {
+            OffspringDistribution::Poisson { mean } => mean.is_finite() && mean >= 0.0,
+            OffspringDistribution::NegativeBinomial { mean, dispersion } => {
+                mean.is_finite() && mean >= 0.0 && dispersion.is_finite() && dispersion > 0.0
+            }
+            OffspringDistribution::Clutch {
+                breeding_probability,
+                ..
+            } => (0.0..=1.0).contains(&breeding_probability),
+        };
+        match valid {
+            true => Ok(()),
+            false => Err("Offspring distributions need finite, non-negative means, positive dispersions, and breeding probabilities between 0 and 1."),
+        }
+    }
+    /// Return the mean number of offspring.
+    pub fn mean(&self) -> f64 {
+        match *self {
+            OffspringDistribution::Poisson { mean }
+            | OffspringDistribution::NegativeBinomial { mean, .. } => mean,
+            OffspringDistribution::Clutch {
+                size,
+                breeding_probability,
+            } => size as f64 * breeding_probability,
+        }
+    }
+    /// Return the variance of the number of offspring.
+    pub fn variance(&self) -> f64 {
+        match *self {
+            OffspringDistribution::Poisson { mean } => mean,
+            OffspringDistribution::NegativeBinomial { mean, dispersion } => {
+                mean + mean * mean / dispersion
+            }
+            OffspringDistribution::Clutch {
+                size,
+                breeding_probability,
+            } => (size as f64).powi(2) * breeding_probability * (1.0 - breeding_probability),
+        }
+    }
+    /// Return the distribution with its mean multiplied by `factor`, keeping its shape. For a
+    /// clutch, the breeding probability is scaled (up to a maximum of one) and the clutch size is
+    /// kept.
+    pub fn scaled(&self, factor: f64) -> OffspringDistribution {
+        match *self {
+            OffspringDistribution::Poisson { mean } => OffspringDistribution::Poisson {
+                mean: mean * factor,
+            },
+            OffspringDistribution::NegativeBinomial { mean, dispersion } => {
+                OffspringDistribution::NegativeBinomial {
+                    mean: mean * factor,
+                    dispersion,
+                }
+            }
+            OffspringDistribution::Clutch {
+                size,
+                breeding_probability,
+            } => OffspringDistribution::Clutch {
+                size,
+                breeding_probability: (breeding_probability * factor).min(1.0),
+            },
+        }
+    }
+    /// Draw a number of offspring.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> u32 {
+        let poisson = |mean: f64, rng: &mut R| match Poisson::new(mean) {
+            Ok(poisson) => poisson.sample(rng) as u32,
+            Err(_) => 0,
+        };
+        match *self {
+            OffspringDistribution::Poisson { mean } => poisson(mean, rng),
+            OffspringDistribution::NegativeBinomial { mean, dispersion } => {
+                // A Poisson count whose mean is itself drawn from a Gamma distribution.
+                let mean = match Gamma::new(dispersion, mean / dispersion) {
+                    Ok(gamma) => gamma.sample(rng),
+                    Err(_) => 0.0,
+                };
+                poisson(mean, rng)
+            }
+            OffspringDistribution::Clutch {
+                size,
+                breeding_probability,
+            } => match rng.gen::<f64>() < breeding_probability {
+                true => size,
+                false => 0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn sample_moments() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for distribution in [
+            OffspringDistribution::Poisson { mean: 2.5 },
+            OffspringDistribution::NegativeBinomial {
+                mean: 2.5,
+                dispersion: 0.5,
+            },
+            OffspringDistribution::Clutch {
+                size: 5,
+                breeding_probability: 0.5,
+            },
+        ] {
+            let draws: Vec<f64> = (0..40_000)
+                .map(|_| distribution.sample(&mut rng) as f64)
+                .collect();
+            let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+            let variance =
+                draws.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (draws.len() - 1) as f64;
+            assert!((mean - distribution.mean()).abs() < 0.05 * distribution.mean());
+            assert!((variance - distribution.variance()).abs() < 0.1 * distribution.variance());
+        }
+        assert_eq!(
+            OffspringDistribution::Poisson { mean: 0.0 }.sample(&mut rng),
+            0
+        );
+    }
+    #[test]
+    fn scaling_and_validation() {
+        let clutch = OffspringDistribution::Clutch {
+            size: 4,
+            breeding_probability: 0.6,
+        };
+        assert_eq!(clutch.scaled(0.5).mean(), 1.2);
+        assert_eq!(clutch.scaled(3.0).mean(), 4.0);
+        assert!(OffspringDistribution::NegativeBinomial {
+            mean: 1.0,
+            dispersion: 0.0
+        }
+        .validate()
+        .is_err());
+        assert!(OffspringDistribution::Poisson { mean: -1.0 }
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! This module contains the modes of reproduction of the individual-based model. Many plants and invertebrates reproduce clonally (by apomixis, parthenogenesis, or vegetative spread) as well as, or instead of, sexually. A clone copies the genotype of its parent, apart from new mutations, and enters the pedigree as genetically identical to it, so clonal reproduction slows the loss of heterozygosity while limiting the creation of new genotypes. Hermaphrodites can also fertilize themselves, which random mating cannot approximate: selfing halves heterozygosity each generation, and the pedigree records both parents of a selfed offspring as the same individual so its inbreeding is counted correctly.
+use super::{DensityDependence, IndividualBasedModel, IndividualSimulation};
+
+/// This enum describes how offspring are produced:
+/// - `Sexual`: every offspring has a mother and a father (the default).
+/// - `Clonal`: every offspring is a clone of its parent.
+/// - `Mixed`: each offspring is a clone with probability `clonal_proportion`, and sexual
+///   otherwise. With sex determination, a female with no males to mate with produces only
+///   clones (facultative parthenogenesis).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReproductionMode {
+    #[default]
+    Sexual,
+    Clonal,
+    Mixed {
+        clonal_proportion: f64,
+    },
+}
+impl ReproductionMode {
+    /// Check that the mode is valid.
+    /// # Errors
+    /// Will return `Err<'static str>` if a clonal proportion is not between 0 and 1.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            ReproductionMode::Mixed { clonal_proportion }
+                if !(0.0..=1.0).contains(clonal_proportion) =>
+            {
+                Err("The clonal proportion must be between 0 and 1.")
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Return the probability that an offspring is a clone.
+    pub fn clonal_proportion(&self) -> f64 {
+        match self {
+            ReproductionMode::Sexual => 0.0,
+            ReproductionMode::Clonal => 1.0,
+            ReproductionMode::Mixed { clonal_proportion } => *clonal_proportion,
+        }
+    }
+}
+
+impl IndividualBasedModel {
+    /// Return the model with offspring produced in the given mode. A clone is born with the
+    /// genotype and sex of its parent, has the parent as its only parent, and is added to the
+    /// pedigree with [`super::Pedigree::add_clone`].
+    /// # Errors
+    /// Will return `Err<'static str>` if the mode is not valid.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, ReproductionMode, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.8).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![20.0].into(), table, vec![0.5])
+    ///     .unwrap()
+    ///     .with_reproduction_mode(ReproductionMode::Clonal)
+    ///     .unwrap()
+    ///     .with_pedigree();
+    /// let mut simulation = model.simulation(0, 3);
+    /// simulation.run(5);
+    /// let pedigree = simulation.get_pedigree().unwrap();
+    /// assert!((20..pedigree.len()).all(|x| pedigree.is_clone(x)));
+    /// ```
+    pub fn with_reproduction_mode(mut self, mode: ReproductionMode) -> Result<Self, &'static str> {
+        mode.validate()?;
+        self.reproduction_mode = mode;
+        Ok(self)
+    }
+    /// Return the mode of reproduction.
+    pub fn get_reproduction_mode(&self) -> ReproductionMode {
+        self.reproduction_mode
+    }
+    /// Return the model with an Allee effect on mating: while fewer than `threshold` individuals
+    /// are alive at the start of a step, each breeder finds a mate that step with probability
+    /// equal to the population divided by the threshold (see
+    /// [`DensityDependence::allee_factor`]), and produces no offspring otherwise. Wholly clonal
+    /// populations need no mates and are unaffected.
+    /// # Errors
+    /// Will return `Err<'static str>` if the threshold is not positive and finite.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.8).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![40.0].into(), table, vec![0.5]).unwrap();
+    /// let final_total = |model: &IndividualBasedModel| -> f64 {
+    ///     let output = model.projection(20, 5, 3);
+    ///     output.get_replicates().iter().map(|x| x.last().unwrap().total()).sum()
+    /// };
+    /// let limited = model.clone().with_mate_limitation(1000.0).unwrap();
+    /// assert!(final_total(&limited) < final_total(&model));
+    /// ```
+    pub fn with_mate_limitation(mut self, threshold: f64) -> Result<Self, &'static str> {
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err("The mate limitation threshold must be positive and finite.");
+        }
+        self.mate_limitation = DensityDependence::Allee {
+            threshold,
+            ceiling: None,
+        };
+        Ok(self)
+    }
+    /// Return the model with each allele of every offspring, sexual or clonal, mutating with the
+    /// given probability. A mutated allele changes to another allele of its locus chosen at random
+    /// from those carried by the founders (the K-allele model), so the founder genotypes must be
+    /// set first.
+    /// # Errors
+    /// Will return `Err<'static str>` if the rate is not between 0 and 1, or the model has no
+    /// genetics.
+    pub fn with_mutation_rate(mut self, rate: f64) -> Result<Self, &'static str> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err("The mutation rate must be between 0 and 1.");
+        }
+        if self.loci.is_empty() {
+            return Err("The founder genotypes must be set before the mutation rate.");
+        }
+        let mut allele_counts = vec![0u16; self.loci.len()];
+        for genotype in &self.founder_genotypes {
+            for (locus, alleles) in genotype.iter().enumerate() {
+                for code in alleles.into_iter().flatten() {
+                    allele_counts[locus] = allele_counts[locus].max(code + 1);
+                }
+            }
+        }
+        self.mutation_rate = rate;
+        self.allele_counts = allele_counts;
+        Ok(self)
+    }
+    /// Return the probability that an allele mutates when passed on.
+    pub fn get_mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+    /// Return the model with each sexual offspring of a hermaphrodite (an individual without a
+    /// sex) produced by self-fertilization with the given probability, and by random mating
+    /// otherwise. A selfed offspring has its parent as both mother and father, inherits two
+    /// gametes from it, and has an inbreeding coefficient of `(1 + F) / 2` for a parent with
+    /// inbreeding `F`, so a constant selfing rate `s` brings the mean inbreeding towards
+    /// `s / (2 - s)`.
+    /// # Errors
+    /// Will return `Err<'static str>` if the rate is not between 0 and 1.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.5).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![30.0].into(), table, vec![0.5])
+    ///     .unwrap()
+    ///     .with_selfing_rate(1.0)
+    ///     .unwrap()
+    ///     .with_pedigree();
+    /// let mut simulation = model.simulation(0, 2);
+    /// simulation.run(1);
+    /// let child = simulation.get_individuals().iter().find(|x| x.get_id() >= 30).unwrap();
+    /// assert_eq!(child.get_parents()[0], child.get_parents()[1]);
+    /// assert_eq!(simulation.get_pedigree().unwrap().inbreeding(child.get_id()), 0.5);
+    /// ```
+    pub fn with_selfing_rate(mut self, rate: f64) -> Result<Self, &'static str> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err("The selfing rate must be between 0 and 1.");
+        }
+        self.selfing_rate = rate;
+        Ok(self)
+    }
+    /// Return the probability that a sexual offspring of a hermaphrodite is selfed.
+    pub fn get_selfing_rate(&self) -> f64 {
+        self.selfing_rate
+    }
+}
+
+impl IndividualSimulation<'_> {
+    /// Return the mean inbreeding coefficient of the individuals alive now, from the pedigree, if
+    /// the model keeps one (see [`IndividualBasedModel::with_pedigree`]).
+    pub fn mean_inbreeding(&self) -> Option<f64> {
+        let ids: Vec<usize> = self.individuals.iter().map(|x| x.id).collect();
+        Some(self.pedigree.as_ref()?.mean_inbreeding(&ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        Genotype, Locus, OffspringDistribution, Sex, SexDetermination, StageTransition,
+        TransitionTable,
+    };
+    use crate::populations::population_level_simulation::CovariateSeries;
+    use crate::simulation::Simulation;
+
+    fn model(mode: ReproductionMode) -> IndividualBasedModel {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.5).unwrap()]).unwrap();
+        let genotypes = (0..40)
+            .map(|x| Genotype::new(vec![[Some(x % 4), Some(x % 3)]]).unwrap())
+            .collect();
+        IndividualBasedModel::build(vec![40.0].into(), table, vec![1.0])
+            .unwrap()
+            .with_founder_genotypes(vec![Locus::new("L1")], genotypes)
+            .unwrap()
+            .with_reproduction_mode(mode)
+            .unwrap()
+            .with_pedigree()
+    }
+
+    #[test]
+    fn clones_copy_their_parent() {
+        let model = model(ReproductionMode::Clonal);
+        let mut simulation = model.simulation(0, 4);
+        simulation.run(4);
+        let founders = model.founders();
+        for individual in simulation.get_individuals() {
+            let mut origin = individual.get_id();
+            while origin >= 40 {
+                origin = simulation
+                    .get_pedigree()
+                    .unwrap()
+                    .get_parents(origin)
+                    .unwrap()[0]
+                    .unwrap();
+            }
+            assert_eq!(individual.get_genotype(), founders[origin].get_genotype());
+            assert_eq!(
+                simulation
+                    .get_pedigree()
+                    .unwrap()
+                    .inbreeding(individual.get_id()),
+                0.0
+            );
+        }
+        let mutating = model.clone().with_mutation_rate(0.2).unwrap();
+        let mut simulation = mutating.simulation(0, 4);
+        simulation.run(4);
+        let changed = simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_id() >= 40)
+            .filter(|x| {
+                let parent = x.get_parents()[0];
+                parent < 40 && x.get_genotype() != founders[parent].get_genotype()
+            })
+            .count();
+        assert!(changed > 0);
+        assert!(simulation
+            .get_individuals()
+            .iter()
+            .flat_map(|x| x.get_genotype().iter().flatten())
+            .all(|x| x.is_some_and(|x| x < 4)));
+        assert!(model.clone().with_mutation_rate(1.5).is_err());
+        assert!(model
+            .with_reproduction_mode(ReproductionMode::Mixed {
+                clonal_proportion: -0.1
+            })
+            .is_err());
+    }
+    #[test]
+    fn mixed_reproduction() {
+        let model = model(ReproductionMode::Mixed {
+            clonal_proportion: 0.3,
+        });
+        let output = model.projection(3, 1, 5);
+        let mut simulation = model.simulation(0, 5);
+        simulation.run(3);
+        assert_eq!(&output.get_replicates()[0][2], simulation.state());
+        let pedigree = simulation.get_pedigree().unwrap();
+        let clones = (40..pedigree.len())
+            .filter(|x| pedigree.is_clone(*x))
+            .count();
+        let proportion = clones as f64 / (pedigree.len() - 40) as f64;
+        assert!((proportion - 0.3).abs() < 0.1);
+        // Females without males reproduce only clonally.
+        let temperature =
+            CovariateSeries::build(vec![String::from("t")], vec![vec![100.0]]).unwrap();
+        let all_female = model
+            .with_sex_determination(
+                SexDetermination::logistic("t", 0.0, 1.0).unwrap(),
+                &temperature,
+            )
+            .unwrap();
+        let mut simulation = all_female.simulation(0, 5);
+        let males: Vec<usize> = simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_sex() == Some(Sex::Male))
+            .map(|x| x.get_id())
+            .collect();
+        simulation.remove_individuals(&males);
+        simulation.run(2);
+        let pedigree = simulation.get_pedigree().unwrap();
+        assert!(pedigree.len() > 40);
+        assert!((40..pedigree.len()).all(|x| pedigree.is_clone(x)));
+    }
+    #[test]
+    fn selfing_inbreeding_approaches_equilibrium() {
+        // Non-overlapping generations of constant size.
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.0).unwrap()]).unwrap();
+        let model = IndividualBasedModel::build(vec![200.0].into(), table, vec![0.0])
+            .unwrap()
+            .with_offspring_distribution(
+                0,
+                OffspringDistribution::Clutch {
+                    size: 1,
+                    breeding_probability: 1.0,
+                },
+            )
+            .unwrap()
+            .with_selfing_rate(0.5)
+            .unwrap()
+            .with_pedigree();
+        let mut simulation = model.simulation(0, 9);
+        simulation.step();
+        assert!((simulation.mean_inbreeding().unwrap() - 0.25).abs() < 0.06);
+        simulation.run(9);
+        assert!((simulation.mean_inbreeding().unwrap() - 1.0 / 3.0).abs() < 0.05);
+        assert!(model.with_selfing_rate(-0.5).is_err());
+    }
+}
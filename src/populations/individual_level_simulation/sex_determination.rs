@@ -0,0 +1,199 @@
+//! This module contains environmental sex determination for the individual-based model. In turtles, crocodilians, and some fish the sex of each offspring depends on the temperature it develops at, so a warming climate can skew the sex ratio long before it affects survival, and the population only declines once males become too scarce.
+use super::{IndividualBasedModel, IndividualSimulation};
+use crate::populations::population_level_simulation::{
+    CovariateSeries, CovariateTrack, PopulationVector,
+};
+use crate::statistics::logistic;
+
+/// This enum lists the sexes of individuals in a model with sex determination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+/// This struct holds a logistic reaction norm giving the proportion of offspring that develop as
+/// females at a value `x` of an environmental covariate (usually incubation temperature):
+/// `1 / (1 + exp(-slope * (x - pivotal)))`. The `pivotal` value gives an even sex ratio; a
+/// positive `slope` produces more females as the covariate rises, as in sea turtles, and a
+/// negative one more males.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::SexDetermination;
+/// let loggerhead = SexDetermination::logistic("temperature", 29.0, 1.5).unwrap();
+/// assert_eq!(loggerhead.female_proportion(29.0), 0.5);
+/// assert!(loggerhead.female_proportion(31.0) > 0.95);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SexDetermination {
+    covariate: String,
+    pivotal: f64,
+    slope: f64,
+}
+impl SexDetermination {
+    /// Build a logistic reaction norm for the named covariate.
+    /// # Errors
+    /// Will return `Err<'static str>` if the pivotal value or the slope is not finite.
+    pub fn logistic(
+        covariate: &str,
+        pivotal: f64,
+        slope: f64,
+    ) -> Result<SexDetermination, &'static str> {
+        if !pivotal.is_finite() || !slope.is_finite() {
+            return Err("The pivotal value and slope of sex determination must be finite.");
+        }
+        Ok(SexDetermination {
+            covariate: String::from(covariate),
+            pivotal,
+            slope,
+        })
+    }
+    /// Return the name of the covariate that determines sex.
+    pub fn get_covariate(&self) -> &str {
+        &self.covariate
+    }
+    /// Return the covariate value that gives an even sex ratio.
+    pub fn get_pivotal(&self) -> f64 {
+        self.pivotal
+    }
+    /// Return the slope of the reaction norm.
+    pub fn get_slope(&self) -> f64 {
+        self.slope
+    }
+    /// Return the proportion of offspring that develop as females at a covariate value.
+    pub fn female_proportion(&self, value: f64) -> f64 {
+        logistic(self.slope * (value - self.pivotal))
+    }
+}
+
+impl IndividualBasedModel {
+    /// Return the model with the sex of each offspring set by the reaction norm, using the value
+    /// of its covariate in the series at the step the offspring is born (the last value for
+    /// steps beyond the series). Founders are female or male with equal probability.
+    ///
+    /// With sex determination, only females produce offspring, and the fecundity of a stage
+    /// counts offspring of both sexes per female. Each offspring's father is drawn from the
+    /// males of stages that can reproduce, so females produce no offspring in a step that starts
+    /// with no such males, unless they can reproduce clonally (see [`super::ReproductionMode`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if the series is empty or has no column for the covariate.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     IndividualBasedModel, Sex, SexDetermination, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::populations::population_level_simulation::CovariateSeries;
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 0.9).unwrap()]).unwrap();
+    /// let warming = CovariateSeries::build(
+    ///     vec![String::from("temperature")],
+    ///     vec![vec![29.0], vec![30.0], vec![31.0]],
+    /// ).unwrap();
+    /// let model = IndividualBasedModel::build(vec![100.0].into(), table, vec![2.0])
+    ///     .unwrap()
+    ///     .with_sex_determination(SexDetermination::logistic("temperature", 29.0, 1.5).unwrap(), &warming)
+    ///     .unwrap();
+    /// assert_eq!(model.female_proportion(0), Some(0.5));
+    /// let mut simulation = model.simulation(0, 1);
+    /// simulation.run(3);
+    /// let females = simulation.counts_by_sex(Sex::Female).total();
+    /// assert!(females > simulation.counts_by_sex(Sex::Male).total());
+    /// ```
+    pub fn with_sex_determination(
+        mut self,
+        determination: SexDetermination,
+        series: &CovariateSeries,
+    ) -> Result<Self, &'static str> {
+        self.sex_covariate = CovariateTrack::from_series(series, determination.get_covariate())?;
+        self.sex_determination = Some(determination);
+        Ok(self)
+    }
+    /// Return the sex determination of the model, if any.
+    pub fn get_sex_determination(&self) -> Option<&SexDetermination> {
+        self.sex_determination.as_ref()
+    }
+    /// Return the proportion of offspring born female during the step after `steps_taken`
+    /// steps, or `None` if the model has no sex determination.
+    pub fn female_proportion(&self, steps_taken: u32) -> Option<f64> {
+        let determination = self.sex_determination.as_ref()?;
+        Some(determination.female_proportion(self.sex_covariate.value(steps_taken)?))
+    }
+}
+
+impl IndividualSimulation<'_> {
+    /// Return the number of individuals of a sex in each stage (zero if the model has no sex
+    /// determination).
+    pub fn counts_by_sex(&self, sex: Sex) -> PopulationVector {
+        let mut counts = vec![0.0; self.model.reproduction.len()];
+        for individual in self.individuals.iter().filter(|x| x.sex == Some(sex)) {
+            counts[individual.lifestage as usize] += 1.0;
+        }
+        PopulationVector::new(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{StageTransition, TransitionTable};
+    use crate::simulation::Simulation;
+
+    fn model(temperature: f64) -> IndividualBasedModel {
+        let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+        let series =
+            CovariateSeries::build(vec![String::from("temperature")], vec![vec![temperature]])
+                .unwrap();
+        IndividualBasedModel::build(vec![400.0].into(), table, vec![1.0])
+            .unwrap()
+            .with_sex_determination(
+                SexDetermination::logistic("temperature", 29.0, -2.0).unwrap(),
+                &series,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn offspring_sex_follows_the_reaction_norm() {
+        let cool = model(27.0);
+        assert!((cool.female_proportion(20).unwrap() - 0.982).abs() < 1e-3);
+        let mut simulation = cool.simulation(0, 5);
+        let founders = simulation.counts_by_sex(Sex::Female).total();
+        assert!((founders - 200.0).abs() < 40.0);
+        simulation.step();
+        let births = simulation.state().total() - 400.0;
+        // Only females breed, each producing one offspring on average.
+        assert!((births - founders).abs() < 4.0 * founders.sqrt());
+        let young_females = simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_id() >= 400 && x.get_sex() == Some(Sex::Female))
+            .count() as f64;
+        assert!((young_females / births - 0.982).abs() < 0.03);
+        assert!(simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_id() >= 400)
+            .all(|x| x.get_parents().len() == 2));
+    }
+    #[test]
+    fn a_population_without_males_stops_breeding() {
+        let hot = model(20.0);
+        let mut simulation = hot.simulation(0, 2);
+        simulation.run(2);
+        // Almost every offspring is female, so after removing the males nothing is born.
+        let males: Vec<usize> = simulation
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_sex() == Some(Sex::Male))
+            .map(|x| x.get_id())
+            .collect();
+        simulation.remove_individuals(&males);
+        let total = simulation.state().total();
+        simulation.step();
+        assert_eq!(simulation.state().total(), total);
+        let missing = CovariateSeries::build(vec![String::from("rainfall")], vec![vec![1.0]]);
+        let determination = SexDetermination::logistic("temperature", 29.0, 1.0).unwrap();
+        assert!(model(29.0)
+            .with_sex_determination(determination, &missing.unwrap())
+            .is_err());
+        assert!(SexDetermination::logistic("temperature", f64::NAN, 1.0).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! This module contains breeding-site limitation for the individual-based model. In territorial species only the individuals that hold a territory or nest site breed, and the rest wait as non-breeding floaters, so the number of breeders is capped however large the population grows. A matrix model has no way to express this, since its fecundity applies to every individual of a stage.
+use super::{IndividualBasedModel, IndividualSimulation, Sex};
+
+/// This enum lists how individuals compete for breeding sites, from the first to win a site to
+/// the last:
+/// - `Age`: the oldest individuals.
+/// - `Stage`: individuals of the latest stages, and the oldest within a stage.
+/// - `Dominance`: the individuals with the highest dominance score, a number between 0 and 1
+///   drawn at random when each individual is born (or founded) and kept for life.
+///
+/// Ties are won by the individual with the lowest identifier (the one born first).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BreederRanking {
+    Age,
+    Stage,
+    Dominance,
+}
+
+/// This struct holds the number of breeding sites available each step and how individuals are
+/// ranked for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BreedingSites {
+    sites: u32,
+    ranking: BreederRanking,
+}
+impl BreedingSites {
+    /// Create a limit of `sites` breeding sites, won in the order given by the ranking.
+    pub fn new(sites: u32, ranking: BreederRanking) -> BreedingSites {
+        BreedingSites { sites, ranking }
+    }
+    /// Return the number of breeding sites.
+    pub fn get_sites(&self) -> u32 {
+        self.sites
+    }
+    /// Return how individuals are ranked for the sites.
+    pub fn get_ranking(&self) -> BreederRanking {
+        self.ranking
+    }
+}
+
+impl IndividualBasedModel {
+    /// Return the model with breeding limited to the holders of a fixed number of breeding
+    /// sites. At the start of each step, the individuals of stages that can reproduce (only the
+    /// females, with sex determination) are ranked, and only the first `sites` of them breed;
+    /// the others are floaters that produce no offspring that step but survive and age as usual.
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::{
+    ///     BreederRanking, BreedingSites, IndividualBasedModel, StageTransition, TransitionTable,
+    /// };
+    /// use ecolysis_cmd::simulation::Simulation;
+    /// let table = TransitionTable::build(vec![StageTransition::new(0, 1, 0.5).unwrap(),
+    ///     StageTransition::new(1, 1, 0.9).unwrap()]).unwrap();
+    /// let model = IndividualBasedModel::build(vec![0.0, 80.0].into(), table, vec![0.0, 1.5])
+    ///     .unwrap()
+    ///     .with_breeding_sites(BreedingSites::new(30, BreederRanking::Age));
+    /// let simulation = model.simulation(0, 1);
+    /// assert_eq!(simulation.territory_holders().unwrap().len(), 30);
+    /// assert_eq!(simulation.floaters().len(), 50);
+    /// ```
+    pub fn with_breeding_sites(mut self, sites: BreedingSites) -> Self {
+        self.breeding_sites = Some(sites);
+        self
+    }
+    /// Return the breeding sites of the model, if breeding is limited.
+    pub fn get_breeding_sites(&self) -> Option<BreedingSites> {
+        self.breeding_sites
+    }
+}
+
+impl IndividualSimulation<'_> {
+    /// Return the identifiers of the individuals that could breed now, in the order of the
+    /// ranking: every individual of a stage that can reproduce, except males.
+    fn breeding_candidates(&self, ranking: BreederRanking) -> Vec<usize> {
+        let fecundity = &self.model.reproduction;
+        let mut candidates: Vec<_> = self
+            .individuals
+            .iter()
+            .filter(|x| fecundity[x.lifestage as usize].mean() > 0.0 && x.sex != Some(Sex::Male))
+            .collect();
+        match ranking {
+            BreederRanking::Age => {
+                candidates.sort_by(|a, b| b.age.cmp(&a.age).then(a.id.cmp(&b.id)))
+            }
+            BreederRanking::Stage => candidates.sort_by(|a, b| {
+                (b.lifestage, b.age)
+                    .cmp(&(a.lifestage, a.age))
+                    .then(a.id.cmp(&b.id))
+            }),
+            BreederRanking::Dominance => {
+                candidates.sort_by(|a, b| b.dominance.total_cmp(&a.dominance).then(a.id.cmp(&b.id)))
+            }
+        }
+        candidates.into_iter().map(|x| x.id).collect()
+    }
+    /// Return the identifiers of the individuals that hold a breeding site now, from the
+    /// highest ranked, or `None` if breeding is not limited.
+    pub fn territory_holders(&self) -> Option<Vec<usize>> {
+        let sites = self.model.breeding_sites?;
+        let mut holders = self.breeding_candidates(sites.ranking);
+        holders.truncate(sites.sites as usize);
+        Some(holders)
+    }
+    /// Return the identifiers of the individuals that could breed now but hold no breeding site
+    /// (empty if breeding is not limited).
+    pub fn floaters(&self) -> Vec<usize> {
+        match self.model.breeding_sites {
+            Some(sites) => self
+                .breeding_candidates(sites.ranking)
+                .into_iter()
+                .skip(sites.sites as usize)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        OffspringDistribution, StageTransition, TransitionTable,
+    };
+    use crate::simulation::Simulation;
+
+    fn model(ranking: BreederRanking) -> IndividualBasedModel {
+        let table = TransitionTable::build(vec![
+            StageTransition::new(0, 1, 1.0).unwrap(),
+            StageTransition::new(1, 1, 1.0).unwrap(),
+        ])
+        .unwrap();
+        IndividualBasedModel::build(vec![0.0, 50.0].into(), table, vec![0.0, 1.0])
+            .unwrap()
+            .with_offspring_distribution(
+                1,
+                OffspringDistribution::Clutch {
+                    size: 2,
+                    breeding_probability: 1.0,
+                },
+            )
+            .unwrap()
+            .with_initial_ages(vec![0, 3])
+            .unwrap()
+            .with_breeding_sites(BreedingSites::new(20, ranking))
+    }
+
+    #[test]
+    fn only_site_holders_breed() {
+        let model = model(BreederRanking::Age);
+        let mut simulation = model.simulation(0, 1);
+        // Every founder has the same age, so the first 20 identifiers win.
+        assert_eq!(
+            simulation.territory_holders(),
+            Some((0..20).collect::<Vec<_>>())
+        );
+        let trajectory = simulation.run(2);
+        assert_eq!(trajectory[0].get_vector(), &vec![40.0, 50.0]);
+        // Newly matured young are younger than the founders and remain floaters.
+        assert_eq!(trajectory[1].get_vector(), &vec![40.0, 90.0]);
+        assert!(simulation.floaters().iter().all(|x| *x >= 20));
+        assert_eq!(simulation.floaters().len(), 70);
+    }
+    #[test]
+    fn dominance_scores_rank_breeders() {
+        let model = model(BreederRanking::Dominance);
+        let simulation = model.simulation(0, 4);
+        let holders = simulation.territory_holders().unwrap();
+        let score = |id: usize| {
+            simulation
+                .get_individuals()
+                .iter()
+                .find(|x| x.get_id() == id)
+                .unwrap()
+                .get_dominance()
+        };
+        let weakest_holder = score(*holders.last().unwrap());
+        assert!(simulation
+            .floaters()
+            .into_iter()
+            .all(|x| score(x) <= weakest_holder));
+        assert!(holders.windows(2).all(|x| score(x[0]) >= score(x[1])));
+        let unlimited = IndividualBasedModel::build(
+            vec![5.0].into(),
+            TransitionTable::build(Vec::new()).unwrap(),
+            vec![1.0],
+        )
+        .unwrap();
+        assert!(unlimited.simulation(0, 0).territory_holders().is_none());
+    }
+}
@@ -0,0 +1,341 @@
+//! This module contains the table of stage transitions that drives the life cycle of individuals in the individual-based model. Each row gives the probability that an individual in one stage survives the step and ends it in another (or the same) stage, optionally only within a range of ages. Any probability left over is the probability of dying, so the table plays the role of the survival (`U`) part of a projection matrix.
+use crate::imports::read_text;
+use crate::populations::population_level_simulation::PopulationMatrix;
+use crate::scenarios::{settings, Setting};
+use csv::ReaderBuilder;
+use std::{error::Error, io::Read};
+
+/// This struct holds one row of a [`TransitionTable`]: the probability that an individual in
+/// stage `from` survives one step and moves to stage `to`. If an age range is set, the
+/// transition only applies to individuals whose age at the start of the step is within it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageTransition {
+    from: u8,
+    to: u8,
+    probability: f64,
+    min_age: Option<u16>,
+    max_age: Option<u16>,
+}
+impl StageTransition {
+    /// Create a transition that applies at any age.
+    /// # Errors
+    /// Will return `Err<'static str>` if the probability is not between 0 and 1.
+    pub fn new(from: u8, to: u8, probability: f64) -> Result<StageTransition, &'static str> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("Transition probabilities must be between 0 and 1.");
+        }
+        Ok(StageTransition {
+            from,
+            to,
+            probability,
+            min_age: None,
+            max_age: None,
+        })
+    }
+    /// Return the transition restricted to individuals of at least `min_age` and at most
+    /// `max_age` (both inclusive, and either can be left open).
+    /// # Errors
+    /// Will return `Err<'static str>` if `min_age` is greater than `max_age`.
+    pub fn with_age_range(
+        mut self,
+        min_age: Option<u16>,
+        max_age: Option<u16>,
+    ) -> Result<StageTransition, &'static str> {
+        if let (Some(min_age), Some(max_age)) = (min_age, max_age) {
+            if min_age > max_age {
+                return Err("The minimum age of a transition must not exceed its maximum age.");
+            }
+        }
+        self.min_age = min_age;
+        self.max_age = max_age;
+        Ok(self)
+    }
+    /// Return the stage the transition starts from.
+    pub fn get_from(&self) -> u8 {
+        self.from
+    }
+    /// Return the stage the transition ends in.
+    pub fn get_to(&self) -> u8 {
+        self.to
+    }
+    /// Return the probability of the transition.
+    pub fn get_probability(&self) -> f64 {
+        self.probability
+    }
+    /// Return the age range the transition applies to.
+    pub fn get_age_range(&self) -> (Option<u16>, Option<u16>) {
+        (self.min_age, self.max_age)
+    }
+    /// Return true if the transition applies to an individual of this stage and age.
+    pub fn applies_to(&self, stage: u8, age: u16) -> bool {
+        self.from == stage
+            && self.min_age.is_none_or(|x| age >= x)
+            && self.max_age.is_none_or(|x| age <= x)
+    }
+}
+
+/// This struct holds the stage transitions of a life cycle (see [`StageTransition`]).
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{StageTransition, TransitionTable};
+/// // Eggs hatch into larvae, which enter diapause until age 3 and then mature.
+/// let table = TransitionTable::read_csv(
+///     "from,to,probability,min_age,max_age\n0,1,0.3,,\n1,1,0.8,,2\n1,2,0.6,3,\n2,2,0.5,,\n".as_bytes(),
+/// ).unwrap();
+/// assert_eq!(table.survival(1, 0), 0.8);
+/// assert_eq!(table.survival(1, 3), 0.6);
+/// assert_eq!(table.next_stage(1, 3, 0.1), Some(2));
+/// assert_eq!(table.next_stage(1, 3, 0.9), None);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransitionTable {
+    transitions: Vec<StageTransition>,
+}
+impl TransitionTable {
+    /// Build a transition table.
+    /// # Errors
+    /// Will return `Err<'static str>` if, for any stage and age, the probabilities of the
+    /// transitions that apply add up to more than one.
+    pub fn build(transitions: Vec<StageTransition>) -> Result<TransitionTable, &'static str> {
+        let table = TransitionTable { transitions };
+        // The set of transitions that applies only changes at these ages, so checking them
+        // checks every age.
+        let mut ages = vec![0];
+        for transition in &table.transitions {
+            ages.extend(transition.min_age);
+            ages.extend(transition.max_age.map(|x| x.saturating_add(1)));
+        }
+        for transition in &table.transitions {
+            for age in &ages {
+                if table.survival(transition.from, *age) > 1.0 + 1e-9 {
+                    return Err(
+                        "The transition probabilities out of a stage must not add up to more than one at any age.",
+                    );
+                }
+            }
+        }
+        Ok(table)
+    }
+    /// Build a transition table from the survival part of a projection matrix: each non-zero
+    /// element `U[to][from]` becomes a transition from `from` to `to` at any age.
+    /// # Errors
+    /// Will return `Err<'static str>` if any element is greater than one or a column adds up to
+    /// more than one.
+    pub fn from_survival_matrix(
+        survival: &PopulationMatrix,
+    ) -> Result<TransitionTable, &'static str> {
+        let mut transitions = Vec::new();
+        for (to, row) in survival.get_matrix().iter().enumerate() {
+            for (from, probability) in row.iter().enumerate() {
+                if *probability != 0.0 {
+                    transitions.push(StageTransition::new(from as u8, to as u8, *probability)?);
+                }
+            }
+        }
+        TransitionTable::build(transitions)
+    }
+    /// Read a transition table from a CSV file with a header row and the columns `from`, `to`,
+    /// and `probability`, plus optional `min_age` and `max_age` columns (an empty cell means no
+    /// limit). Stages are numbered from 0, like the rows of a matrix.
+    /// # Errors
+    /// Returns an error if the file cannot be read, a column is missing, a value is not a
+    /// number, or the table is not valid (see [`TransitionTable::build`]).
+    pub fn read_csv<R: Read>(reader: R) -> Result<TransitionTable, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(contents.as_bytes());
+        let header: Vec<String> = rdr.headers()?.iter().map(|x| x.to_lowercase()).collect();
+        let column = |name: &str| header.iter().position(|x| x == name);
+        let required = |name: &str| {
+            column(name).ok_or_else(|| format!("The transition table has no \"{}\" column.", name))
+        };
+        let (from, to, probability) =
+            (required("from")?, required("to")?, required("probability")?);
+        let (min_age, max_age) = (column("min_age"), column("max_age"));
+        let mut transitions = Vec::new();
+        for (row_number, record) in rdr.records().enumerate() {
+            let record = record?;
+            let cell = |index: usize| record.get(index).unwrap_or_default();
+            let invalid = |index: usize| {
+                format!(
+                    "Row {}, column \"{}\": \"{}\" is not valid.",
+                    row_number + 1,
+                    header[index],
+                    cell(index)
+                )
+            };
+            let age = |index: Option<usize>| match index.map(cell) {
+                None | Some("") => Ok(None),
+                Some(value) => value
+                    .parse::<u16>()
+                    .map(Some)
+                    .map_err(|_| invalid(index.unwrap_or_default())),
+            };
+            let transition = StageTransition::new(
+                cell(from).parse().map_err(|_| invalid(from))?,
+                cell(to).parse().map_err(|_| invalid(to))?,
+                cell(probability)
+                    .parse()
+                    .map_err(|_| invalid(probability))?,
+            )
+            .map_err(|e| format!("Row {}: {}", row_number + 1, e))?
+            .with_age_range(age(min_age)?, age(max_age)?)
+            .map_err(|e| format!("Row {}: {}", row_number + 1, e))?;
+            transitions.push(transition);
+        }
+        Ok(TransitionTable::build(transitions)?)
+    }
+    /// Read a transition table from a TOML file with one `[[transition]]` table per row, each
+    /// setting `from`, `to`, and `probability`, and optionally `min_age` and `max_age`. Only this
+    /// part of TOML is read, with the same `key = value` lines and `#` comments as scenario
+    /// files (see [`crate::scenarios`]).
+    /// # Errors
+    /// Returns an error if the file cannot be read, a setting is outside a `[[transition]]`
+    /// table, unknown, or not a number, a required setting is missing, or the table is not valid
+    /// (see [`TransitionTable::build`]).
+    /// ```
+    /// use ecolysis_cmd::populations::individual_level_simulation::TransitionTable;
+    /// let file = "# Juveniles mature after two years\n\
+    ///             [[transition]]\nfrom = 0\nto = 0\nprobability = 0.5\nmax_age = 1\n\
+    ///             [[transition]]\nfrom = 0\nto = 1\nprobability = 0.5\nmin_age = 2\n";
+    /// let table = TransitionTable::read_toml(file.as_bytes()).unwrap();
+    /// assert_eq!(table.next_stage(0, 3, 0.1), Some(1));
+    /// ```
+    pub fn read_toml<R: Read>(reader: R) -> Result<TransitionTable, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rows: Vec<(usize, [Option<&str>; 5])> = Vec::new();
+        for setting in settings(&contents, "transition table") {
+            match setting? {
+                Setting::Header {
+                    name: "transition",
+                    line,
+                } => rows.push((line, [None; 5])),
+                Setting::Header { name, line } => {
+                    return Err(format!("Line {}: unknown table \"{}\".", line, name).into())
+                }
+                Setting::Value { line, key, value } => {
+                    let (_, fields) = rows.last_mut().ok_or_else(|| {
+                        format!(
+                            "Line {}: settings must follow a [[transition]] header.",
+                            line
+                        )
+                    })?;
+                    let index = ["from", "to", "probability", "min_age", "max_age"]
+                        .iter()
+                        .position(|x| *x == key)
+                        .ok_or_else(|| format!("Line {}: unknown setting \"{}\".", line, key))?;
+                    fields[index] = Some(value);
+                }
+            }
+        }
+        let mut transitions = Vec::new();
+        for (line, [from, to, probability, min_age, max_age]) in rows {
+            let invalid = |name: &str, value: &str| {
+                format!(
+                    "The transition on line {}: {} \"{}\" is not valid.",
+                    line, name, value
+                )
+            };
+            let missing = |name: &str| format!("The transition on line {} has no {}.", line, name);
+            let age = |name: &str, value: Option<&str>| match value {
+                None => Ok(None),
+                Some(value) => value
+                    .parse::<u16>()
+                    .map(Some)
+                    .map_err(|_| invalid(name, value)),
+            };
+            let from = from.ok_or_else(|| missing("from"))?;
+            let to = to.ok_or_else(|| missing("to"))?;
+            let probability = probability.ok_or_else(|| missing("probability"))?;
+            let transition = StageTransition::new(
+                from.parse().map_err(|_| invalid("from", from))?,
+                to.parse().map_err(|_| invalid("to", to))?,
+                probability
+                    .parse()
+                    .map_err(|_| invalid("probability", probability))?,
+            )
+            .map_err(|e| format!("The transition on line {}: {}", line, e))?
+            .with_age_range(age("min_age", min_age)?, age("max_age", max_age)?)
+            .map_err(|e| format!("The transition on line {}: {}", line, e))?;
+            transitions.push(transition);
+        }
+        Ok(TransitionTable::build(transitions)?)
+    }
+    /// Return the transitions in the table.
+    pub fn get_transitions(&self) -> &Vec<StageTransition> {
+        &self.transitions
+    }
+    /// Return the largest stage number used by any transition, or `None` for an empty table.
+    pub fn max_stage(&self) -> Option<u8> {
+        self.transitions.iter().map(|x| x.from.max(x.to)).max()
+    }
+    /// Return the probability that an individual of this stage and age survives one step.
+    pub fn survival(&self, stage: u8, age: u16) -> f64 {
+        self.transitions
+            .iter()
+            .filter(|x| x.applies_to(stage, age))
+            .map(|x| x.probability)
+            .sum()
+    }
+    /// Return the stage an individual of this stage and age moves to, given a uniform random
+    /// number `draw` between 0 and 1, or `None` if it dies. Transitions are tried in the order of
+    /// the table.
+    pub fn next_stage(&self, stage: u8, age: u16, draw: f64) -> Option<u8> {
+        let mut cumulative = 0.0;
+        for transition in self.transitions.iter().filter(|x| x.applies_to(stage, age)) {
+            cumulative += transition.probability;
+            if draw < cumulative {
+                return Some(transition.to);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_tables() {
+        assert!(StageTransition::new(0, 1, 1.2).is_err());
+        assert!(StageTransition::new(0, 1, 0.5)
+            .unwrap()
+            .with_age_range(Some(4), Some(2))
+            .is_err());
+        let young = StageTransition::new(0, 0, 0.6)
+            .unwrap()
+            .with_age_range(None, Some(2))
+            .unwrap();
+        let old = StageTransition::new(0, 1, 0.6)
+            .unwrap()
+            .with_age_range(Some(3), None)
+            .unwrap();
+        assert!(TransitionTable::build(vec![young.clone(), old.clone()]).is_ok());
+        let overlapping = old.with_age_range(Some(2), None).unwrap();
+        assert!(TransitionTable::build(vec![young, overlapping]).is_err());
+        assert!(TransitionTable::read_csv("from,to\n0,1\n".as_bytes()).is_err());
+        assert!(TransitionTable::read_csv("from,to,probability\n0,x,0.5\n".as_bytes()).is_err());
+        for file in [
+            "from = 0\n",
+            "[[stage]]\nfrom = 0\n",
+            "[[transition]]\nfrom = 0\nto = 1\n",
+            "[[transition]]\nfrom = 0\nto = 1\nprobability = 0.5\nage = 2\n",
+            "[[transition]]\nfrom = 0\nto = 1\nprobability = high\n",
+        ] {
+            assert!(TransitionTable::read_toml(file.as_bytes()).is_err());
+        }
+    }
+    #[test]
+    fn table_from_survival_matrix() {
+        let survival = PopulationMatrix::build(vec![vec![0.0, 0.2], vec![0.5, 0.7]]).unwrap();
+        let table = TransitionTable::from_survival_matrix(&survival).unwrap();
+        assert_eq!(table.get_transitions().len(), 3);
+        assert_eq!(table.max_stage(), Some(1));
+        assert!((table.survival(1, 10) - 0.9).abs() < 1e-12);
+        assert_eq!(table.next_stage(0, 0, 0.4), Some(1));
+        assert_eq!(table.next_stage(0, 0, 0.6), None);
+        let too_high = PopulationMatrix::build(vec![vec![0.6, 0.0], vec![0.6, 1.0]]).unwrap();
+        assert!(TransitionTable::from_survival_matrix(&too_high).is_err());
+    }
+}
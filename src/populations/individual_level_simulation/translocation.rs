@@ -0,0 +1,363 @@
+//! This module contains translocations between individual-based populations: several [`IndividualSimulation`]s run side by side, with selected individuals moved between them on a schedule (for example, 2 adults every 5 years), carrying their age, stage, and genotype with them.
+use super::{Individual, IndividualSimulation, LifeEvent, TraitDispersal};
+use crate::populations::patch_occupancy::HabitatTimeline;
+use crate::populations::population_level_simulation::PopulationVector;
+use crate::simulation::Simulation;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// This struct holds one scheduled translocation: up to `count` individuals chosen at random
+/// from the eligible individuals of the source population, moved to the destination after the
+/// step at which it is due. By default every individual is eligible and the translocation is
+/// due after every step.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::Translocation;
+/// // Move 2 adults (stage 1) from population 0 to population 1 every 5 steps, as long as at
+/// // least 20 individuals remain in the source.
+/// let translocation = Translocation::new(0, 1, 2)
+///     .unwrap()
+///     .of_stage(1)
+///     .every(5, 5)
+///     .unwrap()
+///     .with_minimum_source(20);
+/// assert!(translocation.is_due(10) && !translocation.is_due(12));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Translocation {
+    from: usize,
+    to: usize,
+    count: u32,
+    stage: Option<u8>,
+    min_age: u16,
+    max_age: Option<u16>,
+    first_step: u32,
+    interval: u32,
+    last_step: Option<u32>,
+    minimum_source: u32,
+}
+impl Translocation {
+    /// Create a translocation of up to `count` individuals from one population to another.
+    /// # Errors
+    /// Will return `Err<'static str>` if the populations are the same or the count is zero.
+    pub fn new(from: usize, to: usize, count: u32) -> Result<Translocation, &'static str> {
+        if from == to {
+            return Err("A translocation must move individuals between different populations.");
+        }
+        if count == 0 {
+            return Err("A translocation must move at least one individual.");
+        }
+        Ok(Translocation {
+            from,
+            to,
+            count,
+            stage: None,
+            min_age: 0,
+            max_age: None,
+            first_step: 1,
+            interval: 1,
+            last_step: None,
+            minimum_source: 0,
+        })
+    }
+    /// Return the translocation due every `interval` steps, starting after step `first_step`.
+    /// # Errors
+    /// Will return `Err<'static str>` if the interval or first step is zero.
+    pub fn every(mut self, interval: u32, first_step: u32) -> Result<Self, &'static str> {
+        if interval == 0 || first_step == 0 {
+            return Err("The interval and first step of a translocation must be at least 1.");
+        }
+        self.interval = interval;
+        self.first_step = first_step;
+        Ok(self)
+    }
+    /// Return the translocation stopping after the given step.
+    pub fn until(mut self, last_step: u32) -> Self {
+        self.last_step = Some(last_step);
+        self
+    }
+    /// Return the translocation moving only individuals of the given stage.
+    pub fn of_stage(mut self, stage: u8) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+    /// Return the translocation moving only individuals between the given ages (inclusive).
+    /// # Errors
+    /// Will return `Err<'static str>` if the minimum age is above the maximum age.
+    pub fn with_age_range(
+        mut self,
+        min_age: u16,
+        max_age: Option<u16>,
+    ) -> Result<Self, &'static str> {
+        if max_age.is_some_and(|x| x < min_age) {
+            return Err("The minimum age must not be above the maximum age.");
+        }
+        self.min_age = min_age;
+        self.max_age = max_age;
+        Ok(self)
+    }
+    /// Return the translocation moving only as many individuals as leaves at least `size`
+    /// individuals in the source population.
+    pub fn with_minimum_source(mut self, size: u32) -> Self {
+        self.minimum_source = size;
+        self
+    }
+    /// Return the source population.
+    pub fn get_from(&self) -> usize {
+        self.from
+    }
+    /// Return the destination population.
+    pub fn get_to(&self) -> usize {
+        self.to
+    }
+    /// Return whether the translocation is due after the given step.
+    pub fn is_due(&self, step: u32) -> bool {
+        step >= self.first_step
+            && (step - self.first_step) % self.interval == 0
+            && self.last_step.is_none_or(|x| step <= x)
+    }
+    /// Return whether an individual can be moved.
+    fn is_eligible(&self, individual: &Individual) -> bool {
+        self.stage.is_none_or(|x| x == individual.lifestage)
+            && individual.age >= self.min_age
+            && self.max_age.is_none_or(|x| individual.age <= x)
+    }
+}
+
+/// This struct holds several individual-based populations simulated together, linked by
+/// [`Translocation`]s and, optionally, [`TraitDispersal`]. Each step, every population takes one
+/// step, individuals disperse, and then the translocations due after that step are carried out
+/// in the order they were added. Its state is the number of
+/// individuals in each stage of each population.
+/// ```
+/// use ecolysis_cmd::populations::individual_level_simulation::{
+///     IndividualBasedModel, IndividualMetapopulation, StageTransition, TransitionTable, Translocation,
+/// };
+/// use ecolysis_cmd::simulation::Simulation;
+/// let table = TransitionTable::build(vec![StageTransition::new(0, 0, 1.0).unwrap()]).unwrap();
+/// let source = IndividualBasedModel::build(vec![50.0].into(), table.clone(), vec![0.0]).unwrap();
+/// let empty = IndividualBasedModel::build(vec![0.0].into(), table, vec![0.0]).unwrap();
+/// let mut metapopulation = IndividualMetapopulation::new(
+///     vec![source.simulation(0, 1), empty.simulation(0, 1)], 1)
+///     .unwrap()
+///     .with_translocation(Translocation::new(0, 1, 2).unwrap().every(5, 5).unwrap())
+///     .unwrap();
+/// metapopulation.run(10);
+/// assert_eq!(metapopulation.state()[1].total(), 4.0);
+/// ```
+pub struct IndividualMetapopulation<'a> {
+    pub(super) populations: Vec<IndividualSimulation<'a>>,
+    translocations: Vec<Translocation>,
+    states: Vec<PopulationVector>,
+    moved: Vec<(u32, usize, u32)>,
+    pub(super) dispersal: Option<TraitDispersal>,
+    pub(super) dispersed: Vec<(u32, usize, usize, u32)>,
+    pub(super) habitat: Option<HabitatTimeline>,
+    pub(super) rng: StdRng,
+    pub(super) steps_taken: u32,
+}
+impl<'a> IndividualMetapopulation<'a> {
+    /// Create a set of linked populations, with a seed for choosing the individuals to move.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no populations, or the populations do not all
+    /// have the same number of stages and loci.
+    pub fn new(
+        populations: Vec<IndividualSimulation<'a>>,
+        seed: u64,
+    ) -> Result<IndividualMetapopulation<'a>, &'static str> {
+        let first = populations
+            .first()
+            .ok_or("There must be at least one population.")?;
+        let shape = |x: &IndividualSimulation| (x.model.reproduction.len(), x.model.loci.len());
+        if populations.iter().any(|x| shape(x) != shape(first)) {
+            return Err("All populations must have the same number of stages and loci.");
+        }
+        Ok(IndividualMetapopulation {
+            states: populations.iter().map(|x| x.state().clone()).collect(),
+            populations,
+            translocations: Vec::new(),
+            moved: Vec::new(),
+            dispersal: None,
+            dispersed: Vec::new(),
+            habitat: None,
+            rng: StdRng::seed_from_u64(seed),
+            steps_taken: 0,
+        })
+    }
+    /// Return the metapopulation with a translocation added.
+    /// # Errors
+    /// Will return `Err<'static str>` if either population does not exist, or the stage moved
+    /// does not exist.
+    pub fn with_translocation(
+        mut self,
+        translocation: Translocation,
+    ) -> Result<Self, &'static str> {
+        if translocation.from.max(translocation.to) >= self.populations.len() {
+            return Err("The translocation refers to a population that does not exist.");
+        }
+        let stages = self.populations[0].model.reproduction.len();
+        if translocation.stage.is_some_and(|x| x as usize >= stages) {
+            return Err("The translocation refers to a stage that does not exist.");
+        }
+        self.translocations.push(translocation);
+        Ok(self)
+    }
+    /// Return the populations.
+    pub fn get_populations(&self) -> &Vec<IndividualSimulation<'a>> {
+        &self.populations
+    }
+    /// Return each translocation carried out so far: the step after which it happened, the
+    /// index of the translocation in the order added, and the number of individuals moved.
+    pub fn get_moved(&self) -> &Vec<(u32, usize, u32)> {
+        &self.moved
+    }
+}
+impl Simulation for IndividualMetapopulation<'_> {
+    type State = Vec<PopulationVector>;
+    fn step(&mut self) {
+        for population in &mut self.populations {
+            population.step();
+        }
+        self.steps_taken += 1;
+        self.disperse();
+        for (index, translocation) in self.translocations.iter().enumerate() {
+            if !translocation.is_due(self.steps_taken) {
+                continue;
+            }
+            let source = &self.populations[translocation.from];
+            let available =
+                (source.individuals.len() as u32).saturating_sub(translocation.minimum_source);
+            let eligible: Vec<usize> = source
+                .individuals
+                .iter()
+                .filter(|x| translocation.is_eligible(x))
+                .map(|x| x.id)
+                .collect();
+            let count = translocation.count.min(available) as usize;
+            let chosen: Vec<usize> = eligible
+                .choose_multiple(&mut self.rng, count)
+                .copied()
+                .collect();
+            let lineage = self.populations[translocation.from]
+                .get_pedigree()
+                .map_or_else(Vec::new, |x| x.lineage(&chosen));
+            let event = LifeEvent::Translocation {
+                from: translocation.from,
+                to: translocation.to,
+            };
+            self.populations[translocation.from].record_movement(&chosen, event);
+            let moving = self.populations[translocation.from].remove_individuals(&chosen);
+            self.moved
+                .push((self.steps_taken, index, moving.len() as u32));
+            let arrived = self.populations[translocation.to].add_individuals(
+                moving,
+                translocation.from,
+                lineage,
+            );
+            self.populations[translocation.to].record_movement(&arrived, event);
+        }
+        self.states = self.populations.iter().map(|x| x.state().clone()).collect();
+    }
+    fn state(&self) -> &Vec<PopulationVector> {
+        &self.states
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::individual_level_simulation::{
+        EventLog, Genotype, IndividualBasedModel, Locus, StageTransition, TransitionTable,
+    };
+
+    fn stable(count: f64) -> IndividualBasedModel {
+        let table = TransitionTable::build(vec![
+            StageTransition::new(0, 1, 1.0).unwrap(),
+            StageTransition::new(1, 1, 1.0).unwrap(),
+        ])
+        .unwrap();
+        IndividualBasedModel::build(vec![0.0, count].into(), table, vec![0.0, 0.0])
+            .unwrap()
+            .with_founder_genotypes(
+                vec![Locus::new("L1")],
+                vec![Genotype::new(vec![[Some(count as u16), None]]).unwrap(); count as usize],
+            )
+            .unwrap()
+            .with_pedigree()
+    }
+
+    #[test]
+    fn translocations_follow_their_schedule_and_rules() {
+        assert!(Translocation::new(0, 0, 1).is_err());
+        assert!(Translocation::new(0, 1, 0).is_err());
+        assert!(Translocation::new(0, 1, 1).unwrap().every(0, 1).is_err());
+        let (large, small) = (stable(30.0), stable(2.0));
+        let metapopulation =
+            IndividualMetapopulation::new(vec![large.simulation(0, 1), small.simulation(0, 1)], 7)
+                .unwrap();
+        assert!(metapopulation
+            .with_translocation(Translocation::new(0, 2, 1).unwrap())
+            .is_err());
+        let mut metapopulation =
+            IndividualMetapopulation::new(vec![large.simulation(0, 1), small.simulation(0, 1)], 7)
+                .unwrap()
+                .with_translocation(
+                    Translocation::new(0, 1, 5)
+                        .unwrap()
+                        .every(2, 2)
+                        .unwrap()
+                        .until(6)
+                        .with_minimum_source(22),
+                )
+                .unwrap();
+        metapopulation.run(10);
+        // 5 at step 2, 3 at step 4 (leaving 22), none at step 6 or later.
+        let moved: Vec<u32> = metapopulation.get_moved().iter().map(|x| x.2).collect();
+        assert_eq!(moved, vec![5, 3, 0]);
+        assert_eq!(metapopulation.state()[0].total(), 22.0);
+        assert_eq!(metapopulation.state()[1].total(), 10.0);
+        let destination = &metapopulation.get_populations()[1];
+        assert_eq!(destination.get_pedigree().unwrap().len(), 10);
+        let immigrants = destination
+            .get_individuals()
+            .iter()
+            .filter(|x| x.get_genotype().get(0) == Some([Some(30), None]))
+            .count();
+        assert_eq!(immigrants, 8);
+        assert!(destination
+            .get_individuals()
+            .iter()
+            .all(|x| x.get_age() >= 10));
+    }
+    #[test]
+    fn translocations_are_logged() {
+        let (large, small) = (stable(30.0), stable(2.0));
+        let mut metapopulation = IndividualMetapopulation::new(
+            vec![
+                large.simulation(0, 1).with_event_log(EventLog::new()),
+                small.simulation(0, 1).with_event_log(EventLog::new()),
+            ],
+            7,
+        )
+        .unwrap()
+        .with_translocation(Translocation::new(0, 1, 5).unwrap())
+        .unwrap();
+        metapopulation.run(1);
+        let event = LifeEvent::Translocation { from: 0, to: 1 };
+        let moves = |population: usize| -> Vec<(u32, usize)> {
+            metapopulation.get_populations()[population]
+                .get_event_log()
+                .unwrap()
+                .get_entries()
+                .iter()
+                .filter(|x| x.event == event)
+                .map(|x| (x.step, x.individual))
+                .collect()
+        };
+        // The source logs the old identifiers and the destination the new ones.
+        assert_eq!(moves(0).len(), 5);
+        assert!(moves(0).iter().all(|x| x.0 == 1 && x.1 < 30));
+        assert_eq!(moves(1), (2..7).map(|x| (1, x)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,230 @@
+//! This module contains Integral Projection Models (IPMs), in which individuals are described by a continuous trait such as size instead of discrete lifestages. The user supplies survival, growth, and fecundity functions of size, and the model is discretized with the midpoint rule into a Population Matrix that can be projected and analyzed like any other.
+use crate::populations::population_level_simulation::{
+    DecomposedMatrix, PopulationMatrix, PopulationVector,
+};
+
+/// This struct describes the range of sizes covered by an IPM and how finely it is divided. The
+/// range from `lower` to `upper` is split into `points` bins of equal width, and each bin is
+/// represented by its midpoint. Each bin becomes one lifestage of the discretized matrix, so at
+/// most 255 bins can be used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegrationMesh {
+    lower: f64,
+    upper: f64,
+    points: u8,
+}
+impl IntegrationMesh {
+    /// Build an Integration Mesh.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no points or the upper limit is not above the
+    /// lower limit.
+    pub fn build(lower: f64, upper: f64, points: u8) -> Result<IntegrationMesh, &'static str> {
+        if points == 0 {
+            return Err("An integration mesh needs at least one point.");
+        }
+        if upper <= lower || !lower.is_finite() || !upper.is_finite() {
+            return Err("The upper limit of the mesh must be above the lower limit.");
+        }
+        Ok(IntegrationMesh {
+            lower,
+            upper,
+            points,
+        })
+    }
+    /// Return the number of points (bins) in the mesh.
+    pub fn get_points(&self) -> u8 {
+        self.points
+    }
+    /// Return the width of each bin.
+    pub fn width(&self) -> f64 {
+        (self.upper - self.lower) / self.points as f64
+    }
+    /// Return the midpoint of each bin.
+    pub fn midpoints(&self) -> Vec<f64> {
+        let width = self.width();
+        (0..self.points)
+            .map(|i| self.lower + (i as f64 + 0.5) * width)
+            .collect()
+    }
+}
+
+/// This struct holds an Integral Projection Model: an Integration Mesh and the three functions
+/// that make up its kernel `K(z', z) = s(z) g(z', z) + f(z', z)`, where `z` is the size of an
+/// individual this step and `z'` its size (or the size of its offspring) next step.
+/// - `survival(z)`: the probability that an individual of size `z` survives.
+/// - `growth(z', z)`: the probability density of size `z'` next step for a survivor of size `z`.
+/// - `fecundity(z', z)`: the density of offspring of size `z'` produced by an individual of size
+///   `z`.
+/// ```
+/// use ecolysis_cmd::populations::ipm::{IntegralProjectionModel, IntegrationMesh};
+/// let normal = |x: f64, mean: f64, sd: f64| {
+///     (-(x - mean).powi(2) / (2.0 * sd * sd)).exp() / (sd * (2.0 * std::f64::consts::PI).sqrt())
+/// };
+/// let model = IntegralProjectionModel::new(
+///     IntegrationMesh::build(0.0, 10.0, 100).unwrap(),
+///     |z| 1.0 / (1.0 + (-(z - 3.0)).exp()),
+///     move |z1, z| normal(z1, 0.8 + 0.9 * z, 0.5),
+///     move |z1, z| if z > 5.0 { 0.5 * z * normal(z1, 1.0, 0.3) } else { 0.0 },
+/// )
+/// .with_eviction_correction();
+/// let matrix = model.to_matrix();
+/// assert_eq!(matrix.get_lifestage_count(), 100);
+/// // Close to a stable population.
+/// assert!((matrix.lambda() - 1.0).abs() < 0.01);
+/// ```
+pub struct IntegralProjectionModel {
+    mesh: IntegrationMesh,
+    survival: Box<dyn Fn(f64) -> f64>,
+    growth: Box<dyn Fn(f64, f64) -> f64>,
+    fecundity: Box<dyn Fn(f64, f64) -> f64>,
+    correct_eviction: bool,
+}
+impl IntegralProjectionModel {
+    /// Create a new Integral Projection Model from a mesh and survival, growth, and fecundity
+    /// functions.
+    pub fn new<S, G, F>(
+        mesh: IntegrationMesh,
+        survival: S,
+        growth: G,
+        fecundity: F,
+    ) -> IntegralProjectionModel
+    where
+        S: Fn(f64) -> f64 + 'static,
+        G: Fn(f64, f64) -> f64 + 'static,
+        F: Fn(f64, f64) -> f64 + 'static,
+    {
+        IntegralProjectionModel {
+            mesh,
+            survival: Box::new(survival),
+            growth: Box::new(growth),
+            fecundity: Box::new(fecundity),
+            correct_eviction: false,
+        }
+    }
+    /// Return the model with eviction correction turned on. Part of the growth density of
+    /// individuals near the edges of the mesh falls outside it, so those individuals are
+    /// "evicted" and lost from the model. With eviction correction, each column of the
+    /// discretized growth kernel is rescaled to add up to one, so survivors always stay within
+    /// the mesh.
+    pub fn with_eviction_correction(mut self) -> Self {
+        self.correct_eviction = true;
+        self
+    }
+    /// Return the Integration Mesh of the model.
+    pub fn get_mesh(&self) -> &IntegrationMesh {
+        &self.mesh
+    }
+    /// Return the value of the kernel `K(z', z)`.
+    pub fn kernel(&self, next_size: f64, size: f64) -> f64 {
+        (self.survival)(size) * (self.growth)(next_size, size) + (self.fecundity)(next_size, size)
+    }
+    /// Return the discretized survival/growth (`U`) and fecundity (`F`) parts of the kernel as
+    /// rows of values.
+    fn discretize(&self) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let midpoints = self.mesh.midpoints();
+        let width = self.mesh.width();
+        let size = midpoints.len();
+        let mut survival = vec![vec![0.0; size]; size];
+        let mut fecundity = vec![vec![0.0; size]; size];
+        for (column, z) in midpoints.iter().enumerate() {
+            let growth: Vec<f64> = midpoints
+                .iter()
+                .map(|z1| (self.growth)(*z1, *z) * width)
+                .collect();
+            let growth_total: f64 = growth.iter().sum();
+            let scale = if self.correct_eviction && growth_total > 0.0 {
+                1.0 / growth_total
+            } else {
+                1.0
+            };
+            let survival_probability = (self.survival)(*z);
+            for (row, z1) in midpoints.iter().enumerate() {
+                survival[row][column] = survival_probability * growth[row] * scale;
+                fecundity[row][column] = (self.fecundity)(*z1, *z) * width;
+            }
+        }
+        (survival, fecundity)
+    }
+    /// Return the discretized kernel as a Population Matrix, with one lifestage per mesh point.
+    pub fn to_matrix(&self) -> PopulationMatrix {
+        let (survival, fecundity) = self.discretize();
+        let matrix = survival
+            .iter()
+            .zip(&fecundity)
+            .map(|(u, f)| u.iter().zip(f).map(|(x, y)| x + y).collect())
+            .collect();
+        PopulationMatrix::build(matrix).expect("The discretized kernel is always square.")
+    }
+    /// Return the discretized kernel as a Decomposed Matrix, so that the net reproductive rate,
+    /// generation time, and demographic stochasticity can be calculated.
+    /// # Errors
+    /// Will return `Err<'static str>` if any discretized value is negative or any column of the
+    /// survival/growth part adds up to more than one (which can happen without eviction
+    /// correction if the growth density is too coarse for the mesh).
+    pub fn to_decomposed(&self) -> Result<DecomposedMatrix, &'static str> {
+        let (survival, fecundity) = self.discretize();
+        DecomposedMatrix::build(
+            PopulationMatrix::build(survival)?,
+            PopulationMatrix::build(fecundity)?,
+        )
+    }
+    /// Return a Population Vector from a density of individuals over size, by evaluating the
+    /// density at each mesh point and multiplying by the bin width.
+    pub fn population_from_density<D: Fn(f64) -> f64>(&self, density: D) -> PopulationVector {
+        let width = self.mesh.width();
+        PopulationVector::new(
+            self.mesh
+                .midpoints()
+                .iter()
+                .map(|z| density(*z) * width)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normal(x: f64, mean: f64, sd: f64) -> f64 {
+        (-(x - mean).powi(2) / (2.0 * sd * sd)).exp() / (sd * (2.0 * std::f64::consts::PI).sqrt())
+    }
+
+    #[test]
+    fn mesh_validation() {
+        assert!(IntegrationMesh::build(0.0, 1.0, 0).is_err());
+        assert!(IntegrationMesh::build(2.0, 1.0, 10).is_err());
+        let mesh = IntegrationMesh::build(0.0, 1.0, 4).unwrap();
+        assert_eq!(mesh.midpoints(), vec![0.125, 0.375, 0.625, 0.875]);
+    }
+    #[test]
+    fn lambda_of_size_independent_rates() {
+        // With constant survival s and constant fecundity b spread over the mesh, and no
+        // eviction, every individual contributes s + b individuals next step.
+        let model = IntegralProjectionModel::new(
+            IntegrationMesh::build(0.0, 10.0, 80).unwrap(),
+            |_| 0.7,
+            |z1, z| normal(z1, z * 0.5 + 2.5, 0.4),
+            |z1, _| 0.4 * normal(z1, 5.0, 1.0),
+        )
+        .with_eviction_correction();
+        assert!((model.to_matrix().lambda() - 1.1).abs() < 1e-3);
+        let decomposed = model.to_decomposed().unwrap();
+        assert!((decomposed.net_reproductive_rate().unwrap() - 0.4 / 0.3).abs() < 1e-3);
+    }
+    #[test]
+    fn eviction_loses_individuals_without_correction() {
+        let model = IntegralProjectionModel::new(
+            IntegrationMesh::build(0.0, 10.0, 50).unwrap(),
+            |_| 1.0,
+            |z1, z| normal(z1, z + 1.0, 0.5),
+            |_, _| 0.0,
+        );
+        let lost = model.to_matrix();
+        let last_column: f64 = (0..50).map(|row| lost[(row, 49)]).sum();
+        assert!(last_column < 0.5);
+        let corrected = model.with_eviction_correction().to_matrix();
+        let last_column: f64 = (0..50).map(|row| corrected[(row, 49)]).sum();
+        assert!((last_column - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,315 @@
+//! This module contains a patch-occupancy (Levins-style) metapopulation model. Instead of tracking how many individuals live in each patch, each patch is simply occupied or empty. Occupied patches go extinct, and empty patches are colonized from occupied ones, at random each step. This is useful for landscape-scale questions when demographic data for each patch are not available.
+use crate::outputs::{OutputTable, ToOutputTable};
+use crate::populations::population_level_simulation::replicate_seed;
+use crate::simulation::Simulation;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+mod landscape_change;
+pub use landscape_change::*;
+
+/// This struct holds one habitat patch: its location, the probability that it goes extinct in
+/// one step while occupied, and its colonization rate while empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    pub x: f64,
+    pub y: f64,
+    pub extinction_probability: f64,
+    pub colonization_rate: f64,
+}
+
+/// This enum describes how colonization pressure on an empty patch is calculated. The patch is
+/// colonized with probability `1 - exp(-colonization_rate * S)`, where `S` is:
+/// - `Global`: the fraction of the other patches that are occupied, as in the Levins model.
+/// - `DistanceDependent`: the sum of `exp(-distance / dispersal_distance)` over the occupied
+///   patches, so nearby patches contribute more than distant ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colonization {
+    Global,
+    DistanceDependent { dispersal_distance: f64 },
+}
+
+/// This struct holds a patch-occupancy model: a set of patches, which of them start occupied, how
+/// colonization works, and optionally how habitat quality changes over time (see
+/// [`OccupancyModel::with_habitat_timeline`]).
+/// ```
+/// use ecolysis_cmd::populations::patch_occupancy::{Colonization, OccupancyModel, Patch};
+/// let patches = (0..20)
+///     .map(|i| Patch { x: i as f64, y: 0.0, extinction_probability: 0.1, colonization_rate: 0.8 })
+///     .collect();
+/// let model = OccupancyModel::build(patches, vec![true; 20],
+///     Colonization::DistanceDependent { dispersal_distance: 2.0 }).unwrap();
+/// let output = model.project(100, 50, 42);
+/// assert!(output.mean_occupancy(99).unwrap() > 0.0);
+/// assert!(output.mean_occupancy(100).is_none());
+/// ```
+#[derive(Clone, Debug)]
+pub struct OccupancyModel {
+    patches: Vec<Patch>,
+    initial_occupancy: Vec<bool>,
+    colonization: Colonization,
+    habitat: Option<HabitatTimeline>,
+}
+impl OccupancyModel {
+    /// Build a patch-occupancy model.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no patches, the initial occupancy does not have
+    /// one value per patch, any extinction probability is outside 0 to 1, any colonization rate
+    /// is negative, or the dispersal distance is not positive.
+    pub fn build(
+        patches: Vec<Patch>,
+        initial_occupancy: Vec<bool>,
+        colonization: Colonization,
+    ) -> Result<OccupancyModel, &'static str> {
+        if patches.is_empty() {
+            return Err("At least one patch is required.");
+        }
+        if initial_occupancy.len() != patches.len() {
+            return Err("Initial occupancy must have one value per patch.");
+        }
+        if patches
+            .iter()
+            .any(|x| !(0.0..=1.0).contains(&x.extinction_probability))
+        {
+            return Err("Extinction probabilities must be between 0 and 1.");
+        }
+        if patches
+            .iter()
+            .any(|x| !x.colonization_rate.is_finite() || x.colonization_rate < 0.0)
+        {
+            return Err("Colonization rates cannot be negative.");
+        }
+        if let Colonization::DistanceDependent { dispersal_distance } = colonization {
+            if dispersal_distance.is_nan() || dispersal_distance <= 0.0 {
+                return Err("The dispersal distance must be positive.");
+            }
+        }
+        Ok(OccupancyModel {
+            patches,
+            initial_occupancy,
+            colonization,
+            habitat: None,
+        })
+    }
+    /// Return the patches of the model.
+    pub fn get_patches(&self) -> &Vec<Patch> {
+        &self.patches
+    }
+    /// Return which patches are occupied at the start.
+    pub fn get_initial_occupancy(&self) -> &Vec<bool> {
+        &self.initial_occupancy
+    }
+    /// Return the colonization pressure `S` on a patch, given which patches are occupied.
+    pub fn connectivity(&self, patch: usize, occupancy: &[bool]) -> f64 {
+        let others = occupancy
+            .iter()
+            .enumerate()
+            .filter(|(index, occupied)| **occupied && *index != patch);
+        match self.colonization {
+            Colonization::Global => {
+                if self.patches.len() < 2 {
+                    0.0
+                } else {
+                    others.count() as f64 / (self.patches.len() - 1) as f64
+                }
+            }
+            Colonization::DistanceDependent { dispersal_distance } => {
+                let target = &self.patches[patch];
+                others
+                    .map(|(index, _)| {
+                        let source = &self.patches[index];
+                        let distance = (source.x - target.x).hypot(source.y - target.y);
+                        (-distance / dispersal_distance).exp()
+                    })
+                    .sum()
+            }
+        }
+    }
+    /// Return a [`Simulation`] of one replicate. The replicate number and master seed determine
+    /// the random sequence, as for stochastic matrix models.
+    pub fn simulation(&self, replicate: u32, seed: u64) -> OccupancySimulation<'_> {
+        OccupancySimulation {
+            model: self,
+            occupancy: self.initial_occupancy.clone(),
+            rng: StdRng::seed_from_u64(replicate_seed(seed, replicate)),
+            steps_taken: 0,
+        }
+    }
+    /// Return the output of simulating the given number of steps and replicates.
+    pub fn project(&self, steps: u32, replicates: u32, seed: u64) -> OccupancyOutput {
+        OccupancyOutput {
+            replicates: (0..replicates)
+                .map(|replicate| self.simulation(replicate, seed).run(steps))
+                .collect(),
+        }
+    }
+}
+
+/// The running state of one replicate of an [`OccupancyModel`], created by
+/// [`OccupancyModel::simulation`]. The state holds whether each patch is occupied.
+pub struct OccupancySimulation<'a> {
+    model: &'a OccupancyModel,
+    occupancy: Vec<bool>,
+    rng: StdRng,
+    steps_taken: u32,
+}
+impl Simulation for OccupancySimulation<'_> {
+    type State = Vec<bool>;
+    fn step(&mut self) {
+        let rates = self.model.rates(self.steps_taken);
+        let next = (0..self.occupancy.len())
+            .map(|index| {
+                let (extinction_probability, colonization_rate) = rates[index];
+                if self.occupancy[index] {
+                    !self.rng.gen_bool(extinction_probability)
+                } else {
+                    let pressure = self.model.connectivity(index, &self.occupancy);
+                    let probability = 1.0 - (-colonization_rate * pressure).exp();
+                    self.rng.gen_bool(probability.clamp(0.0, 1.0))
+                }
+            })
+            .collect();
+        self.occupancy = next;
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &Vec<bool> {
+        &self.occupancy
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+
+/// This struct stores the output of a patch-occupancy simulation: which patches were occupied at
+/// every step of every replicate.
+pub struct OccupancyOutput {
+    replicates: Vec<Vec<Vec<bool>>>,
+}
+impl OccupancyOutput {
+    /// Return the occupancy of every patch at every step of every replicate.
+    pub fn get_replicates(&self) -> &Vec<Vec<Vec<bool>>> {
+        &self.replicates
+    }
+    /// Return the fraction of patches occupied at a step (starting at 0 for the first step),
+    /// averaged over replicates. Returns `None` if the simulation did not reach that step.
+    pub fn mean_occupancy(&self, step: usize) -> Option<f64> {
+        let fractions = self
+            .replicates
+            .iter()
+            .map(|replicate| {
+                replicate
+                    .get(step)
+                    .map(|occupancy| occupied_fraction(occupancy))
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        if fractions.is_empty() {
+            return None;
+        }
+        Some(fractions.iter().sum::<f64>() / fractions.len() as f64)
+    }
+    /// Return the fraction of replicates in which every patch is empty at the last step.
+    pub fn extinction_probability(&self) -> f64 {
+        let extinct = self
+            .replicates
+            .iter()
+            .filter(|replicate| {
+                replicate
+                    .last()
+                    .is_some_and(|patches| patches.iter().all(|x| !x))
+            })
+            .count();
+        extinct as f64 / self.replicates.len() as f64
+    }
+}
+impl ToOutputTable for OccupancyOutput {
+    /// The table has `replicate`, `step`, and `occupied_fraction` columns, followed by one
+    /// column per patch holding 1 if it is occupied and 0 if it is empty.
+    fn to_output_table(&self) -> OutputTable {
+        let patch_count = self
+            .replicates
+            .iter()
+            .flatten()
+            .next()
+            .map_or(0, |x| x.len());
+        let mut columns = vec![
+            String::from("replicate"),
+            String::from("step"),
+            String::from("occupied_fraction"),
+        ];
+        columns.extend((0..patch_count).map(|x| format!("patch_{}", x)));
+        let mut rows = Vec::new();
+        for (replicate, steps) in self.replicates.iter().enumerate() {
+            for (step, patches) in steps.iter().enumerate() {
+                let mut row = vec![
+                    replicate as f64,
+                    (step + 1) as f64,
+                    occupied_fraction(patches),
+                ];
+                row.extend(patches.iter().map(|x| if *x { 1.0 } else { 0.0 }));
+                rows.push(row);
+            }
+        }
+        OutputTable::build(columns, rows).expect("Rows are built with one value per column.")
+    }
+}
+
+fn occupied_fraction(patches: &[bool]) -> f64 {
+    patches.iter().filter(|x| **x).count() as f64 / patches.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patches(count: usize, extinction_probability: f64, colonization_rate: f64) -> Vec<Patch> {
+        (0..count)
+            .map(|i| Patch {
+                x: i as f64,
+                y: 0.0,
+                extinction_probability,
+                colonization_rate,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn certain_extinction_empties_every_patch() {
+        let model =
+            OccupancyModel::build(patches(10, 1.0, 0.0), vec![true; 10], Colonization::Global)
+                .unwrap();
+        let output = model.project(3, 5, 1);
+        assert_eq!(output.mean_occupancy(0).unwrap(), 0.0);
+        assert_eq!(output.extinction_probability(), 1.0);
+    }
+    #[test]
+    fn levins_occupancy_approaches_equilibrium() {
+        // Discrete-time Levins dynamics: p' = p(1 - e) + (1 - p)(1 - exp(-c p)).
+        let (e, c) = (0.2, 1.0);
+        let mut p: f64 = 0.5;
+        for _ in 0..500 {
+            p = p * (1.0 - e) + (1.0 - p) * (1.0 - (-c * p).exp());
+        }
+        let model = OccupancyModel::build(
+            patches(200, e, c),
+            (0..200).map(|i| i % 2 == 0).collect(),
+            Colonization::Global,
+        )
+        .unwrap();
+        let output = model.project(200, 20, 7);
+        assert!((output.mean_occupancy(199).unwrap() - p).abs() < 0.05);
+    }
+    #[test]
+    fn distant_patches_are_not_colonized() {
+        let mut far = patches(2, 0.0, 5.0);
+        far[1].x = 1_000.0;
+        let model = OccupancyModel::build(
+            far,
+            vec![true, false],
+            Colonization::DistanceDependent {
+                dispersal_distance: 1.0,
+            },
+        )
+        .unwrap();
+        let output = model.project(20, 3, 3);
+        assert_eq!(output.mean_occupancy(19).unwrap(), 0.5);
+        assert_eq!(output.to_output_table().get_columns().len(), 5);
+    }
+}
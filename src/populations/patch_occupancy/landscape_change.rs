@@ -0,0 +1,250 @@
+//! This module contains landscape change for spatial models: habitat quality that changes over
+//! the course of a simulation, given as snapshots (a series of rasters, or per-patch quality
+//! values from land-use scenarios) and interpolated between them, so habitat loss and
+//! restoration unfold gradually while the metapopulation responds.
+//!
+//! A timeline scales the extinction and colonization rates of an [`OccupancyModel`], and weights
+//! where dispersers settle in an individual-based metapopulation (see
+//! `IndividualMetapopulation::with_habitat_timeline`).
+use super::{OccupancyModel, Patch};
+use crate::imports::HabitatRaster;
+
+/// This struct holds the habitat quality of each patch at a series of snapshot steps. The
+/// quality at step `t` (the step after `t` steps have been taken) is interpolated linearly
+/// between the snapshots before and after it, and held at the first or last snapshot outside
+/// them.
+/// ```
+/// use ecolysis_cmd::populations::patch_occupancy::HabitatTimeline;
+/// // The second patch is cleared over ten steps, then restored by step 30.
+/// let timeline = HabitatTimeline::build(vec![
+///     (0, vec![1.0, 1.0]),
+///     (10, vec![1.0, 0.0]),
+///     (30, vec![1.0, 0.8]),
+/// ]).unwrap();
+/// assert_eq!(timeline.quality(5), vec![1.0, 0.5]);
+/// assert_eq!(timeline.quality(20), vec![1.0, 0.4]);
+/// assert_eq!(timeline.quality(50), vec![1.0, 0.8]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HabitatTimeline {
+    steps: Vec<u32>,
+    qualities: Vec<Vec<f64>>,
+}
+impl HabitatTimeline {
+    /// Build a timeline from the quality of each patch at each snapshot step.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no snapshots, the steps are not increasing,
+    /// the snapshots do not all have the same number of patches, or a quality is negative or not
+    /// finite.
+    pub fn build(snapshots: Vec<(u32, Vec<f64>)>) -> Result<HabitatTimeline, &'static str> {
+        let first = snapshots
+            .first()
+            .ok_or("A habitat timeline needs at least one snapshot.")?;
+        let patches = first.1.len();
+        if snapshots.windows(2).any(|x| x[1].0 <= x[0].0) {
+            return Err("Habitat snapshot steps must be increasing.");
+        }
+        if snapshots.iter().any(|x| x.1.len() != patches) {
+            return Err("Every habitat snapshot must have one quality per patch.");
+        }
+        if snapshots
+            .iter()
+            .flat_map(|x| &x.1)
+            .any(|x| !x.is_finite() || *x < 0.0)
+        {
+            return Err("Habitat qualities must be finite and not negative.");
+        }
+        let (steps, qualities) = snapshots.into_iter().unzip();
+        Ok(HabitatTimeline { steps, qualities })
+    }
+    /// Build a timeline from a series of rasters, reading the quality of each patch from the
+    /// cell containing its location. Patches outside a raster or on cells with no data have a
+    /// quality of zero in that snapshot, so the patches should come from
+    /// [`HabitatRaster::union_cell_patches`] to include cells that only gain habitat later.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are no rasters, the steps are not increasing, or a
+    /// value is negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::imports::HabitatRaster;
+    /// use ecolysis_cmd::populations::patch_occupancy::HabitatTimeline;
+    /// let before = HabitatRaster::build(2, 1, 0.0, 0.0, 1.0, vec![Some(1.0), Some(1.0)]).unwrap();
+    /// let after = HabitatRaster::build(2, 1, 0.0, 0.0, 1.0, vec![Some(1.0), None]).unwrap();
+    /// let restored = HabitatRaster::build(2, 1, 0.0, 0.0, 1.0, vec![None, Some(1.0)]).unwrap();
+    /// let patches = HabitatRaster::union_cell_patches(&[&after, &restored], 0.1, 1.0).unwrap();
+    /// assert_eq!(patches.len(), 2);
+    /// let timeline = HabitatTimeline::from_rasters(vec![(0, before), (4, after)], &patches).unwrap();
+    /// assert_eq!(timeline.quality(1), vec![1.0, 0.75]);
+    /// ```
+    pub fn from_rasters(
+        snapshots: Vec<(u32, HabitatRaster)>,
+        patches: &[Patch],
+    ) -> Result<HabitatTimeline, &'static str> {
+        HabitatTimeline::build(
+            snapshots
+                .into_iter()
+                .map(|(step, raster)| {
+                    let qualities = patches
+                        .iter()
+                        .map(|x| raster.value_at(x.x, x.y).unwrap_or(0.0))
+                        .collect();
+                    (step, qualities)
+                })
+                .collect(),
+        )
+    }
+    /// Return the number of patches.
+    pub fn patch_count(&self) -> usize {
+        self.qualities[0].len()
+    }
+    /// Return the steps of the snapshots.
+    pub fn get_steps(&self) -> &Vec<u32> {
+        &self.steps
+    }
+    /// Return the quality of each patch at a step.
+    pub fn quality(&self, step: u32) -> Vec<f64> {
+        let after = self.steps.partition_point(|x| *x <= step);
+        if after == 0 {
+            return self.qualities[0].clone();
+        }
+        if after == self.steps.len() {
+            return self.qualities[after - 1].clone();
+        }
+        let (start, end) = (self.steps[after - 1], self.steps[after]);
+        let weight = (step - start) as f64 / (end - start) as f64;
+        self.qualities[after - 1]
+            .iter()
+            .zip(&self.qualities[after])
+            .map(|(a, b)| a + weight * (b - a))
+            .collect()
+    }
+}
+
+impl OccupancyModel {
+    /// Return the model with habitat quality changing over time. The rates of each patch then
+    /// apply at a quality of one and are scaled by its quality each step as in
+    /// [`HabitatRaster::to_patches`]: the colonization rate is multiplied by the quality and the
+    /// extinction probability divided by it (at most one), so a patch with no habitat left
+    /// cannot be colonized and its population is lost.
+    /// # Errors
+    /// Will return `Err<'static str>` if the timeline does not have one quality per patch.
+    /// ```
+    /// use ecolysis_cmd::populations::patch_occupancy::{Colonization, HabitatTimeline, OccupancyModel, Patch};
+    /// let patches = (0..10)
+    ///     .map(|i| Patch { x: i as f64, y: 0.0, extinction_probability: 0.05, colonization_rate: 2.0 })
+    ///     .collect();
+    /// // Half of the habitat is destroyed over the first 20 steps.
+    /// let lost: Vec<f64> = (0..10).map(|i| if i < 5 { 1.0 } else { 0.0 }).collect();
+    /// let timeline = HabitatTimeline::build(vec![(0, vec![1.0; 10]), (20, lost)]).unwrap();
+    /// let model = OccupancyModel::build(patches, vec![true; 10], Colonization::Global)
+    ///     .unwrap()
+    ///     .with_habitat_timeline(timeline)
+    ///     .unwrap();
+    /// let output = model.project(30, 10, 1);
+    /// assert!(output.mean_occupancy(29).unwrap() <= 0.5);
+    /// ```
+    pub fn with_habitat_timeline(
+        mut self,
+        timeline: HabitatTimeline,
+    ) -> Result<Self, &'static str> {
+        if timeline.patch_count() != self.patches.len() {
+            return Err("The habitat timeline must have one quality per patch.");
+        }
+        self.habitat = Some(timeline);
+        Ok(self)
+    }
+    /// Return the habitat timeline of the model, if any.
+    pub fn get_habitat_timeline(&self) -> Option<&HabitatTimeline> {
+        self.habitat.as_ref()
+    }
+    /// Return the extinction probability and colonization rate of each patch during the step
+    /// after `steps_taken` steps.
+    pub fn rates(&self, steps_taken: u32) -> Vec<(f64, f64)> {
+        match &self.habitat {
+            None => self
+                .patches
+                .iter()
+                .map(|x| (x.extinction_probability, x.colonization_rate))
+                .collect(),
+            Some(timeline) => self
+                .patches
+                .iter()
+                .zip(timeline.quality(steps_taken))
+                .map(|(patch, quality)| match quality > 0.0 {
+                    true => (
+                        (patch.extinction_probability / quality).min(1.0),
+                        patch.colonization_rate * quality,
+                    ),
+                    false => (1.0, 0.0),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::patch_occupancy::Colonization;
+
+    #[test]
+    fn restoration_brings_patches_back() {
+        let patches: Vec<Patch> = (0..40)
+            .map(|i| Patch {
+                x: i as f64,
+                y: 0.0,
+                extinction_probability: 0.1,
+                colonization_rate: 3.0,
+            })
+            .collect();
+        let timeline = HabitatTimeline::build(vec![
+            (0, vec![1.0; 40]),
+            (5, vec![0.0; 40]),
+            (6, vec![0.0; 40]),
+            (10, vec![1.0; 40]),
+        ])
+        .unwrap();
+        let model = OccupancyModel::build(patches.clone(), vec![true; 40], Colonization::Global)
+            .unwrap()
+            .with_habitat_timeline(timeline)
+            .unwrap();
+        let (extinction, colonization) = model.rates(2)[0];
+        assert!((extinction - 0.1 / 0.6).abs() < 1e-12 && (colonization - 1.8).abs() < 1e-12);
+        assert_eq!(model.rates(5)[0], (1.0, 0.0));
+        let output = model.project(12, 5, 2);
+        assert_eq!(output.mean_occupancy(5).unwrap(), 0.0);
+        assert_eq!(output.extinction_probability(), 1.0);
+        // With a few refuges, the restored patches are recolonized.
+        let mut refuges = vec![0.0; 40];
+        refuges[..4].fill(1.0);
+        let timeline = HabitatTimeline::build(vec![
+            (0, refuges.clone()),
+            (5, refuges),
+            (10, vec![1.0; 40]),
+        ])
+        .unwrap();
+        let model = OccupancyModel::build(patches, vec![true; 40], Colonization::Global)
+            .unwrap()
+            .with_habitat_timeline(timeline)
+            .unwrap();
+        let output = model.project(30, 5, 2);
+        assert!(output.mean_occupancy(3).unwrap() <= 0.1);
+        assert!(output.mean_occupancy(29).unwrap() > 0.5);
+        assert!(HabitatTimeline::build(Vec::new()).is_err());
+        assert!(HabitatTimeline::build(vec![(3, vec![1.0]), (3, vec![1.0])]).is_err());
+        assert!(HabitatTimeline::build(vec![(0, vec![1.0]), (3, vec![1.0, 1.0])]).is_err());
+        assert!(HabitatTimeline::build(vec![(0, vec![-1.0])]).is_err());
+        let model = OccupancyModel::build(
+            vec![Patch {
+                x: 0.0,
+                y: 0.0,
+                extinction_probability: 0.1,
+                colonization_rate: 1.0,
+            }],
+            vec![true],
+            Colonization::Global,
+        )
+        .unwrap();
+        let two = HabitatTimeline::build(vec![(0, vec![1.0, 1.0])]).unwrap();
+        assert!(model.with_habitat_timeline(two).is_err());
+    }
+}
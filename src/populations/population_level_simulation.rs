@@ -1,32 +1,344 @@
 //! This module contains functions to simulate population demographics (not including genetics) using forward-direction population-level simulations. Populations are represented by matrices and vectors containing demographic and behavioral information.
+use crate::simulation::Simulation;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    hash::{Hash, Hasher},
+    ops::{Add, Index, Mul},
+};
+mod convergence;
+mod covariates;
+mod decomposition;
+mod dormancy;
+mod eigen_analysis;
+mod estimation;
+mod extinction;
+mod hindcast;
+mod initial_populations;
+mod linear_algebra;
+mod matrix_model;
+mod numeric_guard;
+mod observation;
+mod perturbation;
+mod posterior;
+mod precision;
+mod projection_config;
+mod random_matrix;
+mod schedule;
+mod sparse;
+mod stage_aggregation;
+mod stage_duration;
+mod stochastic;
+mod stochastic_sensitivity;
+mod summation;
+mod time_step;
+mod two_sex;
+mod uncertainty;
+mod verbosity;
+pub use convergence::*;
+pub use covariates::*;
+pub use decomposition::*;
+pub use dormancy::*;
+pub use estimation::*;
+pub use extinction::*;
+pub use hindcast::*;
+pub use initial_populations::*;
+pub use matrix_model::*;
+pub use numeric_guard::*;
+pub use observation::*;
+pub use perturbation::*;
+pub use posterior::*;
+pub use precision::*;
+pub use projection_config::*;
+pub use random_matrix::*;
+pub use schedule::*;
+pub use sparse::*;
+pub use stage_duration::*;
+pub use stochastic::*;
+pub use stochastic_sensitivity::*;
+pub use summation::*;
+pub use time_step::*;
+pub use two_sex::*;
+pub use uncertainty::*;
+pub use verbosity::*;
 
 /// This struct represents a population by a (typically integer) vector. Each value of the vector represents the number of individuals in a lifestage present in the population. For example, a population with 15 hatchlings, 8 juveniles, and 30 adults could be represented by this vector: `[40, 20, 100]`. This struct is meant to contain this type of information. The data is stored as f64 (floating point) values to accommodate conditions when decimal populations are desirable and facilitate calculations that may not return integer values.
-#[derive(Clone)]
-pub struct PopulationVector {
-    vector: Vec<f64>,
+///
+/// `f32` values can be stored instead to save memory, as `PopulationVector<f32>` (see [`Real`]),
+/// although the simulation engines only project `f64` vectors.
+#[derive(Clone, Debug)]
+pub struct PopulationVector<T = f64> {
+    vector: Vec<T>,
     lifestage_count: u8,
 }
-impl PopulationVector {
+impl<T: Real> PopulationVector<T> {
     /// Create a new Population Vector instance by inputting a vector containing f64 values.
-    pub fn new(vector: Vec<f64>) -> PopulationVector {
+    pub fn new(vector: Vec<T>) -> PopulationVector<T> {
         PopulationVector {
             lifestage_count: vector.len() as u8,
-            vector: vector,
+            vector,
         }
     }
+    /// Create a new Population Vector, checking each value as
+    /// [`PopulationVector::set_value_at_index`] does.
+    /// # Errors
+    /// Will return `Err<'static str>` if any value is negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// assert!(PopulationVector::build(vec![40.0, 20.0]).is_ok());
+    /// assert!(PopulationVector::build(vec![40.0, f64::NAN]).is_err());
+    /// ```
+    pub fn build(vector: Vec<T>) -> Result<PopulationVector<T>, &'static str> {
+        for value in &vector {
+            check_element(*value)?;
+        }
+        Ok(PopulationVector::new(vector))
+    }
     /// Return the value stored at a specifc index in the Population Vector based on inputed
     /// integer (u32). The first value is 0.
-    pub fn get_value_at_index(&self, index: u32) -> Option<&f64> {
+    pub fn get_value_at_index(&self, index: u32) -> Option<&T> {
         self.vector.get(index as usize)
     }
     // Return full vector stored in the Population Vector as a `Vec<f64>`.
-    pub fn get_vector(&self) -> &Vec<f64> {
-        return &self.vector;
+    pub fn get_vector(&self) -> &Vec<T> {
+        &self.vector
     }
     // Return the number of items stored in the Population Vector instance. This is used to prevent
     // errors in calculations that require matching vector/matrix lengths.
     pub fn get_lifestage_count(&self) -> u8 {
-        return self.lifestage_count;
+        self.lifestage_count
+    }
+    /// Return an iterator over the values stored in the Population Vector, from the first
+    /// lifestage to the last.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let popvector = PopulationVector::new(vec![40.0, 20.0, 100.0]);
+    /// let total: f64 = popvector.iter().sum();
+    /// assert_eq!(total, 160.0);
+    /// ```
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.vector.iter()
+    }
+    /// Return a copy of the Population Vector storing another floating point type, for example
+    /// to convert a single precision vector back to `f64` for analysis.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let compact: PopulationVector<f32> = PopulationVector::new(vec![40.0, 20.0]).cast();
+    /// assert_eq!(compact.get_vector(), &vec![40.0_f32, 20.0]);
+    /// ```
+    pub fn cast<U: Real>(&self) -> PopulationVector<U> {
+        PopulationVector::new(
+            self.vector
+                .iter()
+                .map(|x| U::from_f64(x.to_f64()))
+                .collect(),
+        )
+    }
+    /// Change the value stored at an index in the Population Vector. The first value is 0.
+    /// # Errors
+    /// Will return `Err<'static str>` if the index is outside the vector, or if the value is
+    /// negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let mut popvector = PopulationVector::new(vec![40.0, 20.0]);
+    /// popvector.set_value_at_index(1, 25.0).unwrap();
+    /// assert_eq!(popvector.get_vector(), &vec![40.0, 25.0]);
+    /// assert!(popvector.set_value_at_index(2, 1.0).is_err());
+    /// ```
+    pub fn set_value_at_index(&mut self, index: u32, value: T) -> Result<(), &'static str> {
+        check_element(value)?;
+        let element = self
+            .vector
+            .get_mut(index as usize)
+            .ok_or("The index is outside the Population Vector.")?;
+        *element = value;
+        Ok(())
+    }
+    /// Consume the Population Vector, returning the values it stored.
+    pub fn into_inner(self) -> Vec<T> {
+        self.vector
+    }
+}
+/// Two Population Vectors are equal if they store exactly the same values. Use
+/// [`PopulationVector::approx_eq`] to allow for floating point error.
+impl<T: Real> PartialEq for PopulationVector<T> {
+    fn eq(&self, other: &PopulationVector<T>) -> bool {
+        self.vector == other.vector
+    }
+}
+/// Hashing is consistent with `==`, so equal vectors have equal hashes (`0.0` and `-0.0` hash
+/// the same). `Eq` is not implemented because `NaN` is not equal to itself.
+impl<T: Real> Hash for PopulationVector<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_values(&self.vector, state);
+    }
+}
+impl<T: Real> PopulationVector<T> {
+    /// Return true if both vectors have the same number of lifestages and every value differs by
+    /// at most `tolerance`.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let popvector = PopulationVector::new(vec![0.1 + 0.2, 1.0]);
+    /// assert!(popvector != PopulationVector::new(vec![0.3, 1.0]));
+    /// assert!(popvector.approx_eq(&PopulationVector::new(vec![0.3, 1.0]), 1e-12));
+    /// ```
+    pub fn approx_eq(&self, other: &PopulationVector<T>, tolerance: f64) -> bool {
+        values_approx_eq(&self.vector, &other.vector, tolerance)
+    }
+}
+/// Return true if both slices have the same length and every pair of values differs by at most
+/// `tolerance`.
+fn values_approx_eq<T: Real>(a: &[T], b: &[T], tolerance: f64) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(x, y)| (x.to_f64() - y.to_f64()).abs() <= tolerance)
+}
+/// Hash floating point values so that values that compare equal hash the same.
+fn hash_values<T: Real, H: Hasher>(values: &[T], state: &mut H) {
+    values.len().hash(state);
+    for value in values {
+        // Adding zero turns -0.0 into 0.0.
+        (value.to_f64() + 0.0).to_bits().hash(state);
+    }
+}
+impl<T: Real> From<Vec<T>> for PopulationVector<T> {
+    fn from(vector: Vec<T>) -> PopulationVector<T> {
+        PopulationVector::new(vector)
+    }
+}
+/// Check that a value can be stored in a Population Vector or Population Matrix.
+fn check_element<T: Real>(value: T) -> Result<(), &'static str> {
+    let value = value.to_f64();
+    if !value.is_finite() || value < 0.0 {
+        return Err("Values must be finite and not negative.");
+    }
+    Ok(())
+}
+impl PopulationVector {
+    /// Return the total population: the sum of every lifestage.
+    pub fn total(&self) -> f64 {
+        self.vector.iter().sum()
+    }
+    /// Return the proportion of the population in each lifestage. The proportions add up to one,
+    /// unless the population is empty, in which case every proportion is zero.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let popvector = PopulationVector::new(vec![40.0, 20.0, 140.0]);
+    /// assert_eq!(popvector.proportions().get_vector(), &vec![0.2, 0.1, 0.7]);
+    /// ```
+    pub fn proportions(&self) -> PopulationVector {
+        let total = self.total();
+        if total == 0.0 {
+            return PopulationVector::new(vec![0.0; self.vector.len()]);
+        }
+        PopulationVector::new(self.vector.iter().map(|x| x / total).collect())
+    }
+    /// Return the Population Vector with every lifestage multiplied by `factor` (the same as the
+    /// `*` operator).
+    pub fn scale(&self, factor: f64) -> PopulationVector {
+        self * factor
+    }
+    /// Return the Population Vector rescaled to the given total, keeping the proportion in each
+    /// lifestage. An empty population stays empty.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let popvector = PopulationVector::new(vec![40.0, 20.0, 140.0]);
+    /// assert_eq!(popvector.normalize_to(50.0).get_vector(), &vec![10.0, 5.0, 35.0]);
+    /// ```
+    pub fn normalize_to(&self, total: f64) -> PopulationVector {
+        self.proportions().scale(total)
+    }
+    /// Add another Population Vector element by element, like the `+` operator but without
+    /// panicking.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lifestage counts of the two vectors do not match.
+    pub fn checked_add(&self, other: &PopulationVector) -> Result<PopulationVector, &'static str> {
+        if self.lifestage_count != other.lifestage_count {
+            return Err("Population Vector lifestage counts do not match.");
+        }
+        Ok(self + other)
+    }
+    /// Return the Population Vector rounded to whole individuals using the given rule.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{PopulationVector, RoundingRule};
+    /// let popvector = PopulationVector::new(vec![3.4, 3.3, 3.3]);
+    /// assert_eq!(popvector.round(RoundingRule::Nearest).get_vector(), &vec![3.0, 3.0, 3.0]);
+    /// assert_eq!(popvector.round(RoundingRule::LargestRemainder).get_vector(), &vec![4.0, 3.0, 3.0]);
+    /// ```
+    pub fn round(&self, rule: RoundingRule) -> PopulationVector {
+        let mut rounded: Vec<f64> = match rule {
+            RoundingRule::Nearest => self.iter().map(|x| x.round()).collect(),
+            RoundingRule::Down => self.iter().map(|x| x.floor()).collect(),
+            RoundingRule::Up => self.iter().map(|x| x.ceil()).collect(),
+            RoundingRule::LargestRemainder | RoundingRule::Stochastic(_) => {
+                self.iter().map(|x| x.floor()).collect()
+            }
+        };
+        match rule {
+            RoundingRule::LargestRemainder => {
+                let missing = (self.total().round() - rounded.iter().sum::<f64>()).max(0.0);
+                let mut order: Vec<usize> = (0..rounded.len()).collect();
+                order.sort_by(|a, b| {
+                    let remainder = |i: usize| self.vector[i] - rounded[i];
+                    remainder(*b).total_cmp(&remainder(*a))
+                });
+                for i in order.into_iter().take(missing as usize) {
+                    rounded[i] += 1.0;
+                }
+            }
+            RoundingRule::Stochastic(seed) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                for (value, original) in rounded.iter_mut().zip(&self.vector) {
+                    if rng.gen::<f64>() < original - *value {
+                        *value += 1.0;
+                    }
+                }
+            }
+            _ => {}
+        }
+        PopulationVector::new(rounded)
+    }
+}
+
+/// The ways a Population Vector can be rounded to whole individuals (see
+/// [`PopulationVector::round`]).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoundingRule {
+    /// Round each lifestage to the nearest whole number, with halves rounded away from zero.
+    Nearest,
+    /// Round each lifestage down.
+    Down,
+    /// Round each lifestage up.
+    Up,
+    /// Round each lifestage down, then add one to the lifestages with the largest fractional parts
+    /// until the total matches the rounded total of the original vector. The total population is
+    /// preserved as closely as possible.
+    LargestRemainder,
+    /// Round each lifestage up with a probability equal to its fractional part, and down
+    /// otherwise, using the given seed. The expected value of each lifestage is unchanged.
+    Stochastic(u64),
+}
+
+/// Return the realized growth rate `N_t+1 / N_t` between each pair of consecutive totals. A step
+/// that starts from an empty population has a growth rate of NaN.
+pub(crate) fn growth_rates(totals: &[f64]) -> Vec<f64> {
+    totals
+        .windows(2)
+        .map(|x| if x[0] == 0.0 { f64::NAN } else { x[1] / x[0] })
+        .collect()
+}
+impl<T> IntoIterator for PopulationVector<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.vector.into_iter()
+    }
+}
+impl<'a, T> IntoIterator for &'a PopulationVector<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.vector.iter()
     }
 }
 
@@ -52,40 +364,363 @@ impl PopulationVector {
 /// [0.6][0][0][0]
 /// [0][0.8][0][0]
 /// [0][0][0.8][0.94]
-pub struct PopulationMatrix {
-    matrix: Vec<Vec<f64>>,
+///
+/// Like Population Vectors, Population Matrices can store `f32` values instead, as
+/// `PopulationMatrix<f32>`. Projection and the `*` and `+` operators work with either type;
+/// eigen-analysis and other analyses need `f64`.
+///
+/// Internally the values are stored in one contiguous row-major list, so projecting a vector is a
+/// sequence of dot products over adjacent memory. This is hidden behind the constructors and
+/// accessors, which always work in rows and columns as described above.
+#[derive(Clone, Debug)]
+pub struct PopulationMatrix<T = f64> {
+    elements: Vec<T>,
     lifestage_count: u8,
+    sparse: Option<SparseMatrix<T>>,
+    summation: Summation,
 }
-impl PopulationMatrix {
+impl<T: Real> PopulationMatrix<T> {
     /// This function builds a Population Matrix from a square vector of vectors (Vec<Vec<f64>>), ensuring that it contains a consistent
     /// number of lifestages across all inputted Lifestage Survival Vectors and in the number of
-    /// inputted Lifestage Survival Vectors. If these conditions are not met, it will return an
-    /// error message.
-    pub fn build(input: Vec<Vec<f64>>) -> Result<PopulationMatrix, &'static str> {
+    /// inputted Lifestage Survival Vectors. If these conditions are not met, or the matrix is empty
+    /// or has more than 255 lifestages, it will return an error message.
+    pub fn build(input: Vec<Vec<T>>) -> Result<PopulationMatrix<T>, &'static str> {
+        if input.is_empty() || input.len() > u8::MAX as usize {
+            return Err("A population matrix must have between 1 and 255 lifestages.");
+        }
         if input.len() == input[0].len() {
             for count in 1..input.len() {
                 if input[count].len() != input[count - 1].len() {
                     return Err("All sub-vectors must be of matching lengths to construct a population matrix.");
                 }
             }
-            return Ok(PopulationMatrix {
-                lifestage_count: input.len() as u8,
-                matrix: input,
-            });
+            Ok(PopulationMatrix::from_rows(input))
         } else {
-            return Err("Number of items in lifestages must match number of inputted sub-vectors.");
+            Err("Number of items in lifestages must match number of inputted sub-vectors.")
         }
     }
+    /// Build a Population Matrix from rows already known to be square. Large matrices that are
+    /// mostly zeros also get a sparse copy, which is used for projection.
+    pub(crate) fn from_rows(matrix: Vec<Vec<T>>) -> PopulationMatrix<T> {
+        let size = matrix.len();
+        let elements = matrix.concat();
+        PopulationMatrix {
+            lifestage_count: size as u8,
+            sparse: sparse_if_worthwhile(&elements, size),
+            elements,
+            summation: Summation::Plain,
+        }
+    }
+    /// Build a Population Matrix from rows already known to be square, like
+    /// [`PopulationMatrix::from_rows`], keeping the summation of this one. Matrices derived from
+    /// another (transposed, cast, combined, or perturbed) are built this way.
+    pub(crate) fn derived<U: Real>(&self, matrix: Vec<Vec<U>>) -> PopulationMatrix<U> {
+        PopulationMatrix::from_rows(matrix).with_summation(self.summation)
+    }
     /// Returns the number of listages represented in the Population Matrix, useful for calculations requiring
     /// matching numbers of lifestages.
     pub fn get_lifestage_count(&self) -> u8 {
-        return self.lifestage_count;
+        self.lifestage_count
     }
-    /// Returns the full matrix of matrices stored in the Population Matrix.
-    pub fn get_matrix(&self) -> &Vec<Vec<f64>> {
-        return &self.matrix;
+    /// Returns a copy of the values stored in the Population Matrix, as rows. Use
+    /// [`PopulationMatrix::rows`] or indexing to read values without copying them.
+    pub fn get_matrix(&self) -> Vec<Vec<T>> {
+        self.rows().map(<[T]>::to_vec).collect()
     }
-
+    /// Return an iterator over the rows of the Population Matrix.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let totals: Vec<f64> = popmatrix.rows().map(|row| row.iter().sum()).collect();
+    /// assert_eq!(totals, vec![2.0, 1.3]);
+    /// ```
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, T> {
+        self.elements.chunks_exact(self.lifestage_count as usize)
+    }
+    /// Return the Population Matrix with the given method of adding up the products of a
+    /// projection (see [`Summation`]). The values of the matrix are unchanged. Matrices derived
+    /// from this one, such as its transpose, its powers, its products and sums with other
+    /// matrices (which take the summation of the left-hand matrix), and perturbed copies, keep
+    /// the same summation.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// use ecolysis_cmd::populations::population_level_simulation::Summation;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]])
+    ///     .unwrap()
+    ///     .with_summation(Summation::Compensated);
+    /// let popvector = PopulationVector::new(vec![10.0, 10.0]);
+    /// assert_eq!((&popmatrix * &popvector).get_vector(), &vec![20.0, 13.0]);
+    /// ```
+    pub fn with_summation(mut self, summation: Summation) -> Self {
+        self.summation = summation;
+        self
+    }
+    /// Return how the products of a projection are added up.
+    pub fn get_summation(&self) -> Summation {
+        self.summation
+    }
+    /// Return a row of the Population Matrix (starting from 0): the contributions of every
+    /// lifestage to one lifestage in the next step. Returns `None` if the row is outside the
+    /// matrix.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// assert_eq!(popmatrix.row(0), Some(&[0.0, 2.0][..]));
+    /// assert_eq!(popmatrix.row(2), None);
+    /// ```
+    pub fn row(&self, row: usize) -> Option<&[T]> {
+        self.rows().nth(row)
+    }
+    /// Return a column of the Population Matrix (starting from 0): the contributions of one
+    /// lifestage to every lifestage in the next step. Returns `None` if the column is outside the
+    /// matrix.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// assert_eq!(popmatrix.column(1), Some(vec![2.0, 0.8]));
+    /// // The column of a stage adds up to its survival plus its fecundity.
+    /// let adult: f64 = popmatrix.column(1).unwrap().iter().sum();
+    /// assert!((adult - 2.8).abs() < 1e-12);
+    /// ```
+    pub fn column(&self, column: usize) -> Option<Vec<T>> {
+        if column >= self.lifestage_count as usize {
+            return None;
+        }
+        Some(self.rows().map(|row| row[column]).collect())
+    }
+    /// Return the diagonal of the Population Matrix: the proportion of each lifestage that stays
+    /// in the same lifestage (stasis).
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// assert_eq!(popmatrix.diagonal(), vec![0.0, 0.8]);
+    /// ```
+    pub fn diagonal(&self) -> Vec<T> {
+        self.rows()
+            .enumerate()
+            .map(|(index, row)| row[index])
+            .collect()
+    }
+    /// Return the transpose of the Population Matrix, whose rows are the columns of this one.
+    /// Transposing is how the left eigenvector (and so the reproductive values) is found, and
+    /// how a projection is run backwards in time.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// assert_eq!(popmatrix.transpose().get_matrix(), vec![vec![0.0, 0.5], vec![2.0, 0.8]]);
+    /// assert_eq!(popmatrix.transpose().transpose(), popmatrix);
+    /// ```
+    pub fn transpose(&self) -> PopulationMatrix<T> {
+        self.derived(
+            (0..self.lifestage_count as usize)
+                .map(|column| self.rows().map(|row| row[column]).collect())
+                .collect(),
+        )
+    }
+    /// Return a copy of the Population Matrix storing another floating point type.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+    /// let compact: PopulationMatrix<f32> = popmatrix.cast();
+    /// let popvector: PopulationVector<f32> = PopulationVector::new(vec![10.0, 10.0]);
+    /// assert_eq!((&compact * &popvector).get_vector(), &vec![20.0_f32, 10.0]);
+    /// ```
+    pub fn cast<U: Real>(&self) -> PopulationMatrix<U> {
+        self.derived(
+            self.rows()
+                .map(|row| row.iter().map(|x| U::from_f64(x.to_f64())).collect())
+                .collect(),
+        )
+    }
+    /// Change the value at a row and column of the Population Matrix (both starting from 0),
+    /// without rebuilding it.
+    /// # Errors
+    /// Will return `Err<'static str>` if the row or column is outside the matrix, or if the value
+    /// is negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let mut popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+    /// popmatrix.set_element(1, 1, 0.6).unwrap();
+    /// assert_eq!(popmatrix[(1, 1)], 0.6);
+    /// assert!(popmatrix.set_element(0, 1, -1.0).is_err());
+    /// ```
+    pub fn set_element(&mut self, row: usize, column: usize, value: T) -> Result<(), &'static str> {
+        check_element(value)?;
+        let size = self.lifestage_count as usize;
+        if row >= size || column >= size {
+            return Err("The row or column is outside the Population Matrix.");
+        }
+        self.elements[row * size + column] = value;
+        if size >= SPARSE_MIN_LIFESTAGES {
+            self.sparse = sparse_if_worthwhile(&self.elements, size);
+        }
+        Ok(())
+    }
+    /// Return a copy of the Population Matrix with the value at a row and column (both starting
+    /// from 0) replaced, leaving the original unchanged. Useful for exploring "what if" changes
+    /// to a single vital rate.
+    /// # Errors
+    /// Will return `Err<'static str>` if the row or column is outside the matrix, or if the value
+    /// is negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let protected = popmatrix.with_element(1, 1, 0.9).unwrap();
+    /// assert!(protected.lambda() > popmatrix.lambda());
+    /// ```
+    pub fn with_element(
+        &self,
+        row: usize,
+        column: usize,
+        value: T,
+    ) -> Result<PopulationMatrix<T>, &'static str> {
+        let mut matrix = self.clone();
+        matrix.set_element(row, column, value)?;
+        Ok(matrix)
+    }
+    /// Return a copy of the Population Matrix with the value at a row and column multiplied by
+    /// `factor`, for example `0.9` for a 10% reduction.
+    /// # Errors
+    /// Will return `Err<'static str>` if the row or column is outside the matrix, or if the
+    /// scaled value is negative or not finite.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let harvested = popmatrix.scaled_element(1, 1, 0.5).unwrap();
+    /// assert_eq!(harvested[(1, 1)], 0.4);
+    /// ```
+    pub fn scaled_element(
+        &self,
+        row: usize,
+        column: usize,
+        factor: f64,
+    ) -> Result<PopulationMatrix<T>, &'static str> {
+        let value = self
+            .row(row)
+            .and_then(|x| x.get(column))
+            .ok_or("The row or column is outside the Population Matrix.")?;
+        self.with_element(row, column, T::from_f64(value.to_f64() * factor))
+    }
+    /// Consume the Population Matrix, returning its rows. The values are stored in one list, so
+    /// each row is still a new allocation; only the stored values are not copied first.
+    pub fn into_inner(self) -> Vec<Vec<T>> {
+        let size = self.lifestage_count as usize;
+        self.elements
+            .chunks_exact(size)
+            .map(<[T]>::to_vec)
+            .collect()
+    }
+}
+/// Two Population Matrices are equal if they store exactly the same values. Use
+/// [`PopulationMatrix::approx_eq`] to allow for floating point error.
+impl<T: Real> PartialEq for PopulationMatrix<T> {
+    fn eq(&self, other: &PopulationMatrix<T>) -> bool {
+        self.lifestage_count == other.lifestage_count
+            && self.elements == other.elements
+            && self.summation == other.summation
+    }
+}
+/// Hashing is consistent with `==` (see the `Hash` implementation of [`PopulationVector`]).
+impl<T: Real> Hash for PopulationMatrix<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for row in self.rows() {
+            hash_values(row, state);
+        }
+    }
+}
+impl<T: Real> PopulationMatrix<T> {
+    /// Return true if both matrices have the same number of lifestages and every value differs
+    /// by at most `tolerance`.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let squared = &popmatrix * &popmatrix;
+    /// assert!(squared.approx_eq(&popmatrix.pow(2), 1e-12));
+    /// ```
+    pub fn approx_eq(&self, other: &PopulationMatrix<T>, tolerance: f64) -> bool {
+        self.lifestage_count == other.lifestage_count
+            && values_approx_eq(&self.elements, &other.elements, tolerance)
+    }
+    /// Add another Population Matrix element by element, like the `+` operator but without
+    /// panicking.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lifestage counts of the two matrices do not match.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let survival = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap();
+    /// let fertility = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.0, 0.0]]).unwrap();
+    /// let matrix = survival.checked_add(&fertility).unwrap();
+    /// assert_eq!(matrix.get_matrix(), vec![vec![0.0, 2.0], vec![0.5, 0.8]]);
+    /// assert!(survival.checked_add(&PopulationMatrix::build(vec![vec![1.0]]).unwrap()).is_err());
+    /// ```
+    pub fn checked_add(
+        &self,
+        other: &PopulationMatrix<T>,
+    ) -> Result<PopulationMatrix<T>, &'static str> {
+        if self.lifestage_count != other.lifestage_count {
+            return Err("Population Matrix lifestage counts do not match.");
+        }
+        Ok(self + other)
+    }
+    /// Multiply by another Population Matrix, like the `*` operator but without panicking.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lifestage counts of the two matrices do not match.
+    pub fn checked_mul(
+        &self,
+        other: &PopulationMatrix<T>,
+    ) -> Result<PopulationMatrix<T>, &'static str> {
+        if self.lifestage_count != other.lifestage_count {
+            return Err("Population Matrix lifestage counts do not match.");
+        }
+        Ok(self * other)
+    }
+}
+impl<T: Real> TryFrom<Vec<Vec<T>>> for PopulationMatrix<T> {
+    type Error = &'static str;
+    /// Build a Population Matrix from rows (see [`PopulationMatrix::build`]).
+    fn try_from(rows: Vec<Vec<T>>) -> Result<PopulationMatrix<T>, &'static str> {
+        PopulationMatrix::build(rows)
+    }
+}
+impl PopulationMatrix {
+    /// Returns an identity Population Matrix (ones on the diagonal, zeroes elsewhere) with the
+    /// given number of lifestages. Projecting a vector by the identity matrix leaves it unchanged.
+    pub fn identity(lifestage_count: u8) -> PopulationMatrix {
+        let size = lifestage_count as usize;
+        let mut matrix = vec![vec![0.0; size]; size];
+        for (count, row) in matrix.iter_mut().enumerate() {
+            row[count] = 1.0;
+        }
+        PopulationMatrix::from_rows(matrix)
+    }
+    /// Returns the Population Matrix raised to the power `n`, which is the matrix that projects a
+    /// population `n` steps forward at once. This uses repeated squaring, so it only needs about
+    /// `log2(n)` matrix multiplications. `pow(0)` returns the identity matrix.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+    /// let popvector = PopulationVector::new(vec![10.0, 4.0]);
+    /// let ten_years = popmatrix.pow(10);
+    /// assert_eq!((&ten_years * &popvector).get_vector(), &vec![10.0, 4.0]);
+    /// ```
+    pub fn pow(&self, n: u32) -> PopulationMatrix {
+        let mut result =
+            PopulationMatrix::identity(self.lifestage_count).with_summation(self.summation);
+        let mut base = self.clone();
+        let mut exponent = n;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+}
+impl<T: Real> PopulationMatrix<T> {
     /// Given an input of a PopulationMatrix and a PopulationVector with the same number of items
     /// in their `matrix` and `vector` values respectively, the function will return a
     /// PopulationMatrix.
@@ -100,6 +735,10 @@ impl PopulationMatrix {
     ///
     ///This calculation is common in Population Variability Analysis (PVA) wherein each row (LifeStageSurvivalVector) represents the probability of recruitment into that life stage over the course of a year. By multiplying a matrix of these probabilities by a vector containing the current population, a researcher can estimate the following year's population.
     ///
+    /// Large matrices that are mostly zeros are projected using their sparse copy (see [`SparseMatrix`]), which only visits the non-zero values.
+    ///
+    /// Matrices set to [`Summation::Compensated`] (see [`PopulationMatrix::with_summation`]) add up each row (or, for sparse matrices, its non-zero values) with compensated summation instead, which limits round-off in very long projections and in large matrices.
+    ///
     /// ## Errors
     /// This function will return an Err('static str') if the number of rows or items within rows in the matrix is not equal to the number of items in the population vector.
     ///
@@ -118,61 +757,327 @@ impl PopulationMatrix {
     /// ```
     pub fn project_vector(
         &self,
-        vector: &PopulationVector,
-    ) -> Result<PopulationVector, &'static str> {
+        vector: &PopulationVector<T>,
+    ) -> Result<PopulationVector<T>, &'static str> {
         if self.lifestage_count != vector.get_lifestage_count() {
             return Err(
                 "the length of inputted population matrix and population vector do not match.",
             );
         }
-        let mut new_population_vector: Vec<f64> = vec![];
-        let mut storage: f64 = 0.0;
-        for lifestage in &self.matrix {
-            for (count2, item) in lifestage.iter().enumerate() {
-                storage += item
-                    * vector
-                        .get_value_at_index(count2 as u32)
-                        .expect("Index out of bounds.");
-            }
-            new_population_vector.push(storage);
-            storage = 0.0;
+        let compensated = self.summation == Summation::Compensated;
+        if let Some(sparse) = &self.sparse {
+            return Ok(PopulationVector::new(match compensated {
+                true => sparse.project_compensated(vector.get_vector())?,
+                false => sparse.project(vector.get_vector())?,
+            }));
+        }
+        if compensated {
+            return Ok(PopulationVector::new(
+                self.elements
+                    .chunks_exact(self.lifestage_count as usize)
+                    .map(|lifestage| compensated_dot(lifestage, vector.get_vector()))
+                    .collect(),
+            ));
         }
+        let vector = vector.get_vector();
+        let new_population_vector = match self.lifestage_count {
+            2 => project_unrolled::<T, 2>(&self.elements, vector),
+            3 => project_unrolled::<T, 3>(&self.elements, vector),
+            4 => project_unrolled::<T, 4>(&self.elements, vector),
+            5 => project_unrolled::<T, 5>(&self.elements, vector),
+            6 => project_unrolled::<T, 6>(&self.elements, vector),
+            7 => project_unrolled::<T, 7>(&self.elements, vector),
+            8 => project_unrolled::<T, 8>(&self.elements, vector),
+            // Keep UNROLLED_PROJECTION_MAX in step with the sizes above.
+            size => self
+                .elements
+                .chunks_exact(size as usize)
+                .map(|lifestage| {
+                    lifestage
+                        .iter()
+                        .zip(vector)
+                        .map(|(item, value)| *item * *value)
+                        .sum()
+                })
+                .collect(),
+        };
         Ok(PopulationVector::new(new_population_vector))
     }
 }
+impl<T: Real> PopulationMatrix<T> {
+    /// Project each of several Population Vectors by the Population Matrix, returning the
+    /// projected vectors in the same order. This is the product of the matrix with a matrix whose
+    /// columns are the vectors, and gives exactly the same results as calling
+    /// [`PopulationMatrix::project_vector`] on each vector.
+    ///
+    /// Dense matrices with more than 8 lifestages project the vectors in blocks of
+    /// [`PROJECTION_BLOCK`]: each value of the matrix is read once per block rather than once per
+    /// vector and multiplies the whole block at once. This saves time when many initial
+    /// populations share one matrix, as in [`DeterministicPva::deterministic_projections`], and in
+    /// fitting a model to a census. Bootstrap resamples and stochastic replicates draw a different
+    /// matrix for each population, so they project one vector at a time. Smaller matrices already
+    /// use the unrolled path of `project_vector`, sparse matrices their sparse copy, and matrices
+    /// with [`Summation::Compensated`] their own summation, so their vectors are projected one at
+    /// a time.
+    /// # Errors
+    /// Will return `Err<'static str>` if any vector does not have the same number of lifestages as
+    /// the matrix.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let projected = popmatrix.project_vectors(&[
+    ///     PopulationVector::new(vec![10.0, 10.0]),
+    ///     PopulationVector::new(vec![4.0, 0.0]),
+    /// ]).unwrap();
+    /// assert_eq!(projected[0].get_vector(), &vec![20.0, 13.0]);
+    /// assert_eq!(projected[1].get_vector(), &vec![0.0, 2.0]);
+    /// ```
+    pub fn project_vectors(
+        &self,
+        vectors: &[PopulationVector<T>],
+    ) -> Result<Vec<PopulationVector<T>>, &'static str> {
+        if vectors
+            .iter()
+            .any(|x| x.get_lifestage_count() != self.lifestage_count)
+        {
+            return Err(
+                "the length of inputted population matrix and population vector do not match.",
+            );
+        }
+        let size = self.lifestage_count as usize;
+        if self.sparse.is_some()
+            || size <= UNROLLED_PROJECTION_MAX
+            || self.summation == Summation::Compensated
+        {
+            return Ok(vectors
+                .iter()
+                .map(|x| {
+                    self.project_vector(x)
+                        .expect("Lifestage counts are checked above.")
+                })
+                .collect());
+        }
+        let mut projected = Vec::with_capacity(vectors.len());
+        let mut columns = vec![[T::default(); PROJECTION_BLOCK]; size];
+        let mut outputs = vec![[T::default(); PROJECTION_BLOCK]; size];
+        for block in vectors.chunks(PROJECTION_BLOCK) {
+            // Lay the block out with the values of each lifestage side by side, so every row of
+            // the matrix updates all the vectors at once. Unused slots of a short block are zero.
+            if block.len() < PROJECTION_BLOCK {
+                columns.fill([T::default(); PROJECTION_BLOCK]);
+            }
+            for (slot, vector) in block.iter().enumerate() {
+                for (values, value) in columns.iter_mut().zip(vector) {
+                    values[slot] = *value;
+                }
+            }
+            for (row, output) in self.elements.chunks_exact(size).zip(&mut outputs) {
+                let mut totals = [T::default(); PROJECTION_BLOCK];
+                for (item, values) in row.iter().zip(&columns) {
+                    for (total, value) in totals.iter_mut().zip(values) {
+                        *total = *total + *item * *value;
+                    }
+                }
+                *output = totals;
+            }
+            projected.extend(
+                (0..block.len())
+                    .map(|slot| PopulationVector::new(outputs.iter().map(|x| x[slot]).collect())),
+            );
+        }
+        Ok(projected)
+    }
+}
+/// The largest number of lifestages projected by `project_unrolled`.
+const UNROLLED_PROJECTION_MAX: usize = 8;
+/// The number of vectors [`PopulationMatrix::project_vectors`] projects together.
+pub const PROJECTION_BLOCK: usize = 8;
+/// Project a vector by a matrix whose number of lifestages is known at compile time. Most
+/// population models have between 2 and 8 lifestages, and fixing the size lets the compiler
+/// unroll each row and drop the bounds checks, which matters when a stochastic run performs
+/// millions of small projections. It does not use explicit SIMD instructions: values are added in
+/// the same order as the general case, so the results are identical. `matrix` is the row-major
+/// list of values.
+fn project_unrolled<T: Real, const N: usize>(matrix: &[T], vector: &[T]) -> Vec<T> {
+    let vector: &[T; N] = vector.try_into().expect("This error should not be possible. Mismatched Vector and Matrix lengths, or non-square Matrix. Please file a bug report.");
+    matrix
+        .chunks_exact(N)
+        .map(|row| {
+            let row: &[T; N] = row.try_into().expect("This error should not be possible. Mismatched Vector and Matrix lengths, or non-square Matrix. Please file a bug report.");
+            row.iter()
+                .zip(vector)
+                .fold(T::default(), |total, (item, value)| total + *item * *value)
+        })
+        .collect()
+}
+/// Access a single value of the Population Matrix by `(row, column)`, where the row is the
+/// lifestage individuals are recruited to and the column is the lifestage they came from. Panics if
+/// either index is out of bounds.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 0.9], vec![0.5, 0.8]]).unwrap();
+/// assert_eq!(popmatrix[(1, 0)], 0.5);
+/// ```
+impl<T> Index<(usize, usize)> for PopulationMatrix<T> {
+    type Output = T;
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        let size = self.lifestage_count as usize;
+        assert!(
+            row < size && column < size,
+            "The row or column is outside the Population Matrix."
+        );
+        &self.elements[row * size + column]
+    }
+}
+/// Project a Population Vector by a Population Matrix using the `*` operator. This is the same
+/// calculation as [`PopulationMatrix::project_vector`].
+/// # Panics
+/// Panics if the number of lifestages in the matrix and vector do not match. Use
+/// [`PopulationMatrix::project_vector`] to recieve an error instead.
+/// ```
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+/// let popvector = PopulationVector::new(vec![10.0, 10.0]);
+/// assert_eq!((&popmatrix * &popvector).get_vector(), &vec![20.0, 10.0]);
+/// ```
+impl<T: Real> Mul<&PopulationVector<T>> for &PopulationMatrix<T> {
+    type Output = PopulationVector<T>;
+    fn mul(self, vector: &PopulationVector<T>) -> PopulationVector<T> {
+        self.project_vector(vector)
+            .expect("Population Matrix and Population Vector lifestage counts do not match.")
+    }
+}
+/// Multiply two Population Matrices together using the `*` operator, returning their matrix
+/// product. Projecting a vector by `&b * &a` is equivalent to projecting it by `a` and then by
+/// `b`, so this can be used to combine several years (or seasons) into one matrix.
+/// # Panics
+/// Panics if the number of lifestages in the two matrices do not match. Use
+/// [`PopulationMatrix::checked_mul`] to recieve an error instead.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+/// let two_years = &popmatrix * &popmatrix;
+/// assert_eq!(two_years.get_matrix(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+/// ```
+impl<T: Real> Mul<&PopulationMatrix<T>> for &PopulationMatrix<T> {
+    type Output = PopulationMatrix<T>;
+    fn mul(self, other: &PopulationMatrix<T>) -> PopulationMatrix<T> {
+        assert_eq!(
+            self.lifestage_count, other.lifestage_count,
+            "Population Matrix lifestage counts do not match."
+        );
+        let size = self.lifestage_count as usize;
+        let mut matrix = vec![vec![T::default(); size]; size];
+        for (row, output_row) in matrix.iter_mut().enumerate() {
+            for (column, item) in output_row.iter_mut().enumerate() {
+                *item = (0..size).map(|k| self[(row, k)] * other[(k, column)]).sum();
+            }
+        }
+        self.derived(matrix)
+    }
+}
+/// Scale every value in the Population Matrix by a constant using the `*` operator. For
+/// example, multiplying by `0.9` reduces every survival and recruitment rate by 10%.
+impl Mul<f64> for &PopulationMatrix {
+    type Output = PopulationMatrix;
+    fn mul(self, scalar: f64) -> PopulationMatrix {
+        self.derived(
+            self.rows()
+                .map(|row| row.iter().map(|item| item * scalar).collect())
+                .collect(),
+        )
+    }
+}
+impl Mul<f64> for PopulationMatrix {
+    type Output = PopulationMatrix;
+    fn mul(self, scalar: f64) -> PopulationMatrix {
+        &self * scalar
+    }
+}
+/// Add two Population Matrices together element by element using the `+` operator.
+/// # Panics
+/// Panics if the number of lifestages in the two matrices do not match. Use
+/// [`PopulationMatrix::checked_add`] to recieve an error instead.
+impl<T: Real> Add<&PopulationMatrix<T>> for &PopulationMatrix<T> {
+    type Output = PopulationMatrix<T>;
+    fn add(self, other: &PopulationMatrix<T>) -> PopulationMatrix<T> {
+        assert_eq!(
+            self.lifestage_count, other.lifestage_count,
+            "Population Matrix lifestage counts do not match."
+        );
+        self.derived(
+            self.rows()
+                .zip(other.rows())
+                .map(|(a, b)| a.iter().zip(b).map(|(x, y)| *x + *y).collect())
+                .collect(),
+        )
+    }
+}
+/// Scale every value in the Population Vector by a constant using the `*` operator.
+impl Mul<f64> for &PopulationVector {
+    type Output = PopulationVector;
+    fn mul(self, scalar: f64) -> PopulationVector {
+        PopulationVector::new(self.iter().map(|x| x * scalar).collect())
+    }
+}
+impl Mul<f64> for PopulationVector {
+    type Output = PopulationVector;
+    fn mul(self, scalar: f64) -> PopulationVector {
+        &self * scalar
+    }
+}
+/// Add two Population Vectors together element by element using the `+` operator.
+/// # Panics
+/// Panics if the number of lifestages in the two vectors do not match. Use
+/// [`PopulationVector::checked_add`] to recieve an error instead.
+/// ```
+/// use ecolysis_cmd::PopulationVector;
+/// let wild = PopulationVector::new(vec![10.0, 4.0, 20.0]);
+/// let released = PopulationVector::new(vec![0.0, 6.0, 0.0]);
+/// assert_eq!((&wild + &released).get_vector(), &vec![10.0, 10.0, 20.0]);
+/// ```
+impl<T: Real> Add<&PopulationVector<T>> for &PopulationVector<T> {
+    type Output = PopulationVector<T>;
+    fn add(self, other: &PopulationVector<T>) -> PopulationVector<T> {
+        assert_eq!(
+            self.lifestage_count, other.lifestage_count,
+            "Population Vector lifestage counts do not match."
+        );
+        PopulationVector::new(self.iter().zip(other).map(|(x, y)| *x + *y).collect())
+    }
+}
 
-/// The PvaDeterministicPopulation struct stores population data for deterministic PVA models, allowing PVA operations to be performed by simply calling
-/// functions on an instance. It contains the following:
+/// The DeterministicPva struct stores population data for deterministic PVA models, allowing PVA operations to be performed by simply calling
+/// functions on an instance. It contains a [`MatrixModelCore`] with the following:
 /// - A Population Vector representing the initial population size.
-/// - A Population Matrix contains data on the survival rates
-/// and recruitment rates of verious lifestages.
-pub struct PvaDeterministicPopulation {
-    initial_population: PopulationVector,
-    projection_matrix: PopulationMatrix,
-}
-impl PvaDeterministicPopulation {
-    /// Return a Result enum containing a new PvaDeterministicPopulation instance with the input of a Population Vector and a Population Matrix.    /// one Population Matrix in the latter vector.
+/// - A single Population Matrix containing data on the survival rates
+///   and recruitment rates of verious lifestages.
+/// - The density dependence applied after each step (none by default).
+#[derive(Clone)]
+pub struct DeterministicPva {
+    core: MatrixModelCore,
+}
+/// The former name of [`DeterministicPva`].
+#[deprecated(note = "renamed to DeterministicPva")]
+pub type PvaDeterministicPopulation = DeterministicPva;
+impl DeterministicPva {
+    /// Return a Result enum containing a new DeterministicPva instance with the input of a Population Vector and a Population Matrix.
     /// # Errors
     /// Will return `Err<'static str>` if the lengths of the Population Vector the Matrix do not match.
     pub fn build(
         initial_population: PopulationVector,
         matrix: PopulationMatrix,
-    ) -> Result<PvaDeterministicPopulation, &'static str> {
-        let expected_lifestage_length = initial_population.get_lifestage_count();
-        if matrix.get_lifestage_count() != expected_lifestage_length {
-            return Err("Population vector size does not match matrices.");
-        }
-        Ok(PvaDeterministicPopulation {
-            initial_population: initial_population,
-            projection_matrix: matrix,
+    ) -> Result<DeterministicPva, &'static str> {
+        Ok(DeterministicPva {
+            core: MatrixModelCore::build(initial_population, vec![matrix])?,
         })
     }
-    /// Return a Result enum containing a new PvaDeterministicPopulation instance with the input of a vector containing f64 values (a population vector) and a square set of vector of vectors containing f64 values (a population matrix).    
+    /// Return a Result enum containing a new DeterministicPva instance with the input of a vector containing f64 values (a population vector) and a square set of vector of vectors containing f64 values (a population matrix).    
     /// # Errors
     /// Will return `Err<'static str>` if the lengths of the Population Vector the Matrix do not match.
     /// ```
-    ///use ecolysis_cmd::populations::population_level_simulation::PvaDeterministicPopulation;
-    ///let new_population = PvaDeterministicPopulation::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![12.0, 55.0, 172.0],
     ///vec![
     ///vec![0.0, 0.0, 0.9],
     ///vec![0.6, 0.9, 0.0],
@@ -182,12 +1087,131 @@ impl PvaDeterministicPopulation {
     pub fn build_from_vectors(
         initial_population: Vec<f64>,
         matrix: Vec<Vec<f64>>,
-    ) -> Result<PvaDeterministicPopulation, &'static str> {
-        PvaDeterministicPopulation::build(
+    ) -> Result<DeterministicPva, &'static str> {
+        DeterministicPva::build(
             PopulationVector::new(initial_population),
             PopulationMatrix::build(matrix)?,
         )
     }
+    /// Return the model with the given density dependence applied after each step.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::{DeterministicPva, DensityDependence};
+    ///let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    ///.with_density_dependence(DensityDependence::Ceiling(100.0));
+    ///assert!(population.project_n_steps(50).iter().sum::<f64>() <= 100.0);
+    /// ```
+    pub fn with_density_dependence(mut self, density_dependence: DensityDependence) -> Self {
+        self.core.set_density_dependence(density_dependence);
+        self
+    }
+    /// Return the model with the given `(row, column)` entries of the matrix treated as
+    /// reproduction, which an Allee effect reduces (see
+    /// [`MatrixModelCore::set_reproduction_entries`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if any entry is outside the matrix.
+    pub fn with_reproduction_entries(
+        mut self,
+        entries: Vec<(usize, usize)>,
+    ) -> Result<Self, &'static str> {
+        self.core.set_reproduction_entries(entries)?;
+        Ok(self)
+    }
+    /// Return the model with the given time step, which outputs of the model record so elapsed
+    /// time can be reported in real units.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::{DeterministicPva, TimeStep, TimeUnit};
+    ///let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    ///.with_time_step(TimeStep::new(1.0, TimeUnit::Month).unwrap());
+    ///let output = population.deterministic_projection(24);
+    ///assert_eq!(output.elapsed_times()[23], 24.0);
+    /// ```
+    pub fn with_time_step(mut self, time_step: TimeStep) -> Self {
+        self.core.set_time_step(time_step);
+        self
+    }
+    /// Return the model with its matrix adding up projections with the given [`Summation`].
+    /// Compensated summation keeps long projections from drifting through round-off.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::{DeterministicPva, Summation};
+    ///let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    ///let compensated = population.clone().with_summation(Summation::Compensated);
+    ///let plain = population.project_n_steps(200);
+    ///let accurate = compensated.project_n_steps(200);
+    ///assert!(plain.approx_eq(&accurate, 1e-9 * accurate.total()));
+    /// ```
+    pub fn with_summation(mut self, summation: Summation) -> Self {
+        self.core.set_summation(summation);
+        self
+    }
+    /// Return the model with a dormant lifestage whose re-entry into the active population may
+    /// depend on a covariate (see [`MatrixModelCore::set_dormancy`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if either lifestage of the dormancy is outside the
+    /// matrices.
+    /// ```
+    /// use ecolysis_cmd::DeterministicPva;
+    /// use ecolysis_cmd::populations::population_level_simulation::{CovariateSeries, Dormancy};
+    /// // Seeds rarely germinate except after a wet year.
+    /// let rain = CovariateSeries::build(vec![String::from("rain")],
+    ///     vec![vec![0.0], vec![0.0], vec![0.0], vec![1.0]]).unwrap();
+    /// let seed_bank = Dormancy::build(0, 1, 0.95, 0.01).unwrap().with_trigger(&rain, "rain", 8.0).unwrap();
+    /// let model = DeterministicPva::build_from_vectors(vec![100.0, 0.0, 0.0],
+    ///     vec![vec![0.0, 0.0, 30.0], vec![0.0, 0.0, 0.0], vec![0.0, 0.3, 0.0]]).unwrap()
+    ///     .with_dormancy(seed_bank).unwrap();
+    /// let output = model.deterministic_projection(4);
+    /// let seedlings: Vec<f64> = output.iter().map(|x| x.get_vector()[1]).collect();
+    /// assert!(seedlings[0] < 1.0 && seedlings[3] > 50.0);
+    /// ```
+    pub fn with_dormancy(mut self, dormancy: Dormancy) -> Result<Self, &'static str> {
+        self.core.set_dormancy(dormancy)?;
+        Ok(self)
+    }
+    /// Return the time step of the model, or `None` if it was not declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.core.get_time_step()
+    }
+    /// Return the generation time of the model (see [`DecomposedMatrix::generation_time`]), with
+    /// every value in `fertility_rows` treated as fertility, in the unit of the declared time
+    /// step (or in steps if there is none).
+    /// # Errors
+    /// Will return `Err<'static str>` if the matrix cannot be split into survival and fertility
+    /// with the given rows, or the generation time is undefined.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::{DeterministicPva, TimeStep};
+    ///let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    ///let steps = population.generation_time(&[0]).unwrap();
+    ///let monthly = population.with_time_step("3 months".parse::<TimeStep>().unwrap());
+    ///assert!((monthly.generation_time(&[0]).unwrap() - 3.0 * steps).abs() < 1e-9);
+    /// ```
+    pub fn generation_time(&self, fertility_rows: &[usize]) -> Result<f64, &'static str> {
+        let steps = DecomposedMatrix::from_fertility_rows(self.get_matrix(), fertility_rows)?
+            .generation_time()?;
+        Ok(self.get_time_step().map_or(steps, |x| x.duration(steps)))
+    }
+    /// Return a copy of the model that starts from a different initial Population Vector.
+    /// # Errors
+    /// Will return `Err<'static str>` if the length of the Population Vector does not match the
+    /// matrix.
+    pub fn with_initial_population(
+        &self,
+        initial_population: PopulationVector,
+    ) -> Result<DeterministicPva, &'static str> {
+        Ok(DeterministicPva {
+            core: self.core.with_initial_population(initial_population)?,
+        })
+    }
+    /// Return the shared model data: initial population, matrix, and density dependence.
+    pub fn get_core(&self) -> &MatrixModelCore {
+        &self.core
+    }
+    /// Return the Population Matrix of the model.
+    pub fn get_matrix(&self) -> &PopulationMatrix {
+        &self.core.get_matrices()[0]
+    }
 
     /// Return a Result enum containing a PvaDeterministicOutput type that holds the output of a determinisitc simulation
     /// given the number of simulation steps to perform (as a u32). This function performs the
@@ -197,8 +1221,8 @@ impl PvaDeterministicPopulation {
     /// lengths of Population Vector and Population Matrix do not match, although this situation should
     /// be prevented by checks when building a PVA Population instance.
     /// ```
-    ///use ecolysis_cmd::populations::population_level_simulation::PvaDeterministicPopulation;
-    ///let new_population = PvaDeterministicPopulation::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![12.0, 55.0, 172.0],
     ///vec![
     ///vec![0.0, 0.0, 0.9],
     ///vec![0.6, 0.9, 0.0],
@@ -207,15 +1231,87 @@ impl PvaDeterministicPopulation {
     ///let simulation_output = new_population.deterministic_projection(100);
     ///simulation_output.print_output();
     /// ```
-
     pub fn deterministic_projection(&self, iterations: u32) -> PvaDeterministicOutput {
-        let mut active_vector = self.initial_population.clone();
-        let mut result: Vec<PopulationVector> = Vec::new();
-        for _ in 1..=iterations {
-            active_vector = self.projection_matrix.project_vector(&active_vector).expect("This error should not be possible. Mismatched Vector and Matrix lengths, or non-square Matrix. Please file a bug report.");
-            result.push(active_vector.clone());
+        PvaDeterministicOutput::new(self.projection_iter().take(iterations as usize).collect())
+            .with_model_time_step(self.get_time_step())
+    }
+    /// Return an iterator that lazily performs the simulation, yielding the Population Vector of
+    /// each step (starting with the first iteration) only when it is requested. Nothing is stored
+    /// between steps, so results can be summarized or filtered without holding the full
+    /// trajectory in memory. The iterator never ends on its own; use `take` or a similar adapter
+    /// to limit the number of steps.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///vec![0.0, 0.0, 0.9],
+    ///vec![0.6, 0.9, 0.0],
+    ///vec![0.0, 0.95, 0.99]
+    ///]).unwrap();
+    ///let largest_total = new_population
+    ///    .projection_iter()
+    ///    .take(100)
+    ///    .map(|step| step.iter().sum::<f64>())
+    ///    .fold(0.0, f64::max);
+    ///assert!(largest_total > 239.0 && largest_total.is_finite());
+    /// ```
+    pub fn projection_iter(&self) -> ProjectionIter<'_> {
+        ProjectionIter {
+            core: &self.core,
+            active_vector: self.core.get_initial_population().clone(),
+            steps_taken: 0,
         }
-        return PvaDeterministicOutput::new(result);
+    }
+    /// Return only the Population Vector reached after the given number of simulation steps,
+    /// without storing the population at every step like `deterministic_projection` does. This is
+    /// useful for quick checks and for very long projections where memory is a concern.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///vec![0.0, 0.0, 0.9],
+    ///vec![0.6, 0.9, 0.0],
+    ///vec![0.0, 0.95, 0.99]
+    ///]).unwrap();
+    ///let final_population = new_population.project_n_steps(1000);
+    ///let trajectory = new_population.deterministic_projection(1000);
+    ///assert_eq!(&final_population, trajectory.iter().last().unwrap());
+    /// ```
+    pub fn project_n_steps(&self, iterations: u32) -> PopulationVector {
+        let mut active_vector = self.core.get_initial_population().clone();
+        for step in 0..iterations {
+            active_vector = self.core.project(0, step, &active_vector);
+        }
+        active_vector
+    }
+}
+
+/// A lazy iterator over the steps of a deterministic projection, created by
+/// [`DeterministicPva::projection_iter`]. It also implements [`Simulation`], so it can
+/// be stepped manually and used wherever a generic simulation is expected.
+pub struct ProjectionIter<'a> {
+    core: &'a MatrixModelCore,
+    active_vector: PopulationVector,
+    steps_taken: u32,
+}
+impl Simulation for ProjectionIter<'_> {
+    type State = PopulationVector;
+    fn step(&mut self) {
+        self.active_vector = self.core.project(0, self.steps_taken, &self.active_vector);
+        self.steps_taken += 1;
+    }
+    fn state(&self) -> &PopulationVector {
+        &self.active_vector
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+impl Iterator for ProjectionIter<'_> {
+    type Item = PopulationVector;
+    fn next(&mut self) -> Option<PopulationVector> {
+        self.step();
+        Some(self.active_vector.clone())
     }
 }
 
@@ -223,27 +1319,75 @@ impl PvaDeterministicPopulation {
 /// Deterministic Population struct.
 pub struct PvaDeterministicOutput {
     result: Vec<PopulationVector>,
+    stop_reason: StopReason,
+    interventions: Vec<u32>,
+    time_step: Option<TimeStep>,
 }
 impl PvaDeterministicOutput {
     // Create a new PvaDeterministicOutput struct from a vector of PopulationVectors
     // (Vec<PopulationVector>).
     pub fn new(simulation_output: Vec<PopulationVector>) -> PvaDeterministicOutput {
-        return PvaDeterministicOutput {
+        PvaDeterministicOutput {
             result: simulation_output,
-        };
+            stop_reason: StopReason::MaxSteps,
+            interventions: Vec::new(),
+            time_step: None,
+        }
+    }
+    /// Return the output with the reason the projection stopped recorded.
+    pub fn with_stop_reason(mut self, stop_reason: StopReason) -> Self {
+        self.stop_reason = stop_reason;
+        self
+    }
+    /// Return the reason the projection stopped. Projections without stopping rules always run
+    /// to their maximum number of steps.
+    pub fn get_stop_reason(&self) -> StopReason {
+        self.stop_reason
+    }
+    /// Return the output with the steps at which vital rates changed recorded.
+    pub fn with_interventions(mut self, interventions: Vec<u32>) -> Self {
+        self.interventions = interventions;
+        self
+    }
+    /// Return the steps at which vital rates changed during the projection (see
+    /// [`MatrixSchedule`]). The population at each of these steps was the last one projected by
+    /// the old vital rates.
+    pub fn get_interventions(&self) -> &Vec<u32> {
+        &self.interventions
+    }
+    /// Return the output with the time step of the model that produced it recorded.
+    pub fn with_time_step(mut self, time_step: TimeStep) -> Self {
+        self.time_step = Some(time_step);
+        self
+    }
+    /// Return the output with the time step of the model that produced it (if any) recorded.
+    pub(crate) fn with_model_time_step(mut self, time_step: Option<TimeStep>) -> Self {
+        self.time_step = time_step;
+        self
+    }
+    /// Return the time step of the model that produced the output, or `None` if it was not
+    /// declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Return the time elapsed at the end of each step, in the unit of the declared time step
+    /// (or in steps if there is none).
+    pub fn elapsed_times(&self) -> Vec<f64> {
+        let length = self.time_step.map_or(1.0, |x| x.get_length());
+        (1..=self.result.len()).map(|x| x as f64 * length).collect()
     }
     /// Print a CSV containing the output of each simulation step to the console.
     pub fn print_output(&self) {
         let mut string = String::new();
-        for (counti, i) in self.result.iter().enumerate() {
-            for (countj, j) in i.get_vector().iter().enumerate() {
+        for (counti, i) in self.iter().enumerate() {
+            for (countj, j) in i.iter().enumerate() {
                 string.push_str(&j.to_string());
-                if countj + 1 < i.get_vector().len() {
+                if countj + 1 < i.get_lifestage_count() as usize {
                     string.push_str(", ");
                 }
             }
             if counti + 1 < self.result.len() {
-                string.push_str("\n");
+                string.push('\n');
             }
         }
         println!("{}", string);
@@ -255,17 +1399,68 @@ impl PvaDeterministicOutput {
     pub fn return_typed_output(&self) -> &Vec<PopulationVector> {
         &self.result
     }
+    /// Return an iterator over the Population Vectors produced at each step of the simulation,
+    /// from the first iteration to the last.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 1.2], vec![0.5, 0.8]]).unwrap();
+    ///let output = new_population.deterministic_projection(5);
+    ///let totals: Vec<f64> = output.iter().map(|popvector| popvector.total()).collect();
+    ///assert_eq!(totals.len(), 5);
+    ///assert!(totals.windows(2).all(|x| x[0] < x[1]));
+    /// ```
+    pub fn iter(&self) -> std::slice::Iter<'_, PopulationVector> {
+        self.result.iter()
+    }
     /// Return a vecotr of vectors, containing floating point values (Vec<Vec<f64>>), representing
     /// all the data from each step of the simulation for a determinisic model. Each item in the outer vector is the output of an
     /// iteration of the simulation. The first item is the first iteration, the last itemn is the last iteration of the simulation. Each of the sub-vectors is a de-typed population vector, representing the demographics of a population.
-
     pub fn return_numerical_output(&self) -> Vec<Vec<f64>> {
         let mut num_vec: Vec<Vec<f64>> = Vec::new();
-        for i in &self.result {
+        for i in self {
             num_vec.push(i.get_vector().clone());
         }
         num_vec
     }
+    /// Return the total population at each step.
+    pub fn total_population(&self) -> Vec<f64> {
+        self.result.iter().map(PopulationVector::total).collect()
+    }
+    /// Return the proportion of the population in each lifestage at each step.
+    pub fn stage_proportions(&self) -> Vec<PopulationVector> {
+        self.result
+            .iter()
+            .map(PopulationVector::proportions)
+            .collect()
+    }
+    /// Return the realized growth rate `N_t+1 / N_t` of the total population between each pair
+    /// of consecutive steps, so there is one fewer value than there are steps.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+    ///let output = new_population.deterministic_projection(3);
+    ///assert_eq!(output.total_population(), vec![25.0, 20.0, 25.0]);
+    ///assert_eq!(output.growth_rates(), vec![0.8, 1.25]);
+    /// ```
+    pub fn growth_rates(&self) -> Vec<f64> {
+        growth_rates(&self.total_population())
+    }
+}
+impl IntoIterator for PvaDeterministicOutput {
+    type Item = PopulationVector;
+    type IntoIter = std::vec::IntoIter<PopulationVector>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.result.into_iter()
+    }
+}
+impl<'a> IntoIterator for &'a PvaDeterministicOutput {
+    type Item = &'a PopulationVector;
+    type IntoIter = std::slice::Iter<'a, PopulationVector>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.result.iter()
+    }
 }
 
 #[cfg(test)]
@@ -273,6 +1468,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::into_iter_on_ref)]
     fn matrix_multiplication() {
         let popvector = PopulationVector::new(vec![40.0, 20.0, 100.0]);
         let mut lifestage_recruit: Vec<Vec<f64>> = vec![vec![0.0, 0.0, 0.1]];
@@ -306,6 +1502,7 @@ mod tests {
         .is_err());
     }
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn pva_simple_matrix_projection_test() {
         let population_vec = PopulationVector::new(vec![40.0, 20.0, 100.0]);
         let matrix = PopulationMatrix::build(vec![
@@ -314,7 +1511,7 @@ mod tests {
             vec![0.0, 0.8, 0.95],
         ])
         .unwrap();
-        let population = PvaDeterministicPopulation::build(population_vec, matrix).unwrap();
+        let population = DeterministicPva::build(population_vec, matrix).unwrap();
         let result = population.deterministic_projection(8);
         result.print_output();
         let correct_result = vec![24.9, 50.8, 273.5];
@@ -329,4 +1526,470 @@ mod tests {
         }
         assert_eq!(correct_result, clean_output[clean_output.len() - 1])
     }
+    #[test]
+    fn vector_iteration() {
+        let popvector = PopulationVector::new(vec![40.0, 20.0, 100.0]);
+        assert_eq!(popvector.iter().sum::<f64>(), 160.0);
+        assert_eq!((&popvector).into_iter().count(), 3);
+        assert_eq!(
+            popvector.into_iter().collect::<Vec<f64>>(),
+            vec![40.0, 20.0, 100.0]
+        );
+    }
+    #[test]
+    fn output_iteration() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 1.0], vec![1.0, 0.0]],
+        )
+        .unwrap();
+        let result = population.deterministic_projection(4);
+        assert_eq!(result.iter().count(), 4);
+        let totals: Vec<f64> = result.into_iter().map(|x| x.iter().sum()).collect();
+        assert_eq!(totals, vec![20.0, 20.0, 20.0, 20.0]);
+    }
+    #[test]
+    fn matrix_operators() {
+        let popmatrix: PopulationMatrix =
+            PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+        let popvector = PopulationVector::new(vec![10.0, 20.0]);
+        assert_eq!(
+            (&popmatrix * &popvector).get_vector(),
+            popmatrix.project_vector(&popvector).unwrap().get_vector()
+        );
+        let two_steps = &popmatrix * &popmatrix;
+        let direct = &two_steps * &popvector;
+        let stepwise = &popmatrix * &(&popmatrix * &popvector);
+        for (x, y) in direct.iter().zip(&stepwise) {
+            assert!((x - y).abs() < 1e-9); // Floating point errors differ between the two routes.
+        }
+        let scaled = &popmatrix * 2.0;
+        assert_eq!(scaled.get_matrix(), vec![vec![0.0, 3.0], vec![1.0, 1.6]]);
+        let summed = &popmatrix + &popmatrix;
+        assert_eq!(summed.get_matrix(), scaled.get_matrix());
+    }
+    #[test]
+    fn vector_operators() {
+        let popvector = PopulationVector::new(vec![10.0, 20.0]);
+        assert_eq!((&popvector * 0.5).get_vector(), &vec![5.0, 10.0]);
+        assert_eq!((&popvector + &popvector).get_vector(), &vec![20.0, 40.0]);
+    }
+    #[test]
+    #[should_panic]
+    fn matrix_operator_mismatched_lengths() {
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+        let _ = &popmatrix * &PopulationVector::new(vec![1.0, 2.0, 3.0]);
+    }
+    #[test]
+    fn checked_operators() {
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+        let other = PopulationMatrix::identity(3);
+        assert!(popmatrix.checked_add(&other).is_err());
+        assert!(popmatrix.checked_mul(&other).is_err());
+        assert_eq!(
+            popmatrix.checked_mul(&popmatrix).unwrap(),
+            &popmatrix * &popmatrix
+        );
+    }
+    #[test]
+    fn matrix_power() {
+        let popmatrix = PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 0.1],
+            vec![0.6, 0.8, 0.0],
+            vec![0.0, 0.8, 0.95],
+        ])
+        .unwrap();
+        assert_eq!(
+            popmatrix.pow(0).get_matrix(),
+            PopulationMatrix::identity(3).get_matrix()
+        );
+        let repeated = &(&popmatrix * &popmatrix) * &popmatrix;
+        for (a, b) in popmatrix
+            .pow(3)
+            .get_matrix()
+            .iter()
+            .zip(repeated.get_matrix())
+        {
+            for (x, y) in a.iter().zip(b) {
+                assert!((x - y).abs() < 1e-12);
+            }
+        }
+    }
+    #[test]
+    fn n_step_projection_matches_full_projection() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![40.0, 20.0, 100.0],
+            vec![
+                vec![0.0, 0.0, 0.1],
+                vec![0.6, 0.8, 0.0],
+                vec![0.0, 0.8, 0.95],
+            ],
+        )
+        .unwrap();
+        let full = population.deterministic_projection(8);
+        assert_eq!(
+            population.project_n_steps(8).get_vector(),
+            full.iter().last().unwrap().get_vector()
+        );
+    }
+    #[test]
+    fn lazy_projection_matches_full_projection() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![40.0, 20.0, 100.0],
+            vec![
+                vec![0.0, 0.0, 0.1],
+                vec![0.6, 0.8, 0.0],
+                vec![0.0, 0.8, 0.95],
+            ],
+        )
+        .unwrap();
+        let lazy: Vec<Vec<f64>> = population
+            .projection_iter()
+            .take(8)
+            .map(|x| x.get_vector().clone())
+            .collect();
+        assert_eq!(
+            lazy,
+            population
+                .deterministic_projection(8)
+                .return_numerical_output()
+        );
+    }
+    #[test]
+    fn derived_series() {
+        let output = PvaDeterministicOutput::new(vec![
+            PopulationVector::new(vec![5.0, 5.0]),
+            PopulationVector::new(vec![0.0, 0.0]),
+            PopulationVector::new(vec![3.0, 1.0]),
+        ]);
+        assert_eq!(output.total_population(), vec![10.0, 0.0, 4.0]);
+        assert_eq!(output.stage_proportions()[1].get_vector(), &vec![0.0, 0.0]);
+        assert_eq!(
+            output.stage_proportions()[2].get_vector(),
+            &vec![0.75, 0.25]
+        );
+        let rates = output.growth_rates();
+        assert_eq!(rates[0], 0.0);
+        assert!(rates[1].is_nan());
+        let stochastic = PvaStochasticOutput::new(vec![output.return_typed_output().clone()]);
+        assert_eq!(stochastic.total_population()[0], vec![10.0, 0.0, 4.0]);
+        assert_eq!(stochastic.growth_rates()[0].len(), 2);
+    }
+    #[test]
+    fn fixed_size_projection_matches_general_case() {
+        for size in 1..=10_usize {
+            let rows: Vec<Vec<f64>> = (0..size)
+                .map(|row| {
+                    (0..size)
+                        .map(|column| ((row * 7 + column * 3) % 11) as f64 / 10.0)
+                        .collect()
+                })
+                .collect();
+            let values: Vec<f64> = (0..size).map(|x| x as f64 * 1.5 + 2.0).collect();
+            let expected: Vec<f64> = rows
+                .iter()
+                .map(|row| row.iter().zip(&values).map(|(x, y)| x * y).sum())
+                .collect();
+            let popmatrix = PopulationMatrix::build(rows).unwrap();
+            let popvector = PopulationVector::new(values);
+            assert_eq!(
+                popmatrix.project_vector(&popvector).unwrap().get_vector(),
+                &expected
+            );
+        }
+    }
+    #[test]
+    fn single_precision_projection_matches_double() {
+        let popmatrix = PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 0.1],
+            vec![0.6, 0.8, 0.0],
+            vec![0.0, 0.8, 0.95],
+        ])
+        .unwrap();
+        let popvector = PopulationVector::new(vec![40.0, 20.0, 100.0]);
+        let mut double = popvector.clone();
+        let mut single: PopulationVector<f32> = popvector.cast();
+        let compact: PopulationMatrix<f32> = popmatrix.cast();
+        for _ in 0..20 {
+            double = &popmatrix * &double;
+            single = &compact * &single;
+        }
+        for (x, y) in double.iter().zip(single.cast::<f64>().iter()) {
+            assert!((x - y).abs() / x < 1e-5);
+        }
+    }
+    #[test]
+    fn matrix_indexing() {
+        let popmatrix = PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 0.1],
+            vec![0.6, 0.8, 0.0],
+            vec![0.0, 0.8, 0.95],
+        ])
+        .unwrap();
+        assert_eq!(popmatrix[(0, 2)], 0.1);
+        assert_eq!(popmatrix[(2, 1)], 0.8);
+        assert_eq!(popmatrix.row(1), Some(&[0.6, 0.8, 0.0][..]));
+        assert_eq!(popmatrix.column(2), Some(vec![0.1, 0.0, 0.95]));
+        assert_eq!(popmatrix.column(3), None);
+        assert_eq!(popmatrix.diagonal(), vec![0.0, 0.8, 0.95]);
+        let transposed = popmatrix.transpose();
+        for row in 0..3 {
+            assert_eq!(transposed.row(row).unwrap(), popmatrix.column(row).unwrap());
+        }
+        assert_eq!(
+            popmatrix.cast::<f32>().transpose().diagonal(),
+            vec![0.0, 0.8, 0.95]
+        );
+    }
+    #[test]
+    fn vector_arithmetic() {
+        let popvector = PopulationVector::new(vec![10.0, 30.0]);
+        assert_eq!(popvector.scale(0.5).get_vector(), &vec![5.0, 15.0]);
+        assert_eq!(popvector.normalize_to(8.0).get_vector(), &vec![2.0, 6.0]);
+        assert_eq!(
+            PopulationVector::new(vec![0.0, 0.0])
+                .normalize_to(8.0)
+                .get_vector(),
+            &vec![0.0, 0.0]
+        );
+        assert_eq!(
+            popvector.checked_add(&popvector).unwrap().get_vector(),
+            &vec![20.0, 60.0]
+        );
+        assert!(popvector
+            .checked_add(&PopulationVector::new(vec![1.0]))
+            .is_err());
+    }
+    #[test]
+    fn vector_rounding() {
+        let popvector = PopulationVector::new(vec![0.5, 1.6, 2.2, 0.7]);
+        assert_eq!(
+            popvector.round(RoundingRule::Nearest).get_vector(),
+            &vec![1.0, 2.0, 2.0, 1.0]
+        );
+        assert_eq!(
+            popvector.round(RoundingRule::Down).get_vector(),
+            &vec![0.0, 1.0, 2.0, 0.0]
+        );
+        assert_eq!(
+            popvector.round(RoundingRule::Up).get_vector(),
+            &vec![1.0, 2.0, 3.0, 1.0]
+        );
+        let largest = popvector.round(RoundingRule::LargestRemainder);
+        assert_eq!(largest.get_vector(), &vec![0.0, 2.0, 2.0, 1.0]);
+        assert_eq!(largest.total(), popvector.total().round());
+        let stochastic = popvector.round(RoundingRule::Stochastic(7));
+        assert_eq!(
+            stochastic.get_vector(),
+            popvector.round(RoundingRule::Stochastic(7)).get_vector()
+        );
+        for (x, y) in stochastic.iter().zip(popvector.iter()) {
+            assert!(*x == y.floor() || *x == y.ceil());
+        }
+        let mean: f64 = (0..2000)
+            .map(|seed| {
+                PopulationVector::new(vec![0.3])
+                    .round(RoundingRule::Stochastic(seed))
+                    .total()
+            })
+            .sum::<f64>()
+            / 2000.0;
+        assert!((mean - 0.3).abs() < 0.05);
+    }
+    #[test]
+    fn conversions_and_setters() {
+        let mut popvector: PopulationVector = vec![1.0, 2.0].into();
+        assert!(popvector.set_value_at_index(0, f64::NAN).is_err());
+        popvector.set_value_at_index(0, 3.0).unwrap();
+        assert_eq!(popvector.into_inner(), vec![3.0, 2.0]);
+        let mut popmatrix =
+            PopulationMatrix::try_from(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+        assert!(PopulationMatrix::try_from(vec![vec![0.0, 2.0]]).is_err());
+        assert!(popmatrix.set_element(2, 0, 1.0).is_err());
+        popmatrix.set_element(0, 0, 0.1).unwrap();
+        let projected = &popmatrix * &PopulationVector::new(vec![10.0, 10.0]);
+        assert_eq!(projected.get_vector(), &vec![21.0, 10.0]);
+        assert_eq!(popmatrix.into_inner(), vec![vec![0.1, 2.0], vec![0.5, 0.5]]);
+        let size = SPARSE_MIN_LIFESTAGES;
+        let mut large = PopulationMatrix::identity(size as u8);
+        large.set_element(0, size - 1, 2.0).unwrap();
+        let projected = large
+            .project_vector(&PopulationVector::new(vec![1.0; size]))
+            .unwrap();
+        assert_eq!(projected.get_vector()[0], 3.0);
+        let mut dense = PopulationMatrix::identity(9);
+        dense.set_element(8, 0, 0.5).unwrap();
+        let projected = &dense * &PopulationVector::new(vec![2.0; 9]);
+        assert_eq!(projected.get_vector()[8], 3.0);
+    }
+    #[test]
+    fn batch_projection_matches_single_projection() {
+        let dense: Vec<Vec<f64>> = (0..9)
+            .map(|row| (0..9).map(|column| (row + column) as f64 / 20.0).collect())
+            .collect();
+        let size = SPARSE_MIN_LIFESTAGES;
+        let mut sparse = PopulationMatrix::identity(size as u8);
+        sparse.set_element(0, size - 1, 2.0).unwrap();
+        for popmatrix in [PopulationMatrix::build(dense).unwrap(), sparse] {
+            let stages = popmatrix.get_lifestage_count() as usize;
+            let vectors: Vec<PopulationVector> = (0..PROJECTION_BLOCK * 2 + 3)
+                .map(|i| PopulationVector::new((0..stages).map(|x| (i * x) as f64).collect()))
+                .collect();
+            let projected = popmatrix.project_vectors(&vectors).unwrap();
+            assert_eq!(projected.len(), vectors.len());
+            for (vector, batch) in vectors.iter().zip(&projected) {
+                assert_eq!(&popmatrix.project_vector(vector).unwrap(), batch);
+            }
+            assert!(popmatrix.project_vectors(&[]).unwrap().is_empty());
+            assert!(popmatrix
+                .project_vectors(&[PopulationVector::new(vec![1.0])])
+                .is_err());
+        }
+    }
+    #[test]
+    fn compensated_summation_projects_every_size() {
+        for size in [2, 9, SPARSE_MIN_LIFESTAGES] {
+            let mut popmatrix = PopulationMatrix::identity(size as u8);
+            popmatrix.set_element(0, size - 1, 2.0).unwrap();
+            let compensated = popmatrix.clone().with_summation(Summation::Compensated);
+            let popvector = PopulationVector::new(vec![0.1; size]);
+            assert_eq!(
+                compensated.project_vector(&popvector),
+                popmatrix.project_vector(&popvector)
+            );
+            assert_eq!(
+                compensated
+                    .project_vectors(std::slice::from_ref(&popvector))
+                    .unwrap()[0],
+                &popmatrix * &popvector
+            );
+            assert_ne!(compensated, popmatrix);
+        }
+    }
+    #[test]
+    fn compensated_summation_limits_drift() {
+        // Project an f32 matrix with 200 lifestages and no zeros for 500 steps, and compare the
+        // error of each summation against the same projection in f64.
+        let size = 200;
+        let value = |row: usize, column: usize| ((row * 31 + column * 17) % 97 + 1) as f32;
+        let rows: Vec<Vec<f32>> = (0..size)
+            .map(|row| (0..size).map(|column| value(row, column)).collect())
+            .collect();
+        let plain = PopulationMatrix::build(rows.clone()).unwrap();
+        let compensated = plain.clone().with_summation(Summation::Compensated);
+        let exact = PopulationMatrix::build(
+            rows.iter()
+                .map(|row| row.iter().map(|x| f64::from(*x)).collect())
+                .collect(),
+        )
+        .unwrap();
+        let lambda = exact.lambda() as f32;
+        let mut vectors = (
+            PopulationVector::new(vec![1.0_f32; size]),
+            PopulationVector::new(vec![1.0_f32; size]),
+            PopulationVector::new(vec![1.0_f64; size]),
+        );
+        for _ in 0..500 {
+            // Rescale by lambda so the values stay in range.
+            let rescale = |x: PopulationVector<f32>| {
+                PopulationVector::new(x.iter().map(|x| x / lambda).collect())
+            };
+            vectors.0 = rescale(plain.project_vector(&vectors.0).unwrap());
+            vectors.1 = rescale(compensated.project_vector(&vectors.1).unwrap());
+            vectors.2 = PopulationVector::new(
+                exact
+                    .project_vector(&vectors.2)
+                    .unwrap()
+                    .iter()
+                    .map(|x| x / f64::from(lambda))
+                    .collect(),
+            );
+        }
+        let error = |x: &PopulationVector<f32>| {
+            x.iter()
+                .zip(vectors.2.iter())
+                .map(|(a, b)| (f64::from(*a) - b).abs() / b)
+                .fold(0.0, f64::max)
+        };
+        assert!(error(&vectors.1) * 4.0 < error(&vectors.0));
+    }
+    #[test]
+    fn sparse_matrices_use_compensated_summation() {
+        let size = SPARSE_MIN_LIFESTAGES * 2;
+        let mut popmatrix = PopulationMatrix::identity(size as u8);
+        popmatrix.set_element(0, 1, 1e16).unwrap();
+        popmatrix.set_element(0, 2, 1e16).unwrap();
+        let mut values = vec![1.0; size];
+        values[2] = -1.0;
+        let popvector = PopulationVector::new(values);
+        // Plain summation loses the first 1 when 1e16 is added, before 1e16 is subtracted again.
+        assert_eq!(
+            popmatrix.project_vector(&popvector).unwrap().get_vector()[0],
+            0.0
+        );
+        let compensated = popmatrix.with_summation(Summation::Compensated);
+        assert_eq!(
+            compensated.project_vector(&popvector).unwrap().get_vector()[0],
+            1.0
+        );
+    }
+    #[test]
+    fn derived_matrices_keep_summation() {
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]])
+            .unwrap()
+            .with_summation(Summation::Compensated);
+        let plain = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap();
+        let cast: PopulationMatrix<f32> = popmatrix.cast();
+        for derived in [
+            popmatrix.transpose(),
+            popmatrix.pow(3),
+            &popmatrix * &plain,
+            &popmatrix + &plain,
+            &popmatrix * 0.9,
+            popmatrix.elasticities(),
+        ] {
+            assert_eq!(derived.get_summation(), Summation::Compensated);
+        }
+        assert_eq!(cast.get_summation(), Summation::Compensated);
+        assert_eq!((&plain * &popmatrix).get_summation(), Summation::Plain);
+    }
+    #[test]
+    fn element_perturbation() {
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+        let changed = popmatrix.with_element(0, 1, 3.0).unwrap();
+        assert_eq!(changed.get_matrix(), vec![vec![0.0, 3.0], vec![0.5, 0.8]]);
+        assert_eq!(popmatrix[(0, 1)], 2.0);
+        assert_eq!(popmatrix.scaled_element(0, 1, 1.5).unwrap()[(0, 1)], 3.0);
+        assert!(popmatrix.with_element(0, 2, 1.0).is_err());
+        assert!(popmatrix.scaled_element(2, 0, 1.0).is_err());
+        assert!(popmatrix.scaled_element(0, 1, -1.0).is_err());
+    }
+    #[test]
+    fn equality_and_hashing() {
+        use std::collections::hash_map::DefaultHasher;
+        let hash = |x: &PopulationVector| {
+            let mut hasher = DefaultHasher::new();
+            x.hash(&mut hasher);
+            hasher.finish()
+        };
+        let popvector = PopulationVector::new(vec![0.0, 1.0]);
+        assert_eq!(popvector, PopulationVector::new(vec![-0.0, 1.0]));
+        assert_eq!(
+            hash(&popvector),
+            hash(&PopulationVector::new(vec![-0.0, 1.0]))
+        );
+        assert_ne!(
+            hash(&popvector),
+            hash(&PopulationVector::new(vec![1.0, 0.0]))
+        );
+        assert_ne!(popvector, PopulationVector::new(vec![0.0, 1.0, 0.0]));
+        assert!(!popvector.approx_eq(&PopulationVector::new(vec![0.0]), 1.0));
+        assert!(popvector.approx_eq(&PopulationVector::new(vec![0.05, 1.0]), 0.1));
+        assert!(!popvector.approx_eq(&PopulationVector::new(vec![0.2, 1.0]), 0.1));
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+        assert_eq!(popmatrix, popmatrix.clone());
+        assert_ne!(popmatrix, popmatrix.scaled_element(1, 1, 1.01).unwrap());
+        assert!(popmatrix.approx_eq(&popmatrix.scaled_element(1, 1, 1.01).unwrap(), 0.01));
+        assert!(!popmatrix.approx_eq(&PopulationMatrix::identity(3), 10.0));
+    }
 }
@@ -0,0 +1,135 @@
+//! This module detects when a deterministic projection has converged to the stable stage distribution, reporting the time to convergence and optionally stopping the projection there.
+use super::{DeterministicPva, PopulationVector, PvaDeterministicOutput, StopReason};
+
+/// Return Keyfitz's delta, the distance between the stage structures of two populations:
+/// `0.5 * sum(|p_i - q_i|)` where `p` and `q` are the proportions in each lifestage. It is zero
+/// when the structures are identical and one when they share no lifestages.
+/// ```
+/// use ecolysis_cmd::PopulationVector;
+/// use ecolysis_cmd::populations::population_level_simulation::keyfitz_delta;
+/// let a = PopulationVector::new(vec![10.0, 30.0]);
+/// let b = PopulationVector::new(vec![50.0, 50.0]);
+/// assert!((keyfitz_delta(&a, &b) - 0.25).abs() < 1e-12);
+/// ```
+pub fn keyfitz_delta(first: &PopulationVector, second: &PopulationVector) -> f64 {
+    first
+        .proportions()
+        .iter()
+        .zip(second.proportions().iter())
+        .map(|(x, y)| (x - y).abs())
+        .sum::<f64>()
+        / 2.0
+}
+
+impl DeterministicPva {
+    /// Return the first step at which the stage structure of the projection is within
+    /// `tolerance` of the stable stage distribution (measured by [`keyfitz_delta`]), or `None`
+    /// if that does not happen within `max_steps` steps. Step 0 is the initial population.
+    /// Populations projected by imprimitive matrices (for example, ones where adults breed only
+    /// every other year) oscillate and may never converge.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    ///let new_population = DeterministicPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///vec![0.0, 0.0, 0.9],
+    ///vec![0.6, 0.9, 0.0],
+    ///vec![0.0, 0.95, 0.99]
+    ///]).unwrap();
+    ///assert_eq!(new_population.time_to_convergence(0.01, 1_000), Some(4));
+    /// ```
+    pub fn time_to_convergence(&self, tolerance: f64, max_steps: u32) -> Option<u32> {
+        let stable = self.get_matrix().stable_stage_distribution();
+        let initial = self.get_core().get_initial_population();
+        if keyfitz_delta(initial, &stable) <= tolerance {
+            return Some(0);
+        }
+        self.projection_iter()
+            .take(max_steps as usize)
+            .position(|x| keyfitz_delta(&x, &stable) <= tolerance)
+            .map(|x| x as u32 + 1)
+    }
+    /// Project the population until its stage structure is within `tolerance` of the stable
+    /// stage distribution, or for `max_steps` steps if it does not converge sooner. Return the
+    /// output (ending at the step of convergence, with a stop reason of
+    /// [`StopReason::Converged`]) and the step of convergence, if it converged.
+    pub fn projection_until_converged(
+        &self,
+        tolerance: f64,
+        max_steps: u32,
+    ) -> (PvaDeterministicOutput, Option<u32>) {
+        let stable = self.get_matrix().stable_stage_distribution();
+        let mut result = Vec::new();
+        if keyfitz_delta(self.get_core().get_initial_population(), &stable) <= tolerance {
+            return (
+                PvaDeterministicOutput::new(result)
+                    .with_stop_reason(StopReason::Converged)
+                    .with_model_time_step(self.get_time_step()),
+                Some(0),
+            );
+        }
+        for popvector in self.projection_iter().take(max_steps as usize) {
+            let converged = keyfitz_delta(&popvector, &stable) <= tolerance;
+            result.push(popvector);
+            if converged {
+                let step = result.len() as u32;
+                return (
+                    PvaDeterministicOutput::new(result)
+                        .with_stop_reason(StopReason::Converged)
+                        .with_model_time_step(self.get_time_step()),
+                    Some(step),
+                );
+            }
+        }
+        (
+            PvaDeterministicOutput::new(result).with_model_time_step(self.get_time_step()),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn population(initial: Vec<f64>) -> DeterministicPva {
+        DeterministicPva::build_from_vectors(
+            initial,
+            vec![
+                vec![0.0, 0.0, 0.1],
+                vec![0.6, 0.8, 0.0],
+                vec![0.0, 0.8, 0.95],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn stable_population_has_converged_already() {
+        let stable = population(vec![1.0, 1.0, 1.0])
+            .get_matrix()
+            .stable_stage_distribution();
+        let converged = population(stable.get_vector().clone());
+        assert_eq!(converged.time_to_convergence(1e-6, 10), Some(0));
+    }
+    #[test]
+    fn early_stop_matches_time_to_convergence() {
+        let model = population(vec![100.0, 0.0, 0.0]);
+        let step = model.time_to_convergence(0.01, 1_000).unwrap();
+        assert!(step > 0);
+        let (output, converged) = model.projection_until_converged(0.01, 1_000);
+        assert_eq!(converged, Some(step));
+        assert_eq!(output.iter().count(), step as usize);
+        assert_eq!(model.time_to_convergence(0.01, step - 1), None);
+    }
+    #[test]
+    fn imprimitive_matrix_never_converges() {
+        let model = DeterministicPva::build_from_vectors(
+            vec![10.0, 0.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.0]],
+        )
+        .unwrap();
+        let (output, converged) = model.projection_until_converged(0.01, 50);
+        assert_eq!(converged, None);
+        assert_eq!(output.iter().count(), 50);
+    }
+}
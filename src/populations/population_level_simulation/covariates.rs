@@ -0,0 +1,326 @@
+//! This module contains covariate-driven matrix models, in which each vital rate changes with environmental covariates such as temperature or rainfall, and covariate series describing how those covariates change over time.
+use super::{PopulationMatrix, PopulationVector, PvaDeterministicOutput};
+use crate::imports::{read_text, CsvFormat};
+use std::{error::Error, io::Read};
+
+/// This struct holds a Population Matrix whose elements depend linearly on named covariates.
+/// For covariate values `x_1 … x_k`, the matrix is `base + x_1 * effect_1 + … + x_k * effect_k`,
+/// with any element that would be negative set to zero. Covariates are usually centred so that
+/// a value of zero means current conditions, making the base matrix the current matrix.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// use ecolysis_cmd::populations::population_level_simulation::CovariateMatrix;
+/// let model = CovariateMatrix::build(
+///     PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap(),
+///     vec![(String::from("temperature"),
+///         PopulationMatrix::build(vec![vec![0.0, -0.2], vec![0.0, -0.05]]).unwrap())],
+/// ).unwrap();
+/// let warmer = model.matrix_for(&[2.0]).unwrap();
+/// assert!((warmer[(0, 1)] - 1.6).abs() < 1e-12);
+/// ```
+#[derive(Clone)]
+pub struct CovariateMatrix {
+    base: PopulationMatrix,
+    effects: Vec<(String, PopulationMatrix)>,
+}
+impl CovariateMatrix {
+    /// Build a Covariate Matrix from a base matrix and one effect matrix per named covariate.
+    /// # Errors
+    /// Will return `Err<'static str>` if an effect matrix has a different number of lifestages
+    /// than the base matrix, or two covariates have the same name.
+    pub fn build(
+        base: PopulationMatrix,
+        effects: Vec<(String, PopulationMatrix)>,
+    ) -> Result<CovariateMatrix, &'static str> {
+        if effects
+            .iter()
+            .any(|(_, effect)| effect.get_lifestage_count() != base.get_lifestage_count())
+        {
+            return Err("Covariate effect matrices must match the size of the base matrix.");
+        }
+        for (index, (name, _)) in effects.iter().enumerate() {
+            if effects[..index].iter().any(|(other, _)| other == name) {
+                return Err("Each covariate can only have one effect matrix.");
+            }
+        }
+        Ok(CovariateMatrix { base, effects })
+    }
+    /// Return the base matrix, used when every covariate is zero.
+    pub fn get_base(&self) -> &PopulationMatrix {
+        &self.base
+    }
+    /// Return the names of the covariates, in the order their values are given.
+    pub fn covariate_names(&self) -> Vec<&str> {
+        self.effects.iter().map(|(name, _)| name.as_str()).collect()
+    }
+    /// Return the matrix for the given covariate values, in the order of
+    /// [`CovariateMatrix::covariate_names`].
+    /// # Errors
+    /// Will return `Err<'static str>` if the number of values does not match the number of
+    /// covariates.
+    pub fn matrix_for(&self, values: &[f64]) -> Result<PopulationMatrix, &'static str> {
+        if values.len() != self.effects.len() {
+            return Err("One value is needed for each covariate.");
+        }
+        let mut matrix = self.base.clone();
+        for ((_, effect), value) in self.effects.iter().zip(values) {
+            matrix = &matrix + &(effect * *value);
+        }
+        PopulationMatrix::build(
+            matrix
+                .get_matrix()
+                .iter()
+                .map(|row| row.iter().map(|x| x.max(0.0)).collect())
+                .collect(),
+        )
+    }
+    /// Project a population through a covariate series, using the matrix for each step's
+    /// covariate values. The projection lasts as many steps as the series has.
+    /// # Errors
+    /// Will return `Err<String>` if the series is missing one of the model's covariates or the
+    /// population does not match the matrix.
+    pub fn project(
+        &self,
+        initial_population: &PopulationVector,
+        series: &CovariateSeries,
+    ) -> Result<PvaDeterministicOutput, String> {
+        if initial_population.get_lifestage_count() != self.base.get_lifestage_count() {
+            return Err(String::from(
+                "Population vector size does not match matrices.",
+            ));
+        }
+        let steps = series.values_for(&self.covariate_names())?;
+        let mut active_vector = initial_population.clone();
+        let mut result = Vec::with_capacity(steps.len());
+        for values in steps {
+            let matrix = self.matrix_for(&values)?;
+            active_vector = &matrix * &active_vector;
+            result.push(active_vector.clone());
+        }
+        Ok(PvaDeterministicOutput::new(result))
+    }
+}
+
+/// This struct holds the values of named covariates at each step of a projection, such as one
+/// climate future.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CovariateSeries {
+    names: Vec<String>,
+    values: Vec<Vec<f64>>,
+}
+impl CovariateSeries {
+    /// Build a Covariate Series from covariate names and one row of values per step.
+    /// # Errors
+    /// Will return `Err<'static str>` if any row does not have one value per covariate.
+    pub fn build(
+        names: Vec<String>,
+        values: Vec<Vec<f64>>,
+    ) -> Result<CovariateSeries, &'static str> {
+        if values.iter().any(|row| row.len() != names.len()) {
+            return Err("Each step must have one value for each covariate.");
+        }
+        Ok(CovariateSeries { names, values })
+    }
+    /// Read a Covariate Series from a CSV file with a header row of covariate names and one row
+    /// of values per step. A column named `step` or `year`, if present, is ignored. The CSV
+    /// format is detected from the file (see [`CsvFormat::detect`]).
+    /// # Errors
+    /// Returns an error if the file cannot be read or a value is not a number.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::CovariateSeries;
+    /// let file = "year,temperature,rainfall\n2030,0.5,-10\n2031,0.6,-12\n";
+    /// let series = CovariateSeries::read_csv(file.as_bytes()).unwrap();
+    /// assert_eq!(series.get_names(), &vec!["temperature", "rainfall"]);
+    /// assert_eq!(series.len(), 2);
+    /// let file = "year;temperature\n2030;0,5\n2031;0,6\n";
+    /// let series = CovariateSeries::read_csv(file.as_bytes()).unwrap();
+    /// assert_eq!(series.get_values(), &vec![vec![0.5], vec![0.6]]);
+    /// ```
+    pub fn read_csv<R: Read>(reader: R) -> Result<CovariateSeries, Box<dyn Error>> {
+        let (contents, format) = CsvFormat::read_detected(reader)?;
+        CovariateSeries::read_csv_with_format(contents.as_slice(), &format)
+    }
+    /// Read a Covariate Series, as [`CovariateSeries::read_csv`] does, from a CSV file written in
+    /// a given format.
+    /// # Errors
+    /// Returns an error if the file cannot be read or a value is not a number in `format`.
+    pub fn read_csv_with_format<R: Read>(
+        reader: R,
+        format: &CsvFormat,
+    ) -> Result<CovariateSeries, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rdr = format.reader_builder().from_reader(contents.as_bytes());
+        let header: Vec<String> = rdr.headers()?.iter().map(String::from).collect();
+        let kept: Vec<usize> = (0..header.len())
+            .filter(|x| !matches!(header[*x].to_lowercase().as_str(), "step" | "year"))
+            .collect();
+        let mut values = Vec::new();
+        for (row_number, record) in rdr.records().enumerate() {
+            let record = record?;
+            let mut row = Vec::with_capacity(kept.len());
+            for column in &kept {
+                let cell = record.get(*column).unwrap_or_default();
+                row.push(format.parse_number(cell).ok_or_else(|| {
+                    format!(
+                        "Row {}, column \"{}\": \"{}\" is not a number.",
+                        row_number + 1,
+                        header[*column],
+                        cell
+                    )
+                })?);
+            }
+            values.push(row);
+        }
+        let names = kept.iter().map(|x| header[*x].clone()).collect();
+        Ok(CovariateSeries::build(names, values)?)
+    }
+    /// Return the covariate names.
+    pub fn get_names(&self) -> &Vec<String> {
+        &self.names
+    }
+    /// Return the covariate values of each step.
+    pub fn get_values(&self) -> &Vec<Vec<f64>> {
+        &self.values
+    }
+    /// Return the number of steps in the series.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    /// Return true if the series has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    /// Return only the first `steps` steps of the series.
+    pub fn truncate(mut self, steps: usize) -> CovariateSeries {
+        self.values.truncate(steps);
+        self
+    }
+    /// Return the values of each step rearranged to follow the order of `names`.
+    /// # Errors
+    /// Returns `Err<String>` naming the first covariate missing from the series.
+    pub fn values_for(&self, names: &[&str]) -> Result<Vec<Vec<f64>>, String> {
+        let mut columns = Vec::with_capacity(names.len());
+        for name in names {
+            columns.push(
+                self.names
+                    .iter()
+                    .position(|x| x == name)
+                    .ok_or_else(|| format!("The covariate series has no \"{}\" column.", name))?,
+            );
+        }
+        Ok(self
+            .values
+            .iter()
+            .map(|row| columns.iter().map(|x| row[*x]).collect())
+            .collect())
+    }
+}
+
+/// This struct holds the values of one covariate at each step, taken from a [`CovariateSeries`]
+/// for a part of a model that responds to a single covariate, such as a germination trigger or a
+/// moving trait optimum. Steps beyond the end of the series use its last value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CovariateTrack {
+    values: Vec<f64>,
+}
+impl CovariateTrack {
+    /// Take the values of the named covariate from a series.
+    /// # Errors
+    /// Will return `Err<'static str>` if the series has no column for the covariate or no steps.
+    pub fn from_series(
+        series: &CovariateSeries,
+        covariate: &str,
+    ) -> Result<CovariateTrack, &'static str> {
+        let values = series
+            .values_for(&[covariate])
+            .map_err(|_| "The covariate series has no column for the covariate.")?;
+        if values.is_empty() {
+            return Err("The covariate series has no steps.");
+        }
+        Ok(CovariateTrack {
+            values: values.into_iter().map(|x| x[0]).collect(),
+        })
+    }
+    /// Create a track with the same value at every step.
+    pub fn constant(value: f64) -> CovariateTrack {
+        CovariateTrack {
+            values: vec![value],
+        }
+    }
+    /// Return the value during the step after `steps_taken` steps, or `None` if the track is
+    /// empty.
+    pub fn value(&self, steps_taken: u32) -> Option<f64> {
+        let last = self.values.last()?;
+        Some(*self.values.get(steps_taken as usize).unwrap_or(last))
+    }
+    /// Return the value of each step of the track.
+    pub fn get_values(&self) -> &Vec<f64> {
+        &self.values
+    }
+    /// Return true if the track has no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> CovariateMatrix {
+        CovariateMatrix::build(
+            PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap(),
+            vec![
+                (
+                    String::from("temperature"),
+                    PopulationMatrix::build(vec![vec![0.0, -1.0], vec![0.0, 0.0]]).unwrap(),
+                ),
+                (
+                    String::from("rainfall"),
+                    PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.1, 0.0]]).unwrap(),
+                ),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn negative_rates_are_clamped() {
+        let matrix = model().matrix_for(&[5.0, 0.0]).unwrap();
+        assert_eq!(matrix[(0, 1)], 0.0);
+        assert!(model().matrix_for(&[1.0]).is_err());
+    }
+    #[test]
+    fn projection_follows_series_columns_by_name() {
+        let series = CovariateSeries::build(
+            vec![String::from("rainfall"), String::from("temperature")],
+            vec![vec![0.0, 0.0], vec![1.0, 0.5]],
+        )
+        .unwrap();
+        let initial = PopulationVector::new(vec![10.0, 10.0]);
+        let output = model().project(&initial, &series).unwrap();
+        let first = &model().get_base().clone() * &initial;
+        let second = &model().matrix_for(&[0.5, 1.0]).unwrap() * &first;
+        assert_eq!(
+            output.return_numerical_output(),
+            vec![first.get_vector().clone(), second.get_vector().clone()]
+        );
+        let missing = CovariateSeries::build(vec![String::from("rainfall")], vec![]).unwrap();
+        assert!(model().project(&initial, &missing).is_err());
+    }
+    #[test]
+    fn tracks_repeat_their_last_value() {
+        let series = CovariateSeries::build(
+            vec![String::from("rainfall"), String::from("temperature")],
+            vec![vec![1.0, 10.0], vec![2.0, 12.0]],
+        )
+        .unwrap();
+        let track = CovariateTrack::from_series(&series, "temperature").unwrap();
+        assert_eq!(track.get_values(), &vec![10.0, 12.0]);
+        assert_eq!(track.value(0), Some(10.0));
+        assert_eq!(track.value(5), Some(12.0));
+        assert_eq!(CovariateTrack::default().value(0), None);
+        assert!(CovariateTrack::from_series(&series, "wind").is_err());
+        assert!(CovariateTrack::from_series(&series.truncate(0), "rainfall").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! This module contains the Decomposed Matrix, which stores the survival/growth and fertility parts of a projection matrix separately. Many analyses (net reproductive rate, generation time, demographic stochasticity) need to know which transitions are survival and which are reproduction.
+use super::linear_algebra::{dominant_eigen, identity, invert, multiply};
+use super::{PopulationMatrix, PopulationVector};
+use rand::Rng;
+use rand_distr::{Binomial, Distribution, Poisson};
+
+/// The tolerance used when checking that the survival of each lifestage is no greater than one.
+const SURVIVAL_TOLERANCE: f64 = 1e-9;
+
+/// This struct represents a projection matrix A split into a survival/growth submatrix U and a
+/// fertility submatrix F, so that `A = U + F`.
+///
+/// Each column of U holds the probabilities that an individual in that lifestage survives and
+/// moves into each lifestage, so the values in a column of U must add up to no more than one.
+/// F holds the average number of offspring in each lifestage produced per individual in each
+/// lifestage.
+#[derive(Clone)]
+pub struct DecomposedMatrix {
+    survival: PopulationMatrix,
+    fertility: PopulationMatrix,
+}
+impl DecomposedMatrix {
+    /// Build a Decomposed Matrix from a survival/growth matrix U and a fertility matrix F.
+    /// # Errors
+    /// Will return `Err<'static str>` if the matrices have different numbers of lifestages, if
+    /// any value is negative, or if any column of U adds up to more than one.
+    pub fn build(
+        survival: PopulationMatrix,
+        fertility: PopulationMatrix,
+    ) -> Result<DecomposedMatrix, &'static str> {
+        if survival.get_lifestage_count() != fertility.get_lifestage_count() {
+            return Err("Survival and fertility matrices must have the same number of lifestages.");
+        }
+        if survival
+            .rows()
+            .chain(fertility.rows())
+            .flatten()
+            .any(|x| *x < 0.0)
+        {
+            return Err("Survival and fertility matrices cannot contain negative values.");
+        }
+        let size = survival.get_lifestage_count() as usize;
+        for column in 0..size {
+            let total: f64 = (0..size).map(|row| survival[(row, column)]).sum();
+            if total > 1.0 + SURVIVAL_TOLERANCE {
+                return Err("Each column of the survival matrix must add up to no more than one.");
+            }
+        }
+        Ok(DecomposedMatrix {
+            survival,
+            fertility,
+        })
+    }
+    /// Split a projection matrix into survival and fertility by treating every value in the
+    /// listed rows as fertility and every other value as survival. This matches the common case
+    /// where only the first row (newborns) holds reproduction, which is written
+    /// `from_fertility_rows(&matrix, &[0])`.
+    /// # Errors
+    /// Will return `Err<'static str>` if a listed row does not exist, or if the resulting
+    /// survival matrix is invalid (see [`DecomposedMatrix::build`]).
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+    /// assert!((decomposed.net_reproductive_rate().unwrap() - 7.5).abs() < 1e-9);
+    /// ```
+    pub fn from_fertility_rows(
+        matrix: &PopulationMatrix,
+        fertility_rows: &[usize],
+    ) -> Result<DecomposedMatrix, &'static str> {
+        let size = matrix.get_lifestage_count() as usize;
+        if fertility_rows.iter().any(|row| *row >= size) {
+            return Err("Fertility row is outside the matrix.");
+        }
+        let mut survival = matrix.get_matrix().clone();
+        let mut fertility = vec![vec![0.0; size]; size];
+        for row in fertility_rows {
+            fertility[*row] = std::mem::replace(&mut survival[*row], vec![0.0; size]);
+        }
+        DecomposedMatrix::build(
+            PopulationMatrix::build(survival)?,
+            PopulationMatrix::build(fertility)?,
+        )
+    }
+    /// Return the survival/growth submatrix U.
+    pub fn get_survival(&self) -> &PopulationMatrix {
+        &self.survival
+    }
+    /// Return the fertility submatrix F.
+    pub fn get_fertility(&self) -> &PopulationMatrix {
+        &self.fertility
+    }
+    /// Return the number of lifestages represented.
+    pub fn get_lifestage_count(&self) -> u8 {
+        self.survival.get_lifestage_count()
+    }
+    /// Return the full projection matrix `A = U + F`.
+    pub fn get_matrix(&self) -> PopulationMatrix {
+        &self.survival + &self.fertility
+    }
+    /// Return the fundamental matrix `N = (I - U)^-1` as nested vectors.
+    fn fundamental(&self) -> Result<Vec<Vec<f64>>, &'static str> {
+        fundamental_of(&self.survival.get_matrix())
+    }
+    /// Return the fundamental matrix `N = (I - U)^-1`. The value at `(i, j)` is the expected
+    /// number of steps an individual starting in lifestage `j` spends in lifestage `i` before it
+    /// dies, counting the starting step. Column `j` is therefore the expected time spent in each
+    /// lifestage by an individual starting in lifestage `j`.
+    /// # Errors
+    /// Will return `Err<'static str>` if `I - U` cannot be inverted, which happens when some
+    /// lifestage has a survival of exactly one.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+    /// let fundamental = decomposed.fundamental_matrix().unwrap();
+    /// // An adult survives 0.8 of the time, so it spends 1 / (1 - 0.8) = 5 steps as an adult.
+    /// assert!((fundamental[(1, 1)] - 5.0).abs() < 1e-9);
+    /// ```
+    pub fn fundamental_matrix(&self) -> Result<PopulationMatrix, &'static str> {
+        PopulationMatrix::build(self.fundamental()?)
+    }
+    /// Return the life expectancy of an individual starting in each lifestage: the expected
+    /// number of steps until death, counting the starting step. These are the column sums of the
+    /// fundamental matrix.
+    /// # Errors
+    /// Will return `Err<'static str>` if the fundamental matrix cannot be calculated.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+    /// let expectancy = decomposed.life_expectancy().unwrap();
+    /// // A newborn lives one step, then survives to adulthood half the time.
+    /// assert!((expectancy.get_vector()[0] - 3.5).abs() < 1e-9);
+    /// ```
+    pub fn life_expectancy(&self) -> Result<PopulationVector, &'static str> {
+        let fundamental = self.fundamental()?;
+        let size = fundamental.len();
+        Ok(PopulationVector::new(
+            (0..size)
+                .map(|column| fundamental.iter().map(|row| row[column]).sum())
+                .collect(),
+        ))
+    }
+    /// Return the probability that an individual starting in each lifestage ever reaches the
+    /// target lifestage before dying (for example, the probability that a seedling survives to
+    /// flower). The probability for the target lifestage itself is one. This is calculated by
+    /// treating the target as absorbing: individuals that reach it are removed from U.
+    /// # Errors
+    /// Will return `Err<'static str>` if the target lifestage does not exist, or if the
+    /// fundamental matrix of the modified U cannot be calculated.
+    pub fn probability_of_reaching(&self, target: usize) -> Result<PopulationVector, &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        if target >= size {
+            return Err("Target lifestage is outside the matrix.");
+        }
+        let mut absorbing = self.survival.get_matrix().clone();
+        for row in &mut absorbing {
+            row[target] = 0.0;
+        }
+        let fundamental = fundamental_of(&absorbing)?;
+        Ok(PopulationVector::new(fundamental[target].clone()))
+    }
+    /// Return the net reproductive rate R0: the average number of offspring produced over the
+    /// lifetime of an individual. It is the dominant eigenvalue of `F (I - U)^-1`.
+    /// # Errors
+    /// Will return `Err<'static str>` if `I - U` cannot be inverted, which happens when some
+    /// lifestage has a survival of exactly one.
+    pub fn net_reproductive_rate(&self) -> Result<f64, &'static str> {
+        let next_generation = multiply(&self.fertility.get_matrix(), &self.fundamental()?);
+        Ok(dominant_eigen(&next_generation).0)
+    }
+    /// Return the generation time `T = ln(R0) / ln(lambda)`, the time it takes the population
+    /// to grow by a factor of R0. It is measured in steps; a model with a declared time step
+    /// reports it in that unit with [`DeterministicPva::generation_time`](super::DeterministicPva::generation_time).
+    /// # Errors
+    /// Will return `Err<'static str>` if R0 cannot be calculated, or if lambda is one (the
+    /// population is stationary), where this definition of generation time is undefined.
+    pub fn generation_time(&self) -> Result<f64, &'static str> {
+        let net_reproductive_rate = self.net_reproductive_rate()?;
+        let lambda = dominant_eigen(&self.get_matrix().get_matrix()).0;
+        if (lambda - 1.0).abs() < 1e-12 {
+            return Err("Generation time is undefined when lambda is one.");
+        }
+        Ok(net_reproductive_rate.ln() / lambda.ln())
+    }
+    /// Project a Population Vector one step with demographic stochasticity: each individual
+    /// survives and moves between lifestages at random according to U (individuals are assumed to
+    /// be whole, so values are rounded first), and the number of offspring is drawn from a
+    /// Poisson distribution with the mean given by F.
+    pub fn project_demographic<R: Rng + ?Sized>(
+        &self,
+        vector: &PopulationVector,
+        rng: &mut R,
+    ) -> PopulationVector {
+        let size = self.get_lifestage_count() as usize;
+        let mut result = vec![0.0; size];
+        for (column, count) in vector.iter().enumerate() {
+            let mut remaining = count.round().max(0.0) as u64;
+            let mut remaining_probability = 1.0;
+            for (row, item) in result.iter_mut().enumerate() {
+                let probability = self.survival[(row, column)];
+                if remaining == 0 || remaining_probability <= 0.0 {
+                    break;
+                }
+                let conditional = (probability / remaining_probability).clamp(0.0, 1.0);
+                let moved = Binomial::new(remaining, conditional)
+                    .expect("Probability is clamped between zero and one.")
+                    .sample(rng);
+                *item += moved as f64;
+                remaining -= moved;
+                remaining_probability -= probability;
+            }
+            let parents = count.round().max(0.0);
+            for (row, item) in result.iter_mut().enumerate() {
+                let mean = self.fertility[(row, column)] * parents;
+                if mean > 0.0 {
+                    *item += Poisson::new(mean)
+                        .expect("Mean offspring number is positive.")
+                        .sample(rng);
+                }
+            }
+        }
+        PopulationVector::new(result)
+    }
+}
+
+/// Return `(I - U)^-1` for a survival/growth matrix U.
+fn fundamental_of(survival: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, &'static str> {
+    let difference: Vec<Vec<f64>> = identity(survival.len())
+        .iter()
+        .zip(survival)
+        .map(|(i, u)| i.iter().zip(u).map(|(a, b)| a - b).collect())
+        .collect();
+    invert(&difference).ok_or("I - U is singular; some lifestage never dies.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn teasel_like() -> DecomposedMatrix {
+        DecomposedMatrix::build(
+            PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap(),
+            PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.0, 0.0]]).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reconstructs_full_matrix() {
+        assert_eq!(
+            teasel_like().get_matrix().get_matrix(),
+            vec![vec![0.0, 3.0], vec![0.5, 0.8]]
+        );
+    }
+    #[test]
+    fn rejects_invalid_survival() {
+        assert!(DecomposedMatrix::build(
+            PopulationMatrix::build(vec![vec![0.5, 0.0], vec![0.6, 0.8]]).unwrap(),
+            PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.0, 0.0]]).unwrap(),
+        )
+        .is_err());
+        assert!(DecomposedMatrix::from_fertility_rows(
+            &PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap(),
+            &[2]
+        )
+        .is_err());
+    }
+    #[test]
+    fn probability_of_reaching_adulthood() {
+        let decomposed = DecomposedMatrix::build(
+            PopulationMatrix::build(vec![
+                vec![0.2, 0.0, 0.0],
+                vec![0.3, 0.4, 0.0],
+                vec![0.0, 0.5, 0.9],
+            ])
+            .unwrap(),
+            PopulationMatrix::build(vec![vec![0.0, 0.0, 2.0], vec![0.0; 3], vec![0.0; 3]]).unwrap(),
+        )
+        .unwrap();
+        let probabilities = decomposed.probability_of_reaching(2).unwrap();
+        // Juveniles leave the middle stage by maturing with probability 0.5 / (1 - 0.4).
+        let juvenile = 0.5 / 0.6;
+        let expected = [0.3 / 0.8 * juvenile, juvenile, 1.0];
+        for (x, y) in probabilities.iter().zip(expected) {
+            assert!((x - y).abs() < 1e-9);
+        }
+        assert!(decomposed.probability_of_reaching(3).is_err());
+        let expectancy = decomposed.life_expectancy().unwrap();
+        assert!((expectancy.get_vector()[2] - 10.0).abs() < 1e-9);
+    }
+    #[test]
+    fn generation_time_matches_definition() {
+        let decomposed = teasel_like();
+        let lambda = (0.8 + (0.64_f64 + 6.0).sqrt()) / 2.0;
+        let expected = 7.5_f64.ln() / lambda.ln();
+        assert!((decomposed.generation_time().unwrap() - expected).abs() < 1e-8);
+    }
+    #[test]
+    fn demographic_projection_averages_to_deterministic() {
+        let decomposed = teasel_like();
+        let vector = PopulationVector::new(vec![100.0, 100.0]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let replicates = 2000;
+        let mut totals = [0.0, 0.0];
+        for _ in 0..replicates {
+            let result = decomposed.project_demographic(&vector, &mut rng);
+            assert!(result.iter().all(|x| x.fract() == 0.0 && *x >= 0.0));
+            totals[0] += result.get_vector()[0];
+            totals[1] += result.get_vector()[1];
+        }
+        assert!((totals[0] / replicates as f64 - 300.0).abs() < 3.0);
+        assert!((totals[1] / replicates as f64 - 130.0).abs() < 3.0);
+    }
+}
@@ -0,0 +1,205 @@
+//! This module contains dormant lifestages such as seed banks and resting eggs, whose members survive in the bank and re-enter the active population only when conditions trigger it. A population can vanish above ground for years and return from the bank, so a dormant stage counts towards persistence: quasi-extinction by the total population includes it, and [`Dormancy::active_criterion`] gives the stricter criterion of having no active individuals for monitoring purposes. Matrix models take a dormant lifestage through `DeterministicPva::with_dormancy` and `StochasticPva::with_dormancy`.
+use super::{CovariateSeries, CovariateTrack, ExtinctionCriterion, PopulationMatrix};
+use crate::statistics::{logistic, logit};
+
+/// This struct describes a dormant lifestage. Each step, a dormant individual survives with
+/// probability `survival` and, if it survives, germinates (or hatches) into the emergence
+/// lifestage with the germination probability; otherwise it stays dormant. Without a trigger
+/// the germination probability is constant. With a trigger, it is
+/// `1 / (1 + exp(-(logit(germination) + slope * x(t))))` for the value `x(t)` of an
+/// environmental covariate that step, so `germination` is the probability when the covariate is
+/// zero, and a positive slope makes re-entry more likely as the covariate rises.
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// use ecolysis_cmd::populations::population_level_simulation::Dormancy;
+/// // Seeds (lifestage 0) survive 90% of years and 20% germinate into seedlings (lifestage 1).
+/// let seed_bank = Dormancy::build(0, 1, 0.9, 0.2).unwrap();
+/// let matrix = PopulationMatrix::build(vec![vec![0.0, 0.0, 30.0], vec![0.0, 0.1, 0.0], vec![0.0, 0.3, 0.8]]).unwrap();
+/// let with_bank = seed_bank.matrix_for(&matrix, 0).unwrap();
+/// assert!((with_bank[(0, 0)] - 0.72).abs() < 1e-12);
+/// assert!((with_bank[(1, 0)] - 0.18).abs() < 1e-12);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dormancy {
+    stage: usize,
+    emergence_stage: usize,
+    survival: f64,
+    germination: f64,
+    trigger_slope: f64,
+    trigger: CovariateTrack,
+}
+impl Dormancy {
+    /// Build a dormant lifestage with a constant germination probability.
+    /// # Errors
+    /// Will return `Err<'static str>` if the dormant and emergence lifestages are the same, or
+    /// the survival or germination probability is not between 0 and 1.
+    pub fn build(
+        stage: usize,
+        emergence_stage: usize,
+        survival: f64,
+        germination: f64,
+    ) -> Result<Dormancy, &'static str> {
+        if stage == emergence_stage {
+            return Err("Dormant individuals must emerge into another lifestage.");
+        }
+        if !(0.0..=1.0).contains(&survival) || !(0.0..=1.0).contains(&germination) {
+            return Err("Dormant survival and germination must be probabilities between 0 and 1.");
+        }
+        Ok(Dormancy {
+            stage,
+            emergence_stage,
+            survival,
+            germination,
+            trigger_slope: 0.0,
+            trigger: CovariateTrack::default(),
+        })
+    }
+    /// Return the dormancy with germination triggered by a covariate in the series (using its
+    /// last value for steps beyond the series), with the given slope on the logit scale.
+    /// # Errors
+    /// Will return `Err<'static str>` if the slope is not finite, the germination probability is
+    /// 0 or 1 (which no trigger can change), or the series is empty or has no column for the
+    /// covariate.
+    pub fn with_trigger(
+        mut self,
+        series: &CovariateSeries,
+        covariate: &str,
+        slope: f64,
+    ) -> Result<Self, &'static str> {
+        if !slope.is_finite() {
+            return Err("The slope of the germination trigger must be finite.");
+        }
+        if self.germination <= 0.0 || self.germination >= 1.0 {
+            return Err("A triggered germination probability must be strictly between 0 and 1.");
+        }
+        self.trigger = CovariateTrack::from_series(series, covariate)?;
+        self.trigger_slope = slope;
+        Ok(self)
+    }
+    /// Return the dormant lifestage.
+    pub fn get_stage(&self) -> usize {
+        self.stage
+    }
+    /// Return the lifestage dormant individuals emerge into.
+    pub fn get_emergence_stage(&self) -> usize {
+        self.emergence_stage
+    }
+    /// Return the probability that a dormant individual survives a step.
+    pub fn get_survival(&self) -> f64 {
+        self.survival
+    }
+    /// Return the probability that a surviving dormant individual germinates during the step
+    /// after `steps_taken` steps.
+    pub fn germination(&self, steps_taken: u32) -> f64 {
+        let Some(value) = self.trigger.value(steps_taken) else {
+            return self.germination;
+        };
+        logistic(logit(self.germination) + self.trigger_slope * value)
+    }
+    /// Return a copy of the matrix whose column for the dormant lifestage is replaced by the
+    /// dormancy during the step after `steps_taken` steps.
+    /// # Errors
+    /// Will return `Err<'static str>` if either lifestage is outside the matrix.
+    pub fn matrix_for(
+        &self,
+        matrix: &PopulationMatrix,
+        steps_taken: u32,
+    ) -> Result<PopulationMatrix, &'static str> {
+        let size = matrix.get_lifestage_count() as usize;
+        if self.stage >= size || self.emergence_stage >= size {
+            return Err("The dormant lifestage is outside the matrix.");
+        }
+        let germination = self.germination(steps_taken);
+        let mut rows = matrix.get_matrix().clone();
+        for (index, row) in rows.iter_mut().enumerate() {
+            row[self.stage] = if index == self.stage {
+                self.survival * (1.0 - germination)
+            } else if index == self.emergence_stage {
+                self.survival * germination
+            } else {
+                0.0
+            };
+        }
+        Ok(PopulationMatrix::build(rows)?.with_summation(matrix.get_summation()))
+    }
+    /// Return the criterion met when the active (non-dormant) lifestages of a population with
+    /// `lifestages` lifestages hold at most `threshold` individuals, whatever remains dormant.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// use ecolysis_cmd::populations::population_level_simulation::{Dormancy, ExtinctionCriterion};
+    /// let seed_bank = Dormancy::build(0, 1, 0.9, 0.2).unwrap();
+    /// let only_seeds = PopulationVector::new(vec![500.0, 0.0, 0.0]);
+    /// assert!(seed_bank.active_criterion(3, 0.0).is_met(&only_seeds));
+    /// assert!(!ExtinctionCriterion::Total(0.0).is_met(&only_seeds));
+    /// ```
+    pub fn active_criterion(&self, lifestages: usize, threshold: f64) -> ExtinctionCriterion {
+        ExtinctionCriterion::StageGroup {
+            lifestages: (0..lifestages).filter(|x| *x != self.stage).collect(),
+            threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DeterministicPva, Summation};
+
+    #[test]
+    fn trigger_changes_germination() {
+        let series = CovariateSeries::build(
+            vec![String::from("rain")],
+            vec![vec![-1.0], vec![0.0], vec![2.0]],
+        )
+        .unwrap();
+        let dormancy = Dormancy::build(0, 1, 0.8, 0.5)
+            .unwrap()
+            .with_trigger(&series, "rain", 1.0)
+            .unwrap();
+        assert!((dormancy.germination(0) - logistic(-1.0)).abs() < 1e-12);
+        assert_eq!(dormancy.germination(1), 0.5);
+        assert!((dormancy.germination(9) - logistic(2.0)).abs() < 1e-12);
+        let matrix = PopulationMatrix::build(vec![vec![0.3, 2.0], vec![0.4, 0.5]]).unwrap();
+        let with_bank = dormancy.matrix_for(&matrix, 1).unwrap();
+        assert_eq!(with_bank.get_matrix(), vec![vec![0.4, 2.0], vec![0.4, 0.5]]);
+        let outside = Dormancy::build(0, 2, 0.8, 0.5).unwrap();
+        assert!(outside.matrix_for(&matrix, 0).is_err());
+        assert!(Dormancy::build(1, 1, 0.8, 0.5).is_err());
+        assert!(Dormancy::build(0, 1, 1.2, 0.5).is_err());
+        assert!(Dormancy::build(0, 1, 0.8, 0.5)
+            .unwrap()
+            .with_trigger(&series, "temperature", 1.0)
+            .is_err());
+        assert!(Dormancy::build(0, 1, 0.8, 0.0)
+            .unwrap()
+            .with_trigger(&series, "rain", 1.0)
+            .is_err());
+    }
+    #[test]
+    fn dormancy_keeps_compensated_summation() {
+        // Plain summation of the first row loses the 1 from the dormant stage when 1e16 is
+        // added, before 1e16 is subtracted again.
+        let model = DeterministicPva::build_from_vectors(
+            vec![4.0, 1.0, -1.0],
+            vec![
+                vec![0.0, 1e16, 1e16],
+                vec![0.0, 0.0, 0.0],
+                vec![0.0, 0.0, 0.0],
+            ],
+        )
+        .unwrap()
+        .with_dormancy(Dormancy::build(0, 1, 0.5, 0.5).unwrap())
+        .unwrap();
+        let first = |model: &DeterministicPva| {
+            model
+                .deterministic_projection(1)
+                .iter()
+                .last()
+                .unwrap()
+                .get_vector()[0]
+        };
+        assert_eq!(first(&model), 0.0);
+        let compensated = model.with_summation(Summation::Compensated);
+        assert_eq!(first(&compensated), 1.0);
+    }
+}
@@ -0,0 +1,129 @@
+//! This module adds eigen-analysis to the Population Matrix: the asymptotic growth rate (lambda), the stable stage distribution, reproductive values, and the sensitivities and elasticities of lambda to each matrix element.
+use super::linear_algebra::dominant_eigen;
+use super::{PopulationMatrix, PopulationVector};
+
+impl PopulationMatrix {
+    /// Return the asymptotic population growth rate, lambda: the dominant eigenvalue of the
+    /// matrix. A lambda above one means the population grows in the long run; below one means it
+    /// declines.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+    /// assert!((popmatrix.lambda() - 1.0).abs() < 1e-9);
+    /// ```
+    pub fn lambda(&self) -> f64 {
+        dominant_eigen(&self.get_matrix()).0
+    }
+    /// Return the stable stage distribution: the proportion of the population in each lifestage
+    /// that a population approaches over time (the right eigenvector of the dominant
+    /// eigenvalue). The values add up to one.
+    pub fn stable_stage_distribution(&self) -> PopulationVector {
+        PopulationVector::new(dominant_eigen(&self.get_matrix()).1)
+    }
+    /// Return the reproductive value of each lifestage (the left eigenvector of the dominant
+    /// eigenvalue), scaled so that the first lifestage has a reproductive value of one. This is
+    /// the relative contribution an individual in each lifestage makes to future population size.
+    pub fn reproductive_values(&self) -> PopulationVector {
+        let left = dominant_eigen(&self.transpose().get_matrix()).1;
+        let first = left[0];
+        if first > 0.0 {
+            PopulationVector::new(left.iter().map(|x| x / first).collect())
+        } else {
+            PopulationVector::new(left)
+        }
+    }
+    /// Return the sensitivity of lambda to a small change in each element of the matrix, as a
+    /// Population Matrix of the same shape. Sensitivities are calculated for every element,
+    /// including ones that are zero.
+    pub fn sensitivities(&self) -> PopulationMatrix {
+        let right = dominant_eigen(&self.get_matrix()).1;
+        let left = dominant_eigen(&self.transpose().get_matrix()).1;
+        let scale: f64 = left.iter().zip(&right).map(|(v, w)| v * w).sum();
+        self.derived(
+            left.iter()
+                .map(|v| right.iter().map(|w| v * w / scale).collect())
+                .collect(),
+        )
+    }
+    /// Return the elasticity of lambda to each element of the matrix: the proportional change in
+    /// lambda caused by a proportional change in the element. Elasticities add up to one, so they
+    /// are often used to rank which vital rates matter most for population growth.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let popmatrix = PopulationMatrix::build(vec![
+    ///     vec![0.0, 0.0, 0.1],
+    ///     vec![0.6, 0.8, 0.0],
+    ///     vec![0.0, 0.8, 0.95],
+    /// ]).unwrap();
+    /// let total: f64 = popmatrix.elasticities().get_matrix().iter().flatten().sum();
+    /// assert!((total - 1.0).abs() < 1e-9);
+    /// ```
+    pub fn elasticities(&self) -> PopulationMatrix {
+        let lambda = self.lambda();
+        let sensitivities = self.sensitivities();
+        self.derived(
+            self.rows()
+                .zip(sensitivities.rows())
+                .map(|(a, s)| a.iter().zip(s).map(|(a, s)| a * s / lambda).collect())
+                .collect(),
+        )
+    }
+    /// Return the `(row, column)` position of the largest elasticity, counting from zero, and
+    /// the elasticity: the vital rate that matters most for population growth.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// // Adult survival matters more than reproduction.
+    /// let popmatrix = PopulationMatrix::build(vec![vec![0.0, 0.5], vec![0.5, 0.9]]).unwrap();
+    /// let (position, elasticity) = popmatrix.largest_elasticity();
+    /// assert_eq!(position, (1, 1));
+    /// assert_eq!(elasticity, popmatrix.elasticities()[(1, 1)]);
+    /// ```
+    pub fn largest_elasticity(&self) -> ((usize, usize), f64) {
+        let elasticities = self.elasticities();
+        let size = self.get_lifestage_count() as usize;
+        let position = (0..size)
+            .flat_map(|row| (0..size).map(move |column| (row, column)))
+            .max_by(|a, b| elasticities[*a].total_cmp(&elasticities[*b]))
+            .unwrap_or((0, 0));
+        (position, elasticities[position])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_matrix() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    #[test]
+    fn lambda_of_two_stage_matrix() {
+        let expected = (0.8 + (0.64_f64 + 6.0).sqrt()) / 2.0;
+        assert!((test_matrix().lambda() - expected).abs() < 1e-9);
+    }
+    #[test]
+    fn stable_stage_is_eigenvector() {
+        let matrix = test_matrix();
+        let stable = matrix.stable_stage_distribution();
+        let projected = &matrix * &stable;
+        for (x, w) in projected.iter().zip(&stable) {
+            assert!((x - matrix.lambda() * w).abs() < 1e-9);
+        }
+    }
+    #[test]
+    fn sensitivity_matches_finite_difference() {
+        let matrix = test_matrix();
+        let step = 1e-6;
+        let perturbed =
+            PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5 + step, 0.8]]).unwrap();
+        let numerical = (perturbed.lambda() - matrix.lambda()) / step;
+        assert!((matrix.sensitivities()[(1, 0)] - numerical).abs() < 1e-4);
+    }
+    #[test]
+    fn reproductive_values_scaled_to_first_stage() {
+        let values = test_matrix().reproductive_values();
+        assert!((values.get_vector()[0] - 1.0).abs() < 1e-12);
+        assert!(values.get_vector()[1] > 1.0);
+    }
+}
@@ -0,0 +1,494 @@
+//! This module contains inverse estimation of vital rates from field census data: selected elements of a Population Matrix are fitted to an observed time series of stage-structured counts, so a matrix can be built from monitoring data when the rates were never measured directly.
+//!
+//! Each count is predicted by projecting the previous count one step, and the rates are chosen to minimize the difference between the predicted and observed counts (by least squares or a Poisson likelihood) with the Nelder-Mead simplex method. Rates that are not being estimated keep their values from the starting matrix.
+use super::{PopulationMatrix, PopulationVector};
+
+/// This struct describes one vital rate to estimate: a single element of the projection matrix
+/// (given by its row and column), and the bounds its estimate must stay within.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EstimatedRate {
+    name: String,
+    row: usize,
+    column: usize,
+    lower: f64,
+    upper: f64,
+}
+impl EstimatedRate {
+    /// Create a new Estimated Rate for the matrix element at `(row, column)`, which can take any
+    /// non-negative value (it has no upper bound). Use [`EstimatedRate::survival`] for
+    /// probabilities.
+    pub fn new(name: &str, row: usize, column: usize) -> EstimatedRate {
+        EstimatedRate {
+            name: name.to_string(),
+            row,
+            column,
+            lower: 0.0,
+            upper: f64::INFINITY,
+        }
+    }
+    /// Create a new Estimated Rate for a survival or transition probability at `(row, column)`,
+    /// which must be between zero and one.
+    pub fn survival(name: &str, row: usize, column: usize) -> EstimatedRate {
+        EstimatedRate {
+            upper: 1.0,
+            ..EstimatedRate::new(name, row, column)
+        }
+    }
+    /// Return the rate with its estimate limited to between `lower` and `upper`.
+    /// # Errors
+    /// Will return `Err<'static str>` if either bound is negative or not finite, or `lower` is
+    /// greater than `upper`.
+    pub fn with_bounds(mut self, lower: f64, upper: f64) -> Result<EstimatedRate, &'static str> {
+        if !lower.is_finite() || !upper.is_finite() || lower < 0.0 {
+            return Err("The bounds of a rate must be finite and not negative.");
+        }
+        if lower > upper {
+            return Err("The lower bound of a rate cannot be greater than its upper bound.");
+        }
+        self.lower = lower;
+        self.upper = upper;
+        Ok(self)
+    }
+    /// Return the name of the rate.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the lower and upper bounds of the rate.
+    pub fn get_bounds(&self) -> (f64, f64) {
+        (self.lower, self.upper)
+    }
+}
+
+/// This enum describes how the difference between predicted and observed counts is measured:
+/// - `LeastSquares`: the sum of squared differences. Large counts dominate the fit.
+/// - `Poisson`: the negative log-likelihood of the observed counts if each is drawn from a
+///   Poisson distribution with the predicted count as its mean. This weights each lifestage by
+///   how precisely it can be counted and is the better choice for small counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FitObjective {
+    #[default]
+    LeastSquares,
+    Poisson,
+}
+impl FitObjective {
+    /// Return the value of the objective for one predicted and one observed count.
+    fn evaluate(self, predicted: f64, observed: f64) -> f64 {
+        match self {
+            FitObjective::LeastSquares => (predicted - observed).powi(2),
+            FitObjective::Poisson if predicted > 0.0 => predicted - observed * predicted.ln(),
+            FitObjective::Poisson if observed > 0.0 => f64::INFINITY,
+            FitObjective::Poisson => 0.0,
+        }
+    }
+}
+
+/// This struct holds an estimation problem: a starting matrix, the rates in it to estimate, and
+/// how the fit is measured and run.
+/// ```
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// use ecolysis_cmd::populations::population_level_simulation::{EstimatedRate, VitalRateFit};
+/// // Five years of counts of juveniles and adults.
+/// let census: Vec<PopulationVector> = [[50.0, 40.0], [80.0, 57.0], [114.0, 86.0],
+///     [172.0, 126.0], [252.0, 189.0]]
+///     .iter()
+///     .map(|x| PopulationVector::new(x.to_vec()))
+///     .collect();
+/// let start = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap();
+/// let fit = VitalRateFit::build(start, vec![
+///     EstimatedRate::new("fecundity", 0, 1),
+///     EstimatedRate::survival("juvenile_survival", 1, 0),
+///     EstimatedRate::survival("adult_survival", 1, 1),
+/// ]).unwrap();
+/// let estimate = fit.fit(&census).unwrap();
+/// assert!(estimate.is_converged());
+/// assert_eq!(estimate.get_values().len(), 3);
+/// assert!((estimate.get_matrix().lambda() - 1.47).abs() < 0.05);
+/// ```
+#[derive(Clone, Debug)]
+pub struct VitalRateFit {
+    matrix: PopulationMatrix,
+    rates: Vec<EstimatedRate>,
+    objective: FitObjective,
+    max_iterations: usize,
+    tolerance: f64,
+}
+impl VitalRateFit {
+    /// Build a fit of the given rates, starting from their values in `matrix` (moved within
+    /// their bounds if needed). The fit uses least squares, at most 10,000 iterations, and a
+    /// relative tolerance of 1e-10 unless changed.
+    /// # Errors
+    /// Will return `Err<'static str>` if no rates are given, a rate is outside the matrix, or
+    /// the same element is estimated twice.
+    pub fn build(
+        matrix: PopulationMatrix,
+        rates: Vec<EstimatedRate>,
+    ) -> Result<VitalRateFit, &'static str> {
+        if rates.is_empty() {
+            return Err("At least one rate must be estimated.");
+        }
+        let size = matrix.get_lifestage_count() as usize;
+        if rates.iter().any(|x| x.row >= size || x.column >= size) {
+            return Err("An estimated rate is outside the matrix.");
+        }
+        for (index, rate) in rates.iter().enumerate() {
+            if rates[..index]
+                .iter()
+                .any(|x| x.row == rate.row && x.column == rate.column)
+            {
+                return Err("Each matrix element can only be estimated once.");
+            }
+        }
+        Ok(VitalRateFit {
+            matrix,
+            rates,
+            objective: FitObjective::default(),
+            max_iterations: 10_000,
+            tolerance: 1e-10,
+        })
+    }
+    /// Return the fit with the given objective.
+    pub fn with_objective(mut self, objective: FitObjective) -> Self {
+        self.objective = objective;
+        self
+    }
+    /// Return the fit with the given maximum number of iterations of the optimizer.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+    /// Return the fit with the given relative tolerance: the optimizer stops once the objective
+    /// values across the simplex differ by less than this fraction of their size.
+    /// # Errors
+    /// Will return `Err<'static str>` if the tolerance is not positive.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Result<Self, &'static str> {
+        if tolerance.is_nan() || tolerance <= 0.0 {
+            return Err("The tolerance must be positive.");
+        }
+        self.tolerance = tolerance;
+        Ok(self)
+    }
+    /// Return the rates being estimated.
+    pub fn get_rates(&self) -> &Vec<EstimatedRate> {
+        &self.rates
+    }
+    /// Return the starting matrix with each estimated rate set to the corresponding value.
+    /// # Errors
+    /// Will return `Err<'static str>` if a value is not finite.
+    fn matrix_with(&self, values: &[f64]) -> Result<PopulationMatrix, &'static str> {
+        let mut matrix = self.matrix.clone();
+        for (rate, value) in self.rates.iter().zip(values) {
+            matrix.set_element(rate.row, rate.column, *value)?;
+        }
+        Ok(matrix)
+    }
+    /// Move each value within the bounds of its rate.
+    fn clamp(&self, values: &mut [f64]) {
+        for (value, rate) in values.iter_mut().zip(&self.rates) {
+            *value = value.clamp(rate.lower, rate.upper);
+        }
+    }
+    /// Return the objective summed over every one-step prediction of the census (infinite if
+    /// the values do not form a valid matrix).
+    fn objective_value(&self, values: &[f64], census: &[PopulationVector]) -> f64 {
+        let Ok(matrix) = self.matrix_with(values) else {
+            return f64::INFINITY;
+        };
+        let predictions = matrix
+            .project_vectors(&census[..census.len() - 1])
+            .expect("Census lengths are checked before fitting.");
+        predictions
+            .iter()
+            .zip(&census[1..])
+            .map(|(prediction, observed)| {
+                prediction
+                    .iter()
+                    .zip(observed.iter())
+                    .map(|(predicted, observed)| self.objective.evaluate(*predicted, *observed))
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+    /// Estimate the rates from a census: a Population Vector of counts for each of a series of
+    /// consecutive steps. Each count after the first is predicted by projecting the one before
+    /// it, so counting error does not accumulate along the series.
+    ///
+    /// The optimizer restarts once from its result to avoid stopping on a collapsed simplex.
+    /// Rates that have little effect on the counts (such as the survival of a stage that is
+    /// rarely counted) are poorly determined; compare fits from different starting matrices when
+    /// in doubt.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are fewer than two censuses, any census has a
+    /// different number of lifestages than the matrix, any count is negative or not finite, or
+    /// the optimizer ends on a value that is not finite.
+    pub fn fit(&self, census: &[PopulationVector]) -> Result<VitalRateEstimate, &'static str> {
+        if census.len() < 2 {
+            return Err("At least two consecutive censuses are required.");
+        }
+        if census
+            .iter()
+            .any(|x| x.get_lifestage_count() != self.matrix.get_lifestage_count())
+        {
+            return Err("Population vector size does not match the matrix.");
+        }
+        if census.iter().flatten().any(|x| *x < 0.0 || !x.is_finite()) {
+            return Err("Census counts must be finite and non-negative.");
+        }
+        let mut start: Vec<f64> = self
+            .rates
+            .iter()
+            .map(|rate| self.matrix.get_matrix()[rate.row][rate.column])
+            .collect();
+        self.clamp(&mut start);
+        let objective = |values: &[f64]| self.objective_value(values, census);
+        let (values, first_iterations, _) = self.nelder_mead(start, &objective);
+        let (values, second_iterations, converged) = self.nelder_mead(values, &objective);
+        Ok(VitalRateEstimate {
+            names: self.rates.iter().map(|x| x.name.clone()).collect(),
+            objective_value: objective(&values),
+            matrix: self.matrix_with(&values)?,
+            values,
+            iterations: first_iterations + second_iterations,
+            converged,
+        })
+    }
+    /// Minimize `objective` from `start` with the Nelder-Mead simplex method, keeping every
+    /// point within the bounds of the rates. Return the best point, the number of iterations,
+    /// and whether the tolerance was reached.
+    fn nelder_mead(
+        &self,
+        start: Vec<f64>,
+        objective: &dyn Fn(&[f64]) -> f64,
+    ) -> (Vec<f64>, usize, bool) {
+        let size = start.len();
+        let mut simplex = vec![start.clone()];
+        for index in 0..size {
+            let mut point = start.clone();
+            let step = 0.1 * start[index].abs().max(0.1);
+            point[index] += if point[index] + step <= self.rates[index].upper {
+                step
+            } else {
+                -step
+            };
+            self.clamp(&mut point);
+            simplex.push(point);
+        }
+        let mut values: Vec<f64> = simplex.iter().map(|x| objective(x)).collect();
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            let mut order: Vec<usize> = (0..=size).collect();
+            order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+            simplex = order.iter().map(|x| simplex[*x].clone()).collect();
+            values = order.iter().map(|x| values[*x]).collect();
+            let (best, worst) = (values[0], values[size]);
+            if (worst - best).abs() <= self.tolerance * (best.abs() + worst.abs()) + 1e-300 {
+                return (simplex.swap_remove(0), iterations, true);
+            }
+            iterations += 1;
+            let centroid: Vec<f64> = (0..size)
+                .map(|index| simplex[..size].iter().map(|x| x[index]).sum::<f64>() / size as f64)
+                .collect();
+            let toward = |factor: f64| {
+                let mut point: Vec<f64> = centroid
+                    .iter()
+                    .zip(&simplex[size])
+                    .map(|(c, w)| c + factor * (c - w))
+                    .collect();
+                self.clamp(&mut point);
+                let value = objective(&point);
+                (point, value)
+            };
+            let (reflected, reflected_value) = toward(1.0);
+            if reflected_value < values[0] {
+                let (expanded, expanded_value) = toward(2.0);
+                (simplex[size], values[size]) = if expanded_value < reflected_value {
+                    (expanded, expanded_value)
+                } else {
+                    (reflected, reflected_value)
+                };
+            } else if reflected_value < values[size - 1] {
+                (simplex[size], values[size]) = (reflected, reflected_value);
+            } else {
+                let (contracted, contracted_value) = if reflected_value < values[size] {
+                    toward(0.5)
+                } else {
+                    toward(-0.5)
+                };
+                if contracted_value < values[size].min(reflected_value) {
+                    (simplex[size], values[size]) = (contracted, contracted_value);
+                } else {
+                    // Shrink every point halfway toward the best one.
+                    for index in 1..=size {
+                        simplex[index] = simplex[0]
+                            .iter()
+                            .zip(&simplex[index])
+                            .map(|(b, x)| b + 0.5 * (x - b))
+                            .collect();
+                        values[index] = objective(&simplex[index]);
+                    }
+                }
+            }
+        }
+        let best = (0..=size)
+            .min_by(|a, b| values[*a].total_cmp(&values[*b]))
+            .unwrap_or(0);
+        (simplex.swap_remove(best), iterations, false)
+    }
+}
+
+/// This struct holds the result of a [`VitalRateFit`]: the estimated value of each rate, the
+/// matrix containing them, and how the optimizer finished.
+#[derive(Clone, Debug)]
+pub struct VitalRateEstimate {
+    names: Vec<String>,
+    values: Vec<f64>,
+    matrix: PopulationMatrix,
+    objective_value: f64,
+    iterations: usize,
+    converged: bool,
+}
+impl VitalRateEstimate {
+    /// Return the names of the estimated rates.
+    pub fn get_names(&self) -> &Vec<String> {
+        &self.names
+    }
+    /// Return the estimated value of each rate, in the order the rates were given.
+    pub fn get_values(&self) -> &Vec<f64> {
+        &self.values
+    }
+    /// Return the estimated value of the rate with the given name, or `None` if there is none.
+    pub fn get_value(&self, name: &str) -> Option<f64> {
+        self.names
+            .iter()
+            .position(|x| x == name)
+            .map(|index| self.values[index])
+    }
+    /// Return the starting matrix with the estimated rates filled in.
+    pub fn get_matrix(&self) -> &PopulationMatrix {
+        &self.matrix
+    }
+    /// Return the value of the objective at the estimate (the residual sum of squares or the
+    /// Poisson negative log-likelihood, without its constant term).
+    pub fn get_objective_value(&self) -> f64 {
+        self.objective_value
+    }
+    /// Return the number of iterations the optimizer took.
+    pub fn get_iterations(&self) -> usize {
+        self.iterations
+    }
+    /// Return true if the optimizer reached its tolerance before the iteration limit.
+    pub fn is_converged(&self) -> bool {
+        self.converged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truth() -> PopulationMatrix {
+        PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 4.0],
+            vec![0.3, 0.6, 0.0],
+            vec![0.0, 0.25, 0.85],
+        ])
+        .unwrap()
+    }
+
+    fn census(matrix: &PopulationMatrix, length: usize) -> Vec<PopulationVector> {
+        let mut counts = vec![PopulationVector::new(vec![100.0, 60.0, 30.0])];
+        for step in 1..length {
+            counts.push(matrix.project_vector(&counts[step - 1]).unwrap());
+        }
+        counts
+    }
+
+    fn rates() -> Vec<EstimatedRate> {
+        vec![
+            EstimatedRate::new("fecundity", 0, 2),
+            EstimatedRate::survival("juvenile_growth", 1, 0),
+            EstimatedRate::survival("subadult_stasis", 1, 1),
+            EstimatedRate::survival("subadult_growth", 2, 1),
+            EstimatedRate::survival("adult_survival", 2, 2),
+        ]
+    }
+
+    fn start() -> PopulationMatrix {
+        PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 1.0],
+            vec![0.5, 0.5, 0.0],
+            vec![0.0, 0.5, 0.5],
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn exact_counts_recover_the_rates() {
+        let counts = census(&truth(), 8);
+        for objective in [FitObjective::LeastSquares, FitObjective::Poisson] {
+            let estimate = VitalRateFit::build(start(), rates())
+                .unwrap()
+                .with_objective(objective)
+                .fit(&counts)
+                .unwrap();
+            assert!(estimate.is_converged());
+            assert!(estimate.get_matrix().approx_eq(&truth(), 1e-3));
+            assert!((estimate.get_value("fecundity").unwrap() - 4.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn estimates_stay_within_bounds() {
+        // Counts that grow faster than any survival below one allows push the rate to its bound.
+        let counts: Vec<PopulationVector> = [[0.0, 10.0], [0.0, 20.0], [0.0, 40.0]]
+            .iter()
+            .map(|x| PopulationVector::new(x.to_vec()))
+            .collect();
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.0, 0.5]]).unwrap();
+        let estimate = VitalRateFit::build(matrix, vec![EstimatedRate::survival("adult", 1, 1)])
+            .unwrap()
+            .fit(&counts)
+            .unwrap();
+        assert!((estimate.get_values()[0] - 1.0).abs() < 1e-9);
+        assert!(estimate.get_objective_value() > 0.0);
+    }
+
+    #[test]
+    fn poisson_objective_handles_zero_predictions() {
+        assert_eq!(FitObjective::Poisson.evaluate(0.0, 0.0), 0.0);
+        assert_eq!(FitObjective::Poisson.evaluate(0.0, 2.0), f64::INFINITY);
+        assert_eq!(FitObjective::LeastSquares.evaluate(1.0, 3.0), 4.0);
+    }
+
+    #[test]
+    fn invalid_fits_are_rejected() {
+        assert!(VitalRateFit::build(start(), Vec::new()).is_err());
+        assert!(VitalRateFit::build(start(), vec![EstimatedRate::new("x", 3, 0)]).is_err());
+        assert!(VitalRateFit::build(
+            start(),
+            vec![EstimatedRate::new("x", 0, 2), EstimatedRate::new("y", 0, 2)]
+        )
+        .is_err());
+        assert!(EstimatedRate::new("x", 0, 0).with_bounds(1.0, 0.0).is_err());
+        assert!(EstimatedRate::new("x", 0, 0)
+            .with_bounds(-1.0, 1.0)
+            .is_err());
+        assert!(EstimatedRate::new("x", 0, 0)
+            .with_bounds(0.0, f64::INFINITY)
+            .is_err());
+        let fit = VitalRateFit::build(start(), rates()).unwrap();
+        assert!(fit.clone().with_tolerance(0.0).is_err());
+        assert!(fit.fit(&census(&truth(), 1)).is_err());
+        assert!(fit
+            .fit(&[
+                PopulationVector::new(vec![1.0, 1.0, 1.0]),
+                PopulationVector::new(vec![1.0, -1.0, 1.0]),
+            ])
+            .is_err());
+        assert!(fit
+            .fit(&[
+                PopulationVector::new(vec![1.0, 1.0]),
+                PopulationVector::new(vec![1.0, 1.0]),
+            ])
+            .is_err());
+    }
+}
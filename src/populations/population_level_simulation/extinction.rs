@@ -0,0 +1,238 @@
+//! This module contains extinction criteria that look at individual lifestages instead of only the total population. Recovery plans often define quasi-extinction by a breeding stage (for example, fewer than two adult females), which the total population can hide when many non-breeding individuals remain.
+use super::{PopulationVector, PvaStochasticOutput, PvaStochasticSummary};
+
+/// This enum describes a condition under which a population counts as quasi-extinct:
+/// - `Total`: the total population is at or below the threshold.
+/// - `Stage`: one lifestage (starting from 0) is at or below the threshold.
+/// - `StageGroup`: the sum of several lifestages, such as every breeding stage, is at or below
+///   the threshold.
+///
+/// A criterion that names a lifestage outside the population is never met, and is rejected by
+/// [`ExtinctionCriterion::check`] and the projections that use it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExtinctionCriterion {
+    Total(f64),
+    Stage {
+        lifestage: usize,
+        threshold: f64,
+    },
+    StageGroup {
+        lifestages: Vec<usize>,
+        threshold: f64,
+    },
+}
+impl ExtinctionCriterion {
+    /// Return true if the population meets the criterion.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::ExtinctionCriterion;
+    /// use ecolysis_cmd::PopulationVector;
+    /// let few_adults = ExtinctionCriterion::Stage { lifestage: 2, threshold: 2.0 };
+    /// assert!(few_adults.is_met(&PopulationVector::new(vec![300.0, 80.0, 1.0])));
+    /// assert!(!ExtinctionCriterion::Total(10.0).is_met(&PopulationVector::new(vec![300.0, 80.0, 1.0])));
+    /// ```
+    pub fn is_met(&self, popvector: &PopulationVector) -> bool {
+        let values = popvector.get_vector();
+        match self {
+            ExtinctionCriterion::Total(threshold) => popvector.total() <= *threshold,
+            ExtinctionCriterion::Stage {
+                lifestage,
+                threshold,
+            } => values.get(*lifestage).is_some_and(|x| x <= threshold),
+            ExtinctionCriterion::StageGroup {
+                lifestages,
+                threshold,
+            } => {
+                lifestages.iter().all(|x| *x < values.len())
+                    && lifestages.iter().map(|x| values[*x]).sum::<f64>() <= *threshold
+            }
+        }
+    }
+
+    /// Check that the criterion can apply to a population with `lifestage_count` lifestages.
+    /// # Errors
+    /// Will return `Err<'static str>` if a stage group names no lifestages or the criterion names
+    /// a lifestage outside the population.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::ExtinctionCriterion;
+    /// let juveniles = ExtinctionCriterion::Stage { lifestage: 1, threshold: 2.0 };
+    /// assert!(juveniles.check(3).is_ok());
+    /// assert!(juveniles.check(1).is_err());
+    /// ```
+    pub fn check(&self, lifestage_count: usize) -> Result<(), &'static str> {
+        let lifestages = match self {
+            ExtinctionCriterion::Total(_) => return Ok(()),
+            ExtinctionCriterion::Stage { lifestage, .. } => std::slice::from_ref(lifestage),
+            ExtinctionCriterion::StageGroup { lifestages, .. } => {
+                if lifestages.is_empty() {
+                    return Err("A stage group extinction criterion needs at least one lifestage.");
+                }
+                lifestages.as_slice()
+            }
+        };
+        if lifestages.iter().any(|x| *x >= lifestage_count) {
+            return Err("An extinction criterion names a lifestage outside the population.");
+        }
+        Ok(())
+    }
+}
+
+/// Return true if the population meets any of the criteria.
+pub(crate) fn any_met(criteria: &[ExtinctionCriterion], popvector: &PopulationVector) -> bool {
+    criteria.iter().any(|x| x.is_met(popvector))
+}
+
+impl PvaStochasticOutput {
+    /// Return the first step (starting at 1) at which each replicate met any of the criteria, or
+    /// `None` if it never did.
+    pub fn extinction_steps_by(&self, criteria: &[ExtinctionCriterion]) -> Vec<Option<u32>> {
+        self.get_replicates()
+            .iter()
+            .map(|steps| {
+                steps
+                    .iter()
+                    .position(|x| any_met(criteria, x))
+                    .map(|x| x as u32 + 1)
+            })
+            .collect()
+    }
+    /// Return the time at which each replicate first met any of the criteria, in the unit of the
+    /// declared time step (or in steps if there is none), or `None` if it never did.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{
+    ///     ExtinctionCriterion, StochasticPva, TimeStep,
+    /// };
+    /// let population = StochasticPva::build_from_vectors(vec![10.0, 10.0], vec![
+    ///     vec![vec![0.0, 0.5], vec![0.3, 0.5]],
+    /// ]).unwrap().with_time_step("2 months".parse::<TimeStep>().unwrap());
+    /// let output = population.stochastic_projection(50, 1, 0);
+    /// let extinct = [ExtinctionCriterion::Total(1.0)];
+    /// let steps = output.extinction_steps_by(&extinct)[0].unwrap();
+    /// assert_eq!(output.extinction_times_by(&extinct)[0], Some(2.0 * steps as f64));
+    /// assert_eq!(output.extinction_times(1.0), output.extinction_times_by(&extinct));
+    /// ```
+    pub fn extinction_times_by(&self, criteria: &[ExtinctionCriterion]) -> Vec<Option<f64>> {
+        let length = self.get_time_step().map_or(1.0, |x| x.get_length());
+        self.extinction_steps_by(criteria)
+            .into_iter()
+            .map(|step| step.map(|x| x as f64 * length))
+            .collect()
+    }
+    /// Return the time at which the total population of each replicate first fell to or below
+    /// `threshold`, in the unit of the declared time step (or in steps if there is none), or
+    /// `None` if it never did.
+    pub fn extinction_times(&self, threshold: f64) -> Vec<Option<f64>> {
+        self.extinction_times_by(&[ExtinctionCriterion::Total(threshold)])
+    }
+    /// Return the proportion of replicates that met any of the criteria at any step. With only
+    /// [`ExtinctionCriterion::Total`] this is the same as
+    /// [`PvaStochasticOutput::extinction_probability`].
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{
+    ///     ExtinctionCriterion, StochasticPva,
+    /// };
+    /// // Many juveniles, but adults decline.
+    /// let population = StochasticPva::build_from_vectors(vec![500.0, 10.0], vec![
+    ///     vec![vec![0.9, 0.0], vec![0.0, 0.5]],
+    /// ]).unwrap();
+    /// let output = population.stochastic_projection(10, 1, 0);
+    /// let adults = ExtinctionCriterion::Stage { lifestage: 1, threshold: 2.0 };
+    /// assert_eq!(output.extinction_probability(2.0), 0.0);
+    /// assert_eq!(output.extinction_probability_by(&[adults]), 1.0);
+    /// ```
+    pub fn extinction_probability_by(&self, criteria: &[ExtinctionCriterion]) -> f64 {
+        let steps = self.extinction_steps_by(criteria);
+        if steps.is_empty() {
+            return 0.0;
+        }
+        steps.iter().filter(|x| x.is_some()).count() as f64 / steps.len() as f64
+    }
+}
+
+impl PvaStochasticSummary {
+    /// Return the summary with a replicate also counted as extinct when it meets any of the
+    /// criteria, in addition to the total population falling to the extinction threshold. The
+    /// criteria must be set before any steps are added.
+    pub fn with_extinction_criteria(mut self, criteria: Vec<ExtinctionCriterion>) -> Self {
+        self.extinction_criteria = criteria;
+        self
+    }
+    /// Return the criteria a replicate is counted as extinct by, besides the extinction
+    /// threshold.
+    pub fn get_extinction_criteria(&self) -> &Vec<ExtinctionCriterion> {
+        &self.extinction_criteria
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{ProjectionConfig, StochasticPva};
+
+    #[test]
+    fn stage_groups_and_missing_lifestages() {
+        let popvector = PopulationVector::new(vec![100.0, 1.0, 0.5]);
+        let breeders = ExtinctionCriterion::StageGroup {
+            lifestages: vec![1, 2],
+            threshold: 2.0,
+        };
+        assert!(breeders.is_met(&popvector));
+        let missing = ExtinctionCriterion::StageGroup {
+            lifestages: vec![1, 3],
+            threshold: 2.0,
+        };
+        assert!(!missing.is_met(&popvector));
+        let missing = ExtinctionCriterion::Stage {
+            lifestage: 3,
+            threshold: 2.0,
+        };
+        assert!(!missing.is_met(&popvector));
+        assert!(any_met(&[missing.clone(), breeders.clone()], &popvector));
+        assert!(breeders.check(3).is_ok());
+        assert!(missing.check(3).is_err());
+        let empty = ExtinctionCriterion::StageGroup {
+            lifestages: Vec::new(),
+            threshold: 2.0,
+        };
+        assert!(empty.check(3).is_err());
+        assert!(ProjectionConfig::new().extinction_criterion(empty).is_err());
+    }
+    #[test]
+    fn summary_counts_stage_extinction() {
+        let population = StochasticPva::build_from_vectors(
+            vec![500.0, 10.0],
+            vec![
+                vec![vec![0.9, 0.0], vec![0.0, 0.5]],
+                vec![vec![0.95, 0.0], vec![0.0, 0.6]],
+            ],
+        )
+        .unwrap();
+        let adults = ExtinctionCriterion::Stage {
+            lifestage: 1,
+            threshold: 2.0,
+        };
+        let config = ProjectionConfig::new().steps(20).replicates(10).seed(3);
+        assert_eq!(
+            population
+                .summarize(&config)
+                .unwrap()
+                .extinction_probability(),
+            0.0
+        );
+        let summary = population
+            .summarize(&config.clone().extinction_criterion(adults.clone()).unwrap())
+            .unwrap();
+        assert_eq!(summary.extinction_probability(), 1.0);
+        let juveniles = ExtinctionCriterion::Stage {
+            lifestage: 2,
+            threshold: 2.0,
+        };
+        assert!(population
+            .summarize(&config.clone().extinction_criterion(juveniles).unwrap())
+            .is_err());
+        let output = population.stochastic_projection(20, 10, 3);
+        assert_eq!(
+            summary.extinction_steps(),
+            &output.extinction_steps_by(&[adults])
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! This module contains tools for reconstructing past abundance from stage-structured counts: projecting a Population Vector backwards with the inverse of the matrix, and fitting the population at an earlier step to a series of historical census counts. Back-projection amplifies errors in the counts at every step, so it reports how unstable the reconstruction is; fitting is the better choice when several censuses are available.
+use super::linear_algebra::{identity, invert, multiply, transpose};
+use super::{PopulationMatrix, PopulationVector};
+
+/// The condition number above which a matrix is considered too close to singular for its inverse
+/// to be trusted.
+pub const UNSTABLE_CONDITION_NUMBER: f64 = 1e8;
+/// The largest number of passes made over the lifestages when fitting an initial population.
+const MAX_FIT_ITERATIONS: usize = 100_000;
+/// The largest change in any lifestage between passes below which a fit is considered converged,
+/// relative to the largest observed count.
+const FIT_TOLERANCE: f64 = 1e-12;
+
+/// Return the 1-norm (the largest column sum of absolute values) of a square matrix.
+fn norm_1(matrix: &[Vec<f64>]) -> f64 {
+    (0..matrix.len())
+        .map(|column| matrix.iter().map(|row| row[column].abs()).sum::<f64>())
+        .fold(0.0, f64::max)
+}
+
+/// Return the product of a square matrix and a vector.
+fn apply(matrix: &[Vec<f64>], vector: &[f64]) -> Vec<f64> {
+    matrix
+        .iter()
+        .map(|row| row.iter().zip(vector).map(|(a, x)| a * x).sum())
+        .collect()
+}
+
+/// This struct holds the result of projecting a Population Vector backwards: the estimated
+/// population at each earlier step, and diagnostics of how far it can be trusted.
+///
+/// The error amplification at step `k` is the 1-norm of the `k`th power of the inverse matrix,
+/// an upper bound on how much an error in the current counts can grow by the time it reaches that
+/// step. Negative abundances mean the current stage structure could not have been produced by the
+/// matrix from any real population, usually because of counting error.
+pub struct BackProjection {
+    steps: Vec<PopulationVector>,
+    condition_number: f64,
+    error_amplification: Vec<f64>,
+    negative_steps: Vec<u32>,
+}
+impl BackProjection {
+    /// Return the estimated population at each earlier step, starting with the step before the
+    /// current population.
+    pub fn get_steps(&self) -> &Vec<PopulationVector> {
+        &self.steps
+    }
+    /// Return the condition number of the matrix in the 1-norm. Values above
+    /// [`UNSTABLE_CONDITION_NUMBER`] mean the inverse is dominated by rounding error.
+    pub fn get_condition_number(&self) -> f64 {
+        self.condition_number
+    }
+    /// Return the largest factor by which errors in the current counts can grow at each earlier
+    /// step, starting with the step before the current population.
+    pub fn get_error_amplification(&self) -> &Vec<f64> {
+        &self.error_amplification
+    }
+    /// Return the steps back (starting at 1) at which any lifestage has a negative abundance.
+    pub fn get_negative_steps(&self) -> &Vec<u32> {
+        &self.negative_steps
+    }
+    /// Return true if the back-projection should not be trusted: the matrix is nearly singular
+    /// or some step has negative abundances.
+    pub fn is_unstable(&self) -> bool {
+        self.condition_number > UNSTABLE_CONDITION_NUMBER || !self.negative_steps.is_empty()
+    }
+}
+
+/// This struct holds the result of fitting the population at step 0 to historical census counts:
+/// the fitted initial population, the trajectory it produces, and how well it matches the
+/// counts.
+pub struct TrajectoryFit {
+    trajectory: Vec<PopulationVector>,
+    residual_sum_of_squares: f64,
+    iterations: usize,
+    converged: bool,
+}
+impl TrajectoryFit {
+    /// Return the fitted population at step 0.
+    pub fn get_initial_population(&self) -> &PopulationVector {
+        &self.trajectory[0]
+    }
+    /// Return the fitted population at each step from 0 to the last census, including steps
+    /// without a census.
+    pub fn get_trajectory(&self) -> &Vec<PopulationVector> {
+        &self.trajectory
+    }
+    /// Return the sum over every census and lifestage of the squared difference between the
+    /// fitted and observed counts.
+    pub fn get_residual_sum_of_squares(&self) -> f64 {
+        self.residual_sum_of_squares
+    }
+    /// Return the number of passes over the lifestages the fit took.
+    pub fn get_iterations(&self) -> usize {
+        self.iterations
+    }
+    /// Return true if the fit converged before the iteration limit.
+    pub fn is_converged(&self) -> bool {
+        self.converged
+    }
+}
+
+impl PopulationMatrix {
+    /// Project a Population Vector `steps` steps backwards by repeatedly multiplying it by the
+    /// inverse of the matrix, reconstructing the populations that would have produced it.
+    ///
+    /// Check [`BackProjection::is_unstable`] before using the result: errors in the counts grow
+    /// with every step back, often so quickly that the reconstruction is meaningless after a few
+    /// steps. [`PopulationMatrix::fit_trajectory`] is more robust when earlier counts exist.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lengths of the vector and matrix do not match, or
+    /// the matrix cannot be inverted (for example, if some lifestage never survives or
+    /// reproduces).
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let past = PopulationVector::new(vec![40.0, 20.0]);
+    /// let current = matrix.project_vector(&matrix.project_vector(&past).unwrap()).unwrap();
+    /// let back = matrix.back_project(&current, 2).unwrap();
+    /// assert!(back.get_steps()[1].approx_eq(&past, 1e-9));
+    /// assert!(!back.is_unstable());
+    /// ```
+    pub fn back_project(
+        &self,
+        current: &PopulationVector,
+        steps: u32,
+    ) -> Result<BackProjection, &'static str> {
+        if current.get_lifestage_count() != self.get_lifestage_count() {
+            return Err("Population vector size does not match the matrix.");
+        }
+        let inverse = invert(&self.get_matrix()).ok_or(
+            "The matrix cannot be inverted, so the population cannot be projected backwards.",
+        )?;
+        let condition_number = norm_1(&self.get_matrix()) * norm_1(&inverse);
+        let scale = current.iter().map(|x| x.abs()).fold(0.0, f64::max);
+        let mut power = inverse.clone();
+        let mut vector = current.get_vector().clone();
+        let mut result = BackProjection {
+            steps: Vec::new(),
+            condition_number,
+            error_amplification: Vec::new(),
+            negative_steps: Vec::new(),
+        };
+        for step in 1..=steps {
+            if step > 1 {
+                power = multiply(&power, &inverse);
+            }
+            vector = apply(&inverse, &vector);
+            // Values that are only negative because of rounding error are not flagged.
+            if vector.iter().any(|x| *x < -1e-9 * scale) {
+                result.negative_steps.push(step);
+            }
+            result.error_amplification.push(norm_1(&power));
+            result.steps.push(PopulationVector::new(vector.clone()));
+        }
+        Ok(result)
+    }
+    /// Fit the population at step 0 to historical census counts by least squares: find the
+    /// non-negative Population Vector whose projection with this matrix is closest to the counts
+    /// at the steps they were taken. Each census is a step number and the counts of each
+    /// lifestage at that step. A census at step 0 is allowed, but step 0 can also be before the
+    /// first census, which hindcasts the population before counting began.
+    ///
+    /// Unlike [`PopulationMatrix::back_project`], this does not need the matrix to be invertible
+    /// and is not thrown off by counting error in a single census.
+    /// # Errors
+    /// Will return `Err<'static str>` if no censuses are given, any census has a different
+    /// number of lifestages than the matrix, any count is negative or not finite, or a step
+    /// appears twice.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// // Counts from steps 3, 4, and 6, with some counting error.
+    /// let census = vec![
+    ///     (3, PopulationVector::new(vec![61.0, 29.0])),
+    ///     (4, PopulationVector::new(vec![58.0, 55.0])),
+    ///     (6, PopulationVector::new(vec![121.0, 84.0])),
+    /// ];
+    /// let fit = matrix.fit_trajectory(&census).unwrap();
+    /// assert_eq!(fit.get_trajectory().len(), 7);
+    /// // The fitted trajectory follows the censuses and hindcasts a smaller population before them.
+    /// for (step, counts) in &census {
+    ///     let fitted = fit.get_trajectory()[*step as usize].total();
+    ///     assert!((fitted - counts.total()).abs() / counts.total() < 0.25);
+    /// }
+    /// assert!(fit.get_initial_population().total() < census[0].1.total());
+    /// ```
+    pub fn fit_trajectory(
+        &self,
+        census: &[(u32, PopulationVector)],
+    ) -> Result<TrajectoryFit, &'static str> {
+        if census.is_empty() {
+            return Err("At least one census is required.");
+        }
+        let size = self.get_lifestage_count() as usize;
+        let mut census = census.to_vec();
+        census.sort_by_key(|(step, _)| *step);
+        if census.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err("Each step can only have one census.");
+        }
+        for (_, counts) in &census {
+            if counts.get_lifestage_count() as usize != size {
+                return Err("Population vector size does not match the matrix.");
+            }
+            if counts.iter().any(|x| *x < 0.0 || !x.is_finite()) {
+                return Err("Census counts must be finite and non-negative.");
+            }
+        }
+        // Build the normal equations: minimise |A^t n - c_t|^2 summed over censuses, which is
+        // n'Hn - 2n'g plus a constant with H = sum (A^t)'A^t and g = sum (A^t)'c_t.
+        let last_step = census.last().map_or(0, |(step, _)| *step);
+        let mut hessian = vec![vec![0.0; size]; size];
+        let mut gradient = vec![0.0; size];
+        let mut power = identity(size);
+        let rows = self.get_matrix();
+        let mut census_iter = census.iter().peekable();
+        for step in 0..=last_step {
+            if step > 0 {
+                power = multiply(&rows, &power);
+            }
+            if let Some((_, counts)) = census_iter.next_if(|(x, _)| *x == step) {
+                let transposed = transpose(&power);
+                let product = multiply(&transposed, &power);
+                let projected = apply(&transposed, counts.get_vector());
+                for row in 0..size {
+                    gradient[row] += projected[row];
+                    for column in 0..size {
+                        hessian[row][column] += product[row][column];
+                    }
+                }
+            }
+        }
+        // Minimise by projected coordinate descent, which keeps every lifestage non-negative and
+        // converges for this convex problem even when H is singular.
+        let scale = census
+            .iter()
+            .flat_map(|(_, counts)| counts.iter())
+            .fold(1.0_f64, |x, y| x.max(*y));
+        let mut initial = vec![0.0; size];
+        let mut iterations = 0;
+        let mut converged = false;
+        while iterations < MAX_FIT_ITERATIONS {
+            iterations += 1;
+            let mut largest_change: f64 = 0.0;
+            for stage in 0..size {
+                if hessian[stage][stage] <= 0.0 {
+                    continue;
+                }
+                let slope: f64 = hessian[stage]
+                    .iter()
+                    .zip(&initial)
+                    .map(|(h, x)| h * x)
+                    .sum::<f64>()
+                    - gradient[stage];
+                let value = (initial[stage] - slope / hessian[stage][stage]).max(0.0);
+                largest_change = largest_change.max((value - initial[stage]).abs());
+                initial[stage] = value;
+            }
+            if largest_change <= FIT_TOLERANCE * scale {
+                converged = true;
+                break;
+            }
+        }
+        let mut trajectory = vec![PopulationVector::new(initial)];
+        for step in 1..=last_step as usize {
+            trajectory.push(self.project_vector(&trajectory[step - 1])?);
+        }
+        let residual_sum_of_squares = census
+            .iter()
+            .map(|(step, counts)| {
+                trajectory[*step as usize]
+                    .iter()
+                    .zip(counts.iter())
+                    .map(|(fitted, observed)| (fitted - observed).powi(2))
+                    .sum::<f64>()
+            })
+            .sum();
+        Ok(TrajectoryFit {
+            trajectory,
+            residual_sum_of_squares,
+            iterations,
+            converged,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    #[test]
+    fn back_projection_reverses_projection() {
+        let past = PopulationVector::new(vec![40.0, 20.0]);
+        let mut current = past.clone();
+        for _ in 0..5 {
+            current = matrix().project_vector(&current).unwrap();
+        }
+        let back = matrix().back_project(&current, 5).unwrap();
+        assert_eq!(back.get_steps().len(), 5);
+        assert!(back.get_steps()[4].approx_eq(&past, 1e-9));
+        assert!(back.get_negative_steps().is_empty());
+        // The inverse has a 1-norm of 2. The matrix has an eigenvalue below one in magnitude,
+        // so errors grow as the projection goes further back.
+        let amplification = back.get_error_amplification();
+        assert!((amplification[0] - 2.0).abs() < 1e-9);
+        assert!(amplification[4] > amplification[0]);
+    }
+
+    #[test]
+    fn inconsistent_stage_structures_are_flagged() {
+        // Juveniles without adults cannot come from any real population under this matrix.
+        let back = matrix()
+            .back_project(&PopulationVector::new(vec![100.0, 0.0]), 3)
+            .unwrap();
+        assert!(back.is_unstable());
+        assert_eq!(back.get_negative_steps()[0], 1);
+    }
+
+    #[test]
+    fn singular_matrices_cannot_be_back_projected() {
+        let leslie = PopulationMatrix::build(vec![vec![0.5, 1.0], vec![0.0, 0.0]]).unwrap();
+        assert!(leslie
+            .back_project(&PopulationVector::new(vec![10.0, 0.0]), 1)
+            .is_err());
+        assert!(matrix()
+            .back_project(&PopulationVector::new(vec![10.0]), 1)
+            .is_err());
+    }
+
+    #[test]
+    fn fitting_recovers_an_exact_trajectory() {
+        let initial = PopulationVector::new(vec![40.0, 20.0]);
+        let mut census = Vec::new();
+        let mut current = initial.clone();
+        for step in 1..=6 {
+            current = matrix().project_vector(&current).unwrap();
+            if step >= 3 {
+                census.push((step, current.clone()));
+            }
+        }
+        let fit = matrix().fit_trajectory(&census).unwrap();
+        assert!(fit.is_converged());
+        assert!(fit.get_initial_population().approx_eq(&initial, 1e-6));
+        assert!(fit.get_residual_sum_of_squares() < 1e-9);
+        assert_eq!(fit.get_trajectory().len(), 7);
+    }
+
+    #[test]
+    fn fitting_keeps_populations_non_negative() {
+        // A single census that back-projection says is impossible is fitted with no juveniles
+        // at step 0 instead of a negative number.
+        let fit = matrix()
+            .fit_trajectory(&[(1, PopulationVector::new(vec![100.0, 0.0]))])
+            .unwrap();
+        assert!(fit.get_initial_population().iter().all(|x| *x >= 0.0));
+        assert!(fit.get_residual_sum_of_squares() > 0.0);
+        // A singular matrix can still be fitted.
+        let leslie = PopulationMatrix::build(vec![vec![0.5, 1.0], vec![0.0, 0.0]]).unwrap();
+        assert!(leslie
+            .fit_trajectory(&[(0, PopulationVector::new(vec![10.0, 2.0]))])
+            .unwrap()
+            .is_converged());
+    }
+
+    #[test]
+    fn invalid_censuses_are_rejected() {
+        let counts = PopulationVector::new(vec![1.0, 1.0]);
+        assert!(matrix().fit_trajectory(&[]).is_err());
+        assert!(matrix()
+            .fit_trajectory(&[(1, counts.clone()), (1, counts.clone())])
+            .is_err());
+        assert!(matrix()
+            .fit_trajectory(&[(1, PopulationVector::new(vec![-1.0, 1.0]))])
+            .is_err());
+        assert!(matrix()
+            .fit_trajectory(&[(1, PopulationVector::new(vec![1.0]))])
+            .is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! This module contains projections of several initial Population Vectors against the same model in one call, such as the low, best, and high estimates of current abundance from a census. Each initial population gets its own output, and the outputs are returned together in the order the initial populations were given.
+use super::{
+    DeterministicPva, PopulationVector, PvaDeterministicOutput, PvaStochasticOutput,
+    PvaStochasticSummary, StochasticPva,
+};
+
+/// This struct holds the outputs of projecting several initial Population Vectors with the same
+/// model: one output (of any type) per initial population, in the order they were given. Use
+/// [`RunRegistry::add_initial_populations`](crate::experiments::RunRegistry::add_initial_populations)
+/// to compare them.
+pub struct InitialPopulationOutputs<T> {
+    initial_populations: Vec<PopulationVector>,
+    outputs: Vec<T>,
+}
+impl<T> InitialPopulationOutputs<T> {
+    /// Return the initial Population Vectors that were projected.
+    pub fn get_initial_populations(&self) -> &Vec<PopulationVector> {
+        &self.initial_populations
+    }
+    /// Return the output for each initial population.
+    pub fn get_outputs(&self) -> &Vec<T> {
+        &self.outputs
+    }
+    /// Return the output for the initial population at `index`, or `None` if there is none.
+    pub fn get_output(&self, index: usize) -> Option<&T> {
+        self.outputs.get(index)
+    }
+    /// Return the number of initial populations that were projected.
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+    /// Return true if no initial populations were projected.
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+    /// Return an iterator over each initial population and its output.
+    pub fn iter(&self) -> impl Iterator<Item = (&PopulationVector, &T)> {
+        self.initial_populations.iter().zip(&self.outputs)
+    }
+}
+
+/// Check that there is at least one initial population, then build a copy of the model starting
+/// from each of them with `build`.
+fn models_for<M>(
+    initial_populations: &[PopulationVector],
+    build: impl Fn(PopulationVector) -> Result<M, &'static str>,
+) -> Result<Vec<M>, &'static str> {
+    if initial_populations.is_empty() {
+        return Err("At least one initial population is required.");
+    }
+    initial_populations.iter().cloned().map(build).collect()
+}
+
+impl DeterministicPva {
+    /// Project each of several initial Population Vectors with the model's matrix and density
+    /// dependence for the given number of steps, as [`DeterministicPva::deterministic_projection`]
+    /// does for the model's own initial population (which is not projected here). Every step
+    /// projects all of the populations together with [`PopulationMatrix::project_vectors`](super::PopulationMatrix::project_vectors).
+    /// # Errors
+    /// Will return `Err<'static str>` if no initial populations are given or any of them does not
+    /// match the length of the matrix.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    /// use ecolysis_cmd::PopulationVector;
+    /// let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///     vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// // The low, best, and high estimates of current abundance.
+    /// let outputs = population.deterministic_projections(vec![
+    ///     PopulationVector::new(vec![40.0, 8.0]),
+    ///     PopulationVector::new(vec![60.0, 12.0]),
+    ///     PopulationVector::new(vec![80.0, 16.0]),
+    /// ], 20).unwrap();
+    /// let final_totals: Vec<f64> = outputs
+    ///     .iter()
+    ///     .map(|(_, output)| *output.total_population().last().unwrap())
+    ///     .collect();
+    /// assert!(final_totals[0] < final_totals[1] && final_totals[1] < final_totals[2]);
+    /// ```
+    pub fn deterministic_projections(
+        &self,
+        initial_populations: Vec<PopulationVector>,
+        iterations: u32,
+    ) -> Result<InitialPopulationOutputs<PvaDeterministicOutput>, &'static str> {
+        if initial_populations.is_empty() {
+            return Err("At least one initial population is required.");
+        }
+        let core = self.get_core();
+        let lifestage_count = core.get_initial_population().get_lifestage_count();
+        if initial_populations
+            .iter()
+            .any(|x| x.get_lifestage_count() != lifestage_count)
+        {
+            return Err("Population vector size does not match matrices.");
+        }
+        let mut trajectories =
+            vec![Vec::with_capacity(iterations as usize); initial_populations.len()];
+        let mut current = initial_populations.clone();
+        for step in 0..iterations {
+            current = core.project_all(0, step, &current)?;
+            for (trajectory, popvector) in trajectories.iter_mut().zip(&current) {
+                trajectory.push(popvector.clone());
+            }
+        }
+        let outputs = trajectories
+            .into_iter()
+            .map(|x| PvaDeterministicOutput::new(x).with_model_time_step(self.get_time_step()))
+            .collect();
+        Ok(InitialPopulationOutputs {
+            initial_populations,
+            outputs,
+        })
+    }
+}
+
+impl StochasticPva {
+    /// Run [`StochasticPva::stochastic_projection`] from each of several initial Population
+    /// Vectors. Every initial population uses the same seed, so replicate `i` draws the same
+    /// sequence of matrices for each of them and differences between the outputs come only from
+    /// the initial populations.
+    /// # Errors
+    /// Will return `Err<'static str>` if no initial populations are given or any of them does not
+    /// match the length of the matrices.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+    /// use ecolysis_cmd::PopulationVector;
+    /// let population = StochasticPva::build_from_vectors(vec![10.0, 10.0], vec![
+    ///     vec![vec![0.0, 1.5], vec![0.4, 0.7]],
+    ///     vec![vec![0.0, 0.5], vec![0.2, 0.6]],
+    /// ]).unwrap();
+    /// let outputs = population.stochastic_projections(vec![
+    ///     PopulationVector::new(vec![5.0, 2.0]),
+    ///     PopulationVector::new(vec![50.0, 20.0]),
+    /// ], 30, 200, 42).unwrap();
+    /// let small = outputs.get_output(0).unwrap().extinction_probability(3.0);
+    /// let large = outputs.get_output(1).unwrap().extinction_probability(3.0);
+    /// assert!(small >= large);
+    /// ```
+    pub fn stochastic_projections(
+        &self,
+        initial_populations: Vec<PopulationVector>,
+        iterations: u32,
+        replicates: u32,
+        seed: u64,
+    ) -> Result<InitialPopulationOutputs<PvaStochasticOutput>, &'static str> {
+        let outputs = models_for(&initial_populations, |x| self.with_initial_population(x))?
+            .into_iter()
+            .map(|model| model.stochastic_projection(iterations, replicates, seed))
+            .collect();
+        Ok(InitialPopulationOutputs {
+            initial_populations,
+            outputs,
+        })
+    }
+    /// Run [`StochasticPva::stochastic_summary`] from each of several initial Population
+    /// Vectors, using the same seed for each as [`StochasticPva::stochastic_projections`] does.
+    /// # Errors
+    /// Will return `Err<'static str>` if no initial populations are given or any of them does not
+    /// match the length of the matrices.
+    pub fn stochastic_summaries(
+        &self,
+        initial_populations: Vec<PopulationVector>,
+        iterations: u32,
+        replicates: u32,
+        seed: u64,
+        extinction_threshold: f64,
+    ) -> Result<InitialPopulationOutputs<PvaStochasticSummary>, &'static str> {
+        let outputs = models_for(&initial_populations, |x| self.with_initial_population(x))?
+            .into_iter()
+            .map(|model| {
+                model.stochastic_summary(iterations, replicates, seed, extinction_threshold)
+            })
+            .collect();
+        Ok(InitialPopulationOutputs {
+            initial_populations,
+            outputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DensityDependence, PopulationMatrix};
+
+    fn matrix() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    #[test]
+    fn each_initial_population_matches_a_separate_model() {
+        let model = DeterministicPva::build(PopulationVector::new(vec![1.0, 1.0]), matrix())
+            .unwrap()
+            .with_density_dependence(DensityDependence::Ceiling(100.0));
+        let initial_populations = vec![
+            PopulationVector::new(vec![10.0, 0.0]),
+            PopulationVector::new(vec![30.0, 5.0]),
+        ];
+        let outputs = model
+            .deterministic_projections(initial_populations.clone(), 25)
+            .unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs.get_initial_populations(), &initial_populations);
+        for (popvector, output) in outputs.iter() {
+            let separate = DeterministicPva::build(popvector.clone(), matrix())
+                .unwrap()
+                .with_density_dependence(DensityDependence::Ceiling(100.0))
+                .deterministic_projection(25);
+            assert_eq!(output.return_typed_output(), separate.return_typed_output());
+        }
+    }
+
+    #[test]
+    fn large_matrices_project_in_blocks() {
+        // Ten lifestages take the blocked path of `project_vectors`.
+        let rows: Vec<Vec<f64>> = (0..10)
+            .map(|row| {
+                (0..10)
+                    .map(|column| ((row * 7 + column * 3) % 11) as f64 / 20.0)
+                    .collect()
+            })
+            .collect();
+        let matrix = PopulationMatrix::build(rows).unwrap();
+        let model = DeterministicPva::build(PopulationVector::new(vec![1.0; 10]), matrix.clone())
+            .unwrap()
+            .with_density_dependence(DensityDependence::Ceiling(1e6));
+        let initial_populations: Vec<PopulationVector> = (0..19)
+            .map(|x| PopulationVector::new((0..10).map(|y| (x * y % 13) as f64).collect()))
+            .collect();
+        let outputs = model
+            .deterministic_projections(initial_populations, 30)
+            .unwrap();
+        for (popvector, output) in outputs.iter() {
+            let separate = model
+                .with_initial_population(popvector.clone())
+                .unwrap()
+                .deterministic_projection(30);
+            assert_eq!(output.return_typed_output(), separate.return_typed_output());
+        }
+    }
+
+    #[test]
+    fn stochastic_groups_share_a_seed() {
+        let model = StochasticPva::build(
+            PopulationVector::new(vec![1.0, 1.0]),
+            vec![
+                matrix(),
+                PopulationMatrix::build(vec![vec![0.0, 0.5], vec![0.2, 0.6]]).unwrap(),
+            ],
+        )
+        .unwrap();
+        let initial_populations = vec![
+            PopulationVector::new(vec![10.0, 10.0]),
+            PopulationVector::new(vec![20.0, 20.0]),
+        ];
+        let outputs = model
+            .stochastic_projections(initial_populations.clone(), 10, 4, 7)
+            .unwrap();
+        // Without density dependence the model is linear, so doubling the initial population
+        // doubles every replicate when the same matrices are drawn.
+        let totals: Vec<Vec<Vec<f64>>> = outputs
+            .get_outputs()
+            .iter()
+            .map(PvaStochasticOutput::total_population)
+            .collect();
+        for (single, double) in totals[0].iter().flatten().zip(totals[1].iter().flatten()) {
+            assert!((single * 2.0 - double).abs() < 1e-9);
+        }
+        let summaries = model
+            .stochastic_summaries(initial_populations, 10, 4, 7, 1.0)
+            .unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries.get_output(1).unwrap().step_count(), 10);
+    }
+
+    #[test]
+    fn invalid_initial_populations_are_rejected() {
+        let model =
+            DeterministicPva::build(PopulationVector::new(vec![1.0, 1.0]), matrix()).unwrap();
+        assert!(model.deterministic_projections(Vec::new(), 5).is_err());
+        assert!(model
+            .deterministic_projections(vec![PopulationVector::new(vec![1.0, 2.0, 3.0])], 5)
+            .is_err());
+        assert!(model
+            .with_initial_population(PopulationVector::new(vec![1.0]))
+            .is_err());
+    }
+}
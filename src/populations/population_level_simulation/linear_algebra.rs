@@ -0,0 +1,156 @@
+//! Small dense linear algebra routines used by the matrix analyses in this module. Matrices are
+//! square and stored as row vectors, like the values inside a Population Matrix.
+//!
+//! With the `nalgebra` feature, inversion, multiplication, and eigen-analysis use nalgebra
+//! instead of the lightweight routines here.
+#[cfg(feature = "nalgebra")]
+mod nalgebra_backend;
+#[cfg(feature = "nalgebra")]
+pub(crate) use nalgebra_backend::{dominant_eigen, invert, multiply};
+
+/// The largest number of iterations used when searching for a dominant eigenvector.
+const MAX_ITERATIONS: usize = 100_000;
+/// The change between iterations below which an eigenvector is considered converged.
+const TOLERANCE: f64 = 1e-13;
+
+/// Return the inverse of a square matrix using Gauss-Jordan elimination with partial pivoting,
+/// or `None` if the matrix is singular.
+#[cfg(not(feature = "nalgebra"))]
+pub(crate) fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let size = matrix.len();
+    let mut left = matrix.to_vec();
+    let mut right = identity(size);
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| left[a][column].abs().total_cmp(&left[b][column].abs()))?;
+        if left[pivot][column].abs() < 1e-14 {
+            return None;
+        }
+        left.swap(column, pivot);
+        right.swap(column, pivot);
+        let divisor = left[column][column];
+        for k in 0..size {
+            left[column][k] /= divisor;
+            right[column][k] /= divisor;
+        }
+        for row in 0..size {
+            if row != column {
+                let factor = left[row][column];
+                if factor != 0.0 {
+                    for k in 0..size {
+                        left[row][k] -= factor * left[column][k];
+                        right[row][k] -= factor * right[column][k];
+                    }
+                }
+            }
+        }
+    }
+    Some(right)
+}
+
+/// Return an identity matrix of the given size.
+pub(crate) fn identity(size: usize) -> Vec<Vec<f64>> {
+    (0..size)
+        .map(|row| (0..size).map(|column| f64::from(row == column)).collect())
+        .collect()
+}
+
+/// Return the product of two square matrices.
+#[cfg(not(feature = "nalgebra"))]
+pub(crate) fn multiply(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let size = a.len();
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|column| (0..size).map(|k| a[row][k] * b[k][column]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+/// Return the transpose of a square matrix.
+pub(crate) fn transpose(matrix: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let size = matrix.len();
+    (0..size)
+        .map(|row| (0..size).map(|column| matrix[column][row]).collect())
+        .collect()
+}
+
+/// Return the dominant eigenvalue of a non-negative matrix and its right eigenvector, scaled to
+/// sum to one. Power iteration is performed on `matrix + I`, which has the same eigenvectors but
+/// whose dominant eigenvalue is strictly the largest in magnitude, so the iteration also converges
+/// for imprimitive (periodic) matrices such as Leslie matrices with one reproductive age class.
+#[cfg(not(feature = "nalgebra"))]
+pub(crate) fn dominant_eigen(matrix: &[Vec<f64>]) -> (f64, Vec<f64>) {
+    power_iteration(matrix)
+}
+
+/// Return the dominant eigenvalue and right eigenvector of a non-negative matrix by power
+/// iteration on `matrix + I` (see [`dominant_eigen`]).
+pub(crate) fn power_iteration(matrix: &[Vec<f64>]) -> (f64, Vec<f64>) {
+    let size = matrix.len();
+    let mut vector = vec![1.0 / size as f64; size];
+    let mut eigenvalue = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let mut next: Vec<f64> = (0..size)
+            .map(|row| {
+                vector[row]
+                    + matrix[row]
+                        .iter()
+                        .zip(&vector)
+                        .map(|(a, x)| a * x)
+                        .sum::<f64>()
+            })
+            .collect();
+        let total: f64 = next.iter().sum();
+        if total == 0.0 || !total.is_finite() {
+            return (0.0, vector);
+        }
+        for x in &mut next {
+            *x /= total;
+        }
+        eigenvalue = total - 1.0;
+        let change: f64 = next.iter().zip(&vector).map(|(a, b)| (a - b).abs()).sum();
+        vector = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    (eigenvalue, vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_of_known_matrix() {
+        let inverse = invert(&[vec![1.0, 0.0], vec![-0.5, 0.2]]).unwrap();
+        for (row, expected) in inverse.iter().zip([[1.0, 0.0], [2.5, 5.0]]) {
+            for (x, y) in row.iter().zip(expected) {
+                assert!((x - y).abs() < 1e-12);
+            }
+        }
+        assert!(invert(&[vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+    #[test]
+    fn dominant_eigen_of_imprimitive_matrix() {
+        let (eigenvalue, vector) = dominant_eigen(&[vec![0.0, 2.0], vec![0.5, 0.0]]);
+        assert!((eigenvalue - 1.0).abs() < 1e-9);
+        assert!((vector[0] - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dominant_eigen_of_nilpotent_matrix() {
+        let survival_only = [
+            vec![0.0, 0.0, 0.0],
+            vec![0.5, 0.0, 0.0],
+            vec![0.0, 0.5, 0.0],
+        ];
+        assert!(dominant_eigen(&survival_only).0.abs() < 1e-3);
+        assert_eq!(
+            dominant_eigen(&[vec![0.0; 3], vec![0.0; 3], vec![0.0; 3]]).0,
+            0.0
+        );
+    }
+}
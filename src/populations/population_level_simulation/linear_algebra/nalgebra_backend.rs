@@ -0,0 +1,123 @@
+//! Versions of the linear algebra routines backed by nalgebra, used in place of the lightweight
+//! ones when the `nalgebra` feature is enabled. nalgebra's LU and Schur decompositions are faster
+//! and more robust for large matrices, such as those produced by integral projection models.
+use super::power_iteration;
+use crate::populations::population_level_simulation::PopulationMatrix;
+use nalgebra::{DMatrix, DVector, Schur};
+
+/// The number of inverse iteration steps used to refine an eigenvector.
+const REFINEMENT_STEPS: usize = 3;
+/// The largest number of iterations of the Schur decomposition before falling back to power
+/// iteration.
+const SCHUR_MAX_ITERATIONS: usize = 10_000;
+
+fn to_dmatrix(matrix: &[Vec<f64>]) -> DMatrix<f64> {
+    let size = matrix.len();
+    DMatrix::from_fn(size, size, |row, column| matrix[row][column])
+}
+
+fn from_dmatrix(matrix: &DMatrix<f64>) -> Vec<Vec<f64>> {
+    matrix
+        .row_iter()
+        .map(|row| row.iter().copied().collect())
+        .collect()
+}
+
+/// Return the inverse of a square matrix using LU decomposition, or `None` if the matrix is
+/// singular.
+pub(crate) fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    to_dmatrix(matrix)
+        .try_inverse()
+        .map(|inverse| from_dmatrix(&inverse))
+}
+
+/// Return the product of two square matrices.
+pub(crate) fn multiply(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    from_dmatrix(&(to_dmatrix(a) * to_dmatrix(b)))
+}
+
+/// Return the dominant eigenvalue of a non-negative matrix and its right eigenvector, scaled to
+/// sum to one. The eigenvalue is the largest real part of the eigenvalues found by Schur
+/// decomposition, which for a non-negative matrix is the spectral radius. The eigenvector is then
+/// found by inverse iteration with a shift just above the eigenvalue.
+pub(crate) fn dominant_eigen(matrix: &[Vec<f64>]) -> (f64, Vec<f64>) {
+    let size = matrix.len();
+    let uniform = vec![1.0 / size as f64; size];
+    let a = to_dmatrix(matrix);
+    // The Schur decomposition can fail to converge for nilpotent matrices (such as a Leslie
+    // matrix without fecundity), so power iteration is used when it does.
+    let Some(schur) = Schur::try_new(a.clone(), f64::EPSILON, SCHUR_MAX_ITERATIONS) else {
+        return power_iteration(matrix);
+    };
+    let eigenvalue = schur
+        .complex_eigenvalues()
+        .iter()
+        .map(|x| x.re)
+        .fold(0.0, f64::max);
+    if eigenvalue <= 0.0 || !eigenvalue.is_finite() {
+        return (0.0, uniform);
+    }
+    let shift = eigenvalue * (1.0 + 1e-10);
+    let lu = (a - DMatrix::identity(size, size) * shift).lu();
+    let mut vector = DVector::from_vec(uniform);
+    for _ in 0..REFINEMENT_STEPS {
+        let Some(next) = lu.solve(&vector) else {
+            break;
+        };
+        let total = next.sum();
+        if total == 0.0 || !total.is_finite() {
+            break;
+        }
+        vector = next / total;
+    }
+    (eigenvalue, vector.iter().copied().collect())
+}
+
+/// Convert a Population Matrix to an nalgebra matrix, for analyses this crate does not provide.
+impl From<&PopulationMatrix> for DMatrix<f64> {
+    fn from(popmatrix: &PopulationMatrix) -> DMatrix<f64> {
+        to_dmatrix(&popmatrix.get_matrix())
+    }
+}
+/// Convert an nalgebra matrix to a Population Matrix. This fails if the matrix is not square or is
+/// larger than a Population Matrix can be.
+impl TryFrom<DMatrix<f64>> for PopulationMatrix {
+    type Error = &'static str;
+    fn try_from(matrix: DMatrix<f64>) -> Result<PopulationMatrix, &'static str> {
+        PopulationMatrix::build(from_dmatrix(&matrix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leslie_matrix(size: usize) -> Vec<Vec<f64>> {
+        let mut matrix = vec![vec![0.0; size]; size];
+        for (column, fecundity) in matrix[0].iter_mut().enumerate().skip(size / 2) {
+            *fecundity = column as f64 / size as f64;
+        }
+        for row in 1..size {
+            matrix[row][row - 1] = 0.9;
+        }
+        matrix
+    }
+
+    #[test]
+    fn large_eigenvector_satisfies_eigen_equation() {
+        let matrix = leslie_matrix(40);
+        let (eigenvalue, vector) = dominant_eigen(&matrix);
+        for (row, w) in matrix.iter().zip(&vector) {
+            let projected: f64 = row.iter().zip(&vector).map(|(a, x)| a * x).sum();
+            assert!((projected - eigenvalue * w).abs() < 1e-9);
+        }
+    }
+    #[test]
+    fn population_matrix_round_trip() {
+        let popmatrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.3]]).unwrap();
+        let matrix = DMatrix::from(&popmatrix);
+        assert_eq!(matrix[(0, 1)], 2.0);
+        let back = PopulationMatrix::try_from(matrix).unwrap();
+        assert_eq!(back.get_matrix(), popmatrix.get_matrix());
+    }
+}
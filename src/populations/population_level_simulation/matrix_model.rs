@@ -0,0 +1,363 @@
+//! This module contains the parts shared by every matrix population model: the initial population, the Population Matrices, the density dependence applied after each step, and the time step.
+use super::{Dormancy, PopulationMatrix, PopulationVector, Summation, TimeStep};
+use std::borrow::Cow;
+
+/// This enum describes how population growth is limited by the size of the population.
+/// - `None`: the population grows (or declines) according to the matrices alone.
+/// - `Ceiling`: after each step, if the total population is above the given carrying capacity,
+///   every lifestage is scaled down proportionally so the total equals the ceiling.
+/// - `Allee`: reproduction is less successful in small populations, for example because
+///   individuals struggle to find mates. When the total population before a step is below
+///   `threshold`, the offspring produced by the reproduction entries of the matrix (see
+///   [`MatrixModelCore::set_reproduction_entries`]) are multiplied by the population divided by
+///   the threshold (see [`DensityDependence::allee_factor`]), while survival is unchanged.
+///   An optional `ceiling` is then applied as for `Ceiling`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DensityDependence {
+    #[default]
+    None,
+    Ceiling(f64),
+    Allee {
+        threshold: f64,
+        ceiling: Option<f64>,
+    },
+}
+impl DensityDependence {
+    /// Return the Population Vector after density dependence has been applied to it. Allee
+    /// effects depend on the offspring produced during the step, which are not known here, so
+    /// only the ceiling is applied; use [`DensityDependence::apply_step`] when they are.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{DensityDependence, PopulationVector};
+    /// let limited = DensityDependence::Ceiling(50.0).apply(PopulationVector::new(vec![60.0, 40.0]));
+    /// assert_eq!(limited.get_vector(), &vec![30.0, 20.0]);
+    /// ```
+    pub fn apply(&self, popvector: PopulationVector) -> PopulationVector {
+        let none = PopulationVector::new(vec![0.0; popvector.get_lifestage_count() as usize]);
+        self.apply_step(&popvector.clone(), popvector, &none)
+    }
+    /// Return the projected Population Vector after density dependence has been applied to it,
+    /// given the population before the step and the offspring in each lifestage produced by
+    /// reproduction during the step (which are part of the projected population).
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{DensityDependence, PopulationVector};
+    /// let allee = DensityDependence::Allee { threshold: 40.0, ceiling: None };
+    /// let previous = PopulationVector::new(vec![0.0, 10.0]);
+    /// let offspring = PopulationVector::new(vec![16.0, 0.0]);
+    /// let limited = allee.apply_step(&previous, PopulationVector::new(vec![20.0, 8.0]), &offspring);
+    /// assert_eq!(limited.get_vector(), &vec![8.0, 8.0]);
+    /// ```
+    pub fn apply_step(
+        &self,
+        previous: &PopulationVector,
+        projected: PopulationVector,
+        offspring: &PopulationVector,
+    ) -> PopulationVector {
+        match *self {
+            DensityDependence::None => projected,
+            DensityDependence::Ceiling(ceiling) => apply_ceiling(ceiling, projected),
+            DensityDependence::Allee { ceiling, .. } => {
+                let lost = 1.0 - self.allee_factor(previous.total());
+                let reduced = PopulationVector::new(
+                    projected
+                        .iter()
+                        .zip(offspring.iter())
+                        .map(|(value, born)| value - lost * born)
+                        .collect(),
+                );
+                match ceiling {
+                    Some(ceiling) => apply_ceiling(ceiling, reduced),
+                    None => reduced,
+                }
+            }
+        }
+    }
+    /// Return the proportion of normal reproductive success achieved by a population of the
+    /// given size: `total / threshold` below an Allee threshold, and one otherwise (or if there is
+    /// no Allee effect). Individual-based models use this to scale mating success (see
+    /// [`crate::populations::individual_level_simulation::IndividualBasedModel::with_mate_limitation`]).
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::DensityDependence;
+    /// let allee = DensityDependence::Allee { threshold: 20.0, ceiling: None };
+    /// assert_eq!(allee.allee_factor(5.0), 0.25);
+    /// assert_eq!(allee.allee_factor(30.0), 1.0);
+    /// ```
+    pub fn allee_factor(&self, total: f64) -> f64 {
+        match *self {
+            DensityDependence::Allee { threshold, .. } if total < threshold => {
+                (total / threshold).max(0.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Scale every lifestage down proportionally if the total population is above the ceiling.
+fn apply_ceiling(ceiling: f64, popvector: PopulationVector) -> PopulationVector {
+    let total: f64 = popvector.iter().sum();
+    if total > ceiling {
+        &popvector * (ceiling / total)
+    } else {
+        popvector
+    }
+}
+
+/// The MatrixModelCore struct holds the data common to deterministic and stochastic matrix
+/// models:
+/// - A Population Vector representing the initial population size.
+/// - One or more Population Matrices, all with the same number of lifestages as the vector.
+/// - The density dependence applied after each step.
+/// - The entries of the matrices that are reproduction, which an Allee effect reduces.
+/// - The time step one projection step represents, if declared.
+/// - A dormant lifestage whose column of every matrix is replaced each step, if declared.
+///
+/// How the matrices add up a projection (see [`Summation`]) is stored on each matrix.
+#[derive(Clone)]
+pub struct MatrixModelCore {
+    initial_population: PopulationVector,
+    matrices: Vec<PopulationMatrix>,
+    density_dependence: DensityDependence,
+    reproduction_entries: Vec<(usize, usize)>,
+    time_step: Option<TimeStep>,
+    dormancy: Option<Dormancy>,
+}
+impl MatrixModelCore {
+    /// Return a Result enum containing a new MatrixModelCore with no density dependence.
+    /// # Errors
+    /// Will return `Err<'static str>` if no matrices are given or the lengths of the Population
+    /// Vector and any of the Matrices do not match.
+    pub fn build(
+        initial_population: PopulationVector,
+        matrices: Vec<PopulationMatrix>,
+    ) -> Result<MatrixModelCore, &'static str> {
+        if matrices.is_empty() {
+            return Err("At least one population matrix is required.");
+        }
+        let expected_lifestage_length = initial_population.get_lifestage_count();
+        if matrices
+            .iter()
+            .any(|matrix| matrix.get_lifestage_count() != expected_lifestage_length)
+        {
+            return Err("Population vector size does not match matrices.");
+        }
+        Ok(MatrixModelCore {
+            initial_population,
+            matrices,
+            density_dependence: DensityDependence::None,
+            reproduction_entries: (1..expected_lifestage_length as usize)
+                .map(|column| (0, column))
+                .collect(),
+            time_step: None,
+            dormancy: None,
+        })
+    }
+    /// Return the initial Population Vector.
+    pub fn get_initial_population(&self) -> &PopulationVector {
+        &self.initial_population
+    }
+    /// Return the Population Matrices of the model.
+    pub fn get_matrices(&self) -> &Vec<PopulationMatrix> {
+        &self.matrices
+    }
+    /// Return the density dependence of the model.
+    pub fn get_density_dependence(&self) -> DensityDependence {
+        self.density_dependence
+    }
+    /// Set the density dependence of the model.
+    pub fn set_density_dependence(&mut self, density_dependence: DensityDependence) {
+        self.density_dependence = density_dependence;
+    }
+    /// Return the `(row, column)` entries of the matrices that are reproduction.
+    pub fn get_reproduction_entries(&self) -> &[(usize, usize)] {
+        &self.reproduction_entries
+    }
+    /// Set the `(row, column)` entries of the matrices that are reproduction, counting from
+    /// zero. By default these are the first row apart from its first entry, which in
+    /// stage-classified models is individuals staying in the first lifestage; a Leslie matrix
+    /// whose first age class breeds should list `(0, 0)` as well.
+    /// # Errors
+    /// Will return `Err<'static str>` if any entry is outside the matrices.
+    pub fn set_reproduction_entries(
+        &mut self,
+        entries: Vec<(usize, usize)>,
+    ) -> Result<(), &'static str> {
+        let size = self.initial_population.get_lifestage_count() as usize;
+        if entries
+            .iter()
+            .any(|(row, column)| *row >= size || *column >= size)
+        {
+            return Err("A reproduction entry is outside the matrices.");
+        }
+        self.reproduction_entries = entries;
+        Ok(())
+    }
+    /// Return the time step of the model, or `None` if it was not declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Set the time step of the model.
+    pub fn set_time_step(&mut self, time_step: TimeStep) {
+        self.time_step = Some(time_step);
+    }
+    /// Return the dormant lifestage of the model, or `None` if it has none.
+    pub fn get_dormancy(&self) -> Option<&Dormancy> {
+        self.dormancy.as_ref()
+    }
+    /// Set the dormant lifestage of the model. Each step, its column of the matrix is replaced
+    /// by the dormancy for that step (see [`Dormancy::matrix_for`]); analytic results such as
+    /// the growth rate still use the matrices as given.
+    /// # Errors
+    /// Will return `Err<'static str>` if either lifestage of the dormancy is outside the
+    /// matrices.
+    pub fn set_dormancy(&mut self, dormancy: Dormancy) -> Result<(), &'static str> {
+        dormancy.matrix_for(&self.matrices[0], 0)?;
+        self.dormancy = Some(dormancy);
+        Ok(())
+    }
+    /// Set how every matrix of the model adds up the products of a projection.
+    pub fn set_summation(&mut self, summation: Summation) {
+        for matrix in self.matrices.iter_mut() {
+            matrix.summation = summation;
+        }
+    }
+    /// Return a copy of the model that starts from a different initial Population Vector.
+    /// # Errors
+    /// Will return `Err<'static str>` if the length of the Population Vector does not match the
+    /// matrices.
+    pub fn with_initial_population(
+        &self,
+        initial_population: PopulationVector,
+    ) -> Result<MatrixModelCore, &'static str> {
+        if initial_population.get_lifestage_count() != self.initial_population.get_lifestage_count()
+        {
+            return Err("Population vector size does not match matrices.");
+        }
+        Ok(MatrixModelCore {
+            initial_population,
+            ..self.clone()
+        })
+    }
+    /// Return the matrix at `matrix_index` as used during the step after `steps_taken` steps,
+    /// with the dormancy of that step if the model has one.
+    pub(super) fn matrix(
+        &self,
+        matrix_index: usize,
+        steps_taken: u32,
+    ) -> Cow<'_, PopulationMatrix> {
+        let matrix = &self.matrices[matrix_index];
+        match &self.dormancy {
+            None => Cow::Borrowed(matrix),
+            Some(dormancy) => Cow::Owned(dormancy.matrix_for(matrix, steps_taken).expect("This error should not be possible. The dormant lifestage was checked against the matrices. Please file a bug report.")),
+        }
+    }
+    /// Project a Population Vector one step using the matrix at `matrix_index` during the step
+    /// after `steps_taken` steps, then apply density dependence.
+    pub(crate) fn project(
+        &self,
+        matrix_index: usize,
+        steps_taken: u32,
+        popvector: &PopulationVector,
+    ) -> PopulationVector {
+        let matrix = self.matrix(matrix_index, steps_taken);
+        let projected = matrix.project_vector(popvector).expect("This error should not be possible. Mismatched Vector and Matrix lengths, or non-square Matrix. Please file a bug report.");
+        self.limit(&matrix, popvector, projected)
+    }
+    /// Apply density dependence to a Population Vector projected from `popvector` by `matrix`.
+    fn limit(
+        &self,
+        matrix: &PopulationMatrix,
+        popvector: &PopulationVector,
+        projected: PopulationVector,
+    ) -> PopulationVector {
+        let size = popvector.get_lifestage_count() as usize;
+        let mut offspring = vec![0.0; size];
+        if let DensityDependence::Allee { .. } = self.density_dependence {
+            let values = popvector.get_vector();
+            for &(row, column) in &self.reproduction_entries {
+                offspring[row] += matrix[(row, column)] * values[column];
+            }
+        }
+        self.density_dependence
+            .apply_step(popvector, projected, &PopulationVector::new(offspring))
+    }
+    /// Project several Population Vectors one step together using the matrix at
+    /// `matrix_index` during the step after `steps_taken` steps (see
+    /// [`PopulationMatrix::project_vectors`]), then apply density dependence to each.
+    pub(crate) fn project_all(
+        &self,
+        matrix_index: usize,
+        steps_taken: u32,
+        popvectors: &[PopulationVector],
+    ) -> Result<Vec<PopulationVector>, &'static str> {
+        let matrix = self.matrix(matrix_index, steps_taken);
+        let projected = matrix.project_vectors(popvectors)?;
+        Ok(popvectors
+            .iter()
+            .zip(projected)
+            .map(|(popvector, projected)| self.limit(&matrix, popvector, projected))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ceiling_only_limits_large_populations() {
+        let ceiling = DensityDependence::Ceiling(100.0);
+        assert_eq!(
+            ceiling
+                .apply(PopulationVector::new(vec![30.0, 20.0]))
+                .get_vector(),
+            &vec![30.0, 20.0]
+        );
+        let limited = ceiling.apply(PopulationVector::new(vec![150.0, 50.0]));
+        assert_eq!(limited.get_vector(), &vec![75.0, 25.0]);
+    }
+    #[test]
+    fn allee_effect_drives_small_populations_extinct() {
+        let matrix = PopulationMatrix::build(vec![vec![0.0, 1.2], vec![0.5, 0.8]]).unwrap();
+        let allee = DensityDependence::Allee {
+            threshold: 50.0,
+            ceiling: Some(500.0),
+        };
+        let project = |start: f64| {
+            let mut core = MatrixModelCore::build(
+                PopulationVector::new(vec![0.0, start]),
+                vec![matrix.clone()],
+            )
+            .unwrap();
+            core.set_density_dependence(allee);
+            let mut popvector = core.get_initial_population().clone();
+            for _ in 0..300 {
+                popvector = core.project(0, 0, &popvector);
+            }
+            popvector.total()
+        };
+        assert!(project(5.0) < 1e-3);
+        assert!((project(100.0) - 500.0).abs() < 1e-6);
+    }
+    #[test]
+    fn allee_effect_spares_survival() {
+        // Lifestage 0 keeps 40% of its members, and lifestage 1 produces 2 offspring each.
+        let matrix = PopulationMatrix::build(vec![vec![0.4, 2.0], vec![0.5, 0.8]]).unwrap();
+        let mut core =
+            MatrixModelCore::build(PopulationVector::new(vec![10.0, 10.0]), vec![matrix]).unwrap();
+        core.set_density_dependence(DensityDependence::Allee {
+            threshold: 80.0,
+            ceiling: None,
+        });
+        let projected = core.project(0, 0, core.get_initial_population());
+        assert_eq!(projected.get_vector(), &vec![4.0 + 20.0 / 4.0, 13.0]);
+        core.set_reproduction_entries(vec![(0, 0), (0, 1)]).unwrap();
+        let projected = core.project(0, 0, core.get_initial_population());
+        assert_eq!(projected.get_vector(), &vec![24.0 / 4.0, 13.0]);
+        assert!(core.set_reproduction_entries(vec![(0, 2)]).is_err());
+    }
+    #[test]
+    fn core_requires_matching_matrices() {
+        let popvector = PopulationVector::new(vec![1.0, 2.0]);
+        assert!(MatrixModelCore::build(popvector.clone(), vec![]).is_err());
+        assert!(MatrixModelCore::build(popvector, vec![PopulationMatrix::identity(3)]).is_err());
+    }
+}
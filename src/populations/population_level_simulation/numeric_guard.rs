@@ -0,0 +1,288 @@
+//! This module contains checked projections, which stop as soon as a lifestage becomes NaN, infinite, or negative instead of carrying the bad value through every remaining step. The error reports the step, the lifestage, and the matrix element that produced the value, so an overflowing growth rate or a corrupt input can be found without searching thousands of rows of output. Projections, records, and summaries configured by a ProjectionConfig make the same check and stop with `StopReason::InvalidValue`, keeping the fault.
+use super::projection_config::ReplicateRun;
+use super::{
+    DeterministicPva, MatrixModelCore, PopulationVector, PvaDeterministicOutput,
+    PvaStochasticOutput, StochasticPva,
+};
+use std::{error::Error, fmt};
+
+/// This struct describes the first invalid value reached by a checked projection:
+/// - `replicate`: the replicate it happened in, for stochastic projections.
+/// - `step`: the step that produced it (0 if the initial population was already invalid).
+/// - `lifestage`: the lifestage (starting from 0) whose value became invalid.
+/// - `value`: the invalid value.
+/// - `matrix`: the position in the model's list of the matrix used for the step.
+/// - `element`: the `(row, column)` of the matrix element responsible: an element that is itself
+///   invalid, or otherwise the largest contribution to an infinite value. `None` if the matrix
+///   is not at fault, for example when density dependence produced the value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumericFault {
+    replicate: Option<u32>,
+    step: u32,
+    lifestage: usize,
+    value: f64,
+    matrix: usize,
+    element: Option<(usize, usize)>,
+}
+impl NumericFault {
+    /// Return the replicate the value was reached in, or `None` for a deterministic projection.
+    pub fn get_replicate(&self) -> Option<u32> {
+        self.replicate
+    }
+    /// Return the step that produced the value.
+    pub fn get_step(&self) -> u32 {
+        self.step
+    }
+    /// Return the lifestage whose value became invalid.
+    pub fn get_lifestage(&self) -> usize {
+        self.lifestage
+    }
+    /// Return the invalid value.
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+    /// Return the position of the matrix used for the step.
+    pub fn get_matrix(&self) -> usize {
+        self.matrix
+    }
+    /// Return the `(row, column)` of the matrix element responsible, if any.
+    pub fn get_element(&self) -> Option<(usize, usize)> {
+        self.element
+    }
+}
+impl fmt::Display for NumericFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(replicate) = self.replicate {
+            write!(f, "Replicate {}, step {}: ", replicate, self.step)?;
+        } else {
+            write!(f, "Step {}: ", self.step)?;
+        }
+        write!(f, "lifestage {} became {}", self.lifestage, self.value)?;
+        match self.element {
+            Some((row, column)) => write!(
+                f,
+                " from element ({}, {}) of matrix {}.",
+                row, column, self.matrix
+            ),
+            None if self.step == 0 => write!(f, " in the initial population."),
+            None => write!(f, " after density dependence."),
+        }
+    }
+}
+impl Error for NumericFault {}
+
+/// Return true if a lifestage cannot hold the value.
+fn is_invalid(value: f64) -> bool {
+    !value.is_finite() || value < 0.0
+}
+
+impl MatrixModelCore {
+    /// Return the fault of step `step` of `replicate` if it projected `previous` to an invalid
+    /// `projected` with the matrix at `matrix_index`, as it was used for that step (after any
+    /// dormancy).
+    pub(super) fn check_step(
+        &self,
+        replicate: Option<u32>,
+        step: u32,
+        matrix_index: usize,
+        previous: &PopulationVector,
+        projected: &PopulationVector,
+    ) -> Result<(), NumericFault> {
+        let Some((lifestage, value)) = projected
+            .iter()
+            .enumerate()
+            .find(|(_, value)| is_invalid(**value))
+        else {
+            return Ok(());
+        };
+        let matrix = self.matrix(matrix_index, step.saturating_sub(1));
+        let element = matrix
+            .row(lifestage)
+            .and_then(|row| {
+                let contributions = row.iter().zip(previous.iter()).map(|(x, y)| x * y);
+                row.iter()
+                    .position(|x| is_invalid(*x))
+                    .or_else(|| contributions.clone().position(is_invalid))
+                    .or_else(|| {
+                        // The sum overflowed although every contribution is finite.
+                        value.is_infinite().then(|| {
+                            contributions
+                                .enumerate()
+                                .max_by(|a, b| a.1.total_cmp(&b.1))
+                                .map_or(0, |x| x.0)
+                        })
+                    })
+            })
+            .map(|column| (lifestage, column));
+        Err(NumericFault {
+            replicate,
+            step,
+            lifestage,
+            value: *value,
+            matrix: matrix_index,
+            element,
+        })
+    }
+    /// Return the fault of an initial population holding an invalid value, if any.
+    pub(super) fn check_initial(&self, replicate: Option<u32>) -> Result<(), NumericFault> {
+        match self
+            .get_initial_population()
+            .iter()
+            .enumerate()
+            .find(|(_, value)| is_invalid(**value))
+        {
+            Some((lifestage, value)) => Err(NumericFault {
+                replicate,
+                step: 0,
+                lifestage,
+                value: *value,
+                matrix: 0,
+                element: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DeterministicPva {
+    /// Return the output of a deterministic projection like
+    /// [`DeterministicPva::deterministic_projection`], but stop with an error at the first step
+    /// that makes any lifestage NaN, infinite, or negative.
+    /// # Errors
+    /// Will return a [`NumericFault`] describing the first invalid value.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::DeterministicPva;
+    /// let exploding = DeterministicPva::build_from_vectors(vec![1.0, 1.0],
+    ///     vec![vec![0.0, 1e200], vec![1e200, 0.0]]).unwrap();
+    /// let fault = exploding.checked_projection(100).err().unwrap();
+    /// assert_eq!(fault.get_step(), 2);
+    /// assert_eq!(fault.get_element(), Some((0, 1)));
+    /// ```
+    pub fn checked_projection(
+        &self,
+        iterations: u32,
+    ) -> Result<PvaDeterministicOutput, NumericFault> {
+        let mut result = Vec::with_capacity(iterations as usize);
+        ReplicateRun::new(self.get_core(), iterations).run(
+            None,
+            &mut self.projection_iter(),
+            |_| 0,
+            |popvector, _| result.push(popvector.clone()),
+        )?;
+        Ok(PvaDeterministicOutput::new(result).with_model_time_step(self.get_time_step()))
+    }
+}
+
+impl StochasticPva {
+    /// Return every step of every replicate like [`StochasticPva::stochastic_projection`], but
+    /// stop with an error at the first step of any replicate that makes a lifestage NaN,
+    /// infinite, or negative.
+    /// # Errors
+    /// Will return a [`NumericFault`] describing the first invalid value.
+    pub fn checked_projection(
+        &self,
+        iterations: u32,
+        replicates: u32,
+        seed: u64,
+    ) -> Result<PvaStochasticOutput, NumericFault> {
+        let run = ReplicateRun::new(self.get_core(), iterations);
+        let mut result = Vec::with_capacity(replicates as usize);
+        for replicate in 0..replicates {
+            let mut steps = Vec::with_capacity(iterations as usize);
+            run.run(
+                Some(replicate),
+                &mut self.replicate_iter(replicate, seed),
+                |x| x.get_last_matrix().unwrap_or(0),
+                |popvector, _| steps.push(popvector.clone()),
+            )?;
+            result.push(steps);
+        }
+        Ok(PvaStochasticOutput::new(result).with_model_time_step(self.get_time_step()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{
+        Dormancy, PopulationMatrix, ProjectionConfig, StopReason,
+    };
+
+    #[test]
+    fn valid_projections_match_unchecked_projections() {
+        let population = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.0], vec![0.3, 0.5]],
+            ],
+        )
+        .unwrap();
+        let checked = population.checked_projection(30, 4, 7).unwrap();
+        let unchecked = population.stochastic_projection(30, 4, 7);
+        assert_eq!(checked.get_replicates(), unchecked.get_replicates());
+    }
+    #[test]
+    fn faults_name_the_step_and_element() {
+        let mut matrix = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+        let valid = matrix.clone();
+        // Values set directly bypass the checks of `set_element`.
+        matrix.elements[2] = f64::NAN;
+        let population =
+            StochasticPva::build(PopulationVector::new(vec![10.0, 0.0]), vec![valid, matrix])
+                .unwrap();
+        let fault = population.checked_projection(50, 3, 1).err().unwrap();
+        assert_eq!(fault.get_replicate(), Some(0));
+        assert_eq!(fault.get_matrix(), 1);
+        assert_eq!(fault.get_lifestage(), 1);
+        assert_eq!(fault.get_element(), Some((1, 0)));
+        assert!(fault.get_value().is_nan());
+        assert!(fault
+            .to_string()
+            .contains("from element (1, 0) of matrix 1"));
+        let invalid = DeterministicPva::build_from_vectors(
+            vec![-1.0, 0.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let fault = invalid.checked_projection(5).err().unwrap();
+        assert_eq!((fault.get_step(), fault.get_element()), (0, None));
+        assert!(fault.to_string().ends_with("in the initial population."));
+    }
+    #[test]
+    fn faults_name_the_element_of_the_matrix_used() {
+        // Dormancy replaces the first column, so the overflow comes from the second.
+        let population = DeterministicPva::build_from_vectors(
+            vec![1e10, 1e10],
+            vec![vec![1e300, 1e300], vec![0.0, 0.0]],
+        )
+        .unwrap()
+        .with_dormancy(Dormancy::build(0, 1, 0.5, 0.5).unwrap())
+        .unwrap();
+        let fault = population.checked_projection(5).err().unwrap();
+        assert_eq!(fault.get_element(), Some((0, 1)));
+    }
+    #[test]
+    fn configured_projections_stop_at_invalid_values() {
+        let exploding = DeterministicPva::build_from_vectors(
+            vec![1.0, 1.0],
+            vec![vec![0.0, 1e200], vec![1e200, 0.0]],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new().steps(10);
+        let output = exploding.project(&config);
+        assert_eq!(output.get_stop_reason(), StopReason::InvalidValue);
+        assert_eq!(output.iter().count(), 1);
+        let record = exploding.record(&config);
+        assert_eq!(record.get_stop_reasons(), &vec![StopReason::InvalidValue]);
+        assert_eq!(record.get_faults()[0].get_step(), 2);
+        let stochastic = StochasticPva::build_from_vectors(
+            vec![1.0, 1.0],
+            vec![vec![vec![0.0, 1e200], vec![1e200, 0.0]]],
+        )
+        .unwrap();
+        let summary = stochastic.summarize(&config.replicates(3)).unwrap();
+        assert_eq!(summary.get_faults().len(), 3);
+        assert_eq!(summary.total_statistics()[1].count(), 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! This module contains observation error models, which turn simulated abundances into synthetic survey data: the counts a monitoring program would record if the simulated population were the real one. Simulated survey data with realistic noise is used to test monitoring designs and to check that state-space and other estimators recover the true abundances and vital rates.
+use super::stochastic::replicate_seed;
+use super::{PopulationVector, PvaDeterministicOutput, PvaStochasticOutput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Binomial, Distribution, Normal, Poisson};
+use std::{fmt, str::FromStr};
+
+/// Mixed into the seeds of observation draws so that they are independent of the draws of a
+/// projection run with the same seed.
+const OBSERVATION_STREAM: u64 = 0x6F62_7365_7276_6564;
+
+/// This enum describes how a survey count relates to the true number of individuals `N` in a
+/// lifestage:
+/// - `Poisson`: the count is drawn from a Poisson distribution with mean `N`, as for counts of
+///   individuals that are each detected or missed independently at a constant rate.
+/// - `Lognormal`: the count is `N` multiplied by lognormal noise with a log-scale standard
+///   deviation of `sigma`, centred so the expected count is `N`. This suits density or index
+///   surveys whose error grows with abundance (a `sigma` of 0.2 is roughly a 20% coefficient of
+///   variation).
+/// - `Binomial`: each individual is detected with probability `detection`, so counts are biased
+///   low by that proportion, as in surveys with imperfect detection. `N` is rounded to a whole
+///   number first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObservationError {
+    Poisson,
+    Lognormal { sigma: f64 },
+    Binomial { detection: f64 },
+}
+impl fmt::Display for ObservationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObservationError::Poisson => write!(f, "poisson"),
+            ObservationError::Lognormal { sigma } => write!(f, "lognormal {}", sigma),
+            ObservationError::Binomial { detection } => write!(f, "binomial {}", detection),
+        }
+    }
+}
+
+/// This struct applies an [`ObservationError`] to simulated abundances. Counts are drawn
+/// independently for each lifestage at each step.
+/// ```
+/// use ecolysis_cmd::populations::population_level_simulation::{DeterministicPva, ObservationModel};
+/// let output = DeterministicPva::build_from_vectors(
+///     vec![200.0, 100.0],
+///     vec![vec![0.0, 1.5], vec![0.5, 0.8]],
+/// )
+/// .unwrap()
+/// .deterministic_projection(10);
+/// let survey: ObservationModel = "binomial 0.6".parse().unwrap();
+/// let observed = survey.observe_deterministic(&output, 1);
+/// assert_eq!(observed.iter().count(), 10);
+/// // Imperfect detection never counts more individuals than there are.
+/// for (counted, truth) in observed.iter().zip(output.iter()) {
+///     assert!(counted.total() <= truth.total().round());
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObservationModel {
+    error: ObservationError,
+}
+impl ObservationModel {
+    /// Create an observation model.
+    /// # Errors
+    /// Will return `Err<'static str>` if a lognormal `sigma` is negative or a binomial
+    /// `detection` is not between zero and one.
+    pub fn new(error: ObservationError) -> Result<ObservationModel, &'static str> {
+        match error {
+            ObservationError::Lognormal { sigma } if !(sigma.is_finite() && sigma >= 0.0) => {
+                Err("The standard deviation of lognormal observation error cannot be negative.")
+            }
+            ObservationError::Binomial { detection } if !(0.0..=1.0).contains(&detection) => {
+                Err("The detection probability must be between zero and one.")
+            }
+            _ => Ok(ObservationModel { error }),
+        }
+    }
+    /// Return the observation error.
+    pub fn get_error(&self) -> ObservationError {
+        self.error
+    }
+    /// Return a survey count for the true numbers of individuals in `vector`, drawing the error
+    /// from `rng`. Negative abundances are treated as zero.
+    pub fn observe<R: Rng + ?Sized>(
+        &self,
+        vector: &PopulationVector,
+        rng: &mut R,
+    ) -> PopulationVector {
+        PopulationVector::new(
+            vector
+                .iter()
+                .map(|n| {
+                    let n = n.max(0.0);
+                    match self.error {
+                        _ if n == 0.0 => 0.0,
+                        ObservationError::Poisson => Poisson::new(n)
+                            .expect("The mean of a Poisson count is positive.")
+                            .sample(rng),
+                        ObservationError::Lognormal { sigma } => {
+                            let noise: f64 = Normal::new(0.0, sigma)
+                                .expect("Sigma is checked when the model is built.")
+                                .sample(rng);
+                            n * (noise - sigma * sigma / 2.0).exp()
+                        }
+                        ObservationError::Binomial { detection } => {
+                            Binomial::new(n.round() as u64, detection)
+                                .expect("Detection is checked when the model is built.")
+                                .sample(rng) as f64
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+    /// Return the survey counts of every step of a deterministic projection, keeping its
+    /// interventions, stop reason, and time step. The same seed always gives the same counts,
+    /// and the counts are independent of a projection run with the same seed.
+    pub fn observe_deterministic(
+        &self,
+        output: &PvaDeterministicOutput,
+        seed: u64,
+    ) -> PvaDeterministicOutput {
+        let mut rng = StdRng::seed_from_u64(replicate_seed(seed, 0) ^ OBSERVATION_STREAM);
+        PvaDeterministicOutput::new(output.iter().map(|x| self.observe(x, &mut rng)).collect())
+            .with_stop_reason(output.get_stop_reason())
+            .with_interventions(output.get_interventions().clone())
+            .with_model_time_step(output.get_time_step())
+    }
+    /// Return the survey counts of every step of every replicate of a stochastic projection,
+    /// keeping its stop reasons and time step. Each replicate's counts are drawn from their own
+    /// seed derived from `seed`, as the replicates of a projection are.
+    pub fn observe_stochastic(
+        &self,
+        output: &PvaStochasticOutput,
+        seed: u64,
+    ) -> PvaStochasticOutput {
+        let replicates = output
+            .get_replicates()
+            .iter()
+            .zip(0..)
+            .map(|(steps, replicate)| {
+                let mut rng =
+                    StdRng::seed_from_u64(replicate_seed(seed, replicate) ^ OBSERVATION_STREAM);
+                steps.iter().map(|x| self.observe(x, &mut rng)).collect()
+            })
+            .collect();
+        PvaStochasticOutput::new(replicates)
+            .with_stop_reasons(output.get_stop_reasons().clone())
+            .expect("The output has one stop reason per replicate.")
+            .with_model_time_step(output.get_time_step())
+    }
+}
+impl fmt::Display for ObservationModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+impl FromStr for ObservationModel {
+    type Err = String;
+    /// Parse `poisson`, `lognormal SIGMA`, or `binomial DETECTION`. The parameter can also follow
+    /// a colon, as in `binomial:0.6`.
+    fn from_str(text: &str) -> Result<ObservationModel, String> {
+        let words: Vec<&str> = text
+            .split(|x: char| x.is_whitespace() || x == ':')
+            .filter(|x| !x.is_empty())
+            .collect();
+        let number = |x: &str| {
+            x.parse::<f64>()
+                .map_err(|_| format!("\"{}\" is not a number.", x))
+        };
+        let error = match words.as_slice() {
+            [name] if name.eq_ignore_ascii_case("poisson") => ObservationError::Poisson,
+            [name, sigma] if name.eq_ignore_ascii_case("lognormal") => ObservationError::Lognormal {
+                sigma: number(sigma)?,
+            },
+            [name, detection] if name.eq_ignore_ascii_case("binomial") => {
+                ObservationError::Binomial {
+                    detection: number(detection)?,
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "\"{}\" is not an observation error. Use poisson, lognormal SIGMA, or binomial DETECTION.",
+                    text.trim()
+                ))
+            }
+        };
+        ObservationModel::new(error).map_err(String::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::StochasticPva;
+    use crate::statistics::RunningStatistics;
+
+    #[test]
+    fn observation_errors_have_the_expected_mean() {
+        let truth = PopulationVector::new(vec![0.0, 100.0]);
+        let mut rng = StdRng::seed_from_u64(3);
+        for (error, mean) in [
+            (ObservationError::Poisson, 100.0),
+            (ObservationError::Lognormal { sigma: 0.3 }, 100.0),
+            (ObservationError::Binomial { detection: 0.4 }, 40.0),
+        ] {
+            let model = ObservationModel::new(error).unwrap();
+            let mut statistics = RunningStatistics::new();
+            for _ in 0..4000 {
+                let observed = model.observe(&truth, &mut rng);
+                assert_eq!(observed.get_vector()[0], 0.0);
+                statistics.push(observed.get_vector()[1]);
+            }
+            assert!((statistics.mean() - mean).abs() < 2.0, "{}", error);
+        }
+    }
+
+    #[test]
+    fn models_are_checked_and_parsed() {
+        assert!(ObservationModel::new(ObservationError::Binomial { detection: 1.5 }).is_err());
+        assert!(ObservationModel::new(ObservationError::Lognormal { sigma: -0.1 }).is_err());
+        assert_eq!(
+            "Lognormal:0.2"
+                .parse::<ObservationModel>()
+                .unwrap()
+                .get_error(),
+            ObservationError::Lognormal { sigma: 0.2 }
+        );
+        let poisson: ObservationModel = "poisson".parse().unwrap();
+        assert_eq!(poisson.to_string().parse::<ObservationModel>(), Ok(poisson));
+        for invalid in ["", "poisson 2", "binomial", "binomial high", "normal 0.1"] {
+            assert!(invalid.parse::<ObservationModel>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn outputs_keep_their_shape() {
+        let output = StochasticPva::build_from_vectors(
+            vec![50.0, 50.0],
+            vec![
+                vec![vec![0.0, 1.2], vec![0.5, 0.8]],
+                vec![vec![0.0, 0.8], vec![0.4, 0.7]],
+            ],
+        )
+        .unwrap()
+        .stochastic_projection(5, 3, 9);
+        let model: ObservationModel = "poisson".parse().unwrap();
+        let observed = model.observe_stochastic(&output, 9);
+        assert_eq!(observed.replicate_count(), 3);
+        assert_eq!(observed.get_stop_reasons(), output.get_stop_reasons());
+        assert!(observed
+            .get_replicates()
+            .iter()
+            .flatten()
+            .flat_map(|x| x.iter())
+            .all(|x| x.fract() == 0.0));
+        assert_eq!(
+            observed.get_replicates(),
+            model.observe_stochastic(&output, 9).get_replicates()
+        );
+        assert_ne!(
+            observed.get_replicates(),
+            model.observe_stochastic(&output, 10).get_replicates()
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! This module contains perturbation schedules: declarative changes to the vital rates of a baseline matrix during a projection, such as a steady decline in survival or a few years of reduced fecundity. Each step's matrix is built from the baseline, so scenario analyses do not need a matrix written by hand for every step.
+//!
+//! Perturbations can also be written as text, as in scenario files:
+//!
+//! ```text
+//! ramp survival -1% from 0 for 20
+//! pulse fecundity 0.5 from 5 for 3
+//! pulse 2,1 x1.2 from 10 for 1
+//! ```
+//!
+//! The first ramps survival down by 1% of its baseline value each step for 20 steps (and keeps it there), the second halves fecundity at steps 5, 6, and 7, and the third raises the element in row 2, column 1 (counting from one) by 20% at step 10.
+use super::{MatrixSchedule, PopulationMatrix, PopulationVector, PvaDeterministicOutput};
+use std::{fmt, str::FromStr};
+
+/// The vital rates a perturbation applies to. Following the common layout where only the first
+/// row holds reproduction:
+/// - `Fecundity`: every element of the first row.
+/// - `Survival`: every element of the other rows (survival, growth, and stasis).
+/// - `All`: every element.
+/// - `Element`: one element, by row and column (counting from zero).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateSelection {
+    Fecundity,
+    Survival,
+    All,
+    Element { row: usize, column: usize },
+}
+impl RateSelection {
+    /// Return true if the element at `row` and `column` is one of the selected rates.
+    fn contains(self, row: usize, column: usize) -> bool {
+        match self {
+            RateSelection::Fecundity => row == 0,
+            RateSelection::Survival => row > 0,
+            RateSelection::All => true,
+            RateSelection::Element {
+                row: selected_row,
+                column: selected_column,
+            } => row == selected_row && column == selected_column,
+        }
+    }
+}
+impl fmt::Display for RateSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RateSelection::Fecundity => write!(f, "fecundity"),
+            RateSelection::Survival => write!(f, "survival"),
+            RateSelection::All => write!(f, "all"),
+            RateSelection::Element { row, column } => write!(f, "{},{}", row + 1, column + 1),
+        }
+    }
+}
+impl FromStr for RateSelection {
+    type Err = String;
+    /// Parse `fecundity`, `survival`, `all`, or an element written `ROW,COLUMN` counting from
+    /// one.
+    fn from_str(text: &str) -> Result<RateSelection, String> {
+        match text.to_lowercase().as_str() {
+            "fecundity" | "fertility" => Ok(RateSelection::Fecundity),
+            "survival" => Ok(RateSelection::Survival),
+            "all" => Ok(RateSelection::All),
+            _ => {
+                let position = |x: &str| x.trim().parse::<usize>().ok().filter(|x| *x > 0);
+                match text.split_once(',').map(|(r, c)| (position(r), position(c))) {
+                    Some((Some(row), Some(column))) => Ok(RateSelection::Element {
+                        row: row - 1,
+                        column: column - 1,
+                    }),
+                    _ => Err(format!(
+                        "\"{}\" is not a set of rates. Use survival, fecundity, all, or ROW,COLUMN.",
+                        text
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// The ways a perturbation changes the selected rates:
+/// - `Ramp`: the rates change by `change_per_step` times their baseline value at every step of
+///   the perturbation (-0.01 is a decline of 1% of the baseline per step), and keep their final
+///   value afterwards. Rates never fall below zero.
+/// - `Pulse`: the rates are multiplied by `factor` during the perturbation, and return to their
+///   baseline values afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PerturbationKind {
+    Ramp { change_per_step: f64 },
+    Pulse { factor: f64 },
+}
+
+/// This struct holds one perturbation: a change to some of the vital rates of a baseline matrix
+/// that starts at a given step and lasts a number of steps. Step `k` is the step that projects
+/// the population from step `k` to step `k + 1`, as in a [`MatrixSchedule`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Perturbation {
+    rates: RateSelection,
+    kind: PerturbationKind,
+    start: u32,
+    duration: u32,
+}
+impl Perturbation {
+    /// Create a ramp that changes `rates` by `change_per_step` times their baseline value at
+    /// each of the `duration` steps from `start`.
+    /// # Errors
+    /// Will return `Err<'static str>` if `duration` is zero or `change_per_step` is not finite.
+    pub fn ramp(
+        rates: RateSelection,
+        change_per_step: f64,
+        start: u32,
+        duration: u32,
+    ) -> Result<Perturbation, &'static str> {
+        if !change_per_step.is_finite() {
+            return Err("The change per step of a ramp must be a number.");
+        }
+        Perturbation::build(
+            rates,
+            PerturbationKind::Ramp { change_per_step },
+            start,
+            duration,
+        )
+    }
+    /// Create a pulse that multiplies `rates` by `factor` for the `duration` steps from `start`.
+    /// # Errors
+    /// Will return `Err<'static str>` if `duration` is zero or `factor` is negative or not
+    /// finite.
+    pub fn pulse(
+        rates: RateSelection,
+        factor: f64,
+        start: u32,
+        duration: u32,
+    ) -> Result<Perturbation, &'static str> {
+        if !factor.is_finite() || factor < 0.0 {
+            return Err("The factor of a pulse cannot be negative.");
+        }
+        Perturbation::build(rates, PerturbationKind::Pulse { factor }, start, duration)
+    }
+    fn build(
+        rates: RateSelection,
+        kind: PerturbationKind,
+        start: u32,
+        duration: u32,
+    ) -> Result<Perturbation, &'static str> {
+        if duration == 0 {
+            return Err("A perturbation must last at least one step.");
+        }
+        if start.checked_add(duration).is_none() {
+            return Err("A perturbation must end before the last possible step.");
+        }
+        Ok(Perturbation {
+            rates,
+            kind,
+            start,
+            duration,
+        })
+    }
+    /// Return the rates the perturbation applies to.
+    pub fn get_rates(&self) -> RateSelection {
+        self.rates
+    }
+    /// Return how the perturbation changes the rates.
+    pub fn get_kind(&self) -> PerturbationKind {
+        self.kind
+    }
+    /// Return the first step the perturbation applies to.
+    pub fn get_start(&self) -> u32 {
+        self.start
+    }
+    /// Return the number of steps the perturbation lasts.
+    pub fn get_duration(&self) -> u32 {
+        self.duration
+    }
+    /// Return the step after the last step of the perturbation.
+    fn end(&self) -> u32 {
+        self.start + self.duration
+    }
+    /// Return the factor the selected rates are multiplied by at `step`.
+    pub fn factor_at(&self, step: u32) -> f64 {
+        match self.kind {
+            PerturbationKind::Ramp { change_per_step } => {
+                let elapsed = if step < self.start {
+                    0
+                } else {
+                    (step - self.start + 1).min(self.duration)
+                };
+                (1.0 + change_per_step * f64::from(elapsed)).max(0.0)
+            }
+            PerturbationKind::Pulse { factor } if (self.start..self.end()).contains(&step) => {
+                factor
+            }
+            PerturbationKind::Pulse { .. } => 1.0,
+        }
+    }
+}
+impl fmt::Display for Perturbation {
+    /// Write the perturbation in the form read by [`Perturbation::from_str`].
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            PerturbationKind::Ramp { change_per_step } => {
+                write!(f, "ramp {} {}", self.rates, change_per_step)?
+            }
+            PerturbationKind::Pulse { factor } => write!(f, "pulse {} x{}", self.rates, factor)?,
+        }
+        write!(f, " from {} for {}", self.start, self.duration)
+    }
+}
+impl FromStr for Perturbation {
+    type Err = String;
+    /// Parse a perturbation written `ramp RATES CHANGE from STEP for STEPS` or
+    /// `pulse RATES FACTOR from STEP for STEPS`. RATES is read by [`RateSelection::from_str`].
+    /// The change or factor can be written as a percentage (`-1%`), and a factor can start with
+    /// `x` (`x0.5`).
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::Perturbation;
+    /// let decline: Perturbation = "ramp survival -1% from 0 for 20".parse().unwrap();
+    /// assert!((decline.factor_at(9) - 0.9).abs() < 1e-12);
+    /// assert!((decline.factor_at(50) - 0.8).abs() < 1e-12);
+    /// ```
+    fn from_str(text: &str) -> Result<Perturbation, String> {
+        let usage = || {
+            format!(
+                "\"{}\" is not a perturbation. Write it like `ramp survival -1% from 0 for 20` or \
+                 `pulse fecundity 0.5 from 5 for 3`.",
+                text.trim()
+            )
+        };
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let [kind, rates, value, "from", start, "for", duration] = words.as_slice() else {
+            return Err(usage());
+        };
+        let rates: RateSelection = rates.parse()?;
+        let number = |x: &str| -> Result<f64, String> {
+            let (number, scale) = match x.strip_suffix('%') {
+                Some(x) => (x, 0.01),
+                None => (x, 1.0),
+            };
+            number
+                .parse::<f64>()
+                .map(|x| x * scale)
+                .map_err(|_| format!("\"{}\" is not a number.", x))
+        };
+        let step = |x: &str| {
+            x.parse::<u32>()
+                .map_err(|_| format!("\"{}\" is not a step number.", x))
+        };
+        let (start, duration) = (step(start)?, step(duration)?);
+        match kind.to_lowercase().as_str() {
+            "ramp" => Perturbation::ramp(rates, number(value)?, start, duration),
+            "pulse" => Perturbation::pulse(
+                rates,
+                number(value.strip_prefix(['x', '×']).unwrap_or(value))?,
+                start,
+                duration,
+            ),
+            _ => return Err(usage()),
+        }
+        .map_err(String::from)
+    }
+}
+
+/// This struct holds a baseline Population Matrix and the perturbations applied to it during a
+/// projection. The matrix at each step is the baseline with each selected rate multiplied by the
+/// factors of every perturbation that applies to it at that step. Survival (every row but the
+/// first) is then limited so each column adds up to at most one, or to its baseline total if
+/// that is already higher, so raising survival never makes individuals more than certain to
+/// survive.
+/// ```
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// use ecolysis_cmd::populations::population_level_simulation::PerturbationSchedule;
+/// let baseline = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+/// let schedule = PerturbationSchedule::new(baseline)
+///     .with_perturbation("ramp survival -1% from 0 for 20".parse().unwrap())
+///     .unwrap()
+///     .with_perturbation("pulse fecundity 0.5 from 5 for 3".parse().unwrap())
+///     .unwrap();
+/// assert_eq!(schedule.matrix_at(6)[(0, 1)], 1.0);
+/// assert!((schedule.matrix_at(30)[(1, 1)] - 0.64).abs() < 1e-12);
+/// let output = schedule.project(&PopulationVector::new(vec![10.0, 10.0]), 30).unwrap();
+/// assert_eq!(output.iter().count(), 30);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PerturbationSchedule {
+    baseline: PopulationMatrix,
+    perturbations: Vec<Perturbation>,
+}
+impl PerturbationSchedule {
+    /// Create a schedule with no perturbations.
+    pub fn new(baseline: PopulationMatrix) -> PerturbationSchedule {
+        PerturbationSchedule {
+            baseline,
+            perturbations: Vec::new(),
+        }
+    }
+    /// Return the schedule with another perturbation applied.
+    /// # Errors
+    /// Will return `Err<'static str>` if the perturbation applies to an element outside the
+    /// baseline matrix.
+    pub fn with_perturbation(mut self, perturbation: Perturbation) -> Result<Self, &'static str> {
+        let size = self.baseline.get_lifestage_count() as usize;
+        if let RateSelection::Element { row, column } = perturbation.rates {
+            if row >= size || column >= size {
+                return Err("A perturbed element is outside the matrix.");
+            }
+        }
+        self.perturbations.push(perturbation);
+        Ok(self)
+    }
+    /// Return the baseline matrix.
+    pub fn get_baseline(&self) -> &PopulationMatrix {
+        &self.baseline
+    }
+    /// Return the perturbations, in the order they were added.
+    pub fn get_perturbations(&self) -> &Vec<Perturbation> {
+        &self.perturbations
+    }
+    /// Return the matrix that projects the population from `step` to `step + 1`.
+    pub fn matrix_at(&self, step: u32) -> PopulationMatrix {
+        let factors: Vec<(RateSelection, f64)> = self
+            .perturbations
+            .iter()
+            .map(|x| (x.rates, x.factor_at(step)))
+            .collect();
+        let baseline = self.baseline.get_matrix();
+        let mut matrix: Vec<Vec<f64>> = baseline
+            .iter()
+            .enumerate()
+            .map(|(row, values)| {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(column, value)| {
+                        factors
+                            .iter()
+                            .filter(|(rates, _)| rates.contains(row, column))
+                            .fold(*value, |value, (_, factor)| value * factor)
+                    })
+                    .collect()
+            })
+            .collect();
+        for column in 0..baseline.len() {
+            let survival = |rows: &[Vec<f64>]| rows[1..].iter().map(|x| x[column]).sum::<f64>();
+            let (total, limit) = (survival(&matrix), survival(&baseline).max(1.0));
+            if total > limit {
+                for row in &mut matrix[1..] {
+                    row[column] *= limit / total;
+                }
+            }
+        }
+        self.baseline.derived(matrix)
+    }
+    /// Return the equivalent [`MatrixSchedule`] for the first `steps` steps, with a change at
+    /// every step where the matrix differs from the step before. Only the matrices of those
+    /// steps are built, however long the perturbations last.
+    pub fn to_matrix_schedule(&self, steps: u32) -> MatrixSchedule {
+        let end = self
+            .perturbations
+            .iter()
+            .map(|x| x.end())
+            .max()
+            .unwrap_or(0)
+            .min(steps.saturating_sub(1));
+        let mut previous = self.matrix_at(0);
+        let mut schedule = MatrixSchedule::new(previous.clone());
+        for step in 1..=end {
+            let matrix = self.matrix_at(step);
+            if matrix != previous {
+                schedule = schedule
+                    .change_at(step, matrix.clone())
+                    .expect("Steps increase and every matrix has the baseline's size.");
+                previous = matrix;
+            }
+        }
+        schedule
+    }
+    /// Project a Population Vector for the given number of steps, recording each step where the
+    /// matrix changes as an intervention (see [`MatrixSchedule::project`]). Each step's matrix
+    /// is built as it is needed.
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector and baseline matrix have different numbers
+    /// of lifestages.
+    pub fn project(
+        &self,
+        initial: &PopulationVector,
+        steps: u32,
+    ) -> Result<PvaDeterministicOutput, &'static str> {
+        let mut result = Vec::with_capacity(steps as usize);
+        let mut interventions = Vec::new();
+        let mut population = initial.clone();
+        let mut previous = self.matrix_at(0);
+        for step in 0..steps {
+            if step > 0 {
+                let matrix = self.matrix_at(step);
+                if matrix != previous {
+                    interventions.push(step);
+                    previous = matrix;
+                }
+            }
+            population = previous.project_vector(&population)?;
+            result.push(population.clone());
+        }
+        Ok(PvaDeterministicOutput::new(result).with_interventions(interventions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    #[test]
+    fn ramps_and_pulses() {
+        let ramp = Perturbation::ramp(RateSelection::Survival, -0.5, 2, 3).unwrap();
+        let factors: Vec<f64> = (0..6).map(|x| ramp.factor_at(x)).collect();
+        assert_eq!(factors, vec![1.0, 1.0, 0.5, 0.0, 0.0, 0.0]);
+        let pulse = Perturbation::pulse(RateSelection::All, 2.0, 1, 2).unwrap();
+        let factors: Vec<f64> = (0..4).map(|x| pulse.factor_at(x)).collect();
+        assert_eq!(factors, vec![1.0, 2.0, 2.0, 1.0]);
+        assert!(Perturbation::pulse(RateSelection::All, -1.0, 0, 1).is_err());
+        assert!(Perturbation::ramp(RateSelection::All, 0.1, 0, 0).is_err());
+        assert!(Perturbation::ramp(RateSelection::All, 0.1, u32::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn perturbations_are_parsed() {
+        let pulse: Perturbation = "pulse 2,1 x120% from 10 for 1".parse().unwrap();
+        assert_eq!(
+            pulse.get_rates(),
+            RateSelection::Element { row: 1, column: 0 }
+        );
+        assert_eq!(pulse.get_kind(), PerturbationKind::Pulse { factor: 1.2 });
+        assert_eq!(pulse.to_string(), "pulse 2,1 x1.2 from 10 for 1");
+        assert_eq!(pulse.to_string().parse::<Perturbation>(), Ok(pulse));
+        let ramp: Perturbation = "Ramp fecundity -0.02 from 3 for 7".parse().unwrap();
+        assert_eq!((ramp.get_start(), ramp.get_duration()), (3, 7));
+        for invalid in [
+            "ramp survival -1%",
+            "shift survival 0.5 from 0 for 1",
+            "pulse 0,1 0.5 from 0 for 1",
+            "pulse adults 0.5 from 0 for 1",
+            "pulse all half from 0 for 1",
+            "pulse all 0.5 from -1 for 1",
+        ] {
+            assert!(invalid.parse::<Perturbation>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn schedule_matches_perturbed_matrices() {
+        let schedule = PerturbationSchedule::new(baseline())
+            .with_perturbation(Perturbation::pulse(RateSelection::Fecundity, 0.5, 2, 2).unwrap())
+            .unwrap()
+            .with_perturbation(
+                Perturbation::pulse(RateSelection::Element { row: 0, column: 1 }, 3.0, 3, 1)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(schedule.matrix_at(3)[(0, 1)], 3.0);
+        assert_eq!(schedule.matrix_at(3)[(1, 1)], 0.8);
+        let matrices = schedule.to_matrix_schedule(11);
+        assert_eq!(matrices.get_change_steps(), vec![2, 3, 4]);
+        assert_eq!(matrices.matrix_at(10), &baseline());
+        assert_eq!(
+            schedule.to_matrix_schedule(4).get_change_steps(),
+            vec![2, 3]
+        );
+        let initial = PopulationVector::new(vec![10.0, 10.0]);
+        let output = schedule.project(&initial, 6).unwrap();
+        let mut expected = initial;
+        for (step, population) in output.iter().enumerate() {
+            expected = schedule
+                .matrix_at(step as u32)
+                .project_vector(&expected)
+                .unwrap();
+            assert!(population.approx_eq(&expected, 1e-12));
+        }
+        assert_eq!(output.get_interventions(), &vec![2, 3, 4]);
+        assert!(PerturbationSchedule::new(baseline())
+            .with_perturbation(
+                Perturbation::pulse(RateSelection::Element { row: 2, column: 0 }, 2.0, 0, 1)
+                    .unwrap()
+            )
+            .is_err());
+    }
+    #[test]
+    fn long_perturbations_only_build_the_projected_steps() {
+        let schedule = PerturbationSchedule::new(baseline())
+            .with_perturbation(
+                Perturbation::ramp(RateSelection::Fecundity, 0.01, 0, u32::MAX - 1).unwrap(),
+            )
+            .unwrap();
+        let output = schedule
+            .project(&PopulationVector::new(vec![10.0, 10.0]), 5)
+            .unwrap();
+        assert_eq!(output.iter().count(), 5);
+        assert_eq!(output.get_interventions(), &vec![1, 2, 3, 4]);
+        assert_eq!(schedule.to_matrix_schedule(5).get_change_steps().len(), 4);
+    }
+    #[test]
+    fn survival_is_limited() {
+        let schedule = PerturbationSchedule::new(baseline())
+            .with_perturbation("ramp survival 10% from 0 for 20".parse().unwrap())
+            .unwrap();
+        let matrix = schedule.matrix_at(0);
+        assert!((matrix[(1, 0)] - 0.55).abs() < 1e-12);
+        let matrix = schedule.matrix_at(19);
+        assert!((matrix[(1, 1)] - 1.0).abs() < 1e-12);
+        assert_eq!(matrix[(0, 1)], 2.0);
+        assert!((matrix[(1, 0)] - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,518 @@
+//! This module contains tools for propagating parameter uncertainty through projections using samples of matrices: drawn from prior distributions placed on individual vital rates, or read from a file of posterior draws produced by a Bayesian model fitted elsewhere (such as JAGS, Stan, or NIMBLE). Every sampled matrix is projected, and the spread of the results gives credible intervals.
+//!
+//! Unlike the parametric bootstrap in [`super::UncertainMatrix`], which works from estimates and standard errors, this keeps any correlation between rates that is present in a posterior sample.
+use super::stochastic::replicate_seed;
+use super::uncertainty::percentile_interval;
+use super::{DeterministicPva, PopulationMatrix, PopulationVector};
+use crate::imports::{read_text, CsvFormat};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Beta, Distribution, Gamma, LogNormal, Uniform};
+use std::{error::Error, io::Read};
+
+/// This enum describes the prior distribution of a vital rate:
+/// - `Uniform`: any value between `lower` and `upper` is equally likely.
+/// - `Beta`: for probabilities such as survival, with shape parameters `alpha` and `beta` (the
+///   mean is `alpha / (alpha + beta)`).
+/// - `Gamma`: for positive rates such as fecundity, with the given `shape` and `scale` (the mean
+///   is `shape * scale`).
+/// - `LogNormal`: for positive rates, where the logarithm of the rate is normally distributed
+///   with mean `mu` and standard deviation `sigma`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateDistribution {
+    Uniform { lower: f64, upper: f64 },
+    Beta { alpha: f64, beta: f64 },
+    Gamma { shape: f64, scale: f64 },
+    LogNormal { mu: f64, sigma: f64 },
+}
+impl RateDistribution {
+    /// Return an error if the parameters do not describe a distribution of non-negative values.
+    fn check(self) -> Result<(), &'static str> {
+        let valid = match self {
+            RateDistribution::Uniform { lower, upper } => {
+                lower >= 0.0 && upper.is_finite() && lower <= upper
+            }
+            RateDistribution::Beta { alpha, beta } => {
+                alpha > 0.0 && beta > 0.0 && alpha.is_finite() && beta.is_finite()
+            }
+            RateDistribution::Gamma { shape, scale } => {
+                shape > 0.0 && scale > 0.0 && shape.is_finite() && scale.is_finite()
+            }
+            RateDistribution::LogNormal { mu, sigma } => {
+                mu.is_finite() && sigma.is_finite() && sigma >= 0.0
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err("The parameters of a prior distribution are not valid.")
+        }
+    }
+    /// Draw one value from the distribution.
+    fn sample<R: Rng + ?Sized>(self, rng: &mut R) -> f64 {
+        // The parameters are checked when the prior is built, so the distributions can be built.
+        match self {
+            RateDistribution::Uniform { lower, upper } if lower == upper => lower,
+            RateDistribution::Uniform { lower, upper } => Uniform::new(lower, upper).sample(rng),
+            RateDistribution::Beta { alpha, beta } => Beta::new(alpha, beta)
+                .expect("Beta parameters are checked when the prior is built.")
+                .sample(rng),
+            RateDistribution::Gamma { shape, scale } => Gamma::new(shape, scale)
+                .expect("Gamma parameters are checked when the prior is built.")
+                .sample(rng),
+            RateDistribution::LogNormal { mu, sigma } => LogNormal::new(mu, sigma)
+                .expect("Log-normal parameters are checked when the prior is built.")
+                .sample(rng),
+        }
+    }
+}
+
+/// This struct holds the prior distribution of one vital rate: a single element of the
+/// projection matrix (given by its row and column).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RatePrior {
+    name: String,
+    row: usize,
+    column: usize,
+    distribution: RateDistribution,
+}
+impl RatePrior {
+    /// Create a new Rate Prior for the matrix element at `(row, column)`.
+    /// # Errors
+    /// Will return `Err<'static str>` if the parameters of the distribution are not valid or it
+    /// could give negative values.
+    pub fn new(
+        name: &str,
+        row: usize,
+        column: usize,
+        distribution: RateDistribution,
+    ) -> Result<RatePrior, &'static str> {
+        distribution.check()?;
+        Ok(RatePrior {
+            name: name.to_string(),
+            row,
+            column,
+            distribution,
+        })
+    }
+    /// Return the name of the rate.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the prior distribution of the rate.
+    pub fn get_distribution(&self) -> RateDistribution {
+        self.distribution
+    }
+}
+
+/// This struct holds a base Population Matrix and prior distributions for some of its elements.
+/// Elements without a prior keep their values from the base matrix in every draw. Priors are
+/// drawn independently; use a posterior sample file for correlated rates.
+/// ```
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// use ecolysis_cmd::populations::population_level_simulation::{MatrixPrior, RateDistribution, RatePrior};
+/// let base = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+/// let prior = MatrixPrior::build(base, vec![
+///     RatePrior::new("fecundity", 0, 1, RateDistribution::Gamma { shape: 40.0, scale: 0.05 }).unwrap(),
+///     RatePrior::new("adult_survival", 1, 1, RateDistribution::Beta { alpha: 80.0, beta: 20.0 }).unwrap(),
+/// ]).unwrap();
+/// let projection = prior.sample(500, 42)
+///     .unwrap()
+///     .project(&PopulationVector::new(vec![20.0, 10.0]), 10)
+///     .unwrap();
+/// let (lower, upper) = projection.lambda_interval(0.95);
+/// assert!(lower < 1.46 && 1.46 < upper);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MatrixPrior {
+    base: PopulationMatrix,
+    priors: Vec<RatePrior>,
+}
+impl MatrixPrior {
+    /// Build a Matrix Prior from a base matrix and the priors of some of its elements.
+    /// # Errors
+    /// Will return `Err<'static str>` if a prior is for an element outside the matrix, or two
+    /// priors share an element or a name.
+    pub fn build(
+        base: PopulationMatrix,
+        priors: Vec<RatePrior>,
+    ) -> Result<MatrixPrior, &'static str> {
+        let size = base.get_lifestage_count() as usize;
+        if priors.iter().any(|x| x.row >= size || x.column >= size) {
+            return Err("A prior is for an element outside the matrix.");
+        }
+        for (index, prior) in priors.iter().enumerate() {
+            if priors[..index]
+                .iter()
+                .any(|x| (x.row == prior.row && x.column == prior.column) || x.name == prior.name)
+            {
+                return Err("Each matrix element and name can only have one prior.");
+            }
+        }
+        Ok(MatrixPrior { base, priors })
+    }
+    /// Return the base matrix.
+    pub fn get_base(&self) -> &PopulationMatrix {
+        &self.base
+    }
+    /// Return the priors of the matrix elements.
+    pub fn get_priors(&self) -> &Vec<RatePrior> {
+        &self.priors
+    }
+    /// Return the base matrix with each rate set to the corresponding value.
+    fn matrix_with(&self, values: &[f64]) -> Result<PopulationMatrix, &'static str> {
+        let mut matrix = self.base.clone();
+        for (prior, value) in self.priors.iter().zip(values) {
+            matrix.set_element(prior.row, prior.column, *value)?;
+        }
+        Ok(matrix)
+    }
+    /// Draw one matrix from the priors.
+    /// # Errors
+    /// Will return `Err<'static str>` if a draw overflows to a value that is not a finite number,
+    /// as a log-normal or gamma prior with a very large mean can.
+    pub fn draw<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<PopulationMatrix, &'static str> {
+        let values: Vec<f64> = self
+            .priors
+            .iter()
+            .map(|x| x.distribution.sample(rng))
+            .collect();
+        if values.iter().any(|x| !x.is_finite()) {
+            return Err("A prior drew a value too large to be a finite number.");
+        }
+        self.matrix_with(&values)
+    }
+    /// Draw `draws` matrices from the priors. Each draw has its own random number generator
+    /// derived from `seed`, so results are reproducible.
+    /// # Errors
+    /// Will return `Err<'static str>` if any draw fails (see [`MatrixPrior::draw`]).
+    pub fn sample(&self, draws: u32, seed: u64) -> Result<MatrixSample, &'static str> {
+        let matrices = (0..draws)
+            .map(|draw| self.draw(&mut StdRng::seed_from_u64(replicate_seed(seed, draw))))
+            .collect::<Result<_, _>>()?;
+        Ok(MatrixSample { matrices })
+    }
+    /// Read a sample of matrices from a CSV file of posterior draws, with one row per draw and
+    /// a header row naming the parameters. Each prior's rate is read from the column with the same
+    /// name (the prior distribution itself is not used); other columns, such as `chain`,
+    /// `iteration`, or `lp__`, are ignored. The delimiter and decimal separator are detected as
+    /// described in [`CsvFormat::detect`].
+    /// # Errors
+    /// Returns an error if the file cannot be read, a rate has no column, a value is not a number,
+    /// or a value is negative.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::{MatrixPrior, RateDistribution, RatePrior};
+    /// let base = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let flat = RateDistribution::Uniform { lower: 0.0, upper: 1.0 };
+    /// let prior = MatrixPrior::build(base, vec![
+    ///     RatePrior::new("phi_juvenile", 1, 0, flat).unwrap(),
+    ///     RatePrior::new("phi_adult", 1, 1, flat).unwrap(),
+    /// ]).unwrap();
+    /// let file = "chain,phi_adult,phi_juvenile\n1,0.81,0.42\n1,0.78,0.47\n2,0.83,0.45\n";
+    /// let sample = prior.read_posterior_csv(file.as_bytes()).unwrap();
+    /// assert_eq!(sample.len(), 3);
+    /// assert_eq!(sample.get_matrices()[1][(1, 0)], 0.47);
+    /// ```
+    pub fn read_posterior_csv<R: Read>(&self, reader: R) -> Result<MatrixSample, Box<dyn Error>> {
+        let (contents, format) = CsvFormat::read_detected(reader)?;
+        self.read_posterior_csv_with_format(contents.as_slice(), &format)
+    }
+    /// Read a sample of matrices, as [`MatrixPrior::read_posterior_csv`] does, from a CSV file
+    /// written in a given format.
+    /// # Errors
+    /// Returns an error if the file cannot be read, a rate has no column, a value is not a number
+    /// in `format`, or a value is negative.
+    pub fn read_posterior_csv_with_format<R: Read>(
+        &self,
+        reader: R,
+        format: &CsvFormat,
+    ) -> Result<MatrixSample, Box<dyn Error>> {
+        let contents = read_text(reader)?;
+        let mut rdr = format.reader_builder().from_reader(contents.as_bytes());
+        let header: Vec<String> = rdr.headers()?.iter().map(String::from).collect();
+        let mut columns = Vec::with_capacity(self.priors.len());
+        for prior in &self.priors {
+            columns.push(
+                header
+                    .iter()
+                    .position(|x| *x == prior.name)
+                    .ok_or_else(|| {
+                        format!("The posterior sample has no \"{}\" column.", prior.name)
+                    })?,
+            );
+        }
+        let mut matrices = Vec::new();
+        for (row_number, record) in rdr.records().enumerate() {
+            let record = record?;
+            let mut values = Vec::with_capacity(columns.len());
+            for column in &columns {
+                let cell = record.get(*column).unwrap_or_default();
+                let value = format.parse_number(cell).ok_or_else(|| {
+                    format!(
+                        "Row {}, column \"{}\": \"{}\" is not a number.",
+                        row_number + 1,
+                        header[*column],
+                        cell
+                    )
+                })?;
+                values.push(value);
+            }
+            matrices.push(
+                self.matrix_with(&values)
+                    .map_err(|e| format!("Row {}: {}", row_number + 1, e))?,
+            );
+        }
+        Ok(MatrixSample { matrices })
+    }
+}
+
+/// This struct holds a sample of Population Matrices, such as draws from a prior or posterior
+/// distribution, that can be projected together.
+#[derive(Clone, Debug)]
+pub struct MatrixSample {
+    matrices: Vec<PopulationMatrix>,
+}
+impl MatrixSample {
+    /// Build a Matrix Sample from matrices drawn elsewhere.
+    /// # Errors
+    /// Will return `Err<'static str>` if the matrices have different numbers of lifestages.
+    pub fn from_matrices(matrices: Vec<PopulationMatrix>) -> Result<MatrixSample, &'static str> {
+        if matrices
+            .windows(2)
+            .any(|pair| pair[0].get_lifestage_count() != pair[1].get_lifestage_count())
+        {
+            return Err("Every matrix in a sample must have the same number of lifestages.");
+        }
+        Ok(MatrixSample { matrices })
+    }
+    /// Return the matrices in the sample.
+    pub fn get_matrices(&self) -> &Vec<PopulationMatrix> {
+        &self.matrices
+    }
+    /// Return the number of matrices in the sample.
+    pub fn len(&self) -> usize {
+        self.matrices.len()
+    }
+    /// Return true if the sample holds no matrices.
+    pub fn is_empty(&self) -> bool {
+        self.matrices.is_empty()
+    }
+    /// Project `initial_population` deterministically with every matrix in the sample for the
+    /// given number of steps, recording lambda and the total population at each step for each
+    /// matrix.
+    /// # Errors
+    /// Will return `Err<'static str>` if the sample is empty or the initial population does not
+    /// match the size of the matrices.
+    pub fn project(
+        &self,
+        initial_population: &PopulationVector,
+        iterations: u32,
+    ) -> Result<SampleProjection, &'static str> {
+        if self.matrices.is_empty() {
+            return Err("The sample holds no matrices.");
+        }
+        let mut projection = SampleProjection {
+            lambdas: Vec::with_capacity(self.matrices.len()),
+            totals: Vec::with_capacity(self.matrices.len()),
+        };
+        for matrix in &self.matrices {
+            let model = DeterministicPva::build(initial_population.clone(), matrix.clone())?;
+            projection.lambdas.push(matrix.lambda());
+            projection.totals.push(
+                model
+                    .deterministic_projection(iterations)
+                    .total_population(),
+            );
+        }
+        Ok(projection)
+    }
+}
+
+/// This struct stores the results of projecting every matrix in a [`MatrixSample`]: lambda and
+/// the total population at each step for each matrix, from which credible intervals are taken.
+pub struct SampleProjection {
+    lambdas: Vec<f64>,
+    totals: Vec<Vec<f64>>,
+}
+impl SampleProjection {
+    /// Return lambda for each matrix in the sample.
+    pub fn get_lambdas(&self) -> &Vec<f64> {
+        &self.lambdas
+    }
+    /// Return the total population at each step for each matrix in the sample.
+    pub fn get_totals(&self) -> &Vec<Vec<f64>> {
+        &self.totals
+    }
+    /// Return the number of steps projected.
+    pub fn step_count(&self) -> usize {
+        self.totals.first().map_or(0, Vec::len)
+    }
+    /// Return the `(lower, upper)` central credible interval of lambda at the given level (for
+    /// example `0.95`).
+    pub fn lambda_interval(&self, level: f64) -> (f64, f64) {
+        percentile_interval(&self.lambdas, level)
+    }
+    /// Return the total population at `step` (starting at 0 for the first step) for each matrix,
+    /// or `None` if the step was not projected.
+    pub fn totals_at(&self, step: usize) -> Option<Vec<f64>> {
+        if step >= self.step_count() {
+            return None;
+        }
+        self.totals.iter().map(|x| x.get(step).copied()).collect()
+    }
+    /// Return the `(lower, upper)` central credible interval of the total population at each
+    /// step.
+    pub fn total_intervals(&self, level: f64) -> Vec<(f64, f64)> {
+        (0..self.step_count())
+            .map_while(|step| self.totals_at(step))
+            .map(|totals| percentile_interval(&totals, level))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap()
+    }
+
+    fn prior() -> MatrixPrior {
+        MatrixPrior::build(
+            base(),
+            vec![
+                RatePrior::new(
+                    "fecundity",
+                    0,
+                    1,
+                    RateDistribution::LogNormal {
+                        mu: 2.0_f64.ln(),
+                        sigma: 0.1,
+                    },
+                )
+                .unwrap(),
+                RatePrior::new(
+                    "adult_survival",
+                    1,
+                    1,
+                    RateDistribution::Uniform {
+                        lower: 0.7,
+                        upper: 0.9,
+                    },
+                )
+                .unwrap(),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn draws_follow_the_priors() {
+        let sample = prior().sample(200, 5).unwrap();
+        assert_eq!(sample.len(), 200);
+        for matrix in sample.get_matrices() {
+            assert!((0.7..0.9).contains(&matrix[(1, 1)]));
+            assert!(matrix[(0, 1)] > 0.0);
+            // Elements without a prior keep their base values.
+            assert_eq!(matrix[(1, 0)], 0.5);
+        }
+        // The same seed gives the same sample.
+        assert_eq!(
+            prior().sample(3, 5).unwrap().get_matrices(),
+            prior().sample(3, 5).unwrap().get_matrices()
+        );
+    }
+
+    #[test]
+    fn overflowing_draws_are_errors() {
+        let huge = RateDistribution::LogNormal {
+            mu: 800.0,
+            sigma: 0.1,
+        };
+        let prior = MatrixPrior::build(
+            base(),
+            vec![RatePrior::new("fecundity", 0, 1, huge).unwrap()],
+        )
+        .unwrap();
+        assert!(prior.sample(2, 1).is_err());
+    }
+
+    #[test]
+    fn credible_intervals_widen_with_time() {
+        let projection = prior()
+            .sample(300, 11)
+            .unwrap()
+            .project(&PopulationVector::new(vec![10.0, 10.0]), 15)
+            .unwrap();
+        assert_eq!(projection.step_count(), 15);
+        assert!(projection.totals_at(14).is_some());
+        assert!(projection.totals_at(15).is_none());
+        let intervals = projection.total_intervals(0.9);
+        let width = |(lower, upper): (f64, f64)| upper - lower;
+        assert!(width(intervals[14]) > width(intervals[0]));
+        let (lower, upper) = projection.lambda_interval(0.9);
+        assert!(lower < base().lambda() && base().lambda() < upper);
+    }
+
+    #[test]
+    fn posterior_files_are_read_by_column_name() {
+        let file = "iteration;fecundity;adult_survival\n1;2,1;0,8\n2;1,9;0,82\n";
+        let sample = prior().read_posterior_csv(file.as_bytes()).unwrap();
+        assert_eq!(sample.get_matrices()[1][(0, 1)], 1.9);
+        assert_eq!(sample.get_matrices()[1][(1, 1)], 0.82);
+        let missing = prior()
+            .read_posterior_csv("fecundity\n2.0\n".as_bytes())
+            .unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "The posterior sample has no \"adult_survival\" column."
+        );
+        let negative = prior()
+            .read_posterior_csv("fecundity,adult_survival\n2.0,-0.1\n".as_bytes())
+            .unwrap_err();
+        assert!(negative.to_string().starts_with("Row 1: "));
+    }
+
+    #[test]
+    fn invalid_priors_are_rejected() {
+        let flat = RateDistribution::Uniform {
+            lower: 0.0,
+            upper: 1.0,
+        };
+        assert!(RatePrior::new(
+            "x",
+            0,
+            0,
+            RateDistribution::Beta {
+                alpha: 0.0,
+                beta: 1.0
+            }
+        )
+        .is_err());
+        assert!(RatePrior::new(
+            "x",
+            0,
+            0,
+            RateDistribution::Uniform {
+                lower: -1.0,
+                upper: 1.0
+            }
+        )
+        .is_err());
+        let x = RatePrior::new("x", 0, 0, flat).unwrap();
+        assert!(
+            MatrixPrior::build(base(), vec![RatePrior::new("y", 2, 0, flat).unwrap()]).is_err()
+        );
+        assert!(MatrixPrior::build(base(), vec![x.clone(), x]).is_err());
+        assert!(MatrixSample::from_matrices(vec![
+            base(),
+            PopulationMatrix::build(vec![vec![1.0]]).unwrap()
+        ])
+        .is_err());
+        assert!(MatrixSample::from_matrices(Vec::new())
+            .unwrap()
+            .project(&PopulationVector::new(vec![1.0, 1.0]), 5)
+            .is_err());
+    }
+}
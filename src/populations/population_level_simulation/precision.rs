@@ -0,0 +1,48 @@
+//! This module contains the [`Real`] trait, which lets Population Vectors and Population Matrices store either `f64` (the default) or `f32` values. Single precision halves the memory used by large matrices and stored trajectories when full precision is not needed; analyses such as eigen-analysis always use `f64`.
+//!
+//! Only the linear algebra is generic. The simulation engines (`DeterministicPva`,
+//! `StochasticPva`, and the individual-based models) and their outputs always work in `f64`,
+//! since density dependence, extinction criteria, and summaries are defined on `f64` values. A
+//! single-precision run projects a `PopulationVector<f32>` with a `PopulationMatrix<f32>`
+//! directly, and `f64` results can be cast to `f32` for storage.
+use std::{
+    fmt::Debug,
+    iter::Sum,
+    ops::{Add, Mul},
+};
+
+/// A floating point type that Population Vectors and Population Matrices can store. It is
+/// implemented for `f64` and `f32`.
+pub trait Real:
+    Copy
+    + Debug
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + Sum
+    + Send
+    + Sync
+    + 'static
+{
+    /// Convert an `f64` to this type, rounding if needed.
+    fn from_f64(value: f64) -> Self;
+    /// Convert this value to an `f64`.
+    fn to_f64(self) -> f64;
+}
+impl Real for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+impl Real for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
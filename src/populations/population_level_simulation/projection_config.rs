@@ -0,0 +1,568 @@
+//! This module contains the ProjectionConfig builder, which collects the settings of a projection (number of steps, replicates, seed, density dependence, extinction threshold, stopping rules, and output detail) so they can be passed to the simulation engines as one value.
+use super::{
+    keyfitz_delta, DensityDependence, DeterministicPva, ExtinctionCriterion, MatrixModelCore,
+    NumericFault, PopulationVector, PvaDeterministicOutput, PvaStochasticOutput,
+    PvaStochasticSummary, StochasticPva, Summation, Verbosity,
+};
+use crate::simulation::Simulation;
+use std::fmt;
+
+/// This enum describes a rule that stops a projection before its maximum number of steps.
+/// - `BelowThreshold`: stop when the total population falls to or below the value.
+/// - `AboveBound`: stop when the total population rises to or above the value.
+/// - `Converged`: stop when the stage structure is within the given tolerance (Keyfitz's delta,
+///   see [`keyfitz_delta`]) of the stable stage distribution. Stochastic projections use the
+///   stable stage distribution of the mean of their matrices (see
+///   [`StochasticPva::mean_matrix`]).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoppingRule {
+    BelowThreshold(f64),
+    AboveBound(f64),
+    Converged(f64),
+}
+
+/// This enum records why a projection stopped. `InvalidValue` means a lifestage became NaN,
+/// infinite, or negative (see [`NumericFault`]); the projection ends at the step before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    MaxSteps,
+    BelowThreshold,
+    AboveBound,
+    Converged,
+    InvalidValue,
+}
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            StopReason::MaxSteps => "max_steps",
+            StopReason::BelowThreshold => "below_threshold",
+            StopReason::AboveBound => "above_bound",
+            StopReason::Converged => "converged",
+            StopReason::InvalidValue => "invalid_value",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Return the reason to stop after reaching `popvector`, checking the rules in order, or `None`
+/// to keep going. `stable` is the stable stage distribution, if convergence can be checked.
+pub(super) fn check_rules(
+    rules: &[StoppingRule],
+    popvector: &PopulationVector,
+    stable: Option<&PopulationVector>,
+) -> Option<StopReason> {
+    let total = popvector.total();
+    rules.iter().find_map(|rule| match *rule {
+        StoppingRule::BelowThreshold(threshold) if total <= threshold => {
+            Some(StopReason::BelowThreshold)
+        }
+        StoppingRule::AboveBound(bound) if total >= bound => Some(StopReason::AboveBound),
+        StoppingRule::Converged(tolerance) => stable
+            .filter(|stable| keyfitz_delta(popvector, stable) <= tolerance)
+            .map(|_| StopReason::Converged),
+        _ => None,
+    })
+}
+
+/// This struct holds what the replicates of one projection share as they step: the model, the
+/// maximum number of steps, the stopping rules, and the stable stage distribution the
+/// `Converged` rule compares against. Every configured projection, record, summary, and checked
+/// projection steps through [`ReplicateRun::run`].
+pub(super) struct ReplicateRun<'a> {
+    core: &'a MatrixModelCore,
+    steps: u32,
+    rules: &'a [StoppingRule],
+    stable: Option<&'a PopulationVector>,
+}
+impl<'a> ReplicateRun<'a> {
+    /// Create a run of up to `steps` steps with no stopping rules.
+    pub(super) fn new(core: &'a MatrixModelCore, steps: u32) -> ReplicateRun<'a> {
+        ReplicateRun {
+            core,
+            steps,
+            rules: &[],
+            stable: None,
+        }
+    }
+    /// Return the run with stopping rules, and the stable stage distribution they may need.
+    pub(super) fn with_rules(
+        mut self,
+        rules: &'a [StoppingRule],
+        stable: Option<&'a PopulationVector>,
+    ) -> Self {
+        self.rules = rules;
+        self.stable = stable;
+        self
+    }
+    /// Run `replicate` (`None` for a deterministic projection) of `simulation`, passing each new
+    /// population and the position of the matrix used for it (from `matrix`) to `visit`, and
+    /// return the reason it stopped.
+    /// # Errors
+    /// Will return a [`NumericFault`] at the first population, including the initial one, that
+    /// holds a NaN, infinite, or negative value. That population is not passed to `visit`.
+    pub(super) fn run<S: Simulation<State = PopulationVector>>(
+        &self,
+        replicate: Option<u32>,
+        simulation: &mut S,
+        matrix: impl Fn(&S) -> usize,
+        mut visit: impl FnMut(&PopulationVector, usize),
+    ) -> Result<StopReason, NumericFault> {
+        self.core.check_initial(replicate)?;
+        for step in 1..=self.steps {
+            let previous = simulation.state().clone();
+            simulation.step();
+            let index = matrix(simulation);
+            self.core
+                .check_step(replicate, step, index, &previous, simulation.state())?;
+            visit(simulation.state(), index);
+            if let Some(reason) = check_rules(self.rules, simulation.state(), self.stable) {
+                return Ok(reason);
+            }
+        }
+        Ok(StopReason::MaxSteps)
+    }
+}
+
+/// This struct holds the settings of a projection. Start with [`ProjectionConfig::new`] and
+/// change only the settings that differ from the defaults:
+/// - `steps`: 100
+/// - `replicates`: 1 (ignored by deterministic models)
+/// - `seed`: 0 (ignored by deterministic models)
+/// - `density_dependence`: use the model's own density dependence
+/// - `extinction_threshold`: 0
+/// - `extinction_criteria`: none, so only the total population decides extinction
+/// - `stopping_rules`: none, so projections always run for `steps` steps
+/// - `verbosity`: [`Verbosity::Trajectories`], the detail of a record made with
+///   [`DeterministicPva::record`] or [`StochasticPva::record`], or of a summary made with
+///   [`StochasticPva::summarize`]. `project` always returns every step.
+/// - `summation`: use the model's own (see [`Summation`])
+/// ```
+/// use ecolysis_cmd::populations::population_level_simulation::{
+///     DensityDependence::Ceiling, ProjectionConfig, StochasticPva,
+/// };
+/// let population = StochasticPva::build_from_vectors(vec![12.0, 55.0, 172.0], vec![
+///     vec![vec![0.0, 0.0, 0.9], vec![0.6, 0.9, 0.0], vec![0.0, 0.95, 0.99]],
+///     vec![vec![0.0, 0.0, 0.4], vec![0.3, 0.7, 0.0], vec![0.0, 0.8, 0.9]],
+/// ]).unwrap();
+/// let config = ProjectionConfig::new()
+///     .steps(100)
+///     .replicates(1000)
+///     .seed(42)
+///     .density_dependence(Ceiling(500.0));
+/// let summary = population.summarize(&config).unwrap();
+/// assert_eq!(summary.step_count(), 100);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectionConfig {
+    steps: u32,
+    replicates: u32,
+    seed: u64,
+    density_dependence: Option<DensityDependence>,
+    extinction_threshold: f64,
+    extinction_criteria: Vec<ExtinctionCriterion>,
+    stopping_rules: Vec<StoppingRule>,
+    verbosity: Verbosity,
+    summation: Option<Summation>,
+}
+impl ProjectionConfig {
+    /// Create a ProjectionConfig with the default settings.
+    pub fn new() -> ProjectionConfig {
+        ProjectionConfig {
+            steps: 100,
+            replicates: 1,
+            seed: 0,
+            density_dependence: None,
+            extinction_threshold: 0.0,
+            extinction_criteria: Vec::new(),
+            stopping_rules: Vec::new(),
+            verbosity: Verbosity::Trajectories,
+            summation: None,
+        }
+    }
+    /// Set the number of steps to project.
+    pub fn steps(mut self, steps: u32) -> Self {
+        self.steps = steps;
+        self
+    }
+    /// Set the number of replicates of a stochastic projection.
+    pub fn replicates(mut self, replicates: u32) -> Self {
+        self.replicates = replicates;
+        self
+    }
+    /// Set the seed of the random number generator of a stochastic projection.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    /// Set the density dependence, replacing the model's own for this projection.
+    pub fn density_dependence(mut self, density_dependence: DensityDependence) -> Self {
+        self.density_dependence = Some(density_dependence);
+        self
+    }
+    /// Set the total population at or below which a replicate counts as extinct.
+    pub fn extinction_threshold(mut self, extinction_threshold: f64) -> Self {
+        self.extinction_threshold = extinction_threshold;
+        self
+    }
+    /// Add a criterion that also counts a replicate as extinct, such as a breeding stage falling
+    /// below a minimum viable number. A replicate is extinct when its total population reaches
+    /// the extinction threshold or it meets any criterion. Lifestages are checked against the
+    /// model when the projection runs.
+    /// # Errors
+    /// Will return `Err<'static str>` if the criterion is a stage group with no lifestages.
+    pub fn extinction_criterion(
+        mut self,
+        criterion: ExtinctionCriterion,
+    ) -> Result<Self, &'static str> {
+        criterion.check(usize::MAX)?;
+        self.extinction_criteria.push(criterion);
+        Ok(self)
+    }
+    /// Add a rule that stops the projection early. With several rules, the first one that applies
+    /// is reported as the stop reason. `steps` is always the maximum.
+    pub fn stop_when(mut self, rule: StoppingRule) -> Self {
+        self.stopping_rules.push(rule);
+        self
+    }
+    /// Set how much of the projection a record or summary keeps.
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+    /// Set how the matrices add up each projection, replacing the model's own for this
+    /// projection. [`Summation::Compensated`] limits round-off in very long projections.
+    pub fn summation(mut self, summation: Summation) -> Self {
+        self.summation = Some(summation);
+        self
+    }
+    /// Return the number of steps to project.
+    pub fn get_steps(&self) -> u32 {
+        self.steps
+    }
+    /// Return the number of replicates.
+    pub fn get_replicates(&self) -> u32 {
+        self.replicates
+    }
+    /// Return the seed.
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    /// Return the density dependence set for this projection, if any.
+    pub fn get_density_dependence(&self) -> Option<DensityDependence> {
+        self.density_dependence
+    }
+    /// Return the extinction threshold.
+    pub fn get_extinction_threshold(&self) -> f64 {
+        self.extinction_threshold
+    }
+    /// Return the extinction criteria.
+    pub fn get_extinction_criteria(&self) -> &Vec<ExtinctionCriterion> {
+        &self.extinction_criteria
+    }
+    /// Return the stopping rules.
+    pub fn get_stopping_rules(&self) -> &Vec<StoppingRule> {
+        &self.stopping_rules
+    }
+    /// Return how much of the projection a record or summary keeps.
+    pub fn get_verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+    /// Return true if a stopping rule needs the stable stage distribution.
+    pub(super) fn checks_convergence(&self) -> bool {
+        self.stopping_rules
+            .iter()
+            .any(|x| matches!(x, StoppingRule::Converged(_)))
+    }
+    /// Return the summation set for this projection, if any.
+    pub fn get_summation(&self) -> Option<Summation> {
+        self.summation
+    }
+}
+impl Default for ProjectionConfig {
+    fn default() -> Self {
+        ProjectionConfig::new()
+    }
+}
+
+impl DeterministicPva {
+    /// Return the output of a deterministic projection configured by a ProjectionConfig,
+    /// including the reason it stopped.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{
+    ///     DeterministicPva, ProjectionConfig, StopReason, StoppingRule,
+    /// };
+    /// let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///     vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let config = ProjectionConfig::new()
+    ///     .steps(1_000)
+    ///     .stop_when(StoppingRule::AboveBound(10_000.0));
+    /// let output = population.project(&config);
+    /// assert_eq!(output.get_stop_reason(), StopReason::AboveBound);
+    /// assert!(output.iter().count() < 1_000);
+    /// ```
+    pub fn project(&self, config: &ProjectionConfig) -> PvaDeterministicOutput {
+        let model = self.configured(config);
+        let stable = config
+            .checks_convergence()
+            .then(|| model.get_matrix().stable_stage_distribution());
+        let mut result = Vec::with_capacity(config.steps as usize);
+        let stop_reason = ReplicateRun::new(model.get_core(), config.steps)
+            .with_rules(&config.stopping_rules, stable.as_ref())
+            .run(
+                None,
+                &mut model.projection_iter(),
+                |_| 0,
+                |popvector, _| result.push(popvector.clone()),
+            )
+            .unwrap_or(StopReason::InvalidValue);
+        PvaDeterministicOutput::new(result)
+            .with_stop_reason(stop_reason)
+            .with_model_time_step(model.get_time_step())
+    }
+    pub(super) fn configured(&self, config: &ProjectionConfig) -> DeterministicPva {
+        let model = match config.density_dependence {
+            Some(density_dependence) => self.clone().with_density_dependence(density_dependence),
+            None => self.clone(),
+        };
+        match config.summation {
+            Some(summation) => model.with_summation(summation),
+            None => model,
+        }
+    }
+}
+
+impl StochasticPva {
+    /// Return every step of every replicate of a stochastic projection configured by a
+    /// ProjectionConfig. Each replicate stops on its own when a stopping rule applies, and the
+    /// reasons are recorded in the output.
+    pub fn project(&self, config: &ProjectionConfig) -> PvaStochasticOutput {
+        let model = self.configured(config);
+        let stable = config
+            .checks_convergence()
+            .then(|| model.mean_matrix().stable_stage_distribution());
+        let run = ReplicateRun::new(model.get_core(), config.steps)
+            .with_rules(&config.stopping_rules, stable.as_ref());
+        let mut replicates = Vec::with_capacity(config.replicates as usize);
+        let mut stop_reasons = Vec::with_capacity(config.replicates as usize);
+        for replicate in 0..config.replicates {
+            let mut steps = Vec::with_capacity(config.steps as usize);
+            let stop_reason = run
+                .run(
+                    Some(replicate),
+                    &mut model.replicate_iter(replicate, config.seed),
+                    |x| x.get_last_matrix().unwrap_or(0),
+                    |popvector, _| steps.push(popvector.clone()),
+                )
+                .unwrap_or(StopReason::InvalidValue);
+            replicates.push(steps);
+            stop_reasons.push(stop_reason);
+        }
+        PvaStochasticOutput::new(replicates)
+            .with_stop_reasons(stop_reasons)
+            .expect("Each replicate records one stop reason.")
+            .with_model_time_step(model.get_time_step())
+    }
+    /// Return a summary of a stochastic projection configured by a ProjectionConfig, as
+    /// [`StochasticPva::stochastic_summary`] does. Stopping rules are ignored, since the summary
+    /// needs every replicate to run for the same number of steps. At [`Verbosity::Final`] the
+    /// summary keeps the statistics of the last step only, though extinction is still checked at
+    /// every step.
+    /// # Errors
+    /// Will return `Err<'static str>` if an extinction criterion names a lifestage outside the
+    /// model.
+    pub fn summarize(
+        &self,
+        config: &ProjectionConfig,
+    ) -> Result<PvaStochasticSummary, &'static str> {
+        let lifestage_count = self.get_initial_population().get_lifestage_count() as usize;
+        for criterion in &config.extinction_criteria {
+            criterion.check(lifestage_count)?;
+        }
+        Ok(self.configured_summary(config))
+    }
+    /// Return the summary of [`StochasticPva::summarize`] without checking the extinction
+    /// criteria.
+    pub(super) fn configured_summary(&self, config: &ProjectionConfig) -> PvaStochasticSummary {
+        let model = self.configured(config);
+        let first_step = match config.verbosity {
+            Verbosity::Final => config.steps.max(1),
+            _ => 1,
+        };
+        let mut summary = PvaStochasticSummary::new(
+            config.steps + 1 - first_step,
+            model.get_initial_population().get_lifestage_count(),
+            config.replicates,
+            config.extinction_threshold,
+        )
+        .with_extinction_criteria(config.extinction_criteria.clone())
+        .starting_at(first_step);
+        if let Some(time_step) = model.get_time_step() {
+            summary = summary.with_time_step(time_step);
+        }
+        let run = ReplicateRun::new(model.get_core(), config.steps);
+        for replicate in 0..config.replicates {
+            let mut step = 0;
+            if let Err(fault) = run.run(
+                Some(replicate),
+                &mut model.replicate_iter(replicate, config.seed),
+                |x| x.get_last_matrix().unwrap_or(0),
+                |popvector, _| {
+                    step += 1;
+                    summary.push(replicate, step, popvector).expect("This error should not be possible. The summary is sized for the replicates and steps of the projection. Please file a bug report.");
+                },
+            ) {
+                summary.add_fault(fault);
+            }
+        }
+        summary
+    }
+    pub(super) fn configured(&self, config: &ProjectionConfig) -> StochasticPva {
+        let model = match config.density_dependence {
+            Some(density_dependence) => self.clone().with_density_dependence(density_dependence),
+            None => self.clone(),
+        };
+        match config.summation {
+            Some(summation) => model.with_summation(summation),
+            None => model,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_projection_matches_positional_arguments() {
+        let population = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.0], vec![0.3, 0.5]],
+            ],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new().steps(20).replicates(5).seed(9);
+        let totals = |output: PvaStochasticOutput| -> Vec<f64> {
+            output
+                .get_replicates()
+                .iter()
+                .flatten()
+                .map(|x| x.iter().sum())
+                .collect()
+        };
+        assert_eq!(
+            totals(population.project(&config)),
+            totals(population.stochastic_projection(20, 5, 9))
+        );
+    }
+    #[test]
+    fn config_density_dependence_overrides_model() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.8]],
+        )
+        .unwrap()
+        .with_density_dependence(DensityDependence::Ceiling(1_000.0));
+        let config = ProjectionConfig::new()
+            .steps(40)
+            .density_dependence(DensityDependence::Ceiling(50.0));
+        let output = population.project(&config);
+        assert!((output.iter().last().unwrap().iter().sum::<f64>() - 50.0).abs() < 1e-9);
+    }
+    #[test]
+    fn config_summation_applies_to_every_matrix() {
+        let population = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.0], vec![0.3, 0.5]],
+            ],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new().summation(Summation::Compensated);
+        assert!(population
+            .configured(&config)
+            .get_matrices()
+            .iter()
+            .all(|x| x.get_summation() == Summation::Compensated));
+        let configured = population.configured(&ProjectionConfig::new());
+        assert_eq!(
+            configured.get_matrices()[0].get_summation(),
+            Summation::Plain
+        );
+    }
+    #[test]
+    fn stopping_rules_report_reasons() {
+        let declining = DeterministicPva::build_from_vectors(
+            vec![100.0, 100.0],
+            vec![vec![0.0, 0.5], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new()
+            .steps(500)
+            .stop_when(StoppingRule::BelowThreshold(1.0))
+            .stop_when(StoppingRule::Converged(1e-9));
+        let output = declining.project(&config);
+        assert_eq!(output.get_stop_reason(), StopReason::Converged);
+        let config = ProjectionConfig::new()
+            .steps(500)
+            .stop_when(StoppingRule::BelowThreshold(1.0));
+        let output = declining.project(&config);
+        assert_eq!(output.get_stop_reason(), StopReason::BelowThreshold);
+        assert!(*output.total_population().last().unwrap() <= 1.0);
+        assert!(output.total_population().iter().rev().nth(1).unwrap() > &1.0);
+        let output = declining.project(&ProjectionConfig::new().steps(5));
+        assert_eq!(output.get_stop_reason(), StopReason::MaxSteps);
+    }
+    #[test]
+    fn stochastic_replicates_converge() {
+        let population = StochasticPva::build_from_vectors(
+            vec![100.0, 0.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.9], vec![0.5, 0.5]],
+            ],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new()
+            .steps(200)
+            .replicates(5)
+            .seed(1)
+            .stop_when(StoppingRule::Converged(0.05));
+        let output = population.project(&config);
+        assert!(output
+            .get_stop_reasons()
+            .iter()
+            .all(|x| *x == StopReason::Converged));
+        assert!(output.get_replicates().iter().all(|x| x.len() < 200));
+        assert!(PvaStochasticOutput::new(vec![Vec::new()])
+            .with_stop_reasons(Vec::new())
+            .is_err());
+    }
+    #[test]
+    fn stochastic_replicates_stop_independently() {
+        let population =
+            StochasticPva::build_from_vectors(vec![10.0], vec![vec![vec![0.5]], vec![vec![1.5]]])
+                .unwrap();
+        let config = ProjectionConfig::new()
+            .steps(200)
+            .replicates(20)
+            .seed(4)
+            .stop_when(StoppingRule::BelowThreshold(1.0))
+            .stop_when(StoppingRule::AboveBound(100.0));
+        let output = population.project(&config);
+        for (steps, reason) in output
+            .get_replicates()
+            .iter()
+            .zip(output.get_stop_reasons())
+        {
+            let total = steps.last().unwrap().total();
+            match reason {
+                StopReason::BelowThreshold => assert!(total <= 1.0),
+                StopReason::AboveBound => assert!(total >= 100.0),
+                _ => assert_eq!(steps.len(), 200),
+            }
+        }
+    }
+}
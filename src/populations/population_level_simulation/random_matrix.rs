@@ -0,0 +1,315 @@
+//! This module contains a generator of random but biologically plausible projection matrices, for classroom exercises and as test fixtures. Survival and fecundity are drawn from configurable ranges, survival out of each lifestage never exceeds one, and fecundity can be scaled so the matrix has a chosen population growth rate.
+use super::stochastic::replicate_seed;
+use super::PopulationMatrix;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The number of bisection steps used to scale fecundity to a target lambda.
+const LAMBDA_ITERATIONS: usize = 200;
+/// The number of times the fecundity scale may be doubled while looking for an upper bound on
+/// the scale that reaches a target lambda.
+const SCALE_DOUBLINGS: usize = 128;
+
+/// This enum describes the structure of a generated matrix:
+/// - `Leslie`: age classes. Individuals in each age class either survive to the next one or die,
+///   so survival is only on the subdiagonal, and none survive past the last age class.
+/// - `Lefkovitch`: size or stage classes. Surviving individuals either stay in their stage
+///   (the diagonal) or grow into the next one (the subdiagonal), and the last stage persists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatrixShape {
+    Leslie,
+    #[default]
+    Lefkovitch,
+}
+
+/// This struct holds the settings for generating random projection matrices. Every lifestage but
+/// the first reproduces, with fecundity (in the first row) drawn from the fecundity range, and
+/// the total survival out of each lifestage is drawn from the survival range. In a Lefkovitch
+/// matrix, each stage's survival is split at random between staying and growing.
+/// ```
+/// use ecolysis_cmd::populations::population_level_simulation::{MatrixShape, RandomMatrixGenerator};
+/// let generator = RandomMatrixGenerator::new(4)
+///     .unwrap()
+///     .with_shape(MatrixShape::Leslie)
+///     .with_survival_range(0.3, 0.8)
+///     .unwrap()
+///     .with_target_lambda(1.05)
+///     .unwrap();
+/// let matrix = generator.generate(7).unwrap();
+/// assert!((matrix.lambda() - 1.05).abs() < 1e-9);
+/// assert_eq!(matrix[(3, 3)], 0.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomMatrixGenerator {
+    lifestage_count: u8,
+    shape: MatrixShape,
+    survival: (f64, f64),
+    fecundity: (f64, f64),
+    target_lambda: Option<f64>,
+}
+impl RandomMatrixGenerator {
+    /// Create a generator of Lefkovitch matrices with the given number of lifestages, survival
+    /// between 0.2 and 0.9, fecundity between 0.5 and 5, and no target lambda.
+    /// # Errors
+    /// Will return `Err<'static str>` if there are fewer than two lifestages.
+    pub fn new(lifestage_count: u8) -> Result<RandomMatrixGenerator, &'static str> {
+        if lifestage_count < 2 {
+            return Err("A generated matrix needs at least two lifestages.");
+        }
+        Ok(RandomMatrixGenerator {
+            lifestage_count,
+            shape: MatrixShape::default(),
+            survival: (0.2, 0.9),
+            fecundity: (0.5, 5.0),
+            target_lambda: None,
+        })
+    }
+    /// Return the generator with the given matrix structure.
+    pub fn with_shape(mut self, shape: MatrixShape) -> Self {
+        self.shape = shape;
+        self
+    }
+    /// Return the generator with survival out of each lifestage drawn between `lower` and
+    /// `upper`.
+    /// # Errors
+    /// Will return `Err<'static str>` if the range is not within zero and one, or `lower` is
+    /// greater than `upper`.
+    pub fn with_survival_range(mut self, lower: f64, upper: f64) -> Result<Self, &'static str> {
+        if !(0.0..=1.0).contains(&lower) || !(0.0..=1.0).contains(&upper) || lower > upper {
+            return Err("The survival range must be within zero and one.");
+        }
+        self.survival = (lower, upper);
+        Ok(self)
+    }
+    /// Return the generator with the fecundity of each reproductive lifestage drawn between
+    /// `lower` and `upper`.
+    /// # Errors
+    /// Will return `Err<'static str>` if `lower` is negative, `upper` is not finite, or `lower` is
+    /// greater than `upper`.
+    pub fn with_fecundity_range(mut self, lower: f64, upper: f64) -> Result<Self, &'static str> {
+        if lower.is_nan() || lower < 0.0 || !upper.is_finite() || lower > upper {
+            return Err("The fecundity range must be non-negative and finite.");
+        }
+        self.fecundity = (lower, upper);
+        Ok(self)
+    }
+    /// Return the generator with fecundity scaled after drawing so that lambda equals `lambda`.
+    /// Survival is left as drawn, and all fecundities are multiplied by the same factor, so they
+    /// may end up outside the fecundity range.
+    /// # Errors
+    /// Will return `Err<'static str>` if `lambda` is not positive and finite.
+    pub fn with_target_lambda(mut self, lambda: f64) -> Result<Self, &'static str> {
+        if !lambda.is_finite() || lambda <= 0.0 {
+            return Err("The target lambda must be positive.");
+        }
+        self.target_lambda = Some(lambda);
+        Ok(self)
+    }
+    /// Return the number of lifestages of the generated matrices.
+    pub fn get_lifestage_count(&self) -> u8 {
+        self.lifestage_count
+    }
+    /// Return the structure of the generated matrices.
+    pub fn get_shape(&self) -> MatrixShape {
+        self.shape
+    }
+    /// Draw one matrix using the given random number generator.
+    /// # Errors
+    /// Will return `Err<'static str>` if a target lambda is set but cannot be reached by scaling
+    /// fecundity: either survival alone gives a higher lambda (lower the survival range or raise
+    /// the target), or no individual survives to reproduce (for example, if every drawn
+    /// fecundity or the survival of a stage is zero), or scaling fecundity would overflow before
+    /// the target is reached.
+    pub fn draw<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<PopulationMatrix, &'static str> {
+        let size = self.lifestage_count as usize;
+        let mut survival = vec![vec![0.0; size]; size];
+        let between =
+            |rng: &mut R, (lower, upper): (f64, f64)| lower + (upper - lower) * rng.gen::<f64>();
+        for stage in 0..size {
+            let total = between(rng, self.survival);
+            match self.shape {
+                MatrixShape::Leslie if stage + 1 < size => survival[stage + 1][stage] = total,
+                MatrixShape::Leslie => {}
+                MatrixShape::Lefkovitch if stage + 1 < size => {
+                    let growth = total * rng.gen::<f64>();
+                    survival[stage + 1][stage] = growth;
+                    survival[stage][stage] = total - growth;
+                }
+                MatrixShape::Lefkovitch => survival[stage][stage] = total,
+            }
+        }
+        let fecundity: Vec<f64> = (0..size)
+            .map(|stage| match stage {
+                0 => 0.0,
+                _ => between(rng, self.fecundity),
+            })
+            .collect();
+        let build = |scale: f64| {
+            let mut matrix = survival.clone();
+            for (stage, value) in fecundity.iter().enumerate() {
+                matrix[0][stage] += scale * value;
+            }
+            PopulationMatrix::build(matrix)
+        };
+        let Some(target) = self.target_lambda else {
+            return build(1.0);
+        };
+        if build(0.0)?.lambda() > target {
+            return Err("Survival alone gives a higher lambda than the target.");
+        }
+        // Scaling fecundity only raises lambda without bound if newborns can survive to a stage
+        // that reproduces.
+        let mut reached = vec![false; size];
+        reached[0] = true;
+        for _ in 0..size {
+            for from in 0..size {
+                for to in 0..size {
+                    if reached[from] && survival[to][from] > 0.0 {
+                        reached[to] = true;
+                    }
+                }
+            }
+        }
+        if !(0..size).any(|stage| reached[stage] && fecundity[stage] > 0.0) {
+            return Err(
+                "The target lambda cannot be reached because no newborns survive to reproduce.",
+            );
+        }
+        // Lambda increases with fecundity, so the scale that reaches the target is found by
+        // doubling an upper bound and then bisecting.
+        let (mut lower, mut upper) = (0.0, 1.0);
+        let mut doublings = 0;
+        while build(upper)?.lambda() < target {
+            if doublings == SCALE_DOUBLINGS {
+                return Err("The target lambda cannot be reached by scaling fecundity.");
+            }
+            lower = upper;
+            upper *= 2.0;
+            doublings += 1;
+        }
+        for _ in 0..LAMBDA_ITERATIONS {
+            let middle = (lower + upper) / 2.0;
+            if build(middle)?.lambda() < target {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+            if upper - lower <= f64::EPSILON * upper {
+                break;
+            }
+        }
+        build((lower + upper) / 2.0)
+    }
+    /// Draw one matrix from a random number generator seeded with `seed`, so the same seed always
+    /// gives the same matrix.
+    /// # Errors
+    /// Will return `Err<'static str>` if a target lambda is set but cannot be reached (see
+    /// [`RandomMatrixGenerator::draw`]).
+    pub fn generate(&self, seed: u64) -> Result<PopulationMatrix, &'static str> {
+        self.draw(&mut StdRng::seed_from_u64(seed))
+    }
+    /// Draw `count` matrices, each from its own random number generator derived from `seed`.
+    /// # Errors
+    /// Will return `Err<'static str>` if a target lambda is set but cannot be reached for any of
+    /// the matrices (see [`RandomMatrixGenerator::draw`]).
+    pub fn generate_many(
+        &self,
+        count: u32,
+        seed: u64,
+    ) -> Result<Vec<PopulationMatrix>, &'static str> {
+        (0..count)
+            .map(|index| self.generate(replicate_seed(seed, index)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survival_out_of_each_stage_is_in_range() {
+        for shape in [MatrixShape::Leslie, MatrixShape::Lefkovitch] {
+            let generator = RandomMatrixGenerator::new(5)
+                .unwrap()
+                .with_shape(shape)
+                .with_survival_range(0.4, 0.7)
+                .unwrap();
+            for matrix in generator.generate_many(50, 3).unwrap() {
+                for stage in 0..5 {
+                    // The first row holds fecundity, except for staying in the first stage.
+                    let mut survival: f64 = (1..5).map(|row| matrix[(row, stage)]).sum();
+                    if stage == 0 {
+                        survival += matrix[(0, 0)];
+                    } else {
+                        assert!((0.5..=5.0).contains(&matrix[(0, stage)]));
+                    }
+                    if shape == MatrixShape::Leslie && stage == 4 {
+                        assert_eq!(survival, 0.0);
+                    } else {
+                        assert!((0.4 - 1e-12..=0.7 + 1e-12).contains(&survival));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn target_lambda_is_reached() {
+        let generator = RandomMatrixGenerator::new(3)
+            .unwrap()
+            .with_target_lambda(0.9)
+            .unwrap();
+        for matrix in generator.generate_many(20, 8).unwrap() {
+            assert!((matrix.lambda() - 0.9).abs() < 1e-9);
+        }
+        assert_eq!(generator.generate(1), generator.generate(1));
+        assert_ne!(generator.generate(1), generator.generate(2));
+    }
+
+    #[test]
+    fn unreachable_targets_are_reported() {
+        // A Lefkovitch matrix whose last stage survives with probability 0.99 cannot decline
+        // faster than that.
+        let persistent = RandomMatrixGenerator::new(2)
+            .unwrap()
+            .with_survival_range(0.99, 0.99)
+            .unwrap()
+            .with_target_lambda(0.5)
+            .unwrap();
+        assert!(persistent.generate(0).is_err());
+        let barren = RandomMatrixGenerator::new(2)
+            .unwrap()
+            .with_fecundity_range(0.0, 0.0)
+            .unwrap()
+            .with_target_lambda(1.2)
+            .unwrap();
+        assert!(barren.generate(0).is_err());
+        let sterile = RandomMatrixGenerator::new(3)
+            .unwrap()
+            .with_shape(MatrixShape::Leslie)
+            .with_survival_range(0.0, 0.0)
+            .unwrap()
+            .with_target_lambda(1.0)
+            .unwrap();
+        assert!(sterile.generate(0).is_err());
+        // Reaching this target would need fecundity far beyond any finite number.
+        let distant = RandomMatrixGenerator::new(2)
+            .unwrap()
+            .with_survival_range(1e-300, 1e-300)
+            .unwrap()
+            .with_target_lambda(1e300)
+            .unwrap();
+        assert!(distant.generate(0).is_err());
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(RandomMatrixGenerator::new(1).is_err());
+        let generator = RandomMatrixGenerator::new(3).unwrap();
+        assert!(generator.clone().with_survival_range(0.5, 1.5).is_err());
+        assert!(generator.clone().with_survival_range(0.8, 0.2).is_err());
+        assert!(generator.clone().with_fecundity_range(-1.0, 2.0).is_err());
+        assert!(generator.clone().with_target_lambda(0.0).is_err());
+        assert!(generator.with_target_lambda(f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! This module contains time-varying deterministic projections, where vital rates change at set steps (for example, when harvest ends or a management action begins) or change gradually from one matrix to another (as during habitat degradation or restoration), and the analysis of population momentum and transient dynamics after such a change.
+use super::{PopulationMatrix, PopulationVector, PvaDeterministicOutput};
+
+/// The ways a schedule can change gradually from one matrix to another (see
+/// [`MatrixSchedule::change_gradually`]).
+/// - `Linear`: each element changes by the same amount every step.
+/// - `Geometric`: each element changes by the same factor every step, which suits rates that
+///   decline or recover proportionally, such as survival under steady habitat loss. Elements that
+///   are zero in one of the two matrices cannot change by a constant factor, so they change
+///   linearly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Geometric,
+}
+impl Interpolation {
+    /// Return the value a fraction `t` (from zero to one) of the way from `from` to `to`.
+    fn between(self, from: f64, to: f64, t: f64) -> f64 {
+        match self {
+            Interpolation::Geometric if from > 0.0 && to > 0.0 => from.powf(1.0 - t) * to.powf(t),
+            _ => from + (to - from) * t,
+        }
+    }
+}
+
+/// This struct stores the Population Matrices used by a time-varying projection: a starting
+/// matrix, and the steps at which each later matrix replaces it. A change at step `k` means the
+/// population at step `k` is projected by the new matrix, so step `k + 1` is the first one
+/// produced by it.
+/// ```
+/// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+/// use ecolysis_cmd::populations::population_level_simulation::MatrixSchedule;
+/// let harvested = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.4, 0.5]]).unwrap();
+/// let protected = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+/// let schedule = MatrixSchedule::new(harvested).change_at(10, protected).unwrap();
+/// let output = schedule.project(&PopulationVector::new(vec![50.0, 50.0]), 30).unwrap();
+/// assert_eq!(output.get_interventions(), &vec![10]);
+/// ```
+#[derive(Clone)]
+pub struct MatrixSchedule {
+    matrices: Vec<(u32, PopulationMatrix)>,
+}
+impl MatrixSchedule {
+    /// Create a schedule that uses one matrix from step 0 until the first change.
+    pub fn new(initial: PopulationMatrix) -> MatrixSchedule {
+        MatrixSchedule {
+            matrices: vec![(0, initial)],
+        }
+    }
+    /// Return the schedule with the matrix replaced from the given step onward.
+    /// # Errors
+    /// Will return `Err<'static str>` if the step is not after the previous change, or if the
+    /// matrix has a different number of lifestages than the starting matrix.
+    pub fn change_at(mut self, step: u32, matrix: PopulationMatrix) -> Result<Self, &'static str> {
+        let (last_step, first) = (
+            self.matrices[self.matrices.len() - 1].0,
+            &self.matrices[0].1,
+        );
+        if step <= last_step {
+            return Err("Matrix changes must be at increasing steps after step 0.");
+        }
+        if matrix.get_lifestage_count() != first.get_lifestage_count() {
+            return Err("All matrices in a schedule must have the same number of lifestages.");
+        }
+        self.matrices.push((step, matrix));
+        Ok(self)
+    }
+    /// Create a schedule that changes gradually from `from` to `to` over `steps` steps: step 0
+    /// uses `from`, each following step moves one `steps`th of the way towards `to`, and `to` is
+    /// used from step `steps` onward.
+    /// # Errors
+    /// Will return `Err<'static str>` if `steps` is zero or the matrices have different numbers
+    /// of lifestages.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::{Interpolation, MatrixSchedule};
+    /// let intact = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let degraded = PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.3, 0.6]]).unwrap();
+    /// let schedule = MatrixSchedule::interpolate(intact, degraded, 4, Interpolation::Linear).unwrap();
+    /// assert_eq!(schedule.matrix_at(2)[(0, 1)], 1.5);
+    /// assert_eq!(schedule.matrix_at(10)[(0, 1)], 1.0);
+    /// ```
+    pub fn interpolate(
+        from: PopulationMatrix,
+        to: PopulationMatrix,
+        steps: u32,
+        interpolation: Interpolation,
+    ) -> Result<MatrixSchedule, &'static str> {
+        MatrixSchedule::new(from).change_gradually(0, steps, to, interpolation)
+    }
+    /// Return the schedule changing gradually from the matrix in use at step `start` to `target`
+    /// over `steps` steps, reaching `target` at step `start + steps` and keeping it afterwards.
+    /// The matrix changes at every step in between, so each is recorded as an intervention in
+    /// projections.
+    /// # Errors
+    /// Will return `Err<'static str>` if `steps` is zero, `start` is before the previous change,
+    /// or `target` has a different number of lifestages than the starting matrix.
+    pub fn change_gradually(
+        mut self,
+        start: u32,
+        steps: u32,
+        target: PopulationMatrix,
+        interpolation: Interpolation,
+    ) -> Result<Self, &'static str> {
+        if steps == 0 {
+            return Err("A gradual change must take at least one step.");
+        }
+        let from = self.matrix_at(start).clone();
+        if target.get_lifestage_count() != from.get_lifestage_count() {
+            return Err("All matrices in a schedule must have the same number of lifestages.");
+        }
+        let end = start
+            .checked_add(steps)
+            .ok_or("The gradual change ends after the last possible step.")?;
+        for step in start + 1..end {
+            let t = f64::from(step - start) / f64::from(steps);
+            let matrix = from
+                .rows()
+                .zip(target.rows())
+                .map(|(from, to)| {
+                    from.iter()
+                        .zip(to)
+                        .map(|(from, to)| interpolation.between(*from, *to, t))
+                        .collect()
+                })
+                .collect();
+            self = self.change_at(step, PopulationMatrix::build(matrix)?)?;
+        }
+        self.change_at(end, target)
+    }
+    /// Return the matrix that projects the population from `step` to `step + 1`.
+    pub fn matrix_at(&self, step: u32) -> &PopulationMatrix {
+        &self
+            .matrices
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= step)
+            .expect("The schedule always starts at step 0.")
+            .1
+    }
+    /// Return the steps at which the matrix changes.
+    pub fn get_change_steps(&self) -> Vec<u32> {
+        self.matrices[1..].iter().map(|(step, _)| *step).collect()
+    }
+    /// Project a Population Vector through the schedule for the given number of steps. The
+    /// output records the change steps as interventions (see
+    /// [`PvaDeterministicOutput::get_interventions`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector and matrices have different numbers of
+    /// lifestages.
+    pub fn project(
+        &self,
+        initial: &PopulationVector,
+        steps: u32,
+    ) -> Result<PvaDeterministicOutput, &'static str> {
+        let mut result = Vec::with_capacity(steps as usize);
+        let mut active_vector = initial.clone();
+        for step in 0..steps {
+            active_vector = self.matrix_at(step).project_vector(&active_vector)?;
+            result.push(active_vector.clone());
+        }
+        Ok(PvaDeterministicOutput::new(result).with_interventions(self.get_change_steps()))
+    }
+    /// Project a Population Vector through the schedule and measure the response to the last
+    /// matrix change (see [`TransientAnalysis`]). If the schedule has no changes, the response is
+    /// measured from step 0.
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector and matrices have different numbers of
+    /// lifestages, or if the projection does not run past the last change.
+    pub fn transient_analysis(
+        &self,
+        initial: &PopulationVector,
+        steps: u32,
+    ) -> Result<TransientAnalysis, &'static str> {
+        let (change, matrix) = &self.matrices[self.matrices.len() - 1];
+        if steps <= *change {
+            return Err("The projection must run past the last matrix change.");
+        }
+        let output = self.project(initial, steps)?;
+        let at_change = match change {
+            0 => initial.clone(),
+            step => output.return_typed_output()[*step as usize - 1].clone(),
+        };
+        let lambda = matrix.lambda();
+        let stable = matrix.stable_stage_distribution();
+        let reproductive_values = matrix.reproductive_values();
+        let weighted = |vector: &PopulationVector| -> f64 {
+            vector
+                .iter()
+                .zip(&reproductive_values)
+                .map(|(n, v)| n * v)
+                .sum()
+        };
+        let momentum = weighted(&at_change) / weighted(&stable) / at_change.total();
+        let relative_trajectory: Vec<f64> = output.total_population()[*change as usize..]
+            .iter()
+            .zip(1..)
+            .map(|(total, t)| total / (lambda.powi(t) * at_change.total()))
+            .collect();
+        Ok(TransientAnalysis {
+            lambda,
+            momentum,
+            reactivity: relative_trajectory[0],
+            max_amplification: relative_trajectory.iter().copied().fold(f64::MIN, f64::max),
+            max_attenuation: relative_trajectory.iter().copied().fold(f64::MAX, f64::min),
+            relative_trajectory,
+            output,
+        })
+    }
+}
+
+/// This struct stores the response of a population to a change in vital rates, measured
+/// relative to the growth expected from the new matrix alone. Each value compares the
+/// population to `N_k * lambda^t`, the size it would reach `t` steps after the change at step
+/// `k` if it were already at the new stable stage distribution, so a population that is
+/// unaffected by its starting structure stays at one.
+/// - `lambda`: the asymptotic growth rate of the new matrix.
+/// - `momentum`: the value the relative trajectory approaches over time. Above one, the
+///   population keeps growing (relative to lambda) after the change; below one, it keeps
+///   shrinking. With a lambda of one, this is the ratio of the final population to the
+///   population at the change, as in Keyfitz's population momentum.
+/// - `reactivity`: the relative population one step after the change.
+/// - `max_amplification` and `max_attenuation`: the largest and smallest relative population
+///   after the change within the projection.
+/// - `relative_trajectory`: the relative population at each step after the change.
+/// - `output`: the full projection, with the change steps recorded as interventions.
+pub struct TransientAnalysis {
+    pub lambda: f64,
+    pub momentum: f64,
+    pub reactivity: f64,
+    pub max_amplification: f64,
+    pub max_attenuation: f64,
+    pub relative_trajectory: Vec<f64>,
+    pub output: PvaDeterministicOutput,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn harvested() -> PopulationMatrix {
+        PopulationMatrix::build(vec![vec![0.0, 0.6], vec![0.5, 0.5]]).unwrap()
+    }
+    fn stationary() -> PopulationMatrix {
+        // Lambda is exactly one: 0.5 * 1.0 / (1 - 0.5) = 1.
+        PopulationMatrix::build(vec![vec![0.0, 1.0], vec![0.5, 0.5]]).unwrap()
+    }
+
+    #[test]
+    fn schedule_rejects_invalid_changes() {
+        let schedule = MatrixSchedule::new(harvested());
+        assert!(schedule.clone().change_at(0, stationary()).is_err());
+        let three = PopulationMatrix::build(vec![vec![0.0; 3]; 3]).unwrap();
+        assert!(schedule.clone().change_at(5, three).is_err());
+        let changed = schedule.change_at(5, stationary()).unwrap();
+        assert!(changed.clone().change_at(5, harvested()).is_err());
+        assert_eq!(changed.matrix_at(4)[(0, 1)], 0.6);
+        assert_eq!(changed.matrix_at(5)[(0, 1)], 1.0);
+    }
+    #[test]
+    fn gradual_changes() {
+        let schedule =
+            MatrixSchedule::interpolate(harvested(), stationary(), 2, Interpolation::Geometric)
+                .unwrap();
+        assert_eq!(schedule.get_change_steps(), vec![1, 2]);
+        assert!((schedule.matrix_at(1)[(0, 1)] - 0.6_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(schedule.matrix_at(2), &stationary());
+        let restored = MatrixSchedule::new(harvested())
+            .change_at(3, stationary())
+            .unwrap()
+            .change_gradually(5, 4, harvested(), Interpolation::Linear)
+            .unwrap();
+        assert_eq!(restored.get_change_steps(), vec![3, 6, 7, 8, 9]);
+        assert!((restored.matrix_at(7)[(0, 1)] - 0.8).abs() < 1e-12);
+        assert_eq!(restored.matrix_at(9), &harvested());
+        let zero = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.5]]).unwrap();
+        let linear =
+            MatrixSchedule::interpolate(harvested(), zero, 2, Interpolation::Geometric).unwrap();
+        assert!((linear.matrix_at(1)[(0, 1)] - 0.3).abs() < 1e-12);
+        assert!(
+            MatrixSchedule::interpolate(harvested(), stationary(), 0, Interpolation::Linear)
+                .is_err()
+        );
+        assert!(restored
+            .change_gradually(2, 2, stationary(), Interpolation::Linear)
+            .is_err());
+    }
+    #[test]
+    fn momentum_matches_long_run_projection() {
+        let schedule = MatrixSchedule::new(harvested())
+            .change_at(10, stationary())
+            .unwrap();
+        let analysis = schedule
+            .transient_analysis(&PopulationVector::new(vec![100.0, 20.0]), 400)
+            .unwrap();
+        assert!((analysis.lambda - 1.0).abs() < 1e-9);
+        let last = analysis.relative_trajectory.last().unwrap();
+        assert!((analysis.momentum - last).abs() < 1e-6);
+        assert_eq!(analysis.relative_trajectory.len(), 390);
+        assert!(analysis.max_amplification >= analysis.momentum);
+        assert!(schedule
+            .transient_analysis(&PopulationVector::new(vec![100.0, 20.0]), 10)
+            .is_err());
+    }
+}
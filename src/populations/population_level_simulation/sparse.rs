@@ -0,0 +1,185 @@
+//! This module contains a compressed sparse row (CSR) copy of a Population Matrix, used to speed up projection of large matrices that are mostly zeros, such as Leslie matrices with many age classes and integral projection models with narrow growth kernels.
+use super::{summation::compensated_sum, PopulationMatrix, Real};
+
+/// Matrices with fewer lifestages than this are always projected densely, since skipping zeros
+/// does not pay for itself in small matrices.
+pub const SPARSE_MIN_LIFESTAGES: usize = 16;
+/// The fraction of a matrix's values that must be zero for it to be projected sparsely.
+pub const SPARSE_ZERO_FRACTION: f64 = 0.75;
+
+/// This struct stores the non-zero values of a square matrix in compressed sparse row (CSR)
+/// form: the values and their column indices row by row, and the position in those lists where
+/// each row starts. Projecting a vector only visits the non-zero values.
+///
+/// A Population Matrix builds one of these automatically when it has at least
+/// [`SPARSE_MIN_LIFESTAGES`] lifestages and at least [`SPARSE_ZERO_FRACTION`] of its values are
+/// zero.
+/// ```
+/// use ecolysis_cmd::populations::SparseMatrix;
+/// let sparse = SparseMatrix::from_dense(&[vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+/// assert_eq!(sparse.nonzero_count(), 2);
+/// assert_eq!(sparse.project(&[10.0, 4.0]), Ok(vec![8.0, 5.0]));
+/// assert!(sparse.project(&[10.0]).is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SparseMatrix<T = f64> {
+    values: Vec<T>,
+    columns: Vec<usize>,
+    row_starts: Vec<usize>,
+}
+impl<T: Real> SparseMatrix<T> {
+    /// Build a sparse matrix from the rows of a dense matrix, keeping only non-zero values.
+    /// # Errors
+    /// Will return `Err<'static str>` if the matrix is not square.
+    pub fn from_dense(matrix: &[Vec<T>]) -> Result<SparseMatrix<T>, &'static str> {
+        if matrix.iter().any(|row| row.len() != matrix.len()) {
+            return Err("A sparse matrix must be square.");
+        }
+        Ok(SparseMatrix::from_rows(matrix.iter().map(Vec::as_slice)))
+    }
+    /// Build a sparse matrix from rows given as slices, keeping only non-zero values. The rows
+    /// must make a square matrix.
+    fn from_rows<'a>(rows: impl Iterator<Item = &'a [T]>) -> SparseMatrix<T>
+    where
+        T: 'a,
+    {
+        let mut values = Vec::new();
+        let mut columns = Vec::new();
+        let mut row_starts = vec![0];
+        for row in rows {
+            for (column, value) in row.iter().enumerate() {
+                if *value != T::default() {
+                    values.push(*value);
+                    columns.push(column);
+                }
+            }
+            row_starts.push(values.len());
+        }
+        SparseMatrix {
+            values,
+            columns,
+            row_starts,
+        }
+    }
+    /// Return the number of non-zero values stored.
+    pub fn nonzero_count(&self) -> usize {
+        self.values.len()
+    }
+    /// Return the number of rows (lifestages) in the matrix.
+    pub fn row_count(&self) -> usize {
+        self.row_starts.len() - 1
+    }
+    /// Multiply a vector by the matrix.
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector does not have one value per row.
+    pub fn project(&self, vector: &[T]) -> Result<Vec<T>, &'static str> {
+        if vector.len() != self.row_count() {
+            return Err("The vector must have one value per row of the sparse matrix.");
+        }
+        Ok(self
+            .row_starts
+            .windows(2)
+            .map(|bounds| {
+                self.values[bounds[0]..bounds[1]]
+                    .iter()
+                    .zip(&self.columns[bounds[0]..bounds[1]])
+                    .fold(T::default(), |total, (value, column)| {
+                        total + *value * vector[*column]
+                    })
+            })
+            .collect())
+    }
+    /// Project a Population Vector like [`SparseMatrix::project`], adding up each row with
+    /// compensated summation (see [`Summation`](super::Summation)).
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector does not have one value per row.
+    pub(crate) fn project_compensated(&self, vector: &[T]) -> Result<Vec<T>, &'static str> {
+        if vector.len() != self.row_count() {
+            return Err("The vector must have one value per row of the sparse matrix.");
+        }
+        Ok(self
+            .row_starts
+            .windows(2)
+            .map(|bounds| {
+                compensated_sum(
+                    self.values[bounds[0]..bounds[1]]
+                        .iter()
+                        .zip(&self.columns[bounds[0]..bounds[1]])
+                        .map(|(value, column)| (*value, vector[*column])),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Return a sparse copy of a matrix with `size` lifestages, given its row-major `elements`, if it
+/// is large and sparse enough to benefit from one.
+pub(crate) fn sparse_if_worthwhile<T: Real>(
+    elements: &[T],
+    size: usize,
+) -> Option<SparseMatrix<T>> {
+    if size < SPARSE_MIN_LIFESTAGES {
+        return None;
+    }
+    let sparse = SparseMatrix::from_rows(elements.chunks_exact(size));
+    let zero_fraction = 1.0 - sparse.nonzero_count() as f64 / (size * size) as f64;
+    (zero_fraction >= SPARSE_ZERO_FRACTION).then_some(sparse)
+}
+
+impl<T: Real> PopulationMatrix<T> {
+    /// Return the sparse copy of the matrix used for projection, if the matrix is large and
+    /// sparse enough to have one.
+    pub fn get_sparse(&self) -> Option<&SparseMatrix<T>> {
+        self.sparse.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::PopulationVector;
+
+    fn leslie_matrix(size: usize) -> Vec<Vec<f64>> {
+        let mut matrix = vec![vec![0.0; size]; size];
+        for (column, fecundity) in matrix[0].iter_mut().enumerate().skip(2) {
+            *fecundity = 0.1 * column as f64;
+        }
+        for row in 1..size {
+            matrix[row][row - 1] = 0.85;
+        }
+        matrix
+    }
+
+    #[test]
+    fn large_leslie_matrix_is_sparse() {
+        let popmatrix = PopulationMatrix::build(leslie_matrix(100)).unwrap();
+        let sparse = popmatrix.get_sparse().unwrap();
+        assert_eq!(sparse.nonzero_count(), 98 + 99);
+        assert_eq!(sparse.row_count(), 100);
+        let small = PopulationMatrix::build(leslie_matrix(8)).unwrap();
+        assert!(small.get_sparse().is_none());
+        let dense = PopulationMatrix::build(vec![vec![1.0; 20]; 20]).unwrap();
+        assert!(dense.get_sparse().is_none());
+    }
+    #[test]
+    fn non_square_matrix_is_rejected() {
+        assert!(SparseMatrix::from_dense(&[vec![0.0, 0.0, 1.0]]).is_err());
+        assert!(SparseMatrix::from_dense(&[vec![0.0, 1.0], vec![1.0]]).is_err());
+        let sparse = SparseMatrix::from_dense(&[vec![0.0, 1.0], vec![1.0, 0.0]]).unwrap();
+        assert!(sparse.project_compensated(&[1.0]).is_err());
+    }
+    #[test]
+    fn sparse_projection_matches_dense() {
+        let rows = leslie_matrix(60);
+        let values: Vec<f64> = (0..60).map(|x| 100.0 - x as f64).collect();
+        let expected: Vec<f64> = rows
+            .iter()
+            .map(|row| row.iter().zip(&values).map(|(a, x)| a * x).sum())
+            .collect();
+        let popmatrix = PopulationMatrix::build(rows).unwrap();
+        let projected = &popmatrix * &PopulationVector::new(values);
+        for (x, y) in projected.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+}
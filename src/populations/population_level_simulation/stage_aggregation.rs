@@ -0,0 +1,338 @@
+//! This module contains tools for changing the stage resolution of a model: collapsing several lifestages into one, or splitting a lifestage that covers a range of ages into finer classes. They are used to reconcile matrices built with different stage definitions, for example before comparing a published five-stage matrix with a locally estimated three-stage one.
+//!
+//! Stages are collapsed with the method of Salguero-Gómez and Plotkin (2010): each transition out of a group is the average of the transitions out of its lifestages, weighted by how many individuals are in each lifestage. With the stable stage distribution as the weights (the default), the collapsed matrix has the same lambda as the original.
+use super::{DecomposedMatrix, PopulationMatrix, PopulationVector};
+
+/// Check that `groups` assigns every one of `size` lifestages to exactly one non-empty group.
+fn check_groups(groups: &[Vec<usize>], size: usize) -> Result<(), &'static str> {
+    if groups.iter().any(|x| x.is_empty()) {
+        return Err("Each group of lifestages must contain at least one lifestage.");
+    }
+    let mut seen = vec![false; size];
+    for stage in groups.iter().flatten() {
+        match seen.get_mut(*stage) {
+            None => return Err("A lifestage in a group is outside the matrix."),
+            Some(true) => return Err("Each lifestage must be in only one group."),
+            Some(x) => *x = true,
+        }
+    }
+    if seen.contains(&false) {
+        return Err("Every lifestage must be in a group.");
+    }
+    Ok(())
+}
+
+/// Collapse a square matrix into one row and column per group, weighting the columns within each
+/// group by `weights`. The groups and weights must already have been checked.
+fn collapse(matrix: &[Vec<f64>], groups: &[Vec<usize>], weights: &[f64]) -> Vec<Vec<f64>> {
+    groups
+        .iter()
+        .map(|to| {
+            groups
+                .iter()
+                .map(|from| {
+                    let total: f64 = from.iter().map(|column| weights[*column]).sum();
+                    from.iter()
+                        .map(|column| {
+                            let moved: f64 = to.iter().map(|row| matrix[*row][*column]).sum();
+                            moved * weights[*column] / total
+                        })
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Check the weights used to collapse a matrix with `size` lifestages into `groups`.
+fn check_weights(
+    weights: &PopulationVector,
+    groups: &[Vec<usize>],
+    size: usize,
+) -> Result<(), &'static str> {
+    if weights.get_lifestage_count() as usize != size {
+        return Err("Population vector size does not match the matrix.");
+    }
+    if weights.iter().any(|x| !x.is_finite() || *x < 0.0) {
+        return Err("Weights cannot be negative.");
+    }
+    let weights = weights.get_vector();
+    if groups
+        .iter()
+        .any(|group| group.iter().map(|x| weights[*x]).sum::<f64>() <= 0.0)
+    {
+        return Err("Each group of lifestages must have a positive total weight.");
+    }
+    Ok(())
+}
+
+impl PopulationMatrix {
+    /// Collapse groups of lifestages into single lifestages, weighting each lifestage within a
+    /// group by the stable stage distribution so that lambda does not change. `groups` lists the
+    /// lifestages (numbered from zero) that make up each new lifestage, in the order of the new
+    /// lifestages; every lifestage must be in exactly one group.
+    /// # Errors
+    /// Will return `Err<'static str>` if a group is empty, a lifestage is missing, repeated, or
+    /// outside the matrix, or the stable stage distribution is zero for every lifestage in a
+    /// group.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// let matrix = PopulationMatrix::build(vec![
+    ///     vec![0.0, 0.0, 2.0],
+    ///     vec![0.5, 0.3, 0.0],
+    ///     vec![0.0, 0.4, 0.9],
+    /// ]).unwrap();
+    /// // Combine the two juvenile lifestages.
+    /// let collapsed = matrix.collapse_stages(&[vec![0, 1], vec![2]]).unwrap();
+    /// assert_eq!(collapsed.get_lifestage_count(), 2);
+    /// assert!((collapsed.lambda() - matrix.lambda()).abs() < 1e-9);
+    /// ```
+    pub fn collapse_stages(&self, groups: &[Vec<usize>]) -> Result<PopulationMatrix, &'static str> {
+        check_groups(groups, self.get_lifestage_count() as usize)?;
+        self.collapse_stages_with_weights(groups, &self.stable_stage_distribution())
+    }
+    /// Collapse groups of lifestages into single lifestages (as for
+    /// [`PopulationMatrix::collapse_stages`]), weighting each lifestage within a group by
+    /// `weights`, such as an observed Population Vector. Lambda is only preserved when the
+    /// weights are proportional to the stable stage distribution within each group.
+    /// # Errors
+    /// Will return `Err<'static str>` if the groups are invalid, the weights do not match the
+    /// matrix or are negative, or every lifestage in a group has a weight of zero.
+    pub fn collapse_stages_with_weights(
+        &self,
+        groups: &[Vec<usize>],
+        weights: &PopulationVector,
+    ) -> Result<PopulationMatrix, &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        check_groups(groups, size)?;
+        check_weights(weights, groups, size)?;
+        PopulationMatrix::build(collapse(&self.get_matrix(), groups, weights.get_vector()))
+    }
+}
+
+impl PopulationVector {
+    /// Collapse groups of lifestages into single lifestages by adding up the individuals in each
+    /// group (see [`PopulationMatrix::collapse_stages`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if a group is empty, or a lifestage is missing, repeated,
+    /// or outside the vector.
+    /// ```
+    /// use ecolysis_cmd::PopulationVector;
+    /// let vector = PopulationVector::new(vec![30.0, 20.0, 5.0]);
+    /// let collapsed = vector.collapse_stages(&[vec![0, 1], vec![2]]).unwrap();
+    /// assert_eq!(collapsed, PopulationVector::new(vec![50.0, 5.0]));
+    /// ```
+    pub fn collapse_stages(&self, groups: &[Vec<usize>]) -> Result<PopulationVector, &'static str> {
+        check_groups(groups, self.get_lifestage_count() as usize)?;
+        let vector = self.get_vector();
+        Ok(PopulationVector::new(
+            groups
+                .iter()
+                .map(|group| group.iter().map(|x| vector[*x]).sum())
+                .collect(),
+        ))
+    }
+    /// Split one lifestage into `classes` lifestages that share its individuals equally, to
+    /// match a matrix split with [`DecomposedMatrix::split_stage`].
+    /// # Errors
+    /// Will return `Err<'static str>` if the lifestage is outside the vector, `classes` is zero,
+    /// or the vector would have more than 255 lifestages.
+    pub fn split_stage(&self, stage: usize, classes: u8) -> Result<PopulationVector, &'static str> {
+        check_split(self.get_lifestage_count(), stage, classes)?;
+        let mut vector = self.get_vector().clone();
+        let share = vector[stage] / f64::from(classes);
+        vector.splice(stage..=stage, vec![share; classes as usize]);
+        Ok(PopulationVector::new(vector))
+    }
+}
+
+/// Check that lifestage `stage` of a model with `size` lifestages can be split into `classes`.
+fn check_split(size: u8, stage: usize, classes: u8) -> Result<(), &'static str> {
+    if stage >= size as usize {
+        return Err("Lifestage is outside the matrix.");
+    }
+    if classes == 0 {
+        return Err("A lifestage must be split into at least one class.");
+    }
+    if size as usize + classes as usize - 1 > u8::MAX as usize {
+        return Err("A model can have at most 255 lifestages.");
+    }
+    Ok(())
+}
+
+impl DecomposedMatrix {
+    /// Collapse groups of lifestages into single lifestages, collapsing survival and fertility
+    /// separately with the same stable stage distribution weights (see
+    /// [`PopulationMatrix::collapse_stages`]). The collapsed parts add up to the collapsed
+    /// projection matrix, so lambda does not change.
+    /// # Errors
+    /// Will return `Err<'static str>` if the groups are invalid, or the stable stage distribution
+    /// is zero for every lifestage in a group.
+    pub fn collapse_stages(&self, groups: &[Vec<usize>]) -> Result<DecomposedMatrix, &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        check_groups(groups, size)?;
+        let weights = self.get_matrix().stable_stage_distribution();
+        check_weights(&weights, groups, size)?;
+        DecomposedMatrix::build(
+            PopulationMatrix::build(collapse(
+                &self.get_survival().get_matrix(),
+                groups,
+                weights.get_vector(),
+            ))?,
+            PopulationMatrix::build(collapse(
+                &self.get_fertility().get_matrix(),
+                groups,
+                weights.get_vector(),
+            ))?,
+        )
+    }
+    /// Split a lifestage that covers a range of ages into `classes` consecutive classes, such
+    /// as splitting a three-year juvenile stage into one class per year. Individuals entering
+    /// the lifestage enter the first class and pass through each class in one step, surviving
+    /// with the total survival of the original lifestage. The last class keeps the original
+    /// transitions out of the lifestage, including staying in it, and every class reproduces like
+    /// the original lifestage. Time spent in the lifestage therefore becomes at least `classes`
+    /// steps, which usually changes lambda; splitting into one class returns an unchanged copy.
+    /// # Errors
+    /// Will return `Err<'static str>` if the lifestage is outside the matrix, `classes` is zero,
+    /// or the matrix would have more than 255 lifestages.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::DecomposedMatrix;
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+    /// // Newborns now spend two steps as juveniles before becoming adults.
+    /// let split = decomposed.split_stage(0, 2).unwrap();
+    /// assert_eq!(
+    ///     split.get_matrix().get_matrix(),
+    ///     vec![vec![0.0, 0.0, 3.0], vec![0.5, 0.0, 0.0], vec![0.0, 0.5, 0.8]]
+    /// );
+    /// ```
+    pub fn split_stage(&self, stage: usize, classes: u8) -> Result<DecomposedMatrix, &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        check_split(self.get_lifestage_count(), stage, classes)?;
+        let classes = classes as usize;
+        let last = stage + classes - 1;
+        // The new position of a lifestage other than the split one, or of the first class.
+        let position = |x: usize| if x > stage { x + classes - 1 } else { x };
+        let new_size = size + classes - 1;
+        let survival = self.get_survival().get_matrix();
+        let fertility = self.get_fertility().get_matrix();
+        let mut new_survival = vec![vec![0.0; new_size]; new_size];
+        let mut new_fertility = vec![vec![0.0; new_size]; new_size];
+        for row in 0..size {
+            for column in 0..size {
+                if column == stage {
+                    let to = if row == stage { last } else { position(row) };
+                    new_survival[to][last] = survival[row][column];
+                    new_fertility[position(row)][stage..=last].fill(fertility[row][column]);
+                } else {
+                    new_survival[position(row)][position(column)] = survival[row][column];
+                    new_fertility[position(row)][position(column)] = fertility[row][column];
+                }
+            }
+        }
+        let total_survival: f64 = (0..size).map(|row| survival[row][stage]).sum();
+        for class in stage..last {
+            new_survival[class + 1][class] = total_survival;
+        }
+        DecomposedMatrix::build(
+            PopulationMatrix::build(new_survival)?,
+            PopulationMatrix::build(new_fertility)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix() -> PopulationMatrix {
+        PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 0.5, 4.0],
+            vec![0.4, 0.2, 0.0, 0.0],
+            vec![0.0, 0.5, 0.6, 0.0],
+            vec![0.0, 0.0, 0.3, 0.85],
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn collapsing_preserves_lambda() {
+        let matrix = matrix();
+        let groups = [vec![0, 1], vec![2, 3]];
+        let collapsed = matrix.collapse_stages(&groups).unwrap();
+        assert!((collapsed.lambda() - matrix.lambda()).abs() < 1e-9);
+        let stable = matrix
+            .stable_stage_distribution()
+            .collapse_stages(&groups)
+            .unwrap();
+        assert!(collapsed
+            .stable_stage_distribution()
+            .approx_eq(&stable, 1e-9));
+        let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0])
+            .unwrap()
+            .collapse_stages(&groups)
+            .unwrap();
+        assert!(decomposed.get_matrix().approx_eq(&collapsed, 1e-12));
+        let identity = matrix
+            .collapse_stages(&[vec![0], vec![1], vec![2], vec![3]])
+            .unwrap();
+        assert!(identity.approx_eq(&matrix, 1e-12));
+    }
+
+    #[test]
+    fn collapsing_with_weights() {
+        let matrix = matrix();
+        let weights = PopulationVector::new(vec![1.0, 3.0, 1.0, 1.0]);
+        let collapsed = matrix
+            .collapse_stages_with_weights(&[vec![0, 1], vec![2], vec![3]], &weights)
+            .unwrap();
+        // Survival out of the juveniles is (0.4 * 1 + (0.2 + 0.5) * 3) / 4.
+        assert!((collapsed[(0, 0)] + collapsed[(1, 0)] - 0.625).abs() < 1e-12);
+        assert!((collapsed[(1, 0)] - 0.375).abs() < 1e-12);
+        let zero = PopulationVector::new(vec![0.0, 0.0, 1.0, 1.0]);
+        assert!(matrix
+            .collapse_stages_with_weights(&[vec![0, 1], vec![2, 3]], &zero)
+            .is_err());
+    }
+
+    #[test]
+    fn groups_are_checked() {
+        let matrix = matrix();
+        assert!(matrix.collapse_stages(&[vec![0, 1], vec![2]]).is_err());
+        assert!(matrix
+            .collapse_stages(&[vec![0, 1], vec![1, 2, 3]])
+            .is_err());
+        assert!(matrix.collapse_stages(&[vec![0, 1, 2, 3, 4]]).is_err());
+        assert!(matrix.collapse_stages(&[vec![0, 1, 2, 3], vec![]]).is_err());
+        assert!(matrix.collapse_stages(&[vec![3, 2, 1, 0]]).is_ok());
+    }
+
+    #[test]
+    fn splitting_a_stage() {
+        let decomposed = DecomposedMatrix::from_fertility_rows(&matrix(), &[0]).unwrap();
+        let unchanged = decomposed.split_stage(2, 1).unwrap();
+        assert_eq!(unchanged.get_matrix(), decomposed.get_matrix());
+        let split = decomposed.split_stage(2, 3).unwrap().get_matrix();
+        assert_eq!(split.get_lifestage_count(), 6);
+        // Juveniles enter the first class and pass through with total survival 0.9; the last
+        // class keeps the stasis of 0.6 and the growth of 0.3 into adults.
+        assert_eq!(split[(2, 1)], 0.5);
+        assert!((split[(3, 2)] - 0.9).abs() < 1e-12);
+        assert_eq!(split[(4, 3)], split[(3, 2)]);
+        assert_eq!(split[(4, 4)], 0.6);
+        assert_eq!(split[(5, 4)], 0.3);
+        assert_eq!(split[(5, 5)], 0.85);
+        for class in 2..5 {
+            assert_eq!(split[(0, class)], 0.5);
+        }
+        assert!(decomposed.split_stage(4, 2).is_err());
+        assert!(decomposed.split_stage(0, 0).is_err());
+        assert!(decomposed.split_stage(0, 255).is_err());
+        let vector = PopulationVector::new(vec![10.0, 6.0])
+            .split_stage(1, 3)
+            .unwrap();
+        assert_eq!(vector, PopulationVector::new(vec![10.0, 2.0, 2.0, 2.0]));
+    }
+}
@@ -0,0 +1,245 @@
+//! This module contains stage durations: the number of steps an individual spends in a lifestage before it advances. A matrix lifestage with a stasis probability implies a geometric duration, in which many individuals leave after one step and a few stay for many, while insect larvae or amphibian tadpoles take a nearly fixed time to develop. A duration can be used by the individual-based model directly, or built into a matrix as a chain of pseudo-stages, one per step of development.
+use super::{DecomposedMatrix, PopulationMatrix};
+use rand::Rng;
+
+/// This enum describes the distribution of the number of steps spent in a lifestage (at least
+/// one):
+/// - `Fixed`: exactly `steps` steps.
+/// - `Uniform`: any number of steps from `minimum` to `maximum` (inclusive), equally likely.
+/// - `Discrete`: `probabilities[k]` is the probability of spending `k + 1` steps. The
+///   probabilities must add up to one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StageDuration {
+    Fixed { steps: u16 },
+    Uniform { minimum: u16, maximum: u16 },
+    Discrete { probabilities: Vec<f64> },
+}
+impl StageDuration {
+    /// Check that the distribution is valid.
+    /// # Errors
+    /// Will return `Err<'static str>` if a duration can be zero, a uniform minimum is above its
+    /// maximum, or the discrete probabilities are empty, negative, or do not add up to one.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            StageDuration::Fixed { steps } if *steps == 0 => {
+                Err("A stage duration must be at least one step.")
+            }
+            StageDuration::Uniform { minimum, maximum } if *minimum == 0 || minimum > maximum => {
+                Err("A uniform stage duration needs a minimum of at least one step that is not above its maximum.")
+            }
+            StageDuration::Discrete { probabilities }
+                if probabilities.is_empty()
+                    || probabilities.len() > u16::MAX as usize
+                    || probabilities.iter().any(|x| !x.is_finite() || *x < 0.0)
+                    || (probabilities.iter().sum::<f64>() - 1.0).abs() > 1e-9 =>
+            {
+                Err("Stage duration probabilities must not be negative and must add up to one.")
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Return the probability of each duration, from one step to the longest possible.
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::StageDuration;
+    /// let duration = StageDuration::Uniform { minimum: 2, maximum: 3 };
+    /// assert_eq!(duration.probabilities(), vec![0.0, 0.5, 0.5]);
+    /// ```
+    pub fn probabilities(&self) -> Vec<f64> {
+        match self {
+            StageDuration::Fixed { steps } => {
+                let mut probabilities = vec![0.0; *steps as usize];
+                probabilities[*steps as usize - 1] = 1.0;
+                probabilities
+            }
+            StageDuration::Uniform { minimum, maximum } => {
+                let share = 1.0 / f64::from(maximum - minimum + 1);
+                let mut probabilities = vec![0.0; *maximum as usize];
+                probabilities[*minimum as usize - 1..].fill(share);
+                probabilities
+            }
+            StageDuration::Discrete { probabilities } => probabilities.clone(),
+        }
+    }
+    /// Return the longest possible duration.
+    pub fn maximum(&self) -> u16 {
+        match self {
+            StageDuration::Fixed { steps } => *steps,
+            StageDuration::Uniform { maximum, .. } => *maximum,
+            StageDuration::Discrete { probabilities } => probabilities.len() as u16,
+        }
+    }
+    /// Return the mean duration.
+    pub fn mean(&self) -> f64 {
+        self.probabilities()
+            .iter()
+            .enumerate()
+            .map(|(k, p)| (k + 1) as f64 * p)
+            .sum()
+    }
+    /// Draw a duration.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u16 {
+        match self {
+            StageDuration::Fixed { steps } => *steps,
+            StageDuration::Uniform { minimum, maximum } => rng.gen_range(*minimum..=*maximum),
+            StageDuration::Discrete { probabilities } => {
+                let draw = rng.gen::<f64>();
+                let mut cumulative = 0.0;
+                for (k, p) in probabilities.iter().enumerate() {
+                    cumulative += p;
+                    if draw < cumulative {
+                        return k as u16 + 1;
+                    }
+                }
+                probabilities.len() as u16
+            }
+        }
+    }
+}
+
+impl DecomposedMatrix {
+    /// Replace a lifestage with a chain of pseudo-stages, one per step of its duration, so that
+    /// individuals spend a duration drawn from `duration` in it before moving to `next_stage`.
+    /// Individuals entering the lifestage enter the first pseudo-stage. Each step, a member
+    /// survives with the total survival of the original lifestage; a survivor that has completed
+    /// its duration moves to `next_stage`, and the others move on to the next pseudo-stage. The
+    /// original transitions out of the lifestage (including stasis) are replaced, and every
+    /// pseudo-stage reproduces like the original lifestage.
+    /// # Errors
+    /// Will return `Err<'static str>` if either lifestage is outside the matrix, they are the
+    /// same, the duration is not valid, or the matrix would have more than 255 lifestages.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::{DecomposedMatrix, StageDuration};
+    /// let decomposed = DecomposedMatrix::build(
+    ///     PopulationMatrix::build(vec![vec![0.6, 0.0], vec![0.2, 0.5]]).unwrap(),
+    ///     PopulationMatrix::build(vec![vec![0.0, 5.0], vec![0.0, 0.0]]).unwrap(),
+    /// ).unwrap();
+    /// // Larvae survive 80% of steps and always take two steps to develop.
+    /// let expanded = decomposed
+    ///     .expand_stage_duration(0, &StageDuration::Fixed { steps: 2 }, 1)
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     expanded.get_matrix().get_matrix(),
+    ///     vec![vec![0.0, 0.0, 5.0], vec![0.8, 0.0, 0.0], vec![0.0, 0.8, 0.5]]
+    /// );
+    /// ```
+    pub fn expand_stage_duration(
+        &self,
+        stage: usize,
+        duration: &StageDuration,
+        next_stage: usize,
+    ) -> Result<DecomposedMatrix, &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        if stage >= size || next_stage >= size {
+            return Err("Lifestage is outside the matrix.");
+        }
+        if stage == next_stage {
+            return Err("Individuals must move to another lifestage after its duration.");
+        }
+        duration.validate()?;
+        let probabilities = duration.probabilities();
+        let classes = probabilities.len();
+        if size + classes - 1 > u8::MAX as usize {
+            return Err("A model can have at most 255 lifestages.");
+        }
+        let last = stage + classes - 1;
+        // The new position of a lifestage other than the expanded one, or of the first class.
+        let position = |x: usize| if x > stage { x + classes - 1 } else { x };
+        let new_size = size + classes - 1;
+        let survival = self.get_survival().get_matrix();
+        let fertility = self.get_fertility().get_matrix();
+        let mut new_survival = vec![vec![0.0; new_size]; new_size];
+        let mut new_fertility = vec![vec![0.0; new_size]; new_size];
+        for row in 0..size {
+            for column in 0..size {
+                if column == stage {
+                    new_fertility[position(row)][stage..=last].fill(fertility[row][column]);
+                } else {
+                    new_survival[position(row)][position(column)] = survival[row][column];
+                    new_fertility[position(row)][position(column)] = fertility[row][column];
+                }
+            }
+        }
+        let total_survival: f64 = (0..size).map(|row| survival[row][stage]).sum();
+        let mut remaining = 1.0;
+        for (class, probability) in probabilities.iter().enumerate() {
+            // The probability of completing the duration now, given it was not completed before.
+            // Everyone left completes it in the last class, whatever rounding left in `remaining`.
+            let completing = match stage + class < last && remaining > 0.0 {
+                true => (probability / remaining).min(1.0),
+                false => 1.0,
+            };
+            remaining -= probability;
+            new_survival[position(next_stage)][stage + class] = total_survival * completing;
+            if stage + class < last {
+                new_survival[stage + class + 1][stage + class] =
+                    total_survival * (1.0 - completing);
+            }
+        }
+        DecomposedMatrix::build(
+            PopulationMatrix::build(new_survival)?,
+            PopulationMatrix::build(new_fertility)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn durations_sample_their_distribution() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let duration = StageDuration::Discrete {
+            probabilities: vec![0.2, 0.0, 0.8],
+        };
+        let draws: Vec<u16> = (0..20_000).map(|_| duration.sample(&mut rng)).collect();
+        assert!(!draws.contains(&2));
+        let mean = draws.iter().map(|x| *x as f64).sum::<f64>() / draws.len() as f64;
+        assert!((mean - duration.mean()).abs() < 0.03);
+        assert!((duration.mean() - 2.6).abs() < 1e-12);
+        assert!(StageDuration::Fixed { steps: 0 }.validate().is_err());
+        assert!(StageDuration::Uniform {
+            minimum: 3,
+            maximum: 2
+        }
+        .validate()
+        .is_err());
+        assert!(StageDuration::Discrete {
+            probabilities: vec![0.5, 0.6]
+        }
+        .validate()
+        .is_err());
+    }
+    #[test]
+    fn expanded_stage_keeps_lifetime_reproduction() {
+        let matrix = PopulationMatrix::build(vec![
+            vec![0.0, 0.0, 6.0],
+            vec![0.5, 0.3, 0.0],
+            vec![0.0, 0.4, 0.7],
+        ])
+        .unwrap();
+        let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+        let duration = StageDuration::Uniform {
+            minimum: 1,
+            maximum: 3,
+        };
+        let expanded = decomposed.expand_stage_duration(1, &duration, 2).unwrap();
+        assert_eq!(expanded.get_lifestage_count(), 5);
+        // Juveniles survive 70% of steps, so on average sum_k P(k) 0.7^k reach adulthood.
+        let reaching = (0.7 + 0.49 + 0.343) / 3.0;
+        let expected = 0.5 * reaching * 6.0 / (1.0 - 0.7);
+        assert!((expanded.net_reproductive_rate().unwrap() - expected).abs() < 1e-9);
+        assert!(decomposed.expand_stage_duration(1, &duration, 1).is_err());
+        assert!(decomposed.expand_stage_duration(3, &duration, 2).is_err());
+        // Rounding in 1 - 0.7 - 0.2 must not leave anyone in the last class.
+        let rounded = StageDuration::Discrete {
+            probabilities: vec![0.7, 0.2, 0.1],
+        };
+        let expanded = decomposed.expand_stage_duration(1, &rounded, 2).unwrap();
+        assert_eq!(expanded.get_survival()[(4, 3)], 0.7);
+        let too_long = StageDuration::Fixed { steps: 300 };
+        assert!(decomposed.expand_stage_duration(1, &too_long, 2).is_err());
+    }
+}
@@ -0,0 +1,710 @@
+//! This module contains stochastic population-level simulations. Environmental stochasticity is represented by a set of Population Matrices (for example, matrices estimated from good and bad years), one of which is drawn at random for each step of each replicate.
+use super::extinction::any_met;
+use super::{
+    growth_rates, DensityDependence, Dormancy, ExtinctionCriterion, MatrixModelCore, NumericFault,
+    PopulationMatrix, PopulationVector, ProjectionConfig, StopReason, Summation, TimeStep,
+};
+use crate::simulation::Simulation;
+use crate::statistics::{QuantileSketch, RunningStatistics};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The StochasticPva struct stores population data for stochastic PVA models. It contains a [`MatrixModelCore`] with the following:
+/// - A Population Vector representing the initial population size.
+/// - A list of Population Matrices, one of which is chosen at random (with equal probability)
+///   for each step of the simulation. Add a matrix to the list more than once to make it more
+///   likely to be chosen.
+/// - The density dependence applied after each step (none by default).
+#[derive(Clone)]
+pub struct StochasticPva {
+    core: MatrixModelCore,
+}
+/// The former name of [`StochasticPva`].
+#[deprecated(note = "renamed to StochasticPva")]
+pub type PvaStochasticPopulation = StochasticPva;
+impl StochasticPva {
+    /// Return a Result enum containing a new StochasticPva instance with the input of a Population Vector and a vector of Population Matrices.
+    /// # Errors
+    /// Will return `Err<'static str>` if no matrices are given or the lengths of the Population Vector and any of the Matrices do not match.
+    pub fn build(
+        initial_population: PopulationVector,
+        matrices: Vec<PopulationMatrix>,
+    ) -> Result<StochasticPva, &'static str> {
+        Ok(StochasticPva {
+            core: MatrixModelCore::build(initial_population, matrices)?,
+        })
+    }
+    /// Return a Result enum containing a new StochasticPva instance with the input of a vector containing f64 values (a population vector) and a vector of square vectors of vectors containing f64 values (population matrices).
+    /// # Errors
+    /// Will return `Err<'static str>` if any matrix is not square or the lengths of the Population Vector and the Matrices do not match.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+    ///let new_population = StochasticPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///    vec![vec![0.0, 0.0, 0.9], vec![0.6, 0.9, 0.0], vec![0.0, 0.95, 0.99]],
+    ///    vec![vec![0.0, 0.0, 0.4], vec![0.3, 0.7, 0.0], vec![0.0, 0.8, 0.9]],
+    ///]);
+    ///assert!(new_population.is_ok());
+    /// ```
+    pub fn build_from_vectors(
+        initial_population: Vec<f64>,
+        matrices: Vec<Vec<Vec<f64>>>,
+    ) -> Result<StochasticPva, &'static str> {
+        let mut built_matrices = Vec::new();
+        for matrix in matrices {
+            built_matrices.push(PopulationMatrix::build(matrix)?);
+        }
+        StochasticPva::build(PopulationVector::new(initial_population), built_matrices)
+    }
+    /// Return the model with the given density dependence applied after each step.
+    pub fn with_density_dependence(mut self, density_dependence: DensityDependence) -> Self {
+        self.core.set_density_dependence(density_dependence);
+        self
+    }
+    /// Return the model with the given `(row, column)` entries of the matrices treated as
+    /// reproduction, which an Allee effect reduces (see
+    /// [`MatrixModelCore::set_reproduction_entries`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if any entry is outside the matrices.
+    pub fn with_reproduction_entries(
+        mut self,
+        entries: Vec<(usize, usize)>,
+    ) -> Result<Self, &'static str> {
+        self.core.set_reproduction_entries(entries)?;
+        Ok(self)
+    }
+    /// Return the model with the given time step, which outputs of the model record so elapsed
+    /// time can be reported in real units.
+    pub fn with_time_step(mut self, time_step: TimeStep) -> Self {
+        self.core.set_time_step(time_step);
+        self
+    }
+    /// Return the model with every matrix adding up projections with the given [`Summation`].
+    pub fn with_summation(mut self, summation: Summation) -> Self {
+        self.core.set_summation(summation);
+        self
+    }
+    /// Return the model with a dormant lifestage whose re-entry into the active population may
+    /// depend on a covariate (see [`MatrixModelCore::set_dormancy`]).
+    /// # Errors
+    /// Will return `Err<'static str>` if either lifestage of the dormancy is outside the
+    /// matrices.
+    pub fn with_dormancy(mut self, dormancy: Dormancy) -> Result<Self, &'static str> {
+        self.core.set_dormancy(dormancy)?;
+        Ok(self)
+    }
+    /// Return the time step of the model, or `None` if it was not declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.core.get_time_step()
+    }
+    /// Return a copy of the model that starts from a different initial Population Vector.
+    /// # Errors
+    /// Will return `Err<'static str>` if the length of the Population Vector does not match the
+    /// matrices.
+    pub fn with_initial_population(
+        &self,
+        initial_population: PopulationVector,
+    ) -> Result<StochasticPva, &'static str> {
+        Ok(StochasticPva {
+            core: self.core.with_initial_population(initial_population)?,
+        })
+    }
+    /// Return the shared model data: initial population, matrices, and density dependence.
+    pub fn get_core(&self) -> &MatrixModelCore {
+        &self.core
+    }
+    /// Return the initial Population Vector.
+    pub fn get_initial_population(&self) -> &PopulationVector {
+        self.core.get_initial_population()
+    }
+    /// Return the Population Matrices that are drawn from during the simulation.
+    pub fn get_matrices(&self) -> &Vec<PopulationMatrix> {
+        self.core.get_matrices()
+    }
+    /// Return the element-by-element mean of the Population Matrices.
+    pub fn mean_matrix(&self) -> PopulationMatrix {
+        let matrices = self.get_matrices();
+        matrices
+            .iter()
+            .skip(1)
+            .fold(matrices[0].clone(), |sum, x| &sum + x)
+            * (1.0 / matrices.len() as f64)
+    }
+
+    /// Return an iterator that lazily simulates a single replicate, yielding the Population
+    /// Vector of each step. The replicate number and the master seed together determine the
+    /// random sequence of matrices, so the same inputs always produce the same replicate. The
+    /// iterator never ends on its own; use `take` or a similar adapter to limit the number of steps.
+    pub fn replicate_iter(&self, replicate: u32, seed: u64) -> StochasticProjectionIter<'_> {
+        StochasticProjectionIter {
+            core: &self.core,
+            active_vector: self.core.get_initial_population().clone(),
+            rng: StdRng::seed_from_u64(replicate_seed(seed, replicate)),
+            steps_taken: 0,
+            last_matrix: None,
+        }
+    }
+    /// Run the simulation for the given number of replicates and steps, passing each result to
+    /// `callback` as soon as it is calculated instead of storing it. The callback recieves the
+    /// replicate number (starting at 0), the step number (starting at 1), and the Population
+    /// Vector for that step.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+    ///let new_population = StochasticPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///    vec![vec![0.0, 0.0, 0.9], vec![0.6, 0.9, 0.0], vec![0.0, 0.95, 0.99]],
+    ///    vec![vec![0.0, 0.0, 0.4], vec![0.3, 0.7, 0.0], vec![0.0, 0.8, 0.9]],
+    ///]).unwrap();
+    ///let mut final_totals = vec![0.0; 100];
+    ///new_population.stream_replicates(50, 100, 42, |replicate, step, popvector| {
+    ///    if step == 50 {
+    ///        final_totals[replicate as usize] = popvector.iter().sum();
+    ///    }
+    ///});
+    /// ```
+    pub fn stream_replicates<F>(&self, iterations: u32, replicates: u32, seed: u64, mut callback: F)
+    where
+        F: FnMut(u32, u32, &PopulationVector),
+    {
+        for replicate in 0..replicates {
+            for (step, popvector) in self
+                .replicate_iter(replicate, seed)
+                .take(iterations as usize)
+                .enumerate()
+            {
+                callback(replicate, step as u32 + 1, &popvector);
+            }
+        }
+    }
+    /// Return a PvaStochasticOutput holding every step of every replicate of a stochastic
+    /// simulation, given the number of steps, the number of replicates, and a seed for the random
+    /// number generator.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+    ///let new_population = StochasticPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///    vec![vec![0.0, 0.0, 0.9], vec![0.6, 0.9, 0.0], vec![0.0, 0.95, 0.99]],
+    ///    vec![vec![0.0, 0.0, 0.4], vec![0.3, 0.7, 0.0], vec![0.0, 0.8, 0.9]],
+    ///]).unwrap();
+    ///let simulation_output = new_population.stochastic_projection(100, 500, 42);
+    ///assert_eq!(simulation_output.get_replicates().len(), 500);
+    ///assert!((0.0..=1.0).contains(&simulation_output.extinction_probability(10.0)));
+    /// ```
+    pub fn stochastic_projection(
+        &self,
+        iterations: u32,
+        replicates: u32,
+        seed: u64,
+    ) -> PvaStochasticOutput {
+        let mut result = Vec::new();
+        for replicate in 0..replicates {
+            result.push(
+                self.replicate_iter(replicate, seed)
+                    .take(iterations as usize)
+                    .collect(),
+            );
+        }
+        PvaStochasticOutput::new(result).with_model_time_step(self.get_time_step())
+    }
+    /// Return a PvaStochasticSummary holding running statistics for each step of a stochastic
+    /// simulation instead of every replicate's trajectory. Memory use depends only on the number
+    /// of steps and lifestages, not on the number of replicates, so this should be used for large
+    /// replicate counts. Replicates whose total population falls to or below
+    /// `extinction_threshold` are flagged as extinct.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::StochasticPva;
+    ///let new_population = StochasticPva::build_from_vectors(vec![12.0, 55.0, 172.0],
+    ///vec![
+    ///    vec![vec![0.0, 0.0, 0.9], vec![0.6, 0.9, 0.0], vec![0.0, 0.95, 0.99]],
+    ///    vec![vec![0.0, 0.0, 0.4], vec![0.3, 0.7, 0.0], vec![0.0, 0.8, 0.9]],
+    ///]).unwrap();
+    ///let summary = new_population.stochastic_summary(100, 1_000, 42, 10.0);
+    ///assert!((0.0..=1.0).contains(&summary.extinction_probability()));
+    ///let quantiles = summary.total_quantiles(99).unwrap();
+    ///assert!(quantiles[0].1 <= quantiles[2].1);
+    ///assert!(summary.total_quantiles(100).is_none());
+    /// ```
+    pub fn stochastic_summary(
+        &self,
+        iterations: u32,
+        replicates: u32,
+        seed: u64,
+        extinction_threshold: f64,
+    ) -> PvaStochasticSummary {
+        self.configured_summary(
+            &ProjectionConfig::new()
+                .steps(iterations)
+                .replicates(replicates)
+                .seed(seed)
+                .extinction_threshold(extinction_threshold),
+        )
+    }
+}
+
+/// Derive the seed for one replicate from the master seed of a run.
+pub(crate) fn replicate_seed(seed: u64, replicate: u32) -> u64 {
+    seed.wrapping_add((replicate as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// A lazy iterator over the steps of one stochastic replicate, created by
+/// [`StochasticPva::replicate_iter`]. It also implements [`Simulation`].
+pub struct StochasticProjectionIter<'a> {
+    core: &'a MatrixModelCore,
+    active_vector: PopulationVector,
+    rng: StdRng,
+    steps_taken: u32,
+    last_matrix: Option<usize>,
+}
+impl StochasticProjectionIter<'_> {
+    /// Return the position in the model's list of the matrix drawn for the last step, or `None`
+    /// before the first step.
+    pub fn get_last_matrix(&self) -> Option<usize> {
+        self.last_matrix
+    }
+}
+impl Simulation for StochasticProjectionIter<'_> {
+    type State = PopulationVector;
+    fn step(&mut self) {
+        let matrix_index = self.rng.gen_range(0..self.core.get_matrices().len());
+        self.active_vector = self
+            .core
+            .project(matrix_index, self.steps_taken, &self.active_vector);
+        self.steps_taken += 1;
+        self.last_matrix = Some(matrix_index);
+    }
+    fn state(&self) -> &PopulationVector {
+        &self.active_vector
+    }
+    fn steps_taken(&self) -> u32 {
+        self.steps_taken
+    }
+}
+impl Iterator for StochasticProjectionIter<'_> {
+    type Item = PopulationVector;
+    fn next(&mut self) -> Option<PopulationVector> {
+        self.step();
+        Some(self.active_vector.clone())
+    }
+}
+
+/// This struct stores the output of a stochastic Population Viability Analysis: the Population
+/// Vector for every step of every replicate.
+pub struct PvaStochasticOutput {
+    replicates: Vec<Vec<PopulationVector>>,
+    stop_reasons: Vec<StopReason>,
+    time_step: Option<TimeStep>,
+}
+impl PvaStochasticOutput {
+    /// Create a new PvaStochasticOutput struct from a vector of replicates, each of which is a
+    /// vector of PopulationVectors (one per step).
+    pub fn new(replicates: Vec<Vec<PopulationVector>>) -> PvaStochasticOutput {
+        PvaStochasticOutput {
+            stop_reasons: vec![StopReason::MaxSteps; replicates.len()],
+            replicates,
+            time_step: None,
+        }
+    }
+    /// Return the output with the time step of the model that produced it recorded.
+    pub fn with_time_step(mut self, time_step: TimeStep) -> Self {
+        self.time_step = Some(time_step);
+        self
+    }
+    /// Return the output with the time step of the model that produced it (if any) recorded.
+    pub(crate) fn with_model_time_step(mut self, time_step: Option<TimeStep>) -> Self {
+        self.time_step = time_step;
+        self
+    }
+    /// Return the time step of the model that produced the output, or `None` if it was not
+    /// declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Return the output with the reason each replicate stopped recorded.
+    /// # Errors
+    /// Will return `Err<'static str>` if there is not one reason per replicate.
+    pub fn with_stop_reasons(
+        mut self,
+        stop_reasons: Vec<StopReason>,
+    ) -> Result<Self, &'static str> {
+        if stop_reasons.len() != self.replicates.len() {
+            return Err("One stop reason is needed per replicate.");
+        }
+        self.stop_reasons = stop_reasons;
+        Ok(self)
+    }
+    /// Return the reason each replicate stopped. Replicates stopped early by a stopping rule
+    /// have fewer steps than the others.
+    pub fn get_stop_reasons(&self) -> &Vec<StopReason> {
+        &self.stop_reasons
+    }
+    /// Return every replicate of the simulation. Each item is a vector of Population Vectors,
+    /// the first being the first iteration and the last being the last iteration.
+    pub fn get_replicates(&self) -> &Vec<Vec<PopulationVector>> {
+        &self.replicates
+    }
+    /// Return the number of replicates stored in the output.
+    pub fn replicate_count(&self) -> usize {
+        self.replicates.len()
+    }
+    /// Return the proportion of replicates in which the total population fell to or below
+    /// `threshold` at any step (the quasi-extinction probability).
+    pub fn extinction_probability(&self, threshold: f64) -> f64 {
+        if self.replicates.is_empty() {
+            return 0.0;
+        }
+        let extinct = self
+            .replicates
+            .iter()
+            .filter(|replicate| {
+                replicate
+                    .iter()
+                    .any(|popvector| popvector.iter().sum::<f64>() <= threshold)
+            })
+            .count();
+        extinct as f64 / self.replicates.len() as f64
+    }
+    /// Return the total population at each step of each replicate.
+    pub fn total_population(&self) -> Vec<Vec<f64>> {
+        self.replicates
+            .iter()
+            .map(|steps| steps.iter().map(PopulationVector::total).collect())
+            .collect()
+    }
+    /// Return the proportion of the population in each lifestage at each step of each replicate.
+    pub fn stage_proportions(&self) -> Vec<Vec<PopulationVector>> {
+        self.replicates
+            .iter()
+            .map(|steps| steps.iter().map(PopulationVector::proportions).collect())
+            .collect()
+    }
+    /// Return the realized growth rates `N_t+1 / N_t` of the total population of each replicate
+    /// (see [`super::PvaDeterministicOutput::growth_rates`]).
+    pub fn growth_rates(&self) -> Vec<Vec<f64>> {
+        self.total_population()
+            .iter()
+            .map(|totals| growth_rates(totals))
+            .collect()
+    }
+    /// Print a CSV containing the output of each step of each replicate to the console. The
+    /// first column is the replicate number and the second is the step number.
+    pub fn print_output(&self) {
+        let mut string = String::new();
+        for (replicate, steps) in self.replicates.iter().enumerate() {
+            for (step, popvector) in steps.iter().enumerate() {
+                string.push_str(&format!("{}, {}", replicate, step + 1));
+                for value in popvector {
+                    string.push_str(&format!(", {}", value));
+                }
+                string.push('\n');
+            }
+        }
+        print!("{}", string);
+    }
+}
+
+/// The quantiles of total population size tracked by [`PvaStochasticSummary`].
+pub const SUMMARY_QUANTILES: [f64; 3] = [0.025, 0.5, 0.975];
+
+/// This struct stores a memory-efficient summary of a stochastic Population Viability Analysis.
+/// For each step it keeps running statistics of every lifestage and of the total population,
+/// plus streaming estimates of the quantiles in [`SUMMARY_QUANTILES`] of the total population.
+/// For each replicate it keeps only the first step (if any) at which it went extinct: its total
+/// population fell to the extinction threshold, or it met one of the extinction criteria (see
+/// [`ExtinctionCriterion`]). A summary made at [`Verbosity::Final`] keeps the statistics of the
+/// last step only (see [`PvaStochasticSummary::first_step`]). A replicate that reaches a NaN,
+/// infinite, or negative value stops there, and its [`NumericFault`] is kept.
+pub struct PvaStochasticSummary {
+    extinction_threshold: f64,
+    lifestage_count: u8,
+    first_step: u32,
+    pub(super) extinction_criteria: Vec<ExtinctionCriterion>,
+    stage_statistics: Vec<Vec<RunningStatistics>>,
+    total_statistics: Vec<RunningStatistics>,
+    total_quantiles: Vec<Vec<QuantileSketch>>,
+    extinction_steps: Vec<Option<u32>>,
+    faults: Vec<NumericFault>,
+    time_step: Option<TimeStep>,
+}
+impl PvaStochasticSummary {
+    /// Create a new, empty PvaStochasticSummary sized for the given number of steps, lifestages,
+    /// and replicates.
+    pub fn new(
+        iterations: u32,
+        lifestage_count: u8,
+        replicates: u32,
+        extinction_threshold: f64,
+    ) -> PvaStochasticSummary {
+        PvaStochasticSummary {
+            extinction_threshold,
+            lifestage_count,
+            first_step: 1,
+            extinction_criteria: Vec::new(),
+            stage_statistics: vec![
+                vec![RunningStatistics::new(); lifestage_count as usize];
+                iterations as usize
+            ],
+            total_statistics: vec![RunningStatistics::new(); iterations as usize],
+            total_quantiles: vec![
+                SUMMARY_QUANTILES
+                    .iter()
+                    .map(|&quantile| QuantileSketch::new(quantile))
+                    .collect();
+                iterations as usize
+            ],
+            extinction_steps: vec![None; replicates as usize],
+            faults: Vec::new(),
+            time_step: None,
+        }
+    }
+    /// Return the summary with the time step of the model that produced it recorded.
+    pub fn with_time_step(mut self, time_step: TimeStep) -> Self {
+        self.time_step = Some(time_step);
+        self
+    }
+    /// Return the summary with statistics kept from `first_step` (starting at 1) onwards.
+    pub(super) fn starting_at(mut self, first_step: u32) -> Self {
+        self.first_step = first_step;
+        self
+    }
+    /// Return the first step (starting at 1) whose statistics are kept. Statistics at position
+    /// `i` belong to step `first_step + i`.
+    pub fn first_step(&self) -> u32 {
+        self.first_step
+    }
+    /// Return the last step summarized (0 if no steps were).
+    pub fn last_step(&self) -> u32 {
+        self.first_step - 1 + self.step_count() as u32
+    }
+    /// Return the time step of the model that produced the summary, or `None` if it was not
+    /// declared.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Add the Population Vector of one step (starting at 1) of one replicate (starting at 0)
+    /// to the summary. Steps before the first step kept, such as step 0 (the initial
+    /// population), are only checked for extinction.
+    /// # Errors
+    /// Will return `Err<'static str>` if the replicate or step is after the last one the
+    /// summary was sized for, or the Population Vector does not have one value per lifestage.
+    pub fn push(
+        &mut self,
+        replicate: u32,
+        step: u32,
+        popvector: &PopulationVector,
+    ) -> Result<(), &'static str> {
+        if popvector.get_lifestage_count() != self.lifestage_count {
+            return Err("The Population Vector must have one value per lifestage of the summary.");
+        }
+        if replicate as usize >= self.extinction_steps.len() {
+            return Err("The replicate is after the last replicate of the summary.");
+        }
+        let index = step.checked_sub(self.first_step).map(|x| x as usize);
+        if index.is_some_and(|x| x >= self.step_count()) {
+            return Err("The step is after the last step of the summary.");
+        }
+        let total: f64 = popvector.iter().sum();
+        if let Some(index) = index {
+            for (stats, value) in self.stage_statistics[index].iter_mut().zip(popvector) {
+                stats.push(*value);
+            }
+            self.total_statistics[index].push(total);
+            for sketch in &mut self.total_quantiles[index] {
+                sketch.push(total);
+            }
+        }
+        let extinction_step = &mut self.extinction_steps[replicate as usize];
+        if extinction_step.is_none()
+            && (total <= self.extinction_threshold || any_met(&self.extinction_criteria, popvector))
+        {
+            *extinction_step = Some(step);
+        }
+        Ok(())
+    }
+    /// Return the number of steps whose statistics are kept.
+    pub fn step_count(&self) -> usize {
+        self.total_statistics.len()
+    }
+    /// Return the mean Population Vector of each step across all replicates.
+    pub fn mean_trajectory(&self) -> Vec<PopulationVector> {
+        self.stage_statistics
+            .iter()
+            .map(|step| PopulationVector::new(step.iter().map(|stats| stats.mean()).collect()))
+            .collect()
+    }
+    /// Return the running statistics of each lifestage at a step (starting at 0), or `None` if
+    /// the step is not kept.
+    pub fn stage_statistics(&self, step: usize) -> Option<&Vec<RunningStatistics>> {
+        self.stage_statistics.get(step)
+    }
+    /// Return the running statistics of the total population at every step.
+    pub fn total_statistics(&self) -> &Vec<RunningStatistics> {
+        &self.total_statistics
+    }
+    /// Return `(quantile, estimate)` pairs for the total population at a step (starting at 0),
+    /// or `None` if the step is not kept.
+    pub fn total_quantiles(&self, step: usize) -> Option<Vec<(f64, f64)>> {
+        Some(
+            self.total_quantiles
+                .get(step)?
+                .iter()
+                .map(|sketch| (sketch.quantile(), sketch.estimate()))
+                .collect(),
+        )
+    }
+    /// Record the fault that stopped a replicate.
+    pub(super) fn add_fault(&mut self, fault: NumericFault) {
+        self.faults.push(fault);
+    }
+    /// Return the faults that stopped replicates early, in replicate order.
+    pub fn get_faults(&self) -> &Vec<NumericFault> {
+        &self.faults
+    }
+    /// Return the first step at which each replicate went extinct, or `None` if it persisted.
+    pub fn extinction_steps(&self) -> &Vec<Option<u32>> {
+        &self.extinction_steps
+    }
+    /// Return the time each replicate went extinct, in the unit of the declared time step (or in
+    /// steps if there is none), or `None` if it persisted.
+    /// ```
+    ///use ecolysis_cmd::populations::population_level_simulation::{StochasticPva, TimeStep};
+    ///let monthly = StochasticPva::build_from_vectors(vec![10.0, 10.0],
+    ///vec![vec![vec![0.0, 0.5], vec![0.3, 0.5]]]).unwrap()
+    ///.with_time_step("2 months".parse::<TimeStep>().unwrap());
+    ///let summary = monthly.stochastic_summary(50, 1, 0, 1.0);
+    ///let steps = summary.extinction_steps()[0].unwrap();
+    ///assert_eq!(summary.extinction_times()[0], Some(2.0 * steps as f64));
+    /// ```
+    pub fn extinction_times(&self) -> Vec<Option<f64>> {
+        let length = self.time_step.map_or(1.0, |x| x.get_length());
+        self.extinction_steps
+            .iter()
+            .map(|step| step.map(|x| x as f64 * length))
+            .collect()
+    }
+    /// Return the proportion of replicates that went extinct at any step.
+    pub fn extinction_probability(&self) -> f64 {
+        if self.extinction_steps.is_empty() {
+            return 0.0;
+        }
+        self.extinction_steps.iter().filter(|x| x.is_some()).count() as f64
+            / self.extinction_steps.len() as f64
+    }
+    /// Return the proportion of replicates that had gone extinct by each step kept.
+    pub fn cumulative_extinction_probability(&self) -> Vec<f64> {
+        let replicates = self.extinction_steps.len().max(1) as f64;
+        (self.first_step..=self.last_step())
+            .map(|step| {
+                self.extinction_steps
+                    .iter()
+                    .filter(|x| matches!(x, Some(extinct) if *extinct <= step))
+                    .count() as f64
+                    / replicates
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_population() -> StochasticPva {
+        StochasticPva::build_from_vectors(
+            vec![40.0, 20.0, 100.0],
+            vec![
+                vec![
+                    vec![0.0, 0.0, 0.1],
+                    vec![0.6, 0.8, 0.0],
+                    vec![0.0, 0.8, 0.95],
+                ],
+                vec![
+                    vec![0.0, 0.0, 0.05],
+                    vec![0.3, 0.5, 0.0],
+                    vec![0.0, 0.5, 0.7],
+                ],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn stochastic_mismatched_matrices() {
+        assert!(StochasticPva::build_from_vectors(
+            vec![1.0, 2.0],
+            vec![vec![vec![0.5, 0.5], vec![0.5, 0.5]], vec![vec![1.0]]]
+        )
+        .is_err());
+        assert!(StochasticPva::build_from_vectors(vec![1.0, 2.0], vec![]).is_err());
+    }
+    #[test]
+    fn stochastic_projection_is_reproducible() {
+        let population = test_population();
+        let first = population.stochastic_projection(20, 5, 7);
+        let second = population.stochastic_projection(20, 5, 7);
+        assert_eq!(first.replicate_count(), 5);
+        for (a, b) in first.get_replicates().iter().zip(second.get_replicates()) {
+            for (x, y) in a.iter().zip(b) {
+                assert_eq!(x.get_vector(), y.get_vector());
+            }
+        }
+    }
+    #[test]
+    fn streaming_matches_stored_output() {
+        let population = test_population();
+        let stored = population.stochastic_projection(10, 3, 11);
+        let mut streamed = vec![Vec::new(); 3];
+        population.stream_replicates(10, 3, 11, |replicate, _, popvector| {
+            streamed[replicate as usize].push(popvector.get_vector().clone());
+        });
+        for (a, b) in stored.get_replicates().iter().zip(&streamed) {
+            let a: Vec<Vec<f64>> = a.iter().map(|x| x.get_vector().clone()).collect();
+            assert_eq!(&a, b);
+        }
+    }
+    #[test]
+    fn summary_matches_stored_output() {
+        let population = test_population();
+        let stored = population.stochastic_projection(15, 40, 5);
+        let summary = population.stochastic_summary(15, 40, 5, 50.0);
+        assert_eq!(
+            summary.extinction_probability(),
+            stored.extinction_probability(50.0)
+        );
+        let last_totals: Vec<f64> = stored
+            .get_replicates()
+            .iter()
+            .map(|replicate| replicate[14].iter().sum())
+            .collect();
+        let mean = last_totals.iter().sum::<f64>() / last_totals.len() as f64;
+        assert!((summary.total_statistics()[14].mean() - mean).abs() < 1e-9);
+        let cumulative = summary.cumulative_extinction_probability();
+        assert_eq!(cumulative.len(), 15);
+        assert!(cumulative.windows(2).all(|x| x[0] <= x[1]));
+        assert_eq!(cumulative[14], summary.extinction_probability());
+    }
+    #[test]
+    fn summary_checks_the_initial_population() {
+        let mut summary = PvaStochasticSummary::new(3, 2, 2, 1.0);
+        summary
+            .push(0, 0, &PopulationVector::new(vec![0.5, 0.0]))
+            .unwrap();
+        summary
+            .push(1, 0, &PopulationVector::new(vec![5.0, 5.0]))
+            .unwrap();
+        assert_eq!(summary.extinction_steps(), &vec![Some(0), None]);
+        assert!(summary.total_statistics().iter().all(|x| x.count() == 0));
+        let popvector = PopulationVector::new(vec![5.0, 5.0]);
+        assert!(summary.push(2, 1, &popvector).is_err());
+        assert!(summary.push(0, 4, &popvector).is_err());
+        assert!(summary
+            .push(0, 1, &PopulationVector::new(vec![5.0]))
+            .is_err());
+        assert!(summary.stage_statistics(2).is_some());
+        assert!(summary.stage_statistics(3).is_none());
+    }
+    #[test]
+    fn extinction_probability_bounds() {
+        let output = test_population().stochastic_projection(50, 20, 3);
+        assert_eq!(output.extinction_probability(-1.0), 0.0);
+        assert_eq!(output.extinction_probability(f64::MAX), 1.0);
+    }
+}
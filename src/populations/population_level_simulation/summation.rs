@@ -0,0 +1,94 @@
+//! This module contains the choice of how the dot products of a projection are added up. Plain summation is fastest and is exact enough for most models, but round-off accumulates over very long projections and over the hundreds of terms in each row of an IPM-scale matrix. Compensated summation keeps a running correction for the round-off of every addition, so each projected value is accurate to about the last bit regardless of the number of terms.
+use super::Real;
+use std::{fmt, str::FromStr};
+
+/// This enum lists how [`PopulationMatrix::project_vector`](super::PopulationMatrix::project_vector)
+/// adds up the products of each row and the Population Vector:
+/// - `Plain`: add the products in order (the default).
+/// - `Compensated`: add the products in order with Neumaier's variant of Kahan summation,
+///   carrying the round-off of each addition to the end. This takes about four times as many
+///   operations and is always done in `f64`, even for `f32` matrices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Summation {
+    #[default]
+    Plain,
+    Compensated,
+}
+impl fmt::Display for Summation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Summation::Plain => "plain",
+            Summation::Compensated => "compensated",
+        };
+        write!(f, "{}", text)
+    }
+}
+impl FromStr for Summation {
+    type Err = String;
+    /// Parse `plain` or `compensated` (or `kahan`), in any case.
+    fn from_str(text: &str) -> Result<Summation, String> {
+        match text.trim().to_lowercase().as_str() {
+            "plain" => Ok(Summation::Plain),
+            "compensated" | "kahan" => Ok(Summation::Compensated),
+            _ => Err(format!(
+                "\"{}\" is not a summation method. Use plain or compensated.",
+                text.trim()
+            )),
+        }
+    }
+}
+
+/// Return the dot product of a row of a matrix and a vector using compensated summation.
+pub(crate) fn compensated_dot<T: Real>(row: &[T], vector: &[T]) -> T {
+    compensated_sum(row.iter().zip(vector).map(|(a, b)| (*a, *b)))
+}
+
+/// Return the sum of the products of pairs of values using compensated summation.
+pub(crate) fn compensated_sum<T: Real>(pairs: impl Iterator<Item = (T, T)>) -> T {
+    let mut total = 0.0_f64;
+    let mut compensation = 0.0_f64;
+    for (item, value) in pairs {
+        let product = item.to_f64() * value.to_f64();
+        let sum = total + product;
+        // Recover the low-order bits lost from whichever term was smaller.
+        if total.abs() >= product.abs() {
+            compensation += (total - sum) + product;
+        } else {
+            compensation += (product - sum) + total;
+        }
+        total = sum;
+    }
+    // An overflowing sum leaves the compensation NaN, so report the overflow itself.
+    if total.is_finite() {
+        T::from_f64(total + compensation)
+    } else {
+        T::from_f64(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compensated_dot_recovers_lost_digits() {
+        let row = vec![1.0; 10_001];
+        let mut vector = vec![1e-16; 10_001];
+        vector[0] = 1.0;
+        let plain: f64 = row.iter().zip(&vector).map(|(a, b)| a * b).sum();
+        assert_eq!(plain, 1.0);
+        assert!((compensated_dot(&row, &vector) - (1.0 + 1e-12)).abs() < 1e-24);
+        let row = [1.0, 1e100, 1.0, -1e100];
+        assert_eq!(compensated_dot(&row, &[1.0; 4]), 2.0);
+        assert_eq!(
+            compensated_dot(&[1e300, 1e300], &[1e10, 0.0]),
+            f64::INFINITY
+        );
+    }
+    #[test]
+    fn summation_parses() {
+        assert_eq!("Kahan".parse::<Summation>(), Ok(Summation::Compensated));
+        assert_eq!(Summation::Plain.to_string().parse(), Ok(Summation::Plain));
+        assert!("exact".parse::<Summation>().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! This module contains the time step of a model: the length of time one projection step represents. Declaring it lets outputs label elapsed time in real units and lets results be converted between models with different time steps, so that (for example) a monthly matrix is never read as if it were annual.
+use std::{fmt, str::FromStr};
+
+/// The number of days in an average calendar year, used to convert between units.
+const DAYS_PER_YEAR: f64 = 365.25;
+
+/// This enum lists the units a time step can be given in. A month is one twelfth of an average
+/// year (365.25 days) and a week is seven days.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeUnit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+impl TimeUnit {
+    /// Return the length of the unit in days.
+    pub fn days(self) -> f64 {
+        match self {
+            TimeUnit::Day => 1.0,
+            TimeUnit::Week => 7.0,
+            TimeUnit::Month => DAYS_PER_YEAR / 12.0,
+            TimeUnit::Year => DAYS_PER_YEAR,
+        }
+    }
+    /// Return the name of the unit, such as `month`. This is also the name of the elapsed time
+    /// column in output tables.
+    pub fn get_name(self) -> &'static str {
+        match self {
+            TimeUnit::Day => "day",
+            TimeUnit::Week => "week",
+            TimeUnit::Month => "month",
+            TimeUnit::Year => "year",
+        }
+    }
+    /// Return the plural name of the unit, such as `months`.
+    pub fn get_plural(self) -> &'static str {
+        match self {
+            TimeUnit::Day => "days",
+            TimeUnit::Week => "weeks",
+            TimeUnit::Month => "months",
+            TimeUnit::Year => "years",
+        }
+    }
+}
+impl fmt::Display for TimeUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+impl FromStr for TimeUnit {
+    type Err = String;
+    /// Parse a unit name, singular or plural, in any case (`year`, `Months`, `d`, etc.).
+    fn from_str(text: &str) -> Result<TimeUnit, String> {
+        match text.trim().to_lowercase().as_str() {
+            "d" | "day" | "days" => Ok(TimeUnit::Day),
+            "w" | "week" | "weeks" => Ok(TimeUnit::Week),
+            "m" | "month" | "months" => Ok(TimeUnit::Month),
+            "y" | "yr" | "year" | "years" => Ok(TimeUnit::Year),
+            _ => Err(format!(
+                "\"{}\" is not a time unit. Use day, week, month, or year.",
+                text.trim()
+            )),
+        }
+    }
+}
+
+/// This struct holds the length of time one projection step represents, such as one year or
+/// six months. Durations measured in steps (generation time, time to extinction, the length of
+/// a projection) can be converted to any unit with it, and growth rates can be converted
+/// between time steps.
+/// ```
+/// use ecolysis_cmd::populations::population_level_simulation::{TimeStep, TimeUnit};
+/// let monthly: TimeStep = "1 month".parse().unwrap();
+/// // A generation time of 30 steps is 30 months, or 2.5 years.
+/// assert_eq!(monthly.duration(30.0), 30.0);
+/// assert!((monthly.convert_duration(30.0, TimeUnit::Year) - 2.5).abs() < 1e-12);
+/// // A monthly lambda of 1.01 is an annual lambda of 1.01^12.
+/// let annual = TimeStep::new(1.0, TimeUnit::Year).unwrap();
+/// assert!((monthly.convert_growth_rate(1.01, &annual) - 1.01_f64.powi(12)).abs() < 1e-12);
+/// assert_eq!(monthly.format_duration(30.0), "30 months");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeStep {
+    length: f64,
+    unit: TimeUnit,
+}
+impl TimeStep {
+    /// Create a time step of `length` units.
+    /// # Errors
+    /// Will return `Err<'static str>` if `length` is not positive and finite.
+    pub fn new(length: f64, unit: TimeUnit) -> Result<TimeStep, &'static str> {
+        if !length.is_finite() || length <= 0.0 {
+            return Err("The length of a time step must be positive.");
+        }
+        Ok(TimeStep { length, unit })
+    }
+    /// Return the number of units in one step.
+    pub fn get_length(&self) -> f64 {
+        self.length
+    }
+    /// Return the unit the time step is given in.
+    pub fn get_unit(&self) -> TimeUnit {
+        self.unit
+    }
+    /// Return the length of `steps` steps in the unit of the time step.
+    pub fn duration(&self, steps: f64) -> f64 {
+        steps * self.length
+    }
+    /// Return the length of `steps` steps in `unit`.
+    pub fn convert_duration(&self, steps: f64, unit: TimeUnit) -> f64 {
+        steps * self.length * self.unit.days() / unit.days()
+    }
+    /// Return the number of steps that cover `duration` of `unit` (which need not be whole).
+    pub fn steps_for(&self, duration: f64, unit: TimeUnit) -> f64 {
+        duration * unit.days() / (self.unit.days() * self.length)
+    }
+    /// Return the growth rate over one step of `other` that is equivalent to a growth rate of
+    /// `lambda` over one step of this time step, `lambda^(other / self)`.
+    pub fn convert_growth_rate(&self, lambda: f64, other: &TimeStep) -> f64 {
+        lambda.powf(self.steps_for(other.length, other.unit))
+    }
+    /// Return `steps` steps written as a duration in the unit of the time step, such as
+    /// `30 months` or `2.5 years`.
+    pub fn format_duration(&self, steps: f64) -> String {
+        let duration = self.duration(steps);
+        let unit = if duration == 1.0 {
+            self.unit.get_name()
+        } else {
+            self.unit.get_plural()
+        };
+        format!("{} {}", duration, unit)
+    }
+}
+impl fmt::Display for TimeStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_duration(1.0))
+    }
+}
+impl FromStr for TimeStep {
+    type Err = String;
+    /// Parse a time step written as a unit with an optional length before it, such as `year`,
+    /// `1 month`, `6 months`, or `0.5 year`.
+    fn from_str(text: &str) -> Result<TimeStep, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (length, unit) = match words.as_slice() {
+            [unit] => (1.0, unit),
+            [length, unit] => (
+                length
+                    .parse::<f64>()
+                    .map_err(|_| format!("\"{}\" is not a number.", length))?,
+                unit,
+            ),
+            _ => {
+                return Err(format!(
+                    "\"{}\" is not a time step. Write it like `1 year` or `6 months`.",
+                    text.trim()
+                ))
+            }
+        };
+        TimeStep::new(length, unit.parse()?).map_err(String::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_convert_between_units() {
+        let fortnight = TimeStep::new(2.0, TimeUnit::Week).unwrap();
+        assert_eq!(fortnight.duration(3.0), 6.0);
+        assert_eq!(fortnight.convert_duration(3.0, TimeUnit::Day), 42.0);
+        assert!((fortnight.steps_for(1.0, TimeUnit::Year) - 365.25 / 14.0).abs() < 1e-12);
+        let half_year = TimeStep::new(0.5, TimeUnit::Year).unwrap();
+        assert!((half_year.convert_duration(4.0, TimeUnit::Month) - 24.0).abs() < 1e-12);
+        assert_eq!(half_year.format_duration(2.0), "1 year");
+    }
+
+    #[test]
+    fn growth_rates_convert_between_time_steps() {
+        let annual = TimeStep::new(1.0, TimeUnit::Year).unwrap();
+        let quarterly = TimeStep::new(3.0, TimeUnit::Month).unwrap();
+        assert!((annual.convert_growth_rate(1.21, &quarterly) - 1.21_f64.powf(0.25)).abs() < 1e-12);
+        assert!((quarterly.convert_growth_rate(1.1, &annual) - 1.1_f64.powi(4)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn time_steps_are_parsed() {
+        assert_eq!(
+            "6 months".parse::<TimeStep>(),
+            TimeStep::new(6.0, TimeUnit::Month).map_err(String::from)
+        );
+        assert_eq!("Year".parse::<TimeStep>().unwrap().to_string(), "1 year");
+        assert!("fortnight".parse::<TimeStep>().is_err());
+        assert!("-1 day".parse::<TimeStep>().is_err());
+        assert!("1 big year".parse::<TimeStep>().is_err());
+    }
+}
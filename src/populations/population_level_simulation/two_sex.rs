@@ -0,0 +1,235 @@
+//! This module contains two-sex matrix models, which track female and male lifestages in one projection matrix. Reproduction is female-dominant: every offspring is produced by females and split between the sexes by the birth sex ratio, so species with skewed or temperature-dependent sex ratios can be modelled by changing one number instead of rebuilding the matrix.
+use super::{DecomposedMatrix, PopulationMatrix, PopulationVector};
+
+/// This struct represents a two-sex matrix model with `n` lifestages for each sex. Its projection
+/// matrix has the `n` female lifestages first and the `n` male lifestages after them:
+///
+/// ```text
+/// [ Uf + r F     0  ]
+/// [ (1 - r) F    Um ]
+/// ```
+///
+/// where `Uf` and `Um` are the female and male survival/growth matrices, `F` is the number of
+/// offspring of both sexes produced per female, and `r` is the birth sex ratio (the proportion
+/// of offspring that are female).
+/// ```
+/// use ecolysis_cmd::PopulationMatrix;
+/// use ecolysis_cmd::populations::population_level_simulation::{DecomposedMatrix, TwoSexMatrix};
+/// let female = DecomposedMatrix::build(
+///     PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.5, 0.8]]).unwrap(),
+///     PopulationMatrix::build(vec![vec![0.0, 4.0], vec![0.0, 0.0]]).unwrap(),
+/// ).unwrap();
+/// let male_survival = PopulationMatrix::build(vec![vec![0.0, 0.0], vec![0.4, 0.7]]).unwrap();
+/// let two_sex = TwoSexMatrix::build(female, male_survival, 0.5).unwrap();
+/// let matrix = two_sex.get_matrix();
+/// assert_eq!(matrix.get_lifestage_count(), 4);
+/// // Half of the four offspring of each adult female are daughters and half are sons.
+/// assert_eq!((matrix[(0, 1)], matrix[(2, 1)]), (2.0, 2.0));
+/// ```
+#[derive(Clone)]
+pub struct TwoSexMatrix {
+    female: DecomposedMatrix,
+    male_survival: PopulationMatrix,
+    sex_ratio: f64,
+}
+impl TwoSexMatrix {
+    /// Build a two-sex matrix from the female survival and fertility (offspring of both sexes
+    /// per female), the male survival, and the birth sex ratio.
+    /// # Errors
+    /// Will return `Err<'static str>` if the female and male matrices have different numbers of
+    /// lifestages, the two sexes together have more than 255 lifestages, the male survival matrix
+    /// is invalid (see [`DecomposedMatrix::build`]), or the sex ratio is not between zero and one.
+    pub fn build(
+        female: DecomposedMatrix,
+        male_survival: PopulationMatrix,
+        sex_ratio: f64,
+    ) -> Result<TwoSexMatrix, &'static str> {
+        let size = female.get_lifestage_count();
+        if male_survival.get_lifestage_count() != size {
+            return Err("Female and male matrices must have the same number of lifestages.");
+        }
+        if size as usize * 2 > u8::MAX as usize {
+            return Err("A two-sex matrix can have at most 127 lifestages per sex.");
+        }
+        if !(0.0..=1.0).contains(&sex_ratio) {
+            return Err("The birth sex ratio must be between zero and one.");
+        }
+        let no_fertility = PopulationMatrix::build(vec![vec![0.0; size as usize]; size as usize])?;
+        DecomposedMatrix::build(male_survival.clone(), no_fertility)?;
+        Ok(TwoSexMatrix {
+            female,
+            male_survival,
+            sex_ratio,
+        })
+    }
+    /// Build a two-sex matrix from a female-only model whose fertility counts daughters, as most
+    /// single-sex matrices do. Fertility is divided by the sex ratio to give offspring of both
+    /// sexes, and males are given the same survival as females unless `male_survival` is given.
+    /// # Errors
+    /// Will return `Err<'static str>` if the sex ratio is zero or any condition of
+    /// [`TwoSexMatrix::build`] is not met.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::{DecomposedMatrix, TwoSexMatrix};
+    /// let single_sex = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+    /// let female = DecomposedMatrix::from_fertility_rows(&single_sex, &[0]).unwrap();
+    /// // 60% of hatchlings are female.
+    /// let two_sex = TwoSexMatrix::from_female_model(female, None, 0.6).unwrap();
+    /// assert!((two_sex.get_matrix().lambda() - single_sex.lambda()).abs() < 1e-9);
+    /// ```
+    pub fn from_female_model(
+        female: DecomposedMatrix,
+        male_survival: Option<PopulationMatrix>,
+        sex_ratio: f64,
+    ) -> Result<TwoSexMatrix, &'static str> {
+        if sex_ratio <= 0.0 {
+            return Err("A female-only model needs a birth sex ratio above zero.");
+        }
+        let fertility = female.get_fertility().get_matrix().clone();
+        let female = DecomposedMatrix::build(
+            female.get_survival().clone(),
+            PopulationMatrix::build(
+                fertility
+                    .iter()
+                    .map(|row| row.iter().map(|x| x / sex_ratio).collect())
+                    .collect(),
+            )?,
+        )?;
+        let male_survival = male_survival.unwrap_or_else(|| female.get_survival().clone());
+        TwoSexMatrix::build(female, male_survival, sex_ratio)
+    }
+    /// Return the female survival and fertility. Fertility counts offspring of both sexes.
+    pub fn get_female(&self) -> &DecomposedMatrix {
+        &self.female
+    }
+    /// Return the male survival/growth matrix.
+    pub fn get_male_survival(&self) -> &PopulationMatrix {
+        &self.male_survival
+    }
+    /// Return the proportion of offspring that are female.
+    pub fn get_sex_ratio(&self) -> f64 {
+        self.sex_ratio
+    }
+    /// Return the number of lifestages of each sex (half the size of the projection matrix).
+    pub fn get_lifestage_count(&self) -> u8 {
+        self.female.get_lifestage_count()
+    }
+    /// Return a copy of the model with a different birth sex ratio, for example one set by
+    /// incubation temperature.
+    /// # Errors
+    /// Will return `Err<'static str>` if the sex ratio is not between zero and one.
+    pub fn with_sex_ratio(&self, sex_ratio: f64) -> Result<TwoSexMatrix, &'static str> {
+        TwoSexMatrix::build(self.female.clone(), self.male_survival.clone(), sex_ratio)
+    }
+    /// Return the projection matrix, with the female lifestages first and the male lifestages
+    /// after them.
+    pub fn get_matrix(&self) -> PopulationMatrix {
+        let size = self.get_lifestage_count() as usize;
+        let female_survival = self.female.get_survival();
+        let fertility = self.female.get_fertility();
+        let mut matrix = vec![vec![0.0; size * 2]; size * 2];
+        for row in 0..size {
+            for column in 0..size {
+                let offspring = fertility[(row, column)];
+                matrix[row][column] = female_survival[(row, column)] + self.sex_ratio * offspring;
+                matrix[size + row][column] = (1.0 - self.sex_ratio) * offspring;
+                matrix[size + row][size + column] = self.male_survival[(row, column)];
+            }
+        }
+        female_survival.derived(matrix)
+    }
+    /// Return the Population Vector of the two-sex model made of the female and male lifestages.
+    /// # Errors
+    /// Will return `Err<'static str>` if either vector does not have one value per lifestage.
+    pub fn combine(
+        &self,
+        female: &PopulationVector,
+        male: &PopulationVector,
+    ) -> Result<PopulationVector, &'static str> {
+        let size = self.get_lifestage_count();
+        if female.get_lifestage_count() != size || male.get_lifestage_count() != size {
+            return Err("Each sex needs one value per lifestage.");
+        }
+        Ok(PopulationVector::new(
+            female.iter().chain(male.iter()).copied().collect(),
+        ))
+    }
+    /// Split a Population Vector of the two-sex model into its female and male lifestages.
+    /// # Errors
+    /// Will return `Err<'static str>` if the vector does not have two values per lifestage.
+    /// ```
+    /// use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+    /// use ecolysis_cmd::populations::population_level_simulation::{DecomposedMatrix, TwoSexMatrix};
+    /// let single_sex = PopulationMatrix::build(vec![vec![0.0, 1.5], vec![0.5, 0.8]]).unwrap();
+    /// let female = DecomposedMatrix::from_fertility_rows(&single_sex, &[0]).unwrap();
+    /// let two_sex = TwoSexMatrix::from_female_model(female, None, 0.25).unwrap();
+    /// let start = two_sex.combine(
+    ///     &PopulationVector::new(vec![0.0, 10.0]),
+    ///     &PopulationVector::new(vec![0.0, 10.0]),
+    /// ).unwrap();
+    /// let (females, males) = two_sex.split(&(&two_sex.get_matrix() * &start)).unwrap();
+    /// // Each adult female has 1.5 daughters and 4.5 sons.
+    /// assert_eq!((females.get_vector()[0], males.get_vector()[0]), (15.0, 45.0));
+    /// ```
+    pub fn split(
+        &self,
+        popvector: &PopulationVector,
+    ) -> Result<(PopulationVector, PopulationVector), &'static str> {
+        let size = self.get_lifestage_count() as usize;
+        if popvector.get_lifestage_count() as usize != size * 2 {
+            return Err("A two-sex Population Vector needs two values per lifestage.");
+        }
+        let (female, male) = popvector.get_vector().split_at(size);
+        Ok((
+            PopulationVector::new(female.to_vec()),
+            PopulationVector::new(male.to_vec()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn female() -> DecomposedMatrix {
+        let single_sex = PopulationMatrix::build(vec![vec![0.0, 1.2], vec![0.5, 0.8]]).unwrap();
+        DecomposedMatrix::from_fertility_rows(&single_sex, &[0]).unwrap()
+    }
+
+    #[test]
+    fn sex_ratio_moves_births_between_sexes() {
+        let two_sex = TwoSexMatrix::from_female_model(female(), None, 0.5).unwrap();
+        let start = two_sex
+            .combine(
+                &PopulationVector::new(vec![0.0, 10.0]),
+                &PopulationVector::new(vec![0.0, 0.0]),
+            )
+            .unwrap();
+        let total_births = |ratio: f64| {
+            let matrix = two_sex.with_sex_ratio(ratio).unwrap().get_matrix();
+            let (females, males) = two_sex.split(&(&matrix * &start)).unwrap();
+            (females.get_vector()[0], males.get_vector()[0])
+        };
+        assert_eq!(total_births(0.5), (12.0, 12.0));
+        assert_eq!(total_births(1.0), (24.0, 0.0));
+        assert_eq!(total_births(0.25), (6.0, 18.0));
+        assert!(two_sex.with_sex_ratio(1.5).is_err());
+    }
+    #[test]
+    fn invalid_two_sex_inputs() {
+        assert!(TwoSexMatrix::from_female_model(female(), None, 0.0).is_err());
+        let large_male = PopulationMatrix::build(vec![vec![0.0, 0.5], vec![0.9, 0.9]]).unwrap();
+        assert!(TwoSexMatrix::build(female(), large_male, 0.5).is_err());
+        assert!(TwoSexMatrix::build(female(), PopulationMatrix::identity(3), 0.5).is_err());
+        let two_sex = TwoSexMatrix::build(female(), PopulationMatrix::identity(2), 0.5).unwrap();
+        assert!(two_sex
+            .split(&PopulationVector::new(vec![1.0, 2.0, 3.0]))
+            .is_err());
+        assert!(two_sex
+            .combine(
+                &PopulationVector::new(vec![1.0]),
+                &PopulationVector::new(vec![1.0, 2.0])
+            )
+            .is_err());
+    }
+}
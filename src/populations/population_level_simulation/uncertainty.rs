@@ -0,0 +1,349 @@
+//! This module contains tools for propagating uncertainty in vital rates through to model results using a parametric bootstrap: matrices are repeatedly drawn from the sampling distributions of their vital rates, and the spread of the results gives confidence intervals.
+use super::stochastic::replicate_seed;
+use super::{DecomposedMatrix, PopulationMatrix, PopulationVector};
+use crate::statistics::quantile;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Beta, Distribution, Gamma};
+
+/// This struct represents a Decomposed Matrix whose vital rates are estimates with known
+/// standard errors. Survival/growth rates are sampled from beta distributions and fertilities
+/// from gamma distributions with the same means and standard errors, so sampled survival stays
+/// between zero and one and sampled fertility stays positive. Rates with a standard error of
+/// zero are treated as known exactly.
+pub struct UncertainMatrix {
+    mean: DecomposedMatrix,
+    survival_errors: Vec<Vec<f64>>,
+    fertility_errors: Vec<Vec<f64>>,
+}
+impl UncertainMatrix {
+    /// Build an Uncertain Matrix from a Decomposed Matrix of mean vital rates and matrices of
+    /// standard errors for the survival (U) and fertility (F) elements.
+    /// # Errors
+    /// Will return `Err<'static str>` if the standard error matrices are not the same shape as
+    /// the mean matrix, or contain negative values.
+    pub fn from_standard_errors(
+        mean: DecomposedMatrix,
+        survival_errors: Vec<Vec<f64>>,
+        fertility_errors: Vec<Vec<f64>>,
+    ) -> Result<UncertainMatrix, &'static str> {
+        let size = mean.get_lifestage_count() as usize;
+        for errors in [&survival_errors, &fertility_errors] {
+            if errors.len() != size || errors.iter().any(|row| row.len() != size) {
+                return Err("Standard error matrices must match the size of the mean matrix.");
+            }
+            if errors.iter().flatten().any(|x| *x < 0.0 || x.is_nan()) {
+                return Err("Standard errors cannot be negative.");
+            }
+        }
+        Ok(UncertainMatrix {
+            mean,
+            survival_errors,
+            fertility_errors,
+        })
+    }
+    /// Build an Uncertain Matrix from a Decomposed Matrix of mean vital rates and the number of
+    /// individuals each rate was estimated from. Survival standard errors are binomial
+    /// (`sqrt(p(1 - p) / n)`) and fertility standard errors are Poisson (`sqrt(f / n)`). A sample
+    /// size of zero means the rate is known exactly.
+    /// # Errors
+    /// Will return `Err<'static str>` if the sample size matrices are not the same shape as the
+    /// mean matrix.
+    /// ```
+    /// use ecolysis_cmd::PopulationMatrix;
+    /// use ecolysis_cmd::populations::population_level_simulation::{DecomposedMatrix, UncertainMatrix};
+    /// let matrix = PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap();
+    /// let decomposed = DecomposedMatrix::from_fertility_rows(&matrix, &[0]).unwrap();
+    /// let uncertain = UncertainMatrix::from_sample_sizes(
+    ///     decomposed,
+    ///     vec![vec![0, 0], vec![40, 25]],
+    ///     vec![vec![0, 25], vec![0, 0]],
+    /// ).unwrap();
+    /// let bootstrap = uncertain.bootstrap(500, 42);
+    /// let (lower, upper) = bootstrap.lambda_interval(0.95);
+    /// assert!(lower < matrix.lambda() && matrix.lambda() < upper);
+    /// ```
+    pub fn from_sample_sizes(
+        mean: DecomposedMatrix,
+        survival_sample_sizes: Vec<Vec<u64>>,
+        fertility_sample_sizes: Vec<Vec<u64>>,
+    ) -> Result<UncertainMatrix, &'static str> {
+        let size = mean.get_lifestage_count() as usize;
+        for sizes in [&survival_sample_sizes, &fertility_sample_sizes] {
+            if sizes.len() != size || sizes.iter().any(|row| row.len() != size) {
+                return Err("Sample size matrices must match the size of the mean matrix.");
+            }
+        }
+        let errors = |matrix: &PopulationMatrix, sizes: &[Vec<u64>], variance: fn(f64) -> f64| {
+            matrix
+                .get_matrix()
+                .iter()
+                .zip(sizes)
+                .map(|(rates, sizes)| {
+                    rates
+                        .iter()
+                        .zip(sizes)
+                        .map(|(rate, n)| match n {
+                            0 => 0.0,
+                            n => (variance(*rate) / *n as f64).sqrt(),
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        let survival_errors = errors(mean.get_survival(), &survival_sample_sizes, |p| {
+            p * (1.0 - p)
+        });
+        let fertility_errors = errors(mean.get_fertility(), &fertility_sample_sizes, |f| f);
+        UncertainMatrix::from_standard_errors(mean, survival_errors, fertility_errors)
+    }
+    /// Return the Decomposed Matrix of mean vital rates.
+    pub fn get_mean(&self) -> &DecomposedMatrix {
+        &self.mean
+    }
+    /// Draw one Decomposed Matrix from the sampling distributions of the vital rates. If the
+    /// sampled survival rates of a lifestage add up to more than one, they are scaled down so that
+    /// they add up to exactly one.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> DecomposedMatrix {
+        let size = self.mean.get_lifestage_count() as usize;
+        let mut survival = vec![vec![0.0; size]; size];
+        let mut fertility = vec![vec![0.0; size]; size];
+        for row in 0..size {
+            for column in 0..size {
+                survival[row][column] = sample_probability(
+                    self.mean.get_survival()[(row, column)],
+                    self.survival_errors[row][column],
+                    rng,
+                );
+                fertility[row][column] = sample_positive(
+                    self.mean.get_fertility()[(row, column)],
+                    self.fertility_errors[row][column],
+                    rng,
+                );
+            }
+        }
+        for column in 0..size {
+            let total: f64 = survival.iter().map(|row| row[column]).sum();
+            if total > 1.0 {
+                for row in survival.iter_mut() {
+                    row[column] /= total;
+                }
+            }
+        }
+        DecomposedMatrix::build(
+            PopulationMatrix::build(survival).expect("Sampled matrices are square."),
+            PopulationMatrix::build(fertility).expect("Sampled matrices are square."),
+        )
+        .expect("Sampled survival is scaled to be valid.")
+    }
+    /// Run a parametric bootstrap with the given number of sampled matrices, recording lambda
+    /// and the elasticities of each sampled matrix.
+    pub fn bootstrap(&self, samples: u32, seed: u64) -> BootstrapOutput {
+        let mut output = BootstrapOutput::default();
+        for sample in 0..samples {
+            let mut rng = StdRng::seed_from_u64(replicate_seed(seed, sample));
+            let matrix = self.sample(&mut rng).get_matrix();
+            output.lambdas.push(matrix.lambda());
+            output.elasticities.push(matrix.elasticities());
+        }
+        output
+    }
+    /// Run a parametric bootstrap like [`UncertainMatrix::bootstrap`], and also estimate the
+    /// extinction probability of each sampled matrix. For each sampled matrix, `replicates`
+    /// projections with demographic stochasticity are run for `iterations` steps from
+    /// `initial_population`, and a replicate counts as extinct if its total population falls to or
+    /// below `extinction_threshold`.
+    /// # Errors
+    /// Will return `Err<'static str>` if the initial population does not match the matrix size.
+    pub fn bootstrap_with_extinction(
+        &self,
+        samples: u32,
+        seed: u64,
+        initial_population: &PopulationVector,
+        iterations: u32,
+        replicates: u32,
+        extinction_threshold: f64,
+    ) -> Result<BootstrapOutput, &'static str> {
+        if initial_population.get_lifestage_count() != self.mean.get_lifestage_count() {
+            return Err("Population vector size does not match matrices.");
+        }
+        let mut output = BootstrapOutput::default();
+        for sample in 0..samples {
+            let mut rng = StdRng::seed_from_u64(replicate_seed(seed, sample));
+            let decomposed = self.sample(&mut rng);
+            let matrix = decomposed.get_matrix();
+            output.lambdas.push(matrix.lambda());
+            output.elasticities.push(matrix.elasticities());
+            let mut extinct = 0;
+            for _ in 0..replicates {
+                let mut active_vector = initial_population.clone();
+                for _ in 0..iterations {
+                    active_vector = decomposed.project_demographic(&active_vector, &mut rng);
+                    if active_vector.iter().sum::<f64>() <= extinction_threshold {
+                        extinct += 1;
+                        break;
+                    }
+                }
+            }
+            output
+                .extinction_probabilities
+                .push(extinct as f64 / replicates.max(1) as f64);
+        }
+        Ok(output)
+    }
+}
+
+/// Draw a probability from a beta distribution with the given mean and standard error.
+fn sample_probability<R: Rng + ?Sized>(mean: f64, error: f64, rng: &mut R) -> f64 {
+    if error == 0.0 || mean <= 0.0 || mean >= 1.0 {
+        return mean;
+    }
+    // The variance of a beta distribution must be below mean * (1 - mean).
+    let variance = (error * error).min(mean * (1.0 - mean) * 0.999);
+    let common = mean * (1.0 - mean) / variance - 1.0;
+    Beta::new(mean * common, (1.0 - mean) * common)
+        .map(|distribution| distribution.sample(rng))
+        .unwrap_or(mean)
+}
+
+/// Draw a positive value from a gamma distribution with the given mean and standard error.
+fn sample_positive<R: Rng + ?Sized>(mean: f64, error: f64, rng: &mut R) -> f64 {
+    if error == 0.0 || mean <= 0.0 {
+        return mean;
+    }
+    let variance = error * error;
+    Gamma::new(mean * mean / variance, variance / mean)
+        .map(|distribution| distribution.sample(rng))
+        .unwrap_or(mean)
+}
+
+/// This struct stores the results of a parametric bootstrap: lambda and the elasticities of every
+/// sampled matrix, and (if requested) the extinction probability of every sampled matrix.
+#[derive(Default)]
+pub struct BootstrapOutput {
+    lambdas: Vec<f64>,
+    elasticities: Vec<PopulationMatrix>,
+    extinction_probabilities: Vec<f64>,
+}
+impl BootstrapOutput {
+    /// Return lambda for each sampled matrix.
+    pub fn get_lambdas(&self) -> &Vec<f64> {
+        &self.lambdas
+    }
+    /// Return the elasticity matrix of each sampled matrix.
+    pub fn get_elasticities(&self) -> &Vec<PopulationMatrix> {
+        &self.elasticities
+    }
+    /// Return the extinction probability of each sampled matrix. This is empty unless the
+    /// bootstrap was run with [`UncertainMatrix::bootstrap_with_extinction`].
+    pub fn get_extinction_probabilities(&self) -> &Vec<f64> {
+        &self.extinction_probabilities
+    }
+    /// Return the `(lower, upper)` percentile confidence interval of lambda at the given level
+    /// (for example `0.95`).
+    pub fn lambda_interval(&self, level: f64) -> (f64, f64) {
+        percentile_interval(&self.lambdas, level)
+    }
+    /// Return the `(lower, upper)` percentile confidence interval of the elasticity of every
+    /// matrix element, indexed `[row][column]`.
+    pub fn elasticity_intervals(&self, level: f64) -> Vec<Vec<(f64, f64)>> {
+        let size = self
+            .elasticities
+            .first()
+            .map_or(0, |x| x.get_lifestage_count() as usize);
+        (0..size)
+            .map(|row| {
+                (0..size)
+                    .map(|column| {
+                        let values: Vec<f64> =
+                            self.elasticities.iter().map(|x| x[(row, column)]).collect();
+                        percentile_interval(&values, level)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    /// Return the `(lower, upper)` percentile confidence interval of the extinction probability,
+    /// or `None` if extinction was not estimated.
+    pub fn extinction_interval(&self, level: f64) -> Option<(f64, f64)> {
+        if self.extinction_probabilities.is_empty() {
+            return None;
+        }
+        Some(percentile_interval(&self.extinction_probabilities, level))
+    }
+}
+
+/// Return the central percentile interval containing `level` of the values.
+pub(crate) fn percentile_interval(values: &[f64], level: f64) -> (f64, f64) {
+    let tail = (1.0 - level.clamp(0.0, 1.0)) / 2.0;
+    (quantile(values, tail), quantile(values, 1.0 - tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean_matrix() -> DecomposedMatrix {
+        DecomposedMatrix::from_fertility_rows(
+            &PopulationMatrix::build(vec![vec![0.0, 3.0], vec![0.5, 0.8]]).unwrap(),
+            &[0],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn zero_error_reproduces_mean() {
+        let uncertain = UncertainMatrix::from_standard_errors(
+            mean_matrix(),
+            vec![vec![0.0; 2]; 2],
+            vec![vec![0.0; 2]; 2],
+        )
+        .unwrap();
+        let bootstrap = uncertain.bootstrap(10, 1);
+        let lambda = mean_matrix().get_matrix().lambda();
+        assert!(bootstrap
+            .get_lambdas()
+            .iter()
+            .all(|x| (x - lambda).abs() < 1e-12));
+        assert!(bootstrap.extinction_interval(0.95).is_none());
+    }
+    #[test]
+    fn sampled_survival_stays_valid() {
+        let uncertain = UncertainMatrix::from_standard_errors(
+            mean_matrix(),
+            vec![vec![0.0, 0.0], vec![0.3, 0.3]],
+            vec![vec![0.0, 2.0], vec![0.0, 0.0]],
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let sample = uncertain.sample(&mut rng);
+            assert!(sample.get_survival()[(1, 1)] <= 1.0);
+            assert!(sample.get_fertility()[(0, 1)] >= 0.0);
+        }
+    }
+    #[test]
+    fn rejects_mismatched_errors() {
+        assert!(
+            UncertainMatrix::from_standard_errors(mean_matrix(), vec![vec![0.0]], vec![]).is_err()
+        );
+    }
+    #[test]
+    fn extinction_interval_is_ordered() {
+        let uncertain = UncertainMatrix::from_sample_sizes(
+            DecomposedMatrix::from_fertility_rows(
+                &PopulationMatrix::build(vec![vec![0.0, 0.9], vec![0.4, 0.6]]).unwrap(),
+                &[0],
+            )
+            .unwrap(),
+            vec![vec![0, 0], vec![20, 20]],
+            vec![vec![0, 20], vec![0, 0]],
+        )
+        .unwrap();
+        let bootstrap = uncertain
+            .bootstrap_with_extinction(20, 9, &PopulationVector::new(vec![5.0, 5.0]), 20, 20, 0.0)
+            .unwrap();
+        let (lower, upper) = bootstrap.extinction_interval(0.9).unwrap();
+        assert!(0.0 <= lower && lower <= upper && upper <= 1.0);
+        assert_eq!(bootstrap.elasticity_intervals(0.9).len(), 2);
+    }
+}
@@ -0,0 +1,392 @@
+//! This module contains projection records, which keep only as much of a projection as was asked for: the final population of each replicate, the total population at each step, the full stage-by-step trajectories, or all of these with per-step diagnostics. A record is built while the projection runs, so detail that is not requested is never stored (and diagnostics are never calculated), which keeps long or heavily replicated runs small.
+use super::projection_config::ReplicateRun;
+use super::{
+    keyfitz_delta, DeterministicPva, NumericFault, PopulationVector, ProjectionConfig,
+    StochasticPva, StopReason, TimeStep,
+};
+use crate::simulation::Simulation;
+use std::{fmt, str::FromStr};
+
+/// This enum lists how much of a projection a [`ProjectionRecord`] keeps, from least to most:
+/// - `Final`: the population of each replicate when it stopped.
+/// - `Totals`: also the total population at every step.
+/// - `Trajectories`: also the population of every lifestage at every step.
+/// - `Diagnostics`: also the [`StepDiagnostics`] of every step.
+///
+/// Each level keeps everything the levels before it keep, so levels can be compared with `<`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Verbosity {
+    Final,
+    Totals,
+    Trajectories,
+    Diagnostics,
+}
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Verbosity::Final => "final",
+            Verbosity::Totals => "totals",
+            Verbosity::Trajectories => "trajectories",
+            Verbosity::Diagnostics => "diagnostics",
+        };
+        write!(f, "{}", text)
+    }
+}
+impl FromStr for Verbosity {
+    type Err = String;
+    /// Parse `final`, `totals`, `trajectories` (or `full`), or `diagnostics` (or `all`), in any
+    /// case.
+    fn from_str(text: &str) -> Result<Verbosity, String> {
+        match text.trim().to_lowercase().as_str() {
+            "final" => Ok(Verbosity::Final),
+            "totals" => Ok(Verbosity::Totals),
+            "trajectories" | "full" => Ok(Verbosity::Trajectories),
+            "diagnostics" | "all" => Ok(Verbosity::Diagnostics),
+            _ => Err(format!(
+                "\"{}\" is not a level of detail. Use final, totals, trajectories, or diagnostics.",
+                text.trim()
+            )),
+        }
+    }
+}
+
+/// This struct holds the diagnostics of one step of a projection.
+/// - `log_growth_rate`: `ln(N_t / N_t-1)`, the realized growth of the total population over the
+///   step (NaN after the population reached zero).
+/// - `structure_distance`: Keyfitz's delta (see [`keyfitz_delta`]) between the stage structure
+///   after the step and the stable stage distribution of the model's mean matrix, which shows
+///   how far the population is from its asymptotic behaviour.
+/// - `matrix`: the position in the model's list of the matrix used for the step (always 0 for a
+///   deterministic model).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepDiagnostics {
+    pub log_growth_rate: f64,
+    pub structure_distance: f64,
+    pub matrix: usize,
+}
+
+/// This struct holds the parts of a projection kept at its [`Verbosity`]. Every record keeps the
+/// final population, number of steps, and stop reason of each replicate, and the
+/// [`NumericFault`] of any replicate stopped by an invalid value; the per-step lists are empty
+/// unless the verbosity asks for them.
+/// ```
+/// use ecolysis_cmd::populations::population_level_simulation::{
+///     ProjectionConfig, StochasticPva, Verbosity,
+/// };
+/// let model = StochasticPva::build_from_vectors(vec![10.0, 10.0], vec![
+///     vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+///     vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+/// ]).unwrap();
+/// let config = ProjectionConfig::new().steps(50).replicates(100).seed(1);
+/// let totals = model.record(&config.clone().verbosity(Verbosity::Totals));
+/// assert_eq!(totals.get_totals()[0].len(), 50);
+/// assert!(totals.get_trajectories().is_empty());
+/// // The final populations do not depend on how much detail was kept.
+/// let last = model.record(&config.verbosity(Verbosity::Final));
+/// assert_eq!(last.get_final_populations(), totals.get_final_populations());
+/// ```
+pub struct ProjectionRecord {
+    verbosity: Verbosity,
+    final_populations: Vec<PopulationVector>,
+    steps_taken: Vec<u32>,
+    stop_reasons: Vec<StopReason>,
+    totals: Vec<Vec<f64>>,
+    trajectories: Vec<Vec<PopulationVector>>,
+    diagnostics: Vec<Vec<StepDiagnostics>>,
+    faults: Vec<NumericFault>,
+    time_step: Option<TimeStep>,
+}
+impl ProjectionRecord {
+    fn new(verbosity: Verbosity, time_step: Option<TimeStep>) -> ProjectionRecord {
+        ProjectionRecord {
+            verbosity,
+            final_populations: Vec::new(),
+            steps_taken: Vec::new(),
+            stop_reasons: Vec::new(),
+            totals: Vec::new(),
+            trajectories: Vec::new(),
+            diagnostics: Vec::new(),
+            faults: Vec::new(),
+            time_step,
+        }
+    }
+    /// Run one replicate of `simulation` and add it to the record. `matrix` returns the matrix
+    /// the simulation used for its last step, and `stable` is the stable stage distribution used
+    /// for diagnostics (and by `run` for the `Converged` stopping rule).
+    fn add_replicate<S: Simulation<State = PopulationVector>>(
+        &mut self,
+        run: &ReplicateRun,
+        replicate: Option<u32>,
+        mut simulation: S,
+        matrix: impl Fn(&S) -> usize,
+        stable: Option<&PopulationVector>,
+    ) {
+        let mut totals = Vec::new();
+        let mut trajectory = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut previous_total = simulation.state().total();
+        let verbosity = self.verbosity;
+        let result = run.run(replicate, &mut simulation, matrix, |state, matrix| {
+            if verbosity >= Verbosity::Totals {
+                totals.push(state.total());
+            }
+            if verbosity >= Verbosity::Trajectories {
+                trajectory.push(state.clone());
+            }
+            if verbosity >= Verbosity::Diagnostics {
+                let total = state.total();
+                diagnostics.push(StepDiagnostics {
+                    log_growth_rate: (total / previous_total).ln(),
+                    structure_distance: stable.map_or(f64::NAN, |x| keyfitz_delta(state, x)),
+                    matrix,
+                });
+                previous_total = total;
+            }
+        });
+        let stop_reason = match result {
+            Ok(reason) => reason,
+            Err(fault) => {
+                self.faults.push(fault);
+                StopReason::InvalidValue
+            }
+        };
+        self.final_populations.push(simulation.state().clone());
+        self.steps_taken.push(simulation.steps_taken());
+        self.stop_reasons.push(stop_reason);
+        if self.verbosity >= Verbosity::Totals {
+            self.totals.push(totals);
+        }
+        if self.verbosity >= Verbosity::Trajectories {
+            self.trajectories.push(trajectory);
+        }
+        if self.verbosity >= Verbosity::Diagnostics {
+            self.diagnostics.push(diagnostics);
+        }
+    }
+    /// Return the verbosity of the record.
+    pub fn get_verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+    /// Return the population of each replicate when it stopped.
+    pub fn get_final_populations(&self) -> &Vec<PopulationVector> {
+        &self.final_populations
+    }
+    /// Return the number of steps each replicate ran for.
+    pub fn get_steps_taken(&self) -> &Vec<u32> {
+        &self.steps_taken
+    }
+    /// Return the reason each replicate stopped.
+    pub fn get_stop_reasons(&self) -> &Vec<StopReason> {
+        &self.stop_reasons
+    }
+    /// Return the total population at every step of each replicate, or an empty list below
+    /// [`Verbosity::Totals`].
+    pub fn get_totals(&self) -> &Vec<Vec<f64>> {
+        &self.totals
+    }
+    /// Return the population at every step of each replicate, or an empty list below
+    /// [`Verbosity::Trajectories`].
+    pub fn get_trajectories(&self) -> &Vec<Vec<PopulationVector>> {
+        &self.trajectories
+    }
+    /// Return the diagnostics of every step of each replicate, or an empty list below
+    /// [`Verbosity::Diagnostics`].
+    pub fn get_diagnostics(&self) -> &Vec<Vec<StepDiagnostics>> {
+        &self.diagnostics
+    }
+    /// Return the faults that stopped replicates early, in replicate order.
+    pub fn get_faults(&self) -> &Vec<NumericFault> {
+        &self.faults
+    }
+    /// Return the time step of the model that produced the record, if it declared one.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Return the number of replicates.
+    pub fn replicate_count(&self) -> usize {
+        self.final_populations.len()
+    }
+}
+
+/// Return true if a record needs the stable stage distribution, for diagnostics or the
+/// `Converged` stopping rule.
+fn stable_needed(config: &ProjectionConfig) -> bool {
+    config.get_verbosity() >= Verbosity::Diagnostics || config.checks_convergence()
+}
+
+impl DeterministicPva {
+    /// Return a record of a deterministic projection configured by a ProjectionConfig, keeping
+    /// only the detail its verbosity asks for. Stopping rules apply as they do in
+    /// [`DeterministicPva::project`].
+    /// ```
+    /// use ecolysis_cmd::populations::population_level_simulation::{
+    ///     DeterministicPva, ProjectionConfig, Verbosity,
+    /// };
+    /// let model = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+    ///     vec![vec![0.0, 2.0], vec![0.5, 0.8]]).unwrap();
+    /// let config = ProjectionConfig::new().steps(30).verbosity(Verbosity::Diagnostics);
+    /// let record = model.record(&config);
+    /// // The population settles into its stable stage distribution.
+    /// let diagnostics = &record.get_diagnostics()[0];
+    /// assert!(diagnostics[29].structure_distance < diagnostics[0].structure_distance);
+    /// ```
+    pub fn record(&self, config: &ProjectionConfig) -> ProjectionRecord {
+        let model = self.configured(config);
+        let stable = stable_needed(config).then(|| model.get_matrix().stable_stage_distribution());
+        let run = ReplicateRun::new(model.get_core(), config.get_steps())
+            .with_rules(config.get_stopping_rules(), stable.as_ref());
+        let mut record = ProjectionRecord::new(config.get_verbosity(), model.get_time_step());
+        record.add_replicate(&run, None, model.projection_iter(), |_| 0, stable.as_ref());
+        record
+    }
+}
+
+impl StochasticPva {
+    /// Return a record of a stochastic projection configured by a ProjectionConfig, keeping only
+    /// the detail its verbosity asks for. Replicates stop on their own as they do in
+    /// [`StochasticPva::project`], and give the same populations whatever the verbosity.
+    pub fn record(&self, config: &ProjectionConfig) -> ProjectionRecord {
+        let model = self.configured(config);
+        let stable = stable_needed(config).then(|| model.mean_matrix().stable_stage_distribution());
+        let run = ReplicateRun::new(model.get_core(), config.get_steps())
+            .with_rules(config.get_stopping_rules(), stable.as_ref());
+        let mut record = ProjectionRecord::new(config.get_verbosity(), model.get_time_step());
+        for replicate in 0..config.get_replicates() {
+            record.add_replicate(
+                &run,
+                Some(replicate),
+                model.replicate_iter(replicate, config.get_seed()),
+                |x| x.get_last_matrix().unwrap_or(0),
+                stable.as_ref(),
+            );
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::StoppingRule;
+
+    fn model() -> StochasticPva {
+        StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 1.6], vec![0.5, 0.7]],
+                vec![vec![0.0, 0.9], vec![0.4, 0.6]],
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn levels_keep_only_what_is_asked_for() {
+        let config = ProjectionConfig::new().steps(12).replicates(3).seed(5);
+        let full = model().record(&config.clone().verbosity(Verbosity::Diagnostics));
+        let output = model().project(&config);
+        assert_eq!(full.get_trajectories(), output.get_replicates());
+        assert_eq!(full.get_totals()[1], output.total_population()[1]);
+        let diagnostics = &full.get_diagnostics()[2];
+        assert_eq!(diagnostics.len(), 12);
+        let totals = &full.get_totals()[2];
+        assert!((diagnostics[1].log_growth_rate - (totals[1] / totals[0]).ln()).abs() < 1e-12);
+        assert!(diagnostics.iter().all(|x| x.matrix < 2));
+        for verbosity in [Verbosity::Final, Verbosity::Totals, Verbosity::Trajectories] {
+            let record = model().record(&config.clone().verbosity(verbosity));
+            assert_eq!(record.get_final_populations(), full.get_final_populations());
+            assert_eq!(
+                record.get_totals().is_empty(),
+                verbosity < Verbosity::Totals
+            );
+            assert_eq!(
+                record.get_trajectories().is_empty(),
+                verbosity < Verbosity::Trajectories
+            );
+            assert!(record.get_diagnostics().is_empty());
+        }
+    }
+
+    #[test]
+    fn records_follow_stopping_rules() {
+        let declining = DeterministicPva::build_from_vectors(
+            vec![100.0, 100.0],
+            vec![vec![0.0, 0.5], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let config = ProjectionConfig::new()
+            .steps(500)
+            .stop_when(StoppingRule::BelowThreshold(1.0))
+            .verbosity(Verbosity::Final);
+        let record = declining.record(&config);
+        let output = declining.project(&config);
+        assert_eq!(record.get_stop_reasons(), &vec![output.get_stop_reason()]);
+        assert_eq!(record.get_steps_taken()[0] as usize, output.iter().count());
+        assert_eq!(
+            Some(&record.get_final_populations()[0]),
+            output.iter().last()
+        );
+    }
+
+    #[test]
+    fn stochastic_records_converge() {
+        let config = ProjectionConfig::new()
+            .steps(200)
+            .replicates(4)
+            .seed(2)
+            .stop_when(StoppingRule::Converged(0.05))
+            .verbosity(Verbosity::Final);
+        let record = model().record(&config);
+        let output = model().project(&config);
+        assert!(record
+            .get_stop_reasons()
+            .iter()
+            .all(|x| *x == StopReason::Converged));
+        assert_eq!(record.get_stop_reasons(), output.get_stop_reasons());
+        let lengths: Vec<u32> = output
+            .get_replicates()
+            .iter()
+            .map(|x| x.len() as u32)
+            .collect();
+        assert_eq!(record.get_steps_taken(), &lengths);
+    }
+
+    #[test]
+    fn summaries_follow_verbosity() {
+        let config = ProjectionConfig::new()
+            .steps(20)
+            .replicates(30)
+            .seed(4)
+            .extinction_threshold(15.0);
+        let full = model().summarize(&config).unwrap();
+        let last = model()
+            .summarize(&config.verbosity(Verbosity::Final))
+            .unwrap();
+        assert_eq!(full.step_count(), 20);
+        assert_eq!((last.step_count(), last.first_step()), (1, 20));
+        assert_eq!(last.last_step(), full.last_step());
+        assert_eq!(
+            last.total_statistics()[0].mean(),
+            full.total_statistics()[19].mean()
+        );
+        assert_eq!(last.extinction_steps(), full.extinction_steps());
+        assert_eq!(
+            last.cumulative_extinction_probability()[0],
+            full.extinction_probability()
+        );
+    }
+
+    #[test]
+    fn verbosity_is_parsed() {
+        assert_eq!("Full".parse::<Verbosity>(), Ok(Verbosity::Trajectories));
+        for verbosity in [
+            Verbosity::Final,
+            Verbosity::Totals,
+            Verbosity::Trajectories,
+            Verbosity::Diagnostics,
+        ] {
+            assert_eq!(verbosity.to_string().parse::<Verbosity>(), Ok(verbosity));
+        }
+        assert!("everything".parse::<Verbosity>().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! This module records provenance for simulation runs: the crate version, random seed, hashes of input files, parameter values, and when the run happened. Metadata can be written as a JSON sidecar file next to an output, or as a commented block at the top of a CSV output.
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "cli")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// An input file used by a run, identified by its path and the SHA-256 hash of its contents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputFile {
+    path: String,
+    sha256: String,
+}
+impl InputFile {
+    /// Return the path of the file, as given when it was added.
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+    /// Return the SHA-256 hash of the file contents as lowercase hexadecimal.
+    pub fn get_sha256(&self) -> &str {
+        &self.sha256
+    }
+}
+
+/// This struct holds the metadata describing one simulation run.
+/// ```
+/// use ecolysis_cmd::provenance::RunMetadata;
+/// let mut metadata = RunMetadata::new().with_seed(42);
+/// metadata.add_parameter("steps", 100);
+/// metadata.add_parameter("replicates", 1000);
+/// assert!(metadata.to_json().contains("\"seed\": 42"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RunMetadata {
+    crate_version: String,
+    created: u64,
+    seed: Option<u64>,
+    input_files: Vec<InputFile>,
+    parameters: Vec<(String, String)>,
+}
+impl RunMetadata {
+    /// Create new run metadata with the current crate version and time. On
+    /// `wasm32-unknown-unknown`, which has no clock, the time is recorded as 0; use
+    /// [`RunMetadata::with_created`] to supply it.
+    pub fn new() -> RunMetadata {
+        RunMetadata {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created: current_unix_seconds(),
+            seed: None,
+            input_files: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+    /// Record the time the run was created, in seconds since the Unix epoch.
+    pub fn with_created(mut self, created: u64) -> RunMetadata {
+        self.created = created;
+        self
+    }
+    /// Record the random seed of the run.
+    pub fn with_seed(mut self, seed: u64) -> RunMetadata {
+        self.seed = Some(seed);
+        self
+    }
+    /// Record an input file, hashing its contents.
+    /// # Errors
+    /// Returns an error if the file cannot be read.
+    #[cfg(feature = "cli")]
+    pub fn add_input_file(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read(path)?;
+        self.add_input(&path.display().to_string(), &contents);
+        Ok(())
+    }
+    /// Record an input that was not read from a file (for example, one uploaded in a web page),
+    /// hashing its contents. `name` is recorded in place of a path.
+    pub fn add_input(&mut self, name: &str, contents: &[u8]) {
+        self.input_files.push(InputFile {
+            path: name.to_string(),
+            sha256: hex(&Sha256::digest(contents)),
+        });
+    }
+    /// Record the value of a parameter of the run.
+    pub fn add_parameter<T: Display>(&mut self, name: &str, value: T) {
+        self.parameters.push((name.to_string(), value.to_string()));
+    }
+    /// Return the version of EcolysisCMD that produced the run.
+    pub fn get_crate_version(&self) -> &str {
+        &self.crate_version
+    }
+    /// Return the seed of the run, if one was recorded.
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+    /// Return the recorded input files.
+    pub fn get_input_files(&self) -> &Vec<InputFile> {
+        &self.input_files
+    }
+    /// Return the recorded parameters as `(name, value)` pairs.
+    pub fn get_parameters(&self) -> &Vec<(String, String)> {
+        &self.parameters
+    }
+    /// Return when the metadata was created as an RFC 3339 UTC timestamp, for example
+    /// `2024-05-01T13:45:00Z`.
+    pub fn created_timestamp(&self) -> String {
+        format_timestamp(self.created)
+    }
+    /// Return the metadata as a JSON object.
+    pub fn to_json(&self) -> String {
+        let seed = self.seed.map_or(String::from("null"), |x| x.to_string());
+        let input_files = self
+            .input_files
+            .iter()
+            .map(|file| {
+                format!(
+                    "\n    {{\"path\": {}, \"sha256\": {}}}",
+                    json_string(&file.path),
+                    json_string(&file.sha256)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let parameters = self
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("\n    {}: {}", json_string(name), json_string(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\n  \"ecolysis_cmd_version\": {},\n  \"created\": {},\n  \"seed\": {},\n  \"input_files\": [{}{}],\n  \"parameters\": {{{}{}}}\n}}\n",
+            json_string(&self.crate_version),
+            json_string(&self.created_timestamp()),
+            seed,
+            input_files,
+            if input_files.is_empty() { "" } else { "\n  " },
+            parameters,
+            if parameters.is_empty() { "" } else { "\n  " },
+        )
+    }
+    /// Write the metadata as a JSON sidecar next to an output file, named after the output with
+    /// `.metadata.json` appended (`results.csv` becomes `results.csv.metadata.json`). Returns the
+    /// path of the sidecar.
+    /// # Errors
+    /// Returns an error if the sidecar cannot be written.
+    #[cfg(feature = "cli")]
+    pub fn write_sidecar(&self, output_path: &Path) -> io::Result<PathBuf> {
+        let mut sidecar = output_path.as_os_str().to_owned();
+        sidecar.push(".metadata.json");
+        let sidecar = PathBuf::from(sidecar);
+        fs::write(&sidecar, self.to_json())?;
+        Ok(sidecar)
+    }
+    /// Write the metadata as lines starting with `#`, which can be placed at the top of a CSV
+    /// file and skipped by most readers (for example `pandas.read_csv(path, comment="#")`).
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_comment_block<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "# ecolysis_cmd_version: {}", self.crate_version)?;
+        writeln!(writer, "# created: {}", self.created_timestamp())?;
+        if let Some(seed) = self.seed {
+            writeln!(writer, "# seed: {}", seed)?;
+        }
+        for file in &self.input_files {
+            writeln!(writer, "# input_file: {} sha256:{}", file.path, file.sha256)?;
+        }
+        for (name, value) in &self.parameters {
+            writeln!(writer, "# parameter: {} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+impl Default for RunMetadata {
+    fn default() -> Self {
+        RunMetadata::new()
+    }
+}
+
+/// Return the current time in seconds since the Unix epoch, or 0 where there is no clock.
+pub(crate) fn current_unix_seconds() -> u64 {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    return 0;
+}
+
+/// Return bytes as lowercase hexadecimal.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Return a string as a quoted JSON string, escaping characters where needed.
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp, using Howard Hinnant's
+/// days-to-civil-date algorithm.
+pub(crate) fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_714_571_100), "2024-05-01T13:45:00Z");
+    }
+    #[cfg(feature = "cli")]
+    #[test]
+    fn input_file_hash_and_sidecar() {
+        let directory = std::env::temp_dir().join("ecolysis_provenance_test");
+        fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("input.csv");
+        fs::write(&input, "abc").unwrap();
+        let mut metadata = RunMetadata::new().with_seed(7);
+        metadata.add_input_file(&input).unwrap();
+        metadata.add_parameter("label", "a \"quoted\" value");
+        assert_eq!(
+            metadata.get_input_files()[0].get_sha256(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let sidecar = metadata.write_sidecar(&directory.join("out.csv")).unwrap();
+        assert!(sidecar.ends_with("out.csv.metadata.json"));
+        let json = fs::read_to_string(&sidecar).unwrap();
+        assert!(json.contains("\"label\": \"a \\\"quoted\\\" value\""));
+        fs::remove_dir_all(directory).unwrap();
+    }
+    #[test]
+    fn input_hash_without_file() {
+        let mut metadata = RunMetadata::new().with_created(0);
+        metadata.add_input("upload", b"abc");
+        assert_eq!(
+            metadata.get_input_files()[0].get_sha256(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(metadata.created_timestamp(), "1970-01-01T00:00:00Z");
+    }
+    #[test]
+    fn comment_block() {
+        let mut metadata = RunMetadata::new();
+        metadata.add_parameter("steps", 10);
+        let mut buffer = Vec::new();
+        metadata.write_comment_block(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.lines().all(|line| line.starts_with("# ")));
+        assert!(text.contains("# parameter: steps = 10\n"));
+        assert!(!text.contains("seed"));
+    }
+}
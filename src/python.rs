@@ -0,0 +1,253 @@
+//! This module exposes Population Matrices, Population Vectors, and the deterministic and stochastic engines as Python classes, with NumPy arrays for matrices, vectors, and outputs. Build the Python package with `maturin develop --features python,pyo3/extension-module`, then `import ecolysis_cmd`:
+//!
+//! ```python
+//! import numpy as np
+//! import ecolysis_cmd
+//! matrices = [np.array([[0.0, 1.6], [0.5, 0.7]]), np.array([[0.0, 0.9], [0.4, 0.6]])]
+//! model = ecolysis_cmd.StochasticPva(np.array([20.0, 20.0]), matrices)
+//! runs = model.project(steps=50, replicates=1000, seed=1)  # shape (1000, 50, 2)
+//! ```
+use crate::populations::population_level_simulation::{
+    DeterministicPva, PopulationMatrix, PopulationVector, StochasticPva,
+};
+use numpy::{
+    ndarray::{Array2, Array3},
+    PyArray1, PyArray2, PyArray3, PyReadonlyArray1, PyReadonlyArray2, ToPyArray,
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+fn value_error(message: &str) -> PyErr {
+    PyValueError::new_err(message.to_string())
+}
+
+fn rows(matrix: &PyReadonlyArray2<'_, f64>) -> Vec<Vec<f64>> {
+    matrix
+        .as_array()
+        .rows()
+        .into_iter()
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+/// Arrange a list of Population Vectors as a `(steps, lifestages)` array.
+fn trajectory_array(trajectory: &[PopulationVector], lifestages: usize) -> Array2<f64> {
+    Array2::from_shape_fn((trajectory.len(), lifestages), |(step, stage)| {
+        trajectory[step].get_vector()[stage]
+    })
+}
+
+/// Arrange stochastic replicates as a `(replicates, steps, lifestages)` array. Every replicate
+/// must have `steps` steps.
+fn replicates_array(
+    replicates: &[Vec<PopulationVector>],
+    steps: usize,
+    lifestages: usize,
+) -> Array3<f64> {
+    Array3::from_shape_fn(
+        (replicates.len(), steps, lifestages),
+        |(replicate, step, stage)| replicates[replicate][step].get_vector()[stage],
+    )
+}
+
+/// A Population Matrix, built from a square 2D NumPy array.
+#[pyclass(name = "PopulationMatrix")]
+#[derive(Clone)]
+struct PyPopulationMatrix {
+    matrix: PopulationMatrix,
+}
+#[pymethods]
+impl PyPopulationMatrix {
+    #[new]
+    fn new(matrix: PyReadonlyArray2<'_, f64>) -> PyResult<Self> {
+        Ok(PyPopulationMatrix {
+            matrix: PopulationMatrix::build(rows(&matrix)).map_err(value_error)?,
+        })
+    }
+    /// The number of lifestages.
+    #[getter]
+    fn lifestage_count(&self) -> u8 {
+        self.matrix.get_lifestage_count()
+    }
+    /// Return the matrix as a 2D NumPy array.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        PyArray2::from_vec2(py, &self.matrix.get_matrix()).map_err(|e| value_error(&e.to_string()))
+    }
+    /// Return the asymptotic growth rate (`lambda` is a Python keyword).
+    #[pyo3(name = "lambda_")]
+    fn lambda(&self) -> f64 {
+        self.matrix.lambda()
+    }
+    /// Return the stable stage distribution.
+    fn stable_stage_distribution<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        self.matrix
+            .stable_stage_distribution()
+            .get_vector()
+            .to_pyarray(py)
+    }
+    /// Return the elasticities of lambda to each element.
+    fn elasticities<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        PyArray2::from_vec2(py, &self.matrix.elasticities().get_matrix())
+            .map_err(|e| value_error(&e.to_string()))
+    }
+    /// Project a population vector one step.
+    fn project<'py>(
+        &self,
+        py: Python<'py>,
+        vector: PyReadonlyArray1<'_, f64>,
+    ) -> PyResult<Bound<'py, PyArray1<f64>>> {
+        let popvector = PopulationVector::new(vector.as_array().to_vec());
+        let projected = self
+            .matrix
+            .project_vector(&popvector)
+            .map_err(value_error)?;
+        Ok(projected.get_vector().to_pyarray(py))
+    }
+}
+
+/// A Population Vector, built from a 1D NumPy array.
+#[pyclass(name = "PopulationVector")]
+struct PyPopulationVector {
+    vector: PopulationVector,
+}
+#[pymethods]
+impl PyPopulationVector {
+    #[new]
+    fn new(vector: PyReadonlyArray1<'_, f64>) -> Self {
+        PyPopulationVector {
+            vector: PopulationVector::new(vector.as_array().to_vec()),
+        }
+    }
+    /// The number of lifestages.
+    #[getter]
+    fn lifestage_count(&self) -> u8 {
+        self.vector.get_lifestage_count()
+    }
+    /// Return the vector as a 1D NumPy array.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        self.vector.get_vector().to_pyarray(py)
+    }
+    /// Return the total population across all lifestages.
+    fn total(&self) -> f64 {
+        self.vector.total()
+    }
+}
+
+/// A deterministic model, built from an initial population and a projection matrix.
+#[pyclass(name = "DeterministicPva")]
+struct PyDeterministicPva {
+    model: DeterministicPva,
+}
+#[pymethods]
+impl PyDeterministicPva {
+    #[new]
+    fn new(
+        initial_population: PyReadonlyArray1<'_, f64>,
+        matrix: PyReadonlyArray2<'_, f64>,
+    ) -> PyResult<Self> {
+        Ok(PyDeterministicPva {
+            model: DeterministicPva::build_from_vectors(
+                initial_population.as_array().to_vec(),
+                rows(&matrix),
+            )
+            .map_err(value_error)?,
+        })
+    }
+    /// Project the population, returning a `(steps, lifestages)` array.
+    fn project<'py>(&self, py: Python<'py>, steps: u32) -> Bound<'py, PyArray2<f64>> {
+        let output = self.model.deterministic_projection(steps);
+        let lifestages = self.model.get_matrix().get_lifestage_count() as usize;
+        PyArray2::from_owned_array(
+            py,
+            trajectory_array(output.return_typed_output(), lifestages),
+        )
+    }
+}
+
+/// A stochastic model, built from an initial population and a list of projection matrices, one
+/// of which is drawn at random for each step.
+#[pyclass(name = "StochasticPva")]
+struct PyStochasticPva {
+    model: StochasticPva,
+}
+#[pymethods]
+impl PyStochasticPva {
+    #[new]
+    fn new(
+        initial_population: PyReadonlyArray1<'_, f64>,
+        matrices: Vec<PyReadonlyArray2<'_, f64>>,
+    ) -> PyResult<Self> {
+        Ok(PyStochasticPva {
+            model: StochasticPva::build_from_vectors(
+                initial_population.as_array().to_vec(),
+                matrices.iter().map(rows).collect(),
+            )
+            .map_err(value_error)?,
+        })
+    }
+    /// Run the projection, returning a `(replicates, steps, lifestages)` array.
+    #[pyo3(signature = (steps, replicates, seed = 0))]
+    fn project<'py>(
+        &self,
+        py: Python<'py>,
+        steps: u32,
+        replicates: u32,
+        seed: u64,
+    ) -> Bound<'py, PyArray3<f64>> {
+        let output = self.model.stochastic_projection(steps, replicates, seed);
+        let lifestages = self.model.get_initial_population().get_lifestage_count() as usize;
+        PyArray3::from_owned_array(
+            py,
+            replicates_array(output.get_replicates(), steps as usize, lifestages),
+        )
+    }
+    /// Return the proportion of replicates whose total population falls to or below `threshold`.
+    #[pyo3(signature = (steps, replicates, threshold, seed = 0))]
+    fn extinction_probability(
+        &self,
+        steps: u32,
+        replicates: u32,
+        threshold: f64,
+        seed: u64,
+    ) -> f64 {
+        self.model
+            .stochastic_summary(steps, replicates, seed, threshold)
+            .extinction_probability()
+    }
+}
+
+/// The `ecolysis_cmd` Python module.
+#[pymodule]
+#[pyo3(name = "ecolysis_cmd")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPopulationMatrix>()?;
+    module.add_class::<PyPopulationVector>()?;
+    module.add_class::<PyDeterministicPva>()?;
+    module.add_class::<PyStochasticPva>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicate_array_layout() {
+        let replicates = StochasticPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![
+                vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+                vec![vec![0.0, 1.0], vec![0.3, 0.5]],
+            ],
+        )
+        .unwrap()
+        .stochastic_projection(4, 3, 1);
+        let array = replicates_array(replicates.get_replicates(), 4, 2);
+        assert_eq!(array.shape(), &[3, 4, 2]);
+        assert_eq!(
+            array[[2, 3, 1]],
+            replicates.get_replicates()[2][3].get_vector()[1]
+        );
+        let trajectory = trajectory_array(&replicates.get_replicates()[0], 2);
+        assert_eq!(trajectory.shape(), &[4, 2]);
+    }
+}
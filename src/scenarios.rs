@@ -0,0 +1,267 @@
+//! This module contains scenario files, which describe a model run in a small text file so that runs can be repeated, validated, and run in batches. A scenario file has one `key = value` setting per line; blank lines and text after `#` are ignored. File paths are relative to the scenario file.
+//!
+//! ```text
+//! # Loggerhead turtle, current management
+//! matrix = loggerhead.csv
+//! initial_population = current_counts.csv
+//! steps = 100
+//! time_step = 1 year
+//! # Optional: changes to the vital rates during the projection (see `Perturbation`)
+//! perturbation = ramp survival -1% from 0 for 20
+//! perturbation = pulse fecundity 0.5 from 5 for 3
+//! # Optional: vital rates driven by covariates (see `CovariateMatrix`)
+//! covariate.temperature = temperature_effects.csv
+//! ```
+#[cfg(feature = "cli")]
+use crate::imports::decode_text;
+use crate::populations::population_level_simulation::{Perturbation, TimeStep};
+#[cfg(feature = "cli")]
+use std::fs;
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+/// The settings recognised in scenario files, in addition to `covariate.<name>` settings.
+/// `perturbation` can be given more than once.
+pub const SCENARIO_KEYS: [&str; 5] = [
+    "matrix",
+    "initial_population",
+    "steps",
+    "time_step",
+    "perturbation",
+];
+
+/// One line of a settings file in the format of scenario files: a `key = value` setting, or the
+/// `[[name]]` header of a repeated table (as in TOML) that the settings after it belong to.
+/// Line numbers start at 1.
+pub(crate) enum Setting<'a> {
+    Header {
+        line: usize,
+        name: &'a str,
+    },
+    Value {
+        line: usize,
+        key: &'a str,
+        value: &'a str,
+    },
+}
+
+/// Return the settings of a file in the format of scenario files, skipping blank lines and text
+/// after `#`, with quotes removed from values. A line that is neither a setting nor a header is
+/// an error, which names the kind of file being read (such as `scenario`).
+pub(crate) fn settings<'a>(
+    contents: &'a str,
+    kind: &'a str,
+) -> impl Iterator<Item = Result<Setting<'a>, String>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(move |(line_number, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let line_number = line_number + 1;
+            if line.is_empty() {
+                return None;
+            }
+            if let Some(name) = line.strip_prefix("[[").and_then(|x| x.strip_suffix("]]")) {
+                return Some(Ok(Setting::Header {
+                    line: line_number,
+                    name: name.trim(),
+                }));
+            }
+            Some(match line.split_once('=') {
+                Some((key, value)) => Ok(Setting::Value {
+                    line: line_number,
+                    key: key.trim(),
+                    value: value.trim().trim_matches('"'),
+                }),
+                None => Err(format!(
+                    "Line {} of the {} is not a `key = value` setting.",
+                    line_number, kind
+                )),
+            })
+        })
+}
+
+/// This struct holds the settings read from a scenario file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    name: String,
+    matrix: PathBuf,
+    initial_population: Option<PathBuf>,
+    steps: Option<u32>,
+    time_step: Option<TimeStep>,
+    perturbations: Vec<Perturbation>,
+    covariates: Vec<(String, PathBuf)>,
+    unknown_keys: Vec<String>,
+}
+impl Scenario {
+    /// Read a scenario file. The scenario is named after the file (without its extension).
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed (see [`Scenario::parse`]).
+    #[cfg(feature = "cli")]
+    pub fn from_file(path: &Path) -> Result<Scenario, Box<dyn Error>> {
+        let contents = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|x| decode_text(&x))
+            .map_err(|e| format!("Could not read scenario file {}: {}", path.display(), e))?;
+        let name = path.file_stem().map_or(String::from("scenario"), |x| {
+            x.to_string_lossy().to_string()
+        });
+        Scenario::parse(&contents, &name, path.parent().unwrap_or(Path::new("")))
+    }
+    /// Parse the contents of a scenario file, resolving file paths relative to `base_directory`.
+    /// Unrecognised settings are kept (see [`Scenario::get_unknown_keys`]) rather than rejected,
+    /// so files written for newer versions can still be read.
+    /// # Errors
+    /// Returns an error if a line is not a `key = value` pair, if a value cannot be read, or if
+    /// the `matrix` setting is missing.
+    /// ```
+    /// use ecolysis_cmd::scenarios::Scenario;
+    /// use std::path::Path;
+    /// let scenario = Scenario::parse("matrix = a.csv\nsteps = 50 # years", "test", Path::new("runs")).unwrap();
+    /// assert_eq!(scenario.get_matrix(), Path::new("runs/a.csv"));
+    /// assert_eq!(scenario.get_steps(), Some(50));
+    /// ```
+    pub fn parse(
+        contents: &str,
+        name: &str,
+        base_directory: &Path,
+    ) -> Result<Scenario, Box<dyn Error>> {
+        let mut matrix = None;
+        let mut initial_population = None;
+        let mut steps = None;
+        let mut time_step = None;
+        let mut perturbations = Vec::new();
+        let mut covariates = Vec::new();
+        let mut unknown_keys = Vec::new();
+        for setting in settings(contents, "scenario") {
+            let (line_number, key, value) = match setting? {
+                Setting::Value { line, key, value } => (line - 1, key, value),
+                Setting::Header { line, .. } => {
+                    return Err(format!(
+                        "Line {} of the scenario is not a `key = value` setting.",
+                        line
+                    )
+                    .into())
+                }
+            };
+            match key {
+                "matrix" => matrix = Some(base_directory.join(value)),
+                "initial_population" => initial_population = Some(base_directory.join(value)),
+                "steps" => {
+                    steps = Some(value.parse().map_err(|_| {
+                        format!(
+                            "Line {}: steps must be a whole number, not \"{}\".",
+                            line_number + 1,
+                            value
+                        )
+                    })?)
+                }
+                "time_step" => {
+                    time_step = Some(
+                        value
+                            .parse()
+                            .map_err(|e| format!("Line {}: {}", line_number + 1, e))?,
+                    )
+                }
+                "perturbation" => perturbations.push(
+                    value
+                        .parse()
+                        .map_err(|e| format!("Line {}: {}", line_number + 1, e))?,
+                ),
+                _ => match key.strip_prefix("covariate.") {
+                    Some(covariate) if !covariate.is_empty() => {
+                        covariates.push((covariate.to_string(), base_directory.join(value)))
+                    }
+                    _ => unknown_keys.push(key.to_string()),
+                },
+            }
+        }
+        Ok(Scenario {
+            name: name.to_string(),
+            matrix: matrix.ok_or("The scenario does not set a matrix file.")?,
+            initial_population,
+            steps,
+            time_step,
+            perturbations,
+            covariates,
+            unknown_keys,
+        })
+    }
+    /// Return the name of the scenario.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Return the path of the projection matrix file.
+    pub fn get_matrix(&self) -> &Path {
+        &self.matrix
+    }
+    /// Return the path of the initial population vector file, if set.
+    pub fn get_initial_population(&self) -> Option<&Path> {
+        self.initial_population.as_deref()
+    }
+    /// Return the number of steps to project, if set.
+    pub fn get_steps(&self) -> Option<u32> {
+        self.steps
+    }
+    /// Return the length of time one step of the model represents, if set.
+    pub fn get_time_step(&self) -> Option<TimeStep> {
+        self.time_step
+    }
+    /// Return the perturbations applied to the matrix during the projection, in the order they
+    /// appear in the file.
+    pub fn get_perturbations(&self) -> &Vec<Perturbation> {
+        &self.perturbations
+    }
+    /// Return the name and effect matrix file of each covariate, from `covariate.<name>`
+    /// settings.
+    pub fn get_covariates(&self) -> &Vec<(String, PathBuf)> {
+        &self.covariates
+    }
+    /// Return any settings in the file that were not recognised.
+    pub fn get_unknown_keys(&self) -> &Vec<String> {
+        &self.unknown_keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_full_scenario() {
+        let scenario = Scenario::parse(
+            "# comment\nmatrix = \"m.csv\"\n\ninitial_population = v.csv\nsteps=10\ntime_step = 6 months\nperturbation = pulse all 0.5 from 1 for 2\ncolour = blue\ncovariate.rain = r.csv\n",
+            "full",
+            Path::new(""),
+        )
+        .unwrap();
+        assert_eq!(scenario.get_name(), "full");
+        assert_eq!(scenario.get_matrix(), Path::new("m.csv"));
+        assert_eq!(scenario.get_initial_population(), Some(Path::new("v.csv")));
+        assert_eq!(scenario.get_unknown_keys(), &vec!["colour"]);
+        assert_eq!(scenario.get_perturbations().len(), 1);
+        assert_eq!(
+            scenario.get_time_step().map(|x| x.to_string()),
+            Some(String::from("6 months"))
+        );
+        assert_eq!(
+            scenario.get_covariates(),
+            &vec![(String::from("rain"), PathBuf::from("r.csv"))]
+        );
+    }
+    #[test]
+    fn parse_errors() {
+        assert!(Scenario::parse("steps = 10", "x", Path::new("")).is_err());
+        assert!(Scenario::parse("matrix = m.csv\nsteps = ten", "x", Path::new("")).is_err());
+        assert!(Scenario::parse("matrix m.csv", "x", Path::new("")).is_err());
+        assert!(Scenario::parse("matrix = m.csv\ntime_step = 1 eon", "x", Path::new("")).is_err());
+        assert!(Scenario::parse(
+            "matrix = m.csv\nperturbation = pulse all",
+            "x",
+            Path::new("")
+        )
+        .is_err());
+    }
+}
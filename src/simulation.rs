@@ -0,0 +1,100 @@
+//! This module defines the [`Simulation`] trait shared by every simulation engine in the crate. A simulation holds the current state of one run of a model and advances it one step at a time, so code that drives or records simulations (the command line interface, experiment runners, and output writers) can be written once and used with any model type.
+use crate::outputs::{stage_columns, OutputTable};
+use crate::populations::population_level_simulation::PopulationVector;
+
+/// This trait is implemented by the running state of a model: for example a deterministic
+/// projection, or one replicate of a stochastic projection.
+/// ```
+/// use ecolysis_cmd::simulation::Simulation;
+/// use ecolysis_cmd::DeterministicPva;
+/// let population = DeterministicPva::build_from_vectors(vec![10.0, 10.0],
+///     vec![vec![0.0, 2.0], vec![0.5, 0.5]]).unwrap();
+/// let mut simulation = population.projection_iter();
+/// simulation.step();
+/// assert_eq!(simulation.state().get_vector(), &vec![20.0, 10.0]);
+/// let trajectory = simulation.run(3);
+/// assert_eq!(trajectory.len(), 3);
+/// assert_eq!(simulation.steps_taken(), 4);
+/// ```
+pub trait Simulation {
+    /// The type describing the state of the simulation at one point in time.
+    type State;
+    /// Advance the simulation by one step.
+    fn step(&mut self);
+    /// Return the current state of the simulation. Before any steps are taken this is the
+    /// initial state.
+    fn state(&self) -> &Self::State;
+    /// Return the number of steps taken so far.
+    fn steps_taken(&self) -> u32;
+    /// Advance the simulation by `steps` steps, returning the state after each one.
+    fn run(&mut self, steps: u32) -> Vec<Self::State>
+    where
+        Self::State: Clone,
+    {
+        (0..steps)
+            .map(|_| {
+                self.step();
+                self.state().clone()
+            })
+            .collect()
+    }
+}
+
+/// Run a simulation whose state is a Population Vector for `steps` steps and return the result as
+/// an [`OutputTable`] with a `step` column followed by one column per lifestage, ready to be
+/// written by any writer in [`crate::outputs`]. Steps are numbered continuing from the number of
+/// steps the simulation had already taken.
+pub fn simulation_table<S>(simulation: &mut S, steps: u32) -> OutputTable
+where
+    S: Simulation<State = PopulationVector>,
+{
+    let lifestage_count = simulation.state().get_lifestage_count();
+    let mut columns = vec![String::from("step")];
+    columns.extend(stage_columns(lifestage_count as usize));
+    let mut rows = Vec::new();
+    for _ in 0..steps {
+        simulation.step();
+        let mut row = vec![simulation.steps_taken() as f64];
+        row.extend(simulation.state().iter());
+        rows.push(row);
+    }
+    OutputTable::build(columns, rows).expect("Rows are built with one value per column.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::populations::population_level_simulation::{DeterministicPva, StochasticPva};
+
+    /// Return the total population after running any simulation for a number of steps.
+    fn final_total<S: Simulation<State = PopulationVector>>(simulation: &mut S, steps: u32) -> f64 {
+        simulation.run(steps);
+        simulation.state().iter().sum()
+    }
+
+    #[test]
+    fn engines_are_interchangeable() {
+        let matrix = vec![vec![0.0, 2.0], vec![0.5, 0.5]];
+        let deterministic =
+            DeterministicPva::build_from_vectors(vec![10.0, 10.0], matrix.clone()).unwrap();
+        let stochastic = StochasticPva::build_from_vectors(vec![10.0, 10.0], vec![matrix]).unwrap();
+        assert_eq!(
+            final_total(&mut deterministic.projection_iter(), 6),
+            final_total(&mut stochastic.replicate_iter(0, 1), 6)
+        );
+    }
+    #[test]
+    fn table_continues_step_numbers() {
+        let population = DeterministicPva::build_from_vectors(
+            vec![10.0, 10.0],
+            vec![vec![0.0, 2.0], vec![0.5, 0.5]],
+        )
+        .unwrap();
+        let mut simulation = population.projection_iter();
+        simulation.step();
+        let table = simulation_table(&mut simulation, 2);
+        assert_eq!(table.get_columns(), &vec!["step", "stage_0", "stage_1"]);
+        assert_eq!(table.get_rows()[0][0], 2.0);
+        assert_eq!(table.get_rows()[1][0], 3.0);
+    }
+}
@@ -0,0 +1,386 @@
+//! This module contains small statistical tools used to summarize simulation results without storing every value, such as running means and variances and streaming quantile estimates, and the distribution functions needed for significance tests.
+
+/// This struct keeps a running count, mean, variance, minimum, and maximum of the values pushed
+/// into it, using Welford's algorithm. Only five numbers are stored no matter how many values are
+/// added.
+/// ```
+/// use ecolysis_cmd::statistics::RunningStatistics;
+/// let mut stats = RunningStatistics::new();
+/// for x in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+///     stats.push(x);
+/// }
+/// assert_eq!(stats.mean(), 5.0);
+/// assert_eq!(stats.max(), 9.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RunningStatistics {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+impl RunningStatistics {
+    /// Create a new, empty RunningStatistics instance.
+    pub fn new() -> RunningStatistics {
+        RunningStatistics {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+    /// Add a value to the running statistics.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+    /// Return the number of values added so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Return the mean of the values added so far, or 0 if there are none.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+    /// Return the sample variance of the values added so far, or 0 if there are fewer than two.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+    /// Return the sample standard deviation of the values added so far.
+    pub fn standard_deviation(&self) -> f64 {
+        self.variance().sqrt()
+    }
+    /// Return the smallest value added so far (infinity if there are none).
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+    /// Return the largest value added so far (negative infinity if there are none).
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+}
+impl Default for RunningStatistics {
+    fn default() -> Self {
+        RunningStatistics::new()
+    }
+}
+
+/// This struct estimates a single quantile (for example the median, or the 97.5th percentile) of
+/// a stream of values without storing them, using the P² algorithm of Jain and Chlamtac (1985).
+/// The estimate is exact for five or fewer values and approximate afterwards.
+/// ```
+/// use ecolysis_cmd::statistics::QuantileSketch;
+/// let mut median = QuantileSketch::new(0.5);
+/// for x in 1..=1001 {
+///     median.push(x as f64);
+/// }
+/// assert!((median.estimate() - 501.0).abs() < 5.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    quantile: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired_positions: [f64; 5],
+    increments: [f64; 5],
+}
+impl QuantileSketch {
+    /// Create a new QuantileSketch estimating the given quantile, which must be between 0 and 1.
+    pub fn new(quantile: f64) -> QuantileSketch {
+        let quantile = quantile.clamp(0.0, 1.0);
+        QuantileSketch {
+            quantile,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired_positions: [
+                0.0,
+                2.0 * quantile,
+                4.0 * quantile,
+                2.0 + 2.0 * quantile,
+                4.0,
+            ],
+            increments: [0.0, quantile / 2.0, quantile, (1.0 + quantile) / 2.0, 1.0],
+        }
+    }
+    /// Return the quantile being estimated.
+    pub fn quantile(&self) -> f64 {
+        self.quantile
+    }
+    /// Add a value to the sketch.
+    pub fn push(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (0..4).find(|&i| value < self.heights[i + 1]).unwrap_or(3)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired_positions.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+        for i in 1..4 {
+            let offset = self.desired_positions[i] - self.positions[i];
+            if (offset >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (offset <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0)
+            {
+                let direction = offset.signum();
+                let parabolic = self.parabolic(i, direction);
+                if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                    self.heights[i] = parabolic;
+                } else {
+                    let neighbour = (i as f64 + direction) as usize;
+                    self.heights[i] += direction * (self.heights[neighbour] - self.heights[i])
+                        / (self.positions[neighbour] - self.positions[i]);
+                }
+                self.positions[i] += direction;
+            }
+        }
+    }
+    fn parabolic(&self, i: usize, direction: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + direction / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + direction) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - direction) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+    /// Return the current estimate of the quantile, or NaN if no values have been added.
+    pub fn estimate(&self) -> f64 {
+        if self.count == 0 {
+            return f64::NAN;
+        }
+        if self.count < 5 {
+            let mut values = self.heights[..self.count].to_vec();
+            values.sort_by(f64::total_cmp);
+            let index = (self.quantile * (values.len() - 1) as f64).round() as usize;
+            return values[index];
+        }
+        self.heights[2]
+    }
+}
+
+/// Return the quantile `q` (between 0 and 1) of a set of values, interpolating linearly between
+/// the two closest values. NaN values are ignored. Returns NaN if there are no values.
+/// ```
+/// use ecolysis_cmd::statistics::quantile;
+/// assert_eq!(quantile(&[4.0, 1.0, 3.0, 2.0, 5.0], 0.5), 3.0);
+/// assert_eq!(quantile(&[1.0, 2.0], 0.25), 1.25);
+/// ```
+pub fn quantile(values: &[f64], q: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Return the logistic function `1 / (1 + exp(-x))` of a value, which maps the logit scale to a
+/// probability.
+pub(crate) fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Return the logit `ln(p / (1 - p))` of a probability, the inverse of [`logistic`].
+pub(crate) fn logit(probability: f64) -> f64 {
+    (probability / (1.0 - probability)).ln()
+}
+
+/// Return the average log growth per step of a series of total population sizes: the mean of
+/// `ln(N_t+1 / N_t)` over the steps before the total first falls to zero, which equals
+/// `ln(N_k / N_0) / k` for the last positive total `N_k`. Returns NaN if the series does not
+/// start with two positive totals.
+/// ```
+/// use ecolysis_cmd::statistics::log_growth_rate;
+/// assert!((log_growth_rate(&[10.0, 20.0, 40.0]) - 2.0_f64.ln()).abs() < 1e-12);
+/// // Growth is measured up to extinction.
+/// assert!((log_growth_rate(&[40.0, 20.0, 0.0, 0.0]) + 2.0_f64.ln()).abs() < 1e-12);
+/// assert!(log_growth_rate(&[10.0]).is_nan());
+/// ```
+pub fn log_growth_rate(totals: &[f64]) -> f64 {
+    let steps = totals
+        .windows(2)
+        .take_while(|x| x[0] > 0.0 && x[1] > 0.0)
+        .count();
+    match steps {
+        0 => f64::NAN,
+        steps => (totals[steps] / totals[0]).ln() / steps as f64,
+    }
+}
+
+/// Return the probability that a Student's t distributed variable with `degrees_of_freedom`
+/// degrees of freedom is at most `t`, as used for the p-value of a regression slope. Returns NaN
+/// if `degrees_of_freedom` is not positive.
+/// ```
+/// use ecolysis_cmd::statistics::student_t_cdf;
+/// assert_eq!(student_t_cdf(0.0, 4.0), 0.5);
+/// // The one-sided 5% critical value for 10 degrees of freedom is 1.812.
+/// assert!((student_t_cdf(-1.812, 10.0) - 0.05).abs() < 1e-3);
+/// ```
+pub fn student_t_cdf(t: f64, degrees_of_freedom: f64) -> f64 {
+    if degrees_of_freedom.is_nan() || degrees_of_freedom <= 0.0 || t.is_nan() {
+        return f64::NAN;
+    }
+    if t.is_infinite() {
+        return if t < 0.0 { 0.0 } else { 1.0 };
+    }
+    let tail = 0.5
+        * incomplete_beta(
+            degrees_of_freedom / (degrees_of_freedom + t * t),
+            degrees_of_freedom / 2.0,
+            0.5,
+        );
+    if t < 0.0 {
+        tail
+    } else {
+        1.0 - tail
+    }
+}
+
+/// Return the natural logarithm of the gamma function for a positive `x`, using the Lanczos
+/// approximation (accurate to about 15 significant figures).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // The reflection formula keeps the approximation accurate for small arguments.
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Return the regularized incomplete beta function `I_x(a, b)` for `x` between 0 and 1, evaluated
+/// with Lentz's continued fraction on whichever side of the distribution converges quickly.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let guard = |x: f64| if x.abs() < TINY { TINY } else { x };
+    let mut c = 1.0;
+    let mut d = 1.0 / guard(1.0 - (a + b) * x / (a + 1.0));
+    let mut result = d;
+    for m in 1..=300 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / guard(1.0 + even * d);
+        c = guard(1.0 + even / c);
+        result *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / guard(1.0 + odd * d);
+        c = guard(1.0 + odd / c);
+        let change = d * c;
+        result *= change;
+        if (change - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_statistics_match_direct_calculation() {
+        let values = [3.5, 1.25, 9.0, 4.0, 4.75, 0.5];
+        let mut stats = RunningStatistics::new();
+        for x in values {
+            stats.push(x);
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+        assert!((stats.mean() - mean).abs() < 1e-12);
+        assert!((stats.variance() - variance).abs() < 1e-12);
+        assert_eq!(stats.min(), 0.5);
+        assert_eq!(stats.count(), 6);
+    }
+    #[test]
+    fn quantile_sketch_small_samples_are_exact() {
+        let mut sketch = QuantileSketch::new(0.5);
+        assert!(sketch.estimate().is_nan());
+        for x in [5.0, 1.0, 3.0] {
+            sketch.push(x);
+        }
+        assert_eq!(sketch.estimate(), 3.0);
+    }
+    #[test]
+    fn quantile_sketch_tails() {
+        let mut lower = QuantileSketch::new(0.05);
+        let mut upper = QuantileSketch::new(0.95);
+        for x in (0..2000).map(|x| ((x * 7919) % 2000) as f64) {
+            lower.push(x);
+            upper.push(x);
+        }
+        assert!((lower.estimate() - 100.0).abs() < 20.0);
+        assert!((upper.estimate() - 1900.0).abs() < 20.0);
+    }
+    #[test]
+    fn student_t_matches_known_values() {
+        // With one degree of freedom the t distribution is the Cauchy distribution.
+        for t in [-3.0, -0.5, 0.7, 12.0] {
+            let cauchy = 0.5 + f64::atan(t) / std::f64::consts::PI;
+            assert!((student_t_cdf(t, 1.0) - cauchy).abs() < 1e-12);
+        }
+        assert!((student_t_cdf(2.015, 5.0) - 0.95).abs() < 1e-4);
+        assert!((student_t_cdf(-1.96, 1e6) - 0.025).abs() < 1e-4);
+        assert!((ln_gamma(5.0) - 24.0_f64.ln()).abs() < 1e-12);
+        assert!(student_t_cdf(1.0, 0.0).is_nan());
+    }
+}
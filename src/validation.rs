@@ -0,0 +1,551 @@
+//! This module contains checks for model inputs that can be run before a simulation: file structure (dimension mismatches and non-numeric cells), implausible vital rates, and eigenvalue diagnostics. Problems are collected into a report rather than stopping at the first one, so users can fix everything in one pass.
+use crate::imports::{read_text, CsvFormat};
+use crate::populations::population_level_simulation::{PopulationMatrix, PopulationVector};
+#[cfg(feature = "cli")]
+use crate::scenarios::Scenario;
+use std::{fmt, io::Read};
+#[cfg(feature = "cli")]
+use std::{fs::File, path::Path};
+
+/// How serious a validation issue is. Errors prevent a model from running; warnings and notes
+/// point out values that are allowed but may be mistakes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A single problem or diagnostic found during validation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    severity: Severity,
+    message: String,
+}
+impl ValidationIssue {
+    /// Return how serious the issue is.
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+    /// Return a description of the issue.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// This struct collects every issue found while validating one or more inputs.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    issues: Vec<ValidationIssue>,
+}
+impl ValidationReport {
+    /// Create a new, empty report.
+    pub fn new() -> ValidationReport {
+        ValidationReport::default()
+    }
+    /// Add an issue to the report.
+    pub fn push(&mut self, severity: Severity, message: String) {
+        self.issues.push(ValidationIssue { severity, message });
+    }
+    /// Add every issue of another report to this one, prefixing their messages with `context`
+    /// (for example the file name).
+    pub fn extend(&mut self, context: &str, other: ValidationReport) {
+        for issue in other.issues {
+            self.push(issue.severity, format!("{}: {}", context, issue.message));
+        }
+    }
+    /// Return every issue found.
+    pub fn get_issues(&self) -> &Vec<ValidationIssue> {
+        &self.issues
+    }
+    /// Return true if any issue is an error.
+    pub fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+}
+
+/// Read a CSV of numbers, reporting every cell that is not a number (with its row and column)
+/// and every row whose length differs from the first row. The format is detected from the file
+/// unless one is given.
+fn read_numeric_csv<R: Read>(
+    reader: R,
+    format: Option<&CsvFormat>,
+    report: &mut ValidationReport,
+) -> Option<Vec<Vec<f64>>> {
+    let (contents, format) = match format {
+        Some(format) => read_text(reader).map(|x| (x.into_bytes(), *format)),
+        None => CsvFormat::read_detected(reader).map_err(Box::from),
+    }
+    .map_err(|error| {
+        report.push(
+            Severity::Error,
+            format!("could not read the file: {}", error),
+        )
+    })
+    .ok()?;
+    let mut rdr = format
+        .reader_builder()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_slice());
+    let mut rows = Vec::new();
+    let mut valid = true;
+    for (row_number, record) in rdr.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                report.push(
+                    Severity::Error,
+                    format!("could not read the file: {}", error),
+                );
+                return None;
+            }
+        };
+        let mut row = Vec::new();
+        for (column_number, cell) in record.iter().enumerate() {
+            match format.parse_number(cell) {
+                Some(value) => row.push(value),
+                None => {
+                    valid = false;
+                    report.push(
+                        Severity::Error,
+                        format!(
+                            "row {}, column {}: \"{}\" is not a number",
+                            row_number + 1,
+                            column_number + 1,
+                            cell
+                        ),
+                    );
+                }
+            }
+        }
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        report.push(Severity::Error, String::from("the file is empty"));
+        return None;
+    }
+    if valid {
+        for (row_number, row) in rows.iter().enumerate().skip(1) {
+            if row.len() != rows[0].len() {
+                valid = false;
+                report.push(
+                    Severity::Error,
+                    format!(
+                        "row {} has {} values, but row 1 has {}",
+                        row_number + 1,
+                        row.len(),
+                        rows[0].len()
+                    ),
+                );
+            }
+        }
+    }
+    valid.then_some(rows)
+}
+
+/// Validate a projection matrix CSV, returning the report and the matrix if it could be built.
+/// The structural checks are followed by [`validate_matrix`]. The CSV format is detected from
+/// the file (see [`CsvFormat::detect`]).
+/// ```
+/// use ecolysis_cmd::validation::validate_matrix_csv;
+/// let (report, matrix) = validate_matrix_csv("0,1.2\n0.5,x\n".as_bytes());
+/// assert!(report.has_errors() && matrix.is_none());
+/// assert!(report.get_issues()[0].get_message().contains("row 2, column 2"));
+/// let (_, matrix) = validate_matrix_csv("0;1,2\n0,5;0,8\n".as_bytes());
+/// assert_eq!(matrix.unwrap().get_matrix(), vec![vec![0.0, 1.2], vec![0.5, 0.8]]);
+/// ```
+pub fn validate_matrix_csv<R: Read>(reader: R) -> (ValidationReport, Option<PopulationMatrix>) {
+    matrix_from_csv(reader, None)
+}
+
+/// Validate a projection matrix CSV written in a given format, as [`validate_matrix_csv`] does.
+pub fn validate_matrix_csv_with_format<R: Read>(
+    reader: R,
+    format: &CsvFormat,
+) -> (ValidationReport, Option<PopulationMatrix>) {
+    matrix_from_csv(reader, Some(format))
+}
+
+fn matrix_from_csv<R: Read>(
+    reader: R,
+    format: Option<&CsvFormat>,
+) -> (ValidationReport, Option<PopulationMatrix>) {
+    let mut report = ValidationReport::new();
+    let Some(rows) = read_numeric_csv(reader, format, &mut report) else {
+        return (report, None);
+    };
+    if rows.len() != rows[0].len() {
+        report.push(
+            Severity::Error,
+            format!(
+                "the matrix has {} rows and {} columns, but must be square",
+                rows.len(),
+                rows[0].len()
+            ),
+        );
+        return (report, None);
+    }
+    match PopulationMatrix::build(rows) {
+        Ok(matrix) => {
+            report.extend("matrix", validate_matrix(&matrix));
+            (report, Some(matrix))
+        }
+        Err(error) => {
+            report.push(Severity::Error, error.to_string());
+            (report, None)
+        }
+    }
+}
+
+/// Check the values of a projection matrix for implausible vital rates, and report eigenvalue
+/// diagnostics. The first row is assumed to hold fertility (the usual layout), and every other
+/// row survival and growth probabilities.
+pub fn validate_matrix(matrix: &PopulationMatrix) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    let size = matrix.get_lifestage_count() as usize;
+    let mut finite = true;
+    for row in 0..size {
+        for column in 0..size {
+            let value = matrix[(row, column)];
+            let location = format!("row {}, column {}", row + 1, column + 1);
+            if !value.is_finite() {
+                finite = false;
+                report.push(Severity::Error, format!("{} is {}", location, value));
+            } else if value < 0.0 {
+                report.push(
+                    Severity::Error,
+                    format!("{} is negative ({})", location, value),
+                );
+            } else if row > 0 && value > 1.0 {
+                report.push(
+                    Severity::Warning,
+                    format!(
+                        "{} is {}, which is above 1; if this is a survival or growth probability it is impossible",
+                        location, value
+                    ),
+                );
+            }
+        }
+    }
+    for column in 0..size {
+        let survival: f64 = (1..size).map(|row| matrix[(row, column)]).sum();
+        if survival > 1.0 + 1e-9 {
+            report.push(
+                Severity::Warning,
+                format!(
+                    "survival out of lifestage {} (column {} below the first row) adds up to {}, which is above 1",
+                    column + 1,
+                    column + 1,
+                    survival
+                ),
+            );
+        }
+        if (0..size).all(|row| matrix[(row, column)] == 0.0) {
+            report.push(
+                Severity::Warning,
+                format!(
+                    "every value in column {} is zero, so lifestage {} contributes nothing to the next step",
+                    column + 1,
+                    column + 1
+                ),
+            );
+        }
+    }
+    if !finite || report.has_errors() {
+        return report;
+    }
+    if !is_irreducible(matrix) {
+        report.push(
+            Severity::Warning,
+            String::from("the matrix is reducible: some lifestages cannot reach others, so lambda may not describe every lifestage"),
+        );
+    } else if !is_primitive(matrix) {
+        report.push(
+            Severity::Warning,
+            String::from("the matrix is imprimitive: the population will oscillate rather than settle into a stable stage distribution"),
+        );
+    }
+    let lambda = matrix.lambda();
+    report.push(
+        Severity::Note,
+        format!(
+            "lambda is {:.4} ({})",
+            lambda,
+            if lambda > 1.0 {
+                "growing"
+            } else if lambda < 1.0 {
+                "declining"
+            } else {
+                "stable"
+            }
+        ),
+    );
+    let stable = matrix
+        .stable_stage_distribution()
+        .iter()
+        .map(|x| format!("{:.3}", x))
+        .collect::<Vec<_>>()
+        .join(", ");
+    report.push(
+        Severity::Note,
+        format!("stable stage distribution is [{}]", stable),
+    );
+    report
+}
+
+/// Return a boolean matrix marking which transitions are possible (non-zero).
+fn transitions(matrix: &PopulationMatrix) -> Vec<Vec<bool>> {
+    matrix
+        .get_matrix()
+        .iter()
+        .map(|row| row.iter().map(|x| *x > 0.0).collect())
+        .collect()
+}
+
+fn boolean_product(a: &[Vec<bool>], b: &[Vec<bool>]) -> Vec<Vec<bool>> {
+    let size = a.len();
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|column| (0..size).any(|k| a[row][k] && b[k][column]))
+                .collect()
+        })
+        .collect()
+}
+
+/// Return true if every lifestage can eventually be reached from every other lifestage.
+fn is_irreducible(matrix: &PopulationMatrix) -> bool {
+    let graph = transitions(matrix);
+    let size = graph.len();
+    let reachable_from_first = |forward: bool| {
+        let mut seen = vec![false; size];
+        let mut stack = vec![0];
+        seen[0] = true;
+        while let Some(stage) = stack.pop() {
+            for (other, visited) in seen.iter_mut().enumerate() {
+                let edge = if forward {
+                    graph[other][stage]
+                } else {
+                    graph[stage][other]
+                };
+                if edge && !*visited {
+                    *visited = true;
+                    stack.push(other);
+                }
+            }
+        }
+        seen.into_iter().all(|x| x)
+    };
+    reachable_from_first(true) && reachable_from_first(false)
+}
+
+/// Return true if some power of the matrix has no zero values, which (for irreducible matrices)
+/// means the population converges to the stable stage distribution. Wielandt's theorem bounds
+/// the power that needs checking at `n² - 2n + 2`.
+fn is_primitive(matrix: &PopulationMatrix) -> bool {
+    let graph = transitions(matrix);
+    let size = graph.len();
+    let mut exponent = size * size + 2 - 2 * size;
+    let mut result: Option<Vec<Vec<bool>>> = None;
+    let mut base = graph;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = Some(match result {
+                Some(result) => boolean_product(&result, &base),
+                None => base.clone(),
+            });
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = boolean_product(&base, &base);
+        }
+    }
+    result.is_some_and(|x| x.iter().flatten().all(|x| *x))
+}
+
+/// Validate an initial population vector CSV (a single row or a single column of numbers),
+/// returning the report and the vector if it could be built. The CSV format is detected from the
+/// file (see [`CsvFormat::detect`]).
+pub fn validate_vector_csv<R: Read>(reader: R) -> (ValidationReport, Option<PopulationVector>) {
+    vector_from_csv(reader, None)
+}
+
+/// Validate an initial population vector CSV written in a given format, as
+/// [`validate_vector_csv`] does.
+pub fn validate_vector_csv_with_format<R: Read>(
+    reader: R,
+    format: &CsvFormat,
+) -> (ValidationReport, Option<PopulationVector>) {
+    vector_from_csv(reader, Some(format))
+}
+
+fn vector_from_csv<R: Read>(
+    reader: R,
+    format: Option<&CsvFormat>,
+) -> (ValidationReport, Option<PopulationVector>) {
+    let mut report = ValidationReport::new();
+    let Some(rows) = read_numeric_csv(reader, format, &mut report) else {
+        return (report, None);
+    };
+    let values: Vec<f64> = if rows.len() == 1 {
+        rows[0].clone()
+    } else if rows.iter().all(|row| row.len() == 1) {
+        rows.iter().map(|row| row[0]).collect()
+    } else {
+        report.push(
+            Severity::Error,
+            String::from("a population vector must be a single row or a single column"),
+        );
+        return (report, None);
+    };
+    for (stage, value) in values.iter().enumerate() {
+        if !value.is_finite() || *value < 0.0 {
+            report.push(
+                Severity::Error,
+                format!("lifestage {} has an invalid count ({})", stage + 1, value),
+            );
+        } else if value.fract() != 0.0 {
+            report.push(
+                Severity::Note,
+                format!("lifestage {} has a fractional count ({})", stage + 1, value),
+            );
+        }
+    }
+    if values.iter().all(|x| *x == 0.0) {
+        report.push(Severity::Warning, String::from("the population is empty"));
+    }
+    (report, Some(PopulationVector::new(values)))
+}
+
+/// Validate a matrix file and (optionally) a population vector file, including whether their
+/// lifestage counts match.
+#[cfg(feature = "cli")]
+pub fn validate_files(matrix_path: &Path, vector_path: Option<&Path>) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    let mut matrix = None;
+    match File::open(matrix_path) {
+        Ok(file) => {
+            let (matrix_report, built) = validate_matrix_csv(file);
+            report.extend(&matrix_path.display().to_string(), matrix_report);
+            matrix = built;
+        }
+        Err(error) => report.push(
+            Severity::Error,
+            format!("{}: could not open file: {}", matrix_path.display(), error),
+        ),
+    }
+    if let Some(vector_path) = vector_path {
+        match File::open(vector_path) {
+            Ok(file) => {
+                let (vector_report, vector) = validate_vector_csv(file);
+                report.extend(&vector_path.display().to_string(), vector_report);
+                if let (Some(matrix), Some(vector)) = (&matrix, vector) {
+                    if matrix.get_lifestage_count() != vector.get_lifestage_count() {
+                        report.push(
+                            Severity::Error,
+                            format!(
+                                "the matrix has {} lifestages but the population vector has {}",
+                                matrix.get_lifestage_count(),
+                                vector.get_lifestage_count()
+                            ),
+                        );
+                    }
+                }
+            }
+            Err(error) => report.push(
+                Severity::Error,
+                format!("{}: could not open file: {}", vector_path.display(), error),
+            ),
+        }
+    }
+    report
+}
+
+/// Validate a scenario file and every file it refers to.
+#[cfg(feature = "cli")]
+pub fn validate_scenario(path: &Path) -> ValidationReport {
+    match Scenario::from_file(path) {
+        Ok(scenario) => {
+            let mut report =
+                validate_files(scenario.get_matrix(), scenario.get_initial_population());
+            for key in scenario.get_unknown_keys() {
+                report.push(
+                    Severity::Warning,
+                    format!("{}: unknown setting \"{}\" is ignored", path.display(), key),
+                );
+            }
+            report
+        }
+        Err(error) => {
+            let mut report = ValidationReport::new();
+            report.push(Severity::Error, format!("{}: {}", path.display(), error));
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ragged_and_non_square_matrices() {
+        let (report, matrix) = validate_matrix_csv("0,1\n0.5\n".as_bytes());
+        assert!(matrix.is_none());
+        assert!(report.get_issues()[0]
+            .get_message()
+            .contains("row 2 has 1 values"));
+        let (report, _) = validate_matrix_csv("0,1,2\n0.5,0.1,0.2\n".as_bytes());
+        assert!(report.get_issues()[0].get_message().contains("square"));
+    }
+    #[test]
+    fn implausible_rates_are_warnings() {
+        let (report, matrix) = validate_matrix_csv("0,5\n0.7,1.2\n".as_bytes());
+        assert!(matrix.is_some());
+        assert!(!report.has_errors());
+        assert!(report
+            .get_issues()
+            .iter()
+            .any(|issue| issue.get_severity() == Severity::Warning
+                && issue.get_message().contains("adds up to")));
+    }
+    #[test]
+    fn eigen_diagnostics() {
+        let reducible = PopulationMatrix::build(vec![vec![0.5, 0.0], vec![0.5, 0.9]]).unwrap();
+        assert!(!is_irreducible(&reducible));
+        let imprimitive = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.0]]).unwrap();
+        assert!(is_irreducible(&imprimitive) && !is_primitive(&imprimitive));
+        let primitive = PopulationMatrix::build(vec![vec![0.0, 2.0], vec![0.5, 0.3]]).unwrap();
+        assert!(is_primitive(&primitive));
+        let report = validate_matrix(&primitive);
+        assert!(report
+            .get_issues()
+            .iter()
+            .any(|issue| issue.get_message().starts_with("lambda is")));
+    }
+    #[test]
+    fn vector_layouts() {
+        let (_, row) = validate_vector_csv("1,2,3\n".as_bytes());
+        let (_, column) = validate_vector_csv("1\n2\n3\n".as_bytes());
+        assert_eq!(row.unwrap().get_vector(), column.unwrap().get_vector());
+        let (report, _) = validate_vector_csv("1,-2\n".as_bytes());
+        assert!(report.has_errors());
+    }
+}
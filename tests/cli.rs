@@ -0,0 +1,479 @@
+//! End-to-end tests of the command line program. Each case runs the built binary on the fixture
+//! files in `tests/fixtures` and compares its exit code, standard output, and standard error with
+//! a golden file in `tests/golden`. Numbers are compared with a relative tolerance, so results
+//! that differ only in the last bits of floating point arithmetic still match.
+//!
+//! After an intended change to the output, rewrite the golden files with
+//! `UPDATE_GOLDEN=1 cargo test --test cli` and review the diff.
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// The relative tolerance for numbers in golden files.
+const TOLERANCE: f64 = 1e-9;
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+/// Run the program in the fixtures directory with `args`, writing `stdin` to its standard input,
+/// and return its exit code, standard output, and standard error as one report.
+fn run(args: &[&str], stdin: Option<&str>) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ecolysis_cmd"))
+        .args(args)
+        .current_dir(fixtures())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("The program could not be started.");
+    let mut input = child.stdin.take().unwrap();
+    input.write_all(stdin.unwrap_or("").as_bytes()).unwrap();
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    format!(
+        "exit: {}\n--- stdout\n{}--- stderr\n{}",
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// Split a line into numbers and the text between them, so numbers can be compared with a
+/// tolerance and everything else exactly.
+fn tokens(line: &str) -> Vec<&str> {
+    line.split(|x: char| x == ',' || x == ':' || x == '"' || x.is_whitespace())
+        .collect()
+}
+
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    match (expected.parse::<f64>(), actual.parse::<f64>()) {
+        (Ok(expected), Ok(actual)) if expected.is_finite() && actual.is_finite() => {
+            (expected - actual).abs() <= TOLERANCE * expected.abs().max(actual.abs()).max(1.0)
+        }
+        _ => expected == actual,
+    }
+}
+
+/// Return a description of the first line where `actual` differs from `expected`, or `None` if
+/// they match.
+fn difference(expected: &str, actual: &str) -> Option<String> {
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    for line in 0..expected_lines.len().max(actual_lines.len()) {
+        let (expected, actual) = (
+            expected_lines.get(line).copied().unwrap_or("<missing>"),
+            actual_lines.get(line).copied().unwrap_or("<missing>"),
+        );
+        let (expected_tokens, actual_tokens) = (tokens(expected), tokens(actual));
+        if expected_tokens.len() != actual_tokens.len()
+            || expected_tokens
+                .iter()
+                .zip(&actual_tokens)
+                .any(|(e, a)| !tokens_match(e, a))
+        {
+            return Some(format!(
+                "line {}:\n  expected: {}\n  actual:   {}",
+                line + 1,
+                expected,
+                actual
+            ));
+        }
+    }
+    None
+}
+
+/// Compare `actual` with the golden file `name`, or rewrite the golden file if `UPDATE_GOLDEN`
+/// is set.
+fn check_golden(name: &str, actual: &str) {
+    let path = golden_path(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing; run with UPDATE_GOLDEN=1 to create it.",
+            path.display()
+        )
+    });
+    if let Some(difference) = difference(&expected, actual) {
+        panic!(
+            "The output differs from {} at {}\n\nFull output:\n{}",
+            path.display(),
+            difference,
+            actual
+        );
+    }
+}
+
+#[test]
+fn numbers_are_compared_with_a_tolerance() {
+    assert!(difference("1,0.30000000000000004\n", "1,0.3\n").is_none());
+    assert!(difference("1,0.3\n", "1,0.31\n").is_some());
+    assert!(difference("lambda: 0.95", "lambda: 0.9500000000001").is_none());
+    assert!(difference("ok", "failed").is_some());
+    assert!(difference("a\nb\n", "a\n").is_some());
+}
+
+#[test]
+fn help_and_unknown_commands() {
+    check_golden("help.txt", &run(&["help"], None));
+    check_golden("unknown_command.txt", &run(&["simulate"], None));
+}
+
+#[test]
+fn project_deterministic() {
+    check_golden(
+        "project_deterministic.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead.csv",
+                "--vector",
+                "loggerhead_vector.csv",
+                "--steps",
+                "10",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn plain_output() {
+    // `--plain` is accepted anywhere, writes no color codes, and leaves an option value of
+    // "--plain" alone.
+    let output = run(
+        &[
+            "--plain",
+            "project",
+            "--plain",
+            "loggerhead.csv",
+            "--vector",
+            "loggerhead_vector.csv",
+            "--steps",
+            "10",
+        ],
+        None,
+    );
+    assert!(!output.contains('\x1b'));
+    check_golden("project_deterministic.txt", &output);
+    let output = run(
+        &[
+            "project",
+            "loggerhead.csv",
+            "--vector",
+            "loggerhead_vector.csv",
+            "--time-step",
+            "--plain",
+        ],
+        None,
+    );
+    assert!(!output.contains('\x1b'));
+    assert!(output.starts_with("exit: 2"));
+    assert!(output.contains("--time-step:"));
+}
+
+#[test]
+fn project_european_csv() {
+    // Semicolons, decimal commas, and thousands separators give the same projection.
+    check_golden(
+        "project_deterministic.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead_excel.csv",
+                "--vector",
+                "loggerhead_vector_excel.csv",
+                "--steps",
+                "10",
+            ],
+            None,
+        ),
+    );
+}
+
+#[cfg(feature = "xlsx")]
+#[test]
+fn project_workbook() {
+    check_golden(
+        "project_deterministic.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead.xlsx#Loggerhead!A3:H10",
+                "--vector",
+                "loggerhead.xlsx#Loggerhead!A12:H12",
+                "--steps",
+                "10",
+            ],
+            None,
+        ),
+    );
+    check_golden(
+        "project_workbook_error.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead.xlsx#Loggerhead!B12:C13",
+                "--vector",
+                "loggerhead_vector.csv",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn project_stochastic() {
+    check_golden(
+        "project_stochastic.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead.csv",
+                "loggerhead_ted.csv",
+                "--vector",
+                "loggerhead_vector.csv",
+                "--steps",
+                "5",
+                "--replicates",
+                "3",
+                "--seed",
+                "42",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn project_detail() {
+    check_golden(
+        "project_detail.txt",
+        &run(
+            &[
+                "project",
+                "loggerhead.csv",
+                "loggerhead_ted.csv",
+                "--vector",
+                "loggerhead_vector.csv",
+                "--steps",
+                "20",
+                "--replicates",
+                "4",
+                "--seed",
+                "3",
+                "--detail",
+                "final",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn project_from_standard_input() {
+    let matrix = fs::read_to_string(fixtures().join("teasel.csv")).unwrap();
+    check_golden(
+        "project_stdin.txt",
+        &run(
+            &["project", "-", "--vector", "teasel_vector.csv", "--steps=5"],
+            Some(&matrix),
+        ),
+    );
+}
+
+#[test]
+fn project_rejects_bad_input() {
+    check_golden(
+        "project_mismatched.txt",
+        &run(
+            &["project", "teasel.csv", "--vector", "loggerhead_vector.csv"],
+            None,
+        ),
+    );
+    check_golden(
+        "project_bad_steps.txt",
+        &run(
+            &[
+                "project",
+                "teasel.csv",
+                "--vector",
+                "teasel_vector.csv",
+                "--steps",
+                "ten",
+            ],
+            None,
+        ),
+    );
+    check_golden(
+        "project_detail_observation.txt",
+        &run(
+            &[
+                "project",
+                "teasel.csv",
+                "--vector",
+                "teasel_vector.csv",
+                "--detail",
+                "final",
+                "--observation",
+                "binomial 0.5",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn validate_files() {
+    check_golden(
+        "validate_loggerhead.txt",
+        &run(
+            &[
+                "validate",
+                "loggerhead.csv",
+                "--vector",
+                "loggerhead_vector.csv",
+            ],
+            None,
+        ),
+    );
+    check_golden(
+        "validate_malformed.txt",
+        &run(&["validate", "malformed.csv"], None),
+    );
+    check_golden(
+        "validate_scenario.txt",
+        &run(&["validate", "--scenario", "batch/current.scenario"], None),
+    );
+}
+
+#[test]
+fn windows_files() {
+    // A byte order mark and Windows line endings are read as plain text, and bytes that are not
+    // UTF-8 are reported with their position.
+    check_golden(
+        "analyze_windows.txt",
+        &run(&["analyze", "teasel_windows.csv"], None),
+    );
+    check_golden(
+        "analyze_latin1.txt",
+        &run(&["analyze", "teasel_latin1.csv"], None),
+    );
+    check_golden(
+        "validate_latin1.txt",
+        &run(&["validate", "teasel_latin1.csv"], None),
+    );
+}
+
+#[test]
+fn analyze_matrix() {
+    check_golden(
+        "analyze_loggerhead.txt",
+        &run(&["analyze", "--matrix", "loggerhead.csv"], None),
+    );
+}
+
+#[test]
+fn compare_futures() {
+    check_golden(
+        "compare.txt",
+        &run(
+            &[
+                "compare",
+                "--scenario",
+                "covariates.scenario",
+                "current.csv",
+                "warming.csv",
+                "--threshold",
+                "60",
+            ],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn generate_matrices() {
+    check_golden(
+        "generate.txt",
+        &run(
+            &[
+                "generate", "--stages", "3", "--shape", "leslie", "--lambda", "1.1", "--seed", "4",
+            ],
+            None,
+        ),
+    );
+    check_golden(
+        "generate_bad_range.txt",
+        &run(
+            &["generate", "--stages", "3", "--survival", "0.5,1.5"],
+            None,
+        ),
+    );
+}
+
+#[test]
+fn demo_datasets() {
+    check_golden("demo_list.txt", &run(&["demo"], None));
+    check_golden(
+        "demo_loggerhead.txt",
+        &run(&["demo", "loggerhead", "--steps", "5"], None),
+    );
+    check_golden("demo_unknown.txt", &run(&["demo", "dodo"], None));
+}
+
+#[test]
+fn batch_directory() {
+    let output = env::temp_dir().join("ecolysis_cli_batch");
+    if output.exists() {
+        fs::remove_dir_all(&output).unwrap();
+    }
+    let report = run(
+        &["batch", "batch", "--output", output.to_str().unwrap()],
+        None,
+    );
+    // The output directory is machine-specific, so it is replaced before comparing.
+    let normalize = |text: &str| text.replace(output.to_str().unwrap(), "$OUTPUT");
+    check_golden("batch.txt", &normalize(&report));
+    check_golden(
+        "batch_index.csv",
+        &normalize(&fs::read_to_string(output.join("index.csv")).unwrap()),
+    );
+    check_golden(
+        "batch_current.csv",
+        &fs::read_to_string(output.join("current.csv")).unwrap(),
+    );
+    check_golden(
+        "batch_ted.csv",
+        &fs::read_to_string(output.join("management/ted.csv")).unwrap(),
+    );
+    check_golden(
+        "batch_manifest.csv",
+        &fs::read_to_string(output.join("manifest.csv"))
+            .unwrap()
+            .lines()
+            // Sidecars hold the time of the run and the index holds the output path.
+            .filter(|line| !line.contains(".metadata.json,") && !line.starts_with("index.csv,"))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>(),
+    );
+    // Results are not written over without --force.
+    let existing = run(
+        &["batch", "batch", "--output", output.to_str().unwrap()],
+        None,
+    );
+    check_golden("batch_existing.txt", &normalize(&existing));
+    fs::remove_dir_all(output).unwrap();
+}
@@ -0,0 +1,130 @@
+//! Property-based tests of matrix construction and projection. Random matrices and vectors are
+//! generated with proptest, and each property must hold for every generated case; failures are
+//! shrunk to a minimal example. Run with `cargo test --test properties`, and set
+//! `PROPTEST_CASES` to run more cases than the default.
+use ecolysis_cmd::imports::read_popbio_csv;
+use ecolysis_cmd::populations::individual_level_simulation::{GenotypeTable, TransitionTable};
+use ecolysis_cmd::validation::{validate_matrix_csv, validate_vector_csv};
+use ecolysis_cmd::{PopulationMatrix, PopulationVector};
+use proptest::prelude::*;
+
+/// Square matrices of 1 to `max_size` lifestages with non-negative vital rates.
+fn square_matrix(max_size: usize) -> impl Strategy<Value = Vec<Vec<f64>>> {
+    (1..=max_size).prop_flat_map(|size| {
+        prop::collection::vec(prop::collection::vec(0.0..10.0f64, size), size)
+    })
+}
+
+/// A non-negative matrix and a non-negative vector with the same number of lifestages. Sizes
+/// above 16 exercise the sparse projection path.
+fn matrix_and_vector() -> impl Strategy<Value = (Vec<Vec<f64>>, Vec<f64>)> {
+    (1..=24usize).prop_flat_map(|size| {
+        (
+            prop::collection::vec(
+                prop::collection::vec(prop_oneof![3 => Just(0.0), 1 => 0.0..10.0f64], size),
+                size,
+            ),
+            prop::collection::vec(0.0..1000.0f64, size),
+        )
+    })
+}
+
+/// Return the product of a matrix and a vector computed directly from the definition.
+fn reference_product(matrix: &[Vec<f64>], vector: &[f64]) -> Vec<f64> {
+    matrix
+        .iter()
+        .map(|row| row.iter().zip(vector).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * (1.0 + a.abs().max(b.abs()))
+}
+
+proptest! {
+    #[test]
+    fn square_matrices_build(matrix in square_matrix(12)) {
+        let popmatrix = PopulationMatrix::build(matrix.clone()).unwrap();
+        prop_assert_eq!(popmatrix.get_lifestage_count() as usize, matrix.len());
+        prop_assert_eq!(popmatrix.get_matrix(), matrix);
+    }
+
+    #[test]
+    fn malformed_matrices_are_rejected(
+        rows in prop::collection::vec(prop::collection::vec(0.0..1.0f64, 0..6), 0..6)
+    ) {
+        let square = !rows.is_empty() && rows.iter().all(|row| row.len() == rows.len());
+        prop_assert_eq!(PopulationMatrix::build(rows).is_ok(), square);
+    }
+
+    #[test]
+    fn mismatched_dimensions_are_rejected(
+        matrix in square_matrix(8),
+        vector in prop::collection::vec(0.0..10.0f64, 0..10),
+    ) {
+        let popmatrix = PopulationMatrix::build(matrix.clone()).unwrap();
+        let result = popmatrix.project_vector(&PopulationVector::new(vector.clone()));
+        prop_assert_eq!(result.is_ok(), vector.len() == matrix.len());
+    }
+
+    #[test]
+    fn projection_matches_the_definition((matrix, vector) in matrix_and_vector()) {
+        let popmatrix = PopulationMatrix::build(matrix.clone()).unwrap();
+        let projected = popmatrix.project_vector(&PopulationVector::new(vector.clone())).unwrap();
+        for (a, b) in projected.iter().zip(reference_product(&matrix, &vector)) {
+            prop_assert!(close(*a, b), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn projection_preserves_non_negativity((matrix, vector) in matrix_and_vector()) {
+        let popmatrix = PopulationMatrix::build(matrix).unwrap();
+        let projected = popmatrix.project_vector(&PopulationVector::new(vector)).unwrap();
+        prop_assert!(projected.iter().all(|x| *x >= 0.0));
+    }
+
+    #[test]
+    fn projection_is_linear(
+        (matrix, x) in matrix_and_vector(),
+        a in 0.0..5.0f64,
+        b in 0.0..5.0f64,
+        seed in any::<u64>(),
+    ) {
+        // A second vector of the same size, derived from the seed.
+        let y: Vec<f64> = (0..x.len())
+            .map(|i| ((seed >> (i % 64)) & 0xff) as f64)
+            .collect();
+        let popmatrix = PopulationMatrix::build(matrix).unwrap();
+        let project = |v: Vec<f64>| popmatrix.project_vector(&PopulationVector::new(v)).unwrap();
+        let combined = project(x.iter().zip(&y).map(|(x, y)| a * x + b * y).collect());
+        let (px, py) = (project(x), project(y));
+        for ((c, x), y) in combined.iter().zip(px.iter()).zip(py.iter()) {
+            prop_assert!(close(*c, a * x + b * y), "{} != {}", c, a * x + b * y);
+        }
+    }
+
+    #[test]
+    fn matrix_csv_round_trips(matrix in square_matrix(10)) {
+        let text: String = matrix
+            .iter()
+            .map(|row| row.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",") + "\n")
+            .collect();
+        let (_, read) = validate_matrix_csv(text.as_bytes());
+        prop_assert_eq!(read.unwrap().into_inner(), matrix);
+    }
+
+    #[test]
+    fn matrix_csv_reader_never_panics(text in "[0-9.,eE+\\-x\n ]{0,200}") {
+        let (report, matrix) = validate_matrix_csv(text.as_bytes());
+        prop_assert!(matrix.is_some() || report.has_errors());
+    }
+
+    #[test]
+    fn csv_readers_never_panic(text in "(?s).{0,200}") {
+        let _ = validate_vector_csv(text.as_bytes());
+        let _ = read_popbio_csv(text.as_bytes());
+        let _ = TransitionTable::read_csv(text.as_bytes());
+        let _ = TransitionTable::read_toml(text.as_bytes());
+        let _ = GenotypeTable::read_csv(text.as_bytes());
+    }
+}